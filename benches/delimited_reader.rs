@@ -0,0 +1,89 @@
+//! Compares the throughput of the delimited reader configs, to choose between csv_core and the
+//! SIMD accelerated parser.
+//!
+//! Run with `cargo bench --bench delimited_reader`.
+
+use futures::StreamExt;
+use sql_bulk_import_profile::delimited_data_source::DelimitedDataSource;
+use sql_bulk_import_profile::import_profile::{DelimitedReaderConfig, Field};
+use std::path::Path;
+use std::time::Instant;
+
+const RECORDS: usize = 1_000_000;
+const ITERATIONS: usize = 5;
+
+async fn read(path: &Path, config: DelimitedReaderConfig) -> usize {
+    DelimitedDataSource::new(path, [] as [&Field; 0], config, None)
+        .await
+        .expect("could not create data source")
+        .fold(0, |records, record| async move {
+            record.expect("could not read record");
+            records + 1
+        })
+        .await
+}
+
+#[tokio::main]
+async fn main() {
+    let datasets = [
+        ("unquoted", |record: usize| {
+            format!(
+                "{},Name {},{}.{:02},2024-01-{:02}\r\n",
+                record,
+                record,
+                record,
+                record % 100,
+                record % 28 + 1
+            )
+        } as fn(usize) -> String),
+        ("quoted", |record: usize| {
+            format!(
+                "{},\"Name, {}\",\"Said \"\"hello\"\"\",\"{}\"\r\n",
+                record,
+                record,
+                record % 7
+            )
+        }),
+    ];
+
+    for (dataset, record) in datasets {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_bench_{}_{}.csv",
+            dataset,
+            std::process::id()
+        ));
+
+        let data = (0..RECORDS).fold(String::from("A,B,C,D\r\n"), |data, index| {
+            data + &record(index)
+        });
+
+        tokio::fs::write(&path, &data)
+            .await
+            .expect("could not write dataset");
+
+        for (name, config) in [
+            ("Csv", DelimitedReaderConfig::Csv),
+            ("SimdCsv", DelimitedReaderConfig::SimdCsv),
+        ] {
+            let start = Instant::now();
+
+            for _ in 0..ITERATIONS {
+                assert_eq!(read(&path, config).await, RECORDS);
+            }
+
+            let seconds = start.elapsed().as_secs_f64() / ITERATIONS as f64;
+
+            println!(
+                "{:<8} {:<8} {:>8.1} ms {:>8.1} MB/s",
+                dataset,
+                name,
+                seconds * 1000.0,
+                data.len() as f64 / seconds / 1_000_000.0,
+            );
+        }
+
+        tokio::fs::remove_file(&path)
+            .await
+            .expect("could not remove dataset");
+    }
+}
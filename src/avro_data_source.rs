@@ -0,0 +1,480 @@
+use crate::data_source::string_map::StringMap;
+use crate::data_source::{
+    DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, ReadRecordError,
+};
+use crate::data_source_file::DataSourceFile;
+use crate::import_profile::Field;
+use apache_avro::Schema;
+use apache_avro::schema::DecimalSchema;
+use apache_avro::types::Value;
+use futures::Stream;
+use rust_decimal::Decimal;
+use std::fmt::Write;
+use std::io::{Cursor, Read};
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+
+/// Days from 0000-03-01 to 1970-01-01, the epoch of Avro dates and timestamps.
+const DAYS_TO_UNIX_EPOCH: i64 = 719_468;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Debug, Error)]
+pub enum CreateAvroDataSourceError {
+    #[error("could not open data source file: {0}")]
+    OpenFileError(PathBuf, #[source] std::io::Error),
+    #[error("could not read container file header: {0}")]
+    ReadHeaderError(PathBuf, #[source] apache_avro::Error),
+    #[error("writer schema is not a record schema")]
+    NotARecordSchema,
+    #[error("field '{0}' is not a field of the writer schema")]
+    UnknownField(String),
+    #[error("could not count records of data source file: {0}")]
+    CountRecordsError(PathBuf, #[source] AvroReadRecordError),
+}
+
+#[derive(Debug, Error)]
+#[error("error reading Avro record ({index})")]
+pub struct AvroReadRecordError {
+    index: DataSourceErrorIndex,
+    #[source]
+    source: AvroReadRecordErrorKind,
+}
+
+#[derive(Debug, Error)]
+enum AvroReadRecordErrorKind {
+    #[error("could not decode record")]
+    Decode(#[from] apache_avro::Error),
+    #[error("expected a record, found: {0:?}")]
+    NotARecord(Value),
+    #[error("value of field '{0}' does not match the writer schema")]
+    SchemaMismatch(Arc<str>),
+    #[error("decimal of field '{0}' exceeds 28 digits")]
+    DecimalOutOfRange(Arc<str>),
+    #[error("unsupported value of field '{0}': {1:?}")]
+    UnsupportedValue(Arc<str>, Value),
+}
+
+impl ReadRecordError for AvroReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+/// Avro data source, reading a record from each record of an Avro object container file, with
+/// logical types read as text SQL Server converts into the column type.
+pub struct AvroDataSource<R: Read> {
+    reader: apache_avro::Reader<'static, R>,
+    /// Position of each field within the records of the writer schema, with its schema
+    fields: Vec<(usize, Arc<str>, Schema)>,
+    record_number: u64,
+}
+
+impl AvroDataSource<Cursor<Vec<u8>>> {
+    /// Opens the container file, which is read into memory and decompressed before any record is
+    /// read, its blocks being decoded as records are read.
+    pub async fn new<'fields>(
+        path: impl AsRef<Path>,
+        fields: impl IntoIterator<Item = &'fields Field>,
+    ) -> Result<Self, CreateAvroDataSourceError> {
+        let reader = Self::open(&path).await?;
+
+        let fields = fields
+            .into_iter()
+            .map(Field::name)
+            .filter(|field| !field.is_empty())
+            .collect::<Vec<_>>();
+
+        Self::from_reader(reader, &fields)
+    }
+
+    /// Counts the records of the data source file, decoding every record.
+    pub async fn count_records(path: impl AsRef<Path>) -> Result<u64, CreateAvroDataSourceError> {
+        let reader = Self::open(&path).await?;
+        let mut records = 0;
+
+        for value in reader {
+            value.map_err(|err| {
+                CreateAvroDataSourceError::CountRecordsError(
+                    path.as_ref().to_owned(),
+                    AvroReadRecordError {
+                        index: DataSourceErrorIndex {
+                            record_number: NonZero::new(records + 1),
+                            line_number: 0,
+                        },
+                        source: err.into(),
+                    },
+                )
+            })?;
+
+            records += 1;
+        }
+
+        Ok(records)
+    }
+
+    async fn open(
+        path: impl AsRef<Path>,
+    ) -> Result<apache_avro::Reader<'static, Cursor<Vec<u8>>>, CreateAvroDataSourceError> {
+        let mut container = Vec::new();
+
+        async {
+            DataSourceFile::open(&path)
+                .await?
+                .read_to_end(&mut container)
+                .await
+        }
+        .await
+        .map_err(|err| CreateAvroDataSourceError::OpenFileError(path.as_ref().to_owned(), err))?;
+
+        apache_avro::Reader::new(Cursor::new(container)).map_err(|err| {
+            CreateAvroDataSourceError::ReadHeaderError(path.as_ref().to_owned(), err)
+        })
+    }
+}
+
+impl<R: Read> AvroDataSource<R> {
+    fn from_reader(
+        reader: apache_avro::Reader<'static, R>,
+        fields: &[&str],
+    ) -> Result<Self, CreateAvroDataSourceError> {
+        let Schema::Record(record_schema) = reader.writer_schema() else {
+            return Err(CreateAvroDataSourceError::NotARecordSchema);
+        };
+
+        let fields = if fields.is_empty() {
+            record_schema
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(position, field)| {
+                    (
+                        position,
+                        Arc::from(field.name.as_str()),
+                        field.schema.clone(),
+                    )
+                })
+                .collect()
+        } else {
+            fields
+                .iter()
+                .map(|&field| {
+                    record_schema
+                        .fields
+                        .iter()
+                        .position(|record_field| record_field.name == field)
+                        .map(|position| {
+                            (
+                                position,
+                                Arc::from(field),
+                                record_schema.fields[position].schema.clone(),
+                            )
+                        })
+                        .ok_or_else(|| CreateAvroDataSourceError::UnknownField(field.to_owned()))
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        Ok(AvroDataSource {
+            reader,
+            fields,
+            record_number: 0,
+        })
+    }
+
+    fn read_record(
+        &self,
+        value: Value,
+        index: DataSourceRecordIndex,
+    ) -> Result<DataSourceRecord, AvroReadRecordErrorKind> {
+        let Value::Record(record) = value else {
+            return Err(AvroReadRecordErrorKind::NotARecord(value));
+        };
+
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|(position, field, schema)| match record.get(*position) {
+                Some((_, value)) => field_value(field, value, schema)
+                    .transpose()
+                    .map(|value| value.map(|value| (field.as_ref(), value))),
+                None => Some(Err(AvroReadRecordErrorKind::SchemaMismatch(field.clone()))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fields: StringMap<Arc<str>> = fields.into_iter().collect();
+
+        Ok(DataSourceRecord::new(fields, index))
+    }
+}
+
+impl<R: Read + Unpin> Stream for AvroDataSource<R> {
+    type Item = Result<DataSourceRecord, AvroReadRecordError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(value) = self.reader.next() else {
+            return Poll::Ready(None);
+        };
+
+        self.record_number += 1;
+
+        let record_number = NonZero::new(self.record_number).expect("Record numbers start at one");
+
+        // a container file is not read a line at a time, and its blocks are compressed, so the
+        // line and byte range of each record index are always zero
+        let result = value
+            .map_err(AvroReadRecordErrorKind::from)
+            .and_then(|value| {
+                self.read_record(
+                    value,
+                    DataSourceRecordIndex {
+                        record_number,
+                        line_start: 0,
+                        line_end: 0,
+                        byte_start: 0,
+                        byte_end: 0,
+                    },
+                )
+            });
+
+        Poll::Ready(Some(result.map_err(|source| AvroReadRecordError {
+            index: DataSourceErrorIndex {
+                record_number: Some(record_number),
+                line_number: 0,
+            },
+            source,
+        })))
+    }
+}
+
+/// Value of a field read from the value of an Avro record field with the schema, `None` if the
+/// value is `null`.
+fn field_value(
+    field: &Arc<str>,
+    value: &Value,
+    schema: &Schema,
+) -> Result<Option<String>, AvroReadRecordErrorKind> {
+    let value = match (value, schema) {
+        (Value::Union(variant, value), Schema::Union(union_schema)) => {
+            let schema = union_schema
+                .variants()
+                .get(*variant as usize)
+                .ok_or_else(|| AvroReadRecordErrorKind::SchemaMismatch(field.clone()))?;
+
+            return field_value(field, value, schema);
+        }
+        (Value::Null, _) => return Ok(None),
+        (Value::Boolean(value), _) => value.to_string(),
+        (Value::Int(value), _) => value.to_string(),
+        (Value::Long(value), _) => value.to_string(),
+        (Value::Float(value), _) => value.to_string(),
+        (Value::Double(value), _) => value.to_string(),
+        (Value::String(value), _) | (Value::Enum(_, value), _) => value.clone(),
+        (Value::Bytes(value), _) | (Value::Fixed(_, value), _) => {
+            value
+                .iter()
+                .fold(String::from("0x"), |mut hexadecimal, byte| {
+                    let _ = write!(hexadecimal, "{byte:02X}");
+
+                    hexadecimal
+                })
+        }
+        (Value::Uuid(value), _) => value.to_string(),
+        (Value::Decimal(value), Schema::Decimal(DecimalSchema { scale, .. })) => {
+            decimal_value(value, *scale)
+                .ok_or_else(|| AvroReadRecordErrorKind::DecimalOutOfRange(field.clone()))?
+                .to_string()
+        }
+        (Value::BigDecimal(value), _) => value.to_string(),
+        (Value::Date(days), _) => format_date(*days as i64),
+        (Value::TimeMillis(value), _) => format_time(*value as i64 * 1_000_000),
+        (Value::TimeMicros(value), _) => format_time(value * 1_000),
+        (Value::TimestampMillis(value), _) | (Value::LocalTimestampMillis(value), _) => {
+            format_timestamp(*value, 1_000)
+        }
+        (Value::TimestampMicros(value), _) | (Value::LocalTimestampMicros(value), _) => {
+            format_timestamp(*value, 1_000_000)
+        }
+        (Value::TimestampNanos(value), _) | (Value::LocalTimestampNanos(value), _) => {
+            format_timestamp(*value, 1_000_000_000)
+        }
+        (Value::Record(_) | Value::Array(_) | Value::Map(_), _) => {
+            serde_json::Value::try_from(value.clone())
+                .map_err(|_| {
+                    AvroReadRecordErrorKind::UnsupportedValue(field.clone(), value.clone())
+                })?
+                .to_string()
+        }
+        (value, _) => {
+            return Err(AvroReadRecordErrorKind::UnsupportedValue(
+                field.clone(),
+                value.clone(),
+            ));
+        }
+    };
+
+    Ok(Some(value))
+}
+
+/// Decimal of the unscaled value, a big-endian two's complement integer, with the scale, or `None`
+/// if the value has more than 28 digits.
+fn decimal_value(value: &apache_avro::Decimal, scale: usize) -> Option<Decimal> {
+    let bytes = Vec::<u8>::try_from(value).ok()?;
+
+    if bytes.len() > 16 {
+        return None;
+    }
+
+    let sign = if bytes.first().is_some_and(|byte| byte & 0x80 != 0) {
+        0xFF
+    } else {
+        0x00
+    };
+
+    let mut unscaled = [sign; 16];
+
+    unscaled[16 - bytes.len()..].copy_from_slice(&bytes);
+
+    Decimal::try_from_i128_with_scale(i128::from_be_bytes(unscaled), scale as u32).ok()
+}
+
+/// Formats the days since 1970-01-01 as `YYYY-MM-DD`, in the proleptic Gregorian calendar.
+pub(crate) fn format_date(days: i64) -> String {
+    let days = days + DAYS_TO_UNIX_EPOCH;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Formats the nanoseconds since midnight as `hh:mm:ss.fffffff`, truncated to the seven fractional
+/// digits of SQL Server times.
+pub(crate) fn format_time(nanoseconds: i64) -> String {
+    let seconds = nanoseconds.div_euclid(1_000_000_000);
+    let nanoseconds = nanoseconds.rem_euclid(1_000_000_000);
+
+    format!(
+        "{:02}:{:02}:{:02}.{:07}",
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60,
+        nanoseconds / 100,
+    )
+}
+
+/// Formats the units since 1970-01-01T00:00:00, with the units per second, as
+/// `YYYY-MM-DDThh:mm:ss.fffffff`.
+fn format_timestamp(value: i64, units_per_second: i64) -> String {
+    let seconds = value.div_euclid(units_per_second);
+    let nanoseconds = value.rem_euclid(units_per_second) * (1_000_000_000 / units_per_second);
+
+    format!(
+        "{}T{}",
+        format_date(seconds.div_euclid(SECONDS_PER_DAY)),
+        format_time(seconds.rem_euclid(SECONDS_PER_DAY) * 1_000_000_000 + nanoseconds),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    const SCHEMA: &str = r#"
+    {
+        "type": "record",
+        "name": "Rate",
+        "fields": [
+            { "name": "Code", "type": "string" },
+            { "name": "Rate", "type": ["null", { "type": "bytes", "logicalType": "decimal", "precision": 9, "scale": 4 }] },
+            { "name": "RateDate", "type": { "type": "int", "logicalType": "date" } },
+            { "name": "Published", "type": { "type": "long", "logicalType": "timestamp-millis" } }
+        ]
+    }
+    "#;
+
+    fn container(records: &[(&str, Option<i64>, i32, i64)]) -> Vec<u8> {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut writer = apache_avro::Writer::new(&schema, Vec::new());
+
+        for &(code, rate, rate_date, published) in records {
+            let rate = match rate {
+                Some(rate) => Value::Union(
+                    1,
+                    Box::new(Value::Decimal(apache_avro::Decimal::from(
+                        rate.to_be_bytes().to_vec(),
+                    ))),
+                ),
+                None => Value::Union(0, Box::new(Value::Null)),
+            };
+
+            writer
+                .append(Value::Record(vec![
+                    ("Code".to_owned(), Value::String(code.to_owned())),
+                    ("Rate".to_owned(), rate),
+                    ("RateDate".to_owned(), Value::Date(rate_date)),
+                    ("Published".to_owned(), Value::TimestampMillis(published)),
+                ]))
+                .unwrap();
+        }
+
+        writer.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_avro_logical_types() {
+        let container = container(&[
+            ("GBP", Some(12_500), 19_723, 1_704_067_199_999),
+            ("EUR", None, -1, -1),
+        ]);
+
+        let reader = apache_avro::Reader::new(Cursor::new(container)).unwrap();
+        let records = AvroDataSource::from_reader(reader, &[])
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get("Code"), Some("GBP"));
+        assert_eq!(records[0].get("Rate"), Some("1.2500"));
+        assert_eq!(records[0].get("RateDate"), Some("2024-01-01"));
+        assert_eq!(
+            records[0].get("Published"),
+            Some("2023-12-31T23:59:59.9990000")
+        );
+        assert_eq!(records[1].get("Rate"), None);
+        assert_eq!(records[1].get("RateDate"), Some("1969-12-31"));
+        assert_eq!(
+            records[1].get("Published"),
+            Some("1969-12-31T23:59:59.9990000")
+        );
+        assert_eq!(records[1].index().record_number.get(), 2);
+    }
+
+    #[test]
+    fn unknown_field() {
+        let reader = apache_avro::Reader::new(Cursor::new(container(&[]))).unwrap();
+
+        assert!(matches!(
+            AvroDataSource::from_reader(reader, &["Missing"]),
+            Err(CreateAvroDataSourceError::UnknownField(field)) if field == "Missing"
+        ));
+    }
+}
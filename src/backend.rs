@@ -0,0 +1,497 @@
+use itertools::Itertools;
+use rustc_hash::FxHashMap as HashMap;
+use std::collections::VecDeque;
+use tiberius::{BaseMetaDataColumn, BulkLoadRequest, Client, ColumnData, FromSql, ToSql, TokenRow};
+use tokio::net::TcpStream;
+use tokio_util::compat::Compat;
+
+/// Executes the statements and bulk inserts generated by the importer, implemented by the
+/// tiberius client, and by [`MockBackend`] to exercise SQL generation without a SQL Server.
+#[allow(async_fn_in_trait)]
+pub trait SqlBackend {
+    type BulkInsert<'a>: BulkInsert
+    where
+        Self: 'a;
+
+    async fn execute(
+        &mut self,
+        statement: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<ExecuteResult, tiberius::error::Error>;
+
+    /// Executes a statement reading rows back, returning the rows of its first result set.
+    async fn query(
+        &mut self,
+        statement: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<Vec<QueryRow>, tiberius::error::Error>;
+
+    /// Name and metadata of each column of the table.
+    async fn column_metadata(
+        &mut self,
+        table: &str,
+    ) -> Result<Vec<(String, BaseMetaDataColumn)>, tiberius::error::Error>;
+
+    /// Starts a bulk insert of rows with the columns into the table.
+    async fn bulk_insert<'a>(
+        &'a mut self,
+        table: &'a str,
+        columns: &[&str],
+    ) -> Result<Self::BulkInsert<'a>, tiberius::error::Error>;
+}
+
+#[allow(async_fn_in_trait)]
+pub trait BulkInsert {
+    async fn send(&mut self, row: TokenRow<'static>) -> Result<(), tiberius::error::Error>;
+
+    async fn finalize(self) -> Result<ExecuteResult, tiberius::error::Error>;
+}
+
+/// Rows affected by each statement of an executed batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecuteResult {
+    rows_affected: Vec<u64>,
+}
+
+impl ExecuteResult {
+    pub fn new(rows_affected: Vec<u64>) -> Self {
+        ExecuteResult { rows_affected }
+    }
+
+    pub fn rows_affected(&self) -> &[u64] {
+        &self.rows_affected
+    }
+
+    pub fn total(&self) -> u64 {
+        self.rows_affected.iter().sum()
+    }
+}
+
+/// Row read back by [`SqlBackend::query`], its values in the order of its columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryRow(Vec<ColumnData<'static>>);
+
+impl QueryRow {
+    pub fn new(values: impl Into<Vec<ColumnData<'static>>>) -> Self {
+        QueryRow(values.into())
+    }
+
+    /// Value of the column at the index, `None` if it is NULL, absent or of another type.
+    pub fn get<'a, R: FromSql<'a>>(&'a self, index: usize) -> Option<R> {
+        self.0
+            .get(index)
+            .and_then(|value| R::from_sql(value).ok().flatten())
+    }
+}
+
+impl From<tiberius::Row> for QueryRow {
+    fn from(row: tiberius::Row) -> Self {
+        QueryRow(row.into_iter().collect())
+    }
+}
+
+impl From<tiberius::ExecuteResult> for ExecuteResult {
+    fn from(result: tiberius::ExecuteResult) -> Self {
+        ExecuteResult::new(result.rows_affected().to_vec())
+    }
+}
+
+impl SqlBackend for Client<Compat<TcpStream>> {
+    type BulkInsert<'a> = BulkLoadRequest<'a, Compat<TcpStream>>;
+
+    async fn execute(
+        &mut self,
+        statement: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<ExecuteResult, tiberius::error::Error> {
+        Ok(Client::execute(self, statement, parameters).await?.into())
+    }
+
+    async fn query(
+        &mut self,
+        statement: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<Vec<QueryRow>, tiberius::error::Error> {
+        // statements without parameters are sent as a batch, such that temporary tables they
+        // create outlive the statement
+        let stream = if parameters.is_empty() {
+            self.simple_query(statement).await?
+        } else {
+            Client::query(self, statement, parameters).await?
+        };
+
+        Ok(stream
+            .into_first_result()
+            .await?
+            .into_iter()
+            .map(QueryRow::from)
+            .collect())
+    }
+
+    async fn column_metadata(
+        &mut self,
+        table: &str,
+    ) -> Result<Vec<(String, BaseMetaDataColumn)>, tiberius::error::Error> {
+        Ok(Client::column_metadata(self, table, &["*"])
+            .await?
+            .into_iter()
+            .map(|metadata| (metadata.col_name.into(), metadata.base))
+            .collect())
+    }
+
+    async fn bulk_insert<'a>(
+        &'a mut self,
+        table: &'a str,
+        columns: &[&str],
+    ) -> Result<Self::BulkInsert<'a>, tiberius::error::Error> {
+        self.bulk_insert_columns(table, columns).await
+    }
+}
+
+impl BulkInsert for BulkLoadRequest<'_, Compat<TcpStream>> {
+    async fn send(&mut self, row: TokenRow<'static>) -> Result<(), tiberius::error::Error> {
+        BulkLoadRequest::send(self, row).await
+    }
+
+    async fn finalize(self) -> Result<ExecuteResult, tiberius::error::Error> {
+        Ok(BulkLoadRequest::finalize(self).await?.into())
+    }
+}
+
+/// Parameters of a multi-row insert statement, below the 2100 parameters SQL Server allows.
+const MAX_INSERT_PARAMETERS: usize = 2000;
+
+/// Rows of a multi-row insert statement, the most a table value constructor allows.
+const MAX_INSERT_ROWS: usize = 1000;
+
+/// Inserts rows with parameterized multi-row `INSERT` statements, batched by the parameter limit,
+/// for environments where bulk load permission is denied.
+pub struct MultiRowInsert<'a, B: SqlBackend> {
+    backend: &'a mut B,
+    table: String,
+    columns: String,
+    column_count: usize,
+    rows_per_statement: usize,
+    rows: Vec<TokenRow<'static>>,
+    inserted: u64,
+}
+
+/// Value of a buffered row, as a parameter of a multi-row insert statement.
+struct RowParameter(ColumnData<'static>);
+
+impl ToSql for RowParameter {
+    fn to_sql(&self) -> ColumnData<'_> {
+        self.0.clone()
+    }
+}
+
+impl<'a, B: SqlBackend> MultiRowInsert<'a, B> {
+    /// Starts a multi-row insert of rows with the columns into the table, executing no statement
+    /// until rows are sent.
+    pub fn new(backend: &'a mut B, table: &str, columns: &[&str]) -> Self {
+        let column_count = columns.len().max(1);
+
+        MultiRowInsert {
+            backend,
+            table: table.to_owned(),
+            columns: columns.join(", "),
+            column_count,
+            rows_per_statement: (MAX_INSERT_PARAMETERS / column_count).clamp(1, MAX_INSERT_ROWS),
+            rows: Vec::new(),
+            inserted: 0,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), tiberius::error::Error> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let values = (0..self.rows.len())
+            .map(|row| {
+                format!(
+                    "({})",
+                    (1..=self.column_count)
+                        .map(|column| format!("@P{}", row * self.column_count + column))
+                        .join(", ")
+                )
+            })
+            .join(", ");
+
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            self.table, self.columns, values
+        );
+
+        let parameters = self
+            .rows
+            .drain(..)
+            .flat_map(|row| row.into_iter().map(RowParameter))
+            .collect::<Vec<_>>();
+
+        let parameters = parameters
+            .iter()
+            .map(|parameter| parameter as &dyn ToSql)
+            .collect::<Vec<_>>();
+
+        self.inserted += self.backend.execute(&statement, &parameters).await?.total();
+
+        Ok(())
+    }
+}
+
+impl<B: SqlBackend> BulkInsert for MultiRowInsert<'_, B> {
+    async fn send(&mut self, row: TokenRow<'static>) -> Result<(), tiberius::error::Error> {
+        self.rows.push(row);
+
+        if self.rows.len() >= self.rows_per_statement {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finalize(mut self) -> Result<ExecuteResult, tiberius::error::Error> {
+        self.flush().await?;
+
+        Ok(ExecuteResult::new(vec![self.inserted]))
+    }
+}
+
+/// In-memory backend recording the statements executed and the rows bulk inserted, each value
+/// recorded in its debug representation, such as `I32(Some(1))`.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    statements: Vec<MockStatement>,
+    bulk_inserts: Vec<MockBulkInsert>,
+    results: VecDeque<ExecuteResult>,
+    rows: VecDeque<Vec<QueryRow>>,
+    column_metadata: HashMap<String, Vec<(String, BaseMetaDataColumn)>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockStatement {
+    pub statement: String,
+    pub parameters: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockBulkInsert {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Bulk insert into a [`MockBackend`], recorded once finalized.
+#[derive(Debug)]
+pub struct MockBulkInsertRequest<'a> {
+    backend: &'a mut MockBackend,
+    bulk_insert: MockBulkInsert,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the result of a later statement, statements without a queued result affecting no
+    /// rows.
+    pub fn push_result(&mut self, rows_affected: impl Into<Vec<u64>>) {
+        self.results
+            .push_back(ExecuteResult::new(rows_affected.into()));
+    }
+
+    /// Queues the rows read back by a later query, queries without queued rows reading none.
+    pub fn push_rows(&mut self, rows: impl Into<Vec<QueryRow>>) {
+        self.rows.push_back(rows.into());
+    }
+
+    /// Sets the columns of the table, tables without columns set having none.
+    pub fn set_column_metadata(
+        &mut self,
+        table: &str,
+        columns: impl Into<Vec<(String, BaseMetaDataColumn)>>,
+    ) {
+        self.column_metadata
+            .insert(table.to_owned(), columns.into());
+    }
+
+    pub fn statements(&self) -> &[MockStatement] {
+        &self.statements
+    }
+
+    pub fn bulk_inserts(&self) -> &[MockBulkInsert] {
+        &self.bulk_inserts
+    }
+
+    fn record(&mut self, statement: &str, parameters: &[&dyn ToSql]) {
+        self.statements.push(MockStatement {
+            statement: statement.to_owned(),
+            parameters: parameters
+                .iter()
+                .map(|parameter| describe_value(&parameter.to_sql()))
+                .collect(),
+        });
+    }
+}
+
+fn describe_value(value: &ColumnData<'_>) -> String {
+    format!("{:?}", value)
+}
+
+impl SqlBackend for MockBackend {
+    type BulkInsert<'a> = MockBulkInsertRequest<'a>;
+
+    async fn execute(
+        &mut self,
+        statement: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<ExecuteResult, tiberius::error::Error> {
+        self.record(statement, parameters);
+
+        Ok(self.results.pop_front().unwrap_or_default())
+    }
+
+    async fn query(
+        &mut self,
+        statement: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<Vec<QueryRow>, tiberius::error::Error> {
+        self.record(statement, parameters);
+
+        Ok(self.rows.pop_front().unwrap_or_default())
+    }
+
+    async fn column_metadata(
+        &mut self,
+        table: &str,
+    ) -> Result<Vec<(String, BaseMetaDataColumn)>, tiberius::error::Error> {
+        Ok(self.column_metadata.get(table).cloned().unwrap_or_default())
+    }
+
+    async fn bulk_insert<'a>(
+        &'a mut self,
+        table: &'a str,
+        columns: &[&str],
+    ) -> Result<Self::BulkInsert<'a>, tiberius::error::Error> {
+        Ok(MockBulkInsertRequest {
+            backend: self,
+            bulk_insert: MockBulkInsert {
+                table: table.to_owned(),
+                columns: columns.iter().map(|column| column.to_string()).collect(),
+                rows: Vec::new(),
+            },
+        })
+    }
+}
+
+impl BulkInsert for MockBulkInsertRequest<'_> {
+    async fn send(&mut self, row: TokenRow<'static>) -> Result<(), tiberius::error::Error> {
+        self.bulk_insert.rows.push(
+            row.into_iter()
+                .map(|value| describe_value(&value))
+                .collect(),
+        );
+
+        Ok(())
+    }
+
+    async fn finalize(self) -> Result<ExecuteResult, tiberius::error::Error> {
+        let rows = self.bulk_insert.rows.len() as u64;
+
+        self.backend.bulk_inserts.push(self.bulk_insert);
+
+        Ok(ExecuteResult::new(vec![rows]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_backend_records_statements_and_rows() -> Result<(), tiberius::error::Error> {
+        let mut backend = MockBackend::new();
+
+        backend.push_result([2, 3]);
+
+        let result = backend
+            .execute("UPDATE [dbo].[Country] SET [Name] = @P1", &[&"France"])
+            .await?;
+
+        assert_eq!(result.total(), 5);
+        assert_eq!(backend.execute("SELECT 1", &[]).await?.total(), 0);
+
+        let mut bulk_insert = backend
+            .bulk_insert("[import].[Country]", &["[Code]"])
+            .await?;
+        let mut row = TokenRow::with_capacity(1);
+
+        row.push(ColumnData::I32(Some(1)));
+        bulk_insert.send(row).await?;
+
+        assert_eq!(bulk_insert.finalize().await?.total(), 1);
+
+        assert_eq!(
+            backend.statements()[0],
+            MockStatement {
+                statement: "UPDATE [dbo].[Country] SET [Name] = @P1".to_owned(),
+                parameters: vec![describe_value(&ColumnData::String(Some("France".into())))],
+            }
+        );
+        assert_eq!(backend.bulk_inserts()[0].rows, [["I32(Some(1))"]]);
+
+        backend.push_rows([QueryRow::new([
+            ColumnData::I64(Some(7)),
+            ColumnData::I64(None),
+        ])]);
+
+        let rows = backend.query("SELECT COUNT_BIG(*), NULL", &[]).await?;
+
+        assert_eq!(rows[0].get::<i64>(0), Some(7));
+        assert_eq!(rows[0].get::<i64>(1), None);
+        assert!(backend.query("SELECT 1", &[]).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multi_row_insert_batches_rows() -> Result<(), tiberius::error::Error> {
+        let mut backend = MockBackend::new();
+        // two rows of 801 parameters each are the most below the parameter limit
+        let columns = ["[Code]"; 801];
+
+        backend.push_result([2]);
+        backend.push_result([1]);
+
+        let mut multi_row_insert =
+            MultiRowInsert::new(&mut backend, "[import].[Country]", &columns);
+
+        for value in 0..3 {
+            let mut row = TokenRow::with_capacity(columns.len());
+
+            for _ in columns {
+                row.push(ColumnData::I32(Some(value)));
+            }
+
+            multi_row_insert.send(row).await?;
+        }
+
+        assert_eq!(multi_row_insert.finalize().await?.total(), 3);
+
+        let statements = backend.statements();
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].parameters.len(), 2 * 801);
+        assert!(
+            statements[0]
+                .statement
+                .starts_with("INSERT INTO [import].[Country] ([Code], [Code], ")
+        );
+        assert!(statements[0].statement.ends_with("@P1601, @P1602)"));
+        assert_eq!(statements[1].parameters[0], "I32(Some(2))");
+        assert!(backend.bulk_inserts().is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use crate::backend::SqlBackend;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::trace;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Time a statement waits for locks held by other sessions before the sessions blocking it are
+/// logged, after which the statement is executed again.
+pub const BLOCKED_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Error number of a statement which waited for a lock for longer than the lock timeout.
+const LOCK_TIMEOUT_ERROR: u32 = 1222;
+
+/// Length of the statement of a blocking session kept for logging.
+const STATEMENT_LENGTH: usize = 200;
+
+/// Session holding locks on a table, blocking statements of the import from acquiring their own.
+#[derive(Debug, Clone)]
+pub struct BlockingSession {
+    session_id: i32,
+    login_name: String,
+    host_name: Option<String>,
+    program_name: Option<String>,
+    status: String,
+    /// Session blocking this session in turn, such that the head of the blocking chain is found
+    blocked_by: Option<i32>,
+    statement: Option<String>,
+}
+
+impl Display for BlockingSession {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "session {} (login {}, host {}, program {}, {}",
+            self.session_id,
+            self.login_name,
+            self.host_name.as_deref().unwrap_or("unknown"),
+            self.program_name.as_deref().unwrap_or("unknown"),
+            self.status,
+        )?;
+
+        if let Some(blocked_by) = self.blocked_by {
+            write!(f, ", blocked by session {}", blocked_by)?;
+        }
+
+        write!(f, ")")?;
+
+        if let Some(statement) = &self.statement {
+            write!(f, ": {}", statement)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the statement failed as it waited for locks for longer than the lock timeout.
+pub fn is_lock_timeout(err: &tiberius::error::Error) -> bool {
+    matches!(err, tiberius::error::Error::Server(token) if token.code() == LOCK_TIMEOUT_ERROR)
+}
+
+/// Sets the time each following statement of the session waits for locks before failing, or
+/// waits indefinitely if none.
+pub async fn set_lock_timeout(
+    client: &mut impl SqlBackend,
+    lock_timeout: Option<Duration>,
+) -> Result<(), tiberius::error::Error> {
+    let statement = format!(
+        "SET LOCK_TIMEOUT {}",
+        lock_timeout.map_or(-1, |lock_timeout| lock_timeout.as_millis() as i64),
+    );
+
+    trace_sql!(statement);
+
+    client.execute(&statement, &[]).await?;
+
+    Ok(())
+}
+
+/// Sessions other than this session holding locks on the table or its partitions, requiring the
+/// VIEW SERVER STATE permission to see sessions of other logins.
+pub async fn blocking_sessions(
+    client: &mut impl SqlBackend,
+    table: &TableIdentifier,
+) -> Result<Vec<BlockingSession>, tiberius::error::Error> {
+    let statement = formatdoc!(
+        "
+        SELECT DISTINCT
+            CAST(L.[request_session_id] AS INT),
+            S.[login_name],
+            S.[host_name],
+            S.[program_name],
+            COALESCE(R.[status], S.[status]),
+            CAST(NULLIF(R.[blocking_session_id], 0) AS INT),
+            LEFT(T.[text], {statement_length})
+        FROM sys.dm_tran_locks AS L
+        JOIN sys.dm_exec_sessions AS S ON S.[session_id] = L.[request_session_id]
+        LEFT JOIN sys.dm_exec_requests AS R ON R.[session_id] = L.[request_session_id]
+        LEFT JOIN sys.dm_exec_connections AS C ON C.[session_id] = L.[request_session_id]
+        OUTER APPLY sys.dm_exec_sql_text(COALESCE(R.[sql_handle], C.[most_recent_sql_handle])) AS T
+        WHERE L.[resource_database_id] = DB_ID()
+            AND L.[request_session_id] <> @@SPID
+            AND L.[request_status] = N'GRANT'
+            AND (
+                L.[resource_associated_entity_id] = OBJECT_ID(@P1)
+                OR L.[resource_associated_entity_id] IN (
+                    SELECT [hobt_id] FROM sys.partitions WHERE [object_id] = OBJECT_ID(@P1)
+                )
+            )
+        ",
+        statement_length = STATEMENT_LENGTH,
+    );
+
+    trace_sql!(statement);
+
+    let rows = client
+        .query(&statement, &[&table.full().to_string()])
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| BlockingSession {
+            session_id: row.get::<i32>(0).unwrap_or_default(),
+            login_name: row.get::<&str>(1).unwrap_or_default().to_owned(),
+            host_name: row.get::<&str>(2).map(str::to_owned),
+            program_name: row.get::<&str>(3).map(str::to_owned),
+            status: row.get::<&str>(4).unwrap_or_default().to_owned(),
+            blocked_by: row.get::<i32>(5),
+            statement: row
+                .get::<&str>(6)
+                .map(|statement| statement.split_whitespace().collect::<Vec<_>>().join(" ")),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use tiberius::ColumnData;
+
+    #[tokio::test]
+    async fn set_and_reset_lock_timeout() -> Result<(), tiberius::error::Error> {
+        let mut backend = MockBackend::new();
+
+        set_lock_timeout(&mut backend, Some(BLOCKED_INTERVAL)).await?;
+        set_lock_timeout(&mut backend, None).await?;
+
+        assert_eq!(backend.statements()[0].statement, "SET LOCK_TIMEOUT 10000");
+        assert_eq!(backend.statements()[1].statement, "SET LOCK_TIMEOUT -1");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn describe_blocking_sessions() -> Result<(), tiberius::error::Error> {
+        let string =
+            |value: Option<&str>| ColumnData::String(value.map(|value| value.to_owned().into()));
+        let mut backend = MockBackend::new();
+
+        backend.push_rows([
+            QueryRow::new([
+                ColumnData::I32(Some(53)),
+                string(Some("etl")),
+                string(Some("worker-1")),
+                string(None),
+                string(Some("sleeping")),
+                ColumnData::I32(None),
+                string(Some("UPDATE [dbo].[Country]\n    SET [Name] = @P1")),
+            ]),
+            QueryRow::new([
+                ColumnData::I32(Some(61)),
+                string(Some("report")),
+                string(None),
+                string(Some("SSMS")),
+                string(Some("suspended")),
+                ColumnData::I32(Some(53)),
+                string(None),
+            ]),
+        ]);
+
+        let sessions = blocking_sessions(&mut backend, &"[dbo].[Country]".parse().unwrap()).await?;
+
+        assert_eq!(
+            sessions.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "session 53 (login etl, host worker-1, program unknown, sleeping): UPDATE [dbo].[Country] SET [Name] = @P1",
+                "session 61 (login report, host unknown, program SSMS, suspended, blocked by session 53)",
+            ]
+        );
+
+        Ok(())
+    }
+}
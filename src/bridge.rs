@@ -0,0 +1,217 @@
+use crate::backend::SqlBackend;
+use crate::column_graph::ColumnGraph;
+use crate::identifier::{ColumnIdentifier, Identifier, TableIdentifier};
+use crate::table_mapper::Bridge;
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace};
+use thiserror::Error;
+
+/// Table of the session holding the left keys of the temporary table, whose relationships are
+/// refreshed in full.
+pub const OWNERS_TABLE: &str = "#bridge_owners";
+
+#[derive(Debug, Error)]
+pub enum BridgeError {
+    #[error("could not find temporary table column for bridge column: {0}")]
+    UnknownColumn(ColumnIdentifier),
+    #[error("could not normalize bridge rows")]
+    NormalizeFailed(#[source] tiberius::error::Error),
+    #[error("could not delete unmatched bridge rows")]
+    DeleteFailed(#[source] tiberius::error::Error),
+}
+
+/// Temporary table columns of the left and right columns of the bridge.
+fn staged_columns(
+    bridge: &Bridge,
+    column_graph: &ColumnGraph,
+) -> Result<(String, String), BridgeError> {
+    let staged_column = |column: &ColumnIdentifier| {
+        column_graph
+            .target_columns()
+            .find(|target_column| target_column.column().identifier() == column)
+            .map(|column| column.unique_identifier().part().to_owned())
+            .ok_or_else(|| BridgeError::UnknownColumn(column.to_owned()))
+    };
+
+    Ok((
+        staged_column(bridge.left_column())?,
+        staged_column(bridge.right_column())?,
+    ))
+}
+
+/// Records the left keys of the temporary table, then orders and deduplicates the pairs of a
+/// symmetric bridge.
+pub async fn normalize(
+    client: &mut impl SqlBackend,
+    temporary_table: &TableIdentifier,
+    bridge: &Bridge,
+    column_graph: &ColumnGraph,
+) -> Result<(), BridgeError> {
+    let (left, right) = staged_columns(bridge, column_graph)?;
+
+    let mut statement = formatdoc!(
+        "
+        DROP TABLE IF EXISTS {OWNERS_TABLE};
+
+        SELECT DISTINCT {left} AS [key]
+        INTO {OWNERS_TABLE}
+        FROM {temporary_table}
+        WHERE {left} IS NOT NULL;
+        ",
+        temporary_table = temporary_table.full(),
+    );
+
+    if bridge.symmetric() {
+        statement += &formatdoc!(
+            "
+
+            UPDATE {temporary_table}
+            SET {left} = {right}, {right} = {left}
+            WHERE {left} > {right};
+
+            WITH Pairs AS (
+                SELECT ROW_NUMBER() OVER (PARTITION BY {left}, {right} ORDER BY (SELECT NULL)) AS [$row_number]
+                FROM {temporary_table}
+            )
+            DELETE FROM Pairs WHERE [$row_number] > 1;
+            ",
+            temporary_table = temporary_table.full(),
+        );
+    }
+
+    trace_sql!(statement);
+
+    client
+        .execute(&statement, &[])
+        .await
+        .map_err(BridgeError::NormalizeFailed)?;
+
+    info!("Normalized bridge rows of {}", temporary_table);
+
+    Ok(())
+}
+
+/// Deletes the target table rows of each left key of the temporary table whose pair is not in the
+/// temporary table, returning the number of rows deleted.
+pub async fn delete_unmatched(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    temporary_table: &TableIdentifier,
+    bridge: &Bridge,
+    column_graph: &ColumnGraph,
+) -> Result<u64, BridgeError> {
+    let (left, right) = staged_columns(bridge, column_graph)?;
+    let left_target = bridge.left_column().part();
+    let right_target = bridge.right_column().part();
+
+    let owner_condition = if bridge.symmetric() {
+        format!("O.[key] IN (T.{left_target}, T.{right_target})")
+    } else {
+        format!("O.[key] = T.{left_target}")
+    };
+
+    let statement = formatdoc!(
+        "
+        DELETE T
+        FROM {target_table} AS T
+        WHERE EXISTS (SELECT 1 FROM {OWNERS_TABLE} AS O WHERE {owner_condition})
+            AND NOT EXISTS (SELECT 1 FROM {temporary_table} AS S WHERE S.{left} = T.{left_target} AND S.{right} = T.{right_target})
+        ",
+        target_table = target_table.full(),
+        temporary_table = temporary_table.full(),
+    );
+
+    trace_sql!(statement);
+
+    let deleted = client
+        .execute(&statement, &[])
+        .await
+        .map_err(BridgeError::DeleteFailed)?
+        .total();
+
+    info!(
+        "Deleted {} unmatched bridge rows of {}",
+        deleted, target_table
+    );
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::import_options::ImportOptions;
+    use crate::table_mapper::{Table, TableMapper};
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn normalize_and_delete_unmatched_symmetric_bridge()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let lookup = |column: &str, field: &str| {
+            serde_json::json!({ "Lookup": {
+                "column_identifier": column,
+                "map_column": true,
+                "table_identifier": "[dbo].[Country]",
+                "output_column_identifier": "[ID]",
+                "key_columns": [{ "ParserKeyColumn": { "key_column_identifier": "[Code]", "field_name": field } }]
+            } })
+        };
+        let table_mapper = TableMapper::new(
+            serde_json::from_value(serde_json::json!({
+                "name": "CountryBorder",
+                "field_group": "CountryBorder",
+                "table_identifier": "[dbo].[CountryBorder]",
+                "delete_mode": "Partial",
+                "delete_action": "None",
+                "duplicate_action": "Reject",
+                "columns": [lookup("[A_CountryID]", "country"), lookup("[B_CountryID]", "neighbour")],
+                "key_columns": ["[A_CountryID]", "[B_CountryID]"],
+                "bridge": { "left_column": "[A_CountryID]", "right_column": "[B_CountryID]", "symmetric": true }
+            }))?,
+            None,
+        )?;
+        let country = "[dbo].[Country]".parse::<TableIdentifier>()?;
+        let table_metadata = HashMap::from_iter([
+            (table_mapper.identifier(), HashMap::default()),
+            (&country, HashMap::default()),
+        ]);
+        let column_graph =
+            ColumnGraph::new(&table_mapper, &table_metadata, &ImportOptions::default())?;
+        let temporary_table = "[import].[CountryBorder]".parse()?;
+        let bridge = table_mapper.bridge().unwrap();
+        let mut backend = MockBackend::new();
+
+        normalize(&mut backend, &temporary_table, bridge, &column_graph).await?;
+
+        backend.push_result([4]);
+
+        let deleted = delete_unmatched(
+            &mut backend,
+            table_mapper.identifier(),
+            &temporary_table,
+            bridge,
+            &column_graph,
+        )
+        .await?;
+
+        assert_eq!(deleted, 4);
+
+        let statements = backend.statements();
+
+        assert!(statements[0].statement.contains("INTO #bridge_owners"));
+        assert!(
+            statements[0]
+                .statement
+                .contains("DELETE FROM Pairs WHERE [$row_number] > 1")
+        );
+        assert!(
+            statements[1]
+                .statement
+                .contains("O.[key] IN (T.[A_CountryID], T.[B_CountryID])")
+        );
+
+        Ok(())
+    }
+}
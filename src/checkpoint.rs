@@ -0,0 +1,263 @@
+use crate::data_source::DataSourceRecordIndex;
+use crate::delimited_data_source::DelimitedCheckpoint;
+use crate::table_mapper::{DeleteMode, DuplicateAction, TableMapper};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{fs, io};
+use thiserror::Error;
+
+/// Progress of a run persisted to a checkpoint file, such that an interrupted run resumes from the
+/// last batch merged.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    state: CheckpointState,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CheckpointState {
+    /// SHA-256 digest of the import profile of the run, such that a checkpoint is not resumed by
+    /// another import profile
+    import_profile_sha256: String,
+    /// Data source file of the run, such that a checkpoint is not resumed against another file
+    data_source: Option<DataSourceFileIdentity>,
+    /// Progress of each table mapper, keyed by table mapper name
+    table_mappers: BTreeMap<String, TableMapperCheckpoint>,
+}
+
+/// Size and modification time of a data source file, which change if the file is replaced.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataSourceFileIdentity {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl DataSourceFileIdentity {
+    /// Identity of the file at the path, or `None` if it is not a local file, such as an object.
+    pub fn new(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+
+        Some(DataSourceFileIdentity {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Progress of a table mapper of the run.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableMapperCheckpoint {
+    /// Whether the table mapper completed
+    pub completed: bool,
+    /// Record number of the last record of the last batch merged
+    pub record_number: u64,
+    /// Position after the last record of the last batch merged, from which a delimited data
+    /// source is resumed without reading the preceding records
+    pub position: Option<DelimitedCheckpoint>,
+}
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("could not read checkpoint file")]
+    Read(#[source] io::Error),
+    #[error("could not deserialize checkpoint file")]
+    Deserialize(#[source] serde_json::Error),
+    #[error("could not write checkpoint file")]
+    Write(#[source] io::Error),
+    #[error("checkpoint file was written by another import profile, SHA-256 {0}")]
+    ImportProfileChanged(String),
+    #[error("checkpoint file was written for another data source file, or the file has changed")]
+    DataSourceChanged,
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint file of an interrupted run of the import profile and data source file,
+    /// or starts a new checkpoint if the file does not exist.
+    pub fn load(
+        path: impl Into<PathBuf>,
+        import_profile_sha256: &str,
+        data_source: Option<DataSourceFileIdentity>,
+    ) -> Result<Self, CheckpointError> {
+        let path = path.into();
+
+        let state = match File::open(&path) {
+            Ok(file) => {
+                let state: CheckpointState =
+                    serde_json::from_reader(file).map_err(CheckpointError::Deserialize)?;
+
+                if state.import_profile_sha256 != import_profile_sha256 {
+                    return Err(CheckpointError::ImportProfileChanged(
+                        state.import_profile_sha256,
+                    ));
+                }
+
+                if state.data_source != data_source {
+                    return Err(CheckpointError::DataSourceChanged);
+                }
+
+                info!(
+                    "Resuming interrupted run from checkpoint {}",
+                    path.display()
+                );
+
+                state
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => CheckpointState {
+                import_profile_sha256: import_profile_sha256.to_owned(),
+                data_source,
+                ..CheckpointState::default()
+            },
+            Err(err) => return Err(CheckpointError::Read(err)),
+        };
+
+        Ok(Checkpoint { path, state })
+    }
+
+    /// Progress of the table mapper by the interrupted run, if any.
+    pub fn table_mapper(&self, name: &str) -> TableMapperCheckpoint {
+        self.state
+            .table_mappers
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Records the batch of the table mapper ending with the record as merged.
+    pub fn commit(
+        &mut self,
+        name: &str,
+        index: &DataSourceRecordIndex,
+    ) -> Result<(), CheckpointError> {
+        let table_mapper = self.state.table_mappers.entry(name.to_owned()).or_default();
+
+        table_mapper.record_number = index.record_number.get();
+        table_mapper.position = Some(DelimitedCheckpoint::after(index));
+
+        self.save()
+    }
+
+    /// Records the table mapper as completed.
+    pub fn complete(&mut self, name: &str) -> Result<(), CheckpointError> {
+        self.state
+            .table_mappers
+            .entry(name.to_owned())
+            .or_default()
+            .completed = true;
+
+        self.save()
+    }
+
+    /// Removes the checkpoint file once the run completes.
+    pub fn remove(self) -> Result<(), CheckpointError> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(CheckpointError::Write(err)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the checkpoint file, replacing it only once written in full.
+    fn save(&self) -> Result<(), CheckpointError> {
+        let partial_path = self.path.with_extension("partial");
+
+        fs::write(
+            &partial_path,
+            serde_json::to_vec_pretty(&self.state).expect("Checkpoints should serialize"),
+        )
+        .and_then(|()| fs::rename(&partial_path, &self.path))
+        .map_err(CheckpointError::Write)
+    }
+}
+
+/// Whether the table mapper may be merged and checkpointed in batches, merging a batch not
+/// depending on the records of other batches.
+pub fn batched(table_mapper: &TableMapper) -> bool {
+    table_mapper.delete_mode() == DeleteMode::Partial
+        && table_mapper.bridge().is_none()
+        && table_mapper.hierarchy().is_none()
+        && matches!(
+            table_mapper.duplicate_action(),
+            DuplicateAction::Retain | DuplicateAction::NoCheck
+        )
+        && table_mapper.merge_order().is_none()
+        && !table_mapper.deferred_lookup_resolution()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZero;
+
+    #[test]
+    fn resume_from_checkpoint_file() -> Result<(), CheckpointError> {
+        let path =
+            std::env::temp_dir().join(format!("checkpoint_test_{}.json", std::process::id()));
+
+        let data_source = DataSourceFileIdentity {
+            len: 100,
+            modified: None,
+        };
+
+        let mut checkpoint = Checkpoint::load(&path, "a1", Some(data_source))?;
+
+        assert_eq!(
+            checkpoint.table_mapper("Country"),
+            TableMapperCheckpoint::default()
+        );
+
+        let index = DataSourceRecordIndex {
+            record_number: NonZero::new(5000).unwrap(),
+            line_start: 5001,
+            line_end: 5001,
+            byte_start: 90_000,
+            byte_end: 90_018,
+        };
+
+        checkpoint.complete("Currency")?;
+        checkpoint.commit("Country", &index)?;
+
+        let checkpoint = Checkpoint::load(&path, "a1", Some(data_source))?;
+
+        assert_eq!(
+            checkpoint.table_mapper("Currency"),
+            TableMapperCheckpoint {
+                completed: true,
+                record_number: 0,
+                position: None,
+            }
+        );
+        assert_eq!(
+            checkpoint.table_mapper("Country"),
+            TableMapperCheckpoint {
+                completed: false,
+                record_number: 5000,
+                position: Some(DelimitedCheckpoint::after(&index)),
+            }
+        );
+        assert!(matches!(
+            Checkpoint::load(&path, "b2", Some(data_source)),
+            Err(CheckpointError::ImportProfileChanged(sha256)) if sha256 == "a1"
+        ));
+        assert!(matches!(
+            Checkpoint::load(
+                &path,
+                "a1",
+                Some(DataSourceFileIdentity {
+                    len: 200,
+                    modified: None,
+                })
+            ),
+            Err(CheckpointError::DataSourceChanged)
+        ));
+
+        checkpoint.remove()?;
+
+        assert!(!path.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use crate::backend::SqlBackend;
+use crate::identifier::{Identifier, SchemaIdentifier, TableIdentifier};
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace};
+use thiserror::Error;
+
+/// Table of the `[import]` schema left behind by a run which did not drop it, such as a run with
+/// `--no-drop`, or one which crashed or was killed.
+#[derive(Debug)]
+pub struct StaleStagingTable {
+    table_identifier: TableIdentifier,
+    age_hours: i32,
+}
+
+#[derive(Debug, Error)]
+pub enum CleanupError {
+    #[error("could not find stale staging tables")]
+    FindFailed(#[source] tiberius::error::Error),
+    #[error("could not drop stale staging table {0}")]
+    DropFailed(TableIdentifier, #[source] tiberius::error::Error),
+}
+
+impl StaleStagingTable {
+    pub fn identifier(&self) -> &TableIdentifier {
+        &self.table_identifier
+    }
+
+    /// Hours since the table was created.
+    pub fn age_hours(&self) -> i32 {
+        self.age_hours
+    }
+}
+
+/// Finds the tables of the `[import]` schema created more than `retention_hours` ago, oldest
+/// first, orphans tables only if `include_orphans` is set.
+pub async fn find_stale_staging_tables(
+    client: &mut impl SqlBackend,
+    retention_hours: i32,
+    include_orphans: bool,
+) -> Result<Vec<StaleStagingTable>, CleanupError> {
+    let schema: SchemaIdentifier = "[import]".parse().unwrap();
+
+    let statement = formatdoc!(
+        "
+        SELECT t.name, DATEDIFF(HOUR, t.create_date, SYSDATETIME())
+        FROM sys.tables t
+        INNER JOIN sys.schemas s ON s.schema_id = t.schema_id
+        WHERE s.name = N'{schema_name}'
+            AND t.create_date < DATEADD(HOUR, -@P1, SYSDATETIME())
+        ORDER BY t.create_date
+        ",
+        schema_name = schema.part_unescaped(),
+    );
+
+    trace_sql!(statement);
+
+    let rows = client
+        .query(&statement, &[&retention_hours])
+        .await
+        .map_err(CleanupError::FindFailed)?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let name = row.get::<&str>(0)?;
+
+            if !include_orphans && name.ends_with("_orphans") {
+                return None;
+            }
+
+            Some(StaleStagingTable {
+                table_identifier: TableIdentifier::with_schema(&schema, name).ok()?,
+                age_hours: row.get::<i32>(1).unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+/// Drops each of the stale staging tables, stopping at the first which could not be dropped.
+pub async fn drop_stale_staging_tables(
+    client: &mut impl SqlBackend,
+    stale_staging_tables: &[StaleStagingTable],
+) -> Result<(), CleanupError> {
+    for stale_staging_table in stale_staging_tables {
+        let statement = formatdoc!(
+            "
+            DROP TABLE IF EXISTS {table_name}
+            ",
+            table_name = stale_staging_table.identifier().full(),
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await.map_err(|err| {
+            CleanupError::DropFailed(stale_staging_table.identifier().clone(), err)
+        })?;
+
+        info!(
+            "Dropped stale staging table {}, created {} hours ago",
+            stale_staging_table.identifier(),
+            stale_staging_table.age_hours(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use tiberius::ColumnData;
+
+    #[tokio::test]
+    async fn find_and_drop_stale_staging_tables() -> Result<(), CleanupError> {
+        let table = |name: &'static str, age_hours| {
+            QueryRow::new([
+                ColumnData::String(Some(name.into())),
+                ColumnData::I32(Some(age_hours)),
+            ])
+        };
+        let mut backend = MockBackend::new();
+
+        backend.push_rows([table("Country", 72), table("Country_orphans", 48)]);
+
+        let stale_staging_tables = find_stale_staging_tables(&mut backend, 24, false).await?;
+
+        assert_eq!(backend.statements()[0].parameters, ["I32(Some(24))"]);
+        assert_eq!(stale_staging_tables.len(), 1);
+        assert_eq!(
+            stale_staging_tables[0].identifier().full(),
+            "[import].[Country]"
+        );
+        assert_eq!(stale_staging_tables[0].age_hours(), 72);
+
+        drop_stale_staging_tables(&mut backend, &stale_staging_tables).await?;
+
+        assert_eq!(
+            backend.statements()[1].statement.trim(),
+            "DROP TABLE IF EXISTS [import].[Country]"
+        );
+
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::identifier::{ColumnIdentifier, Identifier, ParseIdentifierError, TableIdentifier};
 use crate::import_options::ImportOptions;
+use crate::insert_processor::is_supported_type;
 use crate::table_mapper::{
     Column, FieldColumn, LookupColumn, LookupKeyColumn, ParserColumn, ParserKeyColumn,
     ProcessedKeyColumn, StaticColumn, TableMapper, TableMapperColumn,
@@ -15,12 +16,13 @@ use petgraph::stable_graph::EdgeIndex;
 use petgraph::visit::IntoNodeReferences;
 use rustc_hash::FxHashMap as HashMap;
 use rustc_hash::FxHashSet as HashSet;
+use rustc_hash::FxHasher;
 use std::fmt::Write;
 use std::fmt::{Display, Formatter};
-use std::num::NonZeroUsize;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 use thiserror::Error;
-use tiberius::{BaseMetaDataColumn, TypeInfo, VarLenContext, VarLenType};
+use tiberius::{BaseMetaDataColumn, FixedLenType, TypeInfo, VarLenContext, VarLenType};
 
 /* Directed graph of ColumnNode, unit struct edge weights, default index (u32). */
 type ColumnGraphType = StableDiGraph<ColumnNode, (), DefaultIx>;
@@ -68,6 +70,15 @@ pub enum CreateColumnGraphError {
     ProcessedKeyColumnUnknownTargetColumn(ColumnIdentifier),
     #[error("could not topologically sort column graph due to cycle with column: {0}")]
     ColumnCycle(ColumnIdentifier),
+    #[error("unsupported type '{ty}' for column '{column}', map the column to a supported type")]
+    UnsupportedColumnType {
+        column: ColumnIdentifier,
+        ty: String,
+    },
+    #[error("could not create unique identifier '{1}' for column: {0}")]
+    UniqueColumnIdentifier(ColumnIdentifier, String, #[source] ParseIdentifierError),
+    #[error("could not find metadata for table: {0}")]
+    MissingTableMetadata(TableIdentifier),
 }
 
 impl ColumnGraph {
@@ -81,9 +92,25 @@ impl ColumnGraph {
         // do not add edges from processed key columns to dynamically added columns
         let mut dynamic_column_indices: HashSet<NodeIndex> = HashSet::default();
 
+        // staged as numbers when not written to the target table, so that they order numerically
+        let mut sequence_column_indices: HashSet<NodeIndex> = HashSet::default();
+
         // add graph nodes
         for column in table_mapper.columns() {
             match column {
+                TableMapperColumn::Static(static_column)
+                    if let Some(template) = static_column.template() =>
+                {
+                    // rendered into a field of each record, see Templater
+                    graph.add_node(ColumnNode::ParserColumn {
+                        column: ParserColumn::new(
+                            Column::identifier(static_column),
+                            Column::map(static_column),
+                            template.field_name(),
+                        ),
+                        map_column: false,
+                    });
+                }
                 TableMapperColumn::Static(static_column) => {
                     graph.add_node(ColumnNode::StaticColumn {
                         column: static_column.clone(),
@@ -96,6 +123,19 @@ impl ColumnGraph {
                         map_column: false,
                     });
                 }
+                TableMapperColumn::Sequence(sequence_column) => {
+                    // numbered into a field of each record, see Sequencer
+                    let sequence_column_index = graph.add_node(ColumnNode::ParserColumn {
+                        column: ParserColumn::new(
+                            Column::identifier(sequence_column),
+                            Column::map(sequence_column),
+                            sequence_column.field_name(),
+                        ),
+                        map_column: false,
+                    });
+
+                    sequence_column_indices.insert(sequence_column_index);
+                }
                 TableMapperColumn::Lookup(lookup_column) => {
                     let lookup_column_index = graph.add_node(ColumnNode::LookupColumn {
                         column: lookup_column.clone(),
@@ -118,7 +158,8 @@ impl ColumnGraph {
                                             parser_key_column.identifier(),
                                             false,
                                             parser_key_column.field_name(),
-                                        ),
+                                        )
+                                        .with_normalization(lookup_column.key_normalization()),
                                         map_column: false,
                                     });
 
@@ -144,6 +185,14 @@ impl ColumnGraph {
             }
         }
 
+        // staged, but not mapped, such that the merge chooses the action of each row by it
+        if let Some(operation) = table_mapper.operation() {
+            graph.add_node(ColumnNode::ParserColumn {
+                column: ParserColumn::new(operation.identifier(), false, operation.field()),
+                map_column: false,
+            });
+        }
+
         // add graph edges for processed key columns
         for (a, b) in graph
             .node_references()
@@ -293,10 +342,48 @@ impl ColumnGraph {
         }
 
         // map unique identifiers for each column
-        let unique_identifiers = Self::build_unique_identifiers(&graph);
+        let unique_identifiers = Self::build_unique_identifiers(&graph)?;
 
         // map metadata for each column
-        let metadata = Self::build_metadata(&graph, table_metadata);
+        let mut metadata = Self::build_metadata(&graph, table_metadata)?;
+
+        for index in sequence_column_indices {
+            if graph.contains_node(index)
+                && !Self::table_metadata(table_metadata, graph[index].identifier())?
+                    .contains_key(graph[index].identifier())
+            {
+                metadata.insert(
+                    index,
+                    BaseMetaDataColumn {
+                        flags: Default::default(),
+                        ty: TypeInfo::FixedLen(FixedLenType::Int8),
+                    },
+                );
+            }
+        }
+
+        // check the values of parser and static columns can be converted to their column types,
+        // static column tokens are instead typed by SQL Server
+        if let Some((column, column_metadata)) =
+            graph
+                .node_references()
+                .find_map(|(index, column)| match column {
+                    ColumnNode::StaticColumn { column, .. } if column.token().is_some() => None,
+                    // spatial values are instead converted from Well-Known Text by SQL Server
+                    ColumnNode::ParserColumn { column, .. } if column.spatial().is_some() => None,
+                    ColumnNode::StaticColumn { .. } | ColumnNode::ParserColumn { .. }
+                        if !is_supported_type(&metadata[&index].ty) =>
+                    {
+                        Some((column, &metadata[&index]))
+                    }
+                    _ => None,
+                })
+        {
+            return Err(CreateColumnGraphError::UnsupportedColumnType {
+                column: column.identifier().to_owned(),
+                ty: column_metadata.ty.to_string(),
+            });
+        }
 
         Ok(ColumnGraph {
             graph,
@@ -306,43 +393,57 @@ impl ColumnGraph {
         })
     }
 
+    /// Builds unique identifiers from a hash of the identifier and role of each column, rather than
+    /// from its node index, such that they are stable across changes to the rest of the import
+    /// profile. Columns with the same identifier and role are numbered in declaration order.
     fn build_unique_identifiers(
         graph: &ColumnGraphType,
-    ) -> HashMap<NodeIndex, UniqueColumnIdentifier> {
+    ) -> Result<HashMap<NodeIndex, UniqueColumnIdentifier>, CreateColumnGraphError> {
+        let mut occurrences: HashMap<(&ColumnIdentifier, String), usize> = HashMap::default();
+
         graph
             .node_references()
             .map(|(column_index, column)| {
-                let mut unique_name = String::with_capacity(
-                    column.identifier().part_unescaped().len()
-                        + 2
-                        + NonZeroUsize::new(column_index.index())
-                            .unwrap_or(NonZeroUsize::MIN)
-                            .ilog10() as usize,
-                );
+                let role = column.role();
 
-                write!(
-                    &mut unique_name,
-                    "{}_{}",
+                let mut hasher = FxHasher::default();
+                column.identifier().full().hash(&mut hasher);
+                role.hash(&mut hasher);
+
+                let occurrence = occurrences.entry((column.identifier(), role)).or_default();
+
+                let mut unique_name = format!(
+                    "{}_{:08x}",
                     column.identifier().part_unescaped(),
-                    column_index.index(),
-                )
-                .expect("Write to string should be infallible");
-
-                (
-                    column_index,
-                    UniqueColumnIdentifier(
-                        ColumnIdentifier::with_table(&column.identifier().into(), &unique_name)
-                            .expect("Unique column identifiers should be valid"),
-                    ),
-                )
+                    hasher.finish() as u32,
+                );
+
+                if *occurrence > 0 {
+                    write!(&mut unique_name, "_{}", occurrence)
+                        .expect("Write to string should be infallible");
+                }
+
+                *occurrence += 1;
+
+                let unique_identifier =
+                    ColumnIdentifier::with_table(&column.identifier().into(), &unique_name)
+                        .map_err(|err| {
+                            CreateColumnGraphError::UniqueColumnIdentifier(
+                                column.identifier().to_owned(),
+                                unique_name,
+                                err,
+                            )
+                        })?;
+
+                Ok((column_index, UniqueColumnIdentifier(unique_identifier)))
             })
-            .collect::<HashMap<NodeIndex, UniqueColumnIdentifier>>()
+            .collect()
     }
 
     fn build_metadata(
         graph: &ColumnGraphType,
         table_metadata: &HashMap<&TableIdentifier, HashMap<ColumnIdentifier, BaseMetaDataColumn>>,
-    ) -> HashMap<NodeIndex, BaseMetaDataColumn> {
+    ) -> Result<HashMap<NodeIndex, BaseMetaDataColumn>, CreateColumnGraphError> {
         graph
             .node_references()
             .map(|(column_index, column)| {
@@ -355,15 +456,31 @@ impl ColumnGraph {
                     )),
                 };
 
-                let column_schema = table_metadata
-                    .get(&TableIdentifier::from(column.identifier()))
-                    .expect("Table metadata should not be missing")
+                let column_schema = Self::table_metadata(table_metadata, column.identifier())?
                     .get(column.identifier())
                     .unwrap_or(&default_metadata);
 
-                (column_index, column_schema.to_owned())
+                Ok((column_index, column_schema.to_owned()))
             })
-            .collect::<HashMap<NodeIndex, BaseMetaDataColumn>>()
+            .collect()
+    }
+
+    /// Metadata of the columns of the table of the column, retrieved for the target table and the
+    /// lookup tables of the table mapper.
+    fn table_metadata<'a>(
+        table_metadata: &'a HashMap<
+            &TableIdentifier,
+            HashMap<ColumnIdentifier, BaseMetaDataColumn>,
+        >,
+        column_identifier: &ColumnIdentifier,
+    ) -> Result<&'a HashMap<ColumnIdentifier, BaseMetaDataColumn>, CreateColumnGraphError> {
+        let table_identifier = TableIdentifier::from(column_identifier);
+
+        table_metadata
+            .get(&table_identifier)
+            .ok_or(CreateColumnGraphError::MissingTableMetadata(
+                table_identifier,
+            ))
     }
 
     pub fn target_columns(&self) -> impl Iterator<Item = IndexedColumnNode<'_>> {
@@ -381,6 +498,19 @@ impl ColumnGraph {
         })
     }
 
+    /// Returns the column of the temporary table with the identifier, if it is not transient.
+    pub fn staged_column(&self, identifier: &ColumnIdentifier) -> Option<IndexedColumnNode<'_>> {
+        self.graph
+            .node_references()
+            .find(|(_, column)| !column.is_transient() && column.identifier() == identifier)
+            .map(|(index, column)| IndexedColumnNode {
+                index,
+                column,
+                unique_identifier: &self.unique_identifiers[&index],
+                metadata: &self.metadata[&index],
+            })
+    }
+
     pub fn groups(
         &self,
     ) -> impl ExactSizeIterator<Item = impl ExactSizeIterator<Item = IndexedColumnNode<'_>>> {
@@ -410,6 +540,22 @@ impl ColumnGraph {
 }
 
 impl ColumnNode {
+    /// Role of the column within the table mapper, distinguishing columns with the same identifier.
+    fn role(&self) -> String {
+        match self {
+            ColumnNode::StaticColumn { .. } => String::from("static"),
+            ColumnNode::ParserColumn { column, .. } => format!("parser {}", column.field_name()),
+            ColumnNode::LookupColumn { .. } => String::from("lookup"),
+            ColumnNode::LookupColumnParserKeyColumn(parser_key_column) => {
+                format!("parser key {}", parser_key_column.field_name())
+            }
+            ColumnNode::LookupColumnProcessedKeyColumn(processed_key_column) => format!(
+                "processed key {}",
+                processed_key_column.column_identifier().full()
+            ),
+        }
+    }
+
     pub fn is_transient(&self) -> bool {
         match self {
             ColumnNode::StaticColumn { .. } => true,
@@ -511,3 +657,54 @@ impl Column for ColumnNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser_column(column: &str, field_name: &str) -> ColumnNode {
+        ColumnNode::ParserColumn {
+            column: ParserColumn::new(
+                &ColumnIdentifier::from_str(column).unwrap(),
+                true,
+                field_name,
+            ),
+            map_column: false,
+        }
+    }
+
+    fn unique_names(graph: &ColumnGraphType) -> Vec<String> {
+        let unique_identifiers = ColumnGraph::build_unique_identifiers(graph).unwrap();
+
+        graph
+            .node_indices()
+            .map(|index| unique_identifiers[&index].part_unescaped().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn unique_identifiers_are_independent_of_node_indices() {
+        let mut graph = ColumnGraphType::default();
+        graph.add_node(parser_column("[dbo].[Country].[Code]", "Code"));
+
+        let mut shifted_graph = ColumnGraphType::default();
+        shifted_graph.add_node(parser_column("[dbo].[Country].[Name]", "Name"));
+        shifted_graph.add_node(parser_column("[dbo].[Country].[Code]", "Code"));
+
+        assert_eq!(unique_names(&graph)[0], unique_names(&shifted_graph)[1]);
+        assert!(unique_names(&graph)[0].starts_with("Code_"));
+    }
+
+    #[test]
+    fn unique_identifiers_distinguish_columns_with_the_same_role() {
+        let mut graph = ColumnGraphType::default();
+        graph.add_node(parser_column("[dbo].[Country].[Code]", "Code"));
+        graph.add_node(parser_column("[dbo].[Country].[Code]", "Code"));
+        graph.add_node(parser_column("[dbo].[Country].[Code]", "Alpha2"));
+
+        let names = unique_names(&graph);
+
+        assert_eq!(names[1], format!("{}_1", names[0]));
+        assert_ne!(names[0], names[2]);
+    }
+}
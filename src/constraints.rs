@@ -0,0 +1,362 @@
+use crate::backend::SqlBackend;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::temporary_table::RECORD_NUMBER_COLUMN;
+use crate::trace_sql;
+use indoc::formatdoc;
+use itertools::Itertools;
+use log::{info, trace, warn};
+use std::fmt::{Display, Formatter};
+
+/// Error number of a statement conflicting with a constraint.
+const CONSTRAINT_CONFLICT_ERROR: u32 = 547;
+
+/// Record numbers reported for each constraint failing re-validation.
+const REPORTED_RECORDS: usize = 20;
+
+/// Foreign key or check constraint of the target table, disabled while the table mapper is
+/// executed.
+#[derive(Debug, Clone)]
+pub struct DeferredConstraint {
+    /// Quoted name of the constraint
+    name: String,
+    kind: DeferredConstraintKind,
+}
+
+#[derive(Debug, Clone)]
+enum DeferredConstraintKind {
+    /// Check constraint, with the expression each row must not evaluate as false
+    Check { definition: String },
+    /// Foreign key, with the quoted referenced table and each quoted column of the target table
+    /// paired with the referenced column
+    ForeignKey {
+        referenced_table: String,
+        columns: Vec<(String, String)>,
+    },
+}
+
+/// Constraint which could not be re-validated, with the numbers of the records merged into the
+/// rows of the target table which do not satisfy it.
+#[derive(Debug, Clone)]
+pub struct ConstraintViolation {
+    constraint: String,
+    /// First of the records, ordered by record number
+    record_numbers: Vec<i64>,
+    /// Records not satisfying the constraint, including those not reported
+    records: i64,
+}
+
+impl Display for ConstraintViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.constraint)?;
+
+        if self.records == 0 {
+            return write!(f, " (rows not merged by the table mapper)");
+        }
+
+        write!(
+            f,
+            " ({} records: {}",
+            self.records,
+            self.record_numbers.iter().join(", ")
+        )?;
+
+        if self.records > self.record_numbers.len() as i64 {
+            write!(f, ", ...")?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+/// Disables the enabled foreign key and check constraints of the target table, returning the
+/// constraints disabled to be re-validated by [`revalidate`].
+pub async fn defer(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+) -> Result<Vec<DeferredConstraint>, tiberius::error::Error> {
+    let statement = formatdoc!(
+        "
+        SELECT QUOTENAME(C.[name]), C.[definition], NULL, NULL, NULL
+        FROM sys.check_constraints AS C
+        WHERE C.[parent_object_id] = OBJECT_ID(@P1) AND C.[is_disabled] = 0
+        UNION ALL
+        SELECT
+            QUOTENAME(F.[name]),
+            NULL,
+            QUOTENAME(OBJECT_SCHEMA_NAME(F.[referenced_object_id])) + N'.' + QUOTENAME(OBJECT_NAME(F.[referenced_object_id])),
+            QUOTENAME(PC.[name]),
+            QUOTENAME(RC.[name])
+        FROM sys.foreign_keys AS F
+        JOIN sys.foreign_key_columns AS FC ON FC.[constraint_object_id] = F.[object_id]
+        JOIN sys.columns AS PC ON PC.[object_id] = FC.[parent_object_id] AND PC.[column_id] = FC.[parent_column_id]
+        JOIN sys.columns AS RC ON RC.[object_id] = FC.[referenced_object_id] AND RC.[column_id] = FC.[referenced_column_id]
+        WHERE F.[parent_object_id] = OBJECT_ID(@P1) AND F.[is_disabled] = 0
+        "
+    );
+
+    trace_sql!(statement);
+
+    let rows = client
+        .query(&statement, &[&target_table.full().to_string()])
+        .await?;
+
+    let mut constraints: Vec<DeferredConstraint> = Vec::new();
+
+    for row in &rows {
+        let name = row.get::<&str>(0).unwrap_or_default();
+
+        match (
+            row.get::<&str>(1),
+            row.get::<&str>(2),
+            row.get::<&str>(3),
+            row.get::<&str>(4),
+        ) {
+            (Some(definition), ..) => constraints.push(DeferredConstraint {
+                name: name.to_owned(),
+                kind: DeferredConstraintKind::Check {
+                    definition: definition.to_owned(),
+                },
+            }),
+            (None, Some(referenced_table), Some(column), Some(referenced_column)) => {
+                let column = (column.to_owned(), referenced_column.to_owned());
+
+                match constraints.last_mut() {
+                    Some(DeferredConstraint {
+                        name: last_name,
+                        kind: DeferredConstraintKind::ForeignKey { columns, .. },
+                    }) if last_name == name => columns.push(column),
+                    _ => constraints.push(DeferredConstraint {
+                        name: name.to_owned(),
+                        kind: DeferredConstraintKind::ForeignKey {
+                            referenced_table: referenced_table.to_owned(),
+                            columns: vec![column],
+                        },
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for constraint in &constraints {
+        let statement = formatdoc!(
+            "
+            ALTER TABLE {target_table} NOCHECK CONSTRAINT {constraint}
+            ",
+            target_table = target_table.full(),
+            constraint = constraint.name,
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await?;
+    }
+
+    if !constraints.is_empty() {
+        info!(
+            "Deferred constraints {} of {} until the table mapper is merged",
+            constraints
+                .iter()
+                .map(|constraint| &constraint.name)
+                .join(", "),
+            target_table,
+        );
+    }
+
+    Ok(constraints)
+}
+
+/// Enables the deferred constraints, finding the records merged into the rows not satisfying
+/// them, whose constraints are enabled without being trusted.
+pub async fn revalidate(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    temporary_table: &TableIdentifier,
+    on_condition: &str,
+    constraints: &[DeferredConstraint],
+) -> Result<Vec<ConstraintViolation>, tiberius::error::Error> {
+    let mut violations = Vec::new();
+
+    for constraint in constraints {
+        let statement = formatdoc!(
+            "
+            ALTER TABLE {target_table} WITH CHECK CHECK CONSTRAINT {constraint}
+            ",
+            target_table = target_table.full(),
+            constraint = constraint.name,
+        );
+
+        trace_sql!(statement);
+
+        match client.execute(&statement, &[]).await {
+            Ok(_) => continue,
+            Err(tiberius::error::Error::Server(token))
+                if token.code() == CONSTRAINT_CONFLICT_ERROR => {}
+            Err(err) => return Err(err),
+        }
+
+        let statement = formatdoc!(
+            "
+            ALTER TABLE {target_table} WITH NOCHECK CHECK CONSTRAINT {constraint}
+            ",
+            target_table = target_table.full(),
+            constraint = constraint.name,
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await?;
+
+        let violated = match &constraint.kind {
+            DeferredConstraintKind::Check { definition } => {
+                // rows for which the expression is unknown satisfy the constraint
+                format!("NOT {}", qualify(definition, "T"))
+            }
+            DeferredConstraintKind::ForeignKey {
+                referenced_table,
+                columns,
+            } => formatdoc!(
+                "
+                {not_null}
+                    AND NOT EXISTS (SELECT * FROM {referenced_table} AS R WHERE {matches})
+                ",
+                not_null = columns
+                    .iter()
+                    .map(|(column, _)| format!("T.{column} IS NOT NULL"))
+                    .join(" AND "),
+                matches = columns
+                    .iter()
+                    .map(|(column, referenced_column)| format!(
+                        "R.{referenced_column} = T.{column}"
+                    ))
+                    .join(" AND "),
+            ),
+        };
+
+        let statement = formatdoc!(
+            "
+            SELECT TOP ({reported_records}) S.{record_number_column}, COUNT(*) OVER ()
+            FROM {temporary_table} AS S
+            JOIN {target_table} AS T ON {on_condition}
+            WHERE {violated}
+            ORDER BY S.{record_number_column}
+            ",
+            reported_records = REPORTED_RECORDS,
+            record_number_column = RECORD_NUMBER_COLUMN,
+            temporary_table = temporary_table.full(),
+            target_table = target_table.full(),
+            violated = violated.trim_end(),
+        );
+
+        trace_sql!(statement);
+
+        let rows = client.query(&statement, &[]).await?;
+
+        let violation = ConstraintViolation {
+            constraint: constraint.name.clone(),
+            record_numbers: rows.iter().filter_map(|row| row.get::<i64>(0)).collect(),
+            records: rows
+                .first()
+                .and_then(|row| row.get::<i32>(1))
+                .unwrap_or_default()
+                .into(),
+        };
+
+        warn!(
+            "Constraint of {} is not satisfied, enabled without checking existing rows: {}",
+            target_table, violation,
+        );
+
+        violations.push(violation);
+    }
+
+    Ok(violations)
+}
+
+/// Qualifies the column references of the constraint definition with the table alias, such as
+/// `([Digits]>=(0))` to `(T.[Digits]>=(0))`.
+fn qualify(definition: &str, alias: &str) -> String {
+    let mut qualified = String::with_capacity(definition.len());
+    let mut rest = definition;
+    let mut in_string = false;
+
+    while let Some(char) = rest.chars().next() {
+        if char == '[' && !in_string {
+            // `]]` escapes a closing bracket within the identifier
+            let bytes = rest.as_bytes();
+            let mut end = 1;
+
+            while end < bytes.len() {
+                match (bytes[end], bytes.get(end + 1)) {
+                    (b']', Some(b']')) => end += 2,
+                    (b']', _) => break,
+                    _ => end += 1,
+                }
+            }
+
+            let end = (end + 1).min(rest.len());
+            let (identifier, following) = rest.split_at(end);
+
+            if !qualified.ends_with('.') && !following.starts_with(['.', '(']) {
+                qualified.push_str(alias);
+                qualified.push('.');
+            }
+
+            qualified.push_str(identifier);
+            rest = following;
+
+            continue;
+        }
+
+        if char == '\'' {
+            in_string = !in_string;
+        }
+
+        qualified.push(char);
+        rest = &rest[char.len_utf8()..];
+    }
+
+    qualified
+}
+
+/// Enables constraints without checking the existing rows, where the table mapper failed before
+/// they were re-validated.
+pub async fn restore(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    constraints: &[DeferredConstraint],
+) -> Result<(), tiberius::error::Error> {
+    for constraint in constraints {
+        let statement = formatdoc!(
+            "
+            ALTER TABLE {target_table} WITH NOCHECK CHECK CONSTRAINT {constraint}
+            ",
+            target_table = target_table.full(),
+            constraint = constraint.name,
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qualify_check_constraint_definitions() {
+        assert_eq!(qualify("([Digits]>=(0))", "T"), "(T.[Digits]>=(0))");
+        assert_eq!(
+            qualify("([Code] like '[A-Z][A-Z][A-Z]' AND [Code]<>N'XXX')", "T"),
+            "(T.[Code] like '[A-Z][A-Z][A-Z]' AND T.[Code]<>N'XXX')"
+        );
+        assert_eq!(
+            qualify("([dbo].[IsValid]([Code])=(1))", "T"),
+            "([dbo].[IsValid](T.[Code])=(1))"
+        );
+    }
+}
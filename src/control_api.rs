@@ -0,0 +1,367 @@
+use crate::job_queue::{JobError, JobQueue, JobStatus};
+use log::{debug, warn};
+use serde_json::{Value, json};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Maximum size of the head of a request, its request line and headers.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// Maximum size of the body of a request.
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// Time each read of a request may wait for the client, such that idle connections are closed.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves an HTTP API submitting, querying and cancelling the jobs of the job queue, authenticated
+/// by the API token, until the listener fails.
+pub async fn serve(
+    listener: TcpListener,
+    job_queue: Arc<JobQueue>,
+    api_token: String,
+) -> io::Result<()> {
+    let api_token = Arc::<str>::from(api_token);
+
+    loop {
+        let (stream, address) = listener.accept().await?;
+        let job_queue = job_queue.clone();
+        let api_token = api_token.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, &job_queue, &api_token).await {
+                debug!("Control API connection from {} failed: {}", address, err);
+            }
+        });
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Whether the request bears the API token, compared in constant time such that the token
+    /// cannot be guessed from the time taken to reject a request.
+    fn authorized(&self, api_token: &str) -> bool {
+        self.authorization
+            .as_deref()
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .is_some_and(|token| {
+                token.len() == api_token.len()
+                    && token
+                        .bytes()
+                        .zip(api_token.bytes())
+                        .fold(0, |difference, (a, b)| difference | (a ^ b))
+                        == 0
+            })
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    job_queue: &JobQueue,
+    api_token: &str,
+) -> io::Result<()> {
+    let (status, body) = match read_request(&mut stream).await? {
+        Some(request) if request.authorized(api_token) => route(&request, job_queue),
+        Some(_) => (401, json!({ "error": "unauthorized" })),
+        None => (400, json!({ "error": "malformed request" })),
+    };
+
+    let body = body.to_string();
+
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n{body}",
+        reason = match status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            409 => "Conflict",
+            _ => "Internal Server Error",
+        },
+        length = body.len(),
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reads into the buffer, failing if the client sends nothing within the read timeout.
+async fn read_timeout(
+    stream: &mut (impl AsyncRead + Unpin),
+    buffer: &mut Vec<u8>,
+) -> io::Result<usize> {
+    tokio::time::timeout(READ_TIMEOUT, stream.read_buf(buffer))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+}
+
+/// Reads a request with a `Content-Length` body, or returns `None` if it is malformed or too
+/// large.
+async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Request>> {
+    let mut buffer = Vec::with_capacity(4096);
+
+    let head_end = loop {
+        if let Some(head_end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break head_end;
+        }
+
+        if buffer.len() > MAX_HEAD_SIZE || read_timeout(stream, &mut buffer).await? == 0 {
+            return Ok(None);
+        }
+    };
+
+    if head_end > MAX_HEAD_SIZE {
+        return Ok(None);
+    }
+
+    let Ok(head) = std::str::from_utf8(&buffer[..head_end]) else {
+        return Ok(None);
+    };
+
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+
+    let (Some(method), Some(path)) = (request_line.next(), request_line.next()) else {
+        return Ok(None);
+    };
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .collect::<Vec<_>>();
+
+    let header = |header_name: &str| {
+        headers
+            .iter()
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(header_name))
+            .map(|(_, value)| value.trim())
+    };
+
+    let content_length = header("content-length").map_or(Some(0), |value| value.parse().ok());
+
+    let Some(content_length) = content_length.filter(|&length| length <= MAX_REQUEST_SIZE) else {
+        return Ok(None);
+    };
+
+    let mut request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        authorization: header("authorization").map(str::to_owned),
+        body: buffer.split_off(head_end + 4),
+    };
+
+    while request.body.len() < content_length {
+        if read_timeout(stream, &mut request.body).await? == 0 {
+            return Ok(None);
+        }
+    }
+
+    request.body.truncate(content_length);
+
+    Ok(Some(request))
+}
+
+fn route(request: &Request, job_queue: &JobQueue) -> (u16, Value) {
+    let segments = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["jobs"]) => match job_queue.submit(&request.body) {
+            Ok(id) => (201, json!({ "id": id })),
+            Err(
+                err @ (JobError::DeserializeJob(_)
+                | JobError::ParseImportOptions(_)
+                | JobError::Interactive
+                | JobError::RunReport),
+            ) => (400, json!({ "error": error_chain(&err) })),
+            Err(err) => {
+                warn!("Control API could not submit job: {}", error_chain(&err));
+
+                (500, json!({ "error": error_chain(&err) }))
+            }
+        },
+        ("GET", ["jobs", id]) => match job_queue.status(id) {
+            Some(status) => (200, json!({ "id": id, "status": status_name(status) })),
+            None => not_found(),
+        },
+        ("GET", ["jobs", id, "report"]) => match job_queue.status(id) {
+            Some(status) => (
+                200,
+                json!({
+                    "id": id,
+                    "status": status_name(status),
+                    "error": job_queue.error(id),
+                    "report": job_queue.report(id),
+                }),
+            ),
+            None => not_found(),
+        },
+        ("DELETE", ["jobs", id]) => match job_queue.cancel(id) {
+            Ok(Some(status @ (JobStatus::Queued | JobStatus::Running))) => {
+                (200, json!({ "id": id, "cancelled": status_name(status) }))
+            }
+            Ok(Some(status)) => (
+                409,
+                json!({ "error": format!("job is already {}", status_name(status)) }),
+            ),
+            Ok(None) => not_found(),
+            Err(err) => (500, json!({ "error": err.to_string() })),
+        },
+        _ => not_found(),
+    }
+}
+
+fn not_found() -> (u16, Value) {
+    (404, json!({ "error": "not found" }))
+}
+
+fn status_name(status: JobStatus) -> &'static str {
+    match status {
+        JobStatus::Queued => "queued",
+        JobStatus::Running => "running",
+        JobStatus::Succeeded => "succeeded",
+        JobStatus::Failed => "failed",
+        JobStatus::Cancelled => "cancelled",
+    }
+}
+
+fn error_chain(err: &(dyn std::error::Error + 'static)) -> String {
+    std::iter::successors(Some(err), |err| err.source())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: Option<&str>) -> Request {
+        Request {
+            method: "GET".to_owned(),
+            path: "/jobs".to_owned(),
+            authorization: authorization.map(str::to_owned),
+            body: Vec::new(),
+        }
+    }
+
+    fn routed(method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        let directory =
+            std::env::temp_dir().join(format!("control_api_test_{}", std::process::id()));
+        let job_queue = JobQueue::new(&directory, None).unwrap();
+
+        route(
+            &Request {
+                method: method.to_owned(),
+                path: path.to_owned(),
+                authorization: None,
+                body: body.to_vec(),
+            },
+            &job_queue,
+        )
+    }
+
+    #[test]
+    fn authorize_bearer_token() {
+        assert!(request(Some("Bearer s3cret")).authorized("s3cret"));
+
+        assert!(!request(None).authorized("s3cret"));
+        assert!(!request(Some("Bearer s3creT")).authorized("s3cret"));
+        assert!(!request(Some("Bearer s3cret2")).authorized("s3cret"));
+        assert!(!request(Some("Bearer ")).authorized("s3cret"));
+        assert!(!request(Some("Basic s3cret")).authorized("s3cret"));
+        assert!(!request(Some("s3cret")).authorized("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn read_request_with_body() -> io::Result<()> {
+        let mut stream: &[u8] = b"POST /jobs HTTP/1.1\r\nauthorization: Bearer s3cret\r\nContent-Length: 4\r\n\r\nbodytrailing";
+
+        let request = read_request(&mut stream).await?.unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/jobs");
+        assert_eq!(request.authorization.as_deref(), Some("Bearer s3cret"));
+        assert_eq!(request.body, b"body");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_malformed_and_oversized_requests() -> io::Result<()> {
+        for malformed in [
+            &b"GET /jobs HTTP/1.1\r\n"[..],
+            b"GET\r\n\r\n",
+            b"POST /jobs HTTP/1.1\r\nContent-Length: ten\r\n\r\n",
+            b"POST /jobs HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+        ] {
+            assert!(read_request(&mut &*malformed).await?.is_none());
+        }
+
+        let oversized_head = format!(
+            "GET /jobs HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD_SIZE)
+        );
+
+        assert!(
+            read_request(&mut oversized_head.as_bytes())
+                .await?
+                .is_none()
+        );
+
+        let oversized_body = format!(
+            "POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_REQUEST_SIZE + 1
+        );
+
+        assert!(
+            read_request(&mut oversized_body.as_bytes())
+                .await?
+                .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn route_unknown_requests() {
+        for (method, path) in [
+            ("GET", "/"),
+            ("GET", "/queue"),
+            ("PUT", "/jobs"),
+            ("POST", "/jobs/1"),
+            ("GET", "/jobs/1/report/2"),
+            ("GET", "/jobs/0000000000000_0000"),
+            ("GET", "/jobs/..%2Fdone%2F0000000000000_0000"),
+            ("DELETE", "/jobs/../done"),
+        ] {
+            assert_eq!(routed(method, path, b"").0, 404, "{method} {path}");
+        }
+    }
+
+    #[test]
+    fn reject_invalid_jobs() {
+        assert_eq!(routed("POST", "/jobs", b"{").0, 400);
+        assert_eq!(routed("POST", "/jobs", br#"{"profile": "a.json"}"#).0, 400);
+        assert_eq!(
+            routed(
+                "POST",
+                "/jobs",
+                br#"{"import_profile": "a.json", "options": ["--interactive"]}"#
+            )
+            .0,
+            400
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use crate::data_source::ReadRecordError;
+use crate::import_options::ImportOptions;
+use crate::import_profile::{CreateDataSourceError, ImportProfile, ImportProfileDataSourceConfig};
+use crate::insert_processor::{parse_date, parse_datetime2, parse_decimal};
+use crate::record_accounting::RecordAccounting;
+use futures::StreamExt;
+use log::info;
+use rust_decimal::Decimal;
+use rustc_hash::FxHashSet as HashSet;
+use std::fmt::{Display, Formatter};
+use std::pin::Pin;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// Distinct values counted of each field, beyond which the count is reported as a lower bound.
+const DISTINCT_LIMIT: usize = 1000;
+
+/// Maximum length of an `nvarchar` column, beyond which `nvarchar(max)` is suggested.
+const NVARCHAR_MAX_LENGTH: usize = 4000;
+
+/// Statistics of the fields of the records of a field group, read from the data source for the
+/// first table mapper of the field group.
+#[derive(Debug)]
+pub struct FieldGroupProfile {
+    field_group: String,
+    table_mapper: String,
+    records: u64,
+    fields: Vec<FieldProfile>,
+}
+
+/// Statistics of the values of a field, and the type inferred from its values.
+#[derive(Debug)]
+pub struct FieldProfile {
+    name: String,
+    /// Records with a non-empty value
+    values: u64,
+    /// Maximum length of the values, in characters
+    max_length: usize,
+    distinct: HashSet<Box<str>>,
+    /// Whether there are more distinct values than counted
+    distinct_overflow: bool,
+    inferred_type: Option<InferredType>,
+}
+
+/// Narrowest type of which every value of a field is a valid value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InferredType {
+    Bit,
+    Int,
+    BigInt,
+    /// Decimal of the maximum integer digits and scale of the values
+    Decimal {
+        integer_digits: u32,
+        scale: u32,
+    },
+    Float,
+    Date,
+    DateTime,
+    Text,
+}
+
+#[derive(Debug, Error)]
+pub enum DataProfileError {
+    #[error("records of a streaming data source cannot be profiled")]
+    StreamDataSource,
+    #[error(transparent)]
+    CreateDataSource(#[from] CreateDataSourceError),
+    #[error("record could not be read")]
+    ReadRecordFailed(#[source] Box<dyn ReadRecordError>),
+}
+
+/// Profiles the values of each field of the first sample records of each field group of the data
+/// source, or of every record if `sample_records` is 0.
+pub async fn profile(
+    import_profile: &ImportProfile,
+    import_options: &ImportOptions,
+    sample_records: usize,
+) -> Result<Vec<FieldGroupProfile>, DataProfileError> {
+    if let ImportProfileDataSourceConfig::StreamDataSourceConfig { .. } =
+        import_profile.data_source_config()
+    {
+        return Err(DataProfileError::StreamDataSource);
+    }
+
+    let mut field_group_profiles: Vec<FieldGroupProfile> = Vec::new();
+
+    for table_mapper in import_profile.table_mappers() {
+        if field_group_profiles
+            .iter()
+            .any(|profile| profile.field_group == table_mapper.field_group())
+        {
+            continue;
+        }
+
+        let record_accounting = Rc::new(RecordAccounting::default());
+
+        let data_source = import_profile
+            .data_source_config()
+            .create_data_source(
+                table_mapper,
+                import_options,
+                None,
+                None,
+                None,
+                &record_accounting,
+                None,
+            )
+            .await?;
+
+        let limit = if sample_records == 0 {
+            usize::MAX
+        } else {
+            sample_records
+        };
+
+        let mut data_source = Pin::from(data_source).take(limit);
+
+        let mut field_group_profile = FieldGroupProfile {
+            field_group: table_mapper.field_group().to_owned(),
+            table_mapper: table_mapper.name().to_owned(),
+            records: 0,
+            fields: Vec::new(),
+        };
+
+        while let Some(record) = data_source.next().await {
+            let record = record.map_err(DataProfileError::ReadRecordFailed)?;
+
+            field_group_profile.records += 1;
+
+            // fields added for sequence and templated static columns are not of the data source
+            for (name, value) in record.iter().filter(|(name, _)| !name.starts_with('$')) {
+                let index = match field_group_profile
+                    .fields
+                    .iter()
+                    .position(|field| field.name == **name)
+                {
+                    Some(index) => index,
+                    None => {
+                        field_group_profile.fields.push(FieldProfile::new(name));
+                        field_group_profile.fields.len() - 1
+                    }
+                };
+
+                field_group_profile.fields[index].add(value);
+            }
+        }
+
+        info!(
+            "Profiled {} records of field group {}",
+            field_group_profile.records, field_group_profile.field_group,
+        );
+
+        field_group_profiles.push(field_group_profile);
+    }
+
+    Ok(field_group_profiles)
+}
+
+impl FieldGroupProfile {
+    pub fn field_group(&self) -> &str {
+        &self.field_group
+    }
+
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    pub fn fields(&self) -> &[FieldProfile] {
+        &self.fields
+    }
+}
+
+impl Display for FieldGroupProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "## {} ({} records, read for table mapper {})\n",
+            self.field_group, self.records, self.table_mapper,
+        )?;
+        writeln!(
+            f,
+            "| Field | Null rate | Max length | Distinct | Inferred type | Suggested column |"
+        )?;
+        writeln!(f, "| --- | --- | --- | --- | --- | --- |")?;
+
+        for field in &self.fields {
+            let null_rate = match self.records {
+                0 => 0.0,
+                records => (records - field.values) as f64 / records as f64 * 100.0,
+            };
+
+            writeln!(
+                f,
+                "| {} | {:.1}% | {} | {}{} | {} | {} |",
+                field.name.replace('|', "\\|"),
+                null_rate,
+                field.max_length,
+                field.distinct.len(),
+                if field.distinct_overflow { "+" } else { "" },
+                field
+                    .inferred_type
+                    .map_or("empty", |inferred_type| inferred_type.name()),
+                field.suggested_column_type(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FieldProfile {
+    fn new(name: &str) -> Self {
+        FieldProfile {
+            name: name.to_owned(),
+            values: 0,
+            max_length: 0,
+            distinct: HashSet::default(),
+            distinct_overflow: false,
+            inferred_type: None,
+        }
+    }
+
+    fn add(&mut self, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+
+        self.values += 1;
+        self.max_length = self.max_length.max(value.chars().count());
+
+        if self.distinct.len() < DISTINCT_LIMIT {
+            self.distinct.insert(Box::from(value));
+        } else if !self.distinct.contains(value) {
+            self.distinct_overflow = true;
+        }
+
+        let inferred_type = InferredType::infer(value);
+
+        self.inferred_type = Some(match self.inferred_type {
+            Some(previous) => previous.widen(inferred_type),
+            None => inferred_type,
+        });
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn inferred_type(&self) -> Option<InferredType> {
+        self.inferred_type
+    }
+
+    /// Column type suggested for the values, the inferred type, or an `nvarchar` column long
+    /// enough for the longest value.
+    pub fn suggested_column_type(&self) -> String {
+        match self.inferred_type {
+            Some(InferredType::Bit) => String::from("bit"),
+            Some(InferredType::Int) => String::from("int"),
+            Some(InferredType::BigInt) => String::from("bigint"),
+            Some(InferredType::Decimal {
+                integer_digits,
+                scale,
+            }) => format!("decimal({}, {})", (integer_digits + scale).max(1), scale),
+            Some(InferredType::Float) => String::from("float"),
+            Some(InferredType::Date) => String::from("date"),
+            Some(InferredType::DateTime) => String::from("datetime2"),
+            Some(InferredType::Text) | None if self.max_length > NVARCHAR_MAX_LENGTH => {
+                String::from("nvarchar(max)")
+            }
+            Some(InferredType::Text) | None => format!("nvarchar({})", self.max_length.max(1)),
+        }
+    }
+}
+
+impl InferredType {
+    fn infer(value: &str) -> Self {
+        let unsigned = value.strip_prefix(['-', '+']).unwrap_or(value);
+
+        // leading zeros are significant, as of codes and postal codes
+        if unsigned.len() > 1 && unsigned.starts_with('0') && !unsigned.starts_with("0.") {
+            return InferredType::Text;
+        }
+
+        if value.parse::<bool>().is_ok() {
+            InferredType::Bit
+        } else if value.parse::<i32>().is_ok() {
+            InferredType::Int
+        } else if value.parse::<i64>().is_ok() {
+            InferredType::BigInt
+        } else if let Ok(decimal) = value.parse::<Decimal>() {
+            let decimal = decimal.normalize();
+            let integer_digits = match decimal.trunc().abs() {
+                integer if integer.is_zero() => 0,
+                integer => integer.to_string().len() as u32,
+            };
+
+            InferredType::Decimal {
+                integer_digits,
+                scale: decimal.scale(),
+            }
+        } else if parse_decimal(value).is_some() || value.parse::<f64>().is_ok_and(f64::is_finite) {
+            InferredType::Float
+        } else if parse_date(value).is_some() {
+            InferredType::Date
+        } else if parse_datetime2(value, 7).is_some() {
+            InferredType::DateTime
+        } else {
+            InferredType::Text
+        }
+    }
+
+    /// Narrowest type of which the values of both types are valid values.
+    fn widen(self, other: Self) -> Self {
+        use InferredType::*;
+
+        let decimal = |integer_digits: u32, scale: u32| {
+            if integer_digits + scale > 38 {
+                Float
+            } else {
+                Decimal {
+                    integer_digits,
+                    scale,
+                }
+            }
+        };
+
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Int, BigInt) | (BigInt, Int) => BigInt,
+            (
+                Decimal {
+                    integer_digits: a_digits,
+                    scale: a_scale,
+                },
+                Decimal {
+                    integer_digits: b_digits,
+                    scale: b_scale,
+                },
+            ) => decimal(a_digits.max(b_digits), a_scale.max(b_scale)),
+            (
+                Int,
+                Decimal {
+                    integer_digits,
+                    scale,
+                },
+            )
+            | (
+                Decimal {
+                    integer_digits,
+                    scale,
+                },
+                Int,
+            ) => decimal(integer_digits.max(10), scale),
+            (
+                BigInt,
+                Decimal {
+                    integer_digits,
+                    scale,
+                },
+            )
+            | (
+                Decimal {
+                    integer_digits,
+                    scale,
+                },
+                BigInt,
+            ) => decimal(integer_digits.max(19), scale),
+            (Int | BigInt | Decimal { .. } | Float, Int | BigInt | Decimal { .. } | Float) => Float,
+            (Date | DateTime, Date | DateTime) => DateTime,
+            _ => Text,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InferredType::Bit => "boolean",
+            InferredType::Int => "integer",
+            InferredType::BigInt => "big integer",
+            InferredType::Decimal { .. } => "decimal",
+            InferredType::Float => "floating point",
+            InferredType::Date => "date",
+            InferredType::DateTime => "date and time",
+            InferredType::Text => "text",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile_field(values: &[&str]) -> FieldProfile {
+        let mut field_profile = FieldProfile::new("Field");
+
+        for value in values {
+            field_profile.add(value);
+        }
+
+        field_profile
+    }
+
+    #[test]
+    fn suggest_column_types() {
+        let suggested = |values: &[&str]| profile_field(values).suggested_column_type();
+
+        assert_eq!(suggested(&["true", "false", ""]), "bit");
+        assert_eq!(suggested(&["1", "-20", "300"]), "int");
+        assert_eq!(suggested(&["1", "3000000000"]), "bigint");
+        assert_eq!(suggested(&["1.5", "-12.25", "7"]), "decimal(12, 2)");
+        assert_eq!(suggested(&["1.5", "2.5E-3"]), "float");
+        assert_eq!(suggested(&["2024-02-29", "2024-03-01"]), "date");
+        assert_eq!(
+            suggested(&["2024-02-29", "2024-03-01T12:00:00"]),
+            "datetime2"
+        );
+        assert_eq!(suggested(&["01234", "56789"]), "nvarchar(5)");
+        assert_eq!(suggested(&["12", "GB"]), "nvarchar(2)");
+        assert_eq!(suggested(&["", ""]), "nvarchar(1)");
+    }
+
+    #[test]
+    fn count_values_and_distinct_values() {
+        let field_profile = profile_field(&["A", "Bee", "", "A"]);
+
+        assert_eq!(field_profile.values, 3);
+        assert_eq!(field_profile.max_length, 3);
+        assert_eq!(field_profile.distinct.len(), 2);
+        assert!(!field_profile.distinct_overflow);
+        assert_eq!(field_profile.inferred_type(), Some(InferredType::Text));
+    }
+}
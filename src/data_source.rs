@@ -1,14 +1,68 @@
 pub mod string_map;
 
 use crate::data_source::string_map::{StringMap, StringMapIter};
-use rustc_hash::FxBuildHasher;
+use crate::import_profile::Field;
+use futures::future::LocalBoxFuture;
+use futures::stream::LocalBoxStream;
+use rustc_hash::{FxBuildHasher, FxHashMap as HashMap};
+use serde_json::Value;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::num::NonZero;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock, RwLock};
 
 pub type DataSourceStreamItem = Result<DataSourceRecord, Box<dyn ReadRecordError>>;
 
+/// Error of a data source implemented outside of the crate.
+pub type CustomDataSourceError = Box<dyn Error + Send + Sync>;
+
+/// Data sources implemented outside of the crate, keyed by kind.
+static CUSTOM_DATA_SOURCES: LazyLock<RwLock<HashMap<String, Arc<dyn DataSource>>>> =
+    LazyLock::new(Default::default);
+
+/// Data source implemented outside of the crate, such as for a proprietary format, read by each
+/// `CustomDataSourceConfig` of the kind it is registered as, see [`register_data_source`].
+pub trait DataSource: Send + Sync {
+    /// Creates a stream of the records of a field group, with the options of the data source
+    /// config, reading each field from the record.
+    fn records<'a>(
+        self: Arc<Self>,
+        options: &'a Value,
+        fields: &'a [Field],
+    ) -> LocalBoxFuture<
+        'a,
+        Result<LocalBoxStream<'a, DataSourceStreamItem>, CustomDataSourceError>,
+    >;
+
+    /// Counts the records of the data source, reading it an extra time, if supported.
+    fn count_records<'a>(
+        self: Arc<Self>,
+        options: &'a Value,
+    ) -> LocalBoxFuture<'a, Result<u64, CustomDataSourceError>> {
+        let _ = options;
+
+        Box::pin(async { Err("records of the data source cannot be counted".into()) })
+    }
+}
+
+/// Registers a data source implemented outside of the crate as the kind read by a
+/// `CustomDataSourceConfig`, replacing any data source previously registered as the kind.
+pub fn register_data_source(kind: impl Into<String>, data_source: impl DataSource + 'static) {
+    CUSTOM_DATA_SOURCES
+        .write()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(kind.into(), Arc::new(data_source));
+}
+
+/// Data source registered as the kind, if any.
+pub(crate) fn custom_data_source(kind: &str) -> Option<Arc<dyn DataSource>> {
+    CUSTOM_DATA_SOURCES
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(kind)
+        .cloned()
+}
+
 pub trait ReadRecordError: Error + Display + Debug + Send + Sync + 'static {
     fn index(&self) -> DataSourceErrorIndex;
 }
@@ -19,19 +73,29 @@ impl Error for Box<dyn ReadRecordError> {
     }
 }
 
+impl ReadRecordError for Box<dyn ReadRecordError> {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.as_ref().index()
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct DataSourceRecordIndex {
     pub record_number: NonZero<u64>,
     pub line_start: u64,
     pub line_end: u64,
+    /// Byte offset of the start of the record in the data source file
+    pub byte_start: u64,
+    /// Byte offset of the end of the record in the data source file, exclusive
+    pub byte_end: u64,
 }
 
 impl Display for DataSourceRecordIndex {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "record: {}, line: {} - {}",
-            self.record_number, self.line_start, self.line_end,
+            "record: {}, line: {} - {}, bytes: {} - {}",
+            self.record_number, self.line_start, self.line_end, self.byte_start, self.byte_end,
         )
     }
 }
@@ -52,7 +116,7 @@ impl Display for DataSourceErrorIndex {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DataSourceRecord {
     fields: StringMap<Arc<str>>,
     index: DataSourceRecordIndex,
@@ -86,6 +150,42 @@ impl DataSourceRecord {
     pub fn index(&self) -> DataSourceRecordIndex {
         self.index
     }
+
+    #[inline(always)]
+    pub fn iter(&self) -> StringMapIter<'_, Arc<str>, FxBuildHasher> {
+        self.fields.iter()
+    }
+
+    /// Number of fields in the record.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Fields of the record, such that a few may be modified in place, see [`StringMap::insert`]
+    /// and [`StringMap::replace`].
+    #[inline(always)]
+    pub fn fields_mut(&mut self) -> &mut StringMap<Arc<str>> {
+        &mut self.fields
+    }
+
+    /// Splits the record into its fields and index, the inverse of [`DataSourceRecord::new`].
+    pub fn into_parts(self) -> (StringMap<Arc<str>>, DataSourceRecordIndex) {
+        (self.fields, self.index)
+    }
+
+    /// Offsets the index of a record read from part of the data source by the records and lines
+    /// preceding that part.
+    pub(crate) fn offset_index(&mut self, records: u64, lines: u64) {
+        self.index.record_number = self.index.record_number.saturating_add(records);
+        self.index.line_start += lines;
+        self.index.line_end += lines;
+    }
 }
 
 impl<'a> IntoIterator for &'a DataSourceRecord {
@@ -93,6 +193,6 @@ impl<'a> IntoIterator for &'a DataSourceRecord {
     type IntoIter = StringMapIter<'a, Arc<str>, FxBuildHasher>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.fields.into_iter()
+        self.fields.iter()
     }
 }
@@ -6,6 +6,7 @@ use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::{BuildHasher, Hash};
 
+#[derive(Clone)]
 pub struct StringMap<T, S = FxBuildHasher, const AVG_VALUE_SIZE: usize = 16> {
     data: String,
     indices: IndexMap<T, usize, S>,
@@ -30,6 +31,90 @@ impl<T: Borrow<str> + Hash + Eq, S: BuildHasher + Default> StringMap<T, S> {
             None
         }
     }
+
+    /// Inserts the value at the end of the map, or replaces the value in place if the key is
+    /// already present, returning the previous value.
+    pub fn insert(&mut self, key: T, value: &str) -> Option<String> {
+        if self.indices.contains_key(key.borrow()) {
+            return self.replace(key.borrow(), value);
+        }
+
+        // Data past the last index is unused, but would otherwise be prepended to the value
+        self.data
+            .truncate(self.indices.last().map_or(0, |(_, &end)| end));
+        self.data.push_str(value);
+        self.indices.insert(key, self.data.len());
+
+        None
+    }
+
+    /// Replaces the value of the key in place, shifting the values which follow it, returning
+    /// the previous value, or `None` if the key is not present.
+    pub fn replace(&mut self, key: &str, value: &str) -> Option<String> {
+        let i = self.indices.get_index_of(key)?;
+        let start = if i == 0 { 0 } else { self.indices[i - 1] };
+        let end = self.indices[i];
+        let previous = self.data[start..end].to_owned();
+
+        self.data.replace_range(start..end, value);
+
+        let new_end = start + value.len();
+
+        if new_end != end {
+            for index in self.indices.values_mut().skip(i) {
+                *index = *index - end + new_end;
+            }
+        }
+
+        Some(previous)
+    }
+
+    /// Trims leading and trailing whitespace from the value of the key in place, shifting the
+    /// values which follow it, returning whether the key is present.
+    pub fn trim(&mut self, key: &str) -> bool {
+        let Some(i) = self.indices.get_index_of(key) else {
+            return false;
+        };
+
+        let start = if i == 0 { 0 } else { self.indices[i - 1] };
+        let end = self.indices[i];
+        let value = &self.data[start..end];
+        let trimmed = value.trim();
+
+        if trimmed.len() == value.len() {
+            return true;
+        }
+
+        let trimmed_start = start + (trimmed.as_ptr() as usize - value.as_ptr() as usize);
+        let trimmed_end = trimmed_start + trimmed.len();
+        let removed = end - start - trimmed.len();
+
+        self.data.drain(trimmed_end..end);
+        self.data.drain(start..trimmed_start);
+
+        for index in self.indices.values_mut().skip(i) {
+            *index -= removed;
+        }
+
+        true
+    }
+}
+
+impl<T, S> StringMap<T, S> {
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> StringMapIter<'_, T, S> {
+        self.into_iter()
+    }
 }
 
 impl<T, S, K, V, const AVG_VALUE_SIZE: usize> FromIterator<(K, V)>
@@ -149,3 +234,89 @@ where
         Self::from_iter(arr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Asserts that the map holds exactly the expected entries, in order, with monotonically
+    /// increasing indices within the data.
+    fn assert_invariants(map: &StringMap<Arc<str>>, expected: &IndexMap<String, String>) {
+        let ends = map.indices.values().copied().collect::<Vec<_>>();
+
+        assert!(ends.is_sorted());
+        assert!(ends.last().is_none_or(|&end| end <= map.data.len()));
+        assert!(ends.iter().all(|&end| map.data.is_char_boundary(end)));
+
+        assert_eq!(
+            map.into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_owned()))
+                .collect::<Vec<_>>(),
+            expected
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn insert_replace_and_trim_preserve_invariants() {
+        const KEYS: [&str; 5] = ["a", "b", "c", "d", "e"];
+        const VALUES: [&str; 8] = [
+            "",
+            "x",
+            "yy",
+            "long value",
+            "ünïcödé",
+            "z",
+            " \t ",
+            " ü z\t",
+        ];
+
+        // Deterministic pseudo-random sequence of operations, from a linear congruential generator
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |bound: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+
+            (state >> 33) as usize % bound
+        };
+
+        for _ in 0..100 {
+            let mut map = unsafe {
+                StringMap::<Arc<str>>::new(String::from("trailing"), IndexMap::default())
+            };
+            let mut expected = IndexMap::<String, String>::new();
+
+            for _ in 0..20 {
+                let key = KEYS[next(KEYS.len())];
+                let value = VALUES[next(VALUES.len())];
+
+                match next(3) {
+                    0 => assert_eq!(
+                        map.insert(Arc::from(key), value),
+                        expected.insert(key.to_owned(), value.to_owned()),
+                    ),
+                    1 => {
+                        let previous = expected.get_mut(key).map(|expected_value| {
+                            std::mem::replace(expected_value, value.to_owned())
+                        });
+
+                        assert_eq!(map.replace(key, value), previous);
+                    }
+                    _ => {
+                        let present = expected.get_mut(key).map(|expected_value| {
+                            *expected_value = expected_value.trim().to_owned();
+                        });
+
+                        assert_eq!(map.trim(key), present.is_some());
+                    }
+                }
+
+                assert_invariants(&map, &expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use crate::object_storage;
+#[cfg(any(feature = "s3", feature = "azure"))]
+use crate::object_storage::ObjectReader;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
+use std::fmt::{Debug, Formatter};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncSeek, BufReader, ReadBuf};
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// Bytes of the data source file read at a time to be transcoded.
+const TRANSCODE_BUFFER_LENGTH: usize = 8192;
+
+/// Compression of a data source file, detected from the magic bytes at the start of the file
+/// rather than its extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn detect(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// Data source file, decompressed on the fly while read if compressed with gzip or zstd, only
+/// uncompressed local files being seekable.
+#[derive(Debug)]
+pub enum DataSourceFile {
+    Uncompressed(BufReader<Source>),
+    Gzip(GzipDecoder<BufReader<Source>>),
+    Zstd(ZstdDecoder<BufReader<Source>>),
+    Transcoded(Box<Transcoder>),
+}
+
+/// Data source file of an encoding other than UTF-8, transcoded to UTF-8 while read, a byte order
+/// mark taking precedence over the encoding.
+pub struct Transcoder {
+    file: DataSourceFile,
+    decoder: Decoder,
+    input: Box<[u8]>,
+    input_start: usize,
+    input_end: usize,
+    input_ended: bool,
+    output: Vec<u8>,
+    output_start: usize,
+    finished: bool,
+}
+
+/// Location the data source file is read from, either a local file or, with the `s3` or `azure`
+/// feature, an object streamed from object storage.
+#[derive(Debug)]
+pub enum Source {
+    File(File),
+    #[cfg(any(feature = "s3", feature = "azure"))]
+    Object(ObjectReader),
+}
+
+impl DataSourceFile {
+    /// Opens the data source file, detecting its compression. Paths such as `s3://bucket/key` or
+    /// `az://container/blob` are read from object storage.
+    pub async fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(Source::open(path.as_ref()).await?);
+
+        Ok(match Compression::detect(reader.fill_buf().await?) {
+            None => DataSourceFile::Uncompressed(reader),
+            Some(Compression::Gzip) => {
+                let mut decoder = GzipDecoder::new(reader);
+                decoder.multiple_members(true);
+                DataSourceFile::Gzip(decoder)
+            }
+            Some(Compression::Zstd) => {
+                let mut decoder = ZstdDecoder::new(reader);
+                decoder.multiple_members(true);
+                DataSourceFile::Zstd(decoder)
+            }
+        })
+    }
+
+    /// Opens the data source file as text, transcoded to UTF-8 while read from the encoding, or
+    /// from UTF-16 where no encoding is given and the file starts with a UTF-16 byte order mark.
+    ///
+    /// A UTF-8 byte order mark is left in place, to be skipped with [`skip_utf8_bom`] such that it
+    /// is counted in byte offsets into the file.
+    pub async fn open_text(
+        path: impl AsRef<Path>,
+        encoding: Option<&'static Encoding>,
+    ) -> std::io::Result<BufReader<Self>> {
+        if let Some(encoding) = encoding {
+            return Ok(BufReader::new(Self::open(path).await?.transcode(encoding)));
+        }
+
+        let mut reader = BufReader::new(Self::open(&path).await?);
+
+        // the byte order mark is already buffered, so the file is opened again to be transcoded
+        // from its start
+        Ok(match Encoding::for_bom(reader.fill_buf().await?) {
+            Some((encoding, _)) if encoding != UTF_8 => {
+                BufReader::new(Self::open(path).await?.transcode(encoding))
+            }
+            _ => reader,
+        })
+    }
+
+    /// Transcodes the data source file from the encoding to UTF-8 while read, such that byte
+    /// offsets of records are offsets into the transcoded content.
+    pub fn transcode(self, encoding: &'static Encoding) -> Self {
+        DataSourceFile::Transcoded(Box::new(Transcoder {
+            file: self,
+            decoder: encoding.new_decoder(),
+            input: vec![0; TRANSCODE_BUFFER_LENGTH].into_boxed_slice(),
+            input_start: 0,
+            input_end: 0,
+            input_ended: false,
+            output: Vec::new(),
+            output_start: 0,
+            finished: false,
+        }))
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        match self {
+            DataSourceFile::Uncompressed(_) => None,
+            DataSourceFile::Gzip(_) => Some(Compression::Gzip),
+            DataSourceFile::Zstd(_) => Some(Compression::Zstd),
+            DataSourceFile::Transcoded(transcoder) => transcoder.file.compression(),
+        }
+    }
+
+    /// Whether the data source file is an uncompressed local file, which may be seeked.
+    pub fn seekable(&self) -> bool {
+        matches!(self, DataSourceFile::Uncompressed(reader) if matches!(reader.get_ref(), Source::File(_)))
+    }
+}
+
+/// Skips the UTF-8 byte order mark at the start of the reader, such as Excel writes, returning the
+/// bytes skipped.
+pub async fn skip_utf8_bom(reader: &mut (impl AsyncBufRead + Unpin)) -> std::io::Result<usize> {
+    if reader.fill_buf().await?.starts_with(UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+
+        return Ok(UTF8_BOM.len());
+    }
+
+    Ok(0)
+}
+
+impl Source {
+    async fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(url) = path
+            .to_str()
+            .filter(|path| object_storage::is_object_url(path))
+        {
+            #[cfg(any(feature = "s3", feature = "azure"))]
+            return Ok(Source::Object(object_storage::open(url).await?));
+
+            #[cfg(not(any(feature = "s3", feature = "azure")))]
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("reading {} requires the s3 or azure feature", url),
+            ));
+        }
+
+        Ok(Source::File(File::open(path).await?))
+    }
+}
+
+impl AsyncRead for Source {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Source::File(file) => Pin::new(file).poll_read(cx, buf),
+            #[cfg(any(feature = "s3", feature = "azure"))]
+            Source::Object(object) => Pin::new(object).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncSeek for Source {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        match self.get_mut() {
+            Source::File(file) => Pin::new(file).start_seek(position),
+            #[cfg(any(feature = "s3", feature = "azure"))]
+            Source::Object(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "data source objects cannot be seeked",
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        match self.get_mut() {
+            Source::File(file) => Pin::new(file).poll_complete(cx),
+            #[cfg(any(feature = "s3", feature = "azure"))]
+            Source::Object(_) => Poll::Ready(Ok(0)),
+        }
+    }
+}
+
+impl AsyncRead for DataSourceFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            DataSourceFile::Uncompressed(reader) => Pin::new(reader).poll_read(cx, buf),
+            DataSourceFile::Gzip(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            DataSourceFile::Zstd(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            DataSourceFile::Transcoded(transcoder) => {
+                Pin::new(&mut **transcoder).poll_read(cx, buf)
+            }
+        }
+    }
+}
+
+impl AsyncRead for Transcoder {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let transcoder = self.get_mut();
+
+        loop {
+            // transcoded output is buffered, as a character may not fit in the remainder of buf
+            if transcoder.output_start < transcoder.output.len() {
+                let output = &transcoder.output[transcoder.output_start..];
+                let length = output.len().min(buf.remaining());
+
+                buf.put_slice(&output[..length]);
+                transcoder.output_start += length;
+
+                return Poll::Ready(Ok(()));
+            }
+
+            if transcoder.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            if transcoder.input_start == transcoder.input_end && !transcoder.input_ended {
+                let mut input = ReadBuf::new(&mut transcoder.input[..]);
+
+                ready!(Pin::new(&mut transcoder.file).poll_read(cx, &mut input))?;
+
+                transcoder.input_end = input.filled().len();
+                transcoder.input_start = 0;
+                transcoder.input_ended = transcoder.input_end == 0;
+            }
+
+            let input = &transcoder.input[transcoder.input_start..transcoder.input_end];
+            let output_length = transcoder
+                .decoder
+                .max_utf8_buffer_length(input.len())
+                .expect("transcode buffer length should not overflow");
+
+            transcoder.output.resize(output_length, 0);
+
+            let (result, read, written, _) = transcoder.decoder.decode_to_utf8(
+                input,
+                &mut transcoder.output,
+                transcoder.input_ended,
+            );
+
+            transcoder.input_start += read;
+            transcoder.output.truncate(written);
+            transcoder.output_start = 0;
+            transcoder.finished = transcoder.input_ended && result == CoderResult::InputEmpty;
+        }
+    }
+}
+
+impl Debug for Transcoder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transcoder")
+            .field("file", &self.file)
+            .field("encoding", &self.decoder.encoding())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncSeek for DataSourceFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        match self.get_mut() {
+            DataSourceFile::Uncompressed(reader) => Pin::new(reader).start_seek(position),
+            DataSourceFile::Transcoded(transcoder) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "data source files transcoded from {} cannot be seeked",
+                    transcoder.decoder.encoding().name()
+                ),
+            )),
+            compressed => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "{:?} compressed data source files cannot be seeked",
+                    compressed.compression().expect("compressed")
+                ),
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        match self.get_mut() {
+            DataSourceFile::Uncompressed(reader) => Pin::new(reader).poll_complete(cx),
+            _ => Poll::Ready(Ok(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn read_compressed_data_source_files() -> std::io::Result<()> {
+        let content = b"Code,Name\nGBP,Pound\nEUR,Euro\n";
+        let directory = std::env::temp_dir();
+
+        let mut gzip = GzipEncoder::new(Vec::new());
+        gzip.write_all(content).await?;
+        gzip.shutdown().await?;
+
+        let mut zstd = ZstdEncoder::new(Vec::new());
+        zstd.write_all(content).await?;
+        zstd.shutdown().await?;
+
+        for (extension, bytes, compression) in [
+            ("csv", content.to_vec(), None),
+            ("csv.gz", gzip.into_inner(), Some(Compression::Gzip)),
+            ("csv.zst", zstd.into_inner(), Some(Compression::Zstd)),
+        ] {
+            let path = directory.join(format!("currencies_{}.{}", std::process::id(), extension));
+
+            tokio::fs::write(&path, bytes).await?;
+
+            let mut file = DataSourceFile::open(&path).await?;
+            let mut decompressed = Vec::new();
+
+            file.read_to_end(&mut decompressed).await?;
+
+            assert_eq!(file.compression(), compression);
+            assert_eq!(file.seekable(), compression.is_none());
+            assert_eq!(decompressed, content);
+            assert_eq!(
+                file.seek(SeekFrom::Start(0)).await.is_ok(),
+                compression.is_none()
+            );
+
+            tokio::fs::remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_transcoded_data_source_files() -> std::io::Result<()> {
+        let content = "Code,Name\nEUR,Euro €\nISK,Króna\n";
+        let directory = std::env::temp_dir();
+
+        for label in ["windows-1252", "utf-16le", "utf-16be"] {
+            let encoding = Encoding::for_label(label.as_bytes()).expect("known label");
+            let bytes: Vec<u8> = match label {
+                "utf-16le" => content.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+                "utf-16be" => content.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+                _ => encoding.encode(content).0.into_owned(),
+            };
+            let path = directory.join(format!("currencies_{}_{}.csv", std::process::id(), label));
+
+            tokio::fs::write(&path, bytes).await?;
+
+            let mut file = DataSourceFile::open(&path).await?.transcode(encoding);
+            let mut transcoded = String::new();
+
+            file.read_to_string(&mut transcoded).await?;
+
+            assert!(!file.seekable());
+            assert_eq!(transcoded, content);
+            assert!(file.seek(SeekFrom::Start(0)).await.is_err());
+
+            tokio::fs::remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+}
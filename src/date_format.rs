@@ -0,0 +1,246 @@
+use crate::insert_processor::parse_date;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Format of the dates of a data source, such as `DD/MM/YYYY` or `YYYYMMDD hh:mm`, of the tokens
+/// `YYYY`, `MM`, `DD`, `M`, `D`, `MMM`, `hh`, `mm` and `ss`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFormat {
+    text: String,
+    parts: Vec<DateFormatPart>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum DateFormatPart {
+    Literal(char),
+    Year,
+    Month { exact: bool },
+    MonthName,
+    Day { exact: bool },
+    Hour,
+    Minute,
+    Second,
+}
+
+#[derive(Debug, Error)]
+pub enum DateFormatError {
+    #[error("unknown token '{0}'")]
+    UnknownToken(String),
+    #[error("missing token for the {0}")]
+    MissingToken(&'static str),
+}
+
+/// Representation of the dates parsed by a [`DateFormat`], in ISO 8601.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, JsonSchema, Deserialize)]
+pub enum DateOutput {
+    /// `YYYY-MM-DD`, for `date` columns
+    Date,
+    /// `YYYY-MM-DDThh:mm:ss`, for `datetime2` columns, at midnight where the format has no time
+    DateTime,
+}
+
+/// Date and time of day parsed by a [`DateFormat`], within the range of `date` columns.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ParsedDate {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl FromStr for DateFormat {
+    type Err = DateFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if !matches!(c, 'Y' | 'M' | 'D' | 'h' | 'm' | 's') {
+                parts.push(DateFormatPart::Literal(c));
+                continue;
+            }
+
+            let mut token = String::from(c);
+
+            while let Some(next) = chars.next_if_eq(&c) {
+                token.push(next);
+            }
+
+            parts.push(match token.as_str() {
+                "YYYY" => DateFormatPart::Year,
+                "M" => DateFormatPart::Month { exact: false },
+                "MM" => DateFormatPart::Month { exact: true },
+                "MMM" => DateFormatPart::MonthName,
+                "D" => DateFormatPart::Day { exact: false },
+                "DD" => DateFormatPart::Day { exact: true },
+                "hh" => DateFormatPart::Hour,
+                "mm" => DateFormatPart::Minute,
+                "ss" => DateFormatPart::Second,
+                _ => return Err(DateFormatError::UnknownToken(token)),
+            });
+        }
+
+        let has = |predicate: fn(&DateFormatPart) -> bool| parts.iter().any(predicate);
+
+        if !has(|part| matches!(part, DateFormatPart::Year)) {
+            return Err(DateFormatError::MissingToken("year"));
+        }
+
+        if !has(|part| {
+            matches!(
+                part,
+                DateFormatPart::Month { .. } | DateFormatPart::MonthName
+            )
+        }) {
+            return Err(DateFormatError::MissingToken("month"));
+        }
+
+        if !has(|part| matches!(part, DateFormatPart::Day { .. })) {
+            return Err(DateFormatError::MissingToken("day"));
+        }
+
+        Ok(DateFormat {
+            text: s.to_owned(),
+            parts,
+        })
+    }
+}
+
+impl Display for DateFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl DateFormat {
+    /// Parses the whole of the value, returning `None` if it does not match the format or is not
+    /// a valid date and time of day.
+    pub fn parse(&self, value: &str) -> Option<ParsedDate> {
+        let mut date = ParsedDate::default();
+        let mut rest = value;
+
+        for part in &self.parts {
+            match part {
+                DateFormatPart::Literal(c) => rest = rest.strip_prefix(*c)?,
+                DateFormatPart::Year => date.year = take_digits(&mut rest, 4, 4)? as u16,
+                DateFormatPart::Month { exact } => {
+                    date.month = take_digits(&mut rest, if *exact { 2 } else { 1 }, 2)? as u8;
+                }
+                DateFormatPart::MonthName => {
+                    let name = rest.get(..3)?;
+
+                    date.month = MONTH_NAMES
+                        .iter()
+                        .position(|month_name| month_name.eq_ignore_ascii_case(name))?
+                        as u8
+                        + 1;
+                    rest = &rest[3..];
+                }
+                DateFormatPart::Day { exact } => {
+                    date.day = take_digits(&mut rest, if *exact { 2 } else { 1 }, 2)? as u8;
+                }
+                DateFormatPart::Hour => date.hour = take_digits(&mut rest, 2, 2)? as u8,
+                DateFormatPart::Minute => date.minute = take_digits(&mut rest, 2, 2)? as u8,
+                DateFormatPart::Second => date.second = take_digits(&mut rest, 2, 2)? as u8,
+            }
+        }
+
+        let valid = rest.is_empty()
+            && date.hour < 24
+            && date.minute < 60
+            && date.second < 60
+            && parse_date(&date.format(DateOutput::Date)).is_some();
+
+        valid.then_some(date)
+    }
+}
+
+impl ParsedDate {
+    pub fn format(&self, output: DateOutput) -> String {
+        match output {
+            DateOutput::Date => format!("{:04}-{:02}-{:02}", self.year, self.month, self.day),
+            DateOutput::DateTime => format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                self.year, self.month, self.day, self.hour, self.minute, self.second,
+            ),
+        }
+    }
+}
+
+/// Takes at least `min` and at most `max` leading ASCII digits of the value.
+fn take_digits(value: &mut &str, min: usize, max: usize) -> Option<u32> {
+    let digits = value
+        .bytes()
+        .take(max)
+        .take_while(u8::is_ascii_digit)
+        .count();
+
+    if digits < min {
+        return None;
+    }
+
+    let (number, rest) = value.split_at(digits);
+
+    *value = rest;
+
+    number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_vendor_dates() {
+        let parse = |format: &str, value, output| {
+            DateFormat::from_str(format)
+                .unwrap()
+                .parse(value)
+                .map(|date| date.format(output))
+        };
+
+        assert_eq!(
+            parse("DD/MM/YYYY", "31/12/2024", DateOutput::Date).as_deref(),
+            Some("2024-12-31")
+        );
+        assert_eq!(
+            parse("YYYYMMDD", "20240229", DateOutput::DateTime).as_deref(),
+            Some("2024-02-29T00:00:00")
+        );
+        assert_eq!(
+            parse("D MMM YYYY hh:mm", "7 sep 2023 18:05", DateOutput::DateTime).as_deref(),
+            Some("2023-09-07T18:05:00")
+        );
+        assert_eq!(
+            parse("M/D/YYYY", "1/15/2024", DateOutput::Date).as_deref(),
+            Some("2024-01-15")
+        );
+
+        assert_eq!(parse("YYYYMMDD", "20230229", DateOutput::Date), None);
+        assert_eq!(parse("DD/MM/YYYY", "12/31/2024", DateOutput::Date), None);
+        assert_eq!(parse("DD/MM/YYYY", "31/12/2024 ", DateOutput::Date), None);
+        assert_eq!(
+            parse("YYYY-MM-DD hh", "2024-01-01 24", DateOutput::Date),
+            None
+        );
+
+        assert!(matches!(
+            DateFormat::from_str("DD/MM/YY"),
+            Err(DateFormatError::UnknownToken(token)) if token == "YY"
+        ));
+        assert!(matches!(
+            DateFormat::from_str("MM/YYYY"),
+            Err(DateFormatError::MissingToken("day"))
+        ));
+    }
+}
@@ -0,0 +1,614 @@
+use crate::backend::SqlBackend;
+use crate::data_source::{DataSourceErrorIndex, DataSourceRecord, ReadRecordError};
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::temporary_table::RECORD_NUMBER_COLUMN;
+use crate::trace_sql;
+use indoc::formatdoc;
+use itertools::Itertools;
+use log::{info, trace, warn};
+use rustc_hash::FxHashSet as HashSet;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use thiserror::Error;
+
+const KEY_SIZE: u64 = size_of::<u128>() as u64;
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+const HISTORY_TABLE: &str = "[import].[deduplication_history]";
+const HISTORY_INSERT_BATCH_SIZE: usize = 1000;
+
+static SPILL_RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Dropping of records whose fields match a record already read from the data source.
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Deduplication {
+    /// Fields whose combined values identify duplicate records
+    fields: Vec<String>,
+    /// Action taken on duplicate records
+    #[serde(default)]
+    action: DeduplicationAction,
+    /// Maximum number of distinct records tracked in memory
+    #[serde(default = "Deduplication::default_capacity")]
+    capacity: usize,
+    /// Spill tracked records to disk once the capacity is reached, rather than ceasing to track
+    /// further distinct records
+    #[serde(default)]
+    spill_to_disk: bool,
+    /// Also drop records whose fields match a record imported by a previous run, as tracked by
+    /// the deduplication history table
+    #[serde(default)]
+    history: bool,
+    /// Number of days the deduplication history table tracks imported records for, after which
+    /// matching records are imported again
+    #[serde(default = "Deduplication::default_history_retention_days")]
+    history_retention_days: u16,
+}
+
+#[derive(Debug, Default, Clone, JsonSchema, Deserialize)]
+pub enum DeduplicationAction {
+    /// Drop duplicate records
+    #[default]
+    Drop,
+    /// Append duplicate records to a JSON lines file, then drop them
+    Quarantine { path: PathBuf },
+}
+
+impl Deduplication {
+    fn default_capacity() -> usize {
+        1_000_000
+    }
+
+    fn default_history_retention_days() -> u16 {
+        90
+    }
+
+    pub fn history(&self) -> bool {
+        self.history
+    }
+
+    pub fn history_retention_days(&self) -> u16 {
+        self.history_retention_days
+    }
+}
+
+/// Keys of the records imported by previous runs of a table mapper within the retention window,
+/// and of the distinct records read by the current run.
+#[derive(Debug)]
+pub struct DeduplicationHistory {
+    import_profile_name: String,
+    table_mapper_name: String,
+    previous: HashSet<u128>,
+    current: RefCell<Vec<(u64, u128)>>,
+}
+
+impl DeduplicationHistory {
+    /// Loads the keys imported by previous runs of the table mapper of the import profile, creating
+    /// the deduplication history table if it does not exist. Keys imported before the retention
+    /// window are deleted first, such that the loaded keys are bounded by the window.
+    pub async fn load(
+        client: &mut impl SqlBackend,
+        import_profile_name: &str,
+        table_mapper_name: &str,
+        retention_days: u16,
+    ) -> Result<Self, tiberius::error::Error> {
+        let statement = formatdoc!(
+            "
+            IF OBJECT_ID(N'{history_table}', N'U') IS NULL
+            BEGIN
+                CREATE TABLE {history_table} (
+                    [import_profile] NVARCHAR(256) NOT NULL,
+                    [table_mapper] NVARCHAR(128) NOT NULL,
+                    [key] BINARY(16) NOT NULL,
+                    [imported_at] DATETIME2 NOT NULL DEFAULT SYSUTCDATETIME(),
+                    PRIMARY KEY ([import_profile], [table_mapper], [key])
+                )
+            END
+
+            DELETE FROM {history_table}
+            WHERE [import_profile] = @P1
+                AND [table_mapper] = @P2
+                AND [imported_at] < DATEADD(DAY, -@P3, SYSUTCDATETIME())
+            ",
+            history_table = HISTORY_TABLE,
+        );
+
+        trace_sql!(statement);
+
+        let retention_days = i32::from(retention_days);
+
+        let expired = client
+            .execute(
+                statement,
+                &[&import_profile_name, &table_mapper_name, &retention_days],
+            )
+            .await?
+            .total();
+
+        if expired > 0 {
+            info!(
+                "Deleted {} expired deduplication history keys for table mapper {}",
+                expired, table_mapper_name,
+            );
+        }
+
+        let statement = formatdoc!(
+            "
+            SELECT [key] FROM {history_table} WHERE [import_profile] = @P1 AND [table_mapper] = @P2
+            ",
+            history_table = HISTORY_TABLE,
+        );
+
+        trace_sql!(statement);
+
+        let previous = client
+            .query(&statement, &[&import_profile_name, &table_mapper_name])
+            .await?
+            .into_iter()
+            .filter_map(|row| {
+                row.get::<&[u8]>(0)
+                    .and_then(|key| key.try_into().ok())
+                    .map(u128::from_be_bytes)
+            })
+            .collect::<HashSet<_>>();
+
+        info!(
+            "Loaded {} deduplication history keys for table mapper {}",
+            previous.len(),
+            table_mapper_name,
+        );
+
+        Ok(Self {
+            import_profile_name: import_profile_name.to_owned(),
+            table_mapper_name: table_mapper_name.to_owned(),
+            previous,
+            current: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Saves the keys of the records read since last saved whose rows were merged, being the rows
+    /// of their record numbers remaining in the temporary table, rather than rejected or
+    /// quarantined.
+    pub async fn save(
+        &self,
+        client: &mut impl SqlBackend,
+        temporary_table: &TableIdentifier,
+    ) -> Result<(), tiberius::error::Error> {
+        let current = self.current.take();
+        let mut saved = 0;
+
+        for keys in current.chunks(HISTORY_INSERT_BATCH_SIZE) {
+            let statement = formatdoc!(
+                "
+                INSERT INTO {history_table} ([import_profile], [table_mapper], [key])
+                SELECT DISTINCT @P1, @P2, K.[key]
+                FROM (VALUES {values}) AS K ([record_number], [key])
+                WHERE EXISTS (SELECT 1 FROM {temporary_table} T WHERE T.{record_number_column} = K.[record_number])
+                    AND NOT EXISTS (
+                        SELECT 1 FROM {history_table} H
+                        WHERE H.[import_profile] = @P1 AND H.[table_mapper] = @P2 AND H.[key] = K.[key]
+                    )
+                ",
+                history_table = HISTORY_TABLE,
+                values = keys
+                    .iter()
+                    .map(|(record_number, key)| format!("({record_number}, 0x{key:032x})"))
+                    .join(",\n       "),
+                temporary_table = temporary_table.full(),
+                record_number_column = RECORD_NUMBER_COLUMN,
+            );
+
+            trace_sql!(statement);
+
+            saved += client
+                .execute(
+                    &statement,
+                    &[
+                        &self.import_profile_name.as_str(),
+                        &self.table_mapper_name.as_str(),
+                    ],
+                )
+                .await?
+                .total();
+        }
+
+        info!(
+            "Saved {} deduplication history keys for table mapper {}",
+            saved, self.table_mapper_name,
+        );
+
+        Ok(())
+    }
+
+    /// Discards the keys of the records read since last saved, whose rows were not merged.
+    pub fn discard(&self) {
+        self.current.borrow_mut().clear();
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("error deduplicating record ({index}): {source}")]
+pub struct DeduplicationReadRecordError {
+    index: DataSourceErrorIndex,
+    #[source]
+    source: std::io::Error,
+}
+
+impl DeduplicationReadRecordError {
+    fn new(record: &DataSourceRecord, source: std::io::Error) -> Self {
+        Self {
+            index: DataSourceErrorIndex {
+                record_number: Some(record.index().record_number),
+                line_number: record.index().line_start,
+            },
+            source,
+        }
+    }
+}
+
+impl ReadRecordError for DeduplicationReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+/// Tracks the records read from a data source by a 128-bit key hashed from their deduplication
+/// fields, filtering out duplicates.
+#[derive(Debug)]
+pub struct Deduplicator<'a> {
+    deduplication: &'a Deduplication,
+    history: Option<&'a DeduplicationHistory>,
+    keys: HashSet<u128>,
+    spill_runs: Vec<SpillRun>,
+    quarantine: Option<BufWriter<File>>,
+    capacity_reached: bool,
+    duplicates: u64,
+}
+
+impl<'a> Deduplicator<'a> {
+    pub fn new(
+        deduplication: &'a Deduplication,
+        history: Option<&'a DeduplicationHistory>,
+    ) -> Self {
+        Self {
+            deduplication,
+            history,
+            keys: HashSet::default(),
+            spill_runs: Vec::new(),
+            quarantine: None,
+            capacity_reached: false,
+            duplicates: 0,
+        }
+    }
+
+    /// Returns the record if no record with equal deduplication fields was previously filtered.
+    pub fn filter(
+        &mut self,
+        record: DataSourceRecord,
+    ) -> Result<Option<DataSourceRecord>, DeduplicationReadRecordError> {
+        let key = self.key(&record);
+
+        let duplicate = self.keys.contains(&key)
+            || self
+                .history
+                .is_some_and(|history| history.previous.contains(&key))
+            || self
+                .spill_runs
+                .iter_mut()
+                .map(|spill_run| spill_run.contains(key))
+                .find(|contains| !matches!(contains, Ok(false)))
+                .transpose()
+                .map_err(|err| DeduplicationReadRecordError::new(&record, err))?
+                .unwrap_or(false);
+
+        if duplicate {
+            self.duplicates += 1;
+
+            let deduplication = self.deduplication;
+
+            if let DeduplicationAction::Quarantine { path } = &deduplication.action {
+                self.quarantine(path, &record)
+                    .map_err(|err| DeduplicationReadRecordError::new(&record, err))?;
+            }
+
+            return Ok(None);
+        }
+
+        if let Some(history) = self.history {
+            history
+                .current
+                .borrow_mut()
+                .push((record.index().record_number.get(), key));
+        }
+
+        if self.keys.len() >= self.deduplication.capacity {
+            if self.deduplication.spill_to_disk {
+                self.spill()
+                    .map_err(|err| DeduplicationReadRecordError::new(&record, err))?;
+            } else {
+                if !self.capacity_reached {
+                    warn!(
+                        "Deduplication capacity of {} records reached, further distinct records will not be tracked",
+                        self.deduplication.capacity,
+                    );
+
+                    self.capacity_reached = true;
+                }
+
+                return Ok(Some(record));
+            }
+        }
+
+        self.keys.insert(key);
+
+        Ok(Some(record))
+    }
+
+    /// FNV-1a hash of the deduplication fields, stable across runs such that keys may be saved to
+    /// the deduplication history table.
+    fn key(&self, record: &DataSourceRecord) -> u128 {
+        let hash = |hash: u128, bytes: &[u8]| {
+            bytes.iter().fold(hash, |hash, &byte| {
+                (hash ^ byte as u128).wrapping_mul(FNV_PRIME)
+            })
+        };
+
+        self.deduplication
+            .fields
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |key, field| match record.get(field) {
+                Some(value) => hash(
+                    hash(hash(key, &[1]), &(value.len() as u64).to_le_bytes()),
+                    value.as_bytes(),
+                ),
+                None => hash(key, &[0]),
+            })
+    }
+
+    fn spill(&mut self) -> std::io::Result<()> {
+        let mut keys = self.keys.drain().collect::<Vec<_>>();
+
+        keys.sort_unstable();
+
+        self.spill_runs.push(SpillRun::new(&keys)?);
+
+        Ok(())
+    }
+
+    fn quarantine(&mut self, path: &Path, record: &DataSourceRecord) -> std::io::Result<()> {
+        if self.quarantine.is_none() {
+            self.quarantine = Some(BufWriter::new(
+                File::options().create(true).append(true).open(path)?,
+            ));
+        }
+
+        let quarantine = self
+            .quarantine
+            .as_mut()
+            .expect("Quarantine file should be open");
+
+        let index = record.index();
+        let line = serde_json::json!({
+            "record_number": index.record_number,
+            "line_start": index.line_start,
+            "line_end": index.line_end,
+            "byte_start": index.byte_start,
+            "byte_end": index.byte_end,
+            "fields": record
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), serde_json::Value::from(value)))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+
+        serde_json::to_writer(&mut *quarantine, &line)?;
+        quarantine.write_all(b"\n")
+    }
+}
+
+impl Drop for Deduplicator<'_> {
+    fn drop(&mut self) {
+        if let Some(quarantine) = &mut self.quarantine
+            && let Err(err) = quarantine.flush()
+        {
+            warn!("Could not flush deduplication quarantine file: {}", err);
+        }
+
+        if self.duplicates > 0 {
+            info!(
+                "Deduplication filtered {} duplicate records",
+                self.duplicates
+            );
+        }
+    }
+}
+
+/// Sorted keys spilled to a temporary file, removed once dropped.
+#[derive(Debug)]
+struct SpillRun {
+    path: PathBuf,
+    file: Option<File>,
+    len: u64,
+}
+
+impl SpillRun {
+    fn new(keys: &[u128]) -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_{}_{}.dedup",
+            std::process::id(),
+            SPILL_RUN_COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+
+        let mut writer = BufWriter::new(
+            File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?,
+        );
+
+        for key in keys {
+            writer.write_all(&key.to_be_bytes())?;
+        }
+
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+
+        Ok(Self {
+            path,
+            file: Some(file),
+            len: keys.len() as u64,
+        })
+    }
+
+    fn contains(&mut self, key: u128) -> std::io::Result<bool> {
+        let file = self.file.as_mut().expect("Spill run file should be open");
+        let mut buffer = [0u8; KEY_SIZE as usize];
+        let (mut low, mut high) = (0, self.len);
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+
+            file.seek(SeekFrom::Start(middle * KEY_SIZE))?;
+            file.read_exact(&mut buffer)?;
+
+            match u128::from_be_bytes(buffer).cmp(&key) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(true),
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl Drop for SpillRun {
+    fn drop(&mut self) {
+        drop(self.file.take());
+
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!(
+                "Could not remove deduplication spill file {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSourceRecordIndex;
+    use crate::data_source::string_map::StringMap;
+    use std::num::NonZero;
+
+    fn record(record_number: u64, code: &str, name: &str) -> DataSourceRecord {
+        DataSourceRecord::new(
+            StringMap::from([("code", code), ("name", name)]),
+            DataSourceRecordIndex {
+                record_number: NonZero::new(record_number).unwrap(),
+                line_start: record_number,
+                line_end: record_number,
+                byte_start: 0,
+                byte_end: 0,
+            },
+        )
+    }
+
+    fn deduplication(capacity: usize, spill_to_disk: bool) -> Deduplication {
+        Deduplication {
+            fields: vec!["code".to_owned()],
+            action: DeduplicationAction::Drop,
+            capacity,
+            spill_to_disk,
+            history: false,
+            history_retention_days: Deduplication::default_history_retention_days(),
+        }
+    }
+
+    fn filtered(deduplication: &Deduplication, records: Vec<DataSourceRecord>) -> Vec<u64> {
+        let mut deduplicator = Deduplicator::new(deduplication, None);
+
+        records
+            .into_iter()
+            .filter_map(|record| deduplicator.filter(record).unwrap())
+            .map(|record| record.index().record_number.get())
+            .collect()
+    }
+
+    fn records() -> Vec<DataSourceRecord> {
+        vec![
+            record(1, "GBP", "Pound"),
+            record(2, "EUR", "Euro"),
+            record(3, "GBP", "Pound Sterling"),
+            record(4, "USD", "Dollar"),
+            record(5, "EUR", "Euro"),
+        ]
+    }
+
+    #[test]
+    fn deduplicates_on_fields() {
+        assert_eq!(filtered(&deduplication(10, false), records()), [1, 2, 4]);
+    }
+
+    #[test]
+    fn deduplicates_across_spill_runs() {
+        assert_eq!(filtered(&deduplication(1, true), records()), [1, 2, 4]);
+    }
+
+    #[test]
+    fn stops_tracking_at_capacity() {
+        assert_eq!(filtered(&deduplication(1, false), records()), [1, 2, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn saves_history_keys_of_import_profile() -> Result<(), Box<dyn std::error::Error>> {
+        let history = DeduplicationHistory {
+            import_profile_name: "Currencies".to_owned(),
+            table_mapper_name: "Currency".to_owned(),
+            previous: HashSet::default(),
+            current: RefCell::new(vec![(1, 2), (3, 1)]),
+        };
+
+        let mut backend = crate::backend::MockBackend::new();
+
+        backend.push_result([2]);
+
+        history
+            .save(&mut backend, &"[import].[Currency]".parse()?)
+            .await?;
+
+        let statements = backend.statements();
+
+        assert_eq!(statements.len(), 1);
+        assert!(statements[0].statement.contains(
+            "INSERT INTO [import].[deduplication_history] ([import_profile], [table_mapper], [key])"
+        ));
+        assert!(statements[0].statement.contains(&format!(
+            "FROM (VALUES (1, 0x{:032x}),\n       (3, 0x{:032x}))",
+            2, 1
+        )));
+        assert!(
+            statements[0].statement.contains(
+                "FROM [import].[Currency] T WHERE T.[$record_number] = K.[record_number]"
+            )
+        );
+
+        history.current.borrow_mut().push((4, 3));
+        history.discard();
+        history
+            .save(&mut backend, &"[import].[Currency]".parse()?)
+            .await?;
+
+        assert_eq!(backend.statements().len(), 1);
+
+        Ok(())
+    }
+}
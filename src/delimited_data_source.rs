@@ -1,30 +1,60 @@
 mod delimited_data_source_stream;
+mod simd_reader;
+
+pub use delimited_data_source_stream::SkipFooter;
 
 use crate::data_source::string_map::StringMap;
 use crate::data_source::{DataSourceRecord, DataSourceRecordIndex};
+use crate::data_source_file::{DataSourceFile, skip_utf8_bom};
+use crate::delimited_data_source::simd_reader::SimdReader;
 use crate::import_profile::{
     DelimitedReaderConfig, DelimitedReaderCustomConfig, Field, Terminator,
 };
 use csv_core::{ReadRecordResult, Reader};
+use encoding_rs::{Encoding, UTF_8};
+use futures::StreamExt;
 use indexmap::{IndexMap, IndexSet};
 use rustc_hash::FxBuildHasher as BuildHasher;
+use serde::{Deserialize, Serialize};
 use std::char::TryFromCharError;
+use std::io::SeekFrom;
 use std::num::NonZero;
 use std::path::Path;
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 use std::sync::Arc;
 use thiserror::Error;
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncSeek, AsyncSeekExt, BufReader};
 
 #[derive(Debug)]
 pub struct DelimitedDataSource<R> {
     buf_reader: BufReader<R>,
     fields: IndexSet<Arc<str>, BuildHasher>,
-    reader: Reader,
+    reader: DelimitedReader,
     record_buffer: RecordBuffer,
     record_number: Option<NonZero<u64>>,
+    /// Bytes of the data source file consumed by the reader so far
+    byte_offset: u64,
+    /// Byte offset at which the reader started reading the current record
+    record_byte_start: u64,
+    /// Name of the field to hold the raw payload of each record, and the raw payload read so far
+    raw_payload: Option<(Arc<str>, Vec<u8>)>,
+}
+
+/// Position of a delimited data source after its last read record, from which reading may be
+/// resumed without parsing the preceding records again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelimitedCheckpoint {
+    byte_offset: u64,
+    record_number: u64,
+    line_number: u64,
+}
+
+/// Splitter of the records of the data source, with csv_core supporting every reader config.
+#[derive(Debug)]
+enum DelimitedReader {
+    CsvCore(Reader),
+    Simd(SimdReader),
 }
 
 #[derive(Debug)]
@@ -43,6 +73,16 @@ pub enum CreateDelimitedDataSourceError {
     ReadError(#[from] ReadDelimitedDataSourceError),
     #[error("missing fields [{}] from header", .0.join(", "))]
     MissingFields(Vec<String>),
+    #[error(transparent)]
+    SeekError(#[from] SeekDelimitedDataSourceError),
+}
+
+#[derive(Debug, Error)]
+pub enum SeekDelimitedDataSourceError {
+    #[error("could not seek data source file to byte offset {0}")]
+    SeekError(u64, #[source] std::io::Error),
+    #[error("data source file ended before record {0} of the checkpoint")]
+    EndedBeforeCheckpoint(u64),
 }
 
 #[derive(Debug, Error)]
@@ -55,6 +95,14 @@ pub enum ReadDelimitedDataSourceError {
     ParseHeadersError(#[from] Utf8Error),
 }
 
+#[derive(Debug, Error)]
+pub enum SplitRecordError {
+    #[error("could not build delimited reader")]
+    CreateDelimitedReaderError(#[from] BuildDelimitedReaderError),
+    #[error("invalid UTF-8 characters in record")]
+    Utf8Error(#[from] Utf8Error),
+}
+
 #[derive(Debug, Error)]
 pub enum BuildDelimitedReaderError {
     #[error("delimiter character is invalid")]
@@ -69,17 +117,20 @@ pub enum BuildDelimitedReaderError {
     Escape(#[source] TryFromCharError),
 }
 
-impl DelimitedDataSource<File> {
+impl DelimitedDataSource<DataSourceFile> {
     pub async fn new<'fields>(
         path: impl AsRef<Path>,
         fields: impl IntoIterator<Item = &'fields Field>,
         config: DelimitedReaderConfig,
+        raw_payload_field: Option<&str>,
     ) -> Result<Self, CreateDelimitedDataSourceError> {
-        let file = File::open(&path).await?;
-        let buf_reader = BufReader::new(file);
-        let delimited_data_source =
+        let buf_reader = DataSourceFile::open_text(&path, config.encoding()).await?;
+        let mut delimited_data_source =
             DelimitedDataSource::with_buf_reader(config, buf_reader).await?;
 
+        delimited_data_source.raw_payload =
+            raw_payload_field.map(|raw_payload_field| (Arc::from(raw_payload_field), Vec::new()));
+
         let missing_field_names: Vec<String> = fields
             .into_iter()
             .filter_map(|field| {
@@ -99,6 +150,80 @@ impl DelimitedDataSource<File> {
 
         Ok(delimited_data_source)
     }
+
+    /// Creates the data source, resuming from the checkpoint rather than the first record. Files
+    /// which cannot be seeked, such as compressed files, are read past the preceding records.
+    pub async fn resume<'fields>(
+        path: impl AsRef<Path>,
+        fields: impl IntoIterator<Item = &'fields Field>,
+        config: DelimitedReaderConfig,
+        raw_payload_field: Option<&str>,
+        checkpoint: &DelimitedCheckpoint,
+    ) -> Result<Self, CreateDelimitedDataSourceError> {
+        let mut delimited_data_source = Self::new(path, fields, config, raw_payload_field).await?;
+
+        if delimited_data_source.buf_reader.get_ref().seekable() {
+            delimited_data_source.seek(checkpoint).await?;
+        } else {
+            while delimited_data_source.record_number.map_or(0, NonZero::get)
+                < checkpoint.record_number
+            {
+                if delimited_data_source.next().await.is_none() {
+                    return Err(SeekDelimitedDataSourceError::EndedBeforeCheckpoint(
+                        checkpoint.record_number,
+                    )
+                    .into());
+                }
+            }
+        }
+
+        Ok(delimited_data_source)
+    }
+}
+
+impl DelimitedDataSource<DataSourceFile> {
+    /// Counts the records of the data source file, excluding the header, without collecting the
+    /// fields of each record.
+    pub async fn count_records(
+        path: impl AsRef<Path>,
+        config: DelimitedReaderConfig,
+    ) -> Result<u64, CreateDelimitedDataSourceError> {
+        let mut buf_reader = DataSourceFile::open_text(&path, config.encoding()).await?;
+        let mut reader = config
+            .build_reader()
+            .map_err(ReadDelimitedDataSourceError::from)?;
+
+        skip_utf8_bom(&mut buf_reader).await?;
+        skip_lines(
+            &mut buf_reader,
+            config.skip_rows(),
+            config.terminator_byte(),
+        )
+        .await?;
+        let mut output_buffer = [0; 4096];
+        let mut ends_buffer = [0; 256];
+        let mut records = 0u64;
+
+        loop {
+            let (result, bytes_fill_buf, _, _) = reader.read_record(
+                buf_reader.fill_buf().await?,
+                &mut output_buffer,
+                &mut ends_buffer,
+            );
+
+            buf_reader.consume(bytes_fill_buf);
+
+            match result {
+                ReadRecordResult::InputEmpty
+                | ReadRecordResult::OutputFull
+                | ReadRecordResult::OutputEndsFull => continue,
+                ReadRecordResult::Record => records += 1,
+                ReadRecordResult::End => break,
+            }
+        }
+
+        Ok(records.saturating_sub(1 + config.skip_footer_rows()))
+    }
 }
 
 impl<R: AsyncRead + Unpin> DelimitedDataSource<R> {
@@ -108,6 +233,18 @@ impl<R: AsyncRead + Unpin> DelimitedDataSource<R> {
     ) -> Result<Self, ReadDelimitedDataSourceError> {
         let mut reader = config.build_reader()?;
         let mut record_buffer = RecordBuffer::default();
+        // the byte order mark is counted in the byte offset, which is an offset into the file
+        let mut byte_offset = skip_utf8_bom(&mut buf_reader).await? as u64;
+
+        if config.skip_rows() > 0 {
+            byte_offset += skip_lines(
+                &mut buf_reader,
+                config.skip_rows(),
+                config.terminator_byte(),
+            )
+            .await?;
+            reader.set_line(reader.line() + config.skip_rows());
+        }
 
         loop {
             break match buf_reader.fill_buf().await {
@@ -120,6 +257,7 @@ impl<R: AsyncRead + Unpin> DelimitedDataSource<R> {
 
                     buf_reader.consume(bytes_fill_buf);
 
+                    byte_offset += bytes_fill_buf as u64;
                     record_buffer.output_used += bytes_output;
                     record_buffer.ends_used += bytes_end;
 
@@ -155,6 +293,9 @@ impl<R: AsyncRead + Unpin> DelimitedDataSource<R> {
                                 reader,
                                 record_buffer,
                                 record_number: None,
+                                byte_offset,
+                                record_byte_start: byte_offset,
+                                raw_payload: None,
                             })
                         }
                         ReadRecordResult::End => {
@@ -168,6 +309,68 @@ impl<R: AsyncRead + Unpin> DelimitedDataSource<R> {
     }
 }
 
+/// Skips the lines of the reader, returning the bytes skipped, without parsing them as records.
+async fn skip_lines<R: AsyncRead + Unpin>(
+    buf_reader: &mut BufReader<R>,
+    lines: u64,
+    terminator: u8,
+) -> std::io::Result<u64> {
+    let mut skipped = Vec::new();
+    let mut bytes = 0;
+
+    for _ in 0..lines {
+        skipped.clear();
+
+        let read = buf_reader.read_until(terminator, &mut skipped).await?;
+
+        if read == 0 {
+            break;
+        }
+
+        bytes += read as u64;
+    }
+
+    Ok(bytes)
+}
+
+impl DelimitedCheckpoint {
+    /// Position after the record, as read from a delimited data source.
+    pub fn after(index: &DataSourceRecordIndex) -> Self {
+        DelimitedCheckpoint {
+            byte_offset: index.byte_end,
+            record_number: index.record_number.get(),
+            line_number: index.line_end + 1,
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> DelimitedDataSource<R> {
+    /// Seeks to the position of the checkpoint, such that the next record read is the record
+    /// following the last record read before the checkpoint was taken.
+    async fn seek(
+        &mut self,
+        checkpoint: &DelimitedCheckpoint,
+    ) -> Result<(), SeekDelimitedDataSourceError> {
+        self.buf_reader
+            .seek(SeekFrom::Start(checkpoint.byte_offset))
+            .await
+            .map_err(|err| SeekDelimitedDataSourceError::SeekError(checkpoint.byte_offset, err))?;
+
+        self.reader.reset();
+        self.reader.set_line(checkpoint.line_number);
+        self.record_buffer.clear();
+        self.record_number = NonZero::new(checkpoint.record_number);
+        self.byte_offset = checkpoint.byte_offset;
+        self.record_byte_start = checkpoint.byte_offset;
+
+        if let Some((_, raw_payload)) = self.raw_payload.as_mut() {
+            raw_payload.clear();
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 enum ParseRecordError {
     #[error("invalid UTF-8 characters in record")]
@@ -202,25 +405,55 @@ impl RecordBuffer {
         &mut self,
         field_names: &IndexSet<Arc<str>, BuildHasher>,
         index: DataSourceRecordIndex,
+        raw_payload: Option<(&Arc<str>, Vec<u8>)>,
     ) -> Result<DataSourceRecord, ParseRecordError> {
-        if self.ends_buffer[..self.ends_used].len() < field_names.len() {
+        // A blank line is read as a single empty field, so records without any data are read as
+        // a record of empty fields instead, such that they are handled as empty records
+        let empty = self.ends_buffer[..self.ends_used]
+            .iter()
+            .all(|&end| end == 0);
+
+        if self.ends_buffer[..self.ends_used].len() < field_names.len() && !empty {
             self.clear();
             return Err(ParseRecordError::TooFewFields);
         }
 
-        let field_indices: Result<IndexMap<Arc<str>, usize, _>, ParseRecordError> = self
-            .ends_buffer[..self.ends_used]
-            .iter()
-            .enumerate()
-            .map(|(idx, &curr)| match field_names.get_index(idx) {
-                Some(field) => Ok((field.clone(), curr)),
-                None => Err(ParseRecordError::TooManyFields),
-            })
-            .collect();
-
-        let field_data = self.clear();
+        let field_indices: Result<IndexMap<Arc<str>, usize, _>, ParseRecordError> = if empty {
+            Ok(field_names.iter().map(|field| (field.clone(), 0)).collect())
+        } else {
+            self.ends_buffer[..self.ends_used]
+                .iter()
+                .enumerate()
+                .map(|(idx, &curr)| match field_names.get_index(idx) {
+                    Some(field) => Ok((field.clone(), curr)),
+                    None => Err(ParseRecordError::TooManyFields),
+                })
+                .collect()
+        };
+
+        let mut field_data = self.clear();
+        let mut field_indices = field_indices?;
+
+        if let Some((raw_payload_field, mut raw_payload)) = raw_payload {
+            // Trim the terminators surrounding the record, which csv_core consumes along with it
+            let end = raw_payload
+                .iter()
+                .rposition(|byte| !matches!(byte, b'\r' | b'\n'))
+                .map_or(0, |end| end + 1);
+
+            raw_payload.truncate(end);
+
+            let start = raw_payload
+                .iter()
+                .position(|byte| !matches!(byte, b'\r' | b'\n'))
+                .unwrap_or(end);
+
+            field_data.truncate(field_indices.last().map_or(0, |(_, &end)| end));
+            field_data.extend_from_slice(&raw_payload[start..]);
+            field_indices.insert(raw_payload_field.clone(), field_data.len());
+        }
 
-        let fields = unsafe { StringMap::new(String::from_utf8(field_data)?, field_indices?) };
+        let fields = unsafe { StringMap::new(String::from_utf8(field_data)?, field_indices) };
 
         Ok(DataSourceRecord::new(fields, index))
     }
@@ -238,13 +471,17 @@ impl Default for RecordBuffer {
 }
 
 impl DelimitedReaderConfig {
-    fn build_reader(self) -> Result<Reader, BuildDelimitedReaderError> {
+    fn build_reader(self) -> Result<DelimitedReader, BuildDelimitedReaderError> {
         use BuildDelimitedReaderError::*;
         use csv_core::ReaderBuilder;
 
         Ok(match self {
-            DelimitedReaderConfig::Csv => Reader::new(),
-            DelimitedReaderConfig::Txt => ReaderBuilder::new().delimiter(b'\t').build(),
+            DelimitedReaderConfig::Csv => DelimitedReader::CsvCore(Reader::new()),
+            DelimitedReaderConfig::Txt => {
+                DelimitedReader::CsvCore(ReaderBuilder::new().delimiter(b'\t').build())
+            }
+            DelimitedReaderConfig::SimdCsv => DelimitedReader::Simd(SimdReader::new(b',')),
+            DelimitedReaderConfig::SimdTxt => DelimitedReader::Simd(SimdReader::new(b'\t')),
             DelimitedReaderConfig::Custom(DelimitedReaderCustomConfig {
                 delimiter,
                 terminator,
@@ -253,19 +490,137 @@ impl DelimitedReaderConfig {
                 comment,
                 escape,
                 double_quote,
-            }) => ReaderBuilder::new()
-                .delimiter(delimiter.try_into().map_err(Delimiter)?)
-                .terminator(terminator.try_into().map_err(Terminator)?)
-                .quote(quote.try_into().map_err(Quote)?)
-                .quoting(quoting)
-                .comment(comment.map(u8::try_from).transpose().map_err(Comment)?)
-                .escape(escape.map(u8::try_from).transpose().map_err(Escape)?)
-                .double_quote(double_quote)
-                .build(),
+                ..
+            }) => DelimitedReader::CsvCore(
+                ReaderBuilder::new()
+                    .delimiter(delimiter.try_into().map_err(Delimiter)?)
+                    .terminator(terminator.try_into().map_err(Terminator)?)
+                    .quote(quote.try_into().map_err(Quote)?)
+                    .quoting(quoting)
+                    .comment(comment.map(u8::try_from).transpose().map_err(Comment)?)
+                    .escape(escape.map(u8::try_from).transpose().map_err(Escape)?)
+                    .double_quote(double_quote)
+                    .build(),
+            ),
         })
     }
 }
 
+impl DelimitedReaderConfig {
+    /// Byte terminating each line, being LF for CRLF terminators, which CR LF ends with.
+    fn terminator_byte(self) -> u8 {
+        match self {
+            DelimitedReaderConfig::Custom(DelimitedReaderCustomConfig {
+                terminator: Terminator::Any(terminator),
+                ..
+            }) => u8::try_from(terminator).unwrap_or(b'\n'),
+            _ => b'\n',
+        }
+    }
+
+    fn skip_rows(self) -> u64 {
+        match self {
+            DelimitedReaderConfig::Custom(custom) => custom.skip_rows,
+            _ => 0,
+        }
+    }
+
+    pub fn skip_footer_rows(self) -> u64 {
+        match self {
+            DelimitedReaderConfig::Custom(custom) => custom.skip_footer_rows,
+            _ => 0,
+        }
+    }
+
+    /// Encoding of the data source file, when other than UTF-8.
+    fn encoding(self) -> Option<&'static Encoding> {
+        match self {
+            DelimitedReaderConfig::Custom(DelimitedReaderCustomConfig {
+                encoding: Some(encoding),
+                ..
+            }) if encoding != UTF_8 => Some(encoding),
+            _ => None,
+        }
+    }
+}
+
+impl DelimitedReaderConfig {
+    /// Splits a single record, such as the payload of a message, into its fields.
+    pub(crate) fn split_record(self, mut input: &[u8]) -> Result<Vec<String>, SplitRecordError> {
+        let mut reader = self.build_reader()?;
+        let mut record_buffer = RecordBuffer::default();
+
+        loop {
+            let (result, bytes_input, bytes_output, bytes_end) = reader.read_record(
+                input,
+                &mut record_buffer.output_buffer[record_buffer.output_used..],
+                &mut record_buffer.ends_buffer[record_buffer.ends_used..],
+            );
+
+            // once the input is consumed, reading empty input ends the record
+            input = &input[bytes_input..];
+            record_buffer.output_used += bytes_output;
+            record_buffer.ends_used += bytes_end;
+
+            match result {
+                ReadRecordResult::InputEmpty => continue,
+                ReadRecordResult::OutputFull => record_buffer.expand_output(),
+                ReadRecordResult::OutputEndsFull => record_buffer.expand_ends(),
+                ReadRecordResult::Record | ReadRecordResult::End => break,
+            }
+        }
+
+        let mut start = 0;
+
+        record_buffer.ends_buffer[..record_buffer.ends_used]
+            .iter()
+            .map(|&end| {
+                let field = str::from_utf8(&record_buffer.output_buffer[start..end])?;
+
+                start = end;
+
+                Ok(field.to_owned())
+            })
+            .collect()
+    }
+}
+
+impl DelimitedReader {
+    #[inline(always)]
+    fn read_record(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        ends: &mut [usize],
+    ) -> (ReadRecordResult, usize, usize, usize) {
+        match self {
+            DelimitedReader::CsvCore(reader) => reader.read_record(input, output, ends),
+            DelimitedReader::Simd(reader) => reader.read_record(input, output, ends),
+        }
+    }
+
+    fn line(&self) -> u64 {
+        match self {
+            DelimitedReader::CsvCore(reader) => reader.line(),
+            DelimitedReader::Simd(reader) => reader.line(),
+        }
+    }
+
+    fn set_line(&mut self, line: u64) {
+        match self {
+            DelimitedReader::CsvCore(reader) => reader.set_line(line),
+            DelimitedReader::Simd(reader) => reader.set_line(line),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            DelimitedReader::CsvCore(reader) => reader.reset(),
+            DelimitedReader::Simd(reader) => reader.reset(),
+        }
+    }
+}
+
 impl TryFrom<Terminator> for csv_core::Terminator {
     type Error = TryFromCharError;
 
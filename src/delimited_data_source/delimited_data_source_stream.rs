@@ -1,14 +1,31 @@
 use crate::data_source::{
     DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, ReadRecordError,
 };
-use crate::delimited_data_source::{DelimitedDataSource, ParseRecordError};
+use crate::data_source_file::DataSourceFile;
+use crate::delimited_data_source::{
+    CreateDelimitedDataSourceError, DelimitedDataSource, ParseRecordError,
+    ReadDelimitedDataSourceError, RecordBuffer,
+};
+use crate::import_profile::{DelimitedReaderConfig, Field};
 use csv_core::ReadRecordResult;
-use futures::Stream;
+use futures::channel::mpsc;
+use futures::future::ready;
+use futures::stream::BoxStream;
+use futures::{SinkExt, Stream, StreamExt, stream};
+use itertools::Itertools;
+use log::warn;
+use std::collections::VecDeque;
+use std::io::SeekFrom;
 use std::num::NonZero;
+use std::path::Path;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use thiserror::Error;
-use tokio::io::{AsyncBufReadExt, AsyncRead};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader, Take};
+
+/// Records buffered by each chunk of a data source parsed in parallel.
+const CHUNK_CHANNEL_CAPACITY: usize = 1024;
 
 #[derive(Debug, Error)]
 #[error("error reading delimited record ({index}): {source}")]
@@ -35,12 +52,232 @@ impl DelimitedReadRecordError {
     }
 }
 
+impl DelimitedReadRecordError {
+    fn offset_index(mut self, records: u64, lines: u64) -> Self {
+        self.index.record_number = self
+            .index
+            .record_number
+            .map(|record_number| record_number.saturating_add(records));
+        self.index.line_number += lines;
+        self
+    }
+}
+
 impl ReadRecordError for DelimitedReadRecordError {
     fn index(&self) -> DataSourceErrorIndex {
         self.index
     }
 }
 
+enum ChunkItem {
+    Record(Result<DataSourceRecord, DelimitedReadRecordError>),
+    /// Records and lines read by the chunk, sent once the chunk has been read entirely
+    End {
+        records: u64,
+        lines: u64,
+    },
+}
+
+impl DelimitedDataSource<DataSourceFile> {
+    /// Creates a stream of the records of the data source, parsing the file in chunks in parallel
+    /// tasks, yet yielding records in order.
+    ///
+    /// The file is split into chunks on line terminators, so no quoted field may contain one.
+    /// Compressed or transcoded files and objects cannot be split into chunks, so are parsed
+    /// sequentially instead.
+    pub async fn parallel<'fields>(
+        path: impl AsRef<Path>,
+        fields: impl IntoIterator<Item = &'fields Field>,
+        config: DelimitedReaderConfig,
+        raw_payload_field: Option<&str>,
+        parallelism: usize,
+    ) -> Result<
+        BoxStream<'static, Result<DataSourceRecord, DelimitedReadRecordError>>,
+        CreateDelimitedDataSourceError,
+    > {
+        let path = path.as_ref().to_owned();
+        let header = Self::new(&path, fields, config, raw_payload_field).await?;
+
+        if !header.buf_reader.get_ref().seekable() {
+            warn!(
+                "Parsing data source file {} sequentially, as compressed or transcoded files and objects cannot be split into chunks",
+                path.display(),
+            );
+
+            return Ok(header.boxed());
+        }
+
+        let header_lines = header.reader.line() - 1;
+
+        let chunk_boundaries = Self::chunk_boundaries(
+            &path,
+            header.byte_offset,
+            config.terminator_byte(),
+            parallelism.max(1),
+        )
+        .await?;
+
+        let mut receivers = Vec::with_capacity(chunk_boundaries.len());
+
+        for (&start, &end) in chunk_boundaries.iter().tuple_windows() {
+            let mut file = File::open(&path).await?;
+
+            file.seek(SeekFrom::Start(start)).await?;
+
+            let mut chunk: DelimitedDataSource<Take<File>> = DelimitedDataSource {
+                buf_reader: BufReader::new(file.take(end - start)),
+                fields: header.fields.clone(),
+                reader: config
+                    .build_reader()
+                    .map_err(ReadDelimitedDataSourceError::from)?,
+                record_buffer: RecordBuffer::default(),
+                record_number: None,
+                byte_offset: start,
+                record_byte_start: start,
+                raw_payload: header
+                    .raw_payload
+                    .as_ref()
+                    .map(|(raw_payload_field, _)| (raw_payload_field.clone(), Vec::new())),
+            };
+
+            let (mut sender, receiver) = mpsc::channel(CHUNK_CHANNEL_CAPACITY);
+
+            tokio::spawn(async move {
+                while let Some(result) = chunk.next().await {
+                    if sender.send(ChunkItem::Record(result)).await.is_err() {
+                        return;
+                    }
+                }
+
+                let _ = sender
+                    .send(ChunkItem::End {
+                        records: chunk.record_number.map_or(0, NonZero::get),
+                        lines: chunk.reader.line() - 1,
+                    })
+                    .await;
+            });
+
+            receivers.push(receiver);
+        }
+
+        Ok(stream::iter(receivers)
+            .flatten()
+            .scan((0, header_lines), |(records, lines), item| {
+                ready(Some(match item {
+                    ChunkItem::Record(Ok(mut record)) => {
+                        record.offset_index(*records, *lines);
+                        Some(Ok(record))
+                    }
+                    ChunkItem::Record(Err(err)) => Some(Err(err.offset_index(*records, *lines))),
+                    ChunkItem::End {
+                        records: chunk_records,
+                        lines: chunk_lines,
+                    } => {
+                        *records += chunk_records;
+                        *lines += chunk_lines;
+                        None
+                    }
+                }))
+            })
+            .filter_map(ready)
+            .boxed())
+    }
+
+    /// Byte offsets splitting the records of the file into roughly equal chunks, each offset
+    /// directly following a terminator, starting at the first record and ending at the end of the
+    /// file.
+    async fn chunk_boundaries(
+        path: &Path,
+        start: u64,
+        terminator: u8,
+        chunks: usize,
+    ) -> Result<Vec<u64>, std::io::Error> {
+        let mut buf_reader = BufReader::new(File::open(path).await?);
+        let end = buf_reader.get_ref().metadata().await?.len();
+        let chunk_length = (end.saturating_sub(start) / chunks as u64).max(1);
+
+        let mut boundaries = vec![start];
+        let mut skipped = Vec::new();
+
+        for chunk in 1..chunks as u64 {
+            let nominal = start + chunk * chunk_length;
+
+            if nominal >= end || nominal <= *boundaries.last().expect("always non-empty") {
+                continue;
+            }
+
+            // Read from the byte before, such that a chunk starting directly after a terminator
+            // is not moved on to the following terminator
+            buf_reader.seek(SeekFrom::Start(nominal - 1)).await?;
+            skipped.clear();
+
+            let read = buf_reader.read_until(terminator, &mut skipped).await? as u64;
+            let boundary = nominal - 1 + read;
+
+            if skipped.last() == Some(&terminator)
+                && boundary < end
+                && boundary > *boundaries.last().expect("always non-empty")
+            {
+                boundaries.push(boundary);
+            }
+        }
+
+        boundaries.push(end);
+
+        Ok(boundaries)
+    }
+}
+
+/// Stream of the records of a delimited data source, holding back the last records read until the
+/// end of the data source, such that its footer rows are skipped rather than read as records.
+#[derive(Debug)]
+pub struct SkipFooter<S: Stream> {
+    stream: S,
+    buffer: VecDeque<S::Item>,
+    rows: usize,
+}
+
+impl<S: Stream> SkipFooter<S> {
+    pub fn new(stream: S, rows: u64) -> Self {
+        let rows = usize::try_from(rows).unwrap_or(usize::MAX);
+
+        Self {
+            stream,
+            buffer: VecDeque::with_capacity(rows.saturating_add(1).min(1024)),
+            rows,
+        }
+    }
+}
+
+impl<S: Stream + Unpin> Stream for SkipFooter<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let SkipFooter {
+            stream,
+            buffer,
+            rows,
+        } = &mut *self;
+
+        loop {
+            match stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(item)) => {
+                    buffer.push_back(item);
+
+                    if buffer.len() > *rows {
+                        return Poll::Ready(buffer.pop_front());
+                    }
+                }
+                Poll::Ready(None) => {
+                    buffer.clear();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
 impl<R: AsyncRead + Unpin> Stream for DelimitedDataSource<R> {
     type Item = Result<DataSourceRecord, DelimitedReadRecordError>;
 
@@ -51,6 +288,9 @@ impl<R: AsyncRead + Unpin> Stream for DelimitedDataSource<R> {
             reader,
             record_buffer,
             record_number,
+            byte_offset,
+            record_byte_start,
+            raw_payload,
         } = &mut *self;
 
         let line_start = reader.line();
@@ -76,8 +316,13 @@ impl<R: AsyncRead + Unpin> Stream for DelimitedDataSource<R> {
                 &mut record_buffer.ends_buffer[record_buffer.ends_used..],
             );
 
+            if let Some((_, raw_payload)) = raw_payload.as_mut() {
+                raw_payload.extend_from_slice(&fill_buf[..bytes_fill_buf]);
+            }
+
             buf_reader.consume(bytes_fill_buf);
 
+            *byte_offset += bytes_fill_buf as u64;
             record_buffer.output_used += bytes_output;
             record_buffer.ends_used += bytes_end;
 
@@ -94,6 +339,8 @@ impl<R: AsyncRead + Unpin> Stream for DelimitedDataSource<R> {
                 ReadRecordResult::Record => {
                     *record_number = NonZero::new(record_number.map_or(1, |r| r.get() + 1));
 
+                    let byte_start = std::mem::replace(record_byte_start, *byte_offset);
+
                     return Poll::Ready(Some(
                         record_buffer
                             .create_data_source_record(
@@ -102,7 +349,14 @@ impl<R: AsyncRead + Unpin> Stream for DelimitedDataSource<R> {
                                     record_number: record_number.expect("always non-zero"),
                                     line_start,
                                     line_end: reader.line() - 1,
+                                    byte_start,
+                                    byte_end: *byte_offset,
                                 },
+                                raw_payload
+                                    .as_mut()
+                                    .map(|(raw_payload_field, raw_payload)| {
+                                        (&*raw_payload_field, std::mem::take(raw_payload))
+                                    }),
                             )
                             .map_err(|err| {
                                 DelimitedReadRecordError::new(
@@ -124,12 +378,10 @@ impl<R: AsyncRead + Unpin> Stream for DelimitedDataSource<R> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::delimited_data_source::ReadDelimitedDataSourceError;
-    use crate::import_profile::DelimitedReaderConfig;
-    use futures::StreamExt;
+    use crate::delimited_data_source::DelimitedCheckpoint;
+    use crate::import_profile::DelimitedReaderCustomConfig;
     use indexmap::IndexSet;
     use std::io::Cursor;
-    use tokio::io::BufReader;
 
     #[tokio::test]
     async fn create_delimited_data_source() -> Result<(), ReadDelimitedDataSourceError> {
@@ -151,6 +403,64 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn skip_byte_order_mark() -> Result<(), ReadDelimitedDataSourceError> {
+        let data = "\u{FEFF}Code,Name\nGBP,Pound";
+
+        let buf_reader = BufReader::new(Cursor::new(data));
+        let mut delimited_data_source =
+            DelimitedDataSource::with_buf_reader(DelimitedReaderConfig::Csv, buf_reader).await?;
+
+        assert_eq!(
+            delimited_data_source.fields,
+            IndexSet::from(["Code".into(), "Name".into()])
+        );
+
+        let record = delimited_data_source.next().await.unwrap().unwrap();
+
+        assert_eq!(record.get("Code").unwrap(), "GBP");
+        assert_eq!(
+            record.index().byte_start,
+            "\u{FEFF}Code,Name\n".len() as u64
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_title_and_footer_rows() -> Result<(), ReadDelimitedDataSourceError> {
+        let data =
+            "Currency report\n\"Generated 2026-10-15\nCode,Name\nGBP,Pound\nUSD,Dollar\nTotal,2";
+        let config = DelimitedReaderConfig::Custom(DelimitedReaderCustomConfig {
+            delimiter: ',',
+            skip_rows: 2,
+            skip_footer_rows: 1,
+            ..DelimitedReaderCustomConfig::default()
+        });
+
+        let buf_reader = BufReader::new(Cursor::new(data));
+        let delimited_data_source =
+            DelimitedDataSource::with_buf_reader(config, buf_reader).await?;
+
+        assert_eq!(
+            delimited_data_source.fields,
+            IndexSet::from(["Code".into(), "Name".into()])
+        );
+
+        let records = SkipFooter::new(delimited_data_source, config.skip_footer_rows())
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        itertools::assert_equal(
+            records.iter().map(|record| record.get("Code").unwrap()),
+            ["GBP", "USD"],
+        );
+        assert_eq!(records[0].index().line_start, 4);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn read_delimited_data_source() -> Result<(), ReadDelimitedDataSourceError> {
         let data = "A,B,C\na1,b1,c1\na2\na3,b3,c3,d3";
@@ -203,6 +513,145 @@ mod tests {
 
         assert!(delimited_data_source.next().await.is_none());
 
+        Ok(())
+    }
+    #[tokio::test]
+    async fn read_empty_delimited_records() -> Result<(), ReadDelimitedDataSourceError> {
+        let data = "A,B\n,\n,,\na1,b1";
+
+        let mut delimited_data_source = DelimitedDataSource::with_buf_reader(
+            DelimitedReaderConfig::Csv,
+            BufReader::new(Cursor::new(data)),
+        )
+        .await?;
+
+        for _ in 0..2 {
+            let record = delimited_data_source.next().await.unwrap().unwrap();
+
+            assert_eq!((record.get("A"), record.get("B")), (Some(""), Some("")));
+        }
+
+        let record = delimited_data_source.next().await.unwrap().unwrap();
+
+        assert_eq!(record.get("A"), Some("a1"));
+        assert!(delimited_data_source.next().await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_delimited_data_source_from_checkpoint()
+    -> Result<(), CreateDelimitedDataSourceError> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_resume_{}.csv",
+            std::process::id()
+        ));
+
+        tokio::fs::write(&path, "A,B\na1,b1\na2,b2\na3,b3").await?;
+
+        let mut delimited_data_source =
+            DelimitedDataSource::new(&path, [] as [&Field; 0], DelimitedReaderConfig::Csv, None)
+                .await?;
+
+        let record = delimited_data_source.next().await.unwrap().unwrap();
+        let checkpoint = DelimitedCheckpoint::after(record.index());
+
+        let mut delimited_data_source = DelimitedDataSource::resume(
+            &path,
+            [] as [&Field; 0],
+            DelimitedReaderConfig::Csv,
+            None,
+            &checkpoint,
+        )
+        .await?;
+
+        for (record_number, a) in [(2, "a2"), (3, "a3")] {
+            match delimited_data_source.next().await {
+                Some(Ok(record)) => {
+                    assert_eq!(record.index().record_number.get(), record_number);
+                    assert_eq!(record.index().line_start, record_number + 1);
+                    assert_eq!(record.get("A").unwrap(), a);
+                }
+                other => panic!("expected record but got: {:?}", other),
+            };
+        }
+
+        assert!(delimited_data_source.next().await.is_none());
+
+        tokio::fs::remove_file(&path).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_delimited_data_source_in_parallel() -> Result<(), CreateDelimitedDataSourceError>
+    {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_parallel_{}.csv",
+            std::process::id()
+        ));
+
+        let data = (1..=100).fold(String::from("A,B\n"), |data, row| {
+            data + &format!("a{},b{}\n", row, row)
+        });
+
+        tokio::fs::write(&path, &data).await?;
+
+        let records = DelimitedDataSource::parallel(
+            &path,
+            [] as [&Field; 0],
+            DelimitedReaderConfig::Csv,
+            None,
+            4,
+        )
+        .await?
+        .collect::<Vec<_>>()
+        .await;
+
+        tokio::fs::remove_file(&path).await?;
+
+        assert_eq!(records.len(), 100);
+
+        for (row, record) in (1..=100).zip(records) {
+            let record = record.unwrap();
+
+            assert_eq!(record.index().record_number.get(), row);
+            assert_eq!(record.index().line_start, row + 1);
+            assert_eq!(record.get("A").unwrap(), format!("a{}", row));
+            assert_eq!(
+                &data.as_bytes()
+                    [record.index().byte_start as usize..record.index().byte_end as usize],
+                format!("a{},b{}\n", row, row).as_bytes()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_delimited_data_source_raw_payload() -> Result<(), ReadDelimitedDataSourceError> {
+        let data = "A,B\r\na1,b1\r\n\"a,2\",b2";
+
+        let cursor = Cursor::new(data);
+        let buf_reader = BufReader::new(cursor);
+        let mut delimited_data_source =
+            DelimitedDataSource::with_buf_reader(DelimitedReaderConfig::Csv, buf_reader).await?;
+
+        delimited_data_source.raw_payload = Some(("Raw".into(), Vec::new()));
+
+        for (a, b, raw) in [("a1", "b1", "a1,b1"), ("a,2", "b2", "\"a,2\",b2")] {
+            match delimited_data_source.next().await {
+                Some(Ok(record)) => {
+                    assert_eq!(record.get("A").unwrap(), a);
+                    assert_eq!(record.get("B").unwrap(), b);
+                    assert_eq!(record.get("Raw").unwrap(), raw);
+                }
+                other => panic!("expected record but got: {:?}", other),
+            };
+        }
+
+        assert!(delimited_data_source.next().await.is_none());
+
         Ok(())
     }
 }
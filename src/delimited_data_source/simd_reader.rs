@@ -0,0 +1,274 @@
+use csv_core::ReadRecordResult;
+use memchr::{memchr, memchr_iter, memchr3};
+
+/// Splitter of delimited records using SIMD accelerated searches, with the same incremental
+/// interface as [`csv_core::Reader`] but without comments or escapes.
+#[derive(Debug)]
+pub struct SimdReader {
+    delimiter: u8,
+    quote: u8,
+    state: State,
+    /// Length of the output of the current record, across calls to `read_record`
+    output_pos: usize,
+    line: u64,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    StartRecord,
+    StartField,
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+}
+
+impl SimdReader {
+    pub fn new(delimiter: u8) -> Self {
+        Self {
+            delimiter,
+            quote: b'"',
+            state: State::StartRecord,
+            output_pos: 0,
+            line: 1,
+        }
+    }
+
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    pub fn set_line(&mut self, line: u64) {
+        self.line = line;
+    }
+
+    pub fn reset(&mut self) {
+        self.state = State::StartRecord;
+        self.output_pos = 0;
+        self.line = 1;
+    }
+
+    /// Reads a record from the input, returning the result and the bytes read from the input,
+    /// written to the output and written to the ends, as [`csv_core::Reader::read_record`] does.
+    pub fn read_record(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        ends: &mut [usize],
+    ) -> (ReadRecordResult, usize, usize, usize) {
+        if input.is_empty() {
+            if self.state == State::StartRecord {
+                return (ReadRecordResult::End, 0, 0, 0);
+            }
+
+            if ends.is_empty() {
+                return (ReadRecordResult::OutputEndsFull, 0, 0, 0);
+            }
+
+            ends[0] = self.output_pos;
+
+            self.state = State::StartRecord;
+            self.output_pos = 0;
+
+            return (ReadRecordResult::Record, 0, 0, 1);
+        }
+
+        let (mut nin, mut nout, mut nend) = (0, 0, 0);
+
+        while nin < input.len() {
+            match self.state {
+                State::StartRecord => match input[nin] {
+                    b'\n' => {
+                        self.line += 1;
+                        nin += 1;
+                    }
+                    b'\r' => nin += 1,
+                    _ => self.state = State::StartField,
+                },
+                State::StartField => {
+                    if input[nin] == self.quote {
+                        nin += 1;
+                        self.state = State::Quoted;
+                    } else {
+                        self.state = State::Unquoted;
+                    }
+                }
+                State::Unquoted => {
+                    let rest = &input[nin..];
+                    let length = memchr3(self.delimiter, b'\n', b'\r', rest).unwrap_or(rest.len());
+                    let copied = self.copy(&rest[..length], &mut output[nout..]);
+
+                    nin += copied;
+                    nout += copied;
+
+                    if copied < length {
+                        return (ReadRecordResult::OutputFull, nin, nout, nend);
+                    }
+
+                    if nin == input.len() {
+                        break;
+                    }
+
+                    if nend == ends.len() {
+                        return (ReadRecordResult::OutputEndsFull, nin, nout, nend);
+                    }
+
+                    ends[nend] = self.output_pos;
+                    nend += 1;
+
+                    let byte = input[nin];
+
+                    nin += 1;
+
+                    if byte == self.delimiter {
+                        self.state = State::StartField;
+                    } else {
+                        if byte == b'\n' {
+                            self.line += 1;
+                        }
+
+                        self.state = State::StartRecord;
+                        self.output_pos = 0;
+
+                        return (ReadRecordResult::Record, nin, nout, nend);
+                    }
+                }
+                State::Quoted => {
+                    let rest = &input[nin..];
+                    let length = memchr(self.quote, rest).unwrap_or(rest.len());
+                    let copied = self.copy(&rest[..length], &mut output[nout..]);
+
+                    self.line += memchr_iter(b'\n', &rest[..copied]).count() as u64;
+
+                    nin += copied;
+                    nout += copied;
+
+                    if copied < length {
+                        return (ReadRecordResult::OutputFull, nin, nout, nend);
+                    }
+
+                    if nin < input.len() {
+                        nin += 1;
+                        self.state = State::QuoteInQuoted;
+                    }
+                }
+                State::QuoteInQuoted => {
+                    if input[nin] == self.quote {
+                        if self.copy(&[self.quote], &mut output[nout..]) == 0 {
+                            return (ReadRecordResult::OutputFull, nin, nout, nend);
+                        }
+
+                        nin += 1;
+                        nout += 1;
+                        self.state = State::Quoted;
+                    } else {
+                        // Characters following the closing quote are read as unquoted, as
+                        // csv_core does
+                        self.state = State::Unquoted;
+                    }
+                }
+            }
+        }
+
+        (ReadRecordResult::InputEmpty, nin, nout, nend)
+    }
+
+    /// Copies as much of the input into the output as fits, returning the bytes copied.
+    #[inline(always)]
+    fn copy(&mut self, input: &[u8], output: &mut [u8]) -> usize {
+        let copied = input.len().min(output.len());
+
+        output[..copied].copy_from_slice(&input[..copied]);
+        self.output_pos += copied;
+
+        copied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads every record of the input, feeding the input in chunks of the given size.
+    fn read_records(
+        mut read_record: impl FnMut(
+            &[u8],
+            &mut [u8],
+            &mut [usize],
+        ) -> (ReadRecordResult, usize, usize, usize),
+        input: &[u8],
+        chunk_size: usize,
+    ) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        let (mut output, mut output_used) = (vec![0; 4], 0);
+        let (mut ends, mut ends_used) = (vec![0; 1], 0);
+        let mut position = 0;
+
+        loop {
+            let chunk = &input[position..(position + chunk_size).min(input.len())];
+            let (result, nin, nout, nend) =
+                read_record(chunk, &mut output[output_used..], &mut ends[ends_used..]);
+
+            position += nin;
+            output_used += nout;
+            ends_used += nend;
+
+            match result {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => output.resize(output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => ends.resize(ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    let mut start = 0;
+
+                    records.push(
+                        ends[..ends_used]
+                            .iter()
+                            .map(|&end| {
+                                let field = String::from_utf8(output[start..end].to_vec());
+
+                                start = end;
+                                field.unwrap()
+                            })
+                            .collect(),
+                    );
+
+                    output_used = 0;
+                    ends_used = 0;
+                }
+                ReadRecordResult::End => return records,
+            }
+        }
+    }
+
+    #[test]
+    fn read_records_as_csv_core() {
+        let inputs = [
+            "a,b,c\r\n1,2,3\r\n",
+            "a,b,c\n\n1,,3",
+            "\"a,1\",\"b\"\"2\",\"c\r\n3\"\nx,y,z\r",
+            "a,\"b\"c,\nlast,",
+        ];
+
+        for input in inputs {
+            for chunk_size in 1..=input.len() {
+                let mut simd_reader = SimdReader::new(b',');
+                let mut csv_core_reader = csv_core::Reader::new();
+
+                assert_eq!(
+                    read_records(
+                        |input, output, ends| simd_reader.read_record(input, output, ends),
+                        input.as_bytes(),
+                        chunk_size,
+                    ),
+                    read_records(
+                        |input, output, ends| csv_core_reader.read_record(input, output, ends),
+                        input.as_bytes(),
+                        chunk_size,
+                    ),
+                    "input {:?} in chunks of {}",
+                    input,
+                    chunk_size,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,637 @@
+use crate::identifier::Identifier;
+use crate::import_profile::{Formatter, ImportProfile, ImportProfileDataSourceConfig, Required};
+use crate::table_mapper::{
+    Column, FieldColumn, LookupKeyColumn, Table, TableMapper, TableMapperColumn,
+};
+use crate::xml_data_source::{UnknownFieldPolicy, XsdValidation};
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use itertools::Itertools;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "cli", derive(ValueEnum), value(rename_all = "kebab-case"))]
+pub enum DescribeFormat {
+    /// Markdown document
+    Markdown,
+    /// HTML document
+    Html,
+}
+
+/// Describes the import profile in a human-readable format, generated from the parsed import
+/// profile.
+pub fn describe(import_profile: &ImportProfile, format: DescribeFormat) -> String {
+    match format {
+        DescribeFormat::Markdown => describe_with(import_profile, MarkdownWriter::default()),
+        DescribeFormat::Html => describe_with(import_profile, HtmlWriter::default()),
+    }
+}
+
+trait DescribeWriter {
+    fn heading(&mut self, level: usize, text: &str);
+    fn paragraph(&mut self, text: &str);
+    fn list(&mut self, items: &[(&str, String)]);
+    fn table(&mut self, headers: &[&str], rows: &[Vec<String>]);
+    fn finish(self) -> String;
+}
+
+fn describe_with(import_profile: &ImportProfile, mut writer: impl DescribeWriter) -> String {
+    writer.heading(1, import_profile.name());
+
+    if let Some(description) = import_profile.description() {
+        writer.paragraph(description);
+    }
+
+    let field_groups = match import_profile.data_source_config() {
+        ImportProfileDataSourceConfig::XmlDataSourceConfig {
+            path,
+            field_groups,
+            selector,
+            xsd,
+            xsd_validation,
+            recover,
+            raw_payload_field,
+            unknown_fields,
+        } => {
+            let mut properties = vec![
+                ("Type", "XML".to_string()),
+                ("Path", path.display().to_string()),
+                ("Selector", selector.to_string()),
+            ];
+
+            if let Some(xsd) = xsd {
+                properties.push(("XSD", xsd.display().to_string()));
+
+                if *xsd_validation == XsdValidation::Streaming {
+                    properties.push(("XSD validation", "While streaming".to_string()));
+                }
+            }
+
+            if *recover {
+                properties.push(("Malformed records", "Skipped".to_string()));
+            }
+
+            if let Some(raw_payload_field) = raw_payload_field {
+                properties.push(("Raw payload field", raw_payload_field.clone()));
+            }
+
+            match unknown_fields {
+                UnknownFieldPolicy::Error => {}
+                UnknownFieldPolicy::Ignore => {
+                    properties.push(("Unknown fields", "Ignored".to_string()));
+                }
+                UnknownFieldPolicy::Collect(collect_field) => {
+                    properties.push((
+                        "Unknown fields",
+                        format!("Collected into {}", collect_field),
+                    ));
+                }
+            }
+
+            writer.heading(2, "Data source");
+            writer.list(&properties);
+            field_groups
+        }
+        ImportProfileDataSourceConfig::DelimitedDataSourceConfig {
+            path,
+            field_groups,
+            reader_config,
+            raw_payload_field,
+        } => {
+            let mut properties = vec![
+                ("Type", "Delimited".to_string()),
+                ("Path", path.display().to_string()),
+                ("Reader config", format!("{:?}", reader_config)),
+            ];
+
+            if let Some(raw_payload_field) = raw_payload_field {
+                properties.push(("Raw payload field", raw_payload_field.clone()));
+            }
+
+            writer.heading(2, "Data source");
+            writer.list(&properties);
+            field_groups
+        }
+        ImportProfileDataSourceConfig::JsonDataSourceConfig {
+            path,
+            field_groups,
+            format,
+            selector,
+            raw_payload_field,
+        } => {
+            let mut properties = vec![
+                ("Type", "JSON".to_string()),
+                ("Path", path.display().to_string()),
+                ("Format", format!("{:?}", format)),
+            ];
+
+            if let Some(selector) = selector {
+                properties.push(("Selector", selector.clone()));
+            }
+
+            if let Some(raw_payload_field) = raw_payload_field {
+                properties.push(("Raw payload field", raw_payload_field.clone()));
+            }
+
+            writer.heading(2, "Data source");
+            writer.list(&properties);
+            field_groups
+        }
+        ImportProfileDataSourceConfig::AvroDataSourceConfig { path, field_groups } => {
+            writer.heading(2, "Data source");
+            writer.list(&[
+                ("Type", "Avro".to_string()),
+                ("Path", path.display().to_string()),
+            ]);
+            field_groups
+        }
+        ImportProfileDataSourceConfig::SqlDataSourceConfig {
+            query,
+            field_groups,
+            ..
+        } => {
+            writer.heading(2, "Data source");
+            writer.list(&[("Type", "SQL".to_string()), ("Query", query.clone())]);
+            field_groups
+        }
+        ImportProfileDataSourceConfig::StreamDataSourceConfig {
+            broker,
+            field_groups,
+            payload,
+            batch_window,
+            raw_payload_field,
+        } => {
+            let mut properties = vec![
+                ("Type", "Stream".to_string()),
+                ("Broker", broker.to_string()),
+                ("Payload", format!("{:?}", payload)),
+                ("Batch max records", batch_window.max_records.to_string()),
+                ("Batch max age", format!("{}ms", batch_window.max_age_ms)),
+            ];
+
+            if let Some(raw_payload_field) = raw_payload_field {
+                properties.push(("Raw payload field", raw_payload_field.clone()));
+            }
+
+            writer.heading(2, "Data source");
+            writer.list(&properties);
+            field_groups
+        }
+        ImportProfileDataSourceConfig::CustomDataSourceConfig {
+            kind, field_groups, ..
+        } => {
+            writer.heading(2, "Data source");
+            writer.list(&[("Type", "Custom".to_string()), ("Kind", kind.clone())]);
+            field_groups
+        }
+    };
+
+    writer.heading(2, "Field groups");
+
+    for (field_group_name, group) in field_groups.iter().sorted_by_key(|(name, _)| *name) {
+        writer.heading(3, field_group_name);
+        writer.table(
+            &["Field", "Formatters", "Required"],
+            &group
+                .fields()
+                .iter()
+                .map(|field| {
+                    vec![
+                        field.name().to_string(),
+                        field.formatters().iter().map(describe_formatter).join(", "),
+                        field.required().map(describe_required).unwrap_or_default(),
+                    ]
+                })
+                .collect_vec(),
+        );
+
+        let constants = group
+            .constants()
+            .sorted()
+            .map(|(name, value)| vec![name.to_string(), value.to_string()])
+            .collect_vec();
+
+        if !constants.is_empty() {
+            writer.table(&["Constant", "Value"], &constants);
+        }
+    }
+
+    writer.heading(2, "Table mappers");
+
+    for table_mapper in import_profile.table_mappers() {
+        describe_table_mapper(&mut writer, table_mapper);
+    }
+
+    writer.finish()
+}
+
+fn describe_table_mapper(writer: &mut impl DescribeWriter, table_mapper: &TableMapper) {
+    writer.heading(3, table_mapper.name());
+
+    let mut properties = vec![
+        ("Target table", table_mapper.identifier().full().to_string()),
+        ("Field group", table_mapper.field_group().to_string()),
+        ("Delete mode", format!("{:?}", table_mapper.delete_mode())),
+        (
+            "Duplicate action",
+            format!("{:?}", table_mapper.duplicate_action()),
+        ),
+        (
+            "Key columns",
+            table_mapper
+                .normalized_key_columns()
+                .map(|(column, normalizations)| {
+                    if normalizations.is_empty() {
+                        column.part_unescaped().to_string()
+                    } else {
+                        format!(
+                            "{} ({})",
+                            column.part_unescaped(),
+                            normalizations
+                                .iter()
+                                .map(|normalization| format!("{normalization:?}"))
+                                .join(", "),
+                        )
+                    }
+                })
+                .join(", "),
+        ),
+    ];
+
+    let depends_on = table_mapper.depends_on().join(", ");
+
+    if !depends_on.is_empty() {
+        properties.push(("Depends on", depends_on));
+    }
+
+    if let Some(preprocess_function) = table_mapper.preprocess_function() {
+        properties.push(("Preprocess function", preprocess_function.to_string()));
+    }
+
+    let concurrency = table_mapper.concurrency();
+
+    if let Some(max_parallel) = concurrency.max_parallel {
+        properties.push(("Max parallel", max_parallel.to_string()));
+    }
+
+    if concurrency.serializable {
+        properties.push(("Serializable", String::from("yes")));
+    }
+
+    if let Some(merge_order) = table_mapper.merge_order() {
+        properties.push((
+            "Merge order",
+            merge_order
+                .columns()
+                .map(|column| {
+                    format!(
+                        "{} {}",
+                        column.identifier().part_unescaped(),
+                        column.direction().sql_keyword(),
+                    )
+                })
+                .join(", "),
+        ));
+    }
+
+    if !table_mapper.default_on_insert().is_empty() {
+        properties.push((
+            "Default on insert",
+            table_mapper
+                .default_on_insert()
+                .iter()
+                .map(|column| column.part_unescaped())
+                .join(", "),
+        ));
+    }
+
+    if let Some(hierarchy) = table_mapper.hierarchy() {
+        properties.push((
+            "Hierarchy",
+            format!(
+                "{} resolved from {}, merged a level at a time",
+                hierarchy.parent_column().part_unescaped(),
+                hierarchy.parent_key_column().part_unescaped(),
+            ),
+        ));
+    }
+
+    if let Some(route) = table_mapper.route() {
+        properties.push((
+            "Route",
+            format!("Records whose {} is {}", route.field(), route.value()),
+        ));
+    }
+
+    if let Some(bridge) = table_mapper.bridge() {
+        properties.push((
+            "Bridge",
+            format!(
+                "{} to {}{}, refreshed in full per left key",
+                bridge.left_column().part_unescaped(),
+                bridge.right_column().part_unescaped(),
+                if bridge.symmetric() {
+                    ", symmetric"
+                } else {
+                    ""
+                },
+            ),
+        ));
+    }
+
+    if let Some(history) = table_mapper.history() {
+        properties.push((
+            "History",
+            format!(
+                "{} to {}, tracking {}",
+                history.valid_from_column().part_unescaped(),
+                history.valid_to_column().part_unescaped(),
+                history
+                    .tracked_columns()
+                    .iter()
+                    .map(|column| column.part_unescaped())
+                    .join(", "),
+            ),
+        ));
+    }
+
+    if let Some(operation) = table_mapper.operation() {
+        properties.push((
+            "Operation",
+            format!(
+                "{} of {} inserts, {} updates, {} deletes",
+                operation.field(),
+                operation.insert(),
+                operation.update(),
+                operation.delete(),
+            ),
+        ));
+    }
+
+    for computed_field in table_mapper.computed_fields() {
+        properties.push((
+            "Computed field",
+            format!(
+                "{} = {}",
+                computed_field.name(),
+                computed_field.expression_text()
+            ),
+        ));
+    }
+
+    if let Some(surrogate_key) = table_mapper.surrogate_key() {
+        properties.push((
+            "Surrogate key",
+            format!(
+                "{}, captured into {}",
+                surrogate_key.identifier().part_unescaped(),
+                surrogate_key.capture_table().full(),
+            ),
+        ));
+    }
+
+    writer.list(&properties);
+
+    writer.table(
+        &["Column", "Source", "Mapped"],
+        &table_mapper
+            .columns()
+            .map(|column| {
+                let source = match column {
+                    TableMapperColumn::Static(static_column) if static_column.sensitive() => {
+                        String::from("static (sensitive)")
+                    }
+                    TableMapperColumn::Static(static_column)
+                        if static_column.template().is_some() =>
+                    {
+                        format!("template '{}'", static_column.value())
+                    }
+                    TableMapperColumn::Static(static_column) => {
+                        format!("static '{}'", static_column.value())
+                    }
+                    TableMapperColumn::Parser(parser_column)
+                        if let Some(spatial) = parser_column.spatial() =>
+                    {
+                        match spatial.latitude_field() {
+                            Some(latitude_field) => format!(
+                                "fields '{}', '{}' ({} point)",
+                                parser_column.field_name(),
+                                latitude_field,
+                                spatial.spatial_type().keyword(),
+                            ),
+                            None => format!(
+                                "field '{}' ({})",
+                                parser_column.field_name(),
+                                spatial.spatial_type().keyword(),
+                            ),
+                        }
+                    }
+                    TableMapperColumn::Parser(parser_column) if parser_column.json() => {
+                        format!("field '{}' (JSON)", parser_column.field_name())
+                    }
+                    TableMapperColumn::Parser(parser_column)
+                        if parser_column.implied_decimals() > 0 =>
+                    {
+                        format!(
+                            "field '{}' ({} implied decimals)",
+                            parser_column.field_name(),
+                            parser_column.implied_decimals(),
+                        )
+                    }
+                    TableMapperColumn::Parser(parser_column) => {
+                        format!("field '{}'", parser_column.field_name())
+                    }
+                    TableMapperColumn::Sequence(sequence_column)
+                        if sequence_column.group_fields().is_empty() =>
+                    {
+                        format!("sequence from {}", sequence_column.start())
+                    }
+                    TableMapperColumn::Sequence(sequence_column) => format!(
+                        "sequence from {} per {}",
+                        sequence_column.start(),
+                        sequence_column
+                            .group_fields()
+                            .iter()
+                            .map(|field| format!("'{field}'"))
+                            .join(", "),
+                    ),
+                    TableMapperColumn::Lookup(lookup_column) => format!(
+                        "lookup {} from {} on {}",
+                        lookup_column.output_column_identifier().part_unescaped(),
+                        Table::identifier(lookup_column).full(),
+                        lookup_column
+                            .iter_key_columns()
+                            .map(describe_lookup_key_column)
+                            .join(", "),
+                    ),
+                };
+
+                vec![
+                    Column::identifier(column).part_unescaped().to_string(),
+                    source,
+                    if Column::map(column) { "yes" } else { "no" }.to_string(),
+                ]
+            })
+            .collect_vec(),
+    );
+}
+
+fn describe_lookup_key_column(lookup_key_column: &LookupKeyColumn) -> String {
+    match lookup_key_column {
+        LookupKeyColumn::ParserKeyColumn(parser_key_column) => format!(
+            "{} = field '{}'",
+            Column::identifier(parser_key_column).part_unescaped(),
+            parser_key_column.field_name(),
+        ),
+        LookupKeyColumn::ProcessedKeyColumn(processed_key_column) => format!(
+            "{} = column {}",
+            Column::identifier(processed_key_column).part_unescaped(),
+            processed_key_column.column_identifier().part_unescaped(),
+        ),
+    }
+}
+
+fn describe_formatter(formatter: &Formatter) -> String {
+    match formatter {
+        Formatter::Trim => "Trim".to_string(),
+        Formatter::Uppercase => "Uppercase".to_string(),
+        Formatter::Lowercase => "Lowercase".to_string(),
+        Formatter::Regex {
+            pattern,
+            replacement,
+        } => format!("Regex ('{}' to '{}')", pattern.as_str(), replacement),
+        Formatter::Map { mappings, .. } => format!("Map ({} mappings)", mappings.len()),
+        Formatter::ParseDate {
+            input_formats,
+            output,
+        } => format!(
+            "ParseDate ('{}' to {:?})",
+            input_formats.iter().join("', '"),
+            output
+        ),
+        Formatter::ParseNumber { decimal_separator } => {
+            format!("ParseNumber ({:?})", decimal_separator)
+        }
+    }
+}
+
+fn describe_required(required: &Required) -> String {
+    match required {
+        Required::Drop => "drop record".to_string(),
+        Required::Error => "error".to_string(),
+    }
+}
+
+#[derive(Default)]
+struct MarkdownWriter {
+    output: String,
+}
+
+impl DescribeWriter for MarkdownWriter {
+    fn heading(&mut self, level: usize, text: &str) {
+        let _ = writeln!(self.output, "{} {}\n", "#".repeat(level), text);
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        let _ = writeln!(self.output, "{}\n", text);
+    }
+
+    fn list(&mut self, items: &[(&str, String)]) {
+        for (name, value) in items {
+            let _ = writeln!(self.output, "* **{}**: {}", name, value);
+        }
+
+        self.output.push('\n');
+    }
+
+    fn table(&mut self, headers: &[&str], rows: &[Vec<String>]) {
+        let escape = |cell: &str| cell.replace('|', "\\|");
+
+        let _ = writeln!(self.output, "| {} |", headers.join(" | "));
+        let _ = writeln!(self.output, "|{}", " --- |".repeat(headers.len()));
+
+        for row in rows {
+            let _ = writeln!(
+                self.output,
+                "| {} |",
+                row.iter().map(|cell| escape(cell)).join(" | ")
+            );
+        }
+
+        self.output.push('\n');
+    }
+
+    fn finish(self) -> String {
+        self.output
+    }
+}
+
+#[derive(Default)]
+struct HtmlWriter {
+    output: String,
+}
+
+impl HtmlWriter {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+}
+
+impl DescribeWriter for HtmlWriter {
+    fn heading(&mut self, level: usize, text: &str) {
+        let _ = writeln!(
+            self.output,
+            "<h{level}>{}</h{level}>",
+            Self::escape(text),
+            level = level.min(6),
+        );
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        let _ = writeln!(self.output, "<p>{}</p>", Self::escape(text));
+    }
+
+    fn list(&mut self, items: &[(&str, String)]) {
+        self.output.push_str("<ul>\n");
+
+        for (name, value) in items {
+            let _ = writeln!(
+                self.output,
+                "  <li><strong>{}</strong>: {}</li>",
+                Self::escape(name),
+                Self::escape(value),
+            );
+        }
+
+        self.output.push_str("</ul>\n");
+    }
+
+    fn table(&mut self, headers: &[&str], rows: &[Vec<String>]) {
+        self.output.push_str("<table>\n  <tr>");
+
+        for header in headers {
+            let _ = write!(self.output, "<th>{}</th>", Self::escape(header));
+        }
+
+        self.output.push_str("</tr>\n");
+
+        for row in rows {
+            self.output.push_str("  <tr>");
+
+            for cell in row {
+                let _ = write!(self.output, "<td>{}</td>", Self::escape(cell));
+            }
+
+            self.output.push_str("</tr>\n");
+        }
+
+        self.output.push_str("</table>\n");
+    }
+
+    fn finish(self) -> String {
+        self.output
+    }
+}
@@ -0,0 +1,799 @@
+use crate::data_source::DataSourceRecord;
+use crate::insert_processor::parse_decimal;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Field computed from each record by an expression, and added to the record, such that derived
+/// values and conditional logic are written in the import profile without a preprocess script.
+#[derive(Debug)]
+pub struct ComputedField {
+    name: Arc<str>,
+    expression_text: String,
+    expression: Expression,
+}
+
+/// Expression of the fields of a record, such as `if([Amount] > 0, 'credit', 'debit')`, in which
+/// a missing field, or arithmetic of `null`, is `null`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(Value),
+    Field(String),
+    Unary(UnaryOperator, Box<Node>),
+    Binary(BinaryOperator, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+/// Value of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(Decimal),
+    Text(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Concat,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Function {
+    If,
+    Coalesce,
+    IsNull,
+    Number,
+    Upper,
+    Lower,
+    Trim,
+    Len,
+    Left,
+    Right,
+    Substr,
+    Replace,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Round,
+    Abs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Decimal),
+    Text(String),
+    Identifier(String),
+    Field(String),
+    Operator(&'static str),
+}
+
+#[derive(Debug, Error)]
+pub enum ExpressionError {
+    #[error("unexpected character '{0}' at position {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("unterminated text or field name at position {0}")]
+    Unterminated(usize),
+    #[error("invalid number '{0}' at position {1}")]
+    InvalidNumber(String, usize),
+    #[error("unexpected {0} at position {1}")]
+    UnexpectedToken(String, usize),
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+    #[error("unknown function '{0}'")]
+    UnknownFunction(String),
+    #[error("function '{0}' takes {1} arguments, found {2}")]
+    Arguments(String, String, usize),
+}
+
+#[derive(Debug, Error)]
+pub enum EvaluateError {
+    #[error("'{0}' is not a number")]
+    NotANumber(String),
+    #[error("'{0}' is not a boolean")]
+    NotABoolean(String),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("arithmetic overflow")]
+    Overflow,
+}
+
+impl ComputedField {
+    pub fn new(name: &str, expression: &str) -> Result<Self, ExpressionError> {
+        Ok(ComputedField {
+            name: Arc::from(name),
+            expression_text: expression.to_owned(),
+            expression: expression.parse()?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+
+    /// Expression as written in the import profile.
+    pub fn expression_text(&self) -> &str {
+        &self.expression_text
+    }
+
+    /// Adds the field to the record, empty if the expression is `null`.
+    pub fn compute(&self, record: &mut DataSourceRecord) -> Result<(), EvaluateError> {
+        let value = self.expression.evaluate(record)?;
+
+        record
+            .fields_mut()
+            .insert(self.name.clone(), &value.to_string());
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Expression {
+    type Err = ExpressionError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            next: 0,
+        };
+
+        let root = parser.parse_binary(0)?;
+
+        match parser.tokens.get(parser.next) {
+            Some((token, position)) => Err(ExpressionError::UnexpectedToken(
+                token.to_string(),
+                *position,
+            )),
+            None => Ok(Expression { root }),
+        }
+    }
+}
+
+impl Expression {
+    pub fn evaluate(&self, record: &DataSourceRecord) -> Result<Value, EvaluateError> {
+        self.root.evaluate(record)
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+
+    while let Some((position, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '0'..='9' | '.' => {
+                let mut number = String::from(c);
+
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+
+                Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| ExpressionError::InvalidNumber(number, position))?,
+                )
+            }
+            '\'' | '"' => {
+                let quote = c;
+                let mut text = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some((_, c)) if c == quote => match chars.next_if(|&(_, c)| c == quote) {
+                            Some(_) => text.push(quote),
+                            None => break,
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => return Err(ExpressionError::Unterminated(position)),
+                    }
+                }
+
+                Token::Text(text)
+            }
+            '[' => {
+                let name = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|&c| c != ']')
+                    .collect::<String>();
+
+                if !expression[position..].contains(']') {
+                    return Err(ExpressionError::Unterminated(position));
+                }
+
+                Token::Field(name)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut identifier = String::from(c);
+
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    identifier.push(c);
+                }
+
+                Token::Identifier(identifier)
+            }
+            _ => {
+                let next = chars.peek().map(|&(_, c)| c);
+
+                let operator = match (c, next) {
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('!', _) => "!",
+                    ('&', _) => "&",
+                    ('+', _) => "+",
+                    ('-', _) => "-",
+                    ('*', _) => "*",
+                    ('/', _) => "/",
+                    ('%', _) => "%",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    (',', _) => ",",
+                    _ => return Err(ExpressionError::UnexpectedCharacter(c, position)),
+                };
+
+                if operator.len() == 2 {
+                    chars.next();
+                }
+
+                Token::Operator(operator)
+            }
+        };
+
+        tokens.push((token, position));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(Token, usize)],
+    next: usize,
+}
+
+impl Parser<'_> {
+    /// Binary operators of each precedence, from lowest to highest.
+    const PRECEDENCE: [&'static [(&'static str, BinaryOperator)]; 6] = [
+        &[("||", BinaryOperator::Or)],
+        &[("&&", BinaryOperator::And)],
+        &[
+            ("==", BinaryOperator::Equal),
+            ("!=", BinaryOperator::NotEqual),
+            ("<", BinaryOperator::Less),
+            ("<=", BinaryOperator::LessOrEqual),
+            (">", BinaryOperator::Greater),
+            (">=", BinaryOperator::GreaterOrEqual),
+        ],
+        &[("&", BinaryOperator::Concat)],
+        &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)],
+        &[
+            ("*", BinaryOperator::Multiply),
+            ("/", BinaryOperator::Divide),
+            ("%", BinaryOperator::Remainder),
+        ],
+    ];
+
+    fn parse_binary(&mut self, precedence: usize) -> Result<Node, ExpressionError> {
+        let Some(operators) = Self::PRECEDENCE.get(precedence) else {
+            return self.parse_unary();
+        };
+
+        let mut node = self.parse_binary(precedence + 1)?;
+
+        while let Some((Token::Operator(symbol), _)) = self.tokens.get(self.next)
+            && let Some(&(_, operator)) = operators.iter().find(|(other, _)| other == symbol)
+        {
+            self.next += 1;
+
+            let right = self.parse_binary(precedence + 1)?;
+
+            node = Node::Binary(operator, Box::new(node), Box::new(right));
+        }
+
+        Ok(node)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, ExpressionError> {
+        let operator = match self.tokens.get(self.next) {
+            Some((Token::Operator("-"), _)) => UnaryOperator::Negate,
+            Some((Token::Operator("!"), _)) => UnaryOperator::Not,
+            _ => return self.parse_primary(),
+        };
+
+        self.next += 1;
+
+        Ok(Node::Unary(operator, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, ExpressionError> {
+        let (token, position) = self
+            .tokens
+            .get(self.next)
+            .ok_or(ExpressionError::UnexpectedEnd)?;
+
+        self.next += 1;
+
+        Ok(match token {
+            Token::Number(number) => Node::Literal(Value::Number(*number)),
+            Token::Text(text) => Node::Literal(Value::Text(text.clone())),
+            Token::Field(name) => Node::Field(name.clone()),
+            Token::Identifier(identifier) => match identifier.as_str() {
+                "true" => Node::Literal(Value::Bool(true)),
+                "false" => Node::Literal(Value::Bool(false)),
+                "null" => Node::Literal(Value::Null),
+                _ if self.eat("(") => self.parse_call(identifier)?,
+                _ => Node::Field(identifier.clone()),
+            },
+            Token::Operator("(") => {
+                let node = self.parse_binary(0)?;
+
+                self.expect(")")?;
+
+                node
+            }
+            token => {
+                return Err(ExpressionError::UnexpectedToken(
+                    token.to_string(),
+                    *position,
+                ));
+            }
+        })
+    }
+
+    fn parse_call(&mut self, name: &str) -> Result<Node, ExpressionError> {
+        let function = Function::parse(name)?;
+        let mut arguments = Vec::new();
+
+        if !self.eat(")") {
+            loop {
+                arguments.push(self.parse_binary(0)?);
+
+                if self.eat(")") {
+                    break;
+                }
+
+                self.expect(",")?;
+            }
+        }
+
+        let (min, max) = function.arity();
+
+        if arguments.len() < min || arguments.len() > max {
+            let expected = match (min, max) {
+                (min, usize::MAX) => format!("at least {min}"),
+                (min, max) if min == max => min.to_string(),
+                (min, max) => format!("{min} to {max}"),
+            };
+
+            return Err(ExpressionError::Arguments(
+                name.to_owned(),
+                expected,
+                arguments.len(),
+            ));
+        }
+
+        Ok(Node::Call(function, arguments))
+    }
+
+    fn eat(&mut self, operator: &str) -> bool {
+        let eaten = matches!(self.tokens.get(self.next), Some((Token::Operator(other), _)) if *other == operator);
+
+        if eaten {
+            self.next += 1;
+        }
+
+        eaten
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), ExpressionError> {
+        if self.eat(operator) {
+            return Ok(());
+        }
+
+        match self.tokens.get(self.next) {
+            Some((token, position)) => Err(ExpressionError::UnexpectedToken(
+                token.to_string(),
+                *position,
+            )),
+            None => Err(ExpressionError::UnexpectedEnd),
+        }
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "number {number}"),
+            Token::Text(text) => write!(f, "text '{text}'"),
+            Token::Identifier(identifier) => write!(f, "'{identifier}'"),
+            Token::Field(name) => write!(f, "field [{name}]"),
+            Token::Operator(operator) => write!(f, "'{operator}'"),
+        }
+    }
+}
+
+impl Function {
+    fn parse(name: &str) -> Result<Self, ExpressionError> {
+        Ok(match name {
+            "if" => Function::If,
+            "coalesce" => Function::Coalesce,
+            "is_null" => Function::IsNull,
+            "number" => Function::Number,
+            "upper" => Function::Upper,
+            "lower" => Function::Lower,
+            "trim" => Function::Trim,
+            "len" => Function::Len,
+            "left" => Function::Left,
+            "right" => Function::Right,
+            "substr" => Function::Substr,
+            "replace" => Function::Replace,
+            "contains" => Function::Contains,
+            "starts_with" => Function::StartsWith,
+            "ends_with" => Function::EndsWith,
+            "round" => Function::Round,
+            "abs" => Function::Abs,
+            _ => return Err(ExpressionError::UnknownFunction(name.to_owned())),
+        })
+    }
+
+    /// Minimum and maximum number of arguments.
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Function::Coalesce => (1, usize::MAX),
+            Function::Round => (1, 2),
+            Function::IsNull
+            | Function::Number
+            | Function::Upper
+            | Function::Lower
+            | Function::Trim
+            | Function::Len
+            | Function::Abs => (1, 1),
+            Function::Left
+            | Function::Right
+            | Function::Contains
+            | Function::StartsWith
+            | Function::EndsWith => (2, 2),
+            Function::If | Function::Substr | Function::Replace => (3, 3),
+        }
+    }
+}
+
+impl Node {
+    fn evaluate(&self, record: &DataSourceRecord) -> Result<Value, EvaluateError> {
+        match self {
+            Node::Literal(value) => Ok(value.clone()),
+            Node::Field(name) => Ok(record
+                .get(name)
+                .map_or(Value::Null, |value| Value::Text(value.to_owned()))),
+            Node::Unary(UnaryOperator::Negate, node) => match node.evaluate(record)? {
+                Value::Null => Ok(Value::Null),
+                value => Ok(Value::Number(-value.number()?)),
+            },
+            Node::Unary(UnaryOperator::Not, node) => Ok(node
+                .evaluate(record)?
+                .bool()?
+                .map_or(Value::Null, |b| Value::Bool(!b))),
+            Node::Binary(BinaryOperator::And, left, right) => {
+                match left.evaluate(record)?.bool()? {
+                    Some(false) => Ok(Value::Bool(false)),
+                    left => Ok(match (left, right.evaluate(record)?.bool()?) {
+                        (_, Some(false)) => Value::Bool(false),
+                        (Some(true), Some(true)) => Value::Bool(true),
+                        _ => Value::Null,
+                    }),
+                }
+            }
+            Node::Binary(BinaryOperator::Or, left, right) => {
+                match left.evaluate(record)?.bool()? {
+                    Some(true) => Ok(Value::Bool(true)),
+                    left => Ok(match (left, right.evaluate(record)?.bool()?) {
+                        (_, Some(true)) => Value::Bool(true),
+                        (Some(false), Some(false)) => Value::Bool(false),
+                        _ => Value::Null,
+                    }),
+                }
+            }
+            Node::Binary(operator, left, right) => {
+                binary(*operator, left.evaluate(record)?, right.evaluate(record)?)
+            }
+            Node::Call(Function::If, arguments) => {
+                if arguments[0].evaluate(record)?.bool()? == Some(true) {
+                    arguments[1].evaluate(record)
+                } else {
+                    arguments[2].evaluate(record)
+                }
+            }
+            Node::Call(Function::Coalesce, arguments) => {
+                for argument in arguments {
+                    match argument.evaluate(record)? {
+                        Value::Null => continue,
+                        value => return Ok(value),
+                    }
+                }
+
+                Ok(Value::Null)
+            }
+            Node::Call(function, arguments) => call(
+                *function,
+                arguments
+                    .iter()
+                    .map(|argument| argument.evaluate(record))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+        }
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Value, right: Value) -> Result<Value, EvaluateError> {
+    let ordering = |left: &Value, right: &Value| match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        _ => match (left.number(), right.number()) {
+            (Ok(left), Ok(right)) => Some(left.cmp(&right)),
+            _ => Some(left.to_string().cmp(&right.to_string())),
+        },
+    };
+
+    let compare = |accept: fn(Ordering) -> bool| -> Result<Value, EvaluateError> {
+        Ok(ordering(&left, &right).map_or(Value::Null, |ordering| Value::Bool(accept(ordering))))
+    };
+
+    match operator {
+        BinaryOperator::Equal if left == Value::Null || right == Value::Null => {
+            Ok(Value::Bool(left == right))
+        }
+        BinaryOperator::NotEqual if left == Value::Null || right == Value::Null => {
+            Ok(Value::Bool(left != right))
+        }
+        BinaryOperator::Equal => compare(Ordering::is_eq),
+        BinaryOperator::NotEqual => compare(Ordering::is_ne),
+        BinaryOperator::Less => compare(Ordering::is_lt),
+        BinaryOperator::LessOrEqual => compare(Ordering::is_le),
+        BinaryOperator::Greater => compare(Ordering::is_gt),
+        BinaryOperator::GreaterOrEqual => compare(Ordering::is_ge),
+        BinaryOperator::Concat => Ok(Value::Text(format!("{left}{right}"))),
+        _ if left == Value::Null || right == Value::Null => Ok(Value::Null),
+        _ => {
+            let (left, right) = (left.number()?, right.number()?);
+
+            let result = match operator {
+                BinaryOperator::Add => left.checked_add(right),
+                BinaryOperator::Subtract => left.checked_sub(right),
+                BinaryOperator::Multiply => left.checked_mul(right),
+                BinaryOperator::Divide | BinaryOperator::Remainder if right.is_zero() => {
+                    return Err(EvaluateError::DivisionByZero);
+                }
+                BinaryOperator::Divide => left.checked_div(right),
+                BinaryOperator::Remainder => left.checked_rem(right),
+                _ => unreachable!("logical and comparison operators are evaluated above"),
+            };
+
+            result.map(Value::Number).ok_or(EvaluateError::Overflow)
+        }
+    }
+}
+
+fn call(function: Function, arguments: Vec<Value>) -> Result<Value, EvaluateError> {
+    if function == Function::IsNull {
+        return Ok(Value::Bool(arguments[0] == Value::Null));
+    }
+
+    // functions of null are null
+    if arguments.contains(&Value::Null) {
+        return Ok(Value::Null);
+    }
+
+    let text = |index: usize| arguments[index].to_string();
+    let count = |index: usize| -> Result<usize, EvaluateError> {
+        let number = arguments[index].number()?;
+
+        Ok(number
+            .max(Decimal::ZERO)
+            .trunc()
+            .to_usize()
+            .unwrap_or(usize::MAX))
+    };
+
+    Ok(match function {
+        Function::Number => arguments[0].number().map_or(Value::Null, Value::Number),
+        Function::Upper => Value::Text(text(0).to_uppercase()),
+        Function::Lower => Value::Text(text(0).to_lowercase()),
+        Function::Trim => Value::Text(text(0).trim().to_owned()),
+        Function::Len => Value::Number(Decimal::from(text(0).chars().count())),
+        Function::Left => Value::Text(text(0).chars().take(count(1)?).collect()),
+        Function::Right => {
+            let text = text(0);
+            let skip = text.chars().count().saturating_sub(count(1)?);
+
+            Value::Text(text.chars().skip(skip).collect())
+        }
+        Function::Substr => Value::Text(
+            text(0)
+                .chars()
+                .skip(count(1)?.saturating_sub(1))
+                .take(count(2)?)
+                .collect(),
+        ),
+        Function::Replace => Value::Text(text(0).replace(&text(1), &text(2))),
+        Function::Contains => Value::Bool(text(0).contains(&text(1))),
+        Function::StartsWith => Value::Bool(text(0).starts_with(&text(1))),
+        Function::EndsWith => Value::Bool(text(0).ends_with(&text(1))),
+        Function::Round => {
+            let decimal_places = match arguments.get(1) {
+                Some(_) => count(1)?.min(Decimal::MAX_SCALE as usize) as u32,
+                None => 0,
+            };
+
+            Value::Number(
+                arguments[0]
+                    .number()?
+                    .round_dp_with_strategy(decimal_places, RoundingStrategy::MidpointAwayFromZero),
+            )
+        }
+        Function::Abs => Value::Number(arguments[0].number()?.abs()),
+        Function::If | Function::Coalesce | Function::IsNull => {
+            unreachable!("evaluated lazily or above")
+        }
+    })
+}
+
+impl Value {
+    fn number(&self) -> Result<Decimal, EvaluateError> {
+        match self {
+            Value::Number(number) => Ok(*number),
+            Value::Text(text) => {
+                parse_decimal(text.trim()).ok_or_else(|| EvaluateError::NotANumber(text.clone()))
+            }
+            value => Err(EvaluateError::NotANumber(value.to_string())),
+        }
+    }
+
+    /// Truth of a condition, or `None` if `null`.
+    fn bool(&self) -> Result<Option<bool>, EvaluateError> {
+        match self {
+            Value::Null => Ok(None),
+            Value::Bool(b) => Ok(Some(*b)),
+            Value::Text(text) if text == "true" => Ok(Some(true)),
+            Value::Text(text) if text == "false" => Ok(Some(false)),
+            value => Err(EvaluateError::NotABoolean(value.to_string())),
+        }
+    }
+}
+
+/// Text of the value, as added to a record, `null` being empty.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(number) => write!(f, "{}", number.normalize()),
+            Value::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSourceRecordIndex;
+    use crate::data_source::string_map::StringMap;
+    use std::num::NonZero;
+
+    fn evaluate(expression: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let record = DataSourceRecord::new(
+            StringMap::from([
+                ("Amount", "-12.50"),
+                ("Unit Price", "4"),
+                ("Code", "gb"),
+                ("Active", "true"),
+            ]),
+            DataSourceRecordIndex {
+                record_number: NonZero::new(1).unwrap(),
+                line_start: 1,
+                line_end: 1,
+                byte_start: 0,
+                byte_end: 0,
+            },
+        );
+
+        Ok(expression
+            .parse::<Expression>()?
+            .evaluate(&record)?
+            .to_string())
+    }
+
+    #[test]
+    fn evaluate_expressions() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(evaluate("Amount * [Unit Price] + 1")?, "-49");
+        assert_eq!(evaluate("-(1 + 2) * 3 % 4")?, "-1");
+        assert_eq!(evaluate("upper(Code) & '-' & \"it''s\"")?, "GB-it''s");
+        assert_eq!(evaluate("'it''s'")?, "it's");
+        assert_eq!(
+            evaluate("if(Amount < 0 && Active, 'debit', 'credit')")?,
+            "debit"
+        );
+        assert_eq!(evaluate("[Unit Price] == 4.0 || Missing > 1")?, "true");
+        assert_eq!(evaluate("Missing + 1")?, "");
+        assert_eq!(evaluate("is_null(Missing) && !false")?, "true");
+        assert_eq!(evaluate("coalesce(Missing, null, Code)")?, "gb");
+        assert_eq!(evaluate("round(abs(Amount) / 3, 2)")?, "4.17");
+        assert_eq!(
+            evaluate("substr('United Kingdom', 8, 4) & right(Code, 1)")?,
+            "Kingb"
+        );
+        assert_eq!(evaluate("number('n/a')")?, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_expressions() {
+        let parse = |expression: &str| expression.parse::<Expression>();
+
+        assert!(matches!(parse("1 +"), Err(ExpressionError::UnexpectedEnd)));
+        assert!(matches!(
+            parse("1 2"),
+            Err(ExpressionError::UnexpectedToken(_, 2))
+        ));
+        assert!(matches!(
+            parse("'text"),
+            Err(ExpressionError::Unterminated(0))
+        ));
+        assert!(matches!(
+            parse("now()"),
+            Err(ExpressionError::UnknownFunction(name)) if name == "now"
+        ));
+        assert!(matches!(
+            parse("if(true, 1)"),
+            Err(ExpressionError::Arguments(name, expected, 2)) if name == "if" && expected == "3"
+        ));
+        assert!(matches!(
+            evaluate("Code * 2").unwrap_err().downcast_ref::<EvaluateError>(),
+            Some(EvaluateError::NotANumber(text)) if text == "gb"
+        ));
+        assert!(matches!(
+            evaluate("1 / (Amount + 12.5)")
+                .unwrap_err()
+                .downcast_ref::<EvaluateError>(),
+            Some(EvaluateError::DivisionByZero)
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::backend::SqlBackend;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::import_options::ImportOptions;
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{trace, warn};
+use std::pin::Pin;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GuardrailError {
+    #[error(
+        "row count of '{target_table}' would change by {change:.1}% ({target_rows} to {temporary_rows} rows), exceeding {max_change:.1}%, use --force to proceed"
+    )]
+    RowCountChangeExceeded {
+        target_table: TableIdentifier,
+        target_rows: i64,
+        temporary_rows: i64,
+        change: f64,
+        max_change: f64,
+    },
+    #[error("could not count rows")]
+    CountRowsFailed(#[from] tiberius::error::Error),
+}
+
+/// Change of the row count of a target table by a destructive merge, exceeding the maximum
+/// permitted by the import options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowCountChange {
+    pub target_table: TableIdentifier,
+    pub target_rows: i64,
+    pub temporary_rows: i64,
+    /// Percentage of the target table row count
+    pub change: f64,
+}
+
+/// Confirmation of a row count change exceeding the maximum, resolving to whether the merge may
+/// proceed regardless.
+pub type ConfirmRowCountChange = dyn Fn(&RowCountChange) -> Pin<Box<dyn Future<Output = bool>>>;
+
+/// Errors if the target table row count would change by more than permitted by the import options,
+/// unless `--force` is set or the change is confirmed.
+pub async fn check_row_count_change(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    temporary_table: &TableIdentifier,
+    import_options: &ImportOptions,
+    confirm: Option<&ConfirmRowCountChange>,
+) -> Result<(), GuardrailError> {
+    let target_rows = count_rows(client, target_table).await?;
+    let temporary_rows = count_rows(client, temporary_table).await?;
+
+    if target_rows == 0 {
+        return Ok(());
+    }
+
+    let change = (target_rows - temporary_rows).abs() as f64 / target_rows as f64 * 100.0;
+
+    if change <= import_options.max_row_count_change {
+        return Ok(());
+    }
+
+    if import_options.force {
+        warn!(
+            "Row count of {} will change by {:.1}% ({} to {} rows), proceeding due to --force",
+            target_table, change, target_rows, temporary_rows,
+        );
+
+        return Ok(());
+    }
+
+    let row_count_change = RowCountChange {
+        target_table: target_table.to_owned(),
+        target_rows,
+        temporary_rows,
+        change,
+    };
+
+    if let Some(confirm) = confirm
+        && confirm(&row_count_change).await
+    {
+        warn!(
+            "Row count of {} will change by {:.1}% ({} to {} rows), proceeding as confirmed",
+            target_table, change, target_rows, temporary_rows,
+        );
+
+        return Ok(());
+    }
+
+    Err(GuardrailError::RowCountChangeExceeded {
+        target_table: row_count_change.target_table,
+        target_rows,
+        temporary_rows,
+        change,
+        max_change: import_options.max_row_count_change,
+    })
+}
+
+pub(crate) async fn count_rows(
+    client: &mut impl SqlBackend,
+    table: &TableIdentifier,
+) -> Result<i64, tiberius::error::Error> {
+    let statement = formatdoc!(
+        "
+        SELECT COUNT_BIG(*) FROM {table_name}
+        ",
+        table_name = table.full(),
+    );
+
+    trace_sql!(statement);
+
+    Ok(client
+        .query(&statement, &[])
+        .await?
+        .first()
+        .and_then(|row| row.get::<i64>(0))
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use tiberius::ColumnData;
+
+    async fn check(
+        target_rows: i64,
+        temporary_rows: i64,
+        force: bool,
+        confirm: Option<&ConfirmRowCountChange>,
+    ) -> Result<(), GuardrailError> {
+        let mut backend = MockBackend::new();
+
+        for rows in [target_rows, temporary_rows] {
+            backend.push_rows([QueryRow::new([ColumnData::I64(Some(rows))])]);
+        }
+
+        let result = check_row_count_change(
+            &mut backend,
+            &"[dbo].[Country]".parse().unwrap(),
+            &"[import].[Country]".parse().unwrap(),
+            &ImportOptions {
+                force,
+                max_row_count_change: 50.0,
+                ..ImportOptions::default()
+            },
+            confirm,
+        )
+        .await;
+
+        assert_eq!(
+            backend.statements()[1].statement.trim(),
+            "SELECT COUNT_BIG(*) FROM [import].[Country]"
+        );
+
+        result
+    }
+
+    #[tokio::test]
+    async fn check_row_count_change_within_maximum() {
+        assert!(check(100, 60, false, None).await.is_ok());
+        assert!(check(0, 1000, false, None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn check_row_count_change_exceeding_maximum() {
+        assert!(matches!(
+            check(100, 40, false, None).await,
+            Err(GuardrailError::RowCountChangeExceeded {
+                target_rows: 100,
+                temporary_rows: 40,
+                change: 60.0,
+                ..
+            })
+        ));
+        assert!(check(100, 40, true, None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn check_row_count_change_confirmed() {
+        let accept: &ConfirmRowCountChange = &|row_count_change| {
+            let change = row_count_change.change;
+
+            Box::pin(async move { change == 60.0 })
+        };
+        let decline: &ConfirmRowCountChange = &|_| Box::pin(async { false });
+
+        assert!(check(100, 40, false, Some(accept)).await.is_ok());
+        assert!(check(100, 40, false, Some(decline)).await.is_err());
+    }
+}
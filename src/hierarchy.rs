@@ -0,0 +1,182 @@
+use crate::backend::SqlBackend;
+use crate::column_graph::ColumnGraph;
+use crate::identifier::{ColumnIdentifier, Identifier, TableIdentifier};
+use crate::table_mapper::Hierarchy;
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace};
+use thiserror::Error;
+
+/// Table of the session holding the level of each key of the temporary table within the
+/// hierarchy, roots being level 0.
+pub const LEVELS_TABLE: &str = "#hierarchy_levels";
+
+/// Deepest level of a hierarchy, beyond which rows are assumed to form a cycle.
+const MAX_LEVELS: u32 = 1000;
+
+#[derive(Debug, Error)]
+pub enum HierarchyError {
+    #[error("could not find temporary table column for hierarchy column: {0}")]
+    UnknownColumn(ColumnIdentifier),
+    #[error(
+        "{0} rows are not descendants of a root of the hierarchy, their parents forming a cycle"
+    )]
+    Cycle(i64),
+    #[error("could not assign hierarchy levels")]
+    AssignLevelsFailed(#[from] tiberius::error::Error),
+}
+
+/// Assigns each key of the temporary table its level within the hierarchy, returning the deepest
+/// level, or `-1` if the temporary table is empty.
+pub async fn assign_levels(
+    client: &mut impl SqlBackend,
+    temporary_table: &TableIdentifier,
+    key_column: &ColumnIdentifier,
+    hierarchy: &Hierarchy,
+    column_graph: &ColumnGraph,
+) -> Result<i64, HierarchyError> {
+    let staged_column = |column: &ColumnIdentifier| {
+        column_graph
+            .staged_column(column)
+            .map(|column| column.unique_identifier().part().to_owned())
+            .ok_or_else(|| HierarchyError::UnknownColumn(column.to_owned()))
+    };
+
+    let key = staged_column(key_column)?;
+    let parent_key = staged_column(hierarchy.parent_key_column())?;
+
+    let statement = formatdoc!(
+        "
+        DROP TABLE IF EXISTS {LEVELS_TABLE};
+
+        WITH Levels AS (
+            SELECT S.{key} AS [key], 0 AS [level]
+            FROM {temporary_table} AS S
+            WHERE S.{parent_key} IS NULL
+                OR NOT EXISTS (SELECT 1 FROM {temporary_table} AS P WHERE P.{key} = S.{parent_key})
+            UNION ALL
+            SELECT S.{key}, L.[level] + 1
+            FROM {temporary_table} AS S
+            INNER JOIN Levels AS L ON S.{parent_key} = L.[key]
+        )
+        SELECT [key], MAX([level]) AS [level]
+        INTO {LEVELS_TABLE}
+        FROM Levels
+        GROUP BY [key]
+        OPTION (MAXRECURSION {MAX_LEVELS});
+
+        SELECT
+            (SELECT MAX([level]) FROM {LEVELS_TABLE}),
+            (SELECT COUNT_BIG(*) FROM {temporary_table} AS S WHERE NOT EXISTS (SELECT 1 FROM {LEVELS_TABLE} AS L WHERE L.[key] = S.{key}))
+        ",
+        temporary_table = temporary_table.full(),
+    );
+
+    trace_sql!(statement);
+
+    let (deepest_level, unreachable) = client
+        .query(&statement, &[])
+        .await?
+        .first()
+        .map(|row| {
+            (
+                row.get::<i32>(0).map_or(-1, i64::from),
+                row.get::<i64>(1).unwrap_or_default(),
+            )
+        })
+        .unwrap_or((-1, 0));
+
+    if unreachable > 0 {
+        return Err(HierarchyError::Cycle(unreachable));
+    }
+
+    info!(
+        "Assigned hierarchy levels of {}, {} levels deep",
+        temporary_table,
+        deepest_level + 1,
+    );
+
+    Ok(deepest_level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use crate::import_options::ImportOptions;
+    use crate::table_mapper::{Table, TableMapper};
+    use std::collections::HashMap;
+    use tiberius::ColumnData;
+
+    #[tokio::test]
+    async fn assign_levels_of_hierarchy() -> Result<(), Box<dyn std::error::Error>> {
+        let table_mapper = TableMapper::new(
+            serde_json::from_value(serde_json::json!({
+                "name": "OrgUnit",
+                "field_group": "OrgUnit",
+                "table_identifier": "[dbo].[OrgUnit]",
+                "delete_mode": "Partial",
+                "delete_action": "None",
+                "duplicate_action": "Reject",
+                "columns": [
+                    { "Parser": { "column_identifier": "[Code]", "map_column": true, "field_name": "code" } },
+                    { "Parser": { "column_identifier": "[ParentCode]", "map_column": false, "field_name": "parent_code" } },
+                    { "Lookup": {
+                        "column_identifier": "[ParentID]",
+                        "map_column": true,
+                        "table_identifier": "[dbo].[OrgUnit]",
+                        "output_column_identifier": "[ID]",
+                        "key_columns": [{ "ParserKeyColumn": { "key_column_identifier": "[Code]", "field_name": "parent_code" } }],
+                        "orphan_policy": "Null"
+                    } }
+                ],
+                "key_columns": ["[Code]"],
+                "hierarchy": { "parent_column": "[ParentID]", "parent_key_column": "[ParentCode]" }
+            }))?,
+            None,
+        )?;
+        let table_metadata = HashMap::from_iter([(table_mapper.identifier(), HashMap::default())]);
+        let column_graph =
+            ColumnGraph::new(&table_mapper, &table_metadata, &ImportOptions::default())?;
+        let temporary_table = "[import].[OrgUnit]".parse()?;
+        let key_column = table_mapper.key_columns().next().unwrap();
+        let hierarchy = table_mapper.hierarchy().unwrap();
+        let levels = |deepest_level: Option<i32>, unreachable: i64| {
+            [QueryRow::new([
+                ColumnData::I32(deepest_level),
+                ColumnData::I64(Some(unreachable)),
+            ])]
+        };
+        let mut backend = MockBackend::new();
+
+        backend.push_rows(levels(Some(2), 0));
+        backend.push_rows(levels(None, 0));
+        backend.push_rows(levels(Some(1), 3));
+
+        let mut assign_levels = async || {
+            assign_levels(
+                &mut backend,
+                &temporary_table,
+                key_column,
+                hierarchy,
+                &column_graph,
+            )
+            .await
+        };
+
+        assert_eq!(assign_levels().await?, 2);
+        assert_eq!(assign_levels().await?, -1);
+        assert!(matches!(
+            assign_levels().await,
+            Err(HierarchyError::Cycle(3))
+        ));
+
+        assert!(
+            backend.statements()[0]
+                .statement
+                .contains("OPTION (MAXRECURSION 1000)")
+        );
+
+        Ok(())
+    }
+}
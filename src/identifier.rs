@@ -6,8 +6,14 @@ use std::hash::Hash;
 use std::str::FromStr;
 use thiserror::Error;
 
+macro_rules! identifier_part_pattern {
+    () => {
+        r"(\[[^.\[\]\s]+\]|[^.\[\]\s]+)"
+    };
+}
+
 macro_rules! impl_identifier_json {
-    ($ty:ty) => {
+    ($ty:ty, $description:literal, $pattern:expr) => {
         impl<'de> Deserialize<'de> for $ty {
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
             where
@@ -29,7 +35,9 @@ macro_rules! impl_identifier_json {
 
             fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
                 json_schema!({
-                    "type": "string"
+                    "type": "string",
+                    "description": $description,
+                    "pattern": $pattern,
                 })
             }
 
@@ -45,14 +53,22 @@ pub struct DatabaseIdentifier {
     full: String,
 }
 
-impl_identifier_json!(DatabaseIdentifier);
+impl_identifier_json!(
+    DatabaseIdentifier,
+    "Database name, optionally enclosed in brackets",
+    concat!("^", identifier_part_pattern!(), "$")
+);
 
 #[derive(Debug, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub struct SchemaIdentifier {
     full: String,
 }
 
-impl_identifier_json!(SchemaIdentifier);
+impl_identifier_json!(
+    SchemaIdentifier,
+    "Schema name, optionally enclosed in brackets",
+    concat!("^", identifier_part_pattern!(), "$")
+);
 
 #[derive(Debug, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub struct TableIdentifier {
@@ -60,7 +76,17 @@ pub struct TableIdentifier {
     separator_schema_table: usize,
 }
 
-impl_identifier_json!(TableIdentifier);
+impl_identifier_json!(
+    TableIdentifier,
+    "Table name, optionally qualified by schema name (defaults to dbo)",
+    concat!(
+        "^",
+        identifier_part_pattern!(),
+        r"(\.",
+        identifier_part_pattern!(),
+        ")?$"
+    )
+);
 
 #[derive(Debug, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub struct ColumnIdentifier {
@@ -69,7 +95,17 @@ pub struct ColumnIdentifier {
     separator_table_column: usize,
 }
 
-impl_identifier_json!(ColumnIdentifier);
+impl_identifier_json!(
+    ColumnIdentifier,
+    "Column name, qualified by table name and optionally schema name",
+    concat!(
+        "^",
+        identifier_part_pattern!(),
+        r"(\.",
+        identifier_part_pattern!(),
+        "){1,2}$"
+    )
+);
 
 #[derive(Debug, Error)]
 pub enum ParseIdentifierError {
@@ -320,6 +356,23 @@ impl From<&ColumnIdentifier> for TableIdentifier {
     }
 }
 
+/// Schema for an unqualified column name, for use with `#[schemars(schema_with = "...")]`.
+pub(crate) fn column_name_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "string",
+        "description": "Column name, optionally enclosed in brackets",
+        "pattern": concat!("^", identifier_part_pattern!(), "$"),
+    })
+}
+
+/// Schema for a list of unqualified column names, for use with `#[schemars(schema_with = "...")]`.
+pub(crate) fn column_names_schema(generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "array",
+        "items": column_name_schema(generator),
+    })
+}
+
 fn normalize_identifier_part<'a, T>(part: T) -> Result<Cow<'a, str>, ParseIdentifierError>
 where
     T: Into<Cow<'a, str>>,
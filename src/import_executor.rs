@@ -1,26 +1,56 @@
-use crate::column_graph::{ColumnGraph, CreateColumnGraphError};
+use crate::backend::SqlBackend;
+use crate::blocking::BLOCKED_INTERVAL;
+use crate::bridge::BridgeError;
+use crate::checkpoint::{Checkpoint, CheckpointError, DataSourceFileIdentity};
+use crate::column_graph::{ColumnGraph, ColumnNode, CreateColumnGraphError, IndexedColumnNode};
 use crate::data_source::{DataSourceRecordIndex, DataSourceStreamItem, ReadRecordError};
+use crate::deduplication::DeduplicationHistory;
+use crate::guardrail::{ConfirmRowCountChange, GuardrailError, RowCountChange};
+use crate::hierarchy::HierarchyError;
 use crate::identifier::{ColumnIdentifier, Identifier, ParseIdentifierError, TableIdentifier};
-use crate::import_options::ImportOptions;
-use crate::import_profile::{CreateDataSourceError, ImportProfile};
+use crate::import_options::{ImportOptions, InsertStrategy};
+use crate::import_profile::{CreateDataSourceError, ImportProfile, ImportProfileDataSourceConfig};
 use crate::insert_processor::{
     CreateInsertProcessorError, FinalizeInsertProcessorError, InsertProcessor, ProcessRecordError,
 };
-use crate::merge_processor::MergeProcessorError;
-use crate::table_mapper::{Table, TableMapper, TableMapperColumn};
-use crate::temporary_table::{CreateTemporaryTableError, TemporaryTable};
-use crate::update_processor::UpdateProcessorError;
-use crate::{merge_processor, update_processor};
-use futures::{Stream, StreamExt};
+use crate::log::RunLog;
+use crate::merge_processor::{MergePlan, MergeProcessorError, MergeResult, MergeRows};
+use crate::record_accounting::{RecordAccounting, RecordAccountingError};
+use crate::run_history::TableMapperRun;
+use crate::run_report::RunReport;
+use crate::schema_report::SchemaIncompatibility;
+use crate::server_summary::ServerSummary;
+use crate::shared_scan::SharedScan;
+use crate::spatial::SpatialError;
+use crate::stream_data_source::{BatchMetrics, StreamBatch, StreamConsumer, StreamConsumerError};
+use crate::table_mapper::{
+    DeleteMode, Hierarchy, SortDirection, Table, TableMapper, TableMapperColumn, new_run_id,
+};
+use crate::temporary_table::{AbandonedTables, CreateTemporaryTableError, TemporaryTable};
+use crate::update_processor::{LookupResolution, UpdateProcessorError};
+use crate::{
+    blocking, bridge, checkpoint, constraints, guardrail, hierarchy, indexes, insert_processor,
+    merge_processor, run_history, run_report, schema_report, server_summary, snapshot, spatial,
+    statistics, temporary_table, update_processor,
+};
+use futures::future::ready;
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt};
 use itertools::{Itertools, Position};
 use log::{error, info, warn};
 use rustc_hash::{FxBuildHasher as BuildHasher, FxHashMap as HashMap, FxHashSet as HashSet};
-use std::iter::once;
+use std::cell::Cell;
+use std::iter::{once, successors};
+use std::mem;
+use std::panic::{AssertUnwindSafe, resume_unwind};
+use std::path::Path;
 use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tiberius::{BaseMetaDataColumn, Client};
-use tokio::net::TcpStream;
-use tokio_util::compat::Compat;
+use tiberius::BaseMetaDataColumn;
+
+/// Error number of a statement referencing an object which does not exist.
+const INVALID_OBJECT_NAME_ERROR: u32 = 208;
 
 #[derive(Debug, Error)]
 #[error("error executing import profile '{import_profile_name}'")]
@@ -43,6 +73,8 @@ impl ImportExecutorError {
 pub enum ImportExecutorErrorKind {
     #[error("table '{0}' metadata could not be retrieved")]
     TableMetadataRetrievalFailed(TableIdentifier, #[source] tiberius::error::Error),
+    #[error("table '{0}' metadata has an invalid column name")]
+    InvalidMetadataColumnName(TableIdentifier, #[source] ParseIdentifierError),
     #[error("column graph could not be created")]
     CreateColumnGraph(#[from] CreateColumnGraphError),
     #[error("data source could not be created")]
@@ -53,93 +85,1119 @@ pub enum ImportExecutorErrorKind {
     ExecuteTableMapper(#[from] ExecuteTableMapperError),
     #[error("temporary table could not be finalized")]
     FinalizeTemporaryTable(#[source] tiberius::error::Error),
+    #[error("deduplication history could not be loaded")]
+    LoadDeduplicationHistory(#[source] tiberius::error::Error),
+    #[error("deduplication history could not be saved")]
+    SaveDeduplicationHistory(#[source] tiberius::error::Error),
+    #[error("run history could not be loaded")]
+    LoadRunHistory(#[source] tiberius::error::Error),
+    #[error("run history could not be saved")]
+    SaveRunHistory(#[source] tiberius::error::Error),
+    #[error("stream could not be consumed")]
+    ConsumeStream(#[from] StreamConsumerError),
+    #[error("sampled records could not be converted")]
+    PreviewCoercion(#[source] ExecuteTableMapperError),
+    #[error("orphans tables could not be dropped")]
+    DropOrphans(#[source] CreateTemporaryTableError),
+    #[error("database snapshot could not be created")]
+    CreateSnapshot(#[source] tiberius::error::Error),
+    #[error("checkpoint could not be recorded")]
+    Checkpoint(#[from] CheckpointError),
+}
+
+/// Confirmation of the merge plan of a table mapper, resolving to whether the table mapper may be
+/// merged.
+pub type ConfirmMerge = dyn Fn(&TableMapper, &MergePlan) -> Pin<Box<dyn Future<Output = bool>>>;
+
+/// Handle through which the embedder of the importer takes part in the imports of a connection,
+/// the default handle taking no part.
+#[derive(Default)]
+pub struct ImportHandle {
+    confirm_merge: Option<Box<ConfirmMerge>>,
+    confirm_row_count_change: Option<Box<ConfirmRowCountChange>>,
+    abandoned_tables: AbandonedTables,
+    run_log: RunLog,
+}
+
+impl ImportHandle {
+    /// Computes the merge plan of each table mapper before merging it, failing the import if the
+    /// confirmation resolves to false, such as when prompting the user declined the merge.
+    pub fn with_confirm_merge<F>(mut self, confirm_merge: F) -> Self
+    where
+        F: Fn(&TableMapper, &MergePlan) -> Pin<Box<dyn Future<Output = bool>>> + 'static,
+    {
+        self.confirm_merge = Some(Box::new(confirm_merge));
+        self
+    }
+
+    /// Asks for confirmation of a row count change exceeding the maximum of the guardrail before a
+    /// destructive merge, instead of failing the import, such as when prompting the user.
+    pub fn with_confirm_row_count_change<F>(mut self, confirm_row_count_change: F) -> Self
+    where
+        F: Fn(&RowCountChange) -> Pin<Box<dyn Future<Output = bool>>> + 'static,
+    {
+        self.confirm_row_count_change = Some(Box::new(confirm_row_count_change));
+        self
+    }
+
+    /// Temporary tables abandoned by imports through the handle, such as by a cancelled import,
+    /// which are still to be dropped.
+    pub fn abandoned_tables(&self) -> &AbandonedTables {
+        &self.abandoned_tables
+    }
+
+    /// Log of the last import through the handle, within whose scope its error may be reported
+    /// with the secrets of the import redacted.
+    pub fn run_log(&self) -> &RunLog {
+        &self.run_log
+    }
 }
 
 pub async fn import_executor(
-    client: &mut Client<Compat<TcpStream>>,
+    client: &mut impl SqlBackend,
     import_profile: ImportProfile,
     import_options: ImportOptions,
+    import_handle: &ImportHandle,
 ) -> Result<(), ImportExecutorError> {
-    let table_names = import_profile
-        .table_mappers()
-        .flat_map(|table_mapper| {
-            table_mapper
-                .columns()
-                .filter_map(|column| match column {
-                    TableMapperColumn::Lookup(lookup_column) => {
-                        Some(Table::identifier(lookup_column))
-                    }
-                    _ => None,
-                })
-                .chain(once(Table::identifier(table_mapper)))
-        })
-        .collect::<HashSet<_>>();
+    let run_log = import_handle.run_log();
 
-    let mut table_metadata = HashMap::<
-        &TableIdentifier,
-        HashMap<ColumnIdentifier, BaseMetaDataColumn>,
-    >::with_capacity_and_hasher(table_names.len(), BuildHasher);
+    run_log.reset();
+    run_log
+        .scope(execute_run(
+            client,
+            import_profile,
+            import_options,
+            import_handle,
+        ))
+        .await
+}
 
-    for table_name in table_names {
-        table_metadata.insert(
-            table_name,
-            client
-                .column_metadata(table_name.full(), &["*"])
+async fn execute_run(
+    client: &mut impl SqlBackend,
+    import_profile: ImportProfile,
+    import_options: ImportOptions,
+    import_handle: &ImportHandle,
+) -> Result<(), ImportExecutorError> {
+    let started = Instant::now();
+    let mut completed_table_mappers = 0;
+
+    // import options may be reused across imports, such as by a watched directory
+    let import_options = ImportOptions {
+        run_id: new_run_id(),
+        ..import_options
+    };
+
+    for pattern in &import_options.redact_pattern {
+        crate::log::redact_pattern(pattern);
+    }
+
+    for table_mapper in import_profile.table_mappers() {
+        for column in table_mapper.columns() {
+            if let TableMapperColumn::Static(static_column) = column
+                && static_column.sensitive()
+            {
+                crate::log::redact_secret(static_column.value());
+            }
+        }
+    }
+
+    if let Some(dump_sql) = &import_options.dump_sql
+        && let Err(err) = import_handle
+            .run_log()
+            .start_sql_dump(dump_sql, &import_options.run_id)
+    {
+        warn!("Could not dump SQL to {}: {}", dump_sql.display(), err);
+    }
+
+    if let Some(preprocess_log_directory) = &import_options.preprocess_log_directory
+        && let Err(err) = import_handle
+            .run_log()
+            .start_preprocess_log(preprocess_log_directory)
+    {
+        warn!(
+            "Could not log preprocess scripts to {}: {}",
+            preprocess_log_directory.display(),
+            err
+        );
+    }
+
+    // temporary tables abandoned by a previous import through the handle
+    temporary_table::drop_abandoned(client, import_handle.abandoned_tables()).await;
+
+    // orphans are quarantined together across the batches and table mappers of a run, and those
+    // of an interrupted run are retained when it is resumed
+    if !import_options
+        .checkpoint
+        .as_deref()
+        .is_some_and(Path::exists)
+    {
+        temporary_table::drop_orphans(client, import_profile.table_mappers())
+            .await
+            .map_err(|err| {
+                ImportExecutorError::new(&import_profile, ImportExecutorErrorKind::DropOrphans(err))
+            })?;
+    }
+
+    let snapshot = if snapshot::required(&import_profile, &import_options) {
+        Some(
+            snapshot::create(client, &import_options.run_id)
                 .await
-                .map_err(|err| ImportExecutorError::new(
-                    &import_profile,
-                    ImportExecutorErrorKind::TableMetadataRetrievalFailed(table_name.to_owned(), err)
-                ))?
-                .into_iter()
-                .map(|metadata| Ok((
-                    ColumnIdentifier::with_table(table_name, &metadata.col_name)?,
-                    metadata.base,
-                )))
-                .collect::<Result<HashMap<ColumnIdentifier, BaseMetaDataColumn>, ParseIdentifierError>>()
-                .expect("Metadata column identifiers should be valid"),
+                .map_err(|err| {
+                    ImportExecutorError::new(
+                        &import_profile,
+                        ImportExecutorErrorKind::CreateSnapshot(err),
+                    )
+                })?,
+        )
+    } else {
+        None
+    };
+
+    let result = AssertUnwindSafe(execute_import_profile(
+        client,
+        &import_profile,
+        &import_options,
+        import_handle,
+        &mut completed_table_mappers,
+    ))
+    .catch_unwind()
+    .await;
+
+    // temporary tables abandoned by an error or panic are dropped before the panic is resumed
+    temporary_table::drop_abandoned(client, import_handle.abandoned_tables()).await;
+
+    let result = result.unwrap_or_else(|panic| resume_unwind(panic));
+
+    if import_options.dump_sql.is_some() {
+        import_handle.run_log().stop_sql_dump();
+    }
+
+    if import_options.preprocess_log_directory.is_some() {
+        import_handle.run_log().stop_preprocess_log();
+    }
+
+    if let Some(run_report) = &import_options.run_report {
+        let report = RunReport {
+            import_profile_name: import_profile.name(),
+            import_profile_sha256: import_profile.sha256(),
+            run_id: &import_options.run_id,
+            succeeded: result.is_ok(),
+            error: result.as_ref().err().map(|err| {
+                successors(Some(err as &dyn std::error::Error), |err| err.source())
+                    .map(ToString::to_string)
+                    .join(": ")
+            }),
+            elapsed_seconds: started.elapsed().as_secs_f64(),
+            table_mappers: import_profile.table_mappers().len(),
+            completed_table_mappers,
+            snapshot: snapshot.as_deref(),
+            surrogate_key_tables: import_profile
+                .table_mappers()
+                .filter_map(TableMapper::surrogate_key)
+                .map(|surrogate_key| surrogate_key.capture_table().full())
+                .collect(),
+        };
+
+        // reporting is best effort, and does not fail the import
+        if let Err(err) =
+            run_report::write(run_report, &report, import_options.signing_key.as_deref())
+        {
+            warn!(
+                "Could not write report of the run to {}: {}",
+                run_report.display(),
+                err
+            );
+        }
+    }
+
+    if import_options.server_log_summary || import_options.summary_procedure.is_some() {
+        let summary = ServerSummary {
+            import_profile_name: import_profile.name(),
+            run_id: &import_options.run_id,
+            table_mappers: import_profile.table_mappers().len(),
+            completed_table_mappers,
+            elapsed: started.elapsed(),
+            succeeded: result.is_ok(),
+            snapshot: snapshot.as_deref(),
+        };
+
+        // reporting is best effort, and does not fail the import
+        if let Err(err) = server_summary::report(client, &summary, &import_options).await {
+            warn!("Could not report summary of the run to SQL Server: {}", err);
+        }
+    }
+
+    result
+}
+
+async fn execute_import_profile(
+    client: &mut impl SqlBackend,
+    import_profile: &ImportProfile,
+    import_options: &ImportOptions,
+    import_handle: &ImportHandle,
+    completed_table_mappers: &mut usize,
+) -> Result<(), ImportExecutorError> {
+    let table_metadata = retrieve_table_metadata(client, import_profile).await?;
+
+    let ImportProfileDataSourceConfig::StreamDataSourceConfig {
+        broker,
+        batch_window,
+        ..
+    } = import_profile.data_source_config()
+    else {
+        return execute_table_mappers(
+            client,
+            import_profile,
+            import_options,
+            import_handle,
+            &table_metadata,
+            None,
+            completed_table_mappers,
+        )
+        .await;
+    };
+
+    let mut consumer = StreamConsumer::connect(broker, batch_window)
+        .await
+        .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+    info!("Consuming messages from {}", broker);
+
+    let mut batch_metrics = BatchMetrics::default();
+
+    while let Some(stream_batch) = consumer
+        .next_batch(batch_window)
+        .await
+        .map_err(|err| ImportExecutorError::new(import_profile, err))?
+    {
+        info!(
+            "Received batch of {} messages, cut by {:?} after {:.3}s",
+            stream_batch.len(),
+            stream_batch.cut(),
+            stream_batch.latency().as_secs_f64(),
+        );
+
+        *completed_table_mappers = 0;
+
+        execute_table_mappers(
+            client,
+            import_profile,
+            import_options,
+            import_handle,
+            &table_metadata,
+            Some(&stream_batch),
+            completed_table_mappers,
+        )
+        .await?;
+
+        // messages are received again if not committed, so are only committed once merged
+        if import_options.no_merge {
+            continue;
+        }
+
+        let (records, latency) = (stream_batch.len(), stream_batch.latency());
+
+        consumer
+            .commit(stream_batch)
+            .await
+            .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+        batch_metrics.record(records, latency);
+
+        info!(
+            "Committed batch of {} messages with latency {:.3}s ({})",
+            records,
+            latency.as_secs_f64(),
+            batch_metrics,
         );
     }
 
+    info!("Stream from {} closed ({})", broker, batch_metrics);
+
+    Ok(())
+}
+
+/// Executes each table mapper of the import profile in order, reading from the stream batch if
+/// the data source is a streaming data source.
+async fn execute_table_mappers(
+    client: &mut impl SqlBackend,
+    import_profile: &ImportProfile,
+    import_options: &ImportOptions,
+    import_handle: &ImportHandle,
+    table_metadata: &HashMap<&TableIdentifier, HashMap<ColumnIdentifier, BaseMetaDataColumn>>,
+    stream_batch: Option<&StreamBatch>,
+    completed_table_mappers: &mut usize,
+) -> Result<(), ImportExecutorError> {
     let data_source_config = import_profile.data_source_config();
 
-    for table_mapper in import_profile.table_mappers() {
+    // stream batches are already held in memory, and are read from there by each table mapper
+    let shared_scan = (import_options.shared_scan && stream_batch.is_none()).then(|| {
+        SharedScan::new(
+            import_profile
+                .table_mappers()
+                .map(|table_mapper| table_mapper.field_group()),
+        )
+    });
+
+    // stream batches are committed to the message queue instead
+    let mut checkpoint = match &import_options.checkpoint {
+        Some(checkpoint) if stream_batch.is_none() => Some(
+            Checkpoint::load(
+                checkpoint,
+                import_profile.sha256(),
+                data_source_config
+                    .path(import_options)
+                    .and_then(DataSourceFileIdentity::new),
+            )
+            .map_err(|err| ImportExecutorError::new(import_profile, err))?,
+        ),
+        _ => None,
+    };
+
+    let mut deferred_resolutions = Vec::new();
+
+    for (position, table_mapper) in import_profile.table_mappers().enumerate() {
+        let mut import_options =
+            import_options.with_override(table_mapper.import_options_override());
+
+        let table_mapper_checkpoint = checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.table_mapper(table_mapper.name()))
+            .unwrap_or_default();
+
+        if table_mapper_checkpoint.completed {
+            info!(
+                "Skipping table mapper {}, completed by the interrupted run",
+                table_mapper.name()
+            );
+
+            *completed_table_mappers += 1;
+
+            continue;
+        }
+
+        if let Some(max_parallel) = table_mapper.concurrency().max_parallel {
+            import_options.parse_parallelism = import_options
+                .parse_parallelism
+                .min(max_parallel.get().into());
+        }
+
+        let column_graph = ColumnGraph::new(table_mapper, table_metadata, &import_options)
+            .map(Rc::new)
+            .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+        let deduplication_history = match table_mapper.deduplication() {
+            Some(deduplication) if deduplication.history() => Some(
+                DeduplicationHistory::load(
+                    client,
+                    import_profile.name(),
+                    table_mapper.name(),
+                    deduplication.history_retention_days(),
+                )
+                .await
+                .map_err(|err| {
+                    ImportExecutorError::new(
+                        import_profile,
+                        ImportExecutorErrorKind::LoadDeduplicationHistory(err),
+                    )
+                })?,
+            ),
+            _ => None,
+        };
+
+        // stream batches are partial runs, so only whole runs are recorded in the run history
+        let record_run_history = import_options.run_history && stream_batch.is_none();
+
+        let previous_run = if record_run_history {
+            run_history::load_previous(client, import_profile.name(), table_mapper.name())
+                .await
+                .map_err(|err| {
+                    ImportExecutorError::new(
+                        import_profile,
+                        ImportExecutorErrorKind::LoadRunHistory(err),
+                    )
+                })?
+        } else {
+            None
+        };
+
+        let expected_records = if let Some(stream_batch) = stream_batch {
+            Some(stream_batch.len() as u64)
+        } else if import_options.count_records {
+            let scanned_records = shared_scan
+                .as_ref()
+                .and_then(|shared_scan| shared_scan.records(table_mapper.field_group()));
+
+            let expected_records = match scanned_records {
+                Some(scanned_records) => scanned_records,
+                None => data_source_config
+                    .count_records(table_mapper.field_group(), &import_options)
+                    .await
+                    .map_err(|err| ImportExecutorError::new(import_profile, err))?,
+            };
+
+            info!(
+                "Counted {} records in data source for table mapper {}",
+                expected_records,
+                table_mapper.name()
+            );
+
+            Some(expected_records)
+        } else {
+            None
+        };
+
+        let record_accounting = Rc::new(RecordAccounting::default());
+
+        // delimited data sources resume reading after the last merged record, unless replayed or
+        // recorded by a shared scan, which reads the data source from its start
+        let resume_position = table_mapper_checkpoint.position.filter(|_| {
+            matches!(
+                data_source_config,
+                ImportProfileDataSourceConfig::DelimitedDataSourceConfig { .. }
+            ) && shared_scan.is_none()
+        });
+
         let mut data_source: Pin<Box<dyn Stream<Item = DataSourceStreamItem>>> = data_source_config
-            .create_data_source(table_mapper, &import_options)
+            .create_data_source(
+                table_mapper,
+                &import_options,
+                deduplication_history.as_ref(),
+                stream_batch,
+                shared_scan.as_ref(),
+                &record_accounting,
+                resume_position.as_ref(),
+            )
             .await
-            .map_err(|err| ImportExecutorError::new(&import_profile, err))?
+            .map_err(|err| ImportExecutorError::new(import_profile, err))?
             .into();
 
-        let column_graph = ColumnGraph::new(table_mapper, &table_metadata, &import_options)
-            .map_err(|err| ImportExecutorError::new(&import_profile, err))?;
+        // otherwise records merged by the interrupted run are read again, but not staged
+        let resumed_records = table_mapper_checkpoint.record_number;
+
+        if resume_position.is_some() {
+            info!(
+                "Resuming table mapper {} after record {}, merged by the interrupted run",
+                table_mapper.name(),
+                resumed_records
+            );
+        } else if resumed_records > 0 {
+            info!(
+                "Skipping the first {} records of table mapper {}, merged by the interrupted run",
+                resumed_records,
+                table_mapper.name()
+            );
+
+            data_source = Box::pin(data_source.try_filter({
+                let record_accounting = record_accounting.clone();
+
+                move |record| {
+                    let skipped = record.index().record_number.get() <= resumed_records;
+
+                    if skipped {
+                        record_accounting.skipped();
+                    }
+
+                    ready(!skipped)
+                }
+            }));
+        }
+
+        let checkpointed = checkpoint.is_some() && !import_options.no_merge;
+
+        let batch_records = if checkpointed && checkpoint::batched(table_mapper) {
+            usize::try_from(import_options.checkpoint_batch_records).unwrap_or(usize::MAX)
+        } else {
+            usize::MAX
+        };
+
+        // table mappers deferring lookup resolution are neither batched nor share their target
+        // table, so their temporary tables are only resolved once the lookup tables are loaded
+        execute_deferred_resolutions(
+            client,
+            import_profile,
+            &mut deferred_resolutions,
+            &mut checkpoint,
+            |deferred_resolution| deferred_resolution.resolve_after < position,
+        )
+        .await?;
+
+        let mut merge_result = None;
+
+        // each batch is staged and merged in turn, the whole data source being a single batch
+        // unless checkpointed in batches
+        loop {
+            let batch_read = Cell::new(0);
+            let batch_record_index = Cell::new(None);
+
+            let mut batch: Pin<Box<dyn Stream<Item = DataSourceStreamItem>>> = Box::pin(
+                data_source
+                    .as_mut()
+                    .inspect(|item| {
+                        batch_read.set(batch_read.get() + 1);
+
+                        if let Ok(record) = item {
+                            batch_record_index.set(Some(*record.index()));
+                        }
+                    })
+                    .take(batch_records),
+            );
 
-        let temporary_table = TemporaryTable::new(client, table_mapper.identifier(), &column_graph)
+            let temporary_table = TemporaryTable::new(
+                client,
+                table_mapper,
+                &column_graph,
+                &import_options,
+                import_handle.abandoned_tables(),
+            )
             .await
-            .map_err(|err| ImportExecutorError::new(&import_profile, err))?;
+            .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+            let result = execute_table_mapper(
+                client,
+                &mut batch,
+                &column_graph,
+                &temporary_table,
+                table_mapper,
+                &import_options,
+                import_handle,
+                expected_records,
+                &record_accounting,
+            )
+            .await;
+
+            // keys of the deduplication history are saved for the rows merged, identified in the
+            // temporary table before it is dropped, and discarded where the merge was declined
+            if let Some(deduplication_history) = &deduplication_history {
+                match &result {
+                    Ok(Some(_)) => deduplication_history
+                        .save(client, temporary_table.identifier())
+                        .await
+                        .map_err(|err| {
+                            ImportExecutorError::new(
+                                import_profile,
+                                ImportExecutorErrorKind::SaveDeduplicationHistory(err),
+                            )
+                        })?,
+                    _ => deduplication_history.discard(),
+                }
+            }
+
+            // lookups resolving to NULL are resolved again once the table mappers populating their
+            // lookup tables are executed, the temporary table being retained until then
+            if let Ok(Some(_)) = result
+                && table_mapper.deferred_lookup_resolution()
+                && column_graph.groups().len() > 1
+            {
+                deferred_resolutions.push(DeferredResolution {
+                    table_mapper,
+                    column_graph: column_graph.clone(),
+                    temporary_table,
+                    import_options: import_options.clone(),
+                    resolve_after: resolve_after(import_profile, position, table_mapper),
+                    checkpointed,
+                });
+            } else if let Err(err) = temporary_table.finalize(client).await {
+                return Err(ImportExecutorError::new(
+                    import_profile,
+                    ImportExecutorErrorKind::FinalizeTemporaryTable(err),
+                ));
+            };
+
+            let batch_merge_result = match result {
+                Ok(merge_result) => merge_result,
+                Err(err) => return Err(ImportExecutorError::new(import_profile, err)),
+            };
+
+            merge_result = match (merge_result, batch_merge_result) {
+                (Some(merge_result), Some(batch_merge_result)) => Some(MergeResult {
+                    inserted: merge_result.inserted + batch_merge_result.inserted,
+                    updated: merge_result.updated + batch_merge_result.updated,
+                    deleted: merge_result.deleted + batch_merge_result.deleted,
+                }),
+                (merge_result, batch_merge_result) => merge_result.or(batch_merge_result),
+            };
+
+            if batch_read.get() < batch_records {
+                break;
+            }
+
+            if let Some(checkpoint) = &mut checkpoint
+                && checkpointed
+                && let Some(index) = batch_record_index.get()
+            {
+                checkpoint
+                    .commit(table_mapper.name(), &index)
+                    .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+                info!(
+                    "Checkpointed table mapper {} at record {}",
+                    table_mapper.name(),
+                    index.record_number
+                );
+            }
+        }
 
-        let result = execute_table_mapper(
+        // table mappers of which lookups are resolved again are completed once resolved
+        let resolving = deferred_resolutions.iter().any(|deferred_resolution| {
+            deferred_resolution.table_mapper.name() == table_mapper.name()
+        });
+
+        if let Some(checkpoint) = &mut checkpoint
+            && checkpointed
+            && !resolving
+        {
+            checkpoint
+                .complete(table_mapper.name())
+                .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+        }
+
+        if record_run_history && let Some(merge_result) = merge_result {
+            let run = TableMapperRun {
+                records_read: record_accounting.records_read(),
+                inserted: merge_result.inserted,
+                updated: merge_result.updated,
+                deleted: merge_result.deleted,
+            };
+
+            run_history::report(
+                table_mapper.name(),
+                &run,
+                previous_run.as_ref(),
+                import_options.anomaly_factor,
+            );
+
+            run_history::save(
+                client,
+                import_profile.name(),
+                table_mapper.name(),
+                &import_options.run_id,
+                &run,
+            )
+            .await
+            .map_err(|err| {
+                ImportExecutorError::new(
+                    import_profile,
+                    ImportExecutorErrorKind::SaveRunHistory(err),
+                )
+            })?;
+        }
+
+        *completed_table_mappers += 1;
+    }
+
+    execute_deferred_resolutions(
+        client,
+        import_profile,
+        &mut deferred_resolutions,
+        &mut checkpoint,
+        |_| true,
+    )
+    .await?;
+
+    if let Some(checkpoint) = checkpoint {
+        checkpoint
+            .remove()
+            .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+    }
+
+    Ok(())
+}
+
+/// Lookups of a merged table mapper which resolved to NULL, resolved again once the table mappers
+/// populating their lookup tables are executed.
+struct DeferredResolution<'a> {
+    table_mapper: &'a TableMapper,
+    column_graph: Rc<ColumnGraph>,
+    temporary_table: TemporaryTable,
+    import_options: ImportOptions,
+    /// Position of the last table mapper populating a lookup table, after which the lookups are
+    /// resolved
+    resolve_after: usize,
+    /// Whether the table mapper is completed in the checkpoint once its lookups are resolved
+    checkpointed: bool,
+}
+
+impl DeferredResolution<'_> {
+    /// Resolves the lookups which resolved to NULL, then merges only the rows of which a lookup is
+    /// resolved, the other rows being merged already.
+    async fn execute(
+        &self,
+        client: &mut impl SqlBackend,
+    ) -> Result<MergeResult, ExecuteTableMapperError> {
+        for (group_index, group) in self.column_graph.groups().enumerate().skip(1) {
+            update_processor::execute(
+                client,
+                &self.temporary_table,
+                group,
+                &self.column_graph,
+                self.table_mapper.lookup_cache(),
+                LookupResolution::Deferred,
+                true,
+                self.import_options.max_update_lookups.into(),
+                &self.import_options.run_id,
+            )
+            .await?;
+
+            info!(
+                "Deferred lookup resolution completed for table mapper {}, group {}",
+                self.table_mapper.name(),
+                group_index,
+            );
+        }
+
+        let order_columns =
+            merge_processor::order_columns(self.table_mapper.merge_order(), &self.column_graph)?;
+
+        merge(
             client,
-            &mut data_source,
-            &column_graph,
-            &temporary_table,
-            table_mapper,
+            self.table_mapper,
+            &self.temporary_table,
+            &self.column_graph,
+            &order_columns,
+            &self.import_options,
+            MergeRows::Resolved,
         )
-        .await;
+        .await
+    }
+}
+
+/// Position of the last table mapper populating a lookup table of the table mapper at the
+/// position, or the position itself.
+fn resolve_after(
+    import_profile: &ImportProfile,
+    position: usize,
+    table_mapper: &TableMapper,
+) -> usize {
+    let lookup_tables = table_mapper
+        .columns()
+        .filter_map(|column| match column {
+            TableMapperColumn::Lookup(lookup_column) => Some(Table::identifier(lookup_column)),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    import_profile
+        .table_mappers()
+        .enumerate()
+        .skip(position)
+        .filter(|(_, table_mapper)| lookup_tables.contains(table_mapper.identifier()))
+        .map(|(position, _)| position)
+        .last()
+        .unwrap_or(position)
+}
 
-        if let Err(err) = temporary_table.finalize(client, &import_options).await {
+/// Executes the deferred lookup resolutions which are ready, finalizing their temporary tables and
+/// completing their table mappers in the checkpoint.
+async fn execute_deferred_resolutions(
+    client: &mut impl SqlBackend,
+    import_profile: &ImportProfile,
+    deferred_resolutions: &mut Vec<DeferredResolution<'_>>,
+    checkpoint: &mut Option<Checkpoint>,
+    ready: impl Fn(&DeferredResolution<'_>) -> bool,
+) -> Result<(), ImportExecutorError> {
+    let (ready, pending): (Vec<_>, Vec<_>) = mem::take(deferred_resolutions)
+        .into_iter()
+        .partition(|deferred_resolution| ready(deferred_resolution));
+
+    *deferred_resolutions = pending;
+
+    for deferred_resolution in ready {
+        let result = deferred_resolution.execute(client).await;
+
+        if let Err(err) = deferred_resolution.temporary_table.finalize(client).await {
             return Err(ImportExecutorError::new(
-                &import_profile,
+                import_profile,
                 ImportExecutorErrorKind::FinalizeTemporaryTable(err),
             ));
-        };
+        }
+
+        let merge_result = result.map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+        info!(
+            "Merged rows of deferred lookups resolved for table mapper {}, inserted {} and updated {} rows",
+            deferred_resolution.table_mapper.name(),
+            merge_result.inserted,
+            merge_result.updated,
+        );
+
+        if let Some(checkpoint) = checkpoint
+            && deferred_resolution.checkpointed
+        {
+            checkpoint
+                .complete(deferred_resolution.table_mapper.name())
+                .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates the import profile against the target database without importing any records,
+/// converting the first sample records of the data source to the types of their columns.
+pub async fn validate(
+    client: &mut impl SqlBackend,
+    import_profile: ImportProfile,
+    import_options: ImportOptions,
+    sample_records: usize,
+) -> Result<(), ImportExecutorError> {
+    let table_metadata = retrieve_table_metadata(client, &import_profile).await?;
+
+    for table_mapper in import_profile.table_mappers() {
+        let import_options = import_options.with_override(table_mapper.import_options_override());
+
+        let column_graph = ColumnGraph::new(table_mapper, &table_metadata, &import_options)
+            .map_err(|err| ImportExecutorError::new(&import_profile, err))?;
+
+        if sample_records > 0 {
+            preview_coercion(
+                &import_profile,
+                table_mapper,
+                &column_graph,
+                &import_options,
+                sample_records,
+            )
+            .await?;
+        }
+
+        info!("Validated table mapper {}", table_mapper.name());
+    }
+
+    Ok(())
+}
+
+/// Reports where each table mapper of the import profile is incompatible with the schema of the
+/// target database, without reading the data source.
+pub async fn schema_report(
+    client: &mut impl SqlBackend,
+    import_profile: &ImportProfile,
+    import_options: &ImportOptions,
+) -> Result<Vec<SchemaIncompatibility>, ImportExecutorError> {
+    let table_names = table_names(import_profile);
+
+    let mut table_metadata = HashMap::<
+        &TableIdentifier,
+        HashMap<ColumnIdentifier, BaseMetaDataColumn>,
+    >::with_capacity_and_hasher(table_names.len(), BuildHasher);
+
+    for table_name in table_names {
+        match retrieve_column_metadata(client, table_name).await {
+            Ok(column_metadata) => {
+                table_metadata.insert(table_name, column_metadata);
+            }
+            Err(ImportExecutorErrorKind::TableMetadataRetrievalFailed(
+                _,
+                tiberius::error::Error::Server(token),
+            )) if token.code() == INVALID_OBJECT_NAME_ERROR => {}
+            Err(err) => return Err(ImportExecutorError::new(import_profile, err)),
+        }
+    }
+
+    Ok(import_profile
+        .table_mappers()
+        .flat_map(|table_mapper| {
+            let import_options =
+                import_options.with_override(table_mapper.import_options_override());
+
+            schema_report::check_table_mapper(table_mapper, &table_metadata, &import_options)
+        })
+        .collect())
+}
+
+/// Converts the fields of the first sample records of the table mapper as the insert processor
+/// would, warning of columns with values which would be converted to NULL.
+async fn preview_coercion(
+    import_profile: &ImportProfile,
+    table_mapper: &TableMapper,
+    column_graph: &ColumnGraph,
+    import_options: &ImportOptions,
+    sample_records: usize,
+) -> Result<(), ImportExecutorError> {
+    if let ImportProfileDataSourceConfig::StreamDataSourceConfig { .. } =
+        import_profile.data_source_config()
+    {
+        info!(
+            "Skipping coercion preview for table mapper {}, as streaming data sources cannot be sampled",
+            table_mapper.name()
+        );
+
+        return Ok(());
+    }
+
+    let target_columns = column_graph
+        .groups()
+        .next()
+        .into_iter()
+        .flatten()
+        .filter_map(|column| match column.column() {
+            ColumnNode::ParserColumn {
+                column: parser_column,
+                ..
+            } => Some((parser_column, column.metadata())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let record_accounting = Rc::new(RecordAccounting::default());
+
+    let data_source = import_profile
+        .data_source_config()
+        .create_data_source(
+            table_mapper,
+            import_options,
+            None,
+            None,
+            None,
+            &record_accounting,
+            None,
+        )
+        .await
+        .map_err(|err| ImportExecutorError::new(import_profile, err))?;
+
+    let mut data_source = Pin::from(data_source).take(sample_records);
+
+    // number of values converted to NULL, and the first such value, of each column
+    let mut nulled = vec![(0, None); target_columns.len()];
+    let mut records = 0;
+
+    while let Some(record) = data_source.next().await {
+        let record = record.map_err(|err| {
+            ImportExecutorError::new(
+                import_profile,
+                ImportExecutorErrorKind::PreviewCoercion(
+                    ExecuteTableMapperError::ReadRecordFailed(err),
+                ),
+            )
+        })?;
 
-        if let Err(err) = result {
-            return Err(ImportExecutorError::new(&import_profile, err));
+        records += 1;
+
+        for (&(parser_column, metadata), (count, first)) in target_columns.iter().zip(&mut nulled) {
+            let value = record
+                .get(parser_column.field_name())
+                .ok_or_else(|| ProcessRecordError::RecordMissingField {
+                    column: parser_column.identifier().to_owned(),
+                    field: parser_column.field_name().to_owned(),
+                })
+                .and_then(|field_value| {
+                    insert_processor::convert_field(field_value, parser_column, metadata)
+                        .map(|value| (field_value, value))
+                });
+
+            match value {
+                Ok((field_value, value))
+                    if !field_value.is_empty() && insert_processor::is_null(&value) =>
+                {
+                    *count += 1;
+                    first.get_or_insert_with(|| (field_value.to_owned(), record.index()));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    return Err(ImportExecutorError::new(
+                        import_profile,
+                        ImportExecutorErrorKind::PreviewCoercion(
+                            ExecuteTableMapperError::ExecuteRecordFailed(ExecuteRecordError::new(
+                                record.index(),
+                                err,
+                            )),
+                        ),
+                    ));
+                }
+            }
         }
     }
 
+    for (&(parser_column, metadata), (count, first)) in target_columns.iter().zip(nulled) {
+        if let Some((field_value, index)) = first {
+            warn!(
+                "Column {} of type {} would be NULL for {} of {} sampled records of table mapper {}, such as '{}' ({})",
+                parser_column.identifier(),
+                metadata.ty,
+                count,
+                records,
+                table_mapper.name(),
+                field_value,
+                index,
+            );
+        }
+    }
+
+    info!(
+        "Previewed coercion of {} records for table mapper {}",
+        records,
+        table_mapper.name()
+    );
+
     Ok(())
 }
 
+async fn retrieve_table_metadata<'a>(
+    client: &mut impl SqlBackend,
+    import_profile: &'a ImportProfile,
+) -> Result<
+    HashMap<&'a TableIdentifier, HashMap<ColumnIdentifier, BaseMetaDataColumn>>,
+    ImportExecutorError,
+> {
+    let table_names = table_names(import_profile);
+
+    let mut table_metadata = HashMap::<
+        &TableIdentifier,
+        HashMap<ColumnIdentifier, BaseMetaDataColumn>,
+    >::with_capacity_and_hasher(table_names.len(), BuildHasher);
+
+    for table_name in table_names {
+        table_metadata.insert(
+            table_name,
+            retrieve_column_metadata(client, table_name)
+                .await
+                .map_err(|err| ImportExecutorError::new(import_profile, err))?,
+        );
+    }
+
+    Ok(table_metadata)
+}
+
+/// Target tables and lookup tables of the table mappers of the import profile.
+fn table_names(import_profile: &ImportProfile) -> HashSet<&TableIdentifier> {
+    import_profile
+        .table_mappers()
+        .flat_map(|table_mapper| {
+            table_mapper
+                .columns()
+                .filter_map(|column| match column {
+                    TableMapperColumn::Lookup(lookup_column) => {
+                        Some(Table::identifier(lookup_column))
+                    }
+                    _ => None,
+                })
+                .chain(once(Table::identifier(table_mapper)))
+        })
+        .collect()
+}
+
+async fn retrieve_column_metadata(
+    client: &mut impl SqlBackend,
+    table_name: &TableIdentifier,
+) -> Result<HashMap<ColumnIdentifier, BaseMetaDataColumn>, ImportExecutorErrorKind> {
+    client
+        .column_metadata(table_name.full())
+        .await
+        .map_err(|err| {
+            ImportExecutorErrorKind::TableMetadataRetrievalFailed(table_name.to_owned(), err)
+        })?
+        .into_iter()
+        .map(|(column_name, metadata)| {
+            Ok((
+                ColumnIdentifier::with_table(table_name, &column_name)?,
+                metadata,
+            ))
+        })
+        .collect::<Result<HashMap<ColumnIdentifier, BaseMetaDataColumn>, ParseIdentifierError>>()
+        .map_err(|err| {
+            ImportExecutorErrorKind::InvalidMetadataColumnName(table_name.to_owned(), err)
+        })
+}
+
 #[derive(Debug, Error)]
 pub enum ExecuteTableMapperError {
     #[error("insert processor could not be created")]
@@ -178,15 +1236,65 @@ pub enum ExecuteTableMapperError {
         #[source]
         MergeProcessorError,
     ),
+    #[error("guardrail check failed")]
+    Guardrail(
+        #[from]
+        #[source]
+        GuardrailError,
+    ),
+    #[error("hierarchy levels could not be assigned")]
+    Hierarchy(
+        #[from]
+        #[source]
+        HierarchyError,
+    ),
+    #[error("bridge rows could not be refreshed")]
+    Bridge(
+        #[from]
+        #[source]
+        BridgeError,
+    ),
+    #[error("spatial columns could not be converted")]
+    Spatial(
+        #[from]
+        #[source]
+        SpatialError,
+    ),
+    #[error("merge was declined")]
+    MergeDeclined,
+    #[error("indexes could not be disabled")]
+    DisableIndexes(#[source] tiberius::error::Error),
+    #[error("indexes could not be rebuilt")]
+    RebuildIndexes(#[source] tiberius::error::Error),
+    #[error("constraints could not be deferred")]
+    DeferConstraints(#[source] tiberius::error::Error),
+    #[error("deferred constraints could not be re-validated")]
+    RevalidateConstraints(#[source] tiberius::error::Error),
+    #[error("deferred constraints are not satisfied by the merged records: {0}")]
+    ConstraintViolations(String),
+    #[error("statistics could not be updated")]
+    UpdateStatistics(#[source] tiberius::error::Error),
+    #[error("merge was blocked for {0} seconds, exceeding --max-blocked-seconds, by {1}")]
+    MergeBlocked(u64, String),
+    #[error("record accounting check failed")]
+    RecordAccounting(
+        #[from]
+        #[source]
+        RecordAccountingError,
+    ),
 }
 
 async fn execute_table_mapper<'table_mapper, 'stream>(
-    client: &mut Client<Compat<TcpStream>>,
+    client: &mut impl SqlBackend,
     data_source: &mut Pin<Box<dyn Stream<Item = DataSourceStreamItem> + 'stream>>,
     column_graph: &ColumnGraph,
     temporary_table: &TemporaryTable,
     table_mapper: &'table_mapper TableMapper,
-) -> Result<(), ExecuteTableMapperError>
+    import_options: &ImportOptions,
+    import_handle: &ImportHandle,
+    expected_records: Option<u64>,
+    record_accounting: &RecordAccounting,
+) -> Result<Option<MergeResult>, ExecuteTableMapperError>
 where
     'table_mapper: 'stream,
 {
@@ -196,19 +1304,69 @@ where
         table_mapper.name()
     );
 
+    crate::log::set_preprocess_log_table_mapper(table_mapper.name());
+
     for (position, (group_index, group)) in column_graph.groups().enumerate().with_position() {
         if matches!(position, Position::First | Position::Only) {
-            let mut insert_processor = InsertProcessor::new(client, temporary_table, group).await?;
+            let group = group.collect::<Vec<_>>();
+
+            let mut insert_processor = match InsertProcessor::new(
+                client,
+                temporary_table,
+                group.iter().copied(),
+                import_options.insert_strategy,
+            )
+            .await
+            {
+                Err(err)
+                    if import_options.insert_strategy == InsertStrategy::Auto
+                        && err.is_bulk_load_denied() =>
+                {
+                    warn!(
+                        "Bulk load permission denied for table mapper {}, inserting records with multi-row inserts instead",
+                        table_mapper.name(),
+                    );
+
+                    InsertProcessor::new(
+                        client,
+                        temporary_table,
+                        group.into_iter(),
+                        InsertStrategy::MultiRowInsert,
+                    )
+                    .await?
+                }
+                result => result?,
+            };
 
             info!(
                 "Insert processor created for table mapper {}",
                 table_mapper.name()
             );
 
+            let progress_step =
+                expected_records.map(|expected_records| (expected_records / 10).max(1));
+            let mut progress_next = progress_step.unwrap_or(u64::MAX);
+
             let insert_error = loop {
                 match data_source.next().await {
                     Some(Ok(record)) => {
                         let index = record.index();
+                        let record_number = index.record_number.get();
+
+                        if let (Some(expected_records), Some(progress_step)) =
+                            (expected_records, progress_step)
+                            && record_number >= progress_next
+                        {
+                            info!(
+                                "Read {} of {} records for table mapper {}",
+                                record_number,
+                                expected_records,
+                                table_mapper.name(),
+                            );
+
+                            progress_next =
+                                record_number - record_number % progress_step + progress_step;
+                        }
 
                         if let Err(err) = insert_processor.process_record(record).await.map_err(
                             |err| {
@@ -244,8 +1402,33 @@ where
                 table_mapper.name(),
                 result.total()
             );
+
+            // every record read should be inserted or deliberately discarded, so a mismatch
+            // indicates records silently lost by the pipeline
+            record_accounting.inserted(result.total());
+            record_accounting.check()?;
+
+            info!(
+                "Accounted for records of table mapper {} ({})",
+                table_mapper.name(),
+                record_accounting,
+            );
+
+            spatial::convert(client, temporary_table.identifier(), column_graph).await?;
         } else {
-            update_processor::execute(client, temporary_table, group, column_graph).await?;
+            update_processor::execute(
+                client,
+                temporary_table,
+                group,
+                column_graph,
+                table_mapper.lookup_cache(),
+                LookupResolution::All,
+                // orphan policies are applied once lookups resolving to NULL are resolved again
+                !(table_mapper.deferred_lookup_resolution() && !import_options.no_merge),
+                import_options.max_update_lookups.into(),
+                &import_options.run_id,
+            )
+            .await?;
 
             info!(
                 "Update processor completed for table mapper {}, group {}",
@@ -255,16 +1438,323 @@ where
         }
     }
 
-    merge_processor::execute(
+    if let Some(bridge) = table_mapper.bridge() {
+        bridge::normalize(client, temporary_table.identifier(), bridge, column_graph).await?;
+    }
+
+    if import_options.no_merge {
+        info!(
+            "Skipping merge for table mapper {} due to no merge option",
+            table_mapper.name()
+        );
+
+        return Ok(None);
+    }
+
+    if table_mapper.delete_mode() == DeleteMode::Full {
+        guardrail::check_row_count_change(
+            client,
+            table_mapper.identifier(),
+            temporary_table.identifier(),
+            import_options,
+            import_handle.confirm_row_count_change.as_deref(),
+        )
+        .await?;
+    }
+
+    if let Some(confirm_merge) = &import_handle.confirm_merge {
+        let plan = merge_processor::plan(
+            client,
+            table_mapper.identifier(),
+            temporary_table.identifier(),
+            table_mapper.normalized_key_columns(),
+            column_graph.target_columns(),
+        )
+        .await?;
+
+        if !confirm_merge(table_mapper, &plan).await {
+            return Err(ExecuteTableMapperError::MergeDeclined);
+        }
+    }
+
+    let order_columns = merge_processor::order_columns(table_mapper.merge_order(), column_graph)?;
+
+    let disabled_indexes = match import_options.disable_indexes_threshold {
+        Some(threshold) => indexes::disable_if_large(
+            client,
+            table_mapper.identifier(),
+            temporary_table.identifier(),
+            threshold,
+        )
+        .await
+        .map_err(ExecuteTableMapperError::DisableIndexes)?,
+        None => Vec::new(),
+    };
+
+    let deferred_constraints = if import_options.defer_constraints {
+        constraints::defer(client, table_mapper.identifier())
+            .await
+            .map_err(ExecuteTableMapperError::DeferConstraints)?
+    } else {
+        Vec::new()
+    };
+
+    let merge_result = async {
+        let mut merge_result = match table_mapper.hierarchy() {
+            Some(hierarchy) => {
+                merge_hierarchy(
+                    client,
+                    table_mapper,
+                    hierarchy,
+                    temporary_table,
+                    column_graph,
+                    &order_columns,
+                    import_options,
+                )
+                .await?
+            }
+            None => {
+                merge(
+                    client,
+                    table_mapper,
+                    temporary_table,
+                    column_graph,
+                    &order_columns,
+                    import_options,
+                    MergeRows::All,
+                )
+                .await?
+            }
+        };
+
+        if let Some(bridge) = table_mapper.bridge() {
+            merge_result.deleted = bridge::delete_unmatched(
+                client,
+                table_mapper.identifier(),
+                temporary_table.identifier(),
+                bridge,
+                column_graph,
+            )
+            .await?;
+        }
+
+        Ok::<_, ExecuteTableMapperError>(merge_result)
+    }
+    .await;
+
+    // disabled indexes are rebuilt even if the merge failed, as they are otherwise left unusable
+    indexes::rebuild(client, table_mapper.identifier(), &disabled_indexes)
+        .await
+        .map_err(ExecuteTableMapperError::RebuildIndexes)?;
+
+    let merge_result = match merge_result {
+        Ok(merge_result) => merge_result,
+        Err(err) => {
+            // deferred constraints are enabled even if the merge failed, without checking its rows
+            constraints::restore(client, table_mapper.identifier(), &deferred_constraints)
+                .await
+                .map_err(ExecuteTableMapperError::RevalidateConstraints)?;
+
+            return Err(err);
+        }
+    };
+
+    if !deferred_constraints.is_empty() {
+        let on_condition = merge_processor::on_condition(
+            table_mapper.normalized_key_columns(),
+            column_graph.target_columns(),
+        )?;
+
+        let violations = constraints::revalidate(
+            client,
+            table_mapper.identifier(),
+            temporary_table.identifier(),
+            &on_condition,
+            &deferred_constraints,
+        )
+        .await
+        .map_err(ExecuteTableMapperError::RevalidateConstraints)?;
+
+        if !violations.is_empty() {
+            return Err(ExecuteTableMapperError::ConstraintViolations(
+                violations.iter().join("; "),
+            ));
+        }
+    }
+
+    if let Some(threshold) = import_options.update_statistics_threshold {
+        statistics::update_if_modified(client, table_mapper.identifier(), merge_result, threshold)
+            .await
+            .map_err(ExecuteTableMapperError::UpdateStatistics)?;
+    }
+
+    Ok(Some(merge_result))
+}
+
+/// Merges the temporary table into the target table a level of the hierarchy at a time, resolving
+/// the lookups of each level once the level above is merged.
+async fn merge_hierarchy(
+    client: &mut impl SqlBackend,
+    table_mapper: &TableMapper,
+    hierarchy: &Hierarchy,
+    temporary_table: &TemporaryTable,
+    column_graph: &ColumnGraph,
+    order_columns: &[(IndexedColumnNode<'_>, SortDirection)],
+    import_options: &ImportOptions,
+) -> Result<MergeResult, ExecuteTableMapperError> {
+    let key_column = table_mapper
+        .key_columns()
+        .next()
+        .expect("hierarchies should have a single key column");
+
+    let deepest_level = hierarchy::assign_levels(
         client,
-        table_mapper.identifier(),
         temporary_table.identifier(),
-        table_mapper.key_columns(),
-        column_graph.target_columns(),
+        key_column,
+        hierarchy,
+        column_graph,
     )
     .await?;
 
-    Ok(())
+    let mut merge_result = MergeResult::default();
+
+    for level in 0..=deepest_level {
+        if level > 0 {
+            for group in column_graph.groups().skip(1) {
+                update_processor::execute(
+                    client,
+                    temporary_table,
+                    group,
+                    column_graph,
+                    table_mapper.lookup_cache(),
+                    LookupResolution::Unresolved,
+                    // the parent column of a hierarchy has the Null orphan policy, so the orphans
+                    // of the other lookups are handled once they are first resolved
+                    false,
+                    import_options.max_update_lookups.into(),
+                    &import_options.run_id,
+                )
+                .await?;
+            }
+        }
+
+        let level_result = merge(
+            client,
+            table_mapper,
+            temporary_table,
+            column_graph,
+            order_columns,
+            import_options,
+            MergeRows::HierarchyLevel(level),
+        )
+        .await?;
+
+        info!(
+            "Merged hierarchy level {} for table mapper {}, inserted {}, updated {} and deleted {} rows",
+            level,
+            table_mapper.name(),
+            level_result.inserted,
+            level_result.updated,
+            level_result.deleted,
+        );
+
+        merge_result.inserted += level_result.inserted;
+        merge_result.updated += level_result.updated;
+        merge_result.deleted += level_result.deleted;
+    }
+
+    Ok(merge_result)
+}
+
+/// Merges the temporary table into the target table, logging the sessions blocking the merge until
+/// it has been blocked for longer than the import options permit.
+async fn merge(
+    client: &mut impl SqlBackend,
+    table_mapper: &TableMapper,
+    temporary_table: &TemporaryTable,
+    column_graph: &ColumnGraph,
+    order_columns: &[(IndexedColumnNode<'_>, SortDirection)],
+    import_options: &ImportOptions,
+    merge_rows: MergeRows,
+) -> Result<MergeResult, ExecuteTableMapperError> {
+    let max_blocked = import_options.max_blocked_seconds.map(Duration::from_secs);
+    let interval = max_blocked.map_or(BLOCKED_INTERVAL, |max_blocked| {
+        max_blocked.min(BLOCKED_INTERVAL)
+    });
+    let mut blocked = Duration::ZERO;
+
+    let operation = merge_processor::operation_column(table_mapper.operation(), column_graph)?;
+
+    blocking::set_lock_timeout(client, Some(interval))
+        .await
+        .map_err(MergeProcessorError::from)?;
+
+    let result = loop {
+        match merge_processor::execute(
+            client,
+            table_mapper.identifier(),
+            temporary_table.identifier(),
+            table_mapper.normalized_key_columns(),
+            table_mapper.default_on_insert(),
+            table_mapper.surrogate_key(),
+            operation,
+            table_mapper.history(),
+            table_mapper.delete_mode(),
+            merge_rows,
+            column_graph.target_columns(),
+            order_columns,
+            table_mapper.concurrency(),
+            &import_options.run_id,
+        )
+        .await
+        {
+            Err(MergeProcessorError::MergeFailed(err)) if blocking::is_lock_timeout(&err) => {
+                blocked += interval;
+
+                let blocking_sessions = match blocking::blocking_sessions(
+                    client,
+                    table_mapper.identifier(),
+                )
+                .await
+                {
+                    Ok(blocking_sessions) if blocking_sessions.is_empty() => {
+                        String::from("sessions which have since released their locks")
+                    }
+                    Ok(blocking_sessions) => blocking_sessions.iter().join("; "),
+                    Err(err) => {
+                        warn!(
+                            "Could not query sessions blocking the merge for table mapper {}, requires VIEW SERVER STATE permission: {}",
+                            table_mapper.name(),
+                            err,
+                        );
+
+                        String::from("unknown sessions")
+                    }
+                };
+
+                if max_blocked.is_some_and(|max_blocked| blocked >= max_blocked) {
+                    break Err(ExecuteTableMapperError::MergeBlocked(
+                        blocked.as_secs(),
+                        blocking_sessions,
+                    ));
+                }
+
+                warn!(
+                    "Merge for table mapper {} blocked for {} seconds by {}",
+                    table_mapper.name(),
+                    blocked.as_secs(),
+                    blocking_sessions,
+                );
+            }
+            result => break result.map_err(ExecuteTableMapperError::from),
+        }
+    };
+
+    blocking::set_lock_timeout(client, None)
+        .await
+        .map_err(MergeProcessorError::from)?;
+
+    result
 }
 
 #[derive(Debug, Error)]
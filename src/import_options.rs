@@ -1,30 +1,201 @@
+use crate::identifier::TableIdentifier;
+use crate::table_mapper::new_run_id;
+#[cfg(feature = "cli")]
 use clap::{Parser, ValueEnum};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Parser)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(Parser))]
 pub struct ImportOptions {
-    #[arg(short = 'p', long, help_heading = "Data Source")]
+    #[cfg_attr(feature = "cli", arg(short = 'p', long, help_heading = "Data Source"))]
     /// Search for the data source file here instead of from the import profile
     pub path_override: Option<PathBuf>,
-    #[arg(
-        short = 'd',
-        long,
-        default_value = "retain",
-        help_heading = "Data Source"
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            short = 'd',
+            long,
+            default_value = "retain",
+            help_heading = "Data Source"
+        )
     )]
     pub deletion: DataSourceDeletion,
+    /// Count the records of the data source before importing, at the cost of an extra read
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Data Source"))]
+    pub count_records: bool,
+    /// Parse delimited data sources in this many chunks in parallel, only valid where no quoted
+    /// field contains a line terminator
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value_t = 1, help_heading = "Data Source")
+    )]
+    pub parse_parallelism: usize,
+    /// Handling of empty records, such as blank lines, whose fields are all empty
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_enum, default_value_t = EmptyRecordAction::Warn, help_heading = "Data Source")
+    )]
+    pub empty_records: EmptyRecordAction,
+    /// Read each field group of the data source once, spooling its records to a temporary file for
+    /// the later table mappers of the field group rather than reading the data source again
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Data Source"))]
+    pub shared_scan: bool,
+    /// Resolve at most this many lookup columns in each update statement, splitting the lookups of
+    /// a table mapper across several statements
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..))
+    )]
+    pub max_update_lookups: u16,
+    /// Preview the merge plan of each table mapper and wait for confirmation before merging, or
+    /// before proceeding past a guardrail
+    #[cfg(feature = "cli")]
+    #[arg(long)]
+    pub interactive: bool,
+    /// Update the statistics of each target table after merging, where the rows inserted and
+    /// updated are at least this percentage of its rows
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub update_statistics_threshold: Option<f64>,
+    /// Disable the nonclustered indexes of each target table before merging, rebuilding them
+    /// afterwards, where the rows of the temporary table are at least this percentage of its rows
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub disable_indexes_threshold: Option<f64>,
+    /// Disable the foreign key and check constraints of each target table while merging,
+    /// re-validating them afterwards and reporting the records of the rows which do not satisfy
+    /// them, such as for historical backfills
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub defer_constraints: bool,
+    /// Truncate and reuse the temporary table of a previous run if its columns are unchanged,
+    /// rather than dropping and creating it, truncating rather than dropping it after each table
+    /// mapper execution
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Staging"))]
+    pub reuse_staging: bool,
+    /// Compress the temporary table, reducing the space taken by large staging loads at the cost
+    /// of CPU
+    #[cfg_attr(feature = "cli", arg(long, value_enum, help_heading = "Staging"))]
+    pub staging_compression: Option<StagingCompression>,
+    /// Create the temporary table on this filegroup rather than the default filegroup
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Staging"))]
+    pub staging_filegroup: Option<String>,
+    /// Create the temporary table as a memory-optimized table without durability, indexed by the
+    /// key columns, where the server and database support memory-optimized tables. Compression and
+    /// filegroup options only apply where the temporary table falls back to a disk-based table
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Staging"))]
+    pub memory_optimized_staging: bool,
+    /// Create the temporary table as a temporary table in tempdb, visible only to the session
+    /// importing, such that no staging table is created in the database. Tables quarantining
+    /// orphans are still created in the database
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help_heading = "Staging",
+            conflicts_with_all = ["reuse_staging", "staging_filegroup", "memory_optimized_staging"]
+        )
+    )]
+    pub tempdb_staging: bool,
+    /// Insertion of records into the temporary table
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_enum, default_value_t = InsertStrategy::Auto, help_heading = "Staging")
+    )]
+    pub insert_strategy: InsertStrategy,
     /// Do not merge results from the temporary table to the target table
-    #[arg(long, requires = "no_drop", help_heading = "Developer")]
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "no_drop", help_heading = "Developer")
+    )]
     pub no_merge: bool,
     /// Do not drop the temporary table after each table mapper execution
-    #[arg(long, help_heading = "Developer")]
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Developer"))]
     pub no_drop: bool,
     /// Do not merge duplicate columns
-    #[arg(long, help_heading = "Developer")]
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Developer"))]
     pub no_duplicate_optimization: bool,
+    /// Write each generated statement into its own file of this directory, in execution order
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Developer"))]
+    pub dump_sql: Option<PathBuf>,
+    /// Write the log output of preprocess scripts into a file of this directory for each table
+    /// mapper, with the index of the record being transformed
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Developer"))]
+    pub preprocess_log_directory: Option<PathBuf>,
+    /// Proceed even when a guardrail would abort the import
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Guardrails"))]
+    pub force: bool,
+    /// Maximum percentage change of the target table row count for the Full delete mode
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value_t = 50.0, help_heading = "Guardrails")
+    )]
+    pub max_row_count_change: f64,
+    /// Abort the table mapper once its merge has been blocked by locks of other sessions for this
+    /// many seconds, logging the blocking sessions every 10 seconds meanwhile
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Guardrails"))]
+    pub max_blocked_seconds: Option<u64>,
+    /// Create a database snapshot before importing when a table mapper has the Full delete mode,
+    /// such that the database may be reverted to it, recording its name in the summary of the run
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Guardrails"))]
+    pub snapshot_before_delete: bool,
+    /// Record the progress of the run in this checkpoint file, such that an interrupted run resumes
+    /// from the last batch merged when run again with the same file
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Guardrails"))]
+    pub checkpoint: Option<PathBuf>,
+    /// Records of each batch merged while recording a checkpoint
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value_t = 100_000, value_parser = clap::value_parser!(u64).range(1..), help_heading = "Guardrails")
+    )]
+    pub checkpoint_batch_records: u64,
+    /// Verify the import profile against its detached Ed25519 signature, the file of the import
+    /// profile suffixed with .sig, with this PEM encoded public key, aborting before connecting if
+    /// the signature is missing or does not match
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Guardrails"))]
+    pub verifying_key: Option<PathBuf>,
+    /// Write a summary of the run to the SQL Server error log once it completes, requiring sysadmin
+    /// or ALTER TRACE permission
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Reporting"))]
+    pub server_log_summary: bool,
+    /// Execute this stored procedure with a summary of the run once it completes, passing the
+    /// @ProfileName, @RunId, @Succeeded and @Summary parameters
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Reporting"))]
+    pub summary_procedure: Option<TableIdentifier>,
+    /// Record the records read, inserted, updated and deleted by each table mapper in the
+    /// [import].[run_history] table, reporting the change from the previous run
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Reporting"))]
+    pub run_history: bool,
+    /// Warn when the records read, inserted, updated or deleted by a table mapper change by this
+    /// factor from the previous run, either way
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value_t = 10.0, help_heading = "Reporting")
+    )]
+    pub anomaly_factor: f64,
+    /// Write a JSON report of the run to this file once it completes, including the SHA-256
+    /// digest of the import profile and the run id
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Reporting"))]
+    pub run_report: Option<PathBuf>,
+    /// Sign the run report with this PEM encoded Ed25519 private key, writing its detached
+    /// signature to the file of the run report suffixed with .sig
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, help_heading = "Reporting", requires = "run_report")
+    )]
+    pub signing_key: Option<PathBuf>,
+    /// Redact matches of this regular expression from the log, such as secrets logged by the
+    /// preprocess script, in addition to the password of the connection string and sensitive
+    /// static values
+    #[cfg_attr(feature = "cli", arg(long, help_heading = "Reporting"))]
+    pub redact_pattern: Vec<Regex>,
+    /// Identifier of the run, generated again by the import executor for each import
+    #[cfg_attr(feature = "cli", arg(skip = new_run_id()))]
+    pub run_id: String,
 }
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
 pub enum DataSourceDeletion {
     /// Retain the data source file
     Retain,
@@ -32,14 +203,155 @@ pub enum DataSourceDeletion {
     Delete,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum EmptyRecordAction {
+    /// Skip empty records
+    Skip,
+    /// Skip empty records, warning of each
+    Warn,
+    /// Error on empty records
+    Error,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum InsertStrategy {
+    /// Bulk load, falling back to multi-row inserts where bulk load permission is denied
+    Auto,
+    /// Bulk load
+    BulkLoad,
+    /// Parameterized multi-row inserts, slower than bulk load but requiring only insert permission
+    MultiRowInsert,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, JsonSchema, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum StagingCompression {
+    /// Row compression
+    Row,
+    /// Page compression, compressing further than row compression
+    Page,
+}
+
+impl StagingCompression {
+    /// Keyword of the `DATA_COMPRESSION` table option.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            StagingCompression::Row => "ROW",
+            StagingCompression::Page => "PAGE",
+        }
+    }
+}
+
 impl Default for ImportOptions {
     fn default() -> Self {
         Self {
             path_override: None,
             deletion: DataSourceDeletion::Retain,
+            count_records: false,
+            parse_parallelism: 1,
+            empty_records: EmptyRecordAction::Warn,
+            shared_scan: false,
+            max_update_lookups: 16,
+            #[cfg(feature = "cli")]
+            interactive: false,
+            update_statistics_threshold: None,
+            disable_indexes_threshold: None,
+            defer_constraints: false,
+            reuse_staging: false,
+            staging_compression: None,
+            staging_filegroup: None,
+            memory_optimized_staging: false,
+            tempdb_staging: false,
+            insert_strategy: InsertStrategy::Auto,
             no_merge: false,
             no_drop: false,
             no_duplicate_optimization: false,
+            dump_sql: None,
+            preprocess_log_directory: None,
+            force: false,
+            max_row_count_change: 50.0,
+            max_blocked_seconds: None,
+            snapshot_before_delete: false,
+            checkpoint: None,
+            checkpoint_batch_records: 100_000,
+            verifying_key: None,
+            server_log_summary: false,
+            summary_procedure: None,
+            run_history: false,
+            anomaly_factor: 10.0,
+            run_report: None,
+            signing_key: None,
+            redact_pattern: Vec::new(),
+            run_id: new_run_id(),
+        }
+    }
+}
+
+/// Import options overridden for a single table mapper within the import profile, taking
+/// precedence over the import options the import profile is executed with.
+#[derive(Debug, Default, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportOptionsOverride {
+    /// Do not merge results from the temporary table to the target table
+    pub no_merge: Option<bool>,
+    /// Do not drop the temporary table after the table mapper execution
+    pub no_drop: Option<bool>,
+    /// Do not merge duplicate columns
+    pub no_duplicate_optimization: Option<bool>,
+    /// Resolve at most this many lookup columns in each update statement
+    pub max_update_lookups: Option<u16>,
+    /// Maximum percentage change of the target table row count for the Full delete mode
+    pub max_row_count_change: Option<f64>,
+    /// Update the statistics of the target table after merging, where the rows inserted and updated
+    /// are at least this percentage of its rows
+    pub update_statistics_threshold: Option<f64>,
+    /// Disable the nonclustered indexes of the target table before merging, rebuilding them
+    /// afterwards, where the rows of the temporary table are at least this percentage of its rows
+    pub disable_indexes_threshold: Option<f64>,
+    /// Disable the foreign key and check constraints of the target table while merging,
+    /// re-validating them afterwards
+    pub defer_constraints: Option<bool>,
+    /// Compress the temporary table
+    pub staging_compression: Option<StagingCompression>,
+    /// Create the temporary table on this filegroup
+    pub staging_filegroup: Option<String>,
+}
+
+impl ImportOptions {
+    pub fn with_override(&self, import_options_override: &ImportOptionsOverride) -> Self {
+        let ImportOptionsOverride {
+            no_merge,
+            no_drop,
+            no_duplicate_optimization,
+            max_update_lookups,
+            max_row_count_change,
+            update_statistics_threshold,
+            disable_indexes_threshold,
+            defer_constraints,
+            staging_compression,
+            ref staging_filegroup,
+        } = *import_options_override;
+
+        Self {
+            no_merge: no_merge.unwrap_or(self.no_merge),
+            no_drop: no_drop.unwrap_or(self.no_drop),
+            no_duplicate_optimization: no_duplicate_optimization
+                .unwrap_or(self.no_duplicate_optimization),
+            max_update_lookups: max_update_lookups
+                .filter(|&max_update_lookups| max_update_lookups > 0)
+                .unwrap_or(self.max_update_lookups),
+            max_row_count_change: max_row_count_change.unwrap_or(self.max_row_count_change),
+            update_statistics_threshold: update_statistics_threshold
+                .or(self.update_statistics_threshold),
+            disable_indexes_threshold: disable_indexes_threshold.or(self.disable_indexes_threshold),
+            defer_constraints: defer_constraints.unwrap_or(self.defer_constraints),
+            staging_compression: staging_compression.or(self.staging_compression),
+            staging_filegroup: staging_filegroup
+                .clone()
+                .or_else(|| self.staging_filegroup.clone()),
+            ..self.clone()
         }
     }
 }
@@ -1,62 +1,301 @@
 pub(crate) mod import_profile_raw;
 
+use crate::avro_data_source::{AvroDataSource, CreateAvroDataSourceError};
 use crate::data_source::{
-    DataSourceErrorIndex, DataSourceRecord, DataSourceStreamItem, ReadRecordError,
+    CustomDataSourceError, DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex,
+    DataSourceStreamItem, ReadRecordError, custom_data_source,
 };
-use crate::delimited_data_source::{CreateDelimitedDataSourceError, DelimitedDataSource};
-use crate::import_options::ImportOptions;
-use crate::import_profile::import_profile_raw::ImportProfileRaw;
+use crate::date_format::{DateFormat, DateOutput};
+use crate::deduplication::{Deduplication, DeduplicationHistory, Deduplicator};
+use crate::delimited_data_source::{
+    CreateDelimitedDataSourceError, DelimitedCheckpoint, DelimitedDataSource, SkipFooter,
+};
+use crate::expression::{ComputedField, EvaluateError};
+use crate::import_options::{EmptyRecordAction, ImportOptions};
+use crate::import_profile::import_profile_raw::{ImportProfileRaw, PreprocessScript};
+use crate::json_data_source::{CreateJsonDataSourceError, JsonDataSource, JsonFormat};
+use crate::number_format::{DecimalSeparator, normalize_number};
 use crate::preprocess;
 use crate::preprocess::{
     LoadPreprocessRuntimeError, PreprocessTransform, PreprocessTransformError,
 };
-use crate::table_mapper::{CreateTableMapperError, TableMapper};
-use crate::xml_data_source::{CreateXmlDataSourceError, XmlDataSource};
+use crate::record_accounting::RecordAccounting;
+use crate::sequence::Sequencer;
+use crate::shared_scan::{Scan, SharedScan};
+use crate::sql_data_source::{CreateSqlDataSourceError, SqlDataSource};
+use crate::stream_data_source;
+use crate::stream_data_source::{StreamBatch, StreamBatchWindow, StreamBroker, StreamPayload};
+use crate::table_mapper::{CreateTableMapperError, DeleteMode, Operation, Route, TableMapper};
+use crate::template::Templater;
+use crate::xml_data_source::{
+    CreateXmlDataSourceError, UnknownFieldPolicy, XmlDataSource, XsdValidation,
+};
+use encoding_rs::Encoding;
+use futures::future::{Either, ready};
 use futures::{Stream, TryStreamExt};
 use log::warn;
+use petgraph::graph::{DiGraph, NodeIndex};
 use rustc_hash::FxHashMap as HashMap;
-use schemars::JsonSchema;
-use serde::Deserialize;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Debug, JsonSchema)]
 #[schemars(with = "ImportProfileRaw")]
 pub struct ImportProfile {
     name: String,
+    sha256: String,
     description: Option<String>,
     data_source_config: ImportProfileDataSourceConfig,
     table_mappers: Vec<TableMapper>,
 }
 
 #[derive(Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub enum ImportProfileDataSourceConfig {
+    /// XML file, with a record for each element matched by the selector
     XmlDataSourceConfig {
         path: PathBuf,
-        field_groups: HashMap<String, Vec<Field>>,
-        selector: String,
+        /// Fields of each field group, keyed by field group name
+        #[schemars(schema_with = "non_empty_field_groups_schema")]
+        field_groups: HashMap<String, FieldGroup>,
+        selector: XmlSelector,
+        /// XSD to validate the document against
+        #[serde(default)]
+        xsd: Option<PathBuf>,
+        /// Whether the document is validated against the XSD before reading records, or while
+        /// reading them
+        #[serde(default)]
+        xsd_validation: XsdValidation,
+        /// Skip malformed records, resuming at the next record element, instead of failing
+        #[serde(default)]
+        recover: bool,
+        /// Name of a field holding the raw source text of each record, which may be mapped like any
+        /// other field
+        #[serde(default)]
+        raw_payload_field: Option<String>,
+        /// Handling of elements of a record which are not a field of the field group
+        #[serde(default)]
+        unknown_fields: UnknownFieldPolicy,
     },
+    /// Delimited text file, with a record for each line
     DelimitedDataSourceConfig {
         path: PathBuf,
-        field_groups: HashMap<String, Vec<Field>>,
+        /// Fields of each field group, keyed by field group name
+        field_groups: HashMap<String, FieldGroup>,
         reader_config: DelimitedReaderConfig,
+        /// Name of a field holding the raw source text of each record, which may be mapped like any
+        /// other field
+        #[serde(default)]
+        raw_payload_field: Option<String>,
+    },
+    /// JSON or JSON Lines file, with a record for each object of the array matched by the selector
+    JsonDataSourceConfig {
+        path: PathBuf,
+        /// Fields of each field group, keyed by field group name, read from the object key of the
+        /// same name
+        #[schemars(schema_with = "non_empty_field_groups_schema")]
+        field_groups: HashMap<String, FieldGroup>,
+        #[serde(default)]
+        format: JsonFormat,
+        /// Selector of the nested array of objects to read records from, such as `Order/Lines`,
+        /// otherwise the top level array of a document, or the object on each line
+        #[serde(default)]
+        selector: Option<String>,
+        /// Name of a field holding the raw source text of each record, which may be mapped like any
+        /// other field
+        #[serde(default)]
+        raw_payload_field: Option<String>,
+    },
+    /// Avro object container file, with a record for each record of the file
+    AvroDataSourceConfig {
+        path: PathBuf,
+        /// Fields of each field group, keyed by field group name, read from the record field of
+        /// the same name, where an empty field group reads every field of the writer schema
+        field_groups: HashMap<String, FieldGroup>,
+    },
+    /// Query executed against another SQL Server, with a record for each row of its first result
+    /// set
+    SqlDataSourceConfig {
+        /// ADO.NET connection string of the source server
+        connection_string: String,
+        query: String,
+        /// Fields of each field group, keyed by field group name, read from the result set column
+        /// of the same name, where an empty field group reads every column
+        field_groups: HashMap<String, FieldGroup>,
+    },
+    /// Messages consumed from a message queue, with a record for each message, staged and merged
+    /// in batches until the message queue is closed
+    StreamDataSourceConfig {
+        broker: StreamBroker,
+        /// Fields of each field group, keyed by field group name
+        field_groups: HashMap<String, FieldGroup>,
+        payload: StreamPayload,
+        /// Limits deciding when a batch of messages is cut, after which it is staged, merged and
+        /// committed
+        #[serde(default)]
+        batch_window: StreamBatchWindow,
+        /// Name of a field holding the raw payload of each message, which may be mapped like any
+        /// other field
+        #[serde(default)]
+        raw_payload_field: Option<String>,
+    },
+    /// Data source implemented outside of the crate, registered as its kind with
+    /// `data_source::register_data_source`
+    CustomDataSourceConfig {
+        kind: String,
+        /// Options of the data source, as understood by its implementation
+        #[serde(default)]
+        options: serde_json::Value,
+        /// Fields of each field group, keyed by field group name
+        field_groups: HashMap<String, FieldGroup>,
+    },
+}
+
+/// Schema for the field groups of data sources which read only the fields listed, and so reject a
+/// field group without fields, for use with `#[schemars(schema_with = "...")]`.
+fn non_empty_field_groups_schema(generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "object",
+        "additionalProperties": {
+            "allOf": [generator.subschema_for::<FieldGroup>()],
+            "if": { "type": "array" },
+            "then": { "minItems": 1 },
+            "else": { "properties": { "fields": { "minItems": 1 } } },
+        },
+    })
+}
+
+/// Selectors of the elements to read records from, such as `Orders/Order`, `ord:Order`,
+/// `{urn:example}Order` or `Root/*/Item`.
+#[derive(Debug, JsonSchema, Deserialize)]
+#[serde(untagged)]
+pub enum XmlSelector {
+    /// Selector of the records of every field group
+    Selector(String),
+    /// Selectors of the records of every field group, all of the same depth, a record being read
+    /// from each element matched by any of them
+    Selectors(Vec<String>),
+    /// Selectors of the records of each field group, keyed by field group name, such that a
+    /// document holding several record collections feeds a field group from each
+    FieldGroups(HashMap<String, XmlSelector>),
+}
+
+impl XmlSelector {
+    /// Selectors of the records of the field group, if any.
+    pub fn selectors(&self, field_group: &str) -> Option<Vec<&str>> {
+        match self {
+            XmlSelector::Selector(selector) => Some(vec![selector.as_str()]),
+            XmlSelector::Selectors(selectors) => {
+                Some(selectors.iter().map(String::as_str).collect())
+            }
+            XmlSelector::FieldGroups(field_groups) => {
+                field_groups.get(field_group)?.selectors(field_group)
+            }
+        }
+    }
+}
+
+impl Display for XmlSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XmlSelector::Selector(selector) => write!(f, "{}", selector),
+            XmlSelector::Selectors(selectors) => write!(f, "{}", selectors.join(", ")),
+            XmlSelector::FieldGroups(field_groups) => {
+                let mut field_groups = field_groups.iter().collect::<Vec<_>>();
+
+                field_groups.sort_by_key(|(field_group, _)| *field_group);
+
+                for (index, (field_group, selector)) in field_groups.into_iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "; ")?;
+                    }
+
+                    write!(f, "{}: {}", field_group, selector)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fields of a field group, either as a list of fields, or alongside constant pseudo-fields set on
+/// every record read for the field group.
+#[derive(Debug, JsonSchema, Deserialize)]
+#[serde(untagged)]
+pub enum FieldGroup {
+    Fields(Vec<Field>),
+    WithConstants {
+        fields: Vec<Field>,
+        /// Values of constant pseudo-fields, keyed by field name, such as a source system code
+        constants: HashMap<String, String>,
     },
 }
 
+impl FieldGroup {
+    pub fn fields(&self) -> &[Field] {
+        match self {
+            FieldGroup::Fields(fields) | FieldGroup::WithConstants { fields, .. } => fields,
+        }
+    }
+
+    fn fields_mut(&mut self) -> &mut [Field] {
+        match self {
+            FieldGroup::Fields(fields) | FieldGroup::WithConstants { fields, .. } => fields,
+        }
+    }
+
+    pub fn constants(&self) -> impl Iterator<Item = (&str, &str)> {
+        let constants = match self {
+            FieldGroup::Fields(_) => None,
+            FieldGroup::WithConstants { constants, .. } => Some(constants),
+        };
+
+        constants
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
 #[derive(Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Field {
+    /// Name of the field, which for XML may be a path relative to the record element, such as
+    /// `Address/City`
     name: String,
     formatters: Option<Vec<Formatter>>,
     required: Option<Required>,
+    /// Trim leading and trailing whitespace from the field, overriding `trim_fields` of the import
+    /// profile
+    #[serde(default)]
+    trim: Option<bool>,
 }
 
 impl Field {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn formatters(&self) -> &[Formatter] {
+        self.formatters.as_deref().unwrap_or_default()
+    }
+
+    pub fn required(&self) -> Option<&Required> {
+        self.required.as_ref()
+    }
+
+    pub fn trim(&self) -> bool {
+        self.trim.unwrap_or(false)
+    }
 }
 
 #[derive(Debug, JsonSchema, Deserialize)]
@@ -68,39 +307,138 @@ pub enum Formatter {
     /// Lowercase the field
     Lowercase,
     /// Apply regex substitution to the field
-    Regex,
+    Regex {
+        /// Regular expression matching the parts of the field to replace
+        #[serde(deserialize_with = "deserialize_regex")]
+        #[schemars(with = "String")]
+        pattern: regex::Regex,
+        /// Replacement of each match, which may reference capture groups, such as `$1` or
+        /// `${name}`
+        replacement: String,
+    },
     /// Map values for the field
     Map {
+        /// Value of fields matching none of the mappings, otherwise left as is
         default: Option<String>,
+        /// Pairs of a field value and the value it is mapped to
         mappings: Vec<(String, String)>,
     },
+    /// Parse dates of the field into ISO 8601, leaving fields matching none of the formats as is
+    ParseDate {
+        /// Formats of the dates, the first format matching the field being used, such as
+        /// `DD/MM/YYYY` or `YYYYMMDD`, of the tokens `YYYY`, `MM`, `M`, `MMM`, `DD`, `D`, `hh`,
+        /// `mm` and `ss`
+        #[serde(deserialize_with = "deserialize_date_formats")]
+        #[schemars(with = "Vec<String>")]
+        input_formats: Vec<DateFormat>,
+        output: DateOutput,
+    },
+    /// Parse numbers of the field into plain decimal notation, removing thousands separators and
+    /// converting trailing minus signs and parentheses into a leading minus sign, leaving fields
+    /// which are not numbers as is
+    ParseNumber {
+        /// Decimal separator of the numbers, `Point` if not specified
+        #[serde(default)]
+        decimal_separator: DecimalSeparator,
+    },
 }
 
 impl Formatter {
-    pub fn apply<'formatter, 'value>(
-        &'formatter self,
-        value: Cow<'value, str>,
-    ) -> Cow<'value, str> {
-        todo!()
+    /// Formats the field value, borrowing the value where it is unchanged.
+    pub fn apply<'value>(&self, value: Cow<'value, str>) -> Cow<'value, str> {
+        match self {
+            Formatter::Trim => match value {
+                Cow::Borrowed(value) => Cow::Borrowed(value.trim()),
+                Cow::Owned(value) if value.trim().len() == value.len() => Cow::Owned(value),
+                Cow::Owned(value) => Cow::Owned(value.trim().to_owned()),
+            },
+            Formatter::Uppercase if value.chars().any(char::is_lowercase) => {
+                Cow::Owned(value.to_uppercase())
+            }
+            Formatter::Lowercase if value.chars().any(char::is_uppercase) => {
+                Cow::Owned(value.to_lowercase())
+            }
+            Formatter::Uppercase | Formatter::Lowercase => value,
+            Formatter::Regex {
+                pattern,
+                replacement,
+            } => {
+                let replaced = match pattern.replace_all(&value, replacement.as_str()) {
+                    Cow::Borrowed(_) => None,
+                    Cow::Owned(replaced) => Some(replaced),
+                };
+
+                replaced.map_or(value, Cow::Owned)
+            }
+            Formatter::Map { default, mappings } => mappings
+                .iter()
+                .find(|(from, _)| *from == *value)
+                .map(|(_, to)| to)
+                .or(default.as_ref())
+                .map_or(value, |to| Cow::Owned(to.clone())),
+            Formatter::ParseDate {
+                input_formats,
+                output,
+            } => input_formats
+                .iter()
+                .find_map(|input_format| input_format.parse(&value))
+                .map_or(value, |date| Cow::Owned(date.format(*output))),
+            Formatter::ParseNumber { decimal_separator } => {
+                normalize_number(&value, *decimal_separator)
+                    .filter(|number| *number != *value)
+                    .map_or(value, Cow::Owned)
+            }
+        }
     }
 }
 
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<regex::Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+
+    regex::Regex::new(&pattern).map_err(|err| {
+        serde::de::Error::custom(format!("invalid regular expression '{}': {}", pattern, err))
+    })
+}
+
+fn deserialize_date_formats<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<DateFormat>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|format| {
+            format.parse().map_err(|err| {
+                serde::de::Error::custom(format!("invalid date format '{}': {}", format, err))
+            })
+        })
+        .collect()
+}
+
 #[derive(Debug, JsonSchema, Deserialize)]
 pub enum Required {
-    /// Drop record if field is empty
+    /// Drop record if field is empty or missing, once formatted
     Drop,
-    /// Error on record if field is empty
+    /// Error on record if field is empty or missing, once formatted
     Error,
 }
 
 #[derive(Debug, Copy, Clone, JsonSchema, Deserialize)]
 pub enum DelimitedReaderConfig {
+    /// Comma delimited, CRLF terminated, double quoted
     Csv,
+    /// Tab delimited, CRLF terminated, double quoted
     Txt,
+    /// As Csv, but split by a faster SIMD accelerated parser, which does not support the custom
+    /// options
+    SimdCsv,
+    /// As Txt, but split by a faster SIMD accelerated parser, which does not support the custom
+    /// options
+    SimdTxt,
+    /// Custom delimiter, terminator and quoting
     Custom(DelimitedReaderCustomConfig),
 }
 
 #[derive(Debug, Copy, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DelimitedReaderCustomConfig {
     pub delimiter: char,
     pub terminator: Terminator,
@@ -114,12 +452,38 @@ pub struct DelimitedReaderCustomConfig {
     pub escape: Option<char>,
     #[serde(default)]
     pub double_quote: bool,
+    /// Encoding of the data source file, by its WHATWG label such as `windows-1252`, `latin1` or
+    /// `utf-16le`, transcoded to UTF-8 while read, defaulting to UTF-8
+    #[serde(default, deserialize_with = "deserialize_encoding")]
+    #[schemars(with = "Option<String>")]
+    pub encoding: Option<&'static Encoding>,
+    /// Lines preceding the header to skip, such as the title lines of a report, which need not be
+    /// valid delimited records
+    #[serde(default)]
+    pub skip_rows: u64,
+    /// Records following the last data record to skip, such as the summary rows of a report
+    #[serde(default)]
+    pub skip_footer_rows: u64,
+}
+
+fn deserialize_encoding<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<&'static Encoding>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|label| {
+            Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+                serde::de::Error::custom(format!("unknown encoding label '{}'", label))
+            })
+        })
+        .transpose()
 }
 
 #[derive(Debug, Default, Copy, Clone, JsonSchema, Deserialize)]
 pub enum Terminator {
+    /// Either CR, LF or CRLF
     #[default]
     CRLF,
+    /// Custom terminator character
     Any(char),
 }
 
@@ -133,12 +497,17 @@ impl Default for DelimitedReaderCustomConfig {
             comment: None,
             escape: None,
             double_quote: true,
+            encoding: None,
+            skip_rows: 0,
+            skip_footer_rows: 0,
         }
     }
 }
 
 #[derive(Debug, Error)]
 pub enum CreateImportProfileError {
+    #[error("could not read import profile")]
+    ReadError(#[source] std::io::Error),
     #[error("could not deserialize import profile")]
     DeserializationError(
         #[from]
@@ -159,6 +528,25 @@ pub enum CreateImportProfileError {
         #[source]
         LoadPreprocessRuntimeError,
     ),
+    #[error("duplicate table mapper name '{0}'")]
+    DuplicateTableMapperName(String),
+    #[error("table mapper '{table_mapper}' depends on unknown table mapper '{dependency}'")]
+    UnknownTableMapperDependency {
+        table_mapper: String,
+        dependency: String,
+    },
+    #[error("table mapper dependency cycle with table mapper '{0}'")]
+    TableMapperDependencyCycle(String),
+    #[error("table mapper '{0}' routes records to no target table")]
+    RoutingTargetsEmpty(String),
+    #[error("table mapper '{0}' cannot use the Full delete mode with a stream data source")]
+    StreamFullDelete(String),
+    #[error(
+        "table mapper '{0}' cannot defer lookup resolution, as another table mapper loads its target table"
+    )]
+    DeferredSharedTarget(String),
+    #[error("preprocess script file '{0}' of a signed import profile is not pinned by its digest")]
+    UnpinnedPreprocessScript(PathBuf),
 }
 
 impl ImportProfile {
@@ -166,6 +554,11 @@ impl ImportProfile {
         &self.name
     }
 
+    /// SHA-256 digest of the import profile as read, hex encoded.
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
     pub fn description(&self) -> Option<&str> {
         self.description.as_deref()
     }
@@ -179,7 +572,27 @@ impl ImportProfile {
     }
 
     pub async fn new<R: Read>(reader: R) -> Result<Self, CreateImportProfileError> {
-        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        Self::create(reader, false).await
+    }
+
+    /// Creates the import profile from signed content, refusing a preprocess script file not
+    /// pinned by its digest, which the signature would not cover.
+    pub async fn signed<R: Read>(reader: R) -> Result<Self, CreateImportProfileError> {
+        Self::create(reader, true).await
+    }
+
+    async fn create<R: Read>(
+        mut reader: R,
+        signed: bool,
+    ) -> Result<Self, CreateImportProfileError> {
+        let mut content = Vec::new();
+
+        reader
+            .read_to_end(&mut content)
+            .map_err(CreateImportProfileError::ReadError)?;
+
+        let sha256 = format!("{:x}", Sha256::digest(&content));
+        let mut deserializer = serde_json::Deserializer::from_slice(&content);
         let raw = ImportProfileRaw::deserialize(&mut deserializer)
             .map_err(CreateImportProfileError::DeserializationError)?;
 
@@ -202,36 +615,193 @@ impl ImportProfile {
             warn!("Preprocess script '{}' is unused", preprocess_script);
         }
 
+        if signed
+            && let Some(PreprocessScript::File {
+                path, sha256: None, ..
+            }) = &raw.preprocess_script
+        {
+            return Err(CreateImportProfileError::UnpinnedPreprocessScript(
+                path.clone(),
+            ));
+        }
+
         let preprocess_runtime = raw
             .preprocess_script
             .map(preprocess::load_preprocess_runtime)
             .transpose()?;
 
+        // routed table mappers are expanded into a table mapper for each target table, which the
+        // dependencies on the routed table mapper then depend on
+        let mut routes = HashMap::<String, Vec<String>>::default();
+        let mut table_mappers_raw = Vec::with_capacity(raw.table_mappers.len());
+
+        for table_mapper in raw.table_mappers {
+            if table_mapper.routing.is_some() {
+                let name = table_mapper.name.clone();
+                let routed = table_mapper.expand_routing();
+
+                if routed.is_empty() {
+                    return Err(CreateImportProfileError::RoutingTargetsEmpty(name));
+                }
+
+                routes.insert(
+                    name,
+                    routed.iter().map(|routed| routed.name.clone()).collect(),
+                );
+                table_mappers_raw.extend(routed);
+            } else {
+                table_mappers_raw.push(table_mapper);
+            }
+        }
+
+        for table_mapper in &mut table_mappers_raw {
+            table_mapper.depends_on = std::mem::take(&mut table_mapper.depends_on)
+                .into_iter()
+                .flat_map(|dependency| match routes.get(&dependency) {
+                    Some(routed) => routed.clone(),
+                    None => vec![dependency],
+                })
+                .collect();
+        }
+
+        let table_mappers: Vec<TableMapper> = table_mappers_raw
+            .into_iter()
+            .map(|table_mapper| TableMapper::new(table_mapper, preprocess_runtime.as_deref()))
+            .collect::<Result<_, _>>()?;
+
+        // each batch of a stream is merged on its own, so would delete the rows of other batches
+        if let ImportProfileDataSourceConfig::StreamDataSourceConfig { .. } = raw.data_source_config
+            && let Some(table_mapper) = table_mappers
+                .iter()
+                .find(|table_mapper| table_mapper.delete_mode() == DeleteMode::Full)
+        {
+            return Err(CreateImportProfileError::StreamFullDelete(
+                table_mapper.name().to_owned(),
+            ));
+        }
+
+        // the temporary table of a table mapper deferring lookup resolution is retained until its
+        // lookup tables are loaded, so is not created again by another table mapper meanwhile
+        if let Some(table_mapper) = table_mappers.iter().find(|table_mapper| {
+            table_mapper.deferred_lookup_resolution()
+                && table_mappers.iter().any(|other| {
+                    other.name() != table_mapper.name()
+                        && other.identifier() == table_mapper.identifier()
+                })
+        }) {
+            return Err(CreateImportProfileError::DeferredSharedTarget(
+                table_mapper.name().to_owned(),
+            ));
+        }
+
+        let mut data_source_config = raw.data_source_config;
+
+        for field in data_source_config
+            .field_groups_mut()
+            .values_mut()
+            .flat_map(FieldGroup::fields_mut)
+        {
+            field.trim.get_or_insert(raw.trim_fields);
+        }
+
         Ok(ImportProfile {
             name: raw.name,
+            sha256,
             description: raw.description,
-            data_source_config: raw.data_source_config,
-            table_mappers: raw
-                .table_mappers
-                .into_iter()
-                .map(|table_mapper| TableMapper::new(table_mapper, preprocess_runtime.as_deref()))
-                .collect::<Result<_, _>>()?,
+            data_source_config,
+            table_mappers: Self::order_table_mappers(table_mappers)?,
         })
     }
+
+    /// Orders table mappers such that each table mapper is executed after the table mappers it
+    /// depends on.
+    fn order_table_mappers(
+        table_mappers: Vec<TableMapper>,
+    ) -> Result<Vec<TableMapper>, CreateImportProfileError> {
+        let order = {
+            let mut indices = HashMap::<&str, usize>::default();
+
+            for (index, table_mapper) in table_mappers.iter().enumerate() {
+                if indices.insert(table_mapper.name(), index).is_some() {
+                    return Err(CreateImportProfileError::DuplicateTableMapperName(
+                        table_mapper.name().to_owned(),
+                    ));
+                }
+            }
+
+            let dependencies = table_mappers
+                .iter()
+                .map(|table_mapper| {
+                    table_mapper
+                        .depends_on()
+                        .map(|dependency| {
+                            indices.get(dependency).copied().ok_or_else(|| {
+                                CreateImportProfileError::UnknownTableMapperDependency {
+                                    table_mapper: table_mapper.name().to_owned(),
+                                    dependency: dependency.to_owned(),
+                                }
+                            })
+                        })
+                        .collect::<Result<Vec<usize>, _>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // each table mapper is a node, with an edge from each of its dependencies
+            let mut graph = DiGraph::<(), ()>::with_capacity(table_mappers.len(), 0);
+
+            for _ in &table_mappers {
+                graph.add_node(());
+            }
+
+            for (index, dependencies) in dependencies.into_iter().enumerate() {
+                for dependency in dependencies {
+                    graph.add_edge(NodeIndex::new(dependency), NodeIndex::new(index), ());
+                }
+            }
+
+            petgraph::algo::toposort(&graph, None)
+                .map_err(|cycle| {
+                    CreateImportProfileError::TableMapperDependencyCycle(
+                        table_mappers[cycle.node_id().index()].name().to_owned(),
+                    )
+                })?
+                .into_iter()
+                .map(NodeIndex::index)
+                .collect::<Vec<_>>()
+        };
+
+        let mut table_mappers = table_mappers.into_iter().map(Some).collect::<Vec<_>>();
+
+        Ok(order
+            .into_iter()
+            .map(|index| {
+                table_mappers[index]
+                    .take()
+                    .expect("Table mapper should only be ordered once")
+            })
+            .collect())
+    }
 }
 
 #[derive(Debug, Error)]
-#[error("error creating data source for file: {data_source_path}")]
+#[error("error creating data source: {data_source}")]
 pub struct CreateDataSourceError {
-    data_source_path: PathBuf,
+    data_source: String,
     #[source]
     source: CreateDataSourceErrorKind,
 }
 
 impl CreateDataSourceError {
     fn new(data_source_path: &Path, source: impl Into<CreateDataSourceErrorKind>) -> Self {
+        Self::with_description(data_source_path.display(), source)
+    }
+
+    fn with_description(
+        data_source: impl Display,
+        source: impl Into<CreateDataSourceErrorKind>,
+    ) -> Self {
         CreateDataSourceError {
-            data_source_path: data_source_path.to_owned(),
+            data_source: data_source.to_string(),
             source: source.into(),
         }
     }
@@ -245,6 +815,36 @@ pub enum CreateDataSourceErrorKind {
     Xml(#[from] CreateXmlDataSourceError),
     #[error(transparent)]
     Delimited(#[from] CreateDelimitedDataSourceError),
+    #[error(transparent)]
+    Json(#[from] CreateJsonDataSourceError),
+    #[error(transparent)]
+    Avro(#[from] CreateAvroDataSourceError),
+    #[error(transparent)]
+    Sql(#[from] CreateSqlDataSourceError),
+    #[error("records of a streaming data source cannot be counted")]
+    CountStreamRecords,
+    #[error("no data source registered as kind: {0}")]
+    UnknownDataSourceKind(String),
+    #[error("could not read custom data source")]
+    Custom(#[source] CustomDataSourceError),
+}
+
+/// Stages applied by [`ImportProfileDataSourceConfig::prepare_stream`] to the records of a table
+/// mapper read from its data source.
+struct RecordPipeline<'profile, 'stream> {
+    group: &'profile FieldGroup,
+    preprocess_transform: Option<&'profile dyn PreprocessTransform>,
+    computed_fields: &'profile [ComputedField],
+    deduplication: Option<&'profile Deduplication>,
+    deduplication_history: Option<&'stream DeduplicationHistory>,
+    sequencer: Option<Sequencer<'profile>>,
+    templater: Option<Templater<'profile>>,
+    route: Option<&'profile Route>,
+    operation: Option<&'profile Operation>,
+    record_accounting: Rc<RecordAccounting>,
+    empty_records: EmptyRecordAction,
+    /// Shared scan recording the records read, if any
+    scan: Option<Scan<'stream>>,
 }
 
 impl ImportProfileDataSourceConfig {
@@ -252,20 +852,69 @@ impl ImportProfileDataSourceConfig {
         &'profile self,
         table_mapper: &'profile TableMapper,
         import_options: &ImportOptions,
+        deduplication_history: Option<&'stream DeduplicationHistory>,
+        stream_batch: Option<&'stream StreamBatch>,
+        shared_scan: Option<&'stream SharedScan>,
+        record_accounting: &Rc<RecordAccounting>,
+        resume: Option<&DelimitedCheckpoint>,
     ) -> Result<Box<dyn Stream<Item = DataSourceStreamItem> + 'stream>, CreateDataSourceError>
     where
         'profile: 'stream,
     {
         let field_group = table_mapper.field_group();
-        let preprocess_transform = table_mapper.preprocess_transform();
+
+        let record_pipeline = |group, scan| RecordPipeline {
+            group,
+            preprocess_transform: table_mapper.preprocess_transform(),
+            computed_fields: table_mapper.computed_fields(),
+            deduplication: table_mapper.deduplication(),
+            deduplication_history,
+            sequencer: Sequencer::new(table_mapper),
+            templater: Templater::new(table_mapper, &import_options.run_id),
+            route: table_mapper.route(),
+            operation: table_mapper.operation(),
+            record_accounting: record_accounting.clone(),
+            empty_records: import_options.empty_records,
+            scan,
+        };
+
+        let scan = match shared_scan.and_then(|shared_scan| shared_scan.scan(field_group)) {
+            Some(Scan::Replay(scanned)) => {
+                let group = self.field_groups().get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::with_description(
+                        "shared scan",
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                return Self::prepare_stream(
+                    Ok::<_, CreateDataSourceErrorKind>(scanned.replay(record_accounting)),
+                    "shared scan",
+                    record_pipeline(group, None),
+                );
+            }
+            scan => scan,
+        };
 
         match self {
             ImportProfileDataSourceConfig::XmlDataSourceConfig {
                 path,
                 field_groups,
                 selector,
+                xsd,
+                xsd_validation,
+                recover,
+                raw_payload_field,
+                unknown_fields,
             } => {
-                let fields = field_groups.get(field_group).ok_or_else(|| {
+                let group = field_groups.get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::new(
+                        path,
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                let selectors = selector.selectors(field_group).ok_or_else(|| {
                     CreateDataSourceError::new(
                         path,
                         CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
@@ -275,21 +924,115 @@ impl ImportProfileDataSourceConfig {
                 Self::prepare_stream(
                     XmlDataSource::new(
                         import_options.path_override.as_ref().unwrap_or(path),
-                        fields,
-                        selector,
+                        group.fields(),
+                        &selectors,
+                        xsd.as_deref(),
+                        *xsd_validation,
+                        *recover,
+                        raw_payload_field.as_deref(),
+                        unknown_fields,
                     )
-                    .await,
-                    path,
-                    fields,
-                    preprocess_transform,
+                    .await
+                    .map(|data_source| {
+                        data_source.with_record_accounting(record_accounting.clone())
+                    }),
+                    path.display(),
+                    record_pipeline(group, scan),
                 )
             }
             ImportProfileDataSourceConfig::DelimitedDataSourceConfig {
                 path,
                 field_groups,
                 reader_config,
+                raw_payload_field,
+            } => {
+                let group = field_groups.get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::new(
+                        path,
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                let data_source_path = import_options.path_override.as_ref().unwrap_or(path);
+
+                // records are only read in parallel from the start of the data source
+                if import_options.parse_parallelism > 1 && resume.is_none() {
+                    Self::prepare_stream(
+                        DelimitedDataSource::parallel(
+                            data_source_path,
+                            group.fields(),
+                            *reader_config,
+                            raw_payload_field.as_deref(),
+                            import_options.parse_parallelism,
+                        )
+                        .await
+                        .map(|data_source| {
+                            SkipFooter::new(data_source, reader_config.skip_footer_rows())
+                        }),
+                        path.display(),
+                        record_pipeline(group, scan),
+                    )
+                } else {
+                    let data_source = match resume {
+                        Some(checkpoint) => {
+                            DelimitedDataSource::resume(
+                                data_source_path,
+                                group.fields(),
+                                *reader_config,
+                                raw_payload_field.as_deref(),
+                                checkpoint,
+                            )
+                            .await
+                        }
+                        None => {
+                            DelimitedDataSource::new(
+                                data_source_path,
+                                group.fields(),
+                                *reader_config,
+                                raw_payload_field.as_deref(),
+                            )
+                            .await
+                        }
+                    };
+
+                    Self::prepare_stream(
+                        data_source.map(|data_source| {
+                            SkipFooter::new(data_source, reader_config.skip_footer_rows())
+                        }),
+                        path.display(),
+                        record_pipeline(group, scan),
+                    )
+                }
+            }
+            ImportProfileDataSourceConfig::JsonDataSourceConfig {
+                path,
+                field_groups,
+                format,
+                selector,
+                raw_payload_field,
             } => {
-                let fields = field_groups.get(field_group).ok_or_else(|| {
+                let group = field_groups.get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::new(
+                        path,
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                Self::prepare_stream(
+                    JsonDataSource::new(
+                        import_options.path_override.as_ref().unwrap_or(path),
+                        group.fields(),
+                        *format,
+                        selector.as_deref(),
+                        raw_payload_field.as_deref(),
+                    )
+                    .await,
+                    path.display(),
+                    record_pipeline(group, scan),
+                )
+            }
+            ImportProfileDataSourceConfig::AvroDataSourceConfig { path, field_groups } => {
+                let group = field_groups.get(field_group).ok_or_else(|| {
                     CreateDataSourceError::new(
                         path,
                         CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
@@ -297,81 +1040,470 @@ impl ImportProfileDataSourceConfig {
                 })?;
 
                 Self::prepare_stream(
-                    DelimitedDataSource::new(
+                    AvroDataSource::new(
                         import_options.path_override.as_ref().unwrap_or(path),
-                        fields,
-                        *reader_config,
+                        group.fields(),
                     )
                     .await,
-                    path,
-                    fields,
-                    preprocess_transform,
+                    path.display(),
+                    record_pipeline(group, scan),
+                )
+            }
+            ImportProfileDataSourceConfig::SqlDataSourceConfig {
+                connection_string,
+                query,
+                field_groups,
+            } => {
+                let group = field_groups.get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::with_description(
+                        "SQL query",
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                Self::prepare_stream(
+                    SqlDataSource::new(connection_string, query, group.fields()).await,
+                    "SQL query",
+                    record_pipeline(group, scan),
+                )
+            }
+            ImportProfileDataSourceConfig::StreamDataSourceConfig {
+                broker,
+                field_groups,
+                payload,
+                raw_payload_field,
+                ..
+            } => {
+                let group = field_groups.get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::with_description(
+                        broker,
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                let stream_batch =
+                    stream_batch.expect("Streaming data sources should be read a batch at a time");
+
+                Self::prepare_stream(
+                    Ok::<_, CreateDataSourceErrorKind>(stream_data_source::read_batch(
+                        stream_batch,
+                        group.fields(),
+                        *payload,
+                        raw_payload_field.as_deref(),
+                    )),
+                    broker,
+                    record_pipeline(group, scan),
+                )
+            }
+            ImportProfileDataSourceConfig::CustomDataSourceConfig {
+                kind,
+                options,
+                field_groups,
+            } => {
+                let group = field_groups.get(field_group).ok_or_else(|| {
+                    CreateDataSourceError::with_description(
+                        kind,
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                let data_source = custom_data_source(kind).ok_or_else(|| {
+                    CreateDataSourceError::with_description(
+                        kind,
+                        CreateDataSourceErrorKind::UnknownDataSourceKind(kind.clone()),
+                    )
+                })?;
+
+                Self::prepare_stream(
+                    data_source
+                        .records(options, group.fields())
+                        .await
+                        .map_err(CreateDataSourceErrorKind::Custom),
+                    kind,
+                    record_pipeline(group, scan),
+                )
+            }
+        }
+    }
+
+    fn field_groups(&self) -> &HashMap<String, FieldGroup> {
+        match self {
+            ImportProfileDataSourceConfig::XmlDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::DelimitedDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::JsonDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::AvroDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::SqlDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::StreamDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::CustomDataSourceConfig { field_groups, .. } => {
+                field_groups
+            }
+        }
+    }
+
+    fn field_groups_mut(&mut self) -> &mut HashMap<String, FieldGroup> {
+        match self {
+            ImportProfileDataSourceConfig::XmlDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::DelimitedDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::JsonDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::AvroDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::SqlDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::StreamDataSourceConfig { field_groups, .. }
+            | ImportProfileDataSourceConfig::CustomDataSourceConfig { field_groups, .. } => {
+                field_groups
+            }
+        }
+    }
+
+    /// Path of the data source file, or `None` where the data source is not read from a file.
+    pub fn path<'a>(&'a self, import_options: &'a ImportOptions) -> Option<&'a Path> {
+        match self {
+            ImportProfileDataSourceConfig::XmlDataSourceConfig { path, .. }
+            | ImportProfileDataSourceConfig::DelimitedDataSourceConfig { path, .. }
+            | ImportProfileDataSourceConfig::JsonDataSourceConfig { path, .. }
+            | ImportProfileDataSourceConfig::AvroDataSourceConfig { path, .. } => Some(
+                import_options
+                    .path_override
+                    .as_deref()
+                    .unwrap_or(path.as_path()),
+            ),
+            ImportProfileDataSourceConfig::SqlDataSourceConfig { .. }
+            | ImportProfileDataSourceConfig::StreamDataSourceConfig { .. }
+            | ImportProfileDataSourceConfig::CustomDataSourceConfig { .. } => None,
+        }
+    }
+
+    /// Counts the records of the field group of the data source, reading the data source file an
+    /// extra time.
+    pub async fn count_records(
+        &self,
+        field_group: &str,
+        import_options: &ImportOptions,
+    ) -> Result<u64, CreateDataSourceError> {
+        match self {
+            ImportProfileDataSourceConfig::XmlDataSourceConfig { path, selector, .. } => {
+                let selectors = selector.selectors(field_group).ok_or_else(|| {
+                    CreateDataSourceError::new(
+                        path,
+                        CreateDataSourceErrorKind::UnknownFieldGroup(field_group.to_owned()),
+                    )
+                })?;
+
+                XmlDataSource::count_records(
+                    import_options.path_override.as_ref().unwrap_or(path),
+                    &selectors,
                 )
+                .await
+                .map_err(|err| CreateDataSourceError::new(path, err))
+            }
+            ImportProfileDataSourceConfig::DelimitedDataSourceConfig {
+                path,
+                reader_config,
+                ..
+            } => DelimitedDataSource::count_records(
+                import_options.path_override.as_ref().unwrap_or(path),
+                *reader_config,
+            )
+            .await
+            .map_err(|err| CreateDataSourceError::new(path, err)),
+            ImportProfileDataSourceConfig::JsonDataSourceConfig {
+                path,
+                format,
+                selector,
+                ..
+            } => JsonDataSource::count_records(
+                import_options.path_override.as_ref().unwrap_or(path),
+                *format,
+                selector.as_deref(),
+            )
+            .await
+            .map_err(|err| CreateDataSourceError::new(path, err)),
+            ImportProfileDataSourceConfig::AvroDataSourceConfig { path, .. } => {
+                AvroDataSource::count_records(import_options.path_override.as_ref().unwrap_or(path))
+                    .await
+                    .map_err(|err| CreateDataSourceError::new(path, err))
+            }
+            ImportProfileDataSourceConfig::SqlDataSourceConfig {
+                connection_string,
+                query,
+                ..
+            } => SqlDataSource::count_records(connection_string, query)
+                .await
+                .map_err(|err| CreateDataSourceError::with_description("SQL query", err)),
+            ImportProfileDataSourceConfig::StreamDataSourceConfig { broker, .. } => {
+                Err(CreateDataSourceError::with_description(
+                    broker,
+                    CreateDataSourceErrorKind::CountStreamRecords,
+                ))
+            }
+            ImportProfileDataSourceConfig::CustomDataSourceConfig { kind, options, .. } => {
+                let data_source = custom_data_source(kind).ok_or_else(|| {
+                    CreateDataSourceError::with_description(
+                        kind,
+                        CreateDataSourceErrorKind::UnknownDataSourceKind(kind.clone()),
+                    )
+                })?;
+
+                data_source.count_records(options).await.map_err(|err| {
+                    CreateDataSourceError::with_description(
+                        kind,
+                        CreateDataSourceErrorKind::Custom(err),
+                    )
+                })
             }
         }
     }
 
     fn prepare_stream<'profile, 'stream, S, E>(
         result: Result<S, impl Into<CreateDataSourceErrorKind>>,
-        path: &Path,
-        fields: &'profile [Field],
-        preprocess_transform: Option<&'profile dyn PreprocessTransform>,
+        data_source: impl Display,
+        record_pipeline: RecordPipeline<'profile, 'stream>,
     ) -> Result<Box<dyn Stream<Item = DataSourceStreamItem> + 'stream>, CreateDataSourceError>
     where
         S: Stream<Item = Result<DataSourceRecord, E>> + 'stream,
         E: ReadRecordError,
         'profile: 'stream,
     {
+        let RecordPipeline {
+            group,
+            preprocess_transform,
+            computed_fields,
+            deduplication,
+            deduplication_history,
+            sequencer,
+            templater,
+            route,
+            operation,
+            record_accounting,
+            empty_records,
+            scan,
+        } = record_pipeline;
+
+        let records = result
+            .map_err(|err| CreateDataSourceError::with_description(data_source, err.into()))?
+            .map_err(|err| -> Box<dyn ReadRecordError> { Box::new(err) });
+
+        // records read from the data source are kept for the later table mappers of the field group
+        let records = match scan {
+            Some(Scan::Record(shared_scan, field_group)) => {
+                Either::Left(shared_scan.record(field_group, records, record_accounting.clone()))
+            }
+            _ => Either::Right(records),
+        };
+
         Ok(Box::new(
-            result
-                .map_err(|err| CreateDataSourceError::new(path, err.into()))?
-                .map_err(|err| -> Box<dyn ReadRecordError> { Box::new(err) })
-                // WIP: field formatters / required
-                // .try_filter_map(move |record| async move {
-                //     let index = record.index();
-                //
-                //     let blah = fields.iter().map(|field| {
-                //         match record.field(field.name()) {
-                //             Some(record_field) => {
-                //                 let mut record_field: Cow<str> = record_field.into();
-                //
-                //                 for formatter in &field.formatters {
-                //                     record_field = formatter.apply(&*record_field);
-                //                 }
-                //
-                //                 Ok(Some(record_field))
-                //             }
-                //             None => match field.required {
-                //                 Some(Required::Drop) => Ok(None),
-                //                 Some(Required::Error) => Err(()),
-                //                 None => Ok(None),
-                //             }
-                //         }
-                //     }).collect::<Result<Vec<_>, _>>();
-                //
-                //     // TODO: use Cow to only clone when needed,
-                //
-                //     //let record2 = DataSourceRecord::from_iter(blah, index);
-                //
-                //     Ok(Some(record))
-                // })
-                .try_filter_map(move |record| async move {
-                    if let Some(function) = preprocess_transform {
-                        let index = record.index();
-
-                        function
-                            .transform(record)
-                            .map_err(|err| -> Box<dyn ReadRecordError> {
-                                Box::new(PreprocessReadRecordError::new(
-                                    DataSourceErrorIndex {
-                                        record_number: Some(index.record_number),
-                                        line_number: index.line_start,
+            records
+                .inspect_ok({
+                    let record_accounting = record_accounting.clone();
+
+                    move |_| record_accounting.read()
+                })
+                .map_ok({
+                    let constants = group
+                        .constants()
+                        .map(|(name, value)| (Arc::<str>::from(name), value))
+                        .collect::<Vec<_>>();
+
+                    move |mut record| {
+                        for field in group.fields().iter().filter(|field| field.trim()) {
+                            record.fields_mut().trim(field.name());
+                        }
+
+                        for (name, value) in &constants {
+                            record.fields_mut().insert(name.clone(), value);
+                        }
+
+                        record
+                    }
+                })
+                .try_filter({
+                    let record_accounting = record_accounting.clone();
+
+                    move |record| {
+                        let routed = route.is_none_or(|route| route.matches(record));
+
+                        if !routed {
+                            record_accounting.filtered();
+                        }
+
+                        ready(routed)
+                    }
+                })
+                .try_filter_map({
+                    let record_accounting = record_accounting.clone();
+
+                    move |record| {
+                        let empty = !group.fields().is_empty()
+                            && group
+                                .fields()
+                                .iter()
+                                .all(|field| record.get(field.name()).is_none_or(str::is_empty));
+
+                        ready(match empty_records {
+                            _ if !empty => Ok(Some(record)),
+                            EmptyRecordAction::Skip => {
+                                record_accounting.dropped();
+
+                                Ok(None)
+                            }
+                            EmptyRecordAction::Warn => {
+                                warn!("Skipped empty record ({})", record.index());
+                                record_accounting.dropped();
+
+                                Ok(None)
+                            }
+                            EmptyRecordAction::Error => {
+                                Err(Box::new(EmptyReadRecordError::new(record.index()))
+                                    as Box<dyn ReadRecordError>)
+                            }
+                        })
+                    }
+                })
+                .map_ok(move |mut record| {
+                    for field in group.fields() {
+                        let Some(value) = record.get(field.name()) else {
+                            continue;
+                        };
+
+                        let formatted = field
+                            .formatters()
+                            .iter()
+                            .fold(Cow::Borrowed(value), |value, formatter| {
+                                formatter.apply(value)
+                            });
+
+                        if *formatted != *value {
+                            let formatted = formatted.into_owned();
+
+                            record.fields_mut().replace(field.name(), &formatted);
+                        }
+                    }
+
+                    record
+                })
+                .try_filter_map({
+                    let record_accounting = record_accounting.clone();
+
+                    move |record| {
+                        let missing = group.fields().iter().find_map(|field| {
+                            field
+                                .required()
+                                .filter(|_| record.get(field.name()).is_none_or(str::is_empty))
+                                .map(|required| (field, required))
+                        });
+
+                        ready(match missing {
+                            None => Ok(Some(record)),
+                            Some((_, Required::Drop)) => {
+                                record_accounting.dropped();
+
+                                Ok(None)
+                            }
+                            Some((field, Required::Error)) => Err(Box::new(
+                                RequiredReadRecordError::new(record.index(), field),
+                            )
+                                as Box<dyn ReadRecordError>),
+                        })
+                    }
+                })
+                .try_filter_map({
+                    let record_accounting = record_accounting.clone();
+
+                    move |record| {
+                        let record_accounting = record_accounting.clone();
+
+                        async move {
+                            if let Some(function) = preprocess_transform {
+                                let index = record.index();
+
+                                let record = crate::log::with_preprocess_record(index, || {
+                                    function.transform(record)
+                                })
+                                .map_err(
+                                    |err| -> Box<dyn ReadRecordError> {
+                                        Box::new(PreprocessReadRecordError::new(
+                                            DataSourceErrorIndex {
+                                                record_number: Some(index.record_number),
+                                                line_number: index.line_start,
+                                            },
+                                            err,
+                                        ))
                                     },
-                                    err,
-                                ))
-                            })
-                    } else {
-                        Ok(Some(record))
+                                )?;
+
+                                if record.is_none() {
+                                    record_accounting.filtered();
+                                }
+
+                                Ok(record)
+                            } else {
+                                Ok(Some(record))
+                            }
+                        }
+                    }
+                })
+                .and_then(move |mut record| {
+                    let computed = computed_fields.iter().try_for_each(|computed_field| {
+                        computed_field.compute(&mut record).map_err(|err| {
+                            let index = record.index();
+
+                            Box::new(ComputedFieldReadRecordError {
+                                index: DataSourceErrorIndex {
+                                    record_number: Some(index.record_number),
+                                    line_number: index.line_start,
+                                },
+                                field: computed_field.name().to_owned(),
+                                source: err,
+                            }) as Box<dyn ReadRecordError>
+                        })
+                    });
+
+                    ready(computed.map(|()| record))
+                })
+                .and_then(move |record| {
+                    let known = operation.is_none_or(|operation| {
+                        operation.is_operation(record.get(operation.field()).unwrap_or_default())
+                    });
+
+                    ready(match operation {
+                        Some(operation) if !known => {
+                            Err(Box::new(OperationReadRecordError::new(&record, operation))
+                                as Box<dyn ReadRecordError>)
+                        }
+                        _ => Ok(record),
+                    })
+                })
+                .try_filter_map({
+                    let mut deduplicator = deduplication.map(|deduplication| {
+                        Deduplicator::new(deduplication, deduplication_history)
+                    });
+
+                    move |record| {
+                        ready(match &mut deduplicator {
+                            Some(deduplicator) => deduplicator
+                                .filter(record)
+                                .map_err(|err| -> Box<dyn ReadRecordError> { Box::new(err) })
+                                .inspect(|record| {
+                                    if record.is_none() {
+                                        record_accounting.dropped();
+                                    }
+                                }),
+                            None => Ok(Some(record)),
+                        })
+                    }
+                })
+                .map_ok({
+                    let mut sequencer = sequencer;
+
+                    move |mut record| {
+                        if let Some(sequencer) = &mut sequencer {
+                            sequencer.assign(&mut record);
+                        }
+
+                        if let Some(templater) = &templater {
+                            templater.render(&mut record);
+                        }
+
+                        record
                     }
                 }),
         ))
@@ -400,3 +1532,649 @@ impl ReadRecordError for PreprocessReadRecordError {
         self.index
     }
 }
+
+#[derive(Debug, Error)]
+#[error("could not compute field '{field}' ({index})")]
+pub struct ComputedFieldReadRecordError {
+    index: DataSourceErrorIndex,
+    field: String,
+    #[source]
+    source: EvaluateError,
+}
+
+impl ReadRecordError for ComputedFieldReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown operation '{value}' of field '{field}' ({index})")]
+pub struct OperationReadRecordError {
+    index: DataSourceErrorIndex,
+    field: String,
+    value: String,
+}
+
+impl OperationReadRecordError {
+    fn new(record: &DataSourceRecord, operation: &Operation) -> Self {
+        let index = record.index();
+
+        Self {
+            index: DataSourceErrorIndex {
+                record_number: Some(index.record_number),
+                line_number: index.line_start,
+            },
+            field: operation.field().to_owned(),
+            value: record.get(operation.field()).unwrap_or_default().to_owned(),
+        }
+    }
+}
+
+impl ReadRecordError for OperationReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("required field '{field}' is empty ({index})")]
+pub struct RequiredReadRecordError {
+    index: DataSourceErrorIndex,
+    field: String,
+}
+
+impl RequiredReadRecordError {
+    fn new(index: DataSourceRecordIndex, field: &Field) -> Self {
+        Self {
+            index: DataSourceErrorIndex {
+                record_number: Some(index.record_number),
+                line_number: index.line_start,
+            },
+            field: field.name().to_owned(),
+        }
+    }
+}
+
+impl ReadRecordError for RequiredReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("empty record ({index})")]
+pub struct EmptyReadRecordError {
+    index: DataSourceErrorIndex,
+}
+
+impl EmptyReadRecordError {
+    fn new(index: DataSourceRecordIndex) -> Self {
+        Self {
+            index: DataSourceErrorIndex {
+                record_number: Some(index.record_number),
+                line_number: index.line_start,
+            },
+        }
+    }
+}
+
+impl ReadRecordError for EmptyReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn import_profile_json(table_mappers: &[(&str, &[&str])]) -> String {
+        let table_mappers = table_mappers
+            .iter()
+            .map(|(name, depends_on)| {
+                serde_json::json!({
+                    "name": name,
+                    "depends_on": depends_on,
+                    "field_group": "Group",
+                    "table_identifier": format!("[dbo].[{name}]"),
+                    "delete_mode": "Partial",
+                    "delete_action": "None",
+                    "duplicate_action": "Dump",
+                    "columns": [
+                        { "Parser": { "column_identifier": "[Code]", "map_column": true, "field_name": "Code" } }
+                    ],
+                    "key_columns": ["Code"]
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "name": "Test",
+            "data_source_config": {
+                "DelimitedDataSourceConfig": {
+                    "path": "test.csv",
+                    "reader_config": "Csv",
+                    "field_groups": { "Group": [{ "name": "Code" }] }
+                }
+            },
+            "table_mappers": table_mappers
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn order_table_mappers_by_dependency() -> Result<(), CreateImportProfileError> {
+        let json = import_profile_json(&[
+            ("Border", &["Country"]),
+            ("Continent", &[]),
+            ("Country", &["Continent"]),
+            ("Mountain", &["Country"]),
+        ]);
+
+        let import_profile = ImportProfile::new(Cursor::new(json)).await?;
+
+        itertools::assert_equal(
+            import_profile.table_mappers().map(TableMapper::name),
+            ["Continent", "Country", "Border", "Mountain"],
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_deferred_lookup_resolution_of_shared_target_table() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&import_profile_json(&[("A", &[]), ("B", &["A"])]))
+                .expect("Import profile JSON should be valid");
+
+        json["table_mappers"][0]["deferred_lookup_resolution"] = true.into();
+
+        assert!(
+            ImportProfile::new(Cursor::new(json.to_string()))
+                .await
+                .is_ok()
+        );
+
+        json["table_mappers"][1]["table_identifier"] = "[dbo].[A]".into();
+
+        assert!(matches!(
+            ImportProfile::new(Cursor::new(json.to_string())).await,
+            Err(CreateImportProfileError::DeferredSharedTarget(name)) if name == "A"
+        ));
+    }
+
+    #[tokio::test]
+    async fn trim_fields_by_default() -> Result<(), CreateImportProfileError> {
+        let mut json: serde_json::Value = serde_json::from_str(&import_profile_json(&[("A", &[])]))
+            .expect("Import profile JSON should be valid");
+
+        json["trim_fields"] = true.into();
+        json["data_source_config"]["DelimitedDataSourceConfig"]["field_groups"]["Group"] = serde_json::json!([
+            { "name": "Code" },
+            { "name": "Name", "trim": false }
+        ]);
+
+        let import_profile = ImportProfile::new(Cursor::new(json.to_string())).await?;
+
+        let ImportProfileDataSourceConfig::DelimitedDataSourceConfig { field_groups, .. } =
+            import_profile.data_source_config()
+        else {
+            unreachable!()
+        };
+
+        itertools::assert_equal(
+            field_groups["Group"].fields().iter().map(Field::trim),
+            [true, false],
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lua")]
+    #[tokio::test]
+    async fn verify_pinned_preprocess_script() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_pinned_{}.lua",
+            std::process::id()
+        ));
+        let script = "local pinned = true";
+
+        std::fs::write(&path, script)?;
+
+        let mut json: serde_json::Value =
+            serde_json::from_str(&import_profile_json(&[("A", &[])]))?;
+
+        json["preprocess_script"] = serde_json::json!({ "File": { "path": path } });
+
+        assert!(
+            ImportProfile::new(Cursor::new(json.to_string()))
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            ImportProfile::signed(Cursor::new(json.to_string())).await,
+            Err(CreateImportProfileError::UnpinnedPreprocessScript(_))
+        ));
+
+        json["preprocess_script"]["File"]["sha256"] =
+            format!("{:X}", Sha256::digest(script)).into();
+
+        assert!(
+            ImportProfile::signed(Cursor::new(json.to_string()))
+                .await
+                .is_ok()
+        );
+
+        json["preprocess_script"]["File"]["sha256"] = format!("{:x}", Sha256::digest("")).into();
+
+        assert!(matches!(
+            ImportProfile::new(Cursor::new(json.to_string())).await,
+            Err(CreateImportProfileError::LoadPreprocessScriptError(
+                LoadPreprocessRuntimeError::DigestMismatch(_)
+            ))
+        ));
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_formatters_in_order() {
+        let formatters: Vec<Formatter> = serde_json::from_value(serde_json::json!([
+            "Trim",
+            { "Regex": { "pattern": "^(\\d{2})/(\\d{2})$", "replacement": "$2-$1" } },
+            { "Map": { "default": "unknown", "mappings": [["01-12", "December"], ["02-01", "January"]] } },
+            "Uppercase"
+        ]))
+        .expect("Formatters should deserialize");
+
+        let format = |value| {
+            formatters
+                .iter()
+                .fold(Cow::Borrowed(value), |value, formatter| {
+                    formatter.apply(value)
+                })
+                .into_owned()
+        };
+
+        assert_eq!(format(" 12/01 "), "DECEMBER");
+        assert_eq!(format("01/02"), "JANUARY");
+        assert_eq!(format("2024"), "UNKNOWN");
+
+        assert!(matches!(
+            Formatter::Lowercase.apply(Cow::Borrowed("lower")),
+            Cow::Borrowed("lower")
+        ));
+        assert!(matches!(
+            Formatter::Trim.apply(Cow::Borrowed(" a ")),
+            Cow::Borrowed("a")
+        ));
+        assert!(
+            serde_json::from_value::<Formatter>(serde_json::json!({
+                "Regex": { "pattern": "(", "replacement": "" }
+            }))
+            .is_err()
+        );
+
+        let parse_date: Formatter = serde_json::from_value(serde_json::json!({
+            "ParseDate": { "input_formats": ["DD/MM/YYYY", "YYYYMMDD"], "output": "Date" }
+        }))
+        .expect("Formatter should deserialize");
+
+        assert_eq!(parse_date.apply(Cow::Borrowed("05/11/2024")), "2024-11-05");
+        assert_eq!(parse_date.apply(Cow::Borrowed("20241105")), "2024-11-05");
+        assert!(matches!(
+            parse_date.apply(Cow::Borrowed("2024-11-05")),
+            Cow::Borrowed("2024-11-05")
+        ));
+        assert!(
+            serde_json::from_value::<Formatter>(serde_json::json!({
+                "ParseDate": { "input_formats": ["DD/MM/YY"], "output": "Date" }
+            }))
+            .is_err()
+        );
+
+        let parse_number: Formatter = serde_json::from_value(serde_json::json!({
+            "ParseNumber": { "decimal_separator": "Comma" }
+        }))
+        .expect("Formatter should deserialize");
+
+        assert_eq!(parse_number.apply(Cow::Borrowed("1.234,56")), "1234.56");
+        assert_eq!(parse_number.apply(Cow::Borrowed("(500)")), "-500");
+        assert!(matches!(
+            parse_number.apply(Cow::Borrowed("N/A")),
+            Cow::Borrowed("N/A")
+        ));
+        assert!(matches!(
+            serde_json::from_value::<Formatter>(serde_json::json!({ "ParseNumber": {} })),
+            Ok(Formatter::ParseNumber {
+                decimal_separator: DecimalSeparator::Point
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn field_group_constants() -> Result<(), CreateImportProfileError> {
+        let mut json: serde_json::Value = serde_json::from_str(&import_profile_json(&[("A", &[])]))
+            .expect("Import profile JSON should be valid");
+
+        json["data_source_config"]["DelimitedDataSourceConfig"]["field_groups"]["Group"] = serde_json::json!({
+            "fields": [{ "name": "Code" }],
+            "constants": { "SourceSystem": "ECB" }
+        });
+
+        let import_profile = ImportProfile::new(Cursor::new(json.to_string())).await?;
+
+        let ImportProfileDataSourceConfig::DelimitedDataSourceConfig { field_groups, .. } =
+            import_profile.data_source_config()
+        else {
+            unreachable!()
+        };
+
+        itertools::assert_equal(
+            field_groups["Group"].fields().iter().map(Field::name),
+            ["Code"],
+        );
+        itertools::assert_equal(field_groups["Group"].constants(), [("SourceSystem", "ECB")]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expand_routed_table_mappers() -> Result<(), CreateImportProfileError> {
+        use crate::table_mapper::Table;
+
+        let mut json: serde_json::Value = serde_json::from_str(&import_profile_json(&[
+            ("Orders", &[]),
+            ("Lines", &["Orders"]),
+        ]))
+        .expect("Import profile JSON should be valid");
+
+        json["table_mappers"][0]["routing"] = serde_json::json!({
+            "field": "Month",
+            "targets": {
+                "2024-02": "[dbo].[Orders_202402]",
+                "2024-01": "[dbo].[Orders_202401]"
+            }
+        });
+
+        let import_profile = ImportProfile::new(Cursor::new(json.to_string())).await?;
+
+        itertools::assert_equal(
+            import_profile.table_mappers().map(TableMapper::name),
+            ["Orders[2024-01]", "Orders[2024-02]", "Lines"],
+        );
+
+        let routed = import_profile
+            .table_mappers()
+            .next()
+            .expect("routed table mapper");
+
+        assert_eq!(routed.identifier().to_string(), "[dbo].[Orders_202401]");
+        assert_eq!(routed.route().map(Route::value), Some("2024-01"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delta_file_operation() -> Result<(), CreateImportProfileError> {
+        let mut json: serde_json::Value = serde_json::from_str(&import_profile_json(&[("A", &[])]))
+            .expect("Import profile JSON should be valid");
+
+        json["table_mappers"][0]["operation"] = serde_json::json!({ "field": "Op", "delete": "X" });
+
+        let import_profile = ImportProfile::new(Cursor::new(json.to_string())).await?;
+
+        let operation = import_profile
+            .table_mappers()
+            .next()
+            .and_then(TableMapper::operation)
+            .expect("operation");
+
+        assert_eq!(
+            (operation.insert(), operation.update(), operation.delete()),
+            ("I", "U", "X")
+        );
+        assert!(operation.is_operation("X"));
+        assert!(!operation.is_operation("D"));
+        assert_eq!(operation.identifier().to_string(), "[dbo].[A].[$operation]");
+
+        json["table_mappers"][0]["delete_mode"] = "Full".into();
+
+        assert!(matches!(
+            ImportProfile::new(Cursor::new(json.to_string())).await,
+            Err(CreateImportProfileError::CreateTableMapperError(err))
+                if std::error::Error::source(&err).is_some_and(|source| {
+                    source.to_string() == "operation field cannot be used with the Full delete mode"
+                })
+        ));
+
+        json["table_mappers"][0]["delete_mode"] = "Partial".into();
+        json["table_mappers"][0]["operation"]["update"] = "I".into();
+
+        assert!(
+            ImportProfile::new(Cursor::new(json.to_string()))
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_registered_custom_data_source() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::data_source::{CustomDataSourceError, DataSource, register_data_source};
+        use futures::StreamExt;
+        use futures::future::LocalBoxFuture;
+        use futures::stream::{self, LocalBoxStream};
+        use std::num::NonZero;
+
+        struct Countdown;
+
+        impl DataSource for Countdown {
+            fn records<'a>(
+                self: Arc<Self>,
+                options: &'a serde_json::Value,
+                fields: &'a [Field],
+            ) -> LocalBoxFuture<
+                'a,
+                Result<LocalBoxStream<'a, DataSourceStreamItem>, CustomDataSourceError>,
+            > {
+                Box::pin(async move {
+                    let from = options["from"].as_u64().ok_or("missing option: from")?;
+
+                    Ok(stream::iter((1..=from).map(move |record_number| {
+                        Ok(DataSourceRecord::new(
+                            fields
+                                .iter()
+                                .map(|field| (field.name(), (from + 1 - record_number).to_string()))
+                                .collect(),
+                            DataSourceRecordIndex {
+                                record_number: NonZero::new(record_number).unwrap(),
+                                line_start: record_number,
+                                line_end: record_number,
+                                byte_start: 0,
+                                byte_end: 0,
+                            },
+                        ))
+                    }))
+                    .boxed_local())
+                })
+            }
+        }
+
+        register_data_source("Countdown", Countdown);
+
+        let mut json: serde_json::Value = serde_json::from_str(&import_profile_json(&[("A", &[])]))
+            .expect("Import profile JSON should be valid");
+
+        json["data_source_config"] = serde_json::json!({
+            "CustomDataSourceConfig": {
+                "kind": "Countdown",
+                "options": { "from": 3 },
+                "field_groups": { "Group": [{ "name": "Code" }] }
+            }
+        });
+
+        let import_profile = ImportProfile::new(Cursor::new(json.to_string())).await?;
+        let table_mapper = import_profile.table_mappers().next().expect("table mapper");
+
+        let records = import_profile
+            .data_source_config()
+            .create_data_source(
+                table_mapper,
+                &ImportOptions::default(),
+                None,
+                None,
+                None,
+                &Rc::new(RecordAccounting::default()),
+                None,
+            )
+            .await?;
+
+        let codes = Box::into_pin(records)
+            .map(|record| record.map(|record| record.get("Code").map(str::to_owned)))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            codes,
+            [Some("3".into()), Some("2".into()), Some("1".into())]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn enforce_required_fields() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::data_source::{CustomDataSourceError, DataSource, register_data_source};
+        use futures::StreamExt;
+        use futures::future::LocalBoxFuture;
+        use futures::stream::{self, LocalBoxStream};
+        use std::num::NonZero;
+
+        struct Codes;
+
+        impl DataSource for Codes {
+            fn records<'a>(
+                self: Arc<Self>,
+                options: &'a serde_json::Value,
+                _: &'a [Field],
+            ) -> LocalBoxFuture<
+                'a,
+                Result<LocalBoxStream<'a, DataSourceStreamItem>, CustomDataSourceError>,
+            > {
+                Box::pin(async move {
+                    let codes = options["codes"].as_array().ok_or("missing option: codes")?;
+
+                    Ok(
+                        stream::iter(codes.iter().zip(1..).map(|(code, record_number)| {
+                            // records without a code are not empty, as they still have a name
+                            Ok(DataSourceRecord::new(
+                                code.as_str()
+                                    .map(|code| ("Code", code.to_owned()))
+                                    .into_iter()
+                                    .chain([("Name", record_number.to_string())])
+                                    .collect(),
+                                DataSourceRecordIndex {
+                                    record_number: NonZero::new(record_number).unwrap(),
+                                    line_start: record_number,
+                                    line_end: record_number,
+                                    byte_start: 0,
+                                    byte_end: 0,
+                                },
+                            ))
+                        }))
+                        .boxed_local(),
+                    )
+                })
+            }
+        }
+
+        register_data_source("Codes", Codes);
+
+        let read = async |required: &str| -> Result<_, Box<dyn std::error::Error>> {
+            let mut json: serde_json::Value =
+                serde_json::from_str(&import_profile_json(&[("A", &[])]))
+                    .expect("Import profile JSON should be valid");
+
+            json["data_source_config"] = serde_json::json!({
+                "CustomDataSourceConfig": {
+                    "kind": "Codes",
+                    "options": { "codes": ["A", " ", null, "B"] },
+                    "field_groups": {
+                        "Group": [
+                            { "name": "Code", "formatters": ["Trim"], "required": required },
+                            { "name": "Name" }
+                        ]
+                    }
+                }
+            });
+
+            let import_profile = ImportProfile::new(Cursor::new(json.to_string())).await?;
+            let table_mapper = import_profile.table_mappers().next().expect("table mapper");
+
+            let records = import_profile
+                .data_source_config()
+                .create_data_source(
+                    table_mapper,
+                    &ImportOptions::default(),
+                    None,
+                    None,
+                    None,
+                    &Rc::new(RecordAccounting::default()),
+                    None,
+                )
+                .await?;
+
+            Ok(Box::into_pin(records)
+                .map(|record| {
+                    record
+                        .map(|record| record.get("Code").unwrap_or_default().to_owned())
+                        .map_err(|err| err.index().record_number)
+                })
+                .collect::<Vec<_>>()
+                .await)
+        };
+
+        assert_eq!(read("Drop").await?, [Ok("A".into()), Ok("B".into())]);
+        assert_eq!(
+            read("Error").await?,
+            [
+                Ok("A".into()),
+                Err(NonZero::new(2)),
+                Err(NonZero::new(3)),
+                Ok("B".into())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn order_table_mappers_rejects_cycle() {
+        let json = import_profile_json(&[("A", &["B"]), ("B", &["A"])]);
+
+        assert!(matches!(
+            ImportProfile::new(Cursor::new(json)).await,
+            Err(CreateImportProfileError::TableMapperDependencyCycle(name))
+                if name == "A" || name == "B"
+        ));
+    }
+
+    #[tokio::test]
+    async fn order_table_mappers_rejects_unknown_dependency() {
+        let json = import_profile_json(&[("A", &["Missing"])]);
+
+        assert!(matches!(
+            ImportProfile::new(Cursor::new(json)).await,
+            Err(CreateImportProfileError::UnknownTableMapperDependency { dependency, .. })
+                if dependency == "Missing"
+        ));
+    }
+}
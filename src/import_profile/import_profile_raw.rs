@@ -11,16 +11,28 @@ pub struct ImportProfileRaw {
     pub(crate) name: String,
     pub(crate) description: Option<String>,
     pub(crate) data_source_config: ImportProfileDataSourceConfig,
+    /// Trim leading and trailing whitespace from every field of the data source, unless the field
+    /// opts out
+    #[serde(default)]
+    pub(crate) trim_fields: bool,
+    /// Script providing the preprocess functions of the table mappers
     pub(crate) preprocess_script: Option<PreprocessScript>,
+    /// Table mappers, executed in declaration order unless reordered by their dependencies
+    #[schemars(length(min = 1))]
     pub(crate) table_mappers: Vec<TableMapperRaw>,
 }
 
 #[derive(Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub enum PreprocessScript {
+    /// Script file, with the language inferred from the file extension if not specified
     File {
         path: PathBuf,
         language: Option<PreprocessLanguage>,
+        /// Hex encoded SHA-256 digest the script file must match, such that signing the import profile also signs the script
+        sha256: Option<String>,
     },
+    /// Script embedded within the import profile
     Inline {
         script: String,
         language: PreprocessLanguage,
@@ -38,7 +50,7 @@ pub enum PreprocessLanguage {
 impl PreprocessScript {
     pub fn language(&self) -> Option<PreprocessLanguage> {
         match self {
-            PreprocessScript::File { path, language } => match language {
+            PreprocessScript::File { path, language, .. } => match language {
                 Some(language) => Some(*language),
                 None => match path.extension()?.to_str()? {
                     #[cfg(feature = "lua")]
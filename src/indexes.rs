@@ -0,0 +1,161 @@
+use crate::backend::SqlBackend;
+use crate::guardrail::count_rows;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace};
+
+/// Disables the non-unique nonclustered indexes of the target table if the rows of the temporary
+/// table are at least the threshold percentage of its rows, returning those to be rebuilt.
+pub async fn disable_if_large(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    temporary_table: &TableIdentifier,
+    threshold: f64,
+) -> Result<Vec<String>, tiberius::error::Error> {
+    let target_rows = count_rows(client, target_table).await?.max(1);
+    let temporary_rows = count_rows(client, temporary_table).await?;
+    let load = temporary_rows as f64 / target_rows as f64 * 100.0;
+
+    if load < threshold {
+        return Ok(Vec::new());
+    }
+
+    let statement = formatdoc!(
+        "
+        SELECT QUOTENAME([name])
+        FROM sys.indexes
+        WHERE [object_id] = OBJECT_ID(@P1)
+            AND [type] = 2
+            AND [is_unique] = 0
+            AND [is_disabled] = 0
+            AND [is_hypothetical] = 0
+        ORDER BY [index_id]
+        "
+    );
+
+    trace_sql!(statement);
+
+    let indexes = client
+        .query(&statement, &[&target_table.full().to_string()])
+        .await?
+        .into_iter()
+        .filter_map(|row| row.get::<&str>(0).map(str::to_owned))
+        .collect::<Vec<_>>();
+
+    for index in &indexes {
+        let statement = formatdoc!(
+            "
+            ALTER INDEX {index} ON {target_table} DISABLE
+            ",
+            target_table = target_table.full(),
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await?;
+    }
+
+    if !indexes.is_empty() {
+        info!(
+            "Disabled indexes {} of {} for a load of {:.1}% of its rows ({} rows), rebuilding them after the merge",
+            indexes.join(", "),
+            target_table,
+            load,
+            temporary_rows,
+        );
+    }
+
+    Ok(indexes)
+}
+
+/// Rebuilds the disabled indexes of the target table, online where the edition of the server
+/// supports online index operations, such that the target table remains available meanwhile.
+pub async fn rebuild(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    indexes: &[String],
+) -> Result<(), tiberius::error::Error> {
+    for index in indexes {
+        // Enterprise, Azure SQL Database and Azure SQL Managed Instance editions
+        let statement = formatdoc!(
+            "
+            IF SERVERPROPERTY('EngineEdition') IN (3, 5, 8)
+                ALTER INDEX {index} ON {target_table} REBUILD WITH (ONLINE = ON)
+            ELSE
+                ALTER INDEX {index} ON {target_table} REBUILD
+            ",
+            target_table = target_table.full(),
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await?;
+
+        info!("Rebuilt index {} of {}", index, target_table);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use tiberius::ColumnData;
+
+    fn count(rows: i64) -> [QueryRow; 1] {
+        [QueryRow::new([ColumnData::I64(Some(rows))])]
+    }
+
+    #[tokio::test]
+    async fn disable_indexes_of_large_loads() -> Result<(), tiberius::error::Error> {
+        let target_table = "[dbo].[Country]".parse().unwrap();
+        let temporary_table = "[import].[Country]".parse().unwrap();
+        let mut backend = MockBackend::new();
+
+        backend.push_rows(count(100));
+        backend.push_rows(count(40));
+
+        assert!(
+            disable_if_large(&mut backend, &target_table, &temporary_table, 50.0)
+                .await?
+                .is_empty()
+        );
+        assert_eq!(backend.statements().len(), 2);
+
+        backend.push_rows(count(100));
+        backend.push_rows(count(60));
+        backend.push_rows(
+            ["[IX_Country_Name]", "[IX_Country_Region]"]
+                .map(|index| QueryRow::new([ColumnData::String(Some(index.into()))])),
+        );
+
+        let indexes = disable_if_large(&mut backend, &target_table, &temporary_table, 50.0).await?;
+
+        assert_eq!(indexes, ["[IX_Country_Name]", "[IX_Country_Region]"]);
+
+        let statements = backend.statements();
+
+        assert_eq!(statements.len(), 7);
+        assert_eq!(
+            statements[4].parameters,
+            [format!(
+                "{:?}",
+                ColumnData::String(Some("[dbo].[Country]".into()))
+            )]
+        );
+        assert_eq!(
+            statements[5].statement.trim(),
+            "ALTER INDEX [IX_Country_Name] ON [dbo].[Country] DISABLE"
+        );
+
+        rebuild(&mut backend, &target_table, &indexes).await?;
+
+        assert!(backend.statements()[8].statement.contains(
+            "ALTER INDEX [IX_Country_Region] ON [dbo].[Country] REBUILD WITH (ONLINE = ON)"
+        ));
+
+        Ok(())
+    }
+}
@@ -1,30 +1,81 @@
+use crate::backend::{BulkInsert, ExecuteResult, MultiRowInsert, SqlBackend};
 use crate::column_graph::{ColumnNode, IndexedColumnNode, UniqueColumnIdentifier};
 use crate::data_source::DataSourceRecord;
 use crate::identifier::{ColumnIdentifier, Identifier};
+use crate::import_options::InsertStrategy;
+use crate::spatial::{self, well_known_text};
 use crate::table_mapper::{Column, FieldColumn, ParserColumn, Table};
-use crate::temporary_table::TemporaryTable;
-use rust_decimal::Decimal;
+use crate::temporary_table::{RECORD_NUMBER_COLUMN, TemporaryTable};
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use rust_decimal::{Decimal, RoundingStrategy};
 use std::borrow::Cow;
 use thiserror::Error;
+use tiberius::time::{Date, DateTime2, Time};
 use tiberius::{
-    BaseMetaDataColumn, BulkLoadRequest, Client, ColumnData, ExecuteResult, FixedLenType, IntoSql,
-    TokenRow, TypeInfo, VarLenType,
+    BaseMetaDataColumn, ColumnData, FixedLenType, IntoSql, TokenRow, TypeInfo, VarLenType,
 };
-use tokio::net::TcpStream;
-use tokio_util::compat::Compat;
 
-pub struct InsertProcessor<'a> {
+/// Days from 0000-03-01 to 0001-01-01, the epoch of SQL Server dates.
+const DAYS_TO_DATE_EPOCH: i64 = 306;
+
+/// Error number of a bulk load denied for lack of permission.
+const BULK_LOAD_DENIED_ERROR: u32 = 4834;
+
+pub struct InsertProcessor<'a, B: SqlBackend + 'a> {
     target_columns: Vec<(
         &'a ParserColumn,
         &'a UniqueColumnIdentifier,
         &'a BaseMetaDataColumn,
     )>,
-    bulk_insert: BulkLoadRequest<'a, Compat<TcpStream>>,
+    row_insert: RowInsert<'a, B>,
+    /// Whether the number of each record is inserted into the record number column
+    record_numbers: bool,
+}
+
+/// Insertion of rows into the temporary table, bulk inserted, or inserted by multi-row inserts
+/// where bulk load is unavailable.
+enum RowInsert<'a, B: SqlBackend + 'a> {
+    BulkLoad(B::BulkInsert<'a>),
+    MultiRowInsert(MultiRowInsert<'a, B>),
+}
+
+impl<B: SqlBackend> RowInsert<'_, B> {
+    async fn send(&mut self, row: TokenRow<'static>) -> Result<(), tiberius::error::Error> {
+        match self {
+            RowInsert::BulkLoad(bulk_insert) => bulk_insert.send(row).await,
+            RowInsert::MultiRowInsert(multi_row_insert) => multi_row_insert.send(row).await,
+        }
+    }
+
+    async fn finalize(self) -> Result<ExecuteResult, tiberius::error::Error> {
+        match self {
+            RowInsert::BulkLoad(bulk_insert) => bulk_insert.finalize().await,
+            RowInsert::MultiRowInsert(multi_row_insert) => multi_row_insert.finalize().await,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
-#[error(transparent)]
-pub struct CreateInsertProcessorError(#[from] tiberius::error::Error);
+pub enum CreateInsertProcessorError {
+    #[error("column '{0}' is not a parser column, and cannot be bulk inserted")]
+    UnexpectedColumn(ColumnIdentifier),
+    #[error(transparent)]
+    BulkInsertFailed(#[from] tiberius::error::Error),
+}
+
+impl CreateInsertProcessorError {
+    /// Whether the bulk insert was denied for lack of bulk load permission, such that the records
+    /// may instead be inserted with [`InsertStrategy::MultiRowInsert`].
+    pub fn is_bulk_load_denied(&self) -> bool {
+        matches!(
+            self,
+            CreateInsertProcessorError::BulkInsertFailed(tiberius::error::Error::Server(token))
+                if token.code() == BULK_LOAD_DENIED_ERROR
+        )
+    }
+}
 
 #[derive(Debug, Error)]
 #[error(transparent)]
@@ -37,44 +88,79 @@ pub enum ProcessRecordError {
         field: String,
         column: ColumnIdentifier,
     },
+    #[error("unsupported type '{ty}' for column '{column}', map the column to a supported type")]
+    UnsupportedColumnType {
+        column: ColumnIdentifier,
+        ty: String,
+    },
     #[error(transparent)]
     SendRowFailed(#[from] tiberius::error::Error),
 }
 
-impl<'temp_table, 'connection: 'temp_table, 'column_graph: 'temp_table>
-    InsertProcessor<'temp_table>
+impl<'temp_table, 'connection: 'temp_table, 'column_graph: 'temp_table, B: SqlBackend>
+    InsertProcessor<'temp_table, B>
 {
     pub async fn new(
-        client: &'connection mut Client<Compat<TcpStream>>,
+        client: &'connection mut B,
         temporary_table: &'temp_table TemporaryTable,
         columns: impl Iterator<Item = IndexedColumnNode<'column_graph>>,
+        insert_strategy: InsertStrategy,
     ) -> Result<Self, CreateInsertProcessorError> {
         let target_columns = columns
             .map(|column| match column.column() {
                 ColumnNode::ParserColumn {
                     column: parser_column,
                     ..
-                } => (parser_column, column.unique_identifier(), column.metadata()),
-                _ => unreachable!("Expected only parser columns in the first column graph group."),
+                } => Ok((parser_column, column.unique_identifier(), column.metadata())),
+                other => Err(CreateInsertProcessorError::UnexpectedColumn(
+                    other.identifier().to_owned(),
+                )),
             })
-            .collect::<Vec<(
-                &'column_graph ParserColumn,
-                &'column_graph UniqueColumnIdentifier,
-                &'column_graph BaseMetaDataColumn,
-            )>>();
+            .collect::<Result<
+                Vec<(
+                    &'column_graph ParserColumn,
+                    &'column_graph UniqueColumnIdentifier,
+                    &'column_graph BaseMetaDataColumn,
+                )>,
+                CreateInsertProcessorError,
+            >>()?;
 
-        let target_columns_refs = target_columns
+        let record_numbers = temporary_table.record_numbers();
+
+        // spatial columns are inserted as Well-Known Text, converted once inserted
+        let target_columns_names = target_columns
+            .iter()
+            .map(
+                |(parser_column, unique_identifier, _)| match parser_column.spatial() {
+                    Some(_) => Cow::Owned(spatial::text_column(unique_identifier)),
+                    None => Cow::Borrowed(unique_identifier.part()),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let target_columns_refs = target_columns_names
             .iter()
-            .map(|(_, unique_identifier, _)| unique_identifier.part())
+            .map(|name| &**name)
+            .chain(record_numbers.then_some(RECORD_NUMBER_COLUMN))
             .collect::<Vec<_>>();
 
-        let bulk_insert = client
-            .bulk_insert_columns(temporary_table.identifier().full(), &target_columns_refs)
-            .await?;
+        let row_insert = match insert_strategy {
+            InsertStrategy::Auto | InsertStrategy::BulkLoad => RowInsert::BulkLoad(
+                client
+                    .bulk_insert(temporary_table.identifier().full(), &target_columns_refs)
+                    .await?,
+            ),
+            InsertStrategy::MultiRowInsert => RowInsert::MultiRowInsert(MultiRowInsert::new(
+                client,
+                temporary_table.identifier().full(),
+                &target_columns_refs,
+            )),
+        };
 
         Ok(InsertProcessor {
             target_columns,
-            bulk_insert,
+            row_insert,
+            record_numbers,
         })
     }
 
@@ -92,68 +178,587 @@ impl<'temp_table, 'connection: 'temp_table, 'column_graph: 'temp_table>
                 }
             })?;
 
-            let column_data: ColumnData = match metadata.ty {
-                TypeInfo::FixedLen(fixed_len) => match fixed_len {
-                    FixedLenType::Null => ColumnData::Bit(None),
-                    FixedLenType::Int1 => ColumnData::U8(field_value.parse::<u8>().ok()),
-                    FixedLenType::Bit => ColumnData::Bit(field_value.parse::<bool>().ok()),
-                    FixedLenType::Int2 => ColumnData::I16(field_value.parse::<i16>().ok()),
-                    FixedLenType::Int4 => ColumnData::I32(field_value.parse::<i32>().ok()),
-                    FixedLenType::Float4 => ColumnData::F32(field_value.parse::<f32>().ok()),
-                    FixedLenType::Float8 => ColumnData::F64(field_value.parse::<f64>().ok()),
-                    FixedLenType::Int8 => ColumnData::I64(field_value.parse::<i64>().ok()),
-                    _ => panic!(
-                        "Unsupported FixedLen column ({}) type: {:?}",
-                        parser_column.identifier(),
-                        metadata.ty
-                    ),
-                },
-                TypeInfo::VarLenSized(var_len_sized) => match var_len_sized.r#type() {
-                    VarLenType::BigVarChar => {
-                        ColumnData::String(Some(Cow::from(field_value.to_owned())))
-                    }
-                    VarLenType::NVarchar => {
-                        ColumnData::String(Some(Cow::from(field_value.to_owned())))
-                    }
-                    _ => panic!(
-                        "Unsupported VarLenSized column ({}) type: {:?}",
-                        parser_column.identifier(),
-                        metadata.ty
-                    ),
-                },
-                TypeInfo::VarLenSizedPrecision {
-                    ty,
-                    size: _,
-                    precision: _,
-                    scale: _,
-                } => match ty {
-                    VarLenType::Decimaln => field_value.parse::<Decimal>().ok().into_sql(),
-                    VarLenType::Numericn => field_value.parse::<Decimal>().ok().into_sql(),
-                    VarLenType::Money => field_value.parse::<Decimal>().ok().into_sql(),
-                    _ => panic!(
-                        "Unsupported VarLenSizedPrecision column ({}) type: {:?}",
-                        parser_column.identifier(),
-                        metadata.ty
-                    ),
-                },
-                TypeInfo::Xml { .. } => {
-                    panic!(
-                        "Unsupported Xml column ({}) type: {:?}",
-                        parser_column.identifier(),
-                        metadata.ty
-                    );
-                }
-            };
+            match parser_column.spatial() {
+                Some(spatial) => row.push(ColumnData::String(
+                    well_known_text(spatial, field_value, &record)
+                        .map(|text| Cow::Owned(text.into_owned())),
+                )),
+                None => row.push(convert_field(field_value, parser_column, metadata)?),
+            }
+        }
 
-            row.push(column_data);
+        if self.record_numbers {
+            row.push(ColumnData::I64(Some(
+                record.index().record_number.get() as i64
+            )));
         }
 
-        self.bulk_insert.send(row).await?;
+        self.row_insert.send(row).await?;
 
         Ok(())
     }
 
     pub async fn finalize(self) -> Result<ExecuteResult, FinalizeInsertProcessorError> {
-        Ok(self.bulk_insert.finalize().await?)
+        Ok(self.row_insert.finalize().await?)
+    }
+}
+
+/// Converts the field value into a value of the type of the column, as bulk inserted into the
+/// staging table, where values which cannot be converted are NULL.
+pub(crate) fn convert_field(
+    field_value: &str,
+    parser_column: &ParserColumn,
+    metadata: &BaseMetaDataColumn,
+) -> Result<ColumnData<'static>, ProcessRecordError> {
+    let field_value = parser_column.normalization().apply(field_value);
+    let field_value = &*field_value;
+    let number = apply_implied_decimals(field_value, parser_column.implied_decimals());
+    let number = &*number;
+
+    let unsupported_column_type = || ProcessRecordError::UnsupportedColumnType {
+        column: parser_column.identifier().to_owned(),
+        ty: metadata.ty.to_string(),
+    };
+
+    let money = || {
+        MoneyType::of(&metadata.ty)
+            .and_then(|money_type| money_type.parse(number))
+            .into_sql()
+    };
+
+    let string = || {
+        let valid = !parser_column.json() || is_json(field_value);
+
+        ColumnData::String(valid.then(|| Cow::from(field_value.to_owned())))
+    };
+
+    let column_data = match metadata.ty {
+        TypeInfo::FixedLen(fixed_len) => match fixed_len {
+            FixedLenType::Null => ColumnData::Bit(None),
+            FixedLenType::Int1 => ColumnData::U8(number.parse::<u8>().ok()),
+            FixedLenType::Bit => ColumnData::Bit(field_value.parse::<bool>().ok()),
+            FixedLenType::Int2 => ColumnData::I16(number.parse::<i16>().ok()),
+            FixedLenType::Int4 => ColumnData::I32(number.parse::<i32>().ok()),
+            FixedLenType::Float4 => ColumnData::F32(number.parse::<f32>().ok()),
+            FixedLenType::Float8 => ColumnData::F64(number.parse::<f64>().ok()),
+            FixedLenType::Int8 => ColumnData::I64(number.parse::<i64>().ok()),
+            FixedLenType::Money | FixedLenType::Money4 => money(),
+            _ => return Err(unsupported_column_type()),
+        },
+        TypeInfo::VarLenSized(var_len_sized) => match var_len_sized.r#type() {
+            VarLenType::BigVarChar => string(),
+            VarLenType::NVarchar => string(),
+            VarLenType::Text | VarLenType::NText => string(),
+            VarLenType::Image => ColumnData::Binary(parse_binary(field_value).map(Cow::from)),
+            VarLenType::Money => money(),
+            VarLenType::SSVariant => ColumnData::String(Some(Cow::from(field_value.to_owned()))),
+            VarLenType::Daten => ColumnData::Date(parse_date(field_value)),
+            VarLenType::Timen => {
+                ColumnData::Time(parse_time(field_value, var_len_sized.len() as u8))
+            }
+            VarLenType::Datetime2 => {
+                ColumnData::DateTime2(parse_datetime2(field_value, var_len_sized.len() as u8))
+            }
+            _ => return Err(unsupported_column_type()),
+        },
+        TypeInfo::VarLenSizedPrecision {
+            ty,
+            size: _,
+            precision,
+            scale,
+        } => match ty {
+            VarLenType::Decimaln | VarLenType::Numericn => parse_decimal(number)
+                .and_then(|value| fit_decimal(value, precision, scale))
+                .into_sql(),
+            VarLenType::Money => money(),
+            _ => return Err(unsupported_column_type()),
+        },
+        TypeInfo::Xml { .. } => {
+            ColumnData::String(parse_xml(field_value).map(|value| Cow::from(value.to_owned())))
+        }
+    };
+
+    Ok(column_data)
+}
+
+/// Whether the value converted by [`convert_field`] is NULL.
+pub(crate) fn is_null(value: &ColumnData<'_>) -> bool {
+    matches!(
+        value,
+        ColumnData::U8(None)
+            | ColumnData::Bit(None)
+            | ColumnData::I16(None)
+            | ColumnData::I32(None)
+            | ColumnData::I64(None)
+            | ColumnData::F32(None)
+            | ColumnData::F64(None)
+            | ColumnData::String(None)
+            | ColumnData::Binary(None)
+            | ColumnData::Numeric(None)
+            | ColumnData::Date(None)
+            | ColumnData::Time(None)
+            | ColumnData::DateTime2(None)
+    )
+}
+
+/// Parses a binary value from hexadecimal digits, optionally prefixed with `0x` as SQL Server
+/// formats binary literals.
+pub(crate) fn parse_binary(value: &str) -> Option<Vec<u8>> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+
+    digits
+        .as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+
+            Some(((high << 4) | low) as u8)
+        })
+        .collect()
+}
+
+/// Whether the value is a valid JSON object or array, as `ISJSON` accepts by default, with any
+/// surrounding whitespace.
+pub(crate) fn is_json(value: &str) -> bool {
+    value.trim_start().starts_with(['{', '['])
+        && serde_json::from_str::<serde::de::IgnoredAny>(value).is_ok()
+}
+
+/// Parses well-formed XML, either a document or a fragment, without its XML declaration, which SQL
+/// Server rejects where it names an encoding other than UTF-16.
+pub(crate) fn parse_xml(value: &str) -> Option<&str> {
+    let mut reader = Reader::from_str(value);
+    let mut content_start = 0;
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event().ok()? {
+            Event::Decl(_) => content_start = reader.buffer_position() as usize,
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.checked_sub(1)?,
+            Event::GeneralRef(general_ref) => {
+                if !matches!(general_ref.resolve_char_ref(), Ok(Some(_)))
+                    && resolve_predefined_entity(&general_ref.decode().ok()?).is_none()
+                {
+                    return None;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    (depth == 0).then(|| value[content_start..].trim_start())
+}
+
+/// Parses a zero-padded date, `YYYY-MM-DD`, between 0001-01-01 and 9999-12-31 in the proleptic
+/// Gregorian calendar.
+pub(crate) fn parse_date(value: &str) -> Option<Date> {
+    let mut parts = value.splitn(3, '-');
+
+    let year = parse_digits(parts.next()?, 4)?;
+    let month = parse_digits(parts.next()?, 2)?;
+    let day = parse_digits(parts.next()?, 2)?;
+
+    if year == 0 || !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, 1) + day - 1;
+    let next_month = if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    };
+
+    (days < next_month).then(|| Date::new((days - DAYS_TO_DATE_EPOCH) as u32))
+}
+
+/// Parses a time of day, `hh:mm:ss` with any fractional digits, as increments of the scale of the
+/// column, truncating fractional digits beyond the scale.
+pub(crate) fn parse_time(value: &str, scale: u8) -> Option<Time> {
+    let (time, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let mut parts = time.splitn(3, ':');
+
+    let hours = parse_digits(parts.next()?, 2)?;
+    let minutes = parse_digits(parts.next()?, 2)?;
+    let seconds = parse_digits(parts.next()?, 2)?;
+
+    if hours > 23 || minutes > 59 || seconds > 59 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let scale = scale.min(7);
+    let fraction = fraction
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(scale as usize)
+        .fold(0, |fraction, digit| fraction * 10 + (digit - b'0') as u64);
+
+    let seconds = (hours * 3_600 + minutes * 60 + seconds) as u64;
+
+    Some(Time::new(
+        seconds * 10_u64.pow(scale as u32) + fraction,
+        scale,
+    ))
+}
+
+/// Parses a date and time, `YYYY-MM-DDThh:mm:ss` with the date and time separated by either `T` or
+/// a space, optionally suffixed with `Z`. A date alone is read as midnight of the date.
+pub(crate) fn parse_datetime2(value: &str, scale: u8) -> Option<DateTime2> {
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, "00:00:00"));
+
+    Some(DateTime2::new(parse_date(date)?, parse_time(time, scale)?))
+}
+
+/// Parses a number of exactly the given digits, zero-padded and without a sign.
+fn parse_digits(value: &str, digits: usize) -> Option<i64> {
+    if value.len() != digits || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok()
+}
+
+/// Days since 0000-03-01 of the date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era
+}
+
+/// Whether values of the type can be converted from fields by [`InsertProcessor`], and from
+/// static column values as parameters.
+pub(crate) fn is_supported_type(ty: &TypeInfo) -> bool {
+    match ty {
+        TypeInfo::FixedLen(fixed_len) => matches!(
+            fixed_len,
+            FixedLenType::Null
+                | FixedLenType::Int1
+                | FixedLenType::Bit
+                | FixedLenType::Int2
+                | FixedLenType::Int4
+                | FixedLenType::Float4
+                | FixedLenType::Float8
+                | FixedLenType::Int8
+                | FixedLenType::Money
+                | FixedLenType::Money4
+        ),
+        TypeInfo::VarLenSized(var_len_sized) => matches!(
+            var_len_sized.r#type(),
+            VarLenType::BigVarChar
+                | VarLenType::NVarchar
+                | VarLenType::Text
+                | VarLenType::NText
+                | VarLenType::Image
+                | VarLenType::Money
+                | VarLenType::SSVariant
+                | VarLenType::Daten
+                | VarLenType::Timen
+                | VarLenType::Datetime2
+        ),
+        TypeInfo::VarLenSizedPrecision { ty, .. } => matches!(
+            ty,
+            VarLenType::Decimaln | VarLenType::Numericn | VarLenType::Money
+        ),
+        TypeInfo::Xml { .. } => true,
+    }
+}
+
+/// Type of the staging column of a column of the type, where values are bulk inserted as a
+/// different type and converted when merged into the target table.
+pub(crate) fn staging_type(ty: &TypeInfo) -> String {
+    match ty {
+        TypeInfo::VarLenSized(var_len_sized) if var_len_sized.r#type() == VarLenType::SSVariant => {
+            // Values are stored in the sql_variant column with an nvarchar base type
+            String::from("nvarchar(4000)")
+        }
+        TypeInfo::Xml { .. } => {
+            // Values are converted to xml when merged, as XML schema collections of the target
+            // database cannot type a column of a temporary table, deferring the validation of
+            // typed xml values to the merge
+            String::from("nvarchar(max)")
+        }
+        ty => match MoneyType::of(ty) {
+            Some(money_type) => money_type.staging_type().to_owned(),
+            None => ty.to_string(),
+        },
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum MoneyType {
+    Money,
+    SmallMoney,
+}
+
+impl MoneyType {
+    pub(crate) fn of(ty: &TypeInfo) -> Option<Self> {
+        match ty {
+            TypeInfo::FixedLen(FixedLenType::Money) => Some(MoneyType::Money),
+            TypeInfo::FixedLen(FixedLenType::Money4) => Some(MoneyType::SmallMoney),
+            TypeInfo::VarLenSized(var_len_sized) if var_len_sized.r#type() == VarLenType::Money => {
+                if var_len_sized.len() == 4 {
+                    Some(MoneyType::SmallMoney)
+                } else {
+                    Some(MoneyType::Money)
+                }
+            }
+            TypeInfo::VarLenSizedPrecision {
+                ty: VarLenType::Money,
+                ..
+            } => Some(MoneyType::Money),
+            _ => None,
+        }
+    }
+
+    /// Type of the staging column, money values are bulk inserted as decimals of the same range
+    /// and scale, and converted when merged into the target table.
+    pub(crate) fn staging_type(self) -> &'static str {
+        match self {
+            MoneyType::Money => "decimal(19, 4)",
+            MoneyType::SmallMoney => "decimal(10, 4)",
+        }
+    }
+
+    /// Parses a money value, rounded to four decimal places as SQL Server rounds, or `None` if the
+    /// value is invalid or out of range.
+    pub(crate) fn parse(self, value: &str) -> Option<Decimal> {
+        let (min, max) = match self {
+            MoneyType::Money => (Decimal::new(i64::MIN, 4), Decimal::new(i64::MAX, 4)),
+            MoneyType::SmallMoney => (
+                Decimal::new(i32::MIN as i64, 4),
+                Decimal::new(i32::MAX as i64, 4),
+            ),
+        };
+
+        fit_decimal(parse_decimal(value)?, 19, 4).filter(|value| (min..=max).contains(value))
+    }
+}
+
+/// Scales a numeric value by its number of implied decimal places, e.g. `1999` with two implied
+/// decimal places is `19.99`, leaving values which are not numeric as they are.
+pub(crate) fn apply_implied_decimals(value: &str, implied_decimals: u8) -> Cow<'_, str> {
+    if implied_decimals == 0 {
+        return Cow::Borrowed(value);
+    }
+
+    parse_decimal(value)
+        .and_then(|value| value.checked_mul(Decimal::new(1, implied_decimals as u32)))
+        .map_or(Cow::Borrowed(value), |value| {
+            Cow::Owned(value.normalize().to_string())
+        })
+}
+
+/// Parses a decimal value, in either plain or scientific notation (e.g. `1.5E-3`).
+pub(crate) fn parse_decimal(value: &str) -> Option<Decimal> {
+    value
+        .parse::<Decimal>()
+        .or_else(|_| Decimal::from_scientific(value))
+        .ok()
+}
+
+/// Rounds and rescales the value to the scale of a `decimal(precision, scale)` column, or `None` if
+/// the value has more integer digits than the column allows.
+pub(crate) fn fit_decimal(value: Decimal, precision: u8, scale: u8) -> Option<Decimal> {
+    let mut value =
+        value.round_dp_with_strategy(scale as u32, RoundingStrategy::MidpointAwayFromZero);
+
+    value.rescale(scale as u32);
+
+    let integer_digits = precision.saturating_sub(scale) as u32;
+
+    if integer_digits > Decimal::MAX_SCALE
+        || value.abs() < Decimal::from_i128_with_scale(10_i128.pow(integer_digits), 0)
+    {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_money_boundaries() {
+        let cases = [
+            (
+                MoneyType::Money,
+                "922337203685477.5807",
+                Some("922337203685477.5807"),
+            ),
+            (
+                MoneyType::Money,
+                "-922337203685477.5808",
+                Some("-922337203685477.5808"),
+            ),
+            (MoneyType::Money, "922337203685477.5808", None),
+            (
+                MoneyType::Money,
+                "922337203685477.58074",
+                Some("922337203685477.5807"),
+            ),
+            (MoneyType::Money, "1.23455", Some("1.2346")),
+            (MoneyType::Money, "-1.23455", Some("-1.2346")),
+            (MoneyType::Money, "1.5E3", Some("1500.0000")),
+            (MoneyType::Money, "2e-4", Some("0.0002")),
+            (MoneyType::Money, "1,000", None),
+            (MoneyType::SmallMoney, "214748.3647", Some("214748.3647")),
+            (MoneyType::SmallMoney, "-214748.3648", Some("-214748.3648")),
+            (MoneyType::SmallMoney, "214748.3648", None),
+            (MoneyType::SmallMoney, "-214748.36485", None),
+        ];
+
+        for (money_type, value, expected) in cases {
+            let parsed = money_type.parse(value);
+
+            assert_eq!(parsed, expected.map(decimal), "{money_type:?} {value}");
+            assert!(parsed.is_none_or(|parsed| parsed.scale() == 4));
+        }
+    }
+
+    #[test]
+    fn fit_decimal_to_column() {
+        let cases = [
+            ("99999.9999", 9, 4, Some("99999.9999")),
+            ("-99999.99995", 9, 4, None),
+            ("100000", 9, 4, None),
+            ("1.005", 5, 2, Some("1.01")),
+            ("12", 5, 2, Some("12.00")),
+            ("1E-5", 9, 4, Some("0.0000")),
+            ("1.5E2", 5, 2, Some("150.00")),
+            ("12345678901234567890", 38, 0, Some("12345678901234567890")),
+            ("0.5", 1, 0, Some("1")),
+            ("9.5", 1, 0, None),
+        ];
+
+        for (value, precision, scale, expected) in cases {
+            let fitted = fit_decimal(parse_decimal(value).unwrap(), precision, scale);
+
+            assert_eq!(
+                fitted,
+                expected.map(decimal),
+                "{value} as decimal({precision}, {scale})"
+            );
+            assert!(fitted.is_none_or(|fitted| fitted.scale() == scale as u32));
+        }
+    }
+
+    #[test]
+    fn parse_dates_and_times() {
+        assert_eq!(parse_date("0001-01-01").map(|date| date.days()), Some(0));
+        assert_eq!(
+            parse_date("1900-01-01").map(|date| date.days()),
+            Some(693_595)
+        );
+        assert_eq!(
+            parse_date("2024-02-29").map(|date| date.days()),
+            Some(738_944)
+        );
+        assert_eq!(parse_date("2023-02-29").map(|date| date.days()), None);
+        assert_eq!(parse_date("2024-13-01").map(|date| date.days()), None);
+        assert_eq!(parse_date("24-1-1").map(|date| date.days()), None);
+        assert_eq!(parse_date("2024-1-01").map(|date| date.days()), None);
+        assert_eq!(
+            parse_date("0024-01-01").map(|date| date.days()),
+            Some(8_400)
+        );
+        assert_eq!(parse_date("+024-01-01").map(|date| date.days()), None);
+
+        let time = parse_time("23:59:59.9999999", 7).unwrap();
+
+        assert_eq!((time.increments(), time.scale()), (863_999_999_999, 7));
+
+        let time = parse_time("12:30:00.129", 2).unwrap();
+
+        assert_eq!((time.increments(), time.scale()), (4_500_012, 2));
+        assert!(parse_time("24:00:00", 7).is_none());
+        assert!(parse_time("9:05:00", 7).is_none());
+
+        let datetime2 = parse_datetime2("2024-01-01T00:00:01.5Z", 3).unwrap();
+
+        assert_eq!(datetime2.date().days(), 738_885);
+        assert_eq!(datetime2.time().increments(), 1_500);
+        assert_eq!(
+            parse_datetime2("2024-01-01", 0).map(|datetime2| datetime2.time().increments()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn parse_well_formed_xml() {
+        assert_eq!(
+            parse_xml("<Item id=\"1\">A &amp; B</Item>"),
+            Some("<Item id=\"1\">A &amp; B</Item>")
+        );
+        assert_eq!(
+            parse_xml("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Item/>"),
+            Some("<Item/>")
+        );
+        assert_eq!(
+            parse_xml("<Line>1</Line><Line>2</Line>"),
+            Some("<Line>1</Line><Line>2</Line>")
+        );
+        assert_eq!(parse_xml("text"), Some("text"));
+        assert_eq!(parse_xml("<Item>"), None);
+        assert_eq!(parse_xml("<Item></Other>"), None);
+        assert_eq!(parse_xml("</Item>"), None);
+        assert_eq!(parse_xml("&unknown;"), None);
+    }
+
+    #[test]
+    fn validate_json() {
+        assert!(is_json(r#"{"a": [1, 2.5, null, "b"]}"#));
+        assert!(is_json(" [] "));
+        assert!(!is_json("\"text\""));
+        assert!(!is_json("{'a': 1}"));
+        assert!(!is_json(r#"{"a": 1"#));
+        assert!(!is_json("[1] [2]"));
+        assert!(!is_json(""));
+    }
+
+    #[test]
+    fn parse_binary_hexadecimal() {
+        assert_eq!(parse_binary("0x00FFa1"), Some(vec![0x00, 0xff, 0xa1]));
+        assert_eq!(parse_binary("00ff"), Some(vec![0x00, 0xff]));
+        assert_eq!(parse_binary(""), Some(vec![]));
+        assert_eq!(parse_binary("0xf"), None);
+        assert_eq!(parse_binary("+f"), None);
+    }
+
+    #[test]
+    fn scale_by_implied_decimals() {
+        assert_eq!(apply_implied_decimals("1999", 0), "1999");
+        assert_eq!(apply_implied_decimals("1999", 2), "19.99");
+        assert_eq!(apply_implied_decimals("-1200", 2), "-12");
+        assert_eq!(apply_implied_decimals("1.5E3", 3), "1.5");
+        assert_eq!(
+            apply_implied_decimals("5", 28),
+            "0.0000000000000000000000000005"
+        );
+        assert_eq!(apply_implied_decimals("cents", 2), "cents");
+        assert_eq!(
+            MoneyType::Money.parse(&apply_implied_decimals("123456", 4)),
+            Some(decimal("12.3456"))
+        );
     }
 }
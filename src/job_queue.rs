@@ -0,0 +1,516 @@
+use crate::import_executor;
+use crate::import_executor::{ImportExecutorError, ImportHandle};
+use crate::import_options::ImportOptions;
+use crate::signing;
+use crate::signing::OpenImportProfileError;
+use clap::Parser;
+use log::{error, info};
+use rustc_hash::FxHashSet as HashSet;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::File;
+use std::iter::{once, successors};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+use thiserror::Error;
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio_util::compat::Compat;
+
+/// Import job read from a job file of the job queue directory.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// Import profile to execute
+    import_profile: PathBuf,
+    /// Import options as command line arguments, such as `["--path-override", "./data.csv"]`
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+impl Job {
+    /// Parses the import options of the job, which may not be interactive, as jobs are executed
+    /// unattended, nor write a run report, which the job queue writes alongside the job file.
+    fn import_options(&self) -> Result<ImportOptions, JobError> {
+        let import_options = ImportOptions::try_parse_from(
+            once("job").chain(self.options.iter().map(String::as_str)),
+        )
+        .map_err(JobError::ParseImportOptions)?;
+
+        if import_options.interactive {
+            return Err(JobError::Interactive);
+        }
+
+        if import_options.run_report.is_some() {
+            return Err(JobError::RunReport);
+        }
+
+        Ok(import_options)
+    }
+}
+
+/// Directory of job files, each a JSON [`Job`] identified by its file stem, executed in order of
+/// file name and moved into the `done`, `failed` or `cancelled` subdirectory.
+#[derive(Debug)]
+pub struct JobQueue {
+    directory: PathBuf,
+    running: Mutex<Option<RunningJob>>,
+    submitted: AtomicU64,
+    /// Public key every import profile is verified with, whatever the import options of the job
+    verifying_key: Option<PathBuf>,
+    /// Job files which could not be moved once executed, skipped such that they are not executed
+    /// again
+    skipped: Mutex<HashSet<PathBuf>>,
+}
+
+#[derive(Debug)]
+struct RunningJob {
+    job_path: PathBuf,
+    cancel: Arc<Notify>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("could not read job file")]
+    ReadJob(#[source] io::Error),
+    #[error("could not write job file")]
+    WriteJob(#[source] io::Error),
+    #[error("could not deserialize job file")]
+    DeserializeJob(#[source] serde_json::Error),
+    #[error("could not parse job import options")]
+    ParseImportOptions(#[source] clap::Error),
+    #[error("interactive import options are not supported by jobs, which are not attended")]
+    Interactive,
+    #[error("run report import options are not supported by jobs, whose run reports are kept")]
+    RunReport,
+    #[error("could not open import profile")]
+    OpenImportProfile(#[from] OpenImportProfileError),
+    #[error(transparent)]
+    ImportExecutor(#[from] ImportExecutorError),
+}
+
+impl JobQueue {
+    /// Creates the `done`, `failed` and `cancelled` subdirectories of the directory if they do not exist.
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        verifying_key: Option<PathBuf>,
+    ) -> io::Result<JobQueue> {
+        let directory = directory.into();
+
+        fs::create_dir_all(directory.join("done"))?;
+        fs::create_dir_all(directory.join("failed"))?;
+        fs::create_dir_all(directory.join("cancelled"))?;
+
+        Ok(JobQueue {
+            directory,
+            running: Mutex::new(None),
+            submitted: AtomicU64::new(0),
+            verifying_key,
+            skipped: Mutex::new(HashSet::default()),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the path of the job file first in order of file name, if any, other than skipped job
+    /// files.
+    pub fn next(&self) -> io::Result<Option<PathBuf>> {
+        let skipped = self.skipped.lock().unwrap();
+        let mut job_paths = Vec::new();
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+
+            if path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                && !skipped.contains(&path)
+            {
+                job_paths.push(path);
+            }
+        }
+
+        job_paths.sort();
+
+        Ok(job_paths.into_iter().next())
+    }
+
+    /// Writes the job into a new job file, named such that it is executed after the jobs already
+    /// queued, returning the identifier of the job.
+    pub fn submit(&self, job: &[u8]) -> Result<String, JobError> {
+        serde_json::from_slice::<Job>(job)
+            .map_err(JobError::DeserializeJob)?
+            .import_options()?;
+
+        let id = format!(
+            "{:013}_{:04}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            self.submitted.fetch_add(1, Ordering::Relaxed) % 10_000,
+        );
+
+        // written under another extension first, such that a partially written job is not read
+        let job_path = self.directory.join(format!("{id}.json"));
+        let partial_job_path = job_path.with_extension("json.partial");
+
+        fs::write(&partial_job_path, job)
+            .and_then(|()| fs::rename(&partial_job_path, &job_path))
+            .map_err(JobError::WriteJob)?;
+
+        info!("Job {} submitted", id);
+
+        Ok(id)
+    }
+
+    /// Returns the status of the job, or `None` if there is no such job.
+    pub fn status(&self, id: &str) -> Option<JobStatus> {
+        let file_name = Self::file_name(id)?;
+
+        if self.is_running(id) {
+            Some(JobStatus::Running)
+        } else if self.directory.join(&file_name).is_file() {
+            Some(JobStatus::Queued)
+        } else if self.directory.join("done").join(&file_name).is_file() {
+            Some(JobStatus::Succeeded)
+        } else if self.directory.join("failed").join(&file_name).is_file() {
+            Some(JobStatus::Failed)
+        } else if self.directory.join("cancelled").join(&file_name).is_file() {
+            Some(JobStatus::Cancelled)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the description of the error of a failed job.
+    pub fn error(&self, id: &str) -> Option<String> {
+        let file_name = Self::file_name(id)?;
+
+        fs::read_to_string(
+            self.directory
+                .join("failed")
+                .join(file_name)
+                .with_extension("error.txt"),
+        )
+        .ok()
+    }
+
+    /// Returns the run report of a completed job, if it was written.
+    pub fn report(&self, id: &str) -> Option<serde_json::Value> {
+        let file_name = Self::file_name(id)?;
+
+        ["done", "failed", "cancelled"]
+            .into_iter()
+            .find_map(|subdirectory| {
+                let report = fs::read(
+                    self.directory
+                        .join(subdirectory)
+                        .join(&file_name)
+                        .with_extension("report.json"),
+                )
+                .ok()?;
+
+                serde_json::from_slice(&report).ok()
+            })
+    }
+
+    /// Cancels a queued job by moving its job file into the `cancelled` subdirectory, or signals a
+    /// running job to stop, returning the status of the job before it was cancelled.
+    pub fn cancel(&self, id: &str) -> io::Result<Option<JobStatus>> {
+        let Some(file_name) = Self::file_name(id) else {
+            return Ok(None);
+        };
+
+        if let Some(running) = &*self.running.lock().unwrap()
+            && running.job_path.file_stem().is_some_and(|stem| stem == id)
+        {
+            running.cancel.notify_one();
+
+            return Ok(Some(JobStatus::Running));
+        }
+
+        match fs::rename(
+            self.directory.join(&file_name),
+            self.directory.join("cancelled").join(&file_name),
+        ) {
+            Ok(()) => {
+                info!("Job {} cancelled", id);
+
+                Ok(Some(JobStatus::Queued))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(self.status(id)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Marks the job as running, returning a notification of its cancellation.
+    pub fn start(&self, job_path: &Path) -> Arc<Notify> {
+        let cancel = Arc::new(Notify::new());
+
+        *self.running.lock().unwrap() = Some(RunningJob {
+            job_path: job_path.to_owned(),
+            cancel: cancel.clone(),
+        });
+
+        cancel
+    }
+
+    fn is_running(&self, id: &str) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|running| running.job_path.file_stem().is_some_and(|stem| stem == id))
+    }
+
+    /// File name of the job file of the job, or `None` if the identifier could not name one, such
+    /// that identifiers cannot name files outside of the directory.
+    fn file_name(id: &str) -> Option<String> {
+        (!id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .then(|| format!("{id}.json"))
+    }
+
+    /// Reads the job file, then executes its import profile with its import options through the
+    /// import handle.
+    pub async fn execute(
+        &self,
+        client: &mut Client<Compat<TcpStream>>,
+        job_path: &Path,
+        import_handle: &ImportHandle,
+    ) -> Result<(), JobError> {
+        let job: Job = serde_json::from_reader(File::open(job_path).map_err(JobError::ReadJob)?)
+            .map_err(JobError::DeserializeJob)?;
+
+        let mut import_options = job.import_options()?;
+
+        import_options.run_report = Some(Self::partial_report_path(job_path));
+
+        if let Some(verifying_key) = &self.verifying_key {
+            import_options.verifying_key = Some(verifying_key.clone());
+        }
+
+        let import_profile = signing::open_import_profile(
+            &job.import_profile,
+            import_options.verifying_key.as_deref(),
+        )
+        .await?;
+
+        info!(
+            "Executing job {} with import profile {}",
+            job_path.display(),
+            import_profile.name(),
+        );
+
+        import_executor::import_executor(client, import_profile, import_options, import_handle)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Moves the job file of a job cancelled while running into the `cancelled` subdirectory.
+    pub fn cancelled(&self, job_path: &Path) -> io::Result<()> {
+        *self.running.lock().unwrap() = None;
+
+        info!("Job {} cancelled while running", job_path.display());
+
+        let cancelled_path = self.directory.join("cancelled").join(
+            job_path
+                .file_name()
+                .expect("Job paths should have a file name"),
+        );
+
+        Self::move_report(job_path, &cancelled_path)?;
+        fs::rename(job_path, cancelled_path)
+    }
+
+    /// Skips the job file of a job which could not be moved once executed, such that it is not
+    /// executed again by this job queue.
+    pub fn skip(&self, job_path: &Path) {
+        *self.running.lock().unwrap() = None;
+
+        self.skipped.lock().unwrap().insert(job_path.to_owned());
+    }
+
+    /// Path of the run report of a running job, named such that it is not read as a job file.
+    fn partial_report_path(job_path: &Path) -> PathBuf {
+        job_path.with_extension("report.partial")
+    }
+
+    /// Moves the run report of the job, and its signature if signed, alongside the job file at its
+    /// new path, if the report was written.
+    fn move_report(job_path: &Path, new_job_path: &Path) -> io::Result<()> {
+        let report_path = Self::partial_report_path(job_path);
+        let new_report_path = new_job_path.with_extension("report.json");
+
+        for (from, to) in [
+            (
+                signing::signature_path(&report_path),
+                signing::signature_path(&new_report_path),
+            ),
+            (report_path, new_report_path),
+        ] {
+            match fs::rename(from, to) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the job file into the `done` subdirectory, or into the `failed` subdirectory with a
+    /// file describing the error.
+    pub fn complete(
+        &self,
+        job_path: &Path,
+        result: Result<(), &(dyn Error + 'static)>,
+    ) -> io::Result<()> {
+        *self.running.lock().unwrap() = None;
+
+        let file_name = job_path
+            .file_name()
+            .expect("Job paths should have a file name");
+
+        match result {
+            Ok(()) => {
+                info!("Job {} succeeded", job_path.display());
+
+                let done_path = self.directory.join("done").join(file_name);
+
+                Self::move_report(job_path, &done_path)?;
+                fs::rename(job_path, done_path)
+            }
+            Err(err) => {
+                let description = successors(Some(err), |err| err.source())
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n  caused by: ");
+                let description = crate::log::redact(&description);
+
+                error!("Job {} failed: {}", job_path.display(), description);
+
+                let failed_path = self.directory.join("failed").join(file_name);
+
+                fs::write(failed_path.with_extension("error.txt"), &*description)?;
+                Self::move_report(job_path, &failed_path)?;
+                fs::rename(job_path, failed_path)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job_queue(name: &str) -> JobQueue {
+        let directory =
+            std::env::temp_dir().join(format!("job_queue_test_{}_{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&directory);
+
+        JobQueue::new(directory, None).unwrap()
+    }
+
+    #[test]
+    fn file_name_of_job_identifiers() {
+        assert_eq!(
+            JobQueue::file_name("1700000000000_0001").as_deref(),
+            Some("1700000000000_0001.json")
+        );
+        assert_eq!(
+            JobQueue::file_name("nightly-Orders").as_deref(),
+            Some("nightly-Orders.json")
+        );
+
+        for id in [
+            "", ".", "..", "../job", "done/job", "..\\job", "job.json", "job id", "jöb",
+        ] {
+            assert_eq!(JobQueue::file_name(id), None, "{id}");
+        }
+    }
+
+    #[test]
+    fn submit_claim_and_complete_jobs() -> Result<(), Box<dyn Error>> {
+        let job_queue = job_queue("transitions");
+        let job =
+            br#"{"import_profile": "profile.json", "options": ["--path-override", "./data.csv"]}"#;
+
+        let succeeded = job_queue.submit(job)?;
+        let failed = job_queue.submit(job)?;
+        let cancelled = job_queue.submit(job)?;
+
+        assert_eq!(job_queue.status(&succeeded), Some(JobStatus::Queued));
+        assert_eq!(job_queue.status("../done/job"), None);
+
+        // claimed in order of submission
+        let job_path = job_queue.next()?.unwrap();
+
+        assert_eq!(job_path.file_stem().unwrap(), &*succeeded);
+
+        job_queue.start(&job_path);
+
+        assert_eq!(job_queue.status(&succeeded), Some(JobStatus::Running));
+
+        job_queue.complete(&job_path, Ok(()))?;
+
+        assert_eq!(job_queue.status(&succeeded), Some(JobStatus::Succeeded));
+        assert_eq!(job_queue.error(&succeeded), None);
+
+        let job_path = job_queue.next()?.unwrap();
+
+        assert_eq!(job_path.file_stem().unwrap(), &*failed);
+
+        job_queue.start(&job_path);
+        job_queue.complete(&job_path, Err(&io::Error::other("connection lost")))?;
+
+        assert_eq!(job_queue.status(&failed), Some(JobStatus::Failed));
+        assert_eq!(job_queue.error(&failed).as_deref(), Some("connection lost"));
+
+        assert_eq!(job_queue.cancel(&cancelled)?, Some(JobStatus::Queued));
+        assert_eq!(job_queue.status(&cancelled), Some(JobStatus::Cancelled));
+        assert_eq!(job_queue.cancel(&cancelled)?, Some(JobStatus::Cancelled));
+
+        assert_eq!(job_queue.next()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unattended_import_options() {
+        let job_queue = job_queue("options");
+
+        assert!(matches!(
+            job_queue
+                .submit(br#"{"import_profile": "profile.json", "options": ["--interactive"]}"#),
+            Err(JobError::Interactive)
+        ));
+        assert!(matches!(
+            job_queue.submit(br#"{"import_profile": "profile.json", "options": ["--unknown"]}"#),
+            Err(JobError::ParseImportOptions(_))
+        ));
+
+        assert_eq!(job_queue.next().unwrap(), None);
+    }
+}
@@ -0,0 +1,553 @@
+use crate::data_source::string_map::StringMap;
+use crate::data_source::{
+    DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, ReadRecordError,
+};
+use crate::data_source_file::DataSourceFile;
+use crate::import_profile::Field;
+use futures::Stream;
+use indexmap::IndexSet;
+use memchr::memchr;
+use rustc_hash::FxBuildHasher as BuildHasher;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+
+/// Layout of a JSON data source file.
+#[derive(Debug, Copy, Clone, Default, JsonSchema, Deserialize)]
+pub enum JsonFormat {
+    /// A single JSON document, read into memory before any record is read
+    #[default]
+    Document,
+    /// A JSON document on each line, read a line at a time
+    Lines,
+}
+
+#[derive(Debug, Error)]
+pub enum CreateJsonDataSourceError {
+    #[error("could not open data source file: {0}")]
+    OpenFileError(PathBuf, #[source] std::io::Error),
+    #[error("could not deserialize data source file: {0}")]
+    DeserializeError(PathBuf, #[source] serde_json::Error),
+    #[error("invalid selector: {0}")]
+    InvalidSelector(String),
+    #[error("invalid fields")]
+    InvalidFields,
+    #[error("could not count records of data source file: {0}")]
+    CountRecordsError(PathBuf, #[source] JsonReadRecordError),
+}
+
+#[derive(Debug, Error)]
+#[error("error reading JSON record ({index})")]
+pub struct JsonReadRecordError {
+    index: DataSourceErrorIndex,
+    #[source]
+    source: JsonReadRecordErrorKind,
+}
+
+#[derive(Debug, Error)]
+enum JsonReadRecordErrorKind {
+    #[error("could not read line")]
+    ReadLine(#[from] std::io::Error),
+    #[error("could not deserialize JSON")]
+    DeserializeJson(#[from] serde_json::Error),
+    #[error("selector '{0}' does not match an array")]
+    SelectorMismatch(String),
+    #[error("expected an object, found: {0}")]
+    NotAnObject(String),
+}
+
+impl ReadRecordError for JsonReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+/// JSON data source, reading a record from each object of the top level array, or the nested array
+/// matched by the selector, of a document or of each line.
+#[derive(Debug)]
+pub struct JsonDataSource<R> {
+    /// Reader of the lines of a [`JsonFormat::Lines`] data source file, or `None` once the document
+    /// of a [`JsonFormat::Document`] data source file is read
+    buf_reader: Option<BufReader<R>>,
+    line: Vec<u8>,
+    selector_parts: Vec<Box<str>>,
+    fields: IndexSet<Arc<str>, BuildHasher>,
+    record_number: u64,
+    line_number: u64,
+    byte_offset: u64,
+    /// Name of the field to hold the raw payload of each record, the record serialized as JSON
+    raw_payload_field: Option<Arc<str>>,
+    /// Records read but not yet returned, with the line and byte range of the document of each
+    pending_records: VecDeque<(Value, u64, u64, u64)>,
+}
+
+impl JsonDataSource<DataSourceFile> {
+    pub async fn new<'fields>(
+        path: impl AsRef<Path>,
+        fields: impl IntoIterator<Item = &'fields Field>,
+        format: JsonFormat,
+        selector: Option<&str>,
+        raw_payload_field: Option<&str>,
+    ) -> Result<Self, CreateJsonDataSourceError> {
+        let selector_parts = selector_parts(selector);
+
+        let fields: IndexSet<Arc<str>, BuildHasher> = fields
+            .into_iter()
+            .filter_map(|field| {
+                let field = field.name();
+
+                if !field.is_empty() {
+                    Some(Arc::from(field))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        if fields.is_empty() {
+            return Err(CreateJsonDataSourceError::InvalidFields);
+        }
+
+        let mut json_data_source = JsonDataSource {
+            buf_reader: None,
+            line: Vec::new(),
+            selector_parts,
+            fields,
+            record_number: 0,
+            line_number: 0,
+            byte_offset: 0,
+            raw_payload_field: raw_payload_field.map(Arc::from),
+            pending_records: VecDeque::new(),
+        };
+
+        match format {
+            JsonFormat::Document => {
+                let document = read_document(&path).await.map_err(|err| {
+                    CreateJsonDataSourceError::OpenFileError(path.as_ref().to_owned(), err)
+                })?;
+
+                let document = serde_json::from_slice::<Value>(&document).map_err(|err| {
+                    CreateJsonDataSourceError::DeserializeError(path.as_ref().to_owned(), err)
+                })?;
+
+                let records =
+                    select(document, &json_data_source.selector_parts).ok_or_else(|| {
+                        CreateJsonDataSourceError::InvalidSelector(
+                            selector.unwrap_or("").to_owned(),
+                        )
+                    })?;
+
+                // a document is not read a line at a time, so the line of each record index is
+                // always zero
+                json_data_source.pending_records = records
+                    .into_iter()
+                    .map(|record| (record, 0, 0, 0))
+                    .collect();
+            }
+            JsonFormat::Lines => {
+                let file = DataSourceFile::open(&path).await.map_err(|err| {
+                    CreateJsonDataSourceError::OpenFileError(path.as_ref().to_owned(), err)
+                })?;
+
+                json_data_source.buf_reader = Some(BufReader::new(file));
+            }
+        }
+
+        Ok(json_data_source)
+    }
+
+    /// Counts the records of the data source file, without collecting the fields of each record.
+    /// Lines are only deserialized if a selector is set.
+    pub async fn count_records(
+        path: impl AsRef<Path>,
+        format: JsonFormat,
+        selector: Option<&str>,
+    ) -> Result<u64, CreateJsonDataSourceError> {
+        let selector_parts = selector_parts(selector);
+
+        match format {
+            JsonFormat::Document => {
+                let document = read_document(&path).await.map_err(|err| {
+                    CreateJsonDataSourceError::OpenFileError(path.as_ref().to_owned(), err)
+                })?;
+
+                let document = serde_json::from_slice::<Value>(&document).map_err(|err| {
+                    CreateJsonDataSourceError::DeserializeError(path.as_ref().to_owned(), err)
+                })?;
+
+                select(document, &selector_parts)
+                    .map(|records| records.len() as u64)
+                    .ok_or_else(|| {
+                        CreateJsonDataSourceError::InvalidSelector(
+                            selector.unwrap_or("").to_owned(),
+                        )
+                    })
+            }
+            JsonFormat::Lines => {
+                let file = DataSourceFile::open(&path).await.map_err(|err| {
+                    CreateJsonDataSourceError::OpenFileError(path.as_ref().to_owned(), err)
+                })?;
+
+                let mut lines = BufReader::new(file).lines();
+                let mut line_number = 0;
+                let mut records = 0u64;
+
+                loop {
+                    line_number += 1;
+
+                    let count_error = |source: JsonReadRecordErrorKind| {
+                        CreateJsonDataSourceError::CountRecordsError(
+                            path.as_ref().to_owned(),
+                            JsonReadRecordError {
+                                index: DataSourceErrorIndex {
+                                    record_number: None,
+                                    line_number,
+                                },
+                                source,
+                            },
+                        )
+                    };
+
+                    let Some(line) = lines
+                        .next_line()
+                        .await
+                        .map_err(|err| count_error(err.into()))?
+                    else {
+                        break;
+                    };
+
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    if selector_parts.is_empty() {
+                        records += 1;
+                    } else {
+                        let document = serde_json::from_str::<Value>(&line)
+                            .map_err(|err| count_error(err.into()))?;
+
+                        records += select(document, &selector_parts)
+                            .ok_or_else(|| {
+                                count_error(JsonReadRecordErrorKind::SelectorMismatch(
+                                    selector.unwrap_or("").to_owned(),
+                                ))
+                            })?
+                            .len() as u64;
+                    }
+                }
+
+                Ok(records)
+            }
+        }
+    }
+}
+
+/// Reads the whole data source file into memory, decompressing it if compressed.
+async fn read_document(path: impl AsRef<Path>) -> std::io::Result<Vec<u8>> {
+    let mut document = Vec::new();
+
+    DataSourceFile::open(path)
+        .await?
+        .read_to_end(&mut document)
+        .await?;
+
+    Ok(document)
+}
+
+impl<R: AsyncRead + Unpin> JsonDataSource<R> {
+    /// Reads the next line into the line buffer, returning `false` once the end of the file is
+    /// reached.
+    fn poll_read_line(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, std::io::Error>> {
+        let Some(buf_reader) = &mut self.buf_reader else {
+            return Poll::Ready(Ok(false));
+        };
+
+        self.line.clear();
+
+        loop {
+            let available = ready!(Pin::new(&mut *buf_reader).poll_fill_buf(cx))?;
+
+            if available.is_empty() {
+                return Poll::Ready(Ok(!self.line.is_empty()));
+            }
+
+            match memchr(b'\n', available) {
+                Some(i) => {
+                    self.line.extend_from_slice(&available[..=i]);
+                    Pin::new(&mut *buf_reader).consume(i + 1);
+
+                    return Poll::Ready(Ok(true));
+                }
+                None => {
+                    let consumed = available.len();
+
+                    self.line.extend_from_slice(available);
+                    Pin::new(&mut *buf_reader).consume(consumed);
+                }
+            }
+        }
+    }
+
+    /// Reads the records of the document on the line into the pending records.
+    fn read_line_records(&mut self) -> Result<(), JsonReadRecordErrorKind> {
+        let byte_start = self.byte_offset;
+
+        self.line_number += 1;
+        self.byte_offset += self.line.len() as u64;
+
+        if self.line.trim_ascii().is_empty() {
+            return Ok(());
+        }
+
+        let document = serde_json::from_slice::<Value>(&self.line)?;
+
+        let records = if self.selector_parts.is_empty() {
+            vec![document]
+        } else {
+            select(document, &self.selector_parts).ok_or_else(|| {
+                JsonReadRecordErrorKind::SelectorMismatch(self.selector_parts.join("/"))
+            })?
+        };
+
+        let (line_number, byte_end) = (self.line_number, self.byte_offset);
+
+        self.pending_records.extend(
+            records
+                .into_iter()
+                .map(|record| (record, line_number, byte_start, byte_end)),
+        );
+
+        Ok(())
+    }
+
+    fn read_record(
+        &self,
+        record: Value,
+        index: DataSourceRecordIndex,
+    ) -> Result<DataSourceRecord, JsonReadRecordErrorKind> {
+        let Value::Object(object) = record else {
+            return Err(JsonReadRecordErrorKind::NotAnObject(record.to_string()));
+        };
+
+        let mut fields: StringMap<Arc<str>> = self
+            .fields
+            .iter()
+            .filter_map(|field| Some((field.as_ref(), field_value(object.get(field.as_ref())?)?)))
+            .collect();
+
+        if let Some(raw_payload_field) = &self.raw_payload_field {
+            fields.insert(
+                raw_payload_field.clone(),
+                &Value::Object(object).to_string(),
+            );
+        }
+
+        Ok(DataSourceRecord::new(fields, index))
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for JsonDataSource<R> {
+    type Item = Result<DataSourceRecord, JsonReadRecordError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some((record, line_number, byte_start, byte_end)) =
+                self.pending_records.pop_front()
+            {
+                self.record_number += 1;
+
+                let record_number =
+                    NonZero::new(self.record_number).expect("Record numbers start at one");
+
+                let result = self.read_record(
+                    record,
+                    DataSourceRecordIndex {
+                        record_number,
+                        line_start: line_number,
+                        line_end: line_number,
+                        byte_start,
+                        byte_end,
+                    },
+                );
+
+                return Poll::Ready(Some(result.map_err(|source| JsonReadRecordError {
+                    index: DataSourceErrorIndex {
+                        record_number: Some(record_number),
+                        line_number,
+                    },
+                    source,
+                })));
+            }
+
+            let line_error = |line_number, source| JsonReadRecordError {
+                index: DataSourceErrorIndex {
+                    record_number: None,
+                    line_number,
+                },
+                source,
+            };
+
+            match ready!(self.poll_read_line(cx)) {
+                Ok(true) => {
+                    if let Err(err) = self.read_line_records() {
+                        return Poll::Ready(Some(Err(line_error(self.line_number, err))));
+                    }
+                }
+                Ok(false) => return Poll::Ready(None),
+                Err(err) => {
+                    return Poll::Ready(Some(Err(line_error(self.line_number + 1, err.into()))));
+                }
+            }
+        }
+    }
+}
+
+fn selector_parts(selector: Option<&str>) -> Vec<Box<str>> {
+    selector
+        .unwrap_or_default()
+        .split('/')
+        .map(str::trim)
+        .filter(|selector_part| !selector_part.is_empty())
+        .map(Box::from)
+        .collect()
+}
+
+/// Elements of the array matched by the selector, each part of which is the key of a nested
+/// object, or of the document itself if the selector is empty.
+fn select(document: Value, selector_parts: &[Box<str>]) -> Option<Vec<Value>> {
+    let mut value = document;
+
+    for selector_part in selector_parts {
+        value = match value {
+            Value::Object(mut object) => object.remove(selector_part.as_ref())?,
+            _ => return None,
+        };
+    }
+
+    match value {
+        Value::Array(records) => Some(records),
+        _ => None,
+    }
+}
+
+/// Value of a field read from the value of a JSON object, `None` if the value is `null`. Strings
+/// are read without quotes, and any other value as its JSON text.
+pub(crate) fn field_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    fn fields(names: &[&str]) -> Vec<Field> {
+        serde_json::from_value(Value::Array(
+            names
+                .iter()
+                .map(|name| serde_json::json!({ "name": name }))
+                .collect(),
+        ))
+        .unwrap()
+    }
+
+    fn json_data_source(
+        content: &str,
+        fields: &[Field],
+        selector: Option<&str>,
+    ) -> JsonDataSource<&[u8]> {
+        JsonDataSource {
+            buf_reader: Some(BufReader::new(content.as_bytes())),
+            line: Vec::new(),
+            selector_parts: selector_parts(selector),
+            fields: fields.iter().map(|field| Arc::from(field.name())).collect(),
+            record_number: 0,
+            line_number: 0,
+            byte_offset: 0,
+            raw_payload_field: None,
+            pending_records: VecDeque::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_json_lines_records() {
+        let fields = fields(&["Code", "Rate", "Tags"]);
+        let content = concat!(
+            r#"{ "Code": "GBP", "Rate": 1.25, "Tags": ["a"] }"#,
+            "\r\n\n",
+            r#"{ "Code": "EUR", "Rate": null }"#,
+            "\n",
+        );
+
+        let records = json_data_source(content, &fields, None)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get("Code"), Some("GBP"));
+        assert_eq!(records[0].get("Rate"), Some("1.25"));
+        assert_eq!(records[0].get("Tags"), Some(r#"["a"]"#));
+        assert_eq!(records[1].get("Rate"), None);
+        assert_eq!(records[1].index().line_start, 3);
+        assert_eq!(
+            (records[1].index().byte_start, records[1].index().byte_end),
+            (49, content.len() as u64)
+        );
+    }
+
+    #[tokio::test]
+    async fn read_json_lines_nested_records() {
+        let fields = fields(&["LineNo"]);
+        let content = concat!(
+            r#"{ "Order": { "Lines": [{ "LineNo": 1 }, { "LineNo": 2 }] } }"#,
+            "\n",
+            r#"{ "Order": { "Lines": 3 } }"#,
+            "\n",
+        );
+
+        let mut json_data_source = json_data_source(content, &fields, Some("Order/Lines"));
+
+        let first = json_data_source.try_next().await.unwrap().unwrap();
+        let second = json_data_source.try_next().await.unwrap().unwrap();
+
+        assert_eq!(first.get("LineNo"), Some("1"));
+        assert_eq!(second.get("LineNo"), Some("2"));
+        assert_eq!(second.index().record_number.get(), 2);
+
+        let err = json_data_source.try_next().await.unwrap_err();
+
+        assert_eq!(err.index().line_number, 2);
+        assert!(matches!(
+            err.source,
+            JsonReadRecordErrorKind::SelectorMismatch(_)
+        ));
+    }
+
+    #[test]
+    fn select_nested_array() {
+        let document = serde_json::json!({ "Data": { "Rates": [{ "Code": "GBP" }] } });
+
+        assert_eq!(
+            select(document.clone(), &selector_parts(Some("Data/Rates"))),
+            Some(vec![serde_json::json!({ "Code": "GBP" })])
+        );
+        assert_eq!(
+            select(document.clone(), &selector_parts(Some("Data"))),
+            None
+        );
+        assert_eq!(select(document, &selector_parts(None)), None);
+    }
+}
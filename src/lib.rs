@@ -1,16 +1,55 @@
+pub mod avro_data_source;
+pub mod backend;
+pub mod blocking;
+pub mod bridge;
+pub mod checkpoint;
+pub mod cleanup;
 pub mod column_graph;
+pub mod constraints;
+#[cfg(feature = "cli")]
+pub mod control_api;
+pub mod data_profile;
 pub mod data_source;
+pub mod data_source_file;
+pub mod date_format;
+pub mod deduplication;
 pub mod delimited_data_source;
+pub mod describe;
+pub mod expression;
+pub mod guardrail;
+pub mod hierarchy;
 pub mod identifier;
 pub mod import_executor;
 pub mod import_options;
 pub mod import_profile;
+pub mod indexes;
 pub mod insert_processor;
+#[cfg(feature = "cli")]
+pub mod job_queue;
+pub mod json_data_source;
 pub mod log;
 pub mod merge_processor;
+pub mod number_format;
+pub mod object_storage;
+pub mod options_preset;
 mod preprocess;
 pub mod random_data_source;
+pub mod record_accounting;
+pub mod run_history;
+pub mod run_report;
+pub mod schema_report;
+pub mod sequence;
+pub mod server_summary;
+pub mod shared_scan;
+pub mod signing;
+pub mod snapshot;
+pub mod spatial;
+pub mod sql_data_source;
+pub mod statistics;
+pub mod stream_data_source;
 pub mod table_mapper;
+pub mod template;
 pub mod temporary_table;
 pub mod update_processor;
+pub mod watch;
 pub mod xml_data_source;
@@ -1,6 +1,513 @@
+use crate::data_source::DataSourceRecordIndex;
+use log::{Level, Log, Metadata, Record, log};
+use regex::Regex;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
 #[macro_export]
 macro_rules! trace_sql {
-    ($sql:expr) => {
-        trace!("\n{}", $sql.trim());
+    ($sql:expr) => {{
+        let sql = &$sql;
+        let sql: &str = sql.trim();
+
+        trace!("\n{}", sql);
+        $crate::log::dump_sql(module_path!(), sql);
+    }};
+}
+
+tokio::task_local! {
+    /// Log of the import run executed by the task.
+    static RUN_LOG: RunLog;
+}
+
+/// Log of the import runs through an [`crate::import_executor::ImportHandle`], holding the
+/// redactions, SQL dump and preprocess log of the last run.
+#[derive(Debug, Clone, Default)]
+pub struct RunLog(Arc<RunLogState>);
+
+#[derive(Debug, Default)]
+struct RunLogState {
+    redactions: RwLock<Redactions>,
+    sql_dump: Mutex<Option<SqlDump>>,
+    preprocess_log: Mutex<Option<PreprocessLog>>,
+}
+
+/// Directory each statement traced by [`trace_sql!`] is written to, the statements written so far,
+/// and the identifier of the run, set for the duration of a run with `--dump-sql`.
+#[derive(Debug)]
+struct SqlDump {
+    directory: PathBuf,
+    statements: usize,
+    run_id: String,
+}
+
+/// Directory the log output of preprocess scripts is written to, the table mapper executing, and
+/// the log file of each table mapper written so far.
+#[derive(Debug)]
+struct PreprocessLog {
+    directory: PathBuf,
+    table_mapper: Option<String>,
+    files: Vec<(String, BufWriter<File>)>,
+}
+
+impl RunLog {
+    /// Executes the future as the run of the log, the secrets and patterns it redacts being
+    /// redacted from every message it logs, along with those of the process, and its statements
+    /// and preprocess script output written to the sinks started for it.
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        RUN_LOG.scope(self.clone(), future).await
+    }
+
+    /// Calls the function as the run of the log, such as to report the error of the run.
+    pub fn sync_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        RUN_LOG.sync_scope(self.clone(), f)
+    }
+
+    /// Discards the secrets and patterns redacted by the previous run, and stops its sinks.
+    pub fn reset(&self) {
+        *self.0.redactions.write().unwrap() = Redactions::default();
+
+        self.stop_sql_dump();
+        self.stop_preprocess_log();
+    }
+
+    /// Writes each statement traced by the run from now on into its own file of the directory,
+    /// numbered in the order the statements are executed, replacing the files of a previous dump.
+    pub fn start_sql_dump(&self, directory: &Path, run_id: &str) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|extension| extension == "sql") {
+                fs::remove_file(path)?;
+            }
+        }
+
+        *self.0.sql_dump.lock().unwrap() = Some(SqlDump {
+            directory: directory.to_owned(),
+            statements: 0,
+            run_id: run_id.to_owned(),
+        });
+
+        Ok(())
+    }
+
+    pub fn stop_sql_dump(&self) {
+        *self.0.sql_dump.lock().unwrap() = None;
+    }
+
+    /// Writes the log output of preprocess scripts of the run from now on into a file of the
+    /// directory for each table mapper, such as `Countries.log`, rather than the main log, each
+    /// line attached with the index of the record being transformed.
+    pub fn start_preprocess_log(&self, directory: &Path) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+
+        *self.0.preprocess_log.lock().unwrap() = Some(PreprocessLog {
+            directory: directory.to_owned(),
+            table_mapper: None,
+            files: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    pub fn stop_preprocess_log(&self) {
+        if let Some(mut preprocess_log) = self.0.preprocess_log.lock().unwrap().take() {
+            for (_, file) in &mut preprocess_log.files {
+                if let Err(err) = file.flush() {
+                    ::log::warn!("Could not flush preprocess log: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Writes the statement into the dump directory of the run of the task, if any, named after the
+/// module which generated it, such as `0003_merge_processor.sql`.
+#[doc(hidden)]
+pub fn dump_sql(module_path: &str, statement: &str) {
+    let Ok(run_log) = RUN_LOG.try_with(RunLog::clone) else {
+        return;
+    };
+
+    let mut sql_dump = run_log.0.sql_dump.lock().unwrap();
+
+    let Some(sql_dump) = sql_dump.as_mut() else {
+        return;
+    };
+
+    sql_dump.statements += 1;
+
+    let module = module_path.rsplit("::").next().unwrap_or(module_path);
+    let path = sql_dump
+        .directory
+        .join(format!("{:04}_{}.sql", sql_dump.statements, module));
+
+    let statement = redact(statement).replace(sql_dump.run_id.as_str(), "$run_id");
+
+    if let Err(err) = fs::write(&path, statement + "\n") {
+        ::log::warn!("Could not dump statement to {}: {}", path.display(), err);
+    }
+}
+
+thread_local! {
+    /// Index of the record being transformed by a preprocess function on this thread.
+    static PREPROCESS_RECORD: Cell<Option<DataSourceRecordIndex>> = const { Cell::new(None) };
+}
+
+/// Sets the table mapper of the run of the task whose log file preprocess script log output is
+/// written to.
+pub fn set_preprocess_log_table_mapper(table_mapper: &str) {
+    let Ok(run_log) = RUN_LOG.try_with(RunLog::clone) else {
+        return;
+    };
+
+    if let Some(preprocess_log) = run_log.0.preprocess_log.lock().unwrap().as_mut() {
+        preprocess_log.table_mapper = Some(table_mapper.to_owned());
+    }
+}
+
+/// Calls the function, attaching the index of the record to the preprocess script log output it
+/// writes.
+pub(crate) fn with_preprocess_record<T>(index: DataSourceRecordIndex, f: impl FnOnce() -> T) -> T {
+    let previous = PREPROCESS_RECORD.replace(Some(index));
+    let result = f();
+
+    PREPROCESS_RECORD.set(previous);
+
+    result
+}
+
+/// Logs the message of a preprocess script, into the log file of the table mapper if the
+/// preprocess log of the run of the task was started, or else into the main log.
+pub(crate) fn preprocess_log(level: Level, target: &str, message: &str) {
+    let Ok(run_log) = RUN_LOG.try_with(RunLog::clone) else {
+        log!(target: target, level, "{}", message);
+        return;
     };
+
+    let mut preprocess_log = run_log.0.preprocess_log.lock().unwrap();
+
+    let (Some(preprocess_log), Some(index)) = (preprocess_log.as_mut(), PREPROCESS_RECORD.get())
+    else {
+        log!(target: target, level, "{}", message);
+        return;
+    };
+
+    let Some(table_mapper) = preprocess_log.table_mapper.as_deref() else {
+        log!(target: target, level, "{}", message);
+        return;
+    };
+
+    let position = match preprocess_log
+        .files
+        .iter()
+        .position(|(name, _)| name == table_mapper)
+    {
+        Some(position) => position,
+        None => {
+            let file_name: String = table_mapper
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                    _ => '_',
+                })
+                .collect();
+            let path = preprocess_log.directory.join(format!("{}.log", file_name));
+
+            match File::create(&path) {
+                Ok(file) => {
+                    preprocess_log
+                        .files
+                        .push((table_mapper.to_owned(), BufWriter::new(file)));
+                    preprocess_log.files.len() - 1
+                }
+                Err(err) => {
+                    ::log::warn!(
+                        "Could not create preprocess log {}: {}",
+                        path.display(),
+                        err
+                    );
+                    log!(target: target, level, "{}", message);
+                    return;
+                }
+            }
+        }
+    };
+
+    let (_, file) = &mut preprocess_log.files[position];
+
+    if let Err(err) = writeln!(
+        file,
+        "{:<5} {} [{}] {}",
+        level,
+        target,
+        index,
+        redact(message)
+    ) {
+        ::log::warn!("Could not write preprocess log: {}", err);
+    }
+}
+
+/// Replacement of each redacted secret and pattern match.
+const REDACTED: &str = "***";
+
+/// Secrets of the process, such as the password of the connection string it connects with, and
+/// those redacted outside of an import run.
+static PROCESS_REDACTIONS: RwLock<Redactions> = RwLock::new(Redactions {
+    secrets: Vec::new(),
+    patterns: Vec::new(),
+});
+
+#[derive(Debug, Default)]
+struct Redactions {
+    secrets: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+/// Redactions of the run of the task, or of the process outside of an import run.
+fn with_redactions<T>(f: impl FnOnce(&mut Redactions) -> T) -> T {
+    match RUN_LOG.try_with(RunLog::clone) {
+        Ok(run_log) => {
+            let mut redactions = run_log.0.redactions.write().unwrap();
+
+            f(&mut redactions)
+        }
+        Err(_) => f(&mut PROCESS_REDACTIONS.write().unwrap()),
+    }
+}
+
+impl Redactions {
+    fn redact<'a>(&self, mut message: Cow<'a, str>) -> Cow<'a, str> {
+        for secret in &self.secrets {
+            if message.contains(secret.as_str()) {
+                message = Cow::Owned(message.replace(secret.as_str(), REDACTED));
+            }
+        }
+
+        for pattern in &self.patterns {
+            if let Cow::Owned(redacted) = pattern.replace_all(&message, REDACTED) {
+                message = Cow::Owned(redacted);
+            }
+        }
+
+        message
+    }
+}
+
+/// Redacts the secret wherever it occurs from now on, until the end of the import run.
+pub fn redact_secret(secret: &str) {
+    with_redactions(|redactions| {
+        if !secret.is_empty() && !redactions.secrets.iter().any(|existing| existing == secret) {
+            redactions.secrets.push(secret.to_owned());
+        }
+    });
+}
+
+/// Redacts each match of the pattern from now on, until the end of the import run.
+pub fn redact_pattern(pattern: &Regex) {
+    with_redactions(|redactions| {
+        if !redactions
+            .patterns
+            .iter()
+            .any(|existing| existing.as_str() == pattern.as_str())
+        {
+            redactions.patterns.push(pattern.clone());
+        }
+    });
+}
+
+/// Redacts the password of the ADO connection string from now on, such as the connection string
+/// itself, or the password alone, is never logged verbatim.
+pub fn redact_connection_string(connection_string: &str) {
+    let mut rest = connection_string;
+
+    while let Some((key, value)) = rest.split_once('=') {
+        let value = value.trim_start();
+        // values may be quoted by braces or quotes, such that they contain semicolons
+        let (secret, remainder) = match value.chars().next() {
+            Some(open @ ('{' | '\'' | '"')) => {
+                let close = if open == '{' { '}' } else { open };
+
+                match value[1..].find(close) {
+                    Some(end) => (&value[1..=end], &value[end + 2..]),
+                    None => (&value[1..], ""),
+                }
+            }
+            _ => value.split_once(';').unwrap_or((value, "")),
+        };
+
+        if matches!(key.trim().to_ascii_lowercase().as_str(), "password" | "pwd") {
+            redact_secret(secret.trim());
+        }
+
+        rest = remainder.trim_start_matches([';', ' ']);
+    }
+}
+
+/// Redacts the secrets and pattern matches of the process, and of the run of the task, registered
+/// so far from the message.
+pub fn redact(message: &str) -> Cow<'_, str> {
+    let message = PROCESS_REDACTIONS
+        .read()
+        .unwrap()
+        .redact(Cow::Borrowed(message));
+
+    match RUN_LOG.try_with(RunLog::clone) {
+        Ok(run_log) => {
+            let redactions = run_log.0.redactions.read().unwrap();
+
+            redactions.redact(message)
+        }
+        Err(_) => message,
+    }
+}
+
+/// Logger redacting the registered secrets and patterns from each message before it is logged by
+/// the inner logger.
+#[derive(Debug)]
+pub struct RedactingLogger<L>(pub L);
+
+impl<L: Log> Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+
+        match redact(&message) {
+            Cow::Borrowed(_) => self.0.log(record),
+            Cow::Owned(message) => self.0.log(
+                &Record::builder()
+                    .metadata(record.metadata().clone())
+                    .args(format_args!("{}", message))
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::trace;
+
+    #[test]
+    fn dump_traced_statements() -> io::Result<()> {
+        let directory = std::env::temp_dir().join(format!("sql_dump_{}", std::process::id()));
+        let run_log = RunLog::default();
+
+        run_log.start_sql_dump(&directory, "c0ffee00-0000-4000-8000-000000000000")?;
+
+        run_log.sync_scope(|| {
+            trace_sql!("\n  SELECT N'c0ffee00-0000-4000-8000-000000000000'\n");
+        });
+
+        // statements of other runs, or outside of a run, are not dumped with those of the run
+        RunLog::default().sync_scope(|| trace_sql!("SELECT 1"));
+        trace_sql!("SELECT 1");
+
+        run_log.sync_scope(|| {
+            trace_sql!("DROP TABLE [import].[Country]");
+        });
+
+        run_log.stop_sql_dump();
+        run_log.sync_scope(|| trace_sql!("SELECT 2"));
+
+        assert_eq!(
+            fs::read_to_string(directory.join("0001_tests.sql"))?,
+            "SELECT N'$run_id'\n"
+        );
+        assert_eq!(
+            fs::read_to_string(directory.join("0002_tests.sql"))?,
+            "DROP TABLE [import].[Country]\n"
+        );
+        assert!(!directory.join("0003_tests.sql").exists());
+
+        fs::remove_dir_all(directory)
+    }
+
+    #[test]
+    fn redact_secrets_and_patterns() {
+        let message = "Login failed with password Redact;Me1, for IBAN GB82WEST12345698765432";
+        let run_log = RunLog::default();
+
+        run_log.sync_scope(|| {
+            redact_connection_string(
+                "server=tcp:localhost,1433;user=sa;password={Redact;Me1};TrustServerCertificate=true",
+            );
+            redact_pattern(&Regex::new(r"IBAN [A-Z]{2}[0-9]{2}[A-Z0-9]+").unwrap());
+
+            assert_eq!(redact(message), "Login failed with password ***, for ***");
+            assert!(matches!(redact("Login succeeded"), Cow::Borrowed(_)));
+        });
+
+        // the redactions of a run are neither those of another run nor of its next run
+        assert_eq!(redact(message), message);
+        assert_eq!(RunLog::default().sync_scope(|| redact(message)), message);
+
+        run_log.reset();
+
+        assert_eq!(run_log.sync_scope(|| redact(message)), message);
+    }
+
+    #[test]
+    fn route_preprocess_log_to_table_mapper() -> io::Result<()> {
+        let directory = std::env::temp_dir().join(format!("preprocess_log_{}", std::process::id()));
+        let index = DataSourceRecordIndex {
+            record_number: std::num::NonZero::new(2).unwrap(),
+            line_start: 3,
+            line_end: 3,
+            byte_start: 20,
+            byte_end: 31,
+        };
+
+        let run_log = RunLog::default();
+
+        run_log.start_preprocess_log(&directory)?;
+
+        run_log.sync_scope(|| {
+            set_preprocess_log_table_mapper("Country Codes");
+
+            with_preprocess_record(index, || {
+                preprocess_log(Level::Warn, "preprocess::lua", "unknown region\tEU")
+            });
+            preprocess_log(Level::Info, "preprocess::lua", "loaded");
+        });
+
+        // the output of other runs is not written to the preprocess log of the run
+        RunLog::default().sync_scope(|| {
+            with_preprocess_record(index, || {
+                preprocess_log(Level::Warn, "preprocess::lua", "other run")
+            })
+        });
+
+        run_log.stop_preprocess_log();
+
+        assert_eq!(
+            fs::read_to_string(directory.join("Country_Codes.log"))?,
+            "WARN  preprocess::lua [record: 2, line: 3 - 3, bytes: 20 - 31] unknown region\tEU\n"
+        );
+
+        fs::remove_dir_all(directory)
+    }
 }
@@ -1,55 +1,660 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use color_eyre::Report;
-use log::LevelFilter;
+use color_eyre::eyre::{WrapErr, eyre};
+use itertools::Itertools;
+use log::{LevelFilter, error, info};
 use simplelog::{ColorChoice, TermLogger, TerminalMode};
-use sql_bulk_import_profile::import_executor;
+use sql_bulk_import_profile::describe::DescribeFormat;
+use sql_bulk_import_profile::import_executor::ImportHandle;
 use sql_bulk_import_profile::import_options::ImportOptions;
 use sql_bulk_import_profile::import_profile::ImportProfile;
+use sql_bulk_import_profile::job_queue::JobQueue;
+use sql_bulk_import_profile::log::RedactingLogger;
+use sql_bulk_import_profile::merge_processor::MergePlan;
+use sql_bulk_import_profile::options_preset::OptionsPresets;
+use sql_bulk_import_profile::schema_report::SchemaIncompatibility;
+use sql_bulk_import_profile::table_mapper::{DeleteMode, Table, TableMapper, TableMapperColumn};
+use sql_bulk_import_profile::watch::WatchDirectory;
+use sql_bulk_import_profile::{
+    cleanup, control_api, data_profile, describe, import_executor, signing, temporary_table,
+};
+use std::error::Error;
 use std::fs::File;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 use tiberius::{Client, Config};
-use tokio::net::TcpStream;
-use tokio_util::compat::TokioAsyncWriteCompatExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
 
 #[tokio::main]
 async fn main() -> Result<(), Report> {
     color_eyre::install()?;
 
-    let args = Args::parse();
+    let mut args = Args::parse();
 
-    TermLogger::init(
-        args.log_level.into(),
+    if let Some(options_preset) = &args.options_preset {
+        let options_presets = OptionsPresets::new(File::open(&args.options_presets)?)?;
+
+        args = Args::parse_from(options_presets.expand(options_preset, std::env::args_os())?);
+    }
+
+    let level_filter = args.log_level.clone().into();
+
+    log::set_boxed_logger(Box::new(RedactingLogger(TermLogger::new(
+        level_filter,
         simplelog::Config::default(),
         TerminalMode::Mixed,
         ColorChoice::Auto,
-    )?;
+    ))))?;
+    log::set_max_level(level_filter);
 
-    let config = Config::from_ado_string(&args.connection_string)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
+    if let Some(Command::Describe {
+        import_profile,
+        format,
+    }) = args.command
+    {
+        let import_profile_file = File::open(&import_profile)?;
+        let import_profile: ImportProfile = ImportProfile::new(import_profile_file).await?;
 
-    tcp.set_nodelay(true)?;
+        print!("{}", describe::describe(&import_profile, format));
+
+        return Ok(());
+    }
+
+    if let Some(Command::ProfileData {
+        import_profile,
+        sample_records,
+    }) = args.command
+    {
+        let import_profile_file = File::open(&import_profile)?;
+        let import_profile: ImportProfile = ImportProfile::new(import_profile_file).await?;
+
+        for field_group_profile in
+            data_profile::profile(&import_profile, &args.options, sample_records).await?
+        {
+            println!("{}", field_group_profile);
+        }
+
+        return Ok(());
+    }
+
+    if let Some(Command::Validate {
+        connection_string,
+        import_profile,
+        sample_records,
+        against,
+    }) = args.command
+    {
+        let import_profile =
+            signing::open_import_profile(&import_profile, args.options.verifying_key.as_deref())
+                .await?;
+
+        if !against.is_empty() {
+            let mut incompatible_environments = 0;
+
+            for environment in &against {
+                let incompatibilities =
+                    schema_report(environment, &import_profile, &args.options).await;
+
+                match incompatibilities {
+                    Ok(incompatibilities) if incompatibilities.is_empty() => {
+                        println!("{}: compatible", environment);
+                    }
+                    Ok(incompatibilities) => {
+                        incompatible_environments += 1;
+
+                        for incompatibility in incompatibilities {
+                            println!(
+                                "{}: {}",
+                                environment,
+                                error_chain(&Report::new(incompatibility))
+                            );
+                        }
+                    }
+                    Err(err) => {
+                        incompatible_environments += 1;
+
+                        println!("{}: {}", environment, error_chain(&err));
+                    }
+                }
+            }
+
+            if incompatible_environments > 0 {
+                return Err(eyre!(
+                    "import profile is incompatible with {} of {} environments",
+                    incompatible_environments,
+                    against.len()
+                ));
+            }
+
+            return Ok(());
+        }
+
+        let connection_string = connection_string.expect(
+            "Connection string should be required without environments to validate against",
+        );
+
+        let mut client = connect(&connection_string).await?;
+
+        import_executor::validate(&mut client, import_profile, args.options, sample_records)
+            .await?;
+
+        println!("Import profile is valid");
+
+        return Ok(());
+    }
+
+    if let Some(Command::Cleanup {
+        connection_string,
+        retention_hours,
+        include_orphans,
+        dry_run,
+    }) = args.command
+    {
+        let mut client = connect(&connection_string).await?;
+
+        let stale_staging_tables =
+            cleanup::find_stale_staging_tables(&mut client, retention_hours, include_orphans)
+                .await?;
+
+        for stale_staging_table in &stale_staging_tables {
+            println!(
+                "{} ({} hours old)",
+                stale_staging_table.identifier(),
+                stale_staging_table.age_hours(),
+            );
+        }
+
+        if !dry_run {
+            cleanup::drop_stale_staging_tables(&mut client, &stale_staging_tables).await?;
+
+            println!(
+                "Dropped {} stale staging tables",
+                stale_staging_tables.len()
+            );
+        }
+
+        return Ok(());
+    }
+
+    if let Some(Command::Serve {
+        connection_string,
+        jobs_directory,
+        poll_interval_seconds,
+        api_address,
+        api_token,
+    }) = args.command
+    {
+        let job_queue = Arc::new(JobQueue::new(
+            jobs_directory,
+            args.options.verifying_key.clone(),
+        )?);
+
+        info!(
+            "Serving import jobs from {}",
+            job_queue.directory().display()
+        );
+
+        if let Some(api_address) = api_address {
+            let api_token = api_token.expect("API token should be required with an API address");
+            let listener = TcpListener::bind(api_address).await?;
+
+            info!("Serving control API on {}", api_address);
+
+            tokio::spawn(control_api::serve(listener, job_queue.clone(), api_token));
+        }
+
+        // polled between jobs, such that an interrupt during a job stops once the job completes
+        let interrupt = tokio::signal::ctrl_c();
+        tokio::pin!(interrupt);
+
+        // errors of the job queue directory are logged rather than stopping the service, such that
+        // a transient error only skips the job, or the poll, it occurred in
+        loop {
+            loop {
+                let job_path = match job_queue.next() {
+                    Ok(Some(job_path)) => job_path,
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!(
+                            "Could not read jobs from {}: {}",
+                            job_queue.directory().display(),
+                            err
+                        );
+
+                        break;
+                    }
+                };
+
+                let cancel = job_queue.start(&job_path);
+
+                let import_handle = ImportHandle::default();
+
+                // connect for each job, such that a dropped connection only fails a single job
+                let job = async {
+                    let mut client = connect(&connection_string).await?;
+
+                    Ok(job_queue
+                        .execute(&mut client, &job_path, &import_handle)
+                        .await?)
+                };
+
+                let result: Result<(), Report> = tokio::select! {
+                    result = job => result,
+                    _ = cancel.notified() => {
+                        if let Err(err) = job_queue.cancelled(&job_path) {
+                            error!(
+                                "Could not move cancelled job {}, skipping it: {}",
+                                job_path.display(),
+                                err
+                            );
+
+                            job_queue.skip(&job_path);
+                        }
+
+                        // the connection of the job was dropped with it, ending its session, so its
+                        // remaining temporary tables are dropped on another
+                        import_handle.abandoned_tables().end_session();
+
+                        if let Ok(mut client) = connect(&connection_string).await {
+                            temporary_table::drop_abandoned(
+                                &mut client,
+                                import_handle.abandoned_tables(),
+                            )
+                            .await;
+                        }
+
+                        continue;
+                    }
+                };
+
+                // the error of the job is described with the secrets of its run redacted
+                let completed = import_handle.run_log().sync_scope(|| {
+                    job_queue.complete(
+                        &job_path,
+                        result
+                            .as_ref()
+                            .map(|_| ())
+                            .map_err(|err| err.as_ref() as &(dyn Error + 'static)),
+                    )
+                });
+
+                if let Err(err) = completed {
+                    error!(
+                        "Could not move completed job {}, skipping it: {}",
+                        job_path.display(),
+                        err
+                    );
+
+                    job_queue.skip(&job_path);
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(poll_interval_seconds)) => {}
+                _ = &mut interrupt => break,
+            }
+        }
+
+        info!("Stopped serving import jobs");
 
-    let mut client = Client::connect(config, tcp.compat_write()).await?;
+        return Ok(());
+    }
+
+    if let Some(Command::Watch {
+        connection_string,
+        import_profile,
+        directory,
+        pattern,
+        archive_directory,
+        poll_interval_seconds,
+    }) = args.command
+    {
+        let mut watch_directory =
+            WatchDirectory::new(directory, pattern.as_deref(), archive_directory)?;
+
+        info!(
+            "Watching {} for data source files",
+            watch_directory.directory().display()
+        );
+
+        // polled between files, such that an interrupt during an import stops once it completes
+        let interrupt = tokio::signal::ctrl_c();
+        tokio::pin!(interrupt);
+
+        // errors of the watched directory are logged rather than stopping the watch, such that a
+        // transient error only skips the file, or the poll, it occurred in
+        loop {
+            let paths = watch_directory.poll().unwrap_or_else(|err| {
+                error!(
+                    "Could not read files from {}: {}",
+                    watch_directory.directory().display(),
+                    err
+                );
+
+                Vec::new()
+            });
+
+            for path in paths {
+                let import_options = ImportOptions {
+                    path_override: Some(path.clone()),
+                    ..args.options.clone()
+                };
+
+                let import_handle = import_handle(&import_options);
 
-    let import_profile_file = File::open(&args.import_profile)?;
-    let import_profile: ImportProfile = ImportProfile::new(import_profile_file).await?;
+                // connect for each file, such that a dropped connection only fails a single file
+                let import = async {
+                    let import_profile = signing::open_import_profile(
+                        &import_profile,
+                        import_options.verifying_key.as_deref(),
+                    )
+                    .await?;
+
+                    let mut client = connect(&connection_string).await?;
+
+                    info!("Importing {}", path.display());
+
+                    import_executor::import_executor(
+                        &mut client,
+                        import_profile,
+                        import_options.clone(),
+                        &import_handle,
+                    )
+                    .await?;
+
+                    Ok::<_, Report>(())
+                };
+
+                match import.await {
+                    Ok(()) => {
+                        if let Err(err) = watch_directory.imported(&path, import_options.deletion) {
+                            error!(
+                                "Could not remove imported file {}, skipping it: {}",
+                                path.display(),
+                                err
+                            );
+                        }
+                    }
+                    Err(err) => {
+                        import_handle.run_log().sync_scope(|| {
+                            error!("Import of {} failed: {:?}", path.display(), err)
+                        });
+
+                        watch_directory.failed(&path);
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(poll_interval_seconds)) => {}
+                _ = &mut interrupt => break,
+            }
+        }
 
-    import_executor::import_executor(&mut client, import_profile, args.options).await?;
+        info!("Stopped watching for data source files");
+
+        return Ok(());
+    }
+
+    let connection_string = args
+        .connection_string
+        .expect("Connection string should be required without a command");
+    let import_profile = args
+        .import_profile
+        .expect("Import profile should be required without a command");
+
+    let import_profile =
+        signing::open_import_profile(&import_profile, args.options.verifying_key.as_deref())
+            .await?;
+
+    let mut client = connect(&connection_string).await?;
+
+    let import_handle = import_handle(&args.options);
+
+    import_executor::import_executor(&mut client, import_profile, args.options, &import_handle)
+        .await?;
 
     Ok(())
 }
 
+/// Reports where the import profile is incompatible with the schema of the environment, connecting
+/// with the connection string of its `CONNECTION_STRING_` environment variable.
+async fn schema_report(
+    environment: &str,
+    import_profile: &ImportProfile,
+    import_options: &ImportOptions,
+) -> Result<Vec<SchemaIncompatibility>, Report> {
+    let variable = format!("CONNECTION_STRING_{}", environment.to_uppercase());
+    let connection_string = std::env::var(&variable)
+        .wrap_err_with(|| format!("environment variable {} could not be read", variable))?;
+
+    let mut client = connect(&connection_string).await?;
+
+    Ok(import_executor::schema_report(&mut client, import_profile, import_options).await?)
+}
+
+fn error_chain(err: &Report) -> String {
+    err.chain()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+/// Handle of an import from the command line, prompting on stdin to confirm the merge plan of each
+/// table mapper, and row count changes exceeding the guardrail, with `--interactive`.
+fn import_handle(import_options: &ImportOptions) -> ImportHandle {
+    let import_handle = ImportHandle::default();
+
+    if !import_options.interactive {
+        return import_handle;
+    }
+
+    import_handle
+        .with_confirm_merge(|table_mapper, plan| {
+            confirm_blocking(merge_plan_prompt(table_mapper, plan))
+        })
+        .with_confirm_row_count_change(|row_count_change| {
+            confirm_blocking(format!(
+                "Row count of {} would change by {:.1}% ({} to {} rows). Proceed? [y/N] ",
+                row_count_change.target_table,
+                row_count_change.change,
+                row_count_change.target_rows,
+                row_count_change.temporary_rows,
+            ))
+        })
+}
+
+/// Confirms the prompt on a blocking thread, such that the runtime is not blocked meanwhile.
+fn confirm_blocking(prompt: String) -> Pin<Box<dyn Future<Output = bool>>> {
+    Box::pin(async move {
+        tokio::task::spawn_blocking(move || confirm(&prompt))
+            .await
+            .unwrap_or(false)
+    })
+}
+
+/// Describes the merge plan of the table mapper, asking whether to apply it.
+fn merge_plan_prompt(table_mapper: &TableMapper, plan: &MergePlan) -> String {
+    let lookup_tables = table_mapper
+        .columns()
+        .filter_map(|column| match column {
+            TableMapperColumn::Lookup(lookup_column) => Some(Table::identifier(lookup_column)),
+            _ => None,
+        })
+        .unique()
+        .join(", ");
+
+    let mut prompt = format!(
+        "Table mapper {}:\n  target table:  {}\n",
+        table_mapper.name(),
+        table_mapper.identifier()
+    );
+
+    if !lookup_tables.is_empty() {
+        prompt.push_str(&format!("  lookup tables: {}\n", lookup_tables));
+    }
+
+    prompt.push_str(&format!("  inserts:       {}\n", plan.inserts));
+    prompt.push_str(&format!("  updates:       {}\n", plan.updates));
+
+    if table_mapper.delete_mode() == DeleteMode::Full {
+        prompt.push_str(&format!("  deletes:       {}\n", plan.unmatched));
+    }
+
+    prompt.push_str("Apply merge? [y/N] ");
+    prompt
+}
+
+/// Prints the prompt, then reads the confirmation from stdin, declining if it cannot be read.
+fn confirm(prompt: &str) -> bool {
+    print!("{}", prompt);
+
+    if std::io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+async fn connect(connection_string: &str) -> Result<Client<Compat<TcpStream>>, Report> {
+    sql_bulk_import_profile::log::redact_connection_string(connection_string);
+
+    let config = Config::from_ado_string(connection_string)?;
+    let tcp = TcpStream::connect(config.get_addr()).await?;
+
+    tcp.set_nodelay(true)?;
+
+    Ok(Client::connect(config, tcp.compat_write()).await?)
+}
+
 #[derive(Debug, Parser)]
-#[command(version)]
+#[command(version, subcommand_negates_reqs = true, args_override_self = true)]
 struct Args {
-    #[arg(short, long, env = "CONNECTION_STRING")]
-    connection_string: String,
-    #[arg(short, long)]
-    import_profile: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, long, env = "CONNECTION_STRING", required = true)]
+    connection_string: Option<String>,
+    #[arg(short, long, required = true)]
+    import_profile: Option<PathBuf>,
     #[command(flatten)]
     options: ImportOptions,
     #[arg(short, long, env = "LOG_LEVEL", default_value = "warn")]
     log_level: LevelFilterArg,
+    /// Apply the import options of this preset of the options presets file, preceding any options
+    /// given explicitly
+    #[arg(long, env = "OPTIONS_PRESET")]
+    options_preset: Option<String>,
+    /// JSON file of named presets of import options, each a list of command line arguments
+    #[arg(long, env = "OPTIONS_PRESETS", default_value = "options_presets.json")]
+    options_presets: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Describe the import profile as a human-readable document
+    Describe {
+        #[arg(short, long)]
+        import_profile: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = DescribeFormat::Markdown)]
+        format: DescribeFormat,
+    },
+    /// Validate the import profile against the target database without importing
+    Validate {
+        #[arg(
+            short,
+            long,
+            env = "CONNECTION_STRING",
+            required_unless_present = "against"
+        )]
+        connection_string: Option<String>,
+        #[arg(short, long)]
+        import_profile: PathBuf,
+        /// Convert the fields of this many records of the data source to the types of their
+        /// columns, reporting columns whose values would be NULL, or 0 to not read the data source
+        #[arg(long, default_value_t = 100)]
+        sample_records: usize,
+        /// Report where the import profile is incompatible with the schema of each of these
+        /// environments, such as `dev,uat,prod`, without reading the data source, connecting to
+        /// each with the connection string of its `CONNECTION_STRING_` environment variable, such
+        /// as `CONNECTION_STRING_PROD`
+        #[arg(long, value_delimiter = ',')]
+        against: Vec<String>,
+    },
+    /// Profile the fields of the data source, reporting statistics of the values of each field
+    /// and suggesting the type of its column
+    ProfileData {
+        #[arg(short, long)]
+        import_profile: PathBuf,
+        /// Profile this many records of each field group of the data source, or 0 to read every
+        /// record
+        #[arg(long, default_value_t = 10000)]
+        sample_records: usize,
+    },
+    /// Execute import jobs from a directory of job files sequentially, until interrupted
+    Serve {
+        #[arg(short, long, env = "CONNECTION_STRING")]
+        connection_string: String,
+        /// Directory of job files, each a JSON object with an `import_profile` path and optional
+        /// `options` command line arguments, moved into `done` or `failed` once executed
+        #[arg(short, long)]
+        jobs_directory: PathBuf,
+        /// Seconds to wait before checking the directory again once it is empty
+        #[arg(long, default_value_t = 10)]
+        poll_interval_seconds: u64,
+        /// Serve an HTTP API to submit, query and cancel jobs on this address, authenticated by
+        /// the API token
+        #[arg(long, requires = "api_token")]
+        api_address: Option<SocketAddr>,
+        /// Token requests to the HTTP API must bear in their `Authorization: Bearer` header
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        api_token: Option<String>,
+    },
+    /// Watch a directory for data source files, importing each with the import profile once it has
+    /// arrived, until interrupted
+    Watch {
+        #[arg(short, long, env = "CONNECTION_STRING")]
+        connection_string: String,
+        #[arg(short, long)]
+        import_profile: PathBuf,
+        /// Directory to watch for data source files
+        #[arg(short, long)]
+        directory: PathBuf,
+        /// Only import files whose name matches this pattern, where `*` matches any characters and
+        /// `?` any single character, such as `rates_*.csv`
+        #[arg(long)]
+        pattern: Option<String>,
+        /// Move imported files into this directory, unless deleted by the data source deletion
+        #[arg(long)]
+        archive_directory: Option<PathBuf>,
+        /// Seconds to wait before checking the directory again, a file having arrived once its
+        /// size is unchanged between two checks
+        #[arg(long, default_value_t = 10)]
+        poll_interval_seconds: u64,
+    },
+    /// Drop staging tables left in the [import] schema by runs which did not drop them
+    Cleanup {
+        #[arg(short, long, env = "CONNECTION_STRING")]
+        connection_string: String,
+        /// Only drop staging tables created at least this many hours ago
+        #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(i32).range(0..))]
+        retention_hours: i32,
+        /// Also drop orphans tables, which are otherwise retained for inspection
+        #[arg(long)]
+        include_orphans: bool,
+        /// List the stale staging tables without dropping them
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -1,118 +1,407 @@
-use crate::column_graph::IndexedColumnNode;
+use crate::backend::SqlBackend;
+use crate::column_graph::{ColumnGraph, ColumnNode, IndexedColumnNode};
+use crate::hierarchy;
 use crate::identifier::{ColumnIdentifier, Identifier, TableIdentifier};
-use crate::table_mapper::Column;
+use crate::table_mapper::{
+    Column, Concurrency, DeleteMode, History, KeyNormalization, MergeOrder, Operation,
+    SortDirection, SurrogateKey,
+};
+use crate::temporary_table::RESOLVED_COLUMN;
 use crate::trace_sql;
-use indoc::formatdoc;
+use indoc::{formatdoc, indoc};
 use log::trace;
 use rustc_hash::FxHashMap as HashMap;
 use thiserror::Error;
-use tiberius::{Client, ColumnFlag};
-use tokio::net::TcpStream;
-use tokio_util::compat::Compat;
+use tiberius::{ColumnFlag, ToSql};
+
+/// Rows of the target table inserted, updated and deleted by a merge.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MergeResult {
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+/// Rows of the temporary table merged into the target table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MergeRows {
+    /// Every row
+    All,
+    /// Rows of a level of the hierarchy, once the levels above are merged
+    HierarchyLevel(i64),
+    /// Rows of which a lookup resolving to NULL when merged was since resolved, as marked in
+    /// [`RESOLVED_COLUMN`] by the deferred lookup resolution
+    Resolved,
+}
 
 #[derive(Debug, Error)]
 pub enum MergeProcessorError {
     #[error("could not find column target for key column: {0}")]
     KeyColumnUnknownTargetColumn(ColumnIdentifier),
+    #[error("could not find temporary table column for merge order column: {0}")]
+    OrderColumnUnknownColumn(ColumnIdentifier),
+    #[error("could not find temporary table column for operation field: {0}")]
+    OperationUnknownColumn(String),
+    #[error("hierarchy levels are only merged with a key column")]
+    HierarchyKeyColumns,
     #[error(transparent)]
     MergeFailed(#[from] tiberius::error::Error),
 }
 
 pub async fn execute(
-    client: &mut Client<Compat<TcpStream>>,
+    client: &mut impl SqlBackend,
     target_table: &TableIdentifier,
     temporary_table: &TableIdentifier,
-    key_columns: impl IntoIterator<Item = &ColumnIdentifier>,
+    key_columns: impl IntoIterator<Item = (&ColumnIdentifier, &[KeyNormalization])>,
+    default_on_insert: &[ColumnIdentifier],
+    surrogate_key: Option<&SurrogateKey>,
+    operation: Option<(&Operation, IndexedColumnNode<'_>)>,
+    history: Option<&History>,
+    delete_mode: DeleteMode,
+    merge_rows: MergeRows,
     columns: impl IntoIterator<Item = IndexedColumnNode<'_>>,
-) -> Result<(), MergeProcessorError> {
+    order_columns: &[(IndexedColumnNode<'_>, SortDirection)],
+    concurrency: Concurrency,
+    run_id: &str,
+) -> Result<MergeResult, MergeProcessorError> {
     let key_columns = key_columns.into_iter().collect::<Vec<_>>();
     let columns = columns.into_iter().collect::<Vec<_>>();
 
-    // TODO: probably missing handling of static columns here, since they are transient, and not
-    //       handled via update processor.
+    // target table rows absent from the temporary table are only deleted by a merge of every row
+    let delete_unmatched = delete_mode == DeleteMode::Full && merge_rows == MergeRows::All;
+    let deletes = operation.is_some() || delete_unmatched;
 
-    let indexed_key_columns: HashMap<&ColumnIdentifier, &IndexedColumnNode<'_>> = key_columns
-        .iter()
-        .map(|&key_column| {
-            columns
-                .iter()
-                .find(|column| key_column == column.column().identifier())
-                .map(|column| (key_column, column))
-                .ok_or_else(|| {
-                    MergeProcessorError::KeyColumnUnknownTargetColumn(key_column.to_owned())
-                })
-        })
-        .collect::<Result<_, _>>()?;
+    // static columns are not in the temporary table, and are merged from their values instead
+    let mut parameters = Vec::<&str>::new();
 
-    let on_key_columns: String = indexed_key_columns
+    let sources = columns
         .iter()
-        .map(|(identifier, indexed_column)| {
-            format!(
-                "T.{key_column} = S.{column}",
-                key_column = identifier.part(),
-                column = indexed_column.unique_identifier().part(),
-            )
+        .map(|column| match column.column() {
+            ColumnNode::StaticColumn {
+                column: static_column,
+                ..
+            } => match static_column.token() {
+                Some(token) => token.sql_expression(run_id),
+                None => {
+                    parameters.push(static_column.value());
+
+                    format!(
+                        "CAST(@P{parameter_index} AS {column_type})",
+                        parameter_index = parameters.len(),
+                        column_type = column.metadata().ty,
+                    )
+                }
+            },
+            _ => format!("S.{}", column.unique_identifier().part()),
         })
-        .collect::<Vec<_>>()
-        .join(",\n    ");
+        .collect::<Vec<_>>();
+
+    let indexed_key_columns = index_key_columns(&key_columns, &columns)?;
+    let on_key_columns = on_key_columns(&indexed_key_columns);
 
     let set_update_columns: String = columns
         .iter()
-        .filter_map(|column| {
+        .zip(&sources)
+        .filter_map(|(column, source)| {
             if column.metadata().flags == ColumnFlag::Identity
                 || indexed_key_columns.contains_key(column.column().identifier())
             {
                 None
             } else {
                 Some(format!(
-                    "T.{target_column} = S.{temporary_column}",
+                    "T.{target_column} = {source}",
                     target_column = column.column().identifier().part(),
-                    temporary_column = column.unique_identifier().part(),
                 ))
             }
         })
         .collect::<Vec<_>>()
         .join(",\n        ");
 
+    // columns using their default on insert are omitted, such that the default constraint applies
+    let inserted = |column: &IndexedColumnNode<'_>| {
+        column.metadata().flags != ColumnFlag::Identity
+            && !default_on_insert.contains(column.column().identifier())
+    };
+
     let insert_columns_target: String = columns
         .iter()
-        .filter_map(|column| {
-            if column.metadata().flags == ColumnFlag::Identity || column.column().is_transient() {
-                None
-            } else {
-                Some(column.column().identifier().part())
-            }
-        })
+        .filter(|column| inserted(column))
+        .map(|column| column.column().identifier().part())
         .collect::<Vec<_>>()
         .join(", ");
 
     let insert_columns_temporary: String = columns
         .iter()
-        .filter_map(|column| {
-            if column.metadata().flags == ColumnFlag::Identity {
-                None
+        .zip(&sources)
+        .filter_map(|(column, source)| {
+            if inserted(column) {
+                Some(source.as_str())
             } else {
-                Some(column.unique_identifier().part())
+                None
             }
         })
         .collect::<Vec<_>>()
         .join(", ");
 
+    let source_table = match merge_rows {
+        MergeRows::All | MergeRows::Resolved => temporary_table.to_string(),
+        // only the rows of the level of the hierarchy are merged, once the levels above are merged
+        MergeRows::HierarchyLevel(level) => format!(
+            "(SELECT * FROM {temporary_table} AS H WHERE EXISTS (SELECT 1 FROM {levels_table} AS L WHERE L.[key] = H.{key_column} AND L.[level] = {level}))",
+            levels_table = hierarchy::LEVELS_TABLE,
+            key_column = indexed_key_columns
+                .values()
+                .next()
+                .map(|(column, _)| column.unique_identifier().part())
+                .ok_or(MergeProcessorError::HierarchyKeyColumns)?,
+        ),
+    };
+
+    // rows whose lookups are unchanged were merged by the first merge, and are not updated again,
+    // such that triggers and row versions of their target rows only change once. Rows are filtered
+    // once the first of the rows with the same key columns is chosen, as by the first merge
+    let resolved_condition = match merge_rows {
+        MergeRows::All | MergeRows::HierarchyLevel(_) => None,
+        MergeRows::Resolved => Some(format!("{} = 1", RESOLVED_COLUMN)),
+    };
+
+    // only the first of the rows with the same key columns is merged, duplicates otherwise failing
+    // the merge or updating the target row from an arbitrary row
+    let (with_source, source) = if order_columns.is_empty() {
+        let source = match &resolved_condition {
+            None => source_table,
+            Some(resolved_condition) => {
+                format!("(SELECT * FROM {source_table} AS S WHERE {resolved_condition})")
+            }
+        };
+
+        (String::new(), source)
+    } else {
+        // normalized, as rows which only match the same target row once normalized are duplicates
+        let partition_columns = indexed_key_columns
+            .values()
+            .map(|(column, normalizations)| {
+                normalize(&column.unique_identifier().part(), normalizations)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let order_columns = order_columns
+            .iter()
+            .map(|(column, direction)| {
+                format!(
+                    "{column} {direction}",
+                    column = column.unique_identifier().part(),
+                    direction = direction.sql_keyword(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        (
+            formatdoc!(
+                "
+                WITH Source AS (
+                    SELECT *, ROW_NUMBER() OVER (PARTITION BY {partition_columns} ORDER BY {order_columns}) AS [$row_number]
+                    FROM {source_table} AS S
+                )
+                "
+            ),
+            format!(
+                "(SELECT * FROM Source WHERE [$row_number] = 1{resolved_condition})",
+                resolved_condition = resolved_condition
+                    .map(|resolved_condition| format!(" AND {resolved_condition}"))
+                    .unwrap_or_default(),
+            ),
+        )
+    };
+
+    let table_hint = if concurrency.serializable {
+        " WITH (HOLDLOCK)"
+    } else {
+        ""
+    };
+
+    let query_hint = concurrency
+        .max_parallel
+        .map(|max_parallel| format!("\nOPTION (MAXDOP {max_parallel})"))
+        .unwrap_or_default();
+
+    if let Some(history) = history {
+        let (statement, updates_in_place) = history_statement(
+            target_table,
+            history,
+            &columns,
+            &sources,
+            &indexed_key_columns,
+            &insert_columns_target,
+            &insert_columns_temporary,
+            &with_source,
+            &source,
+            table_hint,
+            &query_hint,
+        );
+
+        trace_sql!(statement);
+
+        let parameters = parameters
+            .iter()
+            .map(|parameter| parameter as &dyn ToSql)
+            .collect::<Vec<_>>();
+
+        let result = client.execute(&statement, &parameters).await?;
+
+        let (closed, updated, inserted) = match (updates_in_place, result.rows_affected()) {
+            (true, [.., closed, updated, inserted]) => (*closed, *updated, *inserted),
+            (false, [.., closed, inserted]) => (*closed, 0, *inserted),
+            _ => (0, 0, 0),
+        };
+
+        return Ok(MergeResult {
+            inserted,
+            updated: closed + updated,
+            deleted: 0,
+        });
+    }
+
+    // the operation of each row of a delta file chooses its action, rows whose operation does not
+    // apply to whether they match a target table row being ignored
+    let (when_matched_delete, when_matched, when_not_matched) = match operation {
+        None => (String::new(), String::new(), String::new()),
+        Some((operation, column)) => {
+            let operation_column = format!("S.{}", column.unique_identifier().part());
+
+            parameters.extend([operation.insert(), operation.update(), operation.delete()]);
+
+            let insert_index = parameters.len() - 2;
+
+            (
+                formatdoc!(
+                    "
+                    WHEN MATCHED AND {operation_column} = @P{delete_index} THEN
+                        DELETE
+                    ",
+                    delete_index = parameters.len(),
+                ),
+                format!(
+                    " AND {operation_column} IN (@P{insert_index}, @P{update_index})",
+                    update_index = insert_index + 1,
+                ),
+                format!(" AND {operation_column} = @P{insert_index}"),
+            )
+        }
+    };
+
+    // the action of each merged row is output, then deleted an action at a time, such that the
+    // rows affected by each delete are the rows inserted and updated by the merge
+    let (create_capture_table, output, count_actions) = match surrogate_key {
+        None => (
+            String::new(),
+            String::from("OUTPUT $action INTO @actions"),
+            String::from(indoc! {"
+                DELETE FROM @actions WHERE [action] = N'INSERT';
+                DELETE FROM @actions WHERE [action] = N'UPDATE';
+            "}),
+        ),
+        // the surrogate key of each inserted row is captured with its key columns, the action of
+        // captured rows being cleared once counted, and updated and deleted rows being deleted
+        Some(surrogate_key) => {
+            parameters.push(run_id);
+
+            // deleted rows have no inserted values, their deleted values are captured instead
+            let output_column = |column: &str| {
+                if deletes {
+                    format!("COALESCE(inserted.{column}, deleted.{column})")
+                } else {
+                    format!("inserted.{column}")
+                }
+            };
+
+            let capture_table = surrogate_key.capture_table();
+            let surrogate_key_column = surrogate_key.identifier().part();
+            let key_columns = key_columns
+                .iter()
+                .map(|(key_column, _)| key_column.part())
+                .collect::<Vec<_>>();
+
+            (
+                formatdoc!(
+                    "
+                    IF OBJECT_ID(N'{capture_table}', N'U') IS NULL
+                        SELECT TOP (0)
+                            CAST(NULL AS NVARCHAR(36)) AS [$run_id],
+                            CAST(NULL AS NVARCHAR(10)) AS [$action],
+                            ISNULL(T.{surrogate_key_column}, T.{surrogate_key_column}) AS {surrogate_key_column},
+                            {target_key_columns}
+                        INTO {capture_table}
+                        FROM {target_table} AS T;
+
+                    ",
+                    target_key_columns = key_columns
+                        .iter()
+                        .map(|key_column| format!("T.{key_column}"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                format!(
+                    "OUTPUT @P{parameter_index}, $action, {output_surrogate_key_column}, {output_key_columns}\n\
+                    INTO {capture_table} ([$run_id], [$action], {surrogate_key_column}, {key_columns})",
+                    parameter_index = parameters.len(),
+                    output_surrogate_key_column = output_column(surrogate_key_column),
+                    output_key_columns = key_columns
+                        .iter()
+                        .map(|key_column| output_column(key_column))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    key_columns = key_columns.join(", "),
+                ),
+                formatdoc!(
+                    "
+                    UPDATE {capture_table} SET [$action] = NULL WHERE [$action] = N'INSERT';
+                    DELETE FROM {capture_table} WHERE [$action] = N'UPDATE';
+                    "
+                ),
+            )
+        }
+    };
+
+    // deleted rows are counted after the inserted and updated rows
+    let count_actions = match (deletes, surrogate_key) {
+        (false, _) => count_actions,
+        (true, None) => count_actions + "DELETE FROM @actions WHERE [action] = N'DELETE';\n",
+        (true, Some(surrogate_key)) => format!(
+            "{count_actions}DELETE FROM {capture_table} WHERE [$action] = N'DELETE';\n",
+            capture_table = surrogate_key.capture_table(),
+        ),
+    };
+
+    let when_not_matched_by_source = if delete_unmatched {
+        indoc! {"
+            WHEN NOT MATCHED BY SOURCE THEN
+                DELETE
+        "}
+    } else {
+        ""
+    };
+
     let statement = formatdoc!(
         "
-        MERGE INTO {target_table} AS T
-        USING {temporary_table} AS S
+        DECLARE @actions TABLE ([action] NVARCHAR(10));
+
+        {create_capture_table}{with_source}MERGE INTO {target_table}{table_hint} AS T
+        USING {source} AS S
         ON
             {on_key_columns}
-        WHEN MATCHED THEN
+        {when_matched_delete}WHEN MATCHED{when_matched} THEN
             UPDATE SET
                 {set_update_columns}
-        WHEN NOT MATCHED BY TARGET THEN
+        WHEN NOT MATCHED BY TARGET{when_not_matched} THEN
             INSERT ({insert_columns_target})
-            VALUES ({insert_columns_temporary});
-        ",
+            VALUES ({insert_columns_temporary})
+        {when_not_matched_by_source}{output}{query_hint};
+
+        {count_actions}",
         target_table = target_table,
-        temporary_table = temporary_table,
         on_key_columns = on_key_columns,
         set_update_columns = set_update_columns,
         insert_columns_target = insert_columns_target,
@@ -121,7 +410,539 @@ pub async fn execute(
 
     trace_sql!(statement);
 
-    client.execute(statement, &[]).await?;
+    let parameters = parameters
+        .iter()
+        .map(|parameter| parameter as &dyn ToSql)
+        .collect::<Vec<_>>();
+
+    let result = client.execute(&statement, &parameters).await?;
+
+    let (inserted, updated, deleted) = match (deletes, result.rows_affected()) {
+        (false, [.., inserted, updated]) => (*inserted, *updated, 0),
+        (true, [.., inserted, updated, deleted]) => (*inserted, *updated, *deleted),
+        _ => (0, 0, 0),
+    };
+
+    Ok(MergeResult {
+        inserted,
+        updated,
+        deleted,
+    })
+}
+
+/// Builds the merge of an effective-dated history, returning whether unchanged current rows are
+/// updated in place.
+fn history_statement(
+    target_table: &TableIdentifier,
+    history: &History,
+    columns: &[IndexedColumnNode<'_>],
+    sources: &[String],
+    indexed_key_columns: &IndexedKeyColumns<'_, '_>,
+    insert_columns_target: &str,
+    insert_columns_temporary: &str,
+    with_source: &str,
+    source: &str,
+    table_hint: &str,
+    query_hint: &str,
+) -> (String, bool) {
+    let valid_to_column = history.valid_to_column().part();
+    let current_key_columns = format!(
+        "{on_key_columns}\n    AND T.{valid_to_column} IS NULL",
+        on_key_columns = on_key_columns(indexed_key_columns),
+    );
+
+    // the period starts at the time of the merge, unless the table mapper maps its start
+    let valid_from = columns
+        .iter()
+        .zip(sources)
+        .find(|(column, _)| column.column().identifier() == history.valid_from_column())
+        .map(|(_, source)| source.as_str());
+
+    let (declare_valid_from, valid_from_source, insert_valid_from_target, insert_valid_from) =
+        match valid_from {
+            Some(source) => (String::new(), source, String::new(), ""),
+            None => (
+                String::from("DECLARE @valid_from DATETIME2 = SYSUTCDATETIME();\n\n"),
+                "@valid_from",
+                format!(", {}", history.valid_from_column().part()),
+                ", @valid_from",
+            ),
+        };
+
+    let (target_tracked_columns, source_tracked_columns): (Vec<_>, Vec<_>) = columns
+        .iter()
+        .zip(sources)
+        .filter(|(column, _)| {
+            history
+                .tracked_columns()
+                .contains(column.column().identifier())
+        })
+        .map(|(column, source)| {
+            (
+                format!("T.{}", column.column().identifier().part()),
+                source.as_str(),
+            )
+        })
+        .unzip();
+
+    let set_untracked_columns = columns
+        .iter()
+        .zip(sources)
+        .filter_map(|(column, source)| {
+            let identifier = column.column().identifier();
+
+            if column.metadata().flags == ColumnFlag::Identity
+                || indexed_key_columns.contains_key(identifier)
+                || history.tracked_columns().contains(identifier)
+                || identifier == history.valid_from_column()
+            {
+                None
+            } else {
+                Some(format!("T.{} = {source}", identifier.part()))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",\n    ");
+
+    let update_untracked = if set_untracked_columns.is_empty() {
+        String::new()
+    } else {
+        formatdoc!(
+            "
+            {with_source}UPDATE T
+            SET
+                {set_untracked_columns}
+            FROM {target_table} AS T{table_hint}
+            INNER JOIN {source} AS S
+            ON
+                {current_key_columns}{query_hint};
+
+            "
+        )
+    };
+
+    let statement = formatdoc!(
+        "
+        {declare_valid_from}{with_source}UPDATE T
+        SET T.{valid_to_column} = {valid_from_source}
+        FROM {target_table} AS T{table_hint}
+        INNER JOIN {source} AS S
+        ON
+            {current_key_columns}
+        WHERE EXISTS (SELECT {target_tracked_columns} EXCEPT SELECT {source_tracked_columns}){query_hint};
+
+        {update_untracked}{with_source}INSERT INTO {target_table} ({insert_columns_target}{insert_valid_from_target}, {valid_to_column})
+        SELECT {insert_columns_temporary}{insert_valid_from}, NULL
+        FROM {source} AS S
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM {target_table} AS T{table_hint}
+            WHERE {current_key_columns}
+        ){query_hint};
+        ",
+        target_tracked_columns = target_tracked_columns.join(", "),
+        source_tracked_columns = source_tracked_columns.join(", "),
+    );
+
+    (statement, !update_untracked.is_empty())
+}
+
+/// Resolves the operation field of the table mapper to the column of the temporary table it is
+/// staged into, which is `None` if the table mapper has no operation field.
+pub fn operation_column<'a>(
+    operation: Option<&'a Operation>,
+    column_graph: &'a ColumnGraph,
+) -> Result<Option<(&'a Operation, IndexedColumnNode<'a>)>, MergeProcessorError> {
+    operation
+        .map(|operation| {
+            column_graph
+                .staged_column(operation.identifier())
+                .map(|column| (operation, column))
+                .ok_or_else(|| {
+                    MergeProcessorError::OperationUnknownColumn(operation.field().to_owned())
+                })
+        })
+        .transpose()
+}
+
+/// Resolves the columns of the merge order to the columns of the temporary table, which is empty
+/// if the table mapper has no merge order.
+pub fn order_columns<'a>(
+    merge_order: Option<&MergeOrder>,
+    column_graph: &'a ColumnGraph,
+) -> Result<Vec<(IndexedColumnNode<'a>, SortDirection)>, MergeProcessorError> {
+    merge_order
+        .into_iter()
+        .flat_map(MergeOrder::columns)
+        .map(|order_column| {
+            column_graph
+                .staged_column(order_column.identifier())
+                .map(|column| (column, order_column.direction()))
+                .ok_or_else(|| {
+                    MergeProcessorError::OrderColumnUnknownColumn(
+                        order_column.identifier().to_owned(),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Condition matching the temporary table rows `S` to the target table rows `T` they are merged
+/// into.
+pub fn on_condition<'a>(
+    key_columns: impl IntoIterator<Item = (&'a ColumnIdentifier, &'a [KeyNormalization])>,
+    columns: impl IntoIterator<Item = IndexedColumnNode<'a>>,
+) -> Result<String, MergeProcessorError> {
+    let key_columns = key_columns.into_iter().collect::<Vec<_>>();
+    let columns = columns.into_iter().collect::<Vec<_>>();
+
+    Ok(on_key_columns(&index_key_columns(&key_columns, &columns)?))
+}
+
+type IndexedKeyColumns<'a, 'b> =
+    HashMap<&'a ColumnIdentifier, (&'b IndexedColumnNode<'b>, &'a [KeyNormalization])>;
+
+fn index_key_columns<'a, 'b>(
+    key_columns: &[(&'a ColumnIdentifier, &'a [KeyNormalization])],
+    columns: &'b [IndexedColumnNode<'b>],
+) -> Result<IndexedKeyColumns<'a, 'b>, MergeProcessorError> {
+    key_columns
+        .iter()
+        .map(|&(key_column, normalizations)| {
+            columns
+                .iter()
+                .find(|column| key_column == column.column().identifier())
+                .map(|column| (key_column, (column, normalizations)))
+                .ok_or_else(|| {
+                    MergeProcessorError::KeyColumnUnknownTargetColumn(key_column.to_owned())
+                })
+        })
+        .collect()
+}
+
+fn on_key_columns(indexed_key_columns: &IndexedKeyColumns<'_, '_>) -> String {
+    indexed_key_columns
+        .iter()
+        .map(|(identifier, (indexed_column, normalizations))| {
+            format!(
+                "{key_column} = {column}",
+                key_column = normalize(&format!("T.{}", identifier.part()), normalizations),
+                column = normalize(
+                    &format!("S.{}", indexed_column.unique_identifier().part()),
+                    normalizations,
+                ),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n    AND ")
+}
+
+/// Applies the normalizations to the SQL expression, in order.
+fn normalize(expression: &str, normalizations: &[KeyNormalization]) -> String {
+    normalizations
+        .iter()
+        .fold(expression.to_owned(), |expression, normalization| {
+            normalization.sql_expression(&expression)
+        })
+}
+
+/// Estimated effect of merging the temporary table into the target table.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MergePlan {
+    /// Temporary table rows not matching any target table row
+    pub inserts: i64,
+    /// Temporary table rows matching a target table row
+    pub updates: i64,
+    /// Target table rows not matching any temporary table row
+    pub unmatched: i64,
+}
+
+/// Computes a [`MergePlan`] from a diff of the temporary table against the target table, without
+/// modifying either.
+pub async fn plan(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    temporary_table: &TableIdentifier,
+    key_columns: impl IntoIterator<Item = (&ColumnIdentifier, &[KeyNormalization])>,
+    columns: impl IntoIterator<Item = IndexedColumnNode<'_>>,
+) -> Result<MergePlan, MergeProcessorError> {
+    let key_columns = key_columns.into_iter().collect::<Vec<_>>();
+    let columns = columns.into_iter().collect::<Vec<_>>();
+
+    let indexed_key_columns = index_key_columns(&key_columns, &columns)?;
+    let on_key_columns = on_key_columns(&indexed_key_columns);
+
+    let statement = formatdoc!(
+        "
+        SELECT
+            (SELECT COUNT_BIG(*) FROM {temporary_table} S WHERE NOT EXISTS (SELECT 1 FROM {target_table} T WHERE {on_key_columns})),
+            (SELECT COUNT_BIG(*) FROM {temporary_table} S WHERE EXISTS (SELECT 1 FROM {target_table} T WHERE {on_key_columns})),
+            (SELECT COUNT_BIG(*) FROM {target_table} T WHERE NOT EXISTS (SELECT 1 FROM {temporary_table} S WHERE {on_key_columns}))
+        ",
+    );
+
+    trace_sql!(statement);
+
+    let rows = client.query(&statement, &[]).await?;
+
+    Ok(rows
+        .first()
+        .map(|row| MergePlan {
+            inserts: row.get(0).unwrap_or_default(),
+            updates: row.get(1).unwrap_or_default(),
+            unmatched: row.get(2).unwrap_or_default(),
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::import_options::ImportOptions;
+    use crate::table_mapper::table_mapper_raw::TableMapperRaw;
+    use crate::table_mapper::{Table, TableMapper};
+
+    #[test]
+    fn normalize_applies_normalizations_in_order() {
+        assert_eq!(normalize("S.[Code_1]", &[]), "S.[Code_1]");
+
+        assert_eq!(
+            normalize(
+                "S.[Code_1]",
+                &[KeyNormalization::Trim, KeyNormalization::Upper]
+            ),
+            "UPPER(LTRIM(RTRIM(S.[Code_1])))",
+        );
+
+        assert_eq!(
+            normalize("T.[Code]", &[KeyNormalization::ZeroPad(4)]),
+            "CASE WHEN LEN(CAST(T.[Code] AS NVARCHAR(4000))) < 4 \
+            THEN RIGHT(REPLICATE(N'0', 4) + CAST(T.[Code] AS NVARCHAR(4000)), 4) \
+            ELSE CAST(T.[Code] AS NVARCHAR(4000)) END",
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_history_closes_changed_rows() -> Result<(), Box<dyn std::error::Error>> {
+        let raw: TableMapperRaw = serde_json::from_value(serde_json::json!({
+            "name": "Customer",
+            "field_group": "Customer",
+            "table_identifier": "[dbo].[Customer]",
+            "delete_mode": "Partial",
+            "delete_action": "None",
+            "duplicate_action": "Reject",
+            "columns": [
+                { "Parser": { "column_identifier": "Code", "map_column": true, "field_name": "Code" } },
+                { "Parser": { "column_identifier": "Name", "map_column": true, "field_name": "Name" } },
+                { "Parser": { "column_identifier": "Region", "map_column": true, "field_name": "Region" } }
+            ],
+            "key_columns": ["Code"],
+            "history": {
+                "valid_from_column": "ValidFrom",
+                "valid_to_column": "ValidTo",
+                "tracked_columns": ["Region"]
+            }
+        }))?;
+
+        let table_mapper = TableMapper::new(raw, None)?;
+        let table_metadata = HashMap::from_iter([(table_mapper.identifier(), HashMap::default())]);
+        let column_graph =
+            ColumnGraph::new(&table_mapper, &table_metadata, &ImportOptions::default())?;
+
+        let mut backend = MockBackend::new();
+
+        backend.push_result([2, 5, 3]);
+
+        let result = execute(
+            &mut backend,
+            table_mapper.identifier(),
+            &"[import].[Customer]".parse()?,
+            table_mapper.normalized_key_columns(),
+            table_mapper.default_on_insert(),
+            None,
+            None,
+            table_mapper.history(),
+            DeleteMode::Partial,
+            MergeRows::All,
+            column_graph.target_columns(),
+            &[],
+            Concurrency::default(),
+            "run",
+        )
+        .await?;
+
+        assert_eq!(
+            result,
+            MergeResult {
+                inserted: 3,
+                updated: 7,
+                deleted: 0,
+            }
+        );
+
+        let statement = &backend.statements()[0].statement;
+        let source_column = |name: &str| {
+            column_graph
+                .staged_column(&format!("[dbo].[Customer].[{name}]").parse().unwrap())
+                .map(|column| format!("S.{}", column.unique_identifier().part()))
+                .unwrap()
+        };
+
+        assert!(statement.contains("SET T.[ValidTo] = @valid_from"));
+        assert!(statement.contains(&format!(
+            "EXISTS (SELECT T.[Region] EXCEPT SELECT {})",
+            source_column("Region")
+        )));
+        assert!(statement.contains(&format!("T.[Name] = {}", source_column("Name"))));
+        assert!(!statement.contains("T.[Region] = "));
+        assert!(statement.contains("[ValidFrom], [ValidTo])"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_full_delete_mode_deletes_unmatched_rows()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let raw: TableMapperRaw = serde_json::from_value(serde_json::json!({
+            "name": "Country",
+            "field_group": "Country",
+            "table_identifier": "[dbo].[Country]",
+            "delete_mode": "Full",
+            "delete_action": "None",
+            "duplicate_action": "Reject",
+            "columns": [
+                { "Parser": { "column_identifier": "Code", "map_column": true, "field_name": "Code" } },
+                { "Parser": { "column_identifier": "Name", "map_column": true, "field_name": "Name" } }
+            ],
+            "key_columns": ["Code"]
+        }))?;
+
+        let table_mapper = TableMapper::new(raw, None)?;
+        let table_metadata = HashMap::from_iter([(table_mapper.identifier(), HashMap::default())]);
+        let column_graph =
+            ColumnGraph::new(&table_mapper, &table_metadata, &ImportOptions::default())?;
+
+        let mut backend = MockBackend::new();
+
+        backend.push_result([6, 1, 2, 3]);
+
+        let mut results = Vec::new();
+
+        for merge_rows in [MergeRows::All, MergeRows::Resolved] {
+            results.push(
+                execute(
+                    &mut backend,
+                    table_mapper.identifier(),
+                    &"[import].[Country]".parse()?,
+                    table_mapper.normalized_key_columns(),
+                    table_mapper.default_on_insert(),
+                    None,
+                    None,
+                    None,
+                    table_mapper.delete_mode(),
+                    merge_rows,
+                    column_graph.target_columns(),
+                    &[],
+                    Concurrency::default(),
+                    "run",
+                )
+                .await?,
+            );
+        }
+
+        assert_eq!(
+            results[0],
+            MergeResult {
+                inserted: 1,
+                updated: 2,
+                deleted: 3,
+            }
+        );
+
+        let statements = backend.statements();
+
+        assert!(
+            statements[0].statement.contains(
+                "WHEN NOT MATCHED BY SOURCE THEN\n    DELETE\nOUTPUT $action INTO @actions"
+            )
+        );
+        assert!(
+            statements[0]
+                .statement
+                .contains("DELETE FROM @actions WHERE [action] = N'DELETE';")
+        );
+        assert!(!statements[1].statement.contains("NOT MATCHED BY SOURCE"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_resolved_rows_after_choosing_first_row() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let raw: TableMapperRaw = serde_json::from_value(serde_json::json!({
+            "name": "Employee",
+            "field_group": "Employee",
+            "table_identifier": "[dbo].[Employee]",
+            "delete_mode": "Partial",
+            "delete_action": "None",
+            "duplicate_action": "Retain",
+            "columns": [
+                { "Parser": { "column_identifier": "Code", "map_column": true, "field_name": "Code" } },
+                { "Parser": { "column_identifier": "Name", "map_column": true, "field_name": "Name" } }
+            ],
+            "key_columns": ["Code"]
+        }))?;
+
+        let table_mapper = TableMapper::new(raw, None)?;
+        let table_metadata = HashMap::from_iter([(table_mapper.identifier(), HashMap::default())]);
+        let column_graph =
+            ColumnGraph::new(&table_mapper, &table_metadata, &ImportOptions::default())?;
+        let name_column = column_graph
+            .staged_column(&"[dbo].[Employee].[Name]".parse()?)
+            .unwrap();
+
+        let mut backend = MockBackend::new();
+
+        backend.push_result([0, 0]);
+        backend.push_result([0, 2]);
+
+        for order_columns in [Vec::new(), vec![(name_column, SortDirection::Ascending)]] {
+            execute(
+                &mut backend,
+                table_mapper.identifier(),
+                &"[import].[Employee]".parse()?,
+                table_mapper.normalized_key_columns(),
+                table_mapper.default_on_insert(),
+                None,
+                None,
+                None,
+                DeleteMode::Partial,
+                MergeRows::Resolved,
+                column_graph.target_columns(),
+                &order_columns,
+                Concurrency::default(),
+                "run",
+            )
+            .await?;
+        }
+
+        let statements = backend.statements();
+
+        assert!(
+            statements[0].statement.contains(
+                "USING (SELECT * FROM [import].[Employee] AS S WHERE [$resolved] = 1) AS S"
+            )
+        );
+        assert!(
+            statements[1]
+                .statement
+                .contains("FROM [import].[Employee] AS S\n")
+        );
+        assert!(statements[1].statement.contains(
+            "USING (SELECT * FROM Source WHERE [$row_number] = 1 AND [$resolved] = 1) AS S"
+        ));
 
-    Ok(())
+        Ok(())
+    }
 }
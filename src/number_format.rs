@@ -0,0 +1,147 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Characters separating groups of digits, besides the point or comma which is not the decimal
+/// separator, such as `1 234,56` or `1'234.56`.
+const GROUP_SEPARATORS: [char; 4] = [' ', '\'', '\u{a0}', '\u{202f}'];
+
+/// Separator of the integer and fractional digits of the numbers of a data source.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, JsonSchema, Deserialize)]
+pub enum DecimalSeparator {
+    /// `1,234.56`, commas separating thousands
+    #[default]
+    Point,
+    /// `1.234,56`, points separating thousands
+    Comma,
+}
+
+impl DecimalSeparator {
+    fn char(self) -> char {
+        match self {
+            DecimalSeparator::Point => '.',
+            DecimalSeparator::Comma => ',',
+        }
+    }
+
+    fn group_separator(self) -> char {
+        match self {
+            DecimalSeparator::Point => ',',
+            DecimalSeparator::Comma => '.',
+        }
+    }
+}
+
+/// Normalizes a number into plain decimal notation, or `None` if the value is not a number or its
+/// digits are not grouped in thousands.
+pub fn normalize_number(value: &str, decimal_separator: DecimalSeparator) -> Option<String> {
+    let mut number = value.trim();
+    let mut negative = false;
+
+    if let Some(inner) = number
+        .strip_prefix('(')
+        .and_then(|number| number.strip_suffix(')'))
+    {
+        negative = true;
+        number = inner.trim();
+    }
+
+    if let Some(inner) = number
+        .strip_suffix('-')
+        .or_else(|| number.strip_prefix('-'))
+    {
+        if negative {
+            return None;
+        }
+
+        negative = true;
+        number = inner.trim();
+    } else if let Some(inner) = number.strip_prefix('+') {
+        number = inner.trim_start();
+    }
+
+    let mut normalized = String::with_capacity(number.len() + 1);
+    let mut fraction = false;
+    // digits of the current group, and whether a group separator has been seen
+    let mut group_digits = 0;
+    let mut grouped = false;
+
+    if negative {
+        normalized.push('-');
+    }
+
+    for c in number.chars() {
+        match c {
+            '0'..='9' => {
+                normalized.push(c);
+                group_digits += 1;
+            }
+            c if c == decimal_separator.char() && !fraction => {
+                if grouped && group_digits != 3 {
+                    return None;
+                }
+
+                if !normalized.ends_with(|c: char| c.is_ascii_digit()) {
+                    normalized.push('0');
+                }
+
+                normalized.push('.');
+                fraction = true;
+            }
+            c if !fraction
+                && (c == decimal_separator.group_separator() || GROUP_SEPARATORS.contains(&c)) =>
+            {
+                if group_digits == 0 || group_digits > 3 || (grouped && group_digits != 3) {
+                    return None;
+                }
+
+                group_digits = 0;
+                grouped = true;
+            }
+            _ => return None,
+        }
+    }
+
+    let digits = normalized.bytes().filter(u8::is_ascii_digit).count();
+
+    if digits == 0 || (grouped && !fraction && group_digits != 3) {
+        return None;
+    }
+
+    if normalized.ends_with('.') {
+        normalized.pop();
+    }
+
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_amounts() {
+        let point = |value| normalize_number(value, DecimalSeparator::Point);
+        let comma = |value| normalize_number(value, DecimalSeparator::Comma);
+
+        assert_eq!(comma("1.234,56").as_deref(), Some("1234.56"));
+        assert_eq!(comma("-1 234 567,8").as_deref(), Some("-1234567.8"));
+        assert_eq!(point("1,234.56").as_deref(), Some("1234.56"));
+        assert_eq!(point("1'234.56").as_deref(), Some("1234.56"));
+        assert_eq!(point("(500)").as_deref(), Some("-500"));
+        assert_eq!(point("500-").as_deref(), Some("-500"));
+        assert_eq!(point("( 1,000.00 )").as_deref(), Some("-1000.00"));
+        assert_eq!(point("+.5").as_deref(), Some("0.5"));
+        assert_eq!(comma("-,5").as_deref(), Some("-0.5"));
+        assert_eq!(point("7.").as_deref(), Some("7"));
+        assert_eq!(point("42").as_deref(), Some("42"));
+
+        assert_eq!(point("1,5"), None);
+        assert_eq!(point("1,2345"), None);
+        assert_eq!(point("12,34,567"), None);
+        assert_eq!(point("(500-)"), None);
+        assert_eq!(point("1.2.3"), None);
+        assert_eq!(point("USD 5"), None);
+        assert_eq!(point("-"), None);
+        assert_eq!(comma("1,234.56"), None);
+    }
+}
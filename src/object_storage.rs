@@ -0,0 +1,114 @@
+#[cfg(any(feature = "s3", feature = "azure"))]
+use futures::TryStreamExt;
+#[cfg(any(feature = "s3", feature = "azure"))]
+use object_store::ObjectStore;
+#[cfg(any(feature = "s3", feature = "azure"))]
+use std::fmt::{Debug, Formatter};
+#[cfg(any(feature = "s3", feature = "azure"))]
+use std::pin::Pin;
+#[cfg(any(feature = "s3", feature = "azure"))]
+use std::task::{Context, Poll};
+#[cfg(any(feature = "s3", feature = "azure"))]
+use tokio::io::{AsyncRead, ReadBuf};
+#[cfg(any(feature = "s3", feature = "azure"))]
+use tokio_util::io::StreamReader;
+
+const S3_SCHEME: &str = "s3://";
+const AZURE_SCHEME: &str = "az://";
+
+/// Whether the path of the data source is the URL of an object, `s3://bucket/key` or
+/// `az://container/blob`, rather than a local file.
+pub fn is_object_url(path: &str) -> bool {
+    path.starts_with(S3_SCHEME) || path.starts_with(AZURE_SCHEME)
+}
+
+/// Object streamed from object storage as it is read, never written to disk.
+#[cfg(any(feature = "s3", feature = "azure"))]
+pub struct ObjectReader {
+    url: String,
+    reader: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+#[cfg(any(feature = "s3", feature = "azure"))]
+impl Debug for ObjectReader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectReader")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(any(feature = "s3", feature = "azure"))]
+impl AsyncRead for ObjectReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.reader.as_mut().poll_read(cx, buf)
+    }
+}
+
+/// Opens the object of the URL, with the credentials and region of the object store taken from
+/// the environment, such as `AWS_ACCESS_KEY_ID` or `AZURE_STORAGE_ACCOUNT_NAME`.
+#[cfg(any(feature = "s3", feature = "azure"))]
+pub async fn open(url: &str) -> std::io::Result<ObjectReader> {
+    let (scheme, location) = url
+        .split_once("://")
+        .filter(|(_, location)| !location.is_empty())
+        .ok_or_else(|| invalid_url(url))?;
+    let (container, key) = location
+        .split_once('/')
+        .filter(|(container, key)| !container.is_empty() && !key.is_empty())
+        .ok_or_else(|| invalid_url(url))?;
+
+    let object_store: Box<dyn ObjectStore> = match scheme {
+        #[cfg(feature = "s3")]
+        "s3" => Box::new(
+            object_store::aws::AmazonS3Builder::from_env()
+                .with_bucket_name(container)
+                .build()
+                .map_err(std::io::Error::other)?,
+        ),
+        #[cfg(feature = "azure")]
+        "az" => Box::new(
+            object_store::azure::MicrosoftAzureBuilder::from_env()
+                .with_container_name(container)
+                .build()
+                .map_err(std::io::Error::other)?,
+        ),
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "reading {} requires the {} feature",
+                    url,
+                    if scheme == "s3" { "s3" } else { "azure" },
+                ),
+            ));
+        }
+    };
+
+    let stream = object_store
+        .get(&object_store::path::Path::from(key))
+        .await
+        .map_err(std::io::Error::other)?
+        .into_stream()
+        .map_err(std::io::Error::other);
+
+    Ok(ObjectReader {
+        url: url.to_owned(),
+        reader: Box::pin(StreamReader::new(stream)),
+    })
+}
+
+#[cfg(any(feature = "s3", feature = "azure"))]
+fn invalid_url(url: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+            "invalid object URL {}, expected s3://bucket/key or az://container/blob",
+            url
+        ),
+    )
+}
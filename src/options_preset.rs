@@ -0,0 +1,92 @@
+use rustc_hash::FxHashMap as HashMap;
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::io::Read;
+use thiserror::Error;
+
+/// Named presets of import options, each a list of command line arguments, such as
+/// `{ "nightly": ["--parse-parallelism", "4"] }`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct OptionsPresets(HashMap<String, Vec<String>>);
+
+#[derive(Debug, Error)]
+pub enum OptionsPresetError {
+    #[error("could not read options presets")]
+    Read(#[source] serde_json::Error),
+    #[error("unknown options preset: {0}")]
+    UnknownPreset(String),
+}
+
+impl OptionsPresets {
+    pub fn new(reader: impl Read) -> Result<Self, OptionsPresetError> {
+        serde_json::from_reader(reader).map_err(OptionsPresetError::Read)
+    }
+
+    /// Arguments of the preset inserted after the program name, preceding the given arguments such
+    /// that an option given explicitly takes precedence over the same option of the preset.
+    pub fn expand(
+        &self,
+        preset: &str,
+        args: impl IntoIterator<Item = OsString>,
+    ) -> Result<Vec<OsString>, OptionsPresetError> {
+        let preset_args = self
+            .0
+            .get(preset)
+            .ok_or_else(|| OptionsPresetError::UnknownPreset(preset.to_owned()))?;
+
+        let mut args = args.into_iter();
+
+        Ok(args
+            .next()
+            .into_iter()
+            .chain(preset_args.iter().map(OsString::from))
+            .chain(args)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_preset_before_explicit_options() -> Result<(), OptionsPresetError> {
+        let presets = OptionsPresets::new(
+            r#"{
+                "nightly": ["--parse-parallelism", "4", "--defer-constraints"],
+                "dev": ["--no-drop"]
+            }"#
+            .as_bytes(),
+        )?;
+
+        let args = [
+            "importer",
+            "--options-preset",
+            "nightly",
+            "--parse-parallelism",
+            "8",
+        ]
+        .map(OsString::from);
+
+        assert_eq!(
+            presets.expand("nightly", args.clone())?,
+            [
+                "importer",
+                "--parse-parallelism",
+                "4",
+                "--defer-constraints",
+                "--options-preset",
+                "nightly",
+                "--parse-parallelism",
+                "8",
+            ]
+        );
+        assert!(matches!(
+            presets.expand("staging", args),
+            Err(OptionsPresetError::UnknownPreset(preset)) if preset == "staging"
+        ));
+
+        Ok(())
+    }
+}
@@ -6,14 +6,21 @@ mod preprocess_rhai;
 use crate::data_source::DataSourceRecord;
 use crate::import_profile::import_profile_raw::PreprocessScript;
 use log::error;
+use sha2::{Digest, Sha256};
 use std::error::Error;
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::{fs, io};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum LoadPreprocessRuntimeError {
     #[error("could not determine script language")]
     UnknownLanguage,
+    #[error("could not read script file '{0}'")]
+    ReadScript(PathBuf, #[source] io::Error),
+    #[error("script file '{0}' does not match its SHA-256 digest")]
+    DigestMismatch(PathBuf),
     #[cfg(feature = "lua")]
     #[error("could not load lua")]
     LoadLua(#[from] preprocess_lua::LoadPreprocessRuntimeLuaError),
@@ -25,6 +32,8 @@ pub enum LoadPreprocessRuntimeError {
 pub fn load_preprocess_runtime(
     script: PreprocessScript,
 ) -> Result<Box<dyn PreprocessRuntime>, LoadPreprocessRuntimeError> {
+    let script = read_pinned(script)?;
+
     match script.language() {
         #[cfg(feature = "lua")]
         Some(crate::import_profile::import_profile_raw::PreprocessLanguage::Lua) => {
@@ -38,6 +47,34 @@ pub fn load_preprocess_runtime(
     }
 }
 
+/// Reads a script file pinned by its digest into an inline script once the digest is verified,
+/// such that the content verified is the content executed.
+fn read_pinned(script: PreprocessScript) -> Result<PreprocessScript, LoadPreprocessRuntimeError> {
+    let language = script.language();
+
+    match script {
+        PreprocessScript::File {
+            path,
+            sha256: Some(sha256),
+            ..
+        } => {
+            let language = language.ok_or(LoadPreprocessRuntimeError::UnknownLanguage)?;
+            let content = fs::read_to_string(&path)
+                .map_err(|err| LoadPreprocessRuntimeError::ReadScript(path.clone(), err))?;
+
+            if !format!("{:x}", Sha256::digest(&content)).eq_ignore_ascii_case(&sha256) {
+                return Err(LoadPreprocessRuntimeError::DigestMismatch(path));
+            }
+
+            Ok(PreprocessScript::Inline {
+                script: content,
+                language,
+            })
+        }
+        script => Ok(script),
+    }
+}
+
 pub trait PreprocessRuntime: Debug {
     fn function(
         &self,
@@ -6,7 +6,7 @@ use crate::preprocess::{
 };
 use itertools::Itertools;
 use itertools::Position;
-use log::{debug, error, info, trace, warn};
+use log::Level;
 use mlua::{AsChunk, ChunkMode, Function, IntoLua, Lua, Value, Variadic};
 use std::borrow::Cow;
 use std::collections::BTreeMap;
@@ -35,7 +35,7 @@ pub struct PreprocessLuaTransform {
 }
 
 macro_rules! register_log_function_lua {
-    ($lua:expr, $log:ident) => {{
+    ($lua:expr, $log:ident, $level:expr) => {{
         let function = $lua.create_function(|lua, args: Variadic<Value>| {
             let tostring = lua.globals().get::<Function>("tostring")?;
 
@@ -49,7 +49,7 @@ macro_rules! register_log_function_lua {
                 buf.push_str(&tostring.call::<String>(arg)?);
             }
 
-            $log!(target: "preprocess::lua", "{}", buf);
+            crate::log::preprocess_log($level, "preprocess::lua", &buf);
 
             Ok(())
         });
@@ -76,11 +76,11 @@ impl PreprocessLua {
             .map_err(LoadPreprocessRuntimeLuaError::Configure)?;
 
         (|| {
-            register_log_function_lua!(lua, error)?;
-            register_log_function_lua!(lua, warn)?;
-            register_log_function_lua!(lua, info)?;
-            register_log_function_lua!(lua, debug)?;
-            register_log_function_lua!(lua, trace)?;
+            register_log_function_lua!(lua, error, Level::Error)?;
+            register_log_function_lua!(lua, warn, Level::Warn)?;
+            register_log_function_lua!(lua, info, Level::Info)?;
+            register_log_function_lua!(lua, debug, Level::Debug)?;
+            register_log_function_lua!(lua, trace, Level::Trace)?;
             Ok(())
         })()
         .map_err(LoadPreprocessRuntimeLuaError::Configure)?;
@@ -152,6 +152,8 @@ impl IntoLua for DataSourceRecordIndex {
         table.set("record_number", self.record_number.get())?;
         table.set("line_start", self.line_start)?;
         table.set("line_end", self.line_end)?;
+        table.set("byte_start", self.byte_start)?;
+        table.set("byte_end", self.byte_end)?;
 
         Ok(Value::Table(table))
     }
@@ -3,7 +3,7 @@ use crate::import_profile::import_profile_raw::PreprocessScript;
 use crate::preprocess::{
     PreprocessFunctionError, PreprocessRuntime, PreprocessTransform, PreprocessTransformError,
 };
-use log::{debug, error, info, trace, warn};
+use log::{Level, error};
 use rhai::{AST, Dynamic, Engine, EvalAltResult, FnAccess, Map, ParseError, Scope};
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -34,9 +34,9 @@ pub enum PreprocessTransformRhaiError {
 }
 
 macro_rules! register_log_function_rhai {
-    ($engine:expr, $log:ident) => {
+    ($engine:expr, $log:ident, $level:expr) => {
         $engine.register_fn(stringify!($log), |arg: Dynamic| {
-            $log!(target: "preprocess::rhai", "{}", arg);
+            crate::log::preprocess_log($level, "preprocess::rhai", &arg.to_string());
         })
     };
 }
@@ -69,11 +69,11 @@ impl PreprocessRhai {
         let mut engine = Engine::new();
         let mut scope = Scope::new();
 
-        register_log_function_rhai!(engine, error);
-        register_log_function_rhai!(engine, warn);
-        register_log_function_rhai!(engine, info);
-        register_log_function_rhai!(engine, debug);
-        register_log_function_rhai!(engine, trace);
+        register_log_function_rhai!(engine, error, Level::Error);
+        register_log_function_rhai!(engine, warn, Level::Warn);
+        register_log_function_rhai!(engine, info, Level::Info);
+        register_log_function_rhai!(engine, debug, Level::Debug);
+        register_log_function_rhai!(engine, trace, Level::Trace);
 
         engine
             .run_with_scope(&mut scope, Self::PREPROCESS_SCRIPT_PREAMBLE_RHAI)
@@ -128,7 +128,7 @@ impl PreprocessTransform for PreprocessRhaiTransform {
         let index = record.index();
 
         let fields: Map = record
-            .into_iter()
+            .iter()
             .map(|(k, v)| (k.as_ref().into(), v.into()))
             .collect();
 
@@ -0,0 +1,139 @@
+use std::cell::Cell;
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// Counts of the records of a table mapper at each stage of the pipeline, such that
+/// `read = inserted + dropped + rejected + filtered + skipped`.
+#[derive(Debug, Default)]
+pub struct RecordAccounting {
+    /// Records read from the data source, including those rejected by it
+    read: Cell<u64>,
+    /// Records inserted into the staging table, as reported by the bulk insert
+    inserted: Cell<u64>,
+    /// Duplicate records dropped by deduplication, skipped empty records, and records dropped for
+    /// an empty required field
+    dropped: Cell<u64>,
+    /// Malformed records rejected by the data source
+    rejected: Cell<u64>,
+    /// Records filtered out by the preprocess script, or routed to another target table
+    filtered: Cell<u64>,
+    /// Records merged by the interrupted run being resumed
+    skipped: Cell<u64>,
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "records do not add up, {read} read but {accounted} accounted for ({accounting}), records were lost"
+)]
+pub struct RecordAccountingError {
+    read: u64,
+    accounted: u64,
+    accounting: String,
+}
+
+impl RecordAccounting {
+    pub fn read(&self) {
+        self.read.set(self.read.get() + 1);
+    }
+
+    /// Records read from the data source so far.
+    pub fn records_read(&self) -> u64 {
+        self.read.get()
+    }
+
+    pub fn dropped(&self) {
+        self.dropped.set(self.dropped.get() + 1);
+    }
+
+    /// Counts a record rejected by the data source, which is also counted as read.
+    pub fn rejected(&self) {
+        self.read();
+        self.rejected.set(self.rejected.get() + 1);
+    }
+
+    /// Records rejected by the data source so far.
+    pub fn records_rejected(&self) -> u64 {
+        self.rejected.get()
+    }
+
+    pub fn filtered(&self) {
+        self.filtered.set(self.filtered.get() + 1);
+    }
+
+    pub fn skipped(&self) {
+        self.skipped.set(self.skipped.get() + 1);
+    }
+
+    pub fn inserted(&self, records: u64) {
+        self.inserted.set(self.inserted.get() + records);
+    }
+
+    /// Checks that each record read is accounted for by exactly one of the other counts.
+    pub fn check(&self) -> Result<(), RecordAccountingError> {
+        let accounted = self.inserted.get()
+            + self.dropped.get()
+            + self.rejected.get()
+            + self.filtered.get()
+            + self.skipped.get();
+
+        if accounted != self.read.get() {
+            return Err(RecordAccountingError {
+                read: self.read.get(),
+                accounted,
+                accounting: self.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for RecordAccounting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "read: {}, inserted: {}, dropped: {}, rejected: {}, filtered: {}, skipped: {}",
+            self.read.get(),
+            self.inserted.get(),
+            self.dropped.get(),
+            self.rejected.get(),
+            self.filtered.get(),
+            self.skipped.get(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_accounting_identity() {
+        let accounting = RecordAccounting::default();
+
+        for _ in 0..5 {
+            accounting.read();
+        }
+
+        accounting.rejected();
+        accounting.dropped();
+        accounting.filtered();
+        accounting.inserted(3);
+
+        assert!(accounting.check().is_ok());
+
+        accounting.read();
+
+        let err = accounting.check().unwrap_err();
+
+        assert_eq!((err.read, err.accounted), (7, 6));
+        assert_eq!(
+            err.accounting,
+            "read: 7, inserted: 3, dropped: 1, rejected: 1, filtered: 1, skipped: 0"
+        );
+
+        accounting.skipped();
+
+        assert!(accounting.check().is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::backend::SqlBackend;
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace, warn};
+use std::fmt::{Display, Formatter};
+
+const RUN_HISTORY_TABLE: &str = "[import].[run_history]";
+
+/// Statistics of a successful execution of a table mapper, recorded in the run history table such
+/// that later executions are compared with it.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TableMapperRun {
+    pub records_read: u64,
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+impl TableMapperRun {
+    fn metrics(&self) -> [(&'static str, u64); 4] {
+        [
+            ("records read", self.records_read),
+            ("inserted", self.inserted),
+            ("updated", self.updated),
+            ("deleted", self.deleted),
+        ]
+    }
+
+    /// Metrics which changed by at least the factor from the previous run, either way. Metrics
+    /// which were zero in either run are not compared.
+    pub fn anomalies(&self, previous: &TableMapperRun, factor: f64) -> Vec<&'static str> {
+        self.metrics()
+            .into_iter()
+            .zip(previous.metrics())
+            .filter_map(|((metric, current), (_, previous))| {
+                let (current, previous) = (current as f64, previous as f64);
+
+                (current > 0.0
+                    && previous > 0.0
+                    && (current >= previous * factor || previous >= current * factor))
+                    .then_some(metric)
+            })
+            .collect()
+    }
+}
+
+impl Display for TableMapperRun {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "records read: {}, inserted: {}, updated: {}, deleted: {}",
+            self.records_read, self.inserted, self.updated, self.deleted,
+        )
+    }
+}
+
+/// Change of each metric of the run from the previous run, such as `records read: 120 (+20)`.
+struct TableMapperRunDelta<'a> {
+    current: &'a TableMapperRun,
+    previous: &'a TableMapperRun,
+}
+
+impl Display for TableMapperRunDelta<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, ((metric, current), (_, previous))) in self
+            .current
+            .metrics()
+            .into_iter()
+            .zip(self.previous.metrics())
+            .enumerate()
+        {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(
+                f,
+                "{}: {} ({:+})",
+                metric,
+                current,
+                current as i128 - previous as i128
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads the statistics of the previous successful execution of the table mapper, creating the run
+/// history table if it does not exist, or adding the columns it lacks.
+pub async fn load_previous(
+    client: &mut impl SqlBackend,
+    import_profile_name: &str,
+    table_mapper_name: &str,
+) -> Result<Option<TableMapperRun>, tiberius::error::Error> {
+    let statement = formatdoc!(
+        "
+        IF OBJECT_ID(N'{run_history_table}', N'U') IS NULL
+        BEGIN
+            CREATE TABLE {run_history_table} (
+                [run_id] UNIQUEIDENTIFIER NOT NULL,
+                [import_profile] NVARCHAR(256) NOT NULL,
+                [table_mapper] NVARCHAR(128) NOT NULL,
+                [records_read] BIGINT NOT NULL,
+                [inserted] BIGINT NOT NULL,
+                [updated] BIGINT NOT NULL,
+                [deleted] BIGINT NOT NULL,
+                [completed_at] DATETIME2 NOT NULL DEFAULT SYSUTCDATETIME(),
+                INDEX [IX_run_history_table_mapper] ([import_profile], [table_mapper], [completed_at])
+            )
+        END
+        ELSE IF COL_LENGTH(N'{run_history_table}', N'deleted') IS NULL
+        BEGIN
+            ALTER TABLE {run_history_table} ADD [deleted] BIGINT NOT NULL
+                CONSTRAINT [DF_run_history_deleted] DEFAULT 0
+        END
+        ",
+        run_history_table = RUN_HISTORY_TABLE,
+    );
+
+    trace_sql!(statement);
+
+    client.execute(&statement, &[]).await?;
+
+    let statement = formatdoc!(
+        "
+        SELECT TOP (1) [records_read], [inserted], [updated], [deleted]
+        FROM {run_history_table}
+        WHERE [import_profile] = @P1 AND [table_mapper] = @P2
+        ORDER BY [completed_at] DESC
+        ",
+        run_history_table = RUN_HISTORY_TABLE,
+    );
+
+    trace_sql!(statement);
+
+    let previous = client
+        .query(&statement, &[&import_profile_name, &table_mapper_name])
+        .await?
+        .first()
+        .map(|row| {
+            let metric = |index| row.get::<i64>(index).unwrap_or_default().max(0) as u64;
+
+            TableMapperRun {
+                records_read: metric(0),
+                inserted: metric(1),
+                updated: metric(2),
+                deleted: metric(3),
+            }
+        });
+
+    Ok(previous)
+}
+
+/// Records the statistics of the execution of the table mapper.
+pub async fn save(
+    client: &mut impl SqlBackend,
+    import_profile_name: &str,
+    table_mapper_name: &str,
+    run_id: &str,
+    run: &TableMapperRun,
+) -> Result<(), tiberius::error::Error> {
+    let statement = formatdoc!(
+        "
+        INSERT INTO {run_history_table} ([run_id], [import_profile], [table_mapper], [records_read], [inserted], [updated], [deleted])
+        VALUES (CAST(@P1 AS UNIQUEIDENTIFIER), @P2, @P3, @P4, @P5, @P6, @P7)
+        ",
+        run_history_table = RUN_HISTORY_TABLE,
+    );
+
+    trace_sql!(statement);
+
+    client
+        .execute(
+            &statement,
+            &[
+                &run_id,
+                &import_profile_name,
+                &table_mapper_name,
+                &(run.records_read as i64),
+                &(run.inserted as i64),
+                &(run.updated as i64),
+                &(run.deleted as i64),
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Logs the change of the execution of the table mapper from the previous execution, warning of
+/// each metric which changed by at least the anomaly factor.
+pub fn report(
+    table_mapper_name: &str,
+    run: &TableMapperRun,
+    previous: Option<&TableMapperRun>,
+    anomaly_factor: f64,
+) {
+    let Some(previous) = previous else {
+        info!(
+            "No previous run of table mapper {} to compare with ({})",
+            table_mapper_name, run,
+        );
+
+        return;
+    };
+
+    info!(
+        "Table mapper {} compared with its previous run: {}",
+        table_mapper_name,
+        TableMapperRunDelta {
+            current: run,
+            previous,
+        },
+    );
+
+    for metric in run.anomalies(previous, anomaly_factor) {
+        warn!(
+            "Table mapper {} {} changed by a factor of {} or more from its previous run ({} vs {}), check the data source",
+            table_mapper_name, metric, anomaly_factor, run, previous,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use tiberius::ColumnData;
+
+    #[test]
+    fn anomalies_and_deltas_against_previous_run() {
+        let previous = TableMapperRun {
+            records_read: 1000,
+            inserted: 10,
+            updated: 900,
+            deleted: 40,
+        };
+        let run = TableMapperRun {
+            records_read: 1100,
+            inserted: 200,
+            updated: 50,
+            deleted: 0,
+        };
+
+        assert_eq!(run.anomalies(&previous, 10.0), ["inserted", "updated"]);
+        assert_eq!(run.anomalies(&previous, 20.0), ["inserted"]);
+        assert!(run.anomalies(&TableMapperRun::default(), 10.0).is_empty());
+
+        assert_eq!(
+            TableMapperRunDelta {
+                current: &run,
+                previous: &previous,
+            }
+            .to_string(),
+            "records read: 1100 (+100), inserted: 200 (+190), updated: 50 (-850), deleted: 0 (-40)"
+        );
+    }
+
+    #[tokio::test]
+    async fn load_and_save_deleted_rows() -> Result<(), tiberius::error::Error> {
+        let mut backend = MockBackend::new();
+
+        backend.push_rows([QueryRow::new(
+            [1000, 10, 900, 40].map(|metric| ColumnData::I64(Some(metric))),
+        )]);
+
+        let previous = load_previous(&mut backend, "Countries", "Country").await?;
+
+        assert_eq!(
+            previous,
+            Some(TableMapperRun {
+                records_read: 1000,
+                inserted: 10,
+                updated: 900,
+                deleted: 40,
+            })
+        );
+        assert!(
+            backend.statements()[0]
+                .statement
+                .contains("ALTER TABLE [import].[run_history] ADD [deleted] BIGINT NOT NULL")
+        );
+
+        let run = TableMapperRun {
+            deleted: 7,
+            ..TableMapperRun::default()
+        };
+
+        save(
+            &mut backend,
+            "Countries",
+            "Country",
+            "c0ffee00-0000-4000-8000-000000000000",
+            &run,
+        )
+        .await?;
+
+        assert_eq!(backend.statements()[2].parameters[6], "I64(Some(7))");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use crate::signing;
+use crate::signing::SigningError;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Report of a run of an import profile, written as JSON once the run completes and optionally
+/// signed.
+#[derive(Debug, Serialize)]
+pub struct RunReport<'a> {
+    pub import_profile_name: &'a str,
+    /// SHA-256 digest of the import profile file, hex encoded
+    pub import_profile_sha256: &'a str,
+    pub run_id: &'a str,
+    pub succeeded: bool,
+    /// Error the run failed with, and the errors which caused it
+    pub error: Option<String>,
+    pub elapsed_seconds: f64,
+    pub table_mappers: usize,
+    pub completed_table_mappers: usize,
+    /// Database snapshot created before the import
+    pub snapshot: Option<&'a str>,
+    /// Tables the surrogate keys of the rows inserted by the run were captured into, alongside the
+    /// run id
+    pub surrogate_key_tables: Vec<&'a str>,
+}
+
+#[derive(Debug, Error)]
+pub enum WriteRunReportError {
+    #[error("could not serialize run report")]
+    Serialize(#[from] serde_json::Error),
+    #[error("could not write run report '{0}'")]
+    Write(PathBuf, #[source] std::io::Error),
+    #[error("could not sign run report")]
+    Sign(#[from] SigningError),
+}
+
+/// Writes the report to the path, signing it with the PEM encoded Ed25519 private key if given.
+pub fn write(
+    path: &Path,
+    report: &RunReport<'_>,
+    signing_key: Option<&Path>,
+) -> Result<(), WriteRunReportError> {
+    let content = serde_json::to_vec_pretty(report)?;
+
+    std::fs::write(path, &content)
+        .map_err(|err| WriteRunReportError::Write(path.to_owned(), err))?;
+
+    if let Some(signing_key) = signing_key {
+        signing::sign(path, &content, signing_key)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use crate::column_graph::{ColumnGraph, CreateColumnGraphError};
+use crate::identifier::{ColumnIdentifier, Identifier, TableIdentifier};
+use crate::import_options::ImportOptions;
+use crate::table_mapper::{Column, Table, TableMapper, TableMapperColumn};
+use rustc_hash::FxHashMap as HashMap;
+use std::iter::once;
+use thiserror::Error;
+use tiberius::BaseMetaDataColumn;
+
+/// Incompatibility of a table mapper with the schema of a target database, on which importing
+/// the table mapper would fail.
+#[derive(Debug, Error)]
+pub enum SchemaIncompatibility {
+    #[error("table mapper '{0}': table '{1}' does not exist")]
+    MissingTable(String, TableIdentifier),
+    #[error("table mapper '{}': column '{}' does not exist", .0, .1.full())]
+    MissingColumn(String, ColumnIdentifier),
+    #[error("table mapper '{0}': column graph could not be created")]
+    CreateColumnGraph(String, #[source] CreateColumnGraphError),
+}
+
+/// Checks the table mapper against the metadata of the tables of a target database, reporting
+/// each missing table, mapped column and lookup column.
+pub fn check_table_mapper(
+    table_mapper: &TableMapper,
+    table_metadata: &HashMap<&TableIdentifier, HashMap<ColumnIdentifier, BaseMetaDataColumn>>,
+    import_options: &ImportOptions,
+) -> Vec<SchemaIncompatibility> {
+    let mut incompatibilities = Vec::new();
+    let mut missing_tables = Vec::<TableIdentifier>::new();
+
+    let table_exists = |table_identifier: &TableIdentifier| {
+        table_metadata
+            .get(table_identifier)
+            .is_some_and(|column_metadata| !column_metadata.is_empty())
+    };
+
+    if !table_exists(Table::identifier(table_mapper)) {
+        missing_tables.push(Table::identifier(table_mapper).to_owned());
+    }
+
+    let column_identifiers = table_mapper.columns().flat_map(|column| {
+        let lookup_column_identifiers = match column {
+            TableMapperColumn::Lookup(lookup_column) => lookup_column
+                .iter_key_columns()
+                .map(Column::identifier)
+                .chain(once(lookup_column.output_column_identifier()))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Column::map(column)
+            .then_some(Column::identifier(column))
+            .into_iter()
+            .chain(lookup_column_identifiers)
+    });
+
+    for column_identifier in column_identifiers {
+        let table_identifier = TableIdentifier::from(column_identifier);
+
+        if !table_exists(&table_identifier) {
+            if !missing_tables.contains(&table_identifier) {
+                missing_tables.push(table_identifier);
+            }
+        } else if !table_metadata[&table_identifier].contains_key(column_identifier) {
+            incompatibilities.push(SchemaIncompatibility::MissingColumn(
+                table_mapper.name().to_owned(),
+                column_identifier.to_owned(),
+            ));
+        }
+    }
+
+    // the column graph requires the metadata of every table of the table mapper
+    if missing_tables.is_empty()
+        && let Err(err) = ColumnGraph::new(table_mapper, table_metadata, import_options)
+    {
+        incompatibilities.push(SchemaIncompatibility::CreateColumnGraph(
+            table_mapper.name().to_owned(),
+            err,
+        ));
+    }
+
+    missing_tables
+        .into_iter()
+        .map(|table_identifier| {
+            SchemaIncompatibility::MissingTable(table_mapper.name().to_owned(), table_identifier)
+        })
+        .chain(incompatibilities)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table_mapper::table_mapper_raw::TableMapperRaw;
+    use tiberius::{FixedLenType, TypeInfo};
+
+    fn column_metadata(
+        table_identifier: &TableIdentifier,
+        columns: &[&str],
+    ) -> HashMap<ColumnIdentifier, BaseMetaDataColumn> {
+        columns
+            .iter()
+            .map(|column| {
+                (
+                    ColumnIdentifier::with_table(table_identifier, column).unwrap(),
+                    BaseMetaDataColumn {
+                        flags: Default::default(),
+                        ty: TypeInfo::FixedLen(FixedLenType::Int4),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn report_missing_tables_and_columns() -> Result<(), Box<dyn std::error::Error>> {
+        let raw: TableMapperRaw = serde_json::from_value(serde_json::json!({
+            "name": "Company",
+            "field_group": "Company",
+            "table_identifier": "[dbo].[Company]",
+            "delete_mode": "Partial",
+            "delete_action": "None",
+            "duplicate_action": "Reject",
+            "columns": [
+                { "Parser": { "column_identifier": "Code", "map_column": true, "field_name": "Code" } },
+                { "Parser": { "column_identifier": "Region", "map_column": true, "field_name": "Region" } },
+                { "Parser": { "column_identifier": "Note", "map_column": false, "field_name": "Note" } },
+                {
+                    "Lookup": {
+                        "column_identifier": "CountryID",
+                        "map_column": true,
+                        "table_identifier": "[dbo].[Country]",
+                        "output_column_identifier": "ID",
+                        "key_columns": [
+                            { "ParserKeyColumn": { "key_column_identifier": "Code", "field_name": "CountryCode" } }
+                        ]
+                    }
+                }
+            ],
+            "key_columns": ["Code"]
+        }))?;
+
+        let table_mapper = TableMapper::new(raw, None)?;
+        let company: TableIdentifier = "[dbo].[Company]".parse()?;
+        let country: TableIdentifier = "[dbo].[Country]".parse()?;
+        let import_options = ImportOptions::default();
+
+        let report = |table_metadata| {
+            check_table_mapper(&table_mapper, &table_metadata, &import_options)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            report(HashMap::from_iter([
+                (
+                    &company,
+                    column_metadata(&company, &["Code", "Region", "CountryID"])
+                ),
+                (&country, column_metadata(&country, &["ID", "Code"])),
+            ])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            report(HashMap::from_iter([
+                (&company, column_metadata(&company, &["Code", "CountryID"])),
+                (&country, column_metadata(&country, &["ID", "CountryCode"])),
+            ])),
+            [
+                "table mapper 'Company': column '[dbo].[Company].[Region]' does not exist",
+                "table mapper 'Company': column '[dbo].[Country].[Code]' does not exist",
+            ]
+        );
+        assert_eq!(
+            report(HashMap::from_iter([(
+                &company,
+                column_metadata(&company, &["Code", "Region", "CountryID"])
+            )])),
+            ["table mapper 'Company': table '[dbo].[Country]' does not exist"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::data_source::DataSourceRecord;
+use crate::table_mapper::{FieldColumn, SequenceColumn, TableMapper, TableMapperColumn};
+use rustc_hash::FxHashMap as HashMap;
+use std::sync::Arc;
+
+/// Numbers the records read from a data source for the sequence columns of a table mapper, adding
+/// the number of each sequence column to each record as a field.
+#[derive(Debug)]
+pub struct Sequencer<'a> {
+    sequences: Vec<Sequence<'a>>,
+}
+
+#[derive(Debug)]
+struct Sequence<'a> {
+    column: &'a SequenceColumn,
+    field_name: Arc<str>,
+    /// Next number of each group, keyed by the values of the group fields
+    next: HashMap<Box<[Option<Box<str>>]>, i64>,
+}
+
+impl<'a> Sequencer<'a> {
+    /// Returns `None` if the table mapper has no sequence columns.
+    pub fn new(table_mapper: &'a TableMapper) -> Option<Self> {
+        let sequences = table_mapper
+            .columns()
+            .filter_map(|column| match column {
+                TableMapperColumn::Sequence(sequence_column) => Some(Sequence {
+                    column: sequence_column,
+                    field_name: Arc::from(sequence_column.field_name()),
+                    next: HashMap::default(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        (!sequences.is_empty()).then_some(Sequencer { sequences })
+    }
+
+    pub fn assign(&mut self, record: &mut DataSourceRecord) {
+        for sequence in &mut self.sequences {
+            let group = sequence
+                .column
+                .group_fields()
+                .iter()
+                .map(|field| record.get(field).map(Box::from))
+                .collect::<Box<[_]>>();
+
+            let next = sequence
+                .next
+                .entry(group)
+                .or_insert(sequence.column.start());
+
+            record
+                .fields_mut()
+                .insert(sequence.field_name.clone(), &next.to_string());
+
+            *next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSourceRecordIndex;
+    use crate::data_source::string_map::StringMap;
+    use crate::identifier::ColumnIdentifier;
+    use std::num::NonZero;
+
+    fn record(record_number: u64, order: &str) -> DataSourceRecord {
+        DataSourceRecord::new(
+            StringMap::from([("order", order)]),
+            DataSourceRecordIndex {
+                record_number: NonZero::new(record_number).unwrap(),
+                line_start: record_number,
+                line_end: record_number,
+                byte_start: 0,
+                byte_end: 0,
+            },
+        )
+    }
+
+    fn numbers(sequence_column: &SequenceColumn, orders: &[&str]) -> Vec<String> {
+        let mut sequencer = Sequencer {
+            sequences: vec![Sequence {
+                column: sequence_column,
+                field_name: Arc::from(sequence_column.field_name()),
+                next: HashMap::default(),
+            }],
+        };
+
+        orders
+            .iter()
+            .zip(1..)
+            .map(|(order, record_number)| {
+                let mut record = record(record_number, order);
+
+                sequencer.assign(&mut record);
+
+                record.get(sequence_column.field_name()).unwrap().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numbers_records_of_run() {
+        let column_identifier: ColumnIdentifier = "[dbo].[OrderLine].[LineNo]".parse().unwrap();
+        let sequence_column = SequenceColumn::new(&column_identifier, true, 10, vec![]);
+
+        assert_eq!(
+            numbers(&sequence_column, &["A", "A", "B", "A"]),
+            ["10", "11", "12", "13"],
+        );
+    }
+
+    #[test]
+    fn numbers_records_of_group() {
+        let column_identifier: ColumnIdentifier = "[dbo].[OrderLine].[LineNo]".parse().unwrap();
+        let sequence_column =
+            SequenceColumn::new(&column_identifier, true, 1, vec!["order".to_owned()]);
+
+        assert_eq!(
+            numbers(&sequence_column, &["A", "A", "B", "A", "B"]),
+            ["1", "2", "1", "3", "2"],
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::backend::SqlBackend;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::import_options::ImportOptions;
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::trace;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Outcome of a run of an import profile, reported to SQL Server so that it is visible to DBAs
+/// monitoring the server without access to the application logs.
+#[derive(Debug)]
+pub struct ServerSummary<'a> {
+    pub import_profile_name: &'a str,
+    pub run_id: &'a str,
+    pub table_mappers: usize,
+    pub completed_table_mappers: usize,
+    pub elapsed: Duration,
+    pub succeeded: bool,
+    /// Database snapshot created before the import, which the database may be reverted to
+    pub snapshot: Option<&'a str>,
+}
+
+impl Display for ServerSummary<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sql_bulk_import_profile: import profile '{}' {} in {:.1}s, {} of {} table mappers completed (run {})",
+            self.import_profile_name,
+            if self.succeeded {
+                "succeeded"
+            } else {
+                "failed"
+            },
+            self.elapsed.as_secs_f64(),
+            self.completed_table_mappers,
+            self.table_mappers,
+            self.run_id,
+        )?;
+
+        if let Some(snapshot) = self.snapshot {
+            write!(f, ", database snapshot [{}]", snapshot)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the summary to the SQL Server error log, and executes the summary procedure with it, as
+/// configured by the import options.
+pub async fn report(
+    client: &mut impl SqlBackend,
+    summary: &ServerSummary<'_>,
+    import_options: &ImportOptions,
+) -> Result<(), tiberius::error::Error> {
+    let message = summary.to_string();
+
+    if import_options.server_log_summary {
+        // the message is a substitution argument, such that it is not interpreted as a format
+        // string, and requires sysadmin or ALTER TRACE permission to be written to the error log
+        let statement = "RAISERROR(N'%s', 0, 1, @P1) WITH LOG";
+
+        trace_sql!(statement);
+
+        client.execute(statement, &[&message]).await?;
+    }
+
+    if let Some(summary_procedure) = &import_options.summary_procedure {
+        execute_summary_procedure(client, summary_procedure, summary, &message).await?;
+    }
+
+    Ok(())
+}
+
+async fn execute_summary_procedure(
+    client: &mut impl SqlBackend,
+    summary_procedure: &TableIdentifier,
+    summary: &ServerSummary<'_>,
+    message: &str,
+) -> Result<(), tiberius::error::Error> {
+    let statement = formatdoc!(
+        "
+        EXEC {procedure_name}
+            @ProfileName = @P1,
+            @RunId = @P2,
+            @Succeeded = @P3,
+            @Summary = @P4
+        ",
+        procedure_name = summary_procedure.full(),
+    );
+
+    trace_sql!(statement);
+
+    client
+        .execute(
+            &statement,
+            &[
+                &summary.import_profile_name,
+                &summary.run_id,
+                &summary.succeeded,
+                &message,
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+
+    #[tokio::test]
+    async fn report_to_error_log_and_procedure() -> Result<(), tiberius::error::Error> {
+        let mut backend = MockBackend::new();
+        let import_options = ImportOptions {
+            server_log_summary: true,
+            summary_procedure: Some("[dbo].[ImportSummary]".parse().unwrap()),
+            ..ImportOptions::default()
+        };
+        let summary = ServerSummary {
+            import_profile_name: "Countries",
+            run_id: "c0ffee00-0000-4000-8000-000000000000",
+            table_mappers: 2,
+            completed_table_mappers: 1,
+            elapsed: Duration::from_millis(1500),
+            succeeded: false,
+            snapshot: Some("Countries_import_0"),
+        };
+
+        report(&mut backend, &summary, &import_options).await?;
+
+        let statements = backend.statements();
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(
+            statements[0].statement,
+            "RAISERROR(N'%s', 0, 1, @P1) WITH LOG"
+        );
+        assert!(
+            statements[1]
+                .statement
+                .starts_with("EXEC [dbo].[ImportSummary]")
+        );
+        assert_eq!(statements[1].parameters.len(), 4);
+        assert!(
+            summary
+                .to_string()
+                .contains("import profile 'Countries' failed in 1.5s, 1 of 2 table mappers")
+        );
+        assert!(
+            summary
+                .to_string()
+                .ends_with(", database snapshot [Countries_import_0]")
+        );
+
+        Ok(())
+    }
+}
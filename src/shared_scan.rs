@@ -0,0 +1,487 @@
+use crate::data_source::{
+    DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, DataSourceStreamItem,
+    ReadRecordError,
+};
+use crate::record_accounting::RecordAccounting;
+use futures::future::Either;
+use futures::{Stream, StreamExt, stream};
+use log::warn;
+use rustc_hash::FxHashMap as HashMap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::NonZero;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Records sent to the spool writer but not yet written, bounding the records held in memory
+const SPOOL_CAPACITY: usize = 1024;
+
+/// Identifies the spool files of the shared scans of the process
+static SPOOL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Records of each field group read by its first table mapper, spooled to a temporary file and
+/// replayed to its later table mappers.
+#[derive(Debug, Default)]
+pub struct SharedScan {
+    field_groups: RefCell<HashMap<String, FieldGroupScan>>,
+}
+
+#[derive(Debug, Default)]
+struct FieldGroupScan {
+    /// Table mappers yet to read the field group
+    readers: usize,
+    scanned: Option<Rc<ScannedRecords>>,
+}
+
+/// Spool file of the records of a field group read from the data source, and the number of
+/// records the data source rejected meanwhile, removed once no longer replayed.
+#[derive(Debug)]
+pub(crate) struct ScannedRecords {
+    path: PathBuf,
+    records: u64,
+    rejected: u64,
+}
+
+/// Source of the records of a table mapper of a shared scan.
+pub(crate) enum Scan<'a> {
+    /// Records read by an earlier table mapper of the field group
+    Replay(Rc<ScannedRecords>),
+    /// Records read from the data source, spooled for the later table mappers of the field group
+    Record(&'a SharedScan, &'a str),
+}
+
+/// Record as written to a spool file, one per line.
+#[derive(Serialize, Deserialize)]
+struct SpooledRecord<'a> {
+    index: (NonZero<u64>, u64, u64, u64, u64),
+    #[serde(borrow)]
+    fields: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+/// Records sent to a spool file by the table mapper recording the field group.
+struct Spool {
+    path: PathBuf,
+    sender: mpsc::Sender<DataSourceRecord>,
+    writer: JoinHandle<std::io::Result<u64>>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("could not read spooled records, line: {0}")]
+    Read(u64, #[source] std::io::Error),
+    #[error("malformed spooled record, line: {0}")]
+    Malformed(u64, #[source] serde_json::Error),
+}
+
+impl SharedScan {
+    /// Shared scan of the field groups read by the table mappers, given the field group of each.
+    pub fn new<'a>(field_groups: impl IntoIterator<Item = &'a str>) -> Self {
+        let shared_scan = SharedScan::default();
+
+        for field_group in field_groups {
+            shared_scan
+                .field_groups
+                .borrow_mut()
+                .entry(field_group.to_owned())
+                .or_default()
+                .readers += 1;
+        }
+
+        shared_scan
+    }
+
+    /// Number of records of the field group, including those rejected, if read by an earlier
+    /// table mapper.
+    pub fn records(&self, field_group: &str) -> Option<u64> {
+        self.field_groups
+            .borrow()
+            .get(field_group)
+            .and_then(|field_group_scan| field_group_scan.scanned.as_ref())
+            .map(|scanned| scanned.records + scanned.rejected)
+    }
+
+    /// Source of the records of the next table mapper of the field group, releasing the records
+    /// once the last table mapper of the field group replays them, or `None` if no later table
+    /// mapper reads the field group.
+    pub(crate) fn scan<'a>(&'a self, field_group: &'a str) -> Option<Scan<'a>> {
+        let mut field_groups = self.field_groups.borrow_mut();
+        let field_group_scan = field_groups.get_mut(field_group)?;
+
+        field_group_scan.readers = field_group_scan.readers.saturating_sub(1);
+
+        match field_group_scan.scanned.clone() {
+            Some(scanned) => {
+                if field_group_scan.readers == 0 {
+                    field_group_scan.scanned = None;
+                }
+
+                Some(Scan::Replay(scanned))
+            }
+            None if field_group_scan.readers > 0 => Some(Scan::Record(self, field_group)),
+            None => None,
+        }
+    }
+
+    /// Spools the records read by the stream through a bounded channel, keeping the spool file
+    /// for the later table mappers of the field group once the stream ends, unless the stream or
+    /// spooling fails, in which case the next table mapper reads the data source again.
+    pub(crate) fn record<'a>(
+        &'a self,
+        field_group: &'a str,
+        records: impl Stream<Item = DataSourceStreamItem> + 'a,
+        record_accounting: Rc<RecordAccounting>,
+    ) -> impl Stream<Item = DataSourceStreamItem> + 'a {
+        stream::unfold(
+            (Box::pin(records), Spool::create()),
+            move |(mut records, mut spool)| {
+                let record_accounting = record_accounting.clone();
+
+                async move {
+                    match records.next().await {
+                        Some(Ok(record)) => {
+                            // a failed spool writer drops its receiver, abandoning the spool
+                            if let Some(active) = &spool
+                                && active.sender.send(record.clone()).await.is_err()
+                            {
+                                if let Some(spool) = spool.take() {
+                                    spool.abandon();
+                                }
+                            }
+
+                            Some((Ok(record), (records, spool)))
+                        }
+                        Some(Err(err)) => {
+                            if let Some(spool) = spool {
+                                spool.abandon();
+                            }
+
+                            Some((Err(err), (records, None)))
+                        }
+                        None => {
+                            if let Some(spool) = spool
+                                && let Some(scanned) =
+                                    spool.finish(record_accounting.records_rejected()).await
+                                && let Some(field_group_scan) =
+                                    self.field_groups.borrow_mut().get_mut(field_group)
+                            {
+                                field_group_scan.scanned = Some(Rc::new(scanned));
+                            }
+
+                            None
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
+
+impl Spool {
+    /// Spool file written by a blocking task, receiving the records through a bounded channel, or
+    /// `None` if the spool file cannot be created.
+    fn create() -> Option<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "shared_scan_{}_{}.jsonl",
+            std::process::id(),
+            SPOOL_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file = match File::create(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!(
+                    "Could not create shared scan spool file {}: {}",
+                    path.display(),
+                    err
+                );
+
+                return None;
+            }
+        };
+
+        let (sender, mut receiver) = mpsc::channel::<DataSourceRecord>(SPOOL_CAPACITY);
+
+        let writer = tokio::task::spawn_blocking(move || {
+            let mut file = BufWriter::new(file);
+            let mut records = 0;
+
+            while let Some(record) = receiver.blocking_recv() {
+                let index = record.index();
+
+                let spooled = SpooledRecord {
+                    index: (
+                        index.record_number,
+                        index.line_start,
+                        index.line_end,
+                        index.byte_start,
+                        index.byte_end,
+                    ),
+                    fields: record
+                        .iter()
+                        .map(|(name, value)| (Cow::Borrowed(&**name), Cow::Borrowed(value)))
+                        .collect(),
+                };
+
+                serde_json::to_writer(&mut file, &spooled)?;
+                file.write_all(b"\n")?;
+
+                records += 1;
+            }
+
+            file.flush()?;
+
+            Ok(records)
+        });
+
+        Some(Spool {
+            path,
+            sender,
+            writer,
+        })
+    }
+
+    /// Waits for the spool writer to write the remaining records, or `None` if it failed.
+    async fn finish(self, rejected: u64) -> Option<ScannedRecords> {
+        drop(self.sender);
+
+        let written = self.writer.await;
+
+        // the spool file is removed once dropped, even if only partly written
+        let mut scanned = ScannedRecords {
+            path: self.path,
+            records: 0,
+            rejected,
+        };
+
+        match written {
+            Ok(Ok(records)) => {
+                scanned.records = records;
+
+                Some(scanned)
+            }
+            Ok(Err(err)) => {
+                warn!(
+                    "Could not spool shared scan to {}: {}",
+                    scanned.path.display(),
+                    err
+                );
+
+                None
+            }
+            Err(err) => {
+                warn!("Shared scan spool writer failed: {}", err);
+
+                None
+            }
+        }
+    }
+
+    /// Stops spooling, removing the spool file once the spool writer ends.
+    fn abandon(self) {
+        drop(self.sender);
+
+        let path = self.path;
+
+        tokio::spawn(async move {
+            let _ = self.writer.await;
+            let _ = tokio::fs::remove_file(path).await;
+        });
+    }
+}
+
+impl ScannedRecords {
+    /// Replays the spooled records, counting the records rejected by the data source as rejected
+    /// again.
+    pub(crate) fn replay(
+        self: Rc<Self>,
+        record_accounting: &RecordAccounting,
+    ) -> impl Stream<Item = Result<DataSourceRecord, ReplayError>> + use<> {
+        for _ in 0..self.rejected {
+            record_accounting.rejected();
+        }
+
+        stream::once(tokio::fs::File::open(self.path.clone()))
+            .map(move |file| {
+                let scanned = self.clone();
+
+                match file {
+                    Ok(file) => Either::Left(stream::unfold(
+                        (BufReader::new(file).lines(), 0, scanned),
+                        |(mut lines, line, scanned)| async move {
+                            let line = line + 1;
+
+                            match lines.next_line().await {
+                                Ok(Some(spooled)) => {
+                                    let record = parse_spooled(&spooled, line);
+
+                                    Some((record, (lines, line, scanned)))
+                                }
+                                Ok(None) => None,
+                                Err(err) => Some((
+                                    Err(ReplayError::Read(line, err)),
+                                    (lines, line, scanned),
+                                )),
+                            }
+                        },
+                    )),
+                    Err(err) => Either::Right(stream::iter([Err(ReplayError::Read(0, err))])),
+                }
+            })
+            .flatten()
+    }
+}
+
+impl Drop for ScannedRecords {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!(
+                "Could not remove shared scan spool file {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// Parses a line of a spool file back into the record written.
+fn parse_spooled(spooled: &str, line: u64) -> Result<DataSourceRecord, ReplayError> {
+    let SpooledRecord {
+        index: (record_number, line_start, line_end, byte_start, byte_end),
+        fields,
+    } = serde_json::from_str(spooled).map_err(|err| ReplayError::Malformed(line, err))?;
+
+    Ok(DataSourceRecord::new(
+        fields.into_iter().collect(),
+        DataSourceRecordIndex {
+            record_number,
+            line_start,
+            line_end,
+            byte_start,
+            byte_end,
+        },
+    ))
+}
+
+impl ReadRecordError for ReplayError {
+    fn index(&self) -> DataSourceErrorIndex {
+        match self {
+            ReplayError::Read(line, _) | ReplayError::Malformed(line, _) => DataSourceErrorIndex {
+                record_number: None,
+                line_number: *line,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    fn record(record_number: u64) -> DataSourceRecord {
+        DataSourceRecord::new(
+            [("Id", record_number.to_string())].into(),
+            DataSourceRecordIndex {
+                record_number: NonZero::new(record_number).unwrap(),
+                line_start: record_number,
+                line_end: record_number,
+                byte_start: 0,
+                byte_end: 0,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn replay_records_to_later_table_mappers() {
+        let shared_scan = SharedScan::new(["Orders", "Customers", "Orders", "Orders"]);
+
+        assert!(shared_scan.scan("Customers").is_none());
+
+        let Some(Scan::Record(shared_scan, field_group)) = shared_scan.scan("Orders") else {
+            panic!("first table mapper should record");
+        };
+
+        let record_accounting = Rc::new(RecordAccounting::default());
+
+        record_accounting.rejected();
+
+        let records: Vec<_> = shared_scan
+            .record(
+                field_group,
+                stream::iter([Ok(record(1)), Ok(record(3))]),
+                record_accounting,
+            )
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(shared_scan.records("Orders"), Some(3));
+
+        let path = shared_scan.field_groups.borrow()["Orders"]
+            .scanned
+            .as_ref()
+            .map(|scanned| scanned.path.clone())
+            .unwrap();
+
+        assert!(path.exists());
+
+        for _ in 0..2 {
+            let Some(Scan::Replay(scanned)) = shared_scan.scan("Orders") else {
+                panic!("later table mappers should replay");
+            };
+
+            let record_accounting = RecordAccounting::default();
+            let replayed: Vec<_> = scanned
+                .replay(&record_accounting)
+                .map_ok(|record| record.get("Id").unwrap().to_owned())
+                .try_collect()
+                .await
+                .unwrap();
+
+            assert_eq!(replayed, ["1", "3"]);
+            assert_eq!(record_accounting.records_rejected(), 1);
+        }
+
+        // released once replayed to the last table mapper
+        assert_eq!(shared_scan.records("Orders"), None);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn read_again_after_failed_scan() {
+        let shared_scan = SharedScan::new(["Orders", "Orders", "Orders"]);
+
+        let Some(Scan::Record(shared_scan, field_group)) = shared_scan.scan("Orders") else {
+            panic!("first table mapper should record");
+        };
+
+        let error: Box<dyn ReadRecordError> =
+            Box::new(ReplayError::Read(1, std::io::Error::other("interrupted")));
+
+        let records: Vec<_> = shared_scan
+            .record(
+                field_group,
+                stream::iter([Ok(record(1)), Err(error)]),
+                Rc::new(RecordAccounting::default()),
+            )
+            .collect()
+            .await;
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(shared_scan.records("Orders"), None);
+
+        assert!(matches!(
+            shared_scan.scan("Orders"),
+            Some(Scan::Record(_, "Orders"))
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::import_profile::{CreateImportProfileError, ImportProfile};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, SignatureError, Signer, SigningKey, VerifyingKey};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SigningError {
+    #[error("could not read key file '{0}'")]
+    ReadKey(PathBuf, #[source] io::Error),
+    #[error("key file '{0}' is not a PEM encoded Ed25519 key")]
+    DecodeKey(PathBuf),
+    #[error("could not read '{0}'")]
+    ReadFile(PathBuf, #[source] io::Error),
+    #[error("could not read signature file '{0}'")]
+    ReadSignature(PathBuf, #[source] io::Error),
+    #[error("signature file '{0}' is not an Ed25519 signature")]
+    InvalidSignature(PathBuf, #[source] SignatureError),
+    #[error("signature of '{0}' does not match its content")]
+    VerificationFailed(PathBuf, #[source] SignatureError),
+    #[error("could not write signature file '{0}'")]
+    WriteSignature(PathBuf, #[source] io::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum OpenImportProfileError {
+    #[error("could not read import profile '{0}'")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("could not verify import profile")]
+    Verify(#[from] SigningError),
+    #[error("could not create import profile")]
+    Create(#[from] CreateImportProfileError),
+}
+
+/// Path of the detached signature of the file, the path of the file suffixed with `.sig`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = OsString::from(path.as_os_str());
+
+    signature_path.push(".sig");
+    signature_path.into()
+}
+
+/// Verifies the file against its raw 64 byte detached signature with the PEM encoded Ed25519
+/// public key, returning the verified content.
+pub fn verify(path: &Path, verifying_key: &Path) -> Result<Vec<u8>, SigningError> {
+    let pem = fs::read_to_string(verifying_key)
+        .map_err(|err| SigningError::ReadKey(verifying_key.to_owned(), err))?;
+    let verifying_key = VerifyingKey::from_public_key_pem(&pem)
+        .map_err(|_| SigningError::DecodeKey(verifying_key.to_owned()))?;
+
+    let content = fs::read(path).map_err(|err| SigningError::ReadFile(path.to_owned(), err))?;
+    let signature_path = signature_path(path);
+    let signature = fs::read(&signature_path)
+        .map_err(|err| SigningError::ReadSignature(signature_path.clone(), err))?;
+    let signature = Signature::from_slice(&signature)
+        .map_err(|err| SigningError::InvalidSignature(signature_path, err))?;
+
+    verifying_key
+        .verify_strict(&content, &signature)
+        .map_err(|err| SigningError::VerificationFailed(path.to_owned(), err))?;
+
+    Ok(content)
+}
+
+/// Opens the import profile, verified with the verifying key if any, creating it from the content
+/// verified rather than reading the file again.
+pub async fn open_import_profile(
+    path: &Path,
+    verifying_key: Option<&Path>,
+) -> Result<ImportProfile, OpenImportProfileError> {
+    let Some(verifying_key) = verifying_key else {
+        let content =
+            fs::read(path).map_err(|err| OpenImportProfileError::Read(path.to_owned(), err))?;
+
+        return Ok(ImportProfile::new(Cursor::new(content)).await?);
+    };
+
+    Ok(ImportProfile::signed(Cursor::new(verify(path, verifying_key)?)).await?)
+}
+
+/// Signs the content with the PEM encoded Ed25519 private key, writing the detached signature
+/// alongside the file at the path, returning the path of the signature.
+pub fn sign(path: &Path, content: &[u8], signing_key: &Path) -> Result<PathBuf, SigningError> {
+    let pem = fs::read_to_string(signing_key)
+        .map_err(|err| SigningError::ReadKey(signing_key.to_owned(), err))?;
+    let signing_key = SigningKey::from_pkcs8_pem(&pem)
+        .map_err(|_| SigningError::DecodeKey(signing_key.to_owned()))?;
+
+    let signature_path = signature_path(path);
+
+    fs::write(&signature_path, signing_key.sign(content).to_bytes())
+        .map_err(|err| SigningError::WriteSignature(signature_path.clone(), err))?;
+
+    Ok(signature_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
+
+    #[test]
+    fn sign_and_verify() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir();
+        let path = directory.join(format!("signed_{}.json", std::process::id()));
+        let signing_key_path = directory.join(format!("signing_{}.pem", std::process::id()));
+        let verifying_key_path = directory.join(format!("verifying_{}.pem", std::process::id()));
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+
+        fs::write(
+            &signing_key_path,
+            signing_key.to_pkcs8_pem(LineEnding::LF)?.as_bytes(),
+        )?;
+        fs::write(
+            &verifying_key_path,
+            signing_key
+                .verifying_key()
+                .to_public_key_pem(LineEnding::LF)?,
+        )?;
+        fs::write(&path, b"{\"name\": \"Countries\"}")?;
+
+        let signature_path = sign(&path, &fs::read(&path)?, &signing_key_path)?;
+
+        assert_eq!(
+            signature_path,
+            directory.join(format!("signed_{}.json.sig", std::process::id()))
+        );
+        assert!(verify(&path, &verifying_key_path).is_ok());
+
+        fs::write(&path, b"{\"name\": \"Currencies\"}")?;
+
+        assert!(matches!(
+            verify(&path, &verifying_key_path),
+            Err(SigningError::VerificationFailed(..))
+        ));
+
+        for path in [path, signature_path, signing_key_path, verifying_key_path] {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use crate::backend::SqlBackend;
+use crate::import_options::ImportOptions;
+use crate::import_profile::ImportProfile;
+use crate::table_mapper::DeleteMode;
+use crate::trace_sql;
+use indoc::indoc;
+use log::{info, trace};
+
+/// Whether a snapshot of the database is created before the import profile is executed, that is
+/// when requested and a table mapper has the Full delete mode.
+pub fn required(import_profile: &ImportProfile, import_options: &ImportOptions) -> bool {
+    import_options.snapshot_before_delete
+        && !import_options.no_merge
+        && import_profile.table_mappers().iter().any(|table_mapper| {
+            table_mapper.delete_mode() == DeleteMode::Full
+                && !import_options
+                    .with_override(table_mapper.import_options_override())
+                    .no_merge
+        })
+}
+
+/// Creates a snapshot of the database named after the database and the run, returning the name of
+/// the snapshot.
+pub async fn create(
+    client: &mut impl SqlBackend,
+    run_id: &str,
+) -> Result<String, tiberius::error::Error> {
+    let statement = indoc! {"
+        DECLARE @snapshot sysname = DB_NAME() + N'_import_' + REPLACE(@P1, N'-', N'')
+        DECLARE @files nvarchar(max)
+
+        SELECT @files = STRING_AGG(
+            CONVERT(nvarchar(max), N'(NAME = ' + QUOTENAME(name) + N', FILENAME = N'''
+                + REPLACE(physical_name + N'.' + @snapshot + N'.ss', N'''', N'''''') + N''')'),
+            N', '
+        )
+        FROM sys.database_files
+        WHERE type = 0
+
+        EXEC (N'CREATE DATABASE ' + QUOTENAME(@snapshot) + N' ON ' + @files
+            + N' AS SNAPSHOT OF ' + QUOTENAME(DB_NAME()))
+
+        SELECT @snapshot
+    "};
+
+    trace_sql!(statement);
+
+    let snapshot = client
+        .query(statement, &[&run_id])
+        .await?
+        .first()
+        .and_then(|row| row.get::<&str>(0).map(str::to_owned))
+        .unwrap_or_default();
+
+    info!(
+        "Created database snapshot [{}], revert to it with RESTORE DATABASE ... FROM DATABASE_SNAPSHOT = N'{}'",
+        snapshot, snapshot,
+    );
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use std::io::Cursor;
+    use tiberius::ColumnData;
+
+    #[tokio::test]
+    async fn require_snapshot_for_merged_full_delete_mode() {
+        let import_profile = |delete_mode: &str, no_merge: bool| {
+            ImportProfile::new(Cursor::new(
+                serde_json::json!({
+                    "name": "Test",
+                    "data_source_config": {
+                        "DelimitedDataSourceConfig": {
+                            "path": "test.csv",
+                            "reader_config": "Csv",
+                            "field_groups": { "Group": [{ "name": "Code" }] }
+                        }
+                    },
+                    "table_mappers": [{
+                        "name": "Country",
+                        "field_group": "Group",
+                        "table_identifier": "[dbo].[Country]",
+                        "delete_mode": delete_mode,
+                        "delete_action": "None",
+                        "duplicate_action": "Dump",
+                        "import_options": { "no_merge": no_merge },
+                        "columns": [
+                            { "Parser": { "column_identifier": "[Code]", "map_column": true, "field_name": "Code" } }
+                        ],
+                        "key_columns": ["Code"]
+                    }]
+                })
+                .to_string(),
+            ))
+        };
+
+        let import_options = ImportOptions {
+            snapshot_before_delete: true,
+            ..ImportOptions::default()
+        };
+
+        let full = import_profile("Full", false).await.unwrap();
+
+        assert!(required(&full, &import_options));
+        assert!(!required(&full, &ImportOptions::default()));
+        assert!(!required(
+            &full,
+            &ImportOptions {
+                no_merge: true,
+                ..import_options.clone()
+            }
+        ));
+        assert!(!required(
+            &import_profile("Partial", false).await.unwrap(),
+            &import_options
+        ));
+        assert!(!required(
+            &import_profile("Full", true).await.unwrap(),
+            &import_options
+        ));
+    }
+
+    #[tokio::test]
+    async fn create_snapshot_named_after_run() -> Result<(), tiberius::error::Error> {
+        let mut backend = MockBackend::new();
+
+        backend.push_rows([QueryRow::new([ColumnData::String(Some(
+            "Sales_import_0f8fad5bd9cb469fa16570867728950e".into(),
+        ))])]);
+
+        let snapshot = create(&mut backend, "0f8fad5b-d9cb-469f-a165-70867728950e").await?;
+
+        assert_eq!(snapshot, "Sales_import_0f8fad5bd9cb469fa16570867728950e");
+
+        let statement = &backend.statements()[0];
+
+        assert!(statement.statement.contains(
+            "DECLARE @snapshot sysname = DB_NAME() + N'_import_' + REPLACE(@P1, N'-', N'')"
+        ));
+        assert!(
+            statement
+                .statement
+                .contains("' AS SNAPSHOT OF ' + QUOTENAME(DB_NAME()))")
+        );
+        assert_eq!(
+            statement.parameters,
+            [format!(
+                "{:?}",
+                ColumnData::String(Some("0f8fad5b-d9cb-469f-a165-70867728950e".into()))
+            )]
+        );
+
+        Ok(())
+    }
+}
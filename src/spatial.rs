@@ -0,0 +1,153 @@
+use crate::backend::SqlBackend;
+use crate::column_graph::{ColumnGraph, ColumnNode, UniqueColumnIdentifier};
+use crate::data_source::DataSourceRecord;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::table_mapper::{Spatial, SpatialType};
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace};
+use std::borrow::Cow;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SpatialError {
+    #[error("could not convert Well-Known Text into spatial values")]
+    ConvertFailed(#[source] tiberius::error::Error),
+}
+
+/// Temporary table column holding the Well-Known Text of a spatial column, into which the records
+/// are inserted, as spatial values cannot be bulk inserted.
+pub fn text_column(unique_identifier: &UniqueColumnIdentifier) -> String {
+    format!("[{}$wkt]", unique_identifier.part_unescaped())
+}
+
+/// Well-Known Text of the spatial value of a field, or `None` if its longitude or latitude is
+/// invalid or out of range.
+pub(crate) fn well_known_text<'a>(
+    spatial: &Spatial,
+    field_value: &'a str,
+    record: &DataSourceRecord,
+) -> Option<Cow<'a, str>> {
+    let Some(latitude_field) = spatial.latitude_field() else {
+        return Some(Cow::Borrowed(field_value));
+    };
+
+    let longitude = field_value.trim().parse::<f64>().ok()?;
+    let latitude = record.get(latitude_field)?.trim().parse::<f64>().ok()?;
+
+    let in_range = match spatial.spatial_type() {
+        SpatialType::Geography => {
+            (-180.0..=180.0).contains(&longitude) && (-90.0..=90.0).contains(&latitude)
+        }
+        SpatialType::Geometry => longitude.is_finite() && latitude.is_finite(),
+    };
+
+    in_range.then(|| Cow::Owned(format!("POINT({longitude} {latitude})")))
+}
+
+/// Converts the Well-Known Text inserted into the temporary table into the spatial columns, with
+/// the spatial reference identifier of each column.
+pub async fn convert(
+    client: &mut impl SqlBackend,
+    temporary_table: &TableIdentifier,
+    column_graph: &ColumnGraph,
+) -> Result<(), SpatialError> {
+    let statement = column_graph
+        .target_columns()
+        .filter_map(|column| match column.column() {
+            ColumnNode::ParserColumn {
+                column: parser_column,
+                ..
+            } => parser_column
+                .spatial()
+                .map(|spatial| (column.unique_identifier(), spatial)),
+            _ => None,
+        })
+        .map(|(unique_identifier, spatial)| {
+            formatdoc!(
+                "
+                UPDATE {temporary_table}
+                SET {column} = {spatial_type}::STGeomFromText({text_column}, {srid})
+                WHERE {text_column} IS NOT NULL;
+                ",
+                temporary_table = temporary_table.full(),
+                column = unique_identifier.part(),
+                spatial_type = spatial.spatial_type().keyword(),
+                text_column = text_column(unique_identifier),
+                srid = spatial.srid(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if statement.is_empty() {
+        return Ok(());
+    }
+
+    trace_sql!(statement);
+
+    client
+        .execute(&statement, &[])
+        .await
+        .map_err(SpatialError::ConvertFailed)?;
+
+    info!("Converted spatial columns of {}", temporary_table);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSourceRecordIndex;
+    use std::num::NonZero;
+
+    #[test]
+    fn point_from_longitude_and_latitude() {
+        let record = DataSourceRecord::new(
+            [("Latitude", " 51.5 "), ("Elevation", "91")].into(),
+            DataSourceRecordIndex {
+                record_number: NonZero::new(1).unwrap(),
+                line_start: 1,
+                line_end: 1,
+                byte_start: 0,
+                byte_end: 0,
+            },
+        );
+
+        let spatial = |spatial_type, latitude_field: &str| -> Spatial {
+            serde_json::from_value(serde_json::json!({
+                "spatial_type": spatial_type,
+                "latitude_field": latitude_field,
+            }))
+            .unwrap()
+        };
+
+        let wkt = |spatial: &Spatial, field_value| {
+            well_known_text(spatial, field_value, &record).map(Cow::into_owned)
+        };
+
+        let geography = spatial("Geography", "Latitude");
+
+        assert_eq!(
+            wkt(&geography, "-0.125"),
+            Some("POINT(-0.125 51.5)".to_owned())
+        );
+        assert_eq!(wkt(&geography, "181"), None);
+        assert_eq!(wkt(&geography, "west"), None);
+        assert_eq!(wkt(&spatial("Geography", "Missing"), "0"), None);
+        assert_eq!(
+            wkt(&spatial("Geometry", "Elevation"), "1e3"),
+            Some("POINT(1000 91)".to_owned())
+        );
+
+        let text: Spatial =
+            serde_json::from_value(serde_json::json!({ "spatial_type": "Geometry" })).unwrap();
+
+        assert_eq!(
+            wkt(&text, "LINESTRING(0 0, 1 1)"),
+            Some("LINESTRING(0 0, 1 1)".to_owned())
+        );
+        assert_eq!(text.srid(), 4326);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::avro_data_source::{format_date, format_time};
+use crate::data_source::string_map::StringMap;
+use crate::data_source::{
+    DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, ReadRecordError,
+};
+use crate::import_profile::Field;
+use crate::trace_sql;
+use futures::Stream;
+use futures::StreamExt;
+use indoc::formatdoc;
+use log::trace;
+use rust_decimal::Decimal;
+use std::fmt::Write;
+use std::num::NonZero;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use thiserror::Error;
+use tiberius::{Client, ColumnData, Config, QueryItem, Row};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+
+/// Query items buffered ahead of the records read, such that the source server is read while
+/// records are inserted into the temporary table.
+const QUERY_ITEM_BUFFER: usize = 1024;
+
+/// Days from 0001-01-01, the epoch of SQL Server dates, to 1970-01-01.
+const DATE_EPOCH_TO_UNIX_EPOCH: i64 = 719_162;
+
+/// Days from 1900-01-01, the epoch of `datetime` and `smalldatetime`, to 1970-01-01.
+const DATETIME_EPOCH_TO_UNIX_EPOCH: i64 = 25_567;
+
+#[derive(Debug, Error)]
+pub enum CreateSqlDataSourceError {
+    #[error("invalid connection string")]
+    InvalidConnectionString(#[source] tiberius::error::Error),
+    #[error("could not connect to the source server")]
+    ConnectError(#[source] tiberius::error::Error),
+    #[error("could not execute query")]
+    QueryError(#[source] tiberius::error::Error),
+    #[error("query did not return a result set")]
+    NoResultSet,
+    #[error("field '{0}' is not a column of the query result set")]
+    UnknownField(String),
+    #[error("could not count records of query")]
+    CountRecordsError(#[source] tiberius::error::Error),
+}
+
+#[derive(Debug, Error)]
+#[error("error reading SQL record ({index})")]
+pub struct SqlReadRecordError {
+    index: DataSourceErrorIndex,
+    #[source]
+    source: tiberius::error::Error,
+}
+
+impl ReadRecordError for SqlReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+/// SQL data source, streaming a record from each row of the first result set of a query executed
+/// against another SQL Server on a connection of its own.
+#[derive(Debug)]
+pub struct SqlDataSource {
+    receiver: mpsc::Receiver<Result<QueryItem, tiberius::error::Error>>,
+    /// Position of each field within the columns of the result set
+    fields: Vec<(usize, Arc<str>)>,
+    record_number: u64,
+    /// Whether the first result set has been read, later result sets being ignored
+    done: bool,
+}
+
+impl SqlDataSource {
+    pub async fn new<'fields>(
+        connection_string: &str,
+        query: &str,
+        fields: impl IntoIterator<Item = &'fields Field>,
+    ) -> Result<Self, CreateSqlDataSourceError> {
+        let mut client = connect(connection_string).await?;
+        let (sender, mut receiver) = mpsc::channel(QUERY_ITEM_BUFFER);
+        let query = query.to_owned();
+
+        trace_sql!(query);
+
+        // the task ends once every item is read, or once the data source is dropped, closing the
+        // connection
+        tokio::spawn(async move {
+            let mut stream = match client.query(query.as_str(), &[]).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    let _ = sender.send(Err(err)).await;
+
+                    return;
+                }
+            };
+
+            while let Some(item) = stream.next().await {
+                let failed = item.is_err();
+
+                if sender.send(item).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let columns = match receiver.recv().await {
+            Some(Ok(QueryItem::Metadata(metadata))) => metadata
+                .columns()
+                .iter()
+                .map(|column| column.name().to_owned())
+                .collect::<Vec<_>>(),
+            Some(Ok(QueryItem::Row(_))) | None => {
+                return Err(CreateSqlDataSourceError::NoResultSet);
+            }
+            Some(Err(err)) => return Err(CreateSqlDataSourceError::QueryError(err)),
+        };
+
+        let fields = fields
+            .into_iter()
+            .map(Field::name)
+            .filter(|field| !field.is_empty())
+            .collect::<Vec<_>>();
+
+        let fields = if fields.is_empty() {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(position, column)| (position, Arc::from(column.as_str())))
+                .collect()
+        } else {
+            fields
+                .into_iter()
+                .map(|field| {
+                    columns
+                        .iter()
+                        .position(|column| column == field)
+                        .map(|position| (position, Arc::from(field)))
+                        .ok_or_else(|| CreateSqlDataSourceError::UnknownField(field.to_owned()))
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        Ok(SqlDataSource {
+            receiver,
+            fields,
+            record_number: 0,
+            done: false,
+        })
+    }
+
+    /// Counts the rows of the query, executing it as a derived table, so the query must be valid
+    /// as one, such as without an `ORDER BY` unless it has a `TOP` clause.
+    pub async fn count_records(
+        connection_string: &str,
+        query: &str,
+    ) -> Result<u64, CreateSqlDataSourceError> {
+        let mut client = connect(connection_string).await?;
+
+        let statement = formatdoc!(
+            "
+            SELECT COUNT_BIG(*) FROM (
+            {query}
+            ) AS [Query]
+            ",
+        );
+
+        trace_sql!(statement);
+
+        let records = client
+            .simple_query(statement)
+            .await
+            .map_err(CreateSqlDataSourceError::CountRecordsError)?
+            .into_row()
+            .await
+            .map_err(CreateSqlDataSourceError::CountRecordsError)?
+            .and_then(|row| row.get::<i64, _>(0))
+            .unwrap_or_default();
+
+        Ok(records as u64)
+    }
+
+    fn read_row(&self, row: Row, index: DataSourceRecordIndex) -> DataSourceRecord {
+        let values = row.into_iter().collect::<Vec<_>>();
+
+        let fields: StringMap<Arc<str>> = self
+            .fields
+            .iter()
+            .filter_map(|(position, field)| {
+                Some((field.as_ref(), column_value(values.get(*position)?)?))
+            })
+            .collect();
+
+        DataSourceRecord::new(fields, index)
+    }
+}
+
+impl Stream for SqlDataSource {
+    type Item = Result<DataSourceRecord, SqlReadRecordError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        match ready!(self.receiver.poll_recv(cx)) {
+            Some(Ok(QueryItem::Row(row))) => {
+                self.record_number += 1;
+
+                let record_number =
+                    NonZero::new(self.record_number).expect("Record numbers start at one");
+
+                // a result set is not read a line at a time, so the line and byte range of each
+                // record index are always zero
+                Poll::Ready(Some(Ok(self.read_row(
+                    row,
+                    DataSourceRecordIndex {
+                        record_number,
+                        line_start: 0,
+                        line_end: 0,
+                        byte_start: 0,
+                        byte_end: 0,
+                    },
+                ))))
+            }
+            Some(Ok(QueryItem::Metadata(_))) | None => {
+                self.done = true;
+                self.receiver.close();
+
+                Poll::Ready(None)
+            }
+            Some(Err(source)) => {
+                self.done = true;
+
+                Poll::Ready(Some(Err(SqlReadRecordError {
+                    index: DataSourceErrorIndex {
+                        record_number: None,
+                        line_number: 0,
+                    },
+                    source,
+                })))
+            }
+        }
+    }
+}
+
+async fn connect(
+    connection_string: &str,
+) -> Result<Client<Compat<TcpStream>>, CreateSqlDataSourceError> {
+    crate::log::redact_connection_string(connection_string);
+
+    let config = Config::from_ado_string(connection_string)
+        .map_err(CreateSqlDataSourceError::InvalidConnectionString)?;
+
+    let tcp = TcpStream::connect(config.get_addr())
+        .await
+        .map_err(|err| CreateSqlDataSourceError::ConnectError(err.into()))?;
+
+    tcp.set_nodelay(true)
+        .map_err(|err| CreateSqlDataSourceError::ConnectError(err.into()))?;
+
+    Client::connect(config, tcp.compat_write())
+        .await
+        .map_err(CreateSqlDataSourceError::ConnectError)
+}
+
+/// Value of a field read from the value of a result set column, `None` if the value is `NULL`.
+fn column_value(value: &ColumnData<'static>) -> Option<String> {
+    let value = match value {
+        ColumnData::U8(value) => value.as_ref()?.to_string(),
+        ColumnData::I16(value) => value.as_ref()?.to_string(),
+        ColumnData::I32(value) => value.as_ref()?.to_string(),
+        ColumnData::I64(value) => value.as_ref()?.to_string(),
+        ColumnData::F32(value) => value.as_ref()?.to_string(),
+        ColumnData::F64(value) => value.as_ref()?.to_string(),
+        ColumnData::Bit(value) => value.as_ref()?.to_string(),
+        ColumnData::String(value) => value.as_deref()?.to_owned(),
+        ColumnData::Guid(value) => value.as_ref()?.to_string(),
+        ColumnData::Binary(value) => {
+            value
+                .as_deref()?
+                .iter()
+                .fold(String::from("0x"), |mut hexadecimal, byte| {
+                    let _ = write!(hexadecimal, "{byte:02X}");
+
+                    hexadecimal
+                })
+        }
+        ColumnData::Numeric(value) => {
+            let value = value.as_ref()?;
+
+            Decimal::try_from_i128_with_scale(value.value(), value.scale() as u32)
+                .map_or_else(|_| value.to_string(), |value| value.to_string())
+        }
+        ColumnData::Xml(value) => value.as_deref()?.to_string(),
+        ColumnData::DateTime(value) => {
+            let value = value.as_ref()?;
+
+            format_datetime(
+                value.days() as i64 - DATETIME_EPOCH_TO_UNIX_EPOCH,
+                value.seconds_fragments() as i64 * 10_000_000 / 3,
+            )
+        }
+        ColumnData::SmallDateTime(value) => {
+            let value = value.as_ref()?;
+
+            format_datetime(
+                value.days() as i64 - DATETIME_EPOCH_TO_UNIX_EPOCH,
+                value.seconds_fragments() as i64 * 60_000_000_000,
+            )
+        }
+        ColumnData::Date(value) => {
+            format_date(value.as_ref()?.days() as i64 - DATE_EPOCH_TO_UNIX_EPOCH)
+        }
+        ColumnData::Time(value) => format_time(time_nanoseconds(value.as_ref()?)),
+        ColumnData::DateTime2(value) => {
+            let value = value.as_ref()?;
+
+            format_datetime(
+                value.date().days() as i64 - DATE_EPOCH_TO_UNIX_EPOCH,
+                time_nanoseconds(&value.time()),
+            )
+        }
+        ColumnData::DateTimeOffset(value) => {
+            let value = value.as_ref()?.datetime2();
+
+            format!(
+                "{}Z",
+                format_datetime(
+                    value.date().days() as i64 - DATE_EPOCH_TO_UNIX_EPOCH,
+                    time_nanoseconds(&value.time()),
+                )
+            )
+        }
+    };
+
+    Some(value)
+}
+
+/// Nanoseconds since midnight of the time, counted in increments of its scale.
+fn time_nanoseconds(time: &tiberius::time::Time) -> i64 {
+    time.increments() as i64 * 10_i64.pow(9 - time.scale().min(9) as u32)
+}
+
+/// Formats the days since 1970-01-01 and nanoseconds since midnight as
+/// `YYYY-MM-DDThh:mm:ss.fffffff`.
+fn format_datetime(days: i64, nanoseconds: i64) -> String {
+    format!("{}T{}", format_date(days), format_time(nanoseconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiberius::time::{Date, DateTime, DateTime2, Time};
+
+    #[test]
+    fn column_values() {
+        assert_eq!(column_value(&ColumnData::I32(None)), None);
+        assert_eq!(
+            column_value(&ColumnData::Bit(Some(true))).as_deref(),
+            Some("true")
+        );
+        assert_eq!(
+            column_value(&ColumnData::Binary(Some(vec![0x00, 0xA1].into()))).as_deref(),
+            Some("0x00A1")
+        );
+        assert_eq!(
+            column_value(&ColumnData::Date(Some(Date::new(738_885)))).as_deref(),
+            Some("2024-01-01")
+        );
+        assert_eq!(
+            column_value(&ColumnData::DateTime2(Some(DateTime2::new(
+                Date::new(738_885),
+                Time::new(45_296_123, 3),
+            ))))
+            .as_deref(),
+            Some("2024-01-01T12:34:56.1230000")
+        );
+        assert_eq!(
+            column_value(&ColumnData::DateTime(Some(DateTime::new(45_290, 150)))).as_deref(),
+            Some("2024-01-01T00:00:00.5000000")
+        );
+    }
+}
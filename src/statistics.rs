@@ -0,0 +1,98 @@
+use crate::backend::SqlBackend;
+use crate::guardrail::count_rows;
+use crate::identifier::{Identifier, TableIdentifier};
+use crate::merge_processor::MergeResult;
+use crate::trace_sql;
+use indoc::formatdoc;
+use log::{info, trace};
+
+/// Updates the statistics of the target table if the rows modified by the merge are at least the
+/// threshold percentage of its rows.
+pub async fn update_if_modified(
+    client: &mut impl SqlBackend,
+    target_table: &TableIdentifier,
+    merge_result: MergeResult,
+    threshold: f64,
+) -> Result<(), tiberius::error::Error> {
+    let modified_rows = merge_result.inserted + merge_result.updated + merge_result.deleted;
+
+    if modified_rows == 0 {
+        return Ok(());
+    }
+
+    let target_rows = count_rows(client, target_table).await?.max(1);
+    let modified = modified_rows as f64 / target_rows as f64 * 100.0;
+
+    if modified < threshold {
+        return Ok(());
+    }
+
+    let statement = formatdoc!(
+        "
+        UPDATE STATISTICS {target_table}
+        ",
+        target_table = target_table.full(),
+    );
+
+    trace_sql!(statement);
+
+    client.execute(&statement, &[]).await?;
+
+    info!(
+        "Updated statistics of {}, {:.1}% of its rows were modified ({} of {} rows)",
+        target_table, modified, modified_rows, target_rows,
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, QueryRow};
+    use tiberius::ColumnData;
+
+    #[tokio::test]
+    async fn update_statistics_of_modified_tables() -> Result<(), tiberius::error::Error> {
+        let target_table = "[dbo].[Country]".parse().unwrap();
+        let merge_result = |inserted, updated, deleted| MergeResult {
+            inserted,
+            updated,
+            deleted,
+        };
+        let mut backend = MockBackend::new();
+
+        update_if_modified(&mut backend, &target_table, merge_result(0, 0, 0), 10.0).await?;
+
+        assert!(backend.statements().is_empty());
+
+        for modified in [5, 6] {
+            backend.push_rows([QueryRow::new([ColumnData::I64(Some(100))])]);
+
+            update_if_modified(
+                &mut backend,
+                &target_table,
+                merge_result(modified - 2, 1, 1),
+                6.0,
+            )
+            .await?;
+        }
+
+        let statements = backend
+            .statements()
+            .iter()
+            .map(|statement| statement.statement.trim())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            statements,
+            [
+                "SELECT COUNT_BIG(*) FROM [dbo].[Country]",
+                "SELECT COUNT_BIG(*) FROM [dbo].[Country]",
+                "UPDATE STATISTICS [dbo].[Country]"
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+#[cfg(feature = "amqp")]
+mod amqp;
+#[cfg(feature = "kafka")]
+mod kafka;
+
+use crate::data_source::string_map::StringMap;
+use crate::data_source::{
+    DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, ReadRecordError,
+};
+use crate::delimited_data_source::SplitRecordError;
+use crate::import_profile::{DelimitedReaderConfig, Field};
+use crate::json_data_source::field_value;
+use futures::{Stream, stream};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::num::NonZero;
+use std::str::Utf8Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Message queue consumed by a streaming data source.
+#[derive(Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum StreamBroker {
+    /// Kafka topic, consumed as a member of the consumer group
+    #[cfg(feature = "kafka")]
+    Kafka {
+        bootstrap_servers: String,
+        topic: String,
+        group_id: String,
+    },
+    /// AMQP queue, such as a RabbitMQ queue
+    #[cfg(feature = "amqp")]
+    Amqp { uri: String, queue: String },
+}
+
+impl Display for StreamBroker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            #[cfg(feature = "kafka")]
+            StreamBroker::Kafka { ref topic, .. } => write!(f, "Kafka topic '{}'", topic),
+            #[cfg(feature = "amqp")]
+            StreamBroker::Amqp { ref queue, .. } => write!(f, "AMQP queue '{}'", queue),
+        }
+    }
+}
+
+/// Format of the payload of each message, each message being a single record.
+#[derive(Debug, Copy, Clone, JsonSchema, Deserialize)]
+pub enum StreamPayload {
+    /// JSON object, with a field for each property, where null properties are treated as missing
+    Json,
+    /// Delimited record without a header, with a field for each field of the field group in
+    /// declaration order
+    Delimited(DelimitedReaderConfig),
+}
+
+/// Limits deciding when a batch is cut, after which it is staged and merged, trading the freshness
+/// of the target tables against the overhead of merging small batches.
+#[derive(Debug, Copy, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamBatchWindow {
+    /// Maximum messages of a batch
+    #[serde(default = "StreamBatchWindow::default_max_records")]
+    pub max_records: usize,
+    /// Maximum milliseconds from receiving the first message of a batch until the batch is cut
+    #[serde(default = "StreamBatchWindow::default_max_age_ms")]
+    pub max_age_ms: u64,
+}
+
+impl StreamBatchWindow {
+    fn default_max_records() -> usize {
+        1000
+    }
+
+    fn default_max_age_ms() -> u64 {
+        1000
+    }
+
+    pub fn max_age(&self) -> Duration {
+        Duration::from_millis(self.max_age_ms)
+    }
+
+    /// Instant at which a batch whose first message was received at `first_received` is cut.
+    fn deadline(&self, first_received: Instant) -> tokio::time::Instant {
+        tokio::time::Instant::from_std(first_received + self.max_age())
+    }
+}
+
+impl Default for StreamBatchWindow {
+    fn default() -> Self {
+        Self {
+            max_records: Self::default_max_records(),
+            max_age_ms: Self::default_max_age_ms(),
+        }
+    }
+}
+
+/// Messages received from the broker, staged and merged together before the broker is told they
+/// have been consumed.
+#[derive(Debug)]
+pub struct StreamBatch {
+    messages: Vec<Vec<u8>>,
+    position: StreamPosition,
+    first_received: Instant,
+    cut: BatchCut,
+}
+
+/// Limit of the batch window which cut a batch.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BatchCut {
+    MaxRecords,
+    MaxAge,
+    /// The broker closed the stream before either limit was reached
+    Closed,
+}
+
+/// Position of the last message of a batch, committed to the broker once the batch is merged.
+#[derive(Debug)]
+enum StreamPosition {
+    #[cfg(feature = "kafka")]
+    Kafka(rdkafka::TopicPartitionList),
+    #[cfg(feature = "amqp")]
+    Amqp(u64),
+}
+
+impl StreamBatch {
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn cut(&self) -> BatchCut {
+        self.cut
+    }
+
+    /// Time elapsed since the first message of the batch was received.
+    pub fn latency(&self) -> Duration {
+        self.first_received.elapsed()
+    }
+}
+
+/// Latency of the batches merged so far, from receiving the first message of each batch until it
+/// is committed.
+#[derive(Debug, Default)]
+pub struct BatchMetrics {
+    batches: u64,
+    records: u64,
+    total_latency: Duration,
+    max_latency: Duration,
+}
+
+impl BatchMetrics {
+    pub fn record(&mut self, records: usize, latency: Duration) {
+        self.batches += 1;
+        self.records += records as u64;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+    }
+
+    pub fn mean_latency(&self) -> Duration {
+        self.total_latency
+            .checked_div(self.batches.try_into().unwrap_or(u32::MAX))
+            .unwrap_or_default()
+    }
+}
+
+impl Display for BatchMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} batches, {} records, mean latency {:.3}s, max latency {:.3}s",
+            self.batches,
+            self.records,
+            self.mean_latency().as_secs_f64(),
+            self.max_latency.as_secs_f64(),
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StreamConsumerError {
+    #[error("could not connect to {0}")]
+    Connect(String, #[source] StreamBrokerError),
+    #[error("could not receive messages")]
+    Receive(#[source] StreamBrokerError),
+    #[error("could not commit messages")]
+    Commit(#[source] StreamBrokerError),
+}
+
+#[derive(Debug, Error)]
+pub enum StreamBrokerError {
+    #[cfg(feature = "kafka")]
+    #[error(transparent)]
+    Kafka(#[from] rdkafka::error::KafkaError),
+    #[cfg(feature = "amqp")]
+    #[error(transparent)]
+    Amqp(#[from] lapin::Error),
+}
+
+/// Consumer of the messages of a broker, committing the position of each batch only once told
+/// the batch has been merged, such that messages of a failed batch are received again.
+pub enum StreamConsumer {
+    #[cfg(feature = "kafka")]
+    Kafka(kafka::KafkaConsumer),
+    #[cfg(feature = "amqp")]
+    Amqp(amqp::AmqpConsumer),
+}
+
+impl StreamConsumer {
+    #[cfg_attr(not(feature = "amqp"), allow(unused_variables))]
+    pub async fn connect(
+        broker: &StreamBroker,
+        batch_window: &StreamBatchWindow,
+    ) -> Result<Self, StreamConsumerError> {
+        let connect_error =
+            |err: StreamBrokerError| StreamConsumerError::Connect(broker.to_string(), err);
+
+        match *broker {
+            #[cfg(feature = "kafka")]
+            StreamBroker::Kafka {
+                ref bootstrap_servers,
+                ref topic,
+                ref group_id,
+            } => kafka::KafkaConsumer::connect(bootstrap_servers, topic, group_id)
+                .map(StreamConsumer::Kafka)
+                .map_err(|err| connect_error(err.into())),
+            #[cfg(feature = "amqp")]
+            StreamBroker::Amqp { ref uri, ref queue } => {
+                amqp::AmqpConsumer::connect(uri, queue, batch_window.max_records)
+                    .await
+                    .map(StreamConsumer::Amqp)
+                    .map_err(|err| connect_error(err.into()))
+            }
+        }
+    }
+
+    /// Waits for the next message, then receives further messages until the batch window cuts the
+    /// batch. Returns `None` once the broker closes the stream.
+    pub async fn next_batch(
+        &mut self,
+        batch_window: &StreamBatchWindow,
+    ) -> Result<Option<StreamBatch>, StreamConsumerError> {
+        match *self {
+            #[cfg(feature = "kafka")]
+            StreamConsumer::Kafka(ref mut consumer) => consumer
+                .next_batch(batch_window)
+                .await
+                .map(Some)
+                .map_err(|err| StreamConsumerError::Receive(err.into())),
+            #[cfg(feature = "amqp")]
+            StreamConsumer::Amqp(ref mut consumer) => consumer
+                .next_batch(batch_window)
+                .await
+                .map_err(|err| StreamConsumerError::Receive(err.into())),
+        }
+    }
+
+    /// Commits the position of the batch, such that its messages are not received again.
+    pub async fn commit(&mut self, batch: StreamBatch) -> Result<(), StreamConsumerError> {
+        match (self, batch.position) {
+            #[cfg(feature = "kafka")]
+            (StreamConsumer::Kafka(consumer), StreamPosition::Kafka(position)) => consumer
+                .commit(&position)
+                .map_err(|err| StreamConsumerError::Commit(err.into())),
+            #[cfg(feature = "amqp")]
+            (StreamConsumer::Amqp(consumer), StreamPosition::Amqp(delivery_tag)) => consumer
+                .commit(delivery_tag)
+                .await
+                .map_err(|err| StreamConsumerError::Commit(err.into())),
+            #[cfg(all(feature = "kafka", feature = "amqp"))]
+            _ => {
+                unreachable!("Batches should be committed by the consumer they were received from")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("error reading stream message ({index}): {source}")]
+pub struct StreamReadRecordError {
+    index: DataSourceErrorIndex,
+    #[source]
+    source: StreamReadRecordErrorKind,
+}
+
+#[derive(Debug, Error)]
+enum StreamReadRecordErrorKind {
+    #[error("could not deserialize JSON payload")]
+    DeserializeJson(#[from] serde_json::Error),
+    #[error("could not split delimited payload")]
+    SplitRecord(#[from] SplitRecordError),
+    #[error("expected {expected} fields, found {found}")]
+    FieldCount { expected: usize, found: usize },
+    #[error("invalid UTF-8 characters in payload")]
+    Utf8(#[from] Utf8Error),
+}
+
+impl ReadRecordError for StreamReadRecordError {
+    fn index(&self) -> DataSourceErrorIndex {
+        self.index
+    }
+}
+
+/// Reads a record from each message of the batch, in the order they were received. Messages have
+/// no lines, so the line of each record index is always zero.
+pub fn read_batch<'a>(
+    batch: &'a StreamBatch,
+    fields: &'a [Field],
+    payload: StreamPayload,
+    raw_payload_field: Option<&'a str>,
+) -> impl Stream<Item = Result<DataSourceRecord, StreamReadRecordError>> + 'a {
+    stream::iter(
+        batch
+            .messages
+            .iter()
+            .zip(1..)
+            .map(move |(message, record_number)| {
+                let record_number =
+                    NonZero::new(record_number).expect("Record numbers start at one");
+
+                read_message(message, fields, payload, raw_payload_field)
+                    .map(|fields| {
+                        DataSourceRecord::new(
+                            fields,
+                            DataSourceRecordIndex {
+                                record_number,
+                                line_start: 0,
+                                line_end: 0,
+                                byte_start: 0,
+                                byte_end: message.len() as u64,
+                            },
+                        )
+                    })
+                    .map_err(|source| StreamReadRecordError {
+                        index: DataSourceErrorIndex {
+                            record_number: Some(record_number),
+                            line_number: 0,
+                        },
+                        source,
+                    })
+            }),
+    )
+}
+
+fn read_message(
+    message: &[u8],
+    fields: &[Field],
+    payload: StreamPayload,
+    raw_payload_field: Option<&str>,
+) -> Result<StringMap<Arc<str>>, StreamReadRecordErrorKind> {
+    let mut record: StringMap<Arc<str>> = match payload {
+        StreamPayload::Json => {
+            let object = serde_json::from_slice::<serde_json::Map<String, Value>>(message)?;
+
+            fields
+                .iter()
+                .filter_map(|field| Some((field.name(), field_value(object.get(field.name())?)?)))
+                .collect()
+        }
+        StreamPayload::Delimited(reader_config) => {
+            let values = reader_config.split_record(message)?;
+
+            if values.len() != fields.len() {
+                return Err(StreamReadRecordErrorKind::FieldCount {
+                    expected: fields.len(),
+                    found: values.len(),
+                });
+            }
+
+            fields.iter().map(Field::name).zip(values).collect()
+        }
+    };
+
+    if let Some(raw_payload_field) = raw_payload_field {
+        record.insert(Arc::from(raw_payload_field), std::str::from_utf8(message)?);
+    }
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<Field> {
+        serde_json::from_value(serde_json::Value::Array(
+            names
+                .iter()
+                .map(|name| serde_json::json!({ "name": name }))
+                .collect(),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn batch_metrics_latency() {
+        let mut metrics = BatchMetrics::default();
+
+        assert_eq!(metrics.mean_latency(), Duration::ZERO);
+
+        metrics.record(10, Duration::from_millis(100));
+        metrics.record(30, Duration::from_millis(300));
+
+        assert_eq!(metrics.mean_latency(), Duration::from_millis(200));
+        assert_eq!(
+            metrics.to_string(),
+            "2 batches, 40 records, mean latency 0.200s, max latency 0.300s"
+        );
+    }
+
+    #[test]
+    fn read_json_message() {
+        let fields = fields(&["Code", "Rate", "Name"]);
+
+        let record = read_message(
+            br#"{ "Code": "GBP", "Rate": 1.25, "Name": null, "Other": "x" }"#,
+            &fields,
+            StreamPayload::Json,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(record.get("Code"), Some("GBP"));
+        assert_eq!(record.get("Rate"), Some("1.25"));
+        assert_eq!(record.get("Name"), None);
+        assert_eq!(record.get("Other"), None);
+    }
+
+    #[test]
+    fn read_delimited_message() {
+        let fields = fields(&["Code", "Name"]);
+
+        let record = read_message(
+            b"GBP,\"Pound, Sterling\"",
+            &fields,
+            StreamPayload::Delimited(DelimitedReaderConfig::Csv),
+            Some("Payload"),
+        )
+        .unwrap();
+
+        assert_eq!(record.get("Code"), Some("GBP"));
+        assert_eq!(record.get("Name"), Some("Pound, Sterling"));
+        assert_eq!(record.get("Payload"), Some("GBP,\"Pound, Sterling\""));
+    }
+
+    #[test]
+    fn read_delimited_message_rejects_field_count() {
+        let fields = fields(&["Code", "Name"]);
+
+        assert!(matches!(
+            read_message(
+                b"GBP",
+                &fields,
+                StreamPayload::Delimited(DelimitedReaderConfig::Csv),
+                None,
+            ),
+            Err(StreamReadRecordErrorKind::FieldCount {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::stream_data_source::{BatchCut, StreamBatch, StreamBatchWindow, StreamPosition};
+use futures::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicQosOptions};
+use lapin::types::FieldTable;
+use lapin::{Channel, Connection, ConnectionProperties, Consumer};
+use std::time::Instant;
+
+pub struct AmqpConsumer {
+    _connection: Connection,
+    channel: Channel,
+    consumer: Consumer,
+}
+
+impl AmqpConsumer {
+    pub(super) async fn connect(
+        uri: &str,
+        queue: &str,
+        max_records: usize,
+    ) -> Result<Self, lapin::Error> {
+        let connection = Connection::connect(uri, ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+
+        // unacknowledged messages must fill a batch, as they are only acknowledged once merged
+        channel
+            .basic_qos(
+                max_records.try_into().unwrap_or(u16::MAX),
+                BasicQosOptions::default(),
+            )
+            .await?;
+
+        let consumer = channel
+            .basic_consume(
+                queue,
+                env!("CARGO_PKG_NAME"),
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        Ok(AmqpConsumer {
+            _connection: connection,
+            channel,
+            consumer,
+        })
+    }
+
+    pub(super) async fn next_batch(
+        &mut self,
+        batch_window: &StreamBatchWindow,
+    ) -> Result<Option<StreamBatch>, lapin::Error> {
+        let Some(delivery) = self.consumer.next().await.transpose()? else {
+            return Ok(None);
+        };
+
+        let first_received = Instant::now();
+        let deadline = batch_window.deadline(first_received);
+
+        let mut messages = vec![delivery.data];
+        let mut delivery_tag = delivery.delivery_tag;
+
+        let cut = loop {
+            if messages.len() >= batch_window.max_records {
+                break BatchCut::MaxRecords;
+            }
+
+            let delivery = match tokio::time::timeout_at(deadline, self.consumer.next()).await {
+                Ok(Some(delivery)) => delivery?,
+                Ok(None) => break BatchCut::Closed,
+                Err(_) => break BatchCut::MaxAge,
+            };
+
+            messages.push(delivery.data);
+            delivery_tag = delivery.delivery_tag;
+        };
+
+        Ok(Some(StreamBatch {
+            messages,
+            position: StreamPosition::Amqp(delivery_tag),
+            first_received,
+            cut,
+        }))
+    }
+
+    /// Acknowledges every message up to and including the delivery tag.
+    pub(super) async fn commit(&mut self, delivery_tag: u64) -> Result<(), lapin::Error> {
+        self.channel
+            .basic_ack(delivery_tag, BasicAckOptions { multiple: true })
+            .await
+    }
+}
@@ -0,0 +1,84 @@
+use crate::stream_data_source::{BatchCut, StreamBatch, StreamBatchWindow, StreamPosition};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::BorrowedMessage;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use rustc_hash::FxHashMap as HashMap;
+use std::time::Instant;
+
+pub struct KafkaConsumer {
+    consumer: StreamConsumer,
+    topic: String,
+}
+
+impl KafkaConsumer {
+    pub(super) fn connect(
+        bootstrap_servers: &str,
+        topic: &str,
+        group_id: &str,
+    ) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", bootstrap_servers)
+            .set("group.id", group_id)
+            // offsets are committed once the batch has been merged
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+
+        consumer.subscribe(&[topic])?;
+
+        Ok(KafkaConsumer {
+            consumer,
+            topic: topic.to_owned(),
+        })
+    }
+
+    pub(super) async fn next_batch(
+        &mut self,
+        batch_window: &StreamBatchWindow,
+    ) -> Result<StreamBatch, KafkaError> {
+        let mut messages = Vec::new();
+        let mut offsets = HashMap::<i32, i64>::default();
+
+        push(&mut messages, &mut offsets, self.consumer.recv().await?);
+
+        let first_received = Instant::now();
+        let deadline = batch_window.deadline(first_received);
+
+        let cut = loop {
+            if messages.len() >= batch_window.max_records {
+                break BatchCut::MaxRecords;
+            }
+
+            match tokio::time::timeout_at(deadline, self.consumer.recv()).await {
+                Ok(message) => push(&mut messages, &mut offsets, message?),
+                Err(_) => break BatchCut::MaxAge,
+            }
+        };
+
+        let mut position = TopicPartitionList::new();
+
+        for (partition, offset) in offsets {
+            // the committed offset is that of the next message to consume
+            position.add_partition_offset(&self.topic, partition, Offset::Offset(offset + 1))?;
+        }
+
+        Ok(StreamBatch {
+            messages,
+            position: StreamPosition::Kafka(position),
+            first_received,
+            cut,
+        })
+    }
+
+    pub(super) fn commit(&mut self, position: &TopicPartitionList) -> Result<(), KafkaError> {
+        self.consumer.commit(position, CommitMode::Sync)
+    }
+}
+
+/// Adds the payload of the message to the batch, and its offset to the offsets of its partition.
+fn push(messages: &mut Vec<Vec<u8>>, offsets: &mut HashMap<i32, i64>, message: BorrowedMessage) {
+    messages.push(message.payload().unwrap_or_default().to_vec());
+    offsets.insert(message.partition(), message.offset());
+}
@@ -1,51 +1,214 @@
 pub(crate) mod table_mapper_raw;
 
-use crate::identifier::{ColumnIdentifier, ParseIdentifierError, TableIdentifier};
+use crate::data_source::DataSourceRecord;
+use crate::deduplication::Deduplication;
+use crate::expression::{ComputedField, ExpressionError};
+use crate::identifier::{ColumnIdentifier, Identifier, ParseIdentifierError, TableIdentifier};
+use crate::import_options::ImportOptionsOverride;
 use crate::preprocess::{PreprocessFunctionError, PreprocessRuntime, PreprocessTransform};
-use crate::table_mapper::table_mapper_raw::{LookupKeyColumnRaw, TableMapperColumnRaw};
+use crate::table_mapper::table_mapper_raw::{
+    LookupKeyColumnRaw, MergeOrderColumnRaw, TableMapperColumnRaw,
+};
+use crate::template::{Template, TemplateError};
+use rust_decimal::Decimal;
+use rustc_hash::FxHashMap as HashMap;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use std::borrow::Cow;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::num::NonZeroU16;
 use table_mapper_raw::TableMapperRaw;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct TableMapper {
     name: String,
+    depends_on: Vec<String>,
     field_group: String,
     table_identifier: TableIdentifier,
     delete_mode: DeleteMode,
     delete_action: DeleteAction,
     duplicate_action: DuplicateAction,
+    preprocess_function: Option<String>,
     preprocess_transform: Option<Box<dyn PreprocessTransform>>,
+    deduplication: Option<Deduplication>,
+    lookup_cache: bool,
+    deferred_lookup_resolution: bool,
+    concurrency: Concurrency,
+    import_options_override: ImportOptionsOverride,
+    computed_fields: Vec<ComputedField>,
     columns: Vec<TableMapperColumn>,
     key_columns: Vec<ColumnIdentifier>,
+    key_normalization: HashMap<ColumnIdentifier, Vec<KeyNormalization>>,
+    merge_order: Option<MergeOrder>,
+    default_on_insert: Vec<ColumnIdentifier>,
+    surrogate_key: Option<SurrogateKey>,
+    hierarchy: Option<Hierarchy>,
+    bridge: Option<Bridge>,
+    operation: Option<Operation>,
+    history: Option<History>,
+    route: Option<Route>,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, JsonSchema, Deserialize)]
 pub enum DeleteMode {
+    /// Retain target table rows which are not present in the data source
     Partial,
+    /// Delete target table rows which are not present in the data source
     Full,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
+/// Hints limiting the load the table mapper puts on the server, such that heavy table mappers do
+/// not oversubscribe it while small table mappers run freely.
+#[derive(Debug, Default, Copy, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Concurrency {
+    /// Maximum degree of parallelism of the merge, and maximum chunks the data source is parsed in
+    /// parallel
+    #[serde(default)]
+    pub max_parallel: Option<NonZeroU16>,
+    /// Merge under serializable isolation, holding range locks on the target table until the merge
+    /// completes, such that other sessions cannot insert rows with the same key columns meanwhile
+    #[serde(default)]
+    pub serializable: bool,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
 pub enum DuplicateAction {
+    /// Error on records with duplicate key columns
     Reject,
+    /// Retain records with duplicate key columns
     Retain,
+    /// Dump records with duplicate key columns
     Dump,
+    /// Do not check records for duplicate key columns
     NoCheck,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
 pub enum DeleteAction {
+    /// Take no action on deleted rows
     None,
 }
 
+/// Normalization of a key column value, applied to both sides of the comparison when merging, such
+/// that values differing only by case or padding match the same target table row.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, JsonSchema, Deserialize)]
+pub enum KeyNormalization {
+    /// Remove leading and trailing spaces
+    Trim,
+    /// Convert to upper case
+    Upper,
+    /// Convert to lower case
+    Lower,
+    /// Pad with leading zeros to the width, values at least as wide are unchanged
+    ZeroPad(u16),
+}
+
+/// Order of temporary table rows with the same key columns, only the first of which is merged.
+#[derive(Debug, Clone)]
+pub struct MergeOrder {
+    order_by: Vec<MergeOrderColumn>,
+    tie_breaker: MergeOrderColumn,
+}
+
+/// Self-referencing hierarchy of the target table, merged a level at a time.
+#[derive(Debug, Clone)]
+pub struct Hierarchy {
+    parent_column: ColumnIdentifier,
+    parent_key_column: ColumnIdentifier,
+}
+
+/// Field value selecting the records of a table mapper expanded from a routing, the table mapper
+/// reading only the records routed to its target table.
+#[derive(Debug, Clone)]
+pub struct Route {
+    field: String,
+    value: String,
+}
+
+/// Many-to-many bridge of the target table, whose key columns are lookups of its two sides.
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    left_column: ColumnIdentifier,
+    right_column: ColumnIdentifier,
+    symmetric: bool,
+}
+
+/// Field of each record of a delta file holding its operation, the record being inserted, or
+/// updating or deleting the target table row matching its key columns, by the value of the field.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    field: String,
+    column_identifier: ColumnIdentifier,
+    insert: String,
+    update: String,
+    delete: String,
+}
+
+/// Effective-dated history of the target table, a slowly changing dimension of type 2, the current
+/// row of each key having no end of its period.
+#[derive(Debug, Clone)]
+pub struct History {
+    valid_from_column: ColumnIdentifier,
+    valid_to_column: ColumnIdentifier,
+    tracked_columns: Vec<ColumnIdentifier>,
+}
+
+/// Spatial type of a parser column, whose field values are Well-Known Text, or the longitudes of
+/// points paired with the latitudes of another field.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spatial {
+    spatial_type: SpatialType,
+    /// Spatial reference identifier of the values, WGS 84 by default
+    #[serde(default = "Spatial::default_srid")]
+    srid: i32,
+    /// Field of the latitude of each point, the field of the column being its longitude
+    #[serde(default)]
+    latitude_field: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, JsonSchema, Deserialize)]
+pub enum SpatialType {
+    /// Round-earth coordinates
+    Geography,
+    /// Planar coordinates
+    Geometry,
+}
+
+/// Identity column of the target table whose values assigned to inserted rows are captured, and
+/// the table they are captured into.
+#[derive(Debug, Clone)]
+pub struct SurrogateKey {
+    column_identifier: ColumnIdentifier,
+    capture_table: TableIdentifier,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeOrderColumn {
+    column_identifier: ColumnIdentifier,
+    direction: SortDirection,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, JsonSchema, Deserialize)]
+pub enum SortDirection {
+    /// Order from the lowest value, such that the first occurrence is merged
+    #[default]
+    Ascending,
+    /// Order from the highest value, such that the last occurrence is merged
+    Descending,
+}
+
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub enum TableMapperColumn {
     Static(StaticColumn),
     Parser(ParserColumn),
     Lookup(LookupColumn),
+    Sequence(SequenceColumn),
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
@@ -53,6 +216,8 @@ pub struct StaticColumn {
     column_identifier: ColumnIdentifier,
     map_column: bool,
     value: String,
+    sensitive: bool,
+    template: Option<Template>,
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
@@ -60,6 +225,19 @@ pub struct ParserColumn {
     column_identifier: ColumnIdentifier,
     map_column: bool,
     field_name: String,
+    normalization: LookupKeyNormalization,
+    json: bool,
+    spatial: Option<Spatial>,
+    implied_decimals: u8,
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub struct SequenceColumn {
+    column_identifier: ColumnIdentifier,
+    map_column: bool,
+    start: i64,
+    group_fields: Vec<String>,
+    field_name: String,
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
@@ -69,6 +247,37 @@ pub struct LookupColumn {
     table_identifier: TableIdentifier,
     output_column_identifier: ColumnIdentifier,
     key_columns: Vec<LookupKeyColumn>,
+    orphan_policy: OrphanPolicy,
+    key_normalization: LookupKeyNormalization,
+}
+
+/// Normalization of the key values of a lookup, such that values differing only by their Unicode
+/// representation, case or accents match.
+#[derive(Debug, Default, Copy, Clone, Hash, Eq, PartialEq, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LookupKeyNormalization {
+    /// Normalize field values to Unicode normalization form C, such that composed and decomposed
+    /// accented characters match
+    #[serde(default)]
+    unicode: bool,
+    /// Match key values case insensitively
+    #[serde(default)]
+    case_insensitive: bool,
+    /// Match key values accent insensitively
+    #[serde(default)]
+    accent_insensitive: bool,
+}
+
+/// Handling of rows whose lookup key columns have values, but do not resolve to a lookup row.
+#[derive(Debug, Default, Copy, Clone, Hash, Eq, PartialEq, JsonSchema, Deserialize)]
+pub enum OrphanPolicy {
+    /// Insert the row with a NULL lookup column
+    #[default]
+    Null,
+    /// Move the row from the temporary table into the orphans table, unless lookups are deferred
+    Quarantine,
+    /// Insert a placeholder row into the lookup table from the key columns, then resolve again
+    Placeholder,
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
@@ -115,6 +324,52 @@ impl LookupColumn {
     pub fn output_column_identifier(&self) -> &ColumnIdentifier {
         &self.output_column_identifier
     }
+
+    pub fn orphan_policy(&self) -> OrphanPolicy {
+        self.orphan_policy
+    }
+
+    pub fn key_normalization(&self) -> LookupKeyNormalization {
+        self.key_normalization
+    }
+}
+
+impl LookupKeyNormalization {
+    /// Normalizes the field value, borrowing it if no normalization is enabled.
+    pub fn apply<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        if !self.unicode && !self.case_insensitive && !self.accent_insensitive {
+            return Cow::Borrowed(value);
+        }
+
+        let mut value = if self.accent_insensitive {
+            value
+                .nfd()
+                .filter(|&c| !is_combining_mark(c))
+                .nfc()
+                .collect()
+        } else if self.unicode {
+            value.nfc().collect()
+        } else {
+            value.to_owned()
+        };
+
+        if self.case_insensitive {
+            value = value.to_lowercase();
+        }
+
+        Cow::Owned(value)
+    }
+
+    /// Collation lookup table key columns of character types are compared with, or `None` to
+    /// compare with the collation of the column.
+    pub fn collation(&self) -> Option<&'static str> {
+        match (self.case_insensitive, self.accent_insensitive) {
+            (false, false) => None,
+            (true, false) => Some("Latin1_General_100_CI_AS"),
+            (false, true) => Some("Latin1_General_100_CS_AI"),
+            (true, true) => Some("Latin1_General_100_CI_AI"),
+        }
+    }
 }
 
 impl ParserColumn {
@@ -127,6 +382,64 @@ impl ParserColumn {
             column_identifier: column_identifier.clone(),
             map_column,
             field_name: field_name.to_owned(),
+            normalization: LookupKeyNormalization::default(),
+            json: false,
+            spatial: None,
+            implied_decimals: 0,
+        }
+    }
+
+    /// Normalizes the field values of the column as the key values of a lookup.
+    pub fn with_normalization(mut self, normalization: LookupKeyNormalization) -> ParserColumn {
+        self.normalization = normalization;
+        self
+    }
+
+    pub fn normalization(&self) -> LookupKeyNormalization {
+        self.normalization
+    }
+
+    /// Whether the field values are JSON, those which are not valid JSON being NULL.
+    pub fn json(&self) -> bool {
+        self.json
+    }
+
+    /// Spatial type the field values are converted into, if a spatial column.
+    pub fn spatial(&self) -> Option<&Spatial> {
+        self.spatial.as_ref()
+    }
+
+    /// Number of decimal places implied by the field values, e.g. `2` for amounts in cents, by
+    /// which the values are scaled when converted into a numeric column.
+    pub fn implied_decimals(&self) -> u8 {
+        self.implied_decimals
+    }
+}
+
+impl Spatial {
+    fn default_srid() -> i32 {
+        4326
+    }
+
+    pub fn spatial_type(&self) -> SpatialType {
+        self.spatial_type
+    }
+
+    pub fn srid(&self) -> i32 {
+        self.srid
+    }
+
+    pub fn latitude_field(&self) -> Option<&str> {
+        self.latitude_field.as_deref()
+    }
+}
+
+impl SpatialType {
+    /// Name of the data type.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            SpatialType::Geography => "geography",
+            SpatialType::Geometry => "geometry",
         }
     }
 }
@@ -135,6 +448,84 @@ impl StaticColumn {
     pub fn value(&self) -> &str {
         &self.value
     }
+
+    pub fn token(&self) -> Option<StaticToken> {
+        StaticToken::parse(&self.value)
+    }
+
+    pub fn sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    /// Template rendered per record as the value of the column, see
+    /// [`crate::template::Templater`].
+    pub fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+}
+
+/// Static column value expanded when the table mapper is executed, rather than bound as a
+/// parameter, such that it is typed by SQL Server as the column it is written to.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum StaticToken {
+    /// Local date and time of the server, `$now`
+    Now,
+    /// UTC date and time of the server, `$utcnow`
+    UtcNow,
+    /// Login name of the connection, `$user`
+    User,
+    /// Workstation name of the connection, `$hostname`
+    Hostname,
+    /// Identifier of the run, the same for every table mapper of the run, `$run_id`
+    RunId,
+}
+
+impl StaticToken {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "$now" => Some(StaticToken::Now),
+            "$utcnow" => Some(StaticToken::UtcNow),
+            "$user" => Some(StaticToken::User),
+            "$hostname" => Some(StaticToken::Hostname),
+            "$run_id" => Some(StaticToken::RunId),
+            _ => None,
+        }
+    }
+
+    /// SQL expression evaluating to the value of the token within the run.
+    pub fn sql_expression(self, run_id: &str) -> String {
+        match self {
+            StaticToken::Now => String::from("SYSDATETIME()"),
+            StaticToken::UtcNow => String::from("SYSUTCDATETIME()"),
+            StaticToken::User => String::from("SUSER_SNAME()"),
+            StaticToken::Hostname => String::from("HOST_NAME()"),
+            StaticToken::RunId => format!("N'{}'", run_id),
+        }
+    }
+}
+
+/// Generates the identifier of a run, formatted as a random (version 4) UUID such that it may be
+/// written to `uniqueidentifier` columns.
+pub fn new_run_id() -> String {
+    let random = |seed: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+
+        hasher.write_u64(seed);
+        hasher.finish()
+    };
+
+    // Set the version (4) and variant (RFC 9562) bits
+    let uuid = ((random(0) as u128) << 64) | random(1) as u128;
+    let uuid = (uuid & !((0xf000 << 64) | (0xc000 << 48))) | (0x4000 << 64) | (0x8000 << 48);
+
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        uuid >> 96,
+        (uuid >> 80) & 0xffff,
+        (uuid >> 64) & 0xffff,
+        (uuid >> 48) & 0xffff,
+        uuid & 0xffff_ffff_ffff,
+    )
 }
 
 #[derive(Debug, Error)]
@@ -167,6 +558,44 @@ pub enum CreateTableMapperErrorKind {
     FindPreprocessFunction(String),
     #[error("could not create preprocess function '{0}'")]
     CreatePreprocessFunction(String, #[source] PreprocessFunctionError),
+    #[error("key normalization for '{0}', which is not a key column")]
+    KeyNormalizationUnknownKeyColumn(String),
+    #[error("key column '{0}' cannot use its default on insert")]
+    DefaultOnInsertKeyColumn(String),
+    #[error("hierarchy parent column '{0}' is not a lookup column with the Null orphan policy")]
+    HierarchyParentColumn(String),
+    #[error("lookup column '{0}' cannot quarantine orphans with deferred lookup resolution")]
+    DeferredQuarantine(String),
+    #[error("hierarchy parent key column '{0}' is not a parser column")]
+    HierarchyParentKeyColumn(String),
+    #[error("hierarchy requires a single key column, found {0}")]
+    HierarchyKeyColumns(usize),
+    #[error("bridge column '{0}' is not a lookup key column")]
+    BridgeColumn(String),
+    #[error("bridge requires two key columns, found {0}")]
+    BridgeKeyColumns(usize),
+    #[error("operation values must be distinct, found '{0}' more than once")]
+    OperationValues(String),
+    #[error("operation field cannot be used with the Full delete mode")]
+    OperationDeleteMode,
+    #[error("operation field cannot be used with a bridge")]
+    OperationBridge,
+    #[error("history tracked column '{0}' is not a mapped column, or is a key column")]
+    HistoryTrackedColumn(String),
+    #[error("history requires at least one tracked column")]
+    HistoryNoTrackedColumns,
+    #[error("history valid to column '{0}' cannot be a column of the table mapper")]
+    HistoryValidToColumn(String),
+    #[error("the Full delete mode cannot be used with {0}")]
+    FullDeleteIncompatible(&'static str),
+    #[error("history cannot be used with {0}")]
+    HistoryIncompatible(&'static str),
+    #[error("parser column '{0}' implies {1} decimal places, more than the maximum of 28")]
+    ImpliedDecimals(String, u8),
+    #[error("invalid template of static column '{0}'")]
+    Template(String, #[source] TemplateError),
+    #[error("invalid expression of computed field '{0}'")]
+    ComputedField(String, #[source] ExpressionError),
 }
 
 #[derive(Debug, Error)]
@@ -183,18 +612,44 @@ impl TableMapper {
             .into_iter()
             .map(|table_mapper_column_raw| Ok(
                 match table_mapper_column_raw {
-                    TableMapperColumnRaw::Static(static_column_raw) => TableMapperColumn::Static(StaticColumn {
-                        column_identifier: ColumnIdentifier::with_table(&raw.table_identifier, &static_column_raw.column_identifier)
-                            .map_err(|err| ParseTableMapperIdentifierError(static_column_raw.column_identifier, err))?,
-                        map_column: static_column_raw.map_column,
-                        value: static_column_raw.value,
-                    }),
+                    TableMapperColumnRaw::Static(static_column_raw) => {
+                        let column_identifier = ColumnIdentifier::with_table(&raw.table_identifier, &static_column_raw.column_identifier)
+                            .map_err(|err| ParseTableMapperIdentifierError(static_column_raw.column_identifier, err))?;
+
+                        let template = static_column_raw.template
+                            .then(|| Template::parse(&column_identifier, &static_column_raw.value))
+                            .transpose()
+                            .map_err(|err| CreateTableMapperErrorKind::Template(column_identifier.part_unescaped().to_owned(), err))?;
+
+                        TableMapperColumn::Static(StaticColumn {
+                            column_identifier,
+                            map_column: static_column_raw.map_column,
+                            value: static_column_raw.value,
+                            sensitive: static_column_raw.sensitive,
+                            template,
+                        })
+                    }
                     TableMapperColumnRaw::Parser(parser_column_raw) => TableMapperColumn::Parser(ParserColumn {
                         column_identifier: ColumnIdentifier::with_table(&raw.table_identifier, &parser_column_raw.column_identifier)
                             .map_err(|err| ParseTableMapperIdentifierError(parser_column_raw.column_identifier, err))?,
                         map_column: parser_column_raw.map_column,
                         field_name: parser_column_raw.field_name,
+                        normalization: LookupKeyNormalization::default(),
+                        json: parser_column_raw.json,
+                        spatial: parser_column_raw.spatial,
+                        implied_decimals: parser_column_raw.implied_decimals,
                     }),
+                    TableMapperColumnRaw::Sequence(sequence_column_raw) => {
+                        let column_identifier = ColumnIdentifier::with_table(&raw.table_identifier, &sequence_column_raw.column_identifier)
+                            .map_err(|err| ParseTableMapperIdentifierError(sequence_column_raw.column_identifier, err))?;
+
+                        TableMapperColumn::Sequence(SequenceColumn::new(
+                            &column_identifier,
+                            sequence_column_raw.map_column,
+                            sequence_column_raw.start,
+                            sequence_column_raw.group_fields,
+                        ))
+                    }
                     TableMapperColumnRaw::Lookup(lookup_column_raw) => {
                         let output_column_identifier = ColumnIdentifier::with_table(&lookup_column_raw.table_identifier, &lookup_column_raw.output_column_identifier)
                             .map_err(|err| ParseTableMapperIdentifierError(lookup_column_raw.output_column_identifier, err))?;
@@ -226,14 +681,36 @@ impl TableMapper {
                             table_identifier: lookup_column_raw.table_identifier,
                             output_column_identifier,
                             key_columns,
+                            orphan_policy: lookup_column_raw.orphan_policy,
+                            key_normalization: lookup_column_raw.key_normalization,
                         })
                     }
                 }
             ))
-            .collect::<Result<_, ParseTableMapperIdentifierError>>().map_err(|err| {
+            .collect::<Result<_, CreateTableMapperErrorKind>>().map_err(|err| {
             CreateTableMapperError::new(&raw.table_identifier, err)
         })?;
 
+        // rows are merged before their lookups are resolved for the last time, so cannot be
+        // quarantined by then
+        if raw.deferred_lookup_resolution
+            && let Some(lookup_column) = columns.iter().find_map(|column| match column {
+                TableMapperColumn::Lookup(lookup_column)
+                    if lookup_column.orphan_policy() == OrphanPolicy::Quarantine =>
+                {
+                    Some(lookup_column)
+                }
+                _ => None,
+            })
+        {
+            return Err(CreateTableMapperError::new(
+                &raw.table_identifier,
+                CreateTableMapperErrorKind::DeferredQuarantine(
+                    lookup_column.identifier().part_unescaped().to_owned(),
+                ),
+            ));
+        }
+
         let key_columns: Vec<ColumnIdentifier> = raw
             .key_columns
             .into_iter()
@@ -244,6 +721,303 @@ impl TableMapper {
             .collect::<Result<_, ParseTableMapperIdentifierError>>()
             .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
 
+        let key_normalization = raw
+            .key_normalization
+            .into_iter()
+            .map(|(key_column, normalizations)| {
+                let key_column_identifier =
+                    ColumnIdentifier::with_table(&raw.table_identifier, &key_column)
+                        .map_err(|err| ParseTableMapperIdentifierError(key_column.clone(), err))?;
+
+                if key_columns.contains(&key_column_identifier) {
+                    Ok((key_column_identifier, normalizations))
+                } else {
+                    Err(CreateTableMapperErrorKind::KeyNormalizationUnknownKeyColumn(key_column))
+                }
+            })
+            .collect::<Result<_, CreateTableMapperErrorKind>>()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
+        let default_on_insert = raw
+            .default_on_insert
+            .into_iter()
+            .map(|column| {
+                let column_identifier =
+                    ColumnIdentifier::with_table(&raw.table_identifier, &column)
+                        .map_err(|err| ParseTableMapperIdentifierError(column.clone(), err))?;
+
+                if key_columns.contains(&column_identifier) {
+                    Err(CreateTableMapperErrorKind::DefaultOnInsertKeyColumn(column))
+                } else {
+                    Ok(column_identifier)
+                }
+            })
+            .collect::<Result<_, CreateTableMapperErrorKind>>()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
+        if let Some(parser_column) = columns.iter().find_map(|column| match column {
+            TableMapperColumn::Parser(parser_column)
+                if parser_column.implied_decimals as u32 > Decimal::MAX_SCALE =>
+            {
+                Some(parser_column)
+            }
+            _ => None,
+        }) {
+            return Err(CreateTableMapperError::new(
+                &raw.table_identifier,
+                CreateTableMapperErrorKind::ImpliedDecimals(
+                    parser_column.column_identifier.part_unescaped().to_owned(),
+                    parser_column.implied_decimals,
+                ),
+            ));
+        }
+
+        let surrogate_key = raw
+            .surrogate_key
+            .map(|column| {
+                let column_identifier =
+                    ColumnIdentifier::with_table(&raw.table_identifier, &column)
+                        .map_err(|err| ParseTableMapperIdentifierError(column, err))?;
+                let capture_table = TableIdentifier::with_schema(
+                    &"[import]".parse().unwrap(),
+                    &format!("{}_surrogate_keys", raw.table_identifier.part_unescaped()),
+                )
+                .map_err(|err| {
+                    ParseTableMapperIdentifierError(raw.table_identifier.to_string(), err)
+                })?;
+
+                Ok(SurrogateKey {
+                    column_identifier,
+                    capture_table,
+                })
+            })
+            .transpose()
+            .map_err(|err: ParseTableMapperIdentifierError| {
+                CreateTableMapperError::new(&raw.table_identifier, err)
+            })?;
+
+        let hierarchy = raw
+            .hierarchy
+            .map(|hierarchy_raw| {
+                let parse = |column: String| {
+                    ColumnIdentifier::with_table(&raw.table_identifier, &column)
+                        .map_err(|err| ParseTableMapperIdentifierError(column, err))
+                };
+
+                let parent_column = parse(hierarchy_raw.parent_column)?;
+                let parent_key_column = parse(hierarchy_raw.parent_key_column)?;
+
+                if key_columns.len() != 1 {
+                    return Err(CreateTableMapperErrorKind::HierarchyKeyColumns(
+                        key_columns.len(),
+                    ));
+                }
+
+                if !columns.iter().any(|column| {
+                    matches!(column, TableMapperColumn::Lookup(lookup_column)
+                        if lookup_column.identifier() == &parent_column
+                            && lookup_column.orphan_policy() == OrphanPolicy::Null)
+                }) {
+                    return Err(CreateTableMapperErrorKind::HierarchyParentColumn(
+                        parent_column.part_unescaped().to_owned(),
+                    ));
+                }
+
+                if !columns.iter().any(|column| {
+                    matches!(column, TableMapperColumn::Parser(parser_column)
+                        if parser_column.identifier() == &parent_key_column)
+                }) {
+                    return Err(CreateTableMapperErrorKind::HierarchyParentKeyColumn(
+                        parent_key_column.part_unescaped().to_owned(),
+                    ));
+                }
+
+                Ok(Hierarchy {
+                    parent_column,
+                    parent_key_column,
+                })
+            })
+            .transpose()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
+        let bridge = raw
+            .bridge
+            .map(|bridge_raw| {
+                let parse = |column: String| {
+                    ColumnIdentifier::with_table(&raw.table_identifier, &column)
+                        .map_err(|err| ParseTableMapperIdentifierError(column, err))
+                };
+
+                let left_column = parse(bridge_raw.left_column)?;
+                let right_column = parse(bridge_raw.right_column)?;
+
+                if key_columns.len() != 2 {
+                    return Err(CreateTableMapperErrorKind::BridgeKeyColumns(
+                        key_columns.len(),
+                    ));
+                }
+
+                for bridge_column in [&left_column, &right_column] {
+                    if !key_columns.contains(bridge_column)
+                        || !columns.iter().any(|column| {
+                            matches!(column, TableMapperColumn::Lookup(lookup_column)
+                                if lookup_column.identifier() == bridge_column)
+                        })
+                    {
+                        return Err(CreateTableMapperErrorKind::BridgeColumn(
+                            bridge_column.part_unescaped().to_owned(),
+                        ));
+                    }
+                }
+
+                if left_column == right_column {
+                    return Err(CreateTableMapperErrorKind::BridgeColumn(
+                        right_column.part_unescaped().to_owned(),
+                    ));
+                }
+
+                Ok(Bridge {
+                    left_column,
+                    right_column,
+                    symmetric: bridge_raw.symmetric,
+                })
+            })
+            .transpose()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
+        let operation = raw
+            .operation
+            .map(|operation_raw| {
+                if operation_raw.insert == operation_raw.update
+                    || operation_raw.insert == operation_raw.delete
+                {
+                    return Err(CreateTableMapperErrorKind::OperationValues(
+                        operation_raw.insert,
+                    ));
+                }
+
+                if operation_raw.update == operation_raw.delete {
+                    return Err(CreateTableMapperErrorKind::OperationValues(
+                        operation_raw.update,
+                    ));
+                }
+
+                if raw.delete_mode == DeleteMode::Full {
+                    return Err(CreateTableMapperErrorKind::OperationDeleteMode);
+                }
+
+                if bridge.is_some() {
+                    return Err(CreateTableMapperErrorKind::OperationBridge);
+                }
+
+                // distinct from the columns of the target table, staged but never mapped
+                Ok(Operation {
+                    field: operation_raw.field,
+                    column_identifier: ColumnIdentifier::with_table(
+                        &raw.table_identifier,
+                        "[$operation]",
+                    )
+                    .unwrap(),
+                    insert: operation_raw.insert,
+                    update: operation_raw.update,
+                    delete: operation_raw.delete,
+                })
+            })
+            .transpose()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
+        let history = raw
+            .history
+            .map(|history_raw| {
+                let parse = |column: String| {
+                    ColumnIdentifier::with_table(&raw.table_identifier, &column)
+                        .map_err(|err| ParseTableMapperIdentifierError(column, err))
+                };
+
+                let valid_from_column = parse(history_raw.valid_from_column)?;
+                let valid_to_column = parse(history_raw.valid_to_column)?;
+                let tracked_columns = history_raw
+                    .tracked_columns
+                    .into_iter()
+                    .map(parse)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let incompatible = [
+                    (raw.delete_mode == DeleteMode::Full, "the Full delete mode"),
+                    (raw.deferred_lookup_resolution, "deferred lookup resolution"),
+                    (surrogate_key.is_some(), "a surrogate key"),
+                    (hierarchy.is_some(), "a hierarchy"),
+                    (bridge.is_some(), "a bridge"),
+                    (operation.is_some(), "an operation field"),
+                ];
+
+                if let Some((_, feature)) = incompatible.into_iter().find(|(used, _)| *used) {
+                    return Err(CreateTableMapperErrorKind::HistoryIncompatible(feature));
+                }
+
+                if tracked_columns.is_empty() {
+                    return Err(CreateTableMapperErrorKind::HistoryNoTrackedColumns);
+                }
+
+                for tracked_column in &tracked_columns {
+                    if key_columns.contains(tracked_column)
+                        || *tracked_column == valid_from_column
+                        || !columns
+                            .iter()
+                            .any(|column| column.identifier() == tracked_column && column.map())
+                    {
+                        return Err(CreateTableMapperErrorKind::HistoryTrackedColumn(
+                            tracked_column.part_unescaped().to_owned(),
+                        ));
+                    }
+                }
+
+                if columns
+                    .iter()
+                    .any(|column| column.identifier() == &valid_to_column)
+                {
+                    return Err(CreateTableMapperErrorKind::HistoryValidToColumn(
+                        valid_to_column.part_unescaped().to_owned(),
+                    ));
+                }
+
+                Ok(History {
+                    valid_from_column,
+                    valid_to_column,
+                    tracked_columns,
+                })
+            })
+            .transpose()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
+        let merge_order = raw
+            .merge_order
+            .map(|merge_order_raw| {
+                let parse = |column: MergeOrderColumnRaw| {
+                    ColumnIdentifier::with_table(&raw.table_identifier, &column.column_identifier)
+                        .map(|column_identifier| MergeOrderColumn {
+                            column_identifier,
+                            direction: column.direction,
+                        })
+                        .map_err(|err| {
+                            ParseTableMapperIdentifierError(column.column_identifier, err)
+                        })
+                };
+
+                Ok(MergeOrder {
+                    order_by: merge_order_raw
+                        .order_by
+                        .into_iter()
+                        .map(parse)
+                        .collect::<Result<_, _>>()?,
+                    tie_breaker: parse(merge_order_raw.tie_breaker)?,
+                })
+            })
+            .transpose()
+            .map_err(|err: ParseTableMapperIdentifierError| {
+                CreateTableMapperError::new(&raw.table_identifier, err)
+            })?;
+
         let preprocess_transform: Option<Box<dyn PreprocessTransform>> = raw
             .preprocess_function
             .as_deref()
@@ -274,16 +1048,67 @@ impl TableMapper {
             })
             .transpose()?;
 
+        // rows absent from the temporary table are deleted by a merge of every row, which levels
+        // of a hierarchy are not, and rows skipped as duplicates of earlier runs are absent too
+        if raw.delete_mode == DeleteMode::Full {
+            let incompatible = [
+                (hierarchy.is_some(), "a hierarchy"),
+                (bridge.is_some(), "a bridge"),
+                (
+                    raw.deduplication
+                        .as_ref()
+                        .is_some_and(|deduplication| deduplication.history()),
+                    "a deduplication history",
+                ),
+            ];
+
+            if let Some((_, feature)) = incompatible.into_iter().find(|(used, _)| *used) {
+                return Err(CreateTableMapperError::new(
+                    &raw.table_identifier,
+                    CreateTableMapperErrorKind::FullDeleteIncompatible(feature),
+                ));
+            }
+        }
+
+        let computed_fields = raw
+            .computed_fields
+            .into_iter()
+            .map(|computed_field_raw| {
+                ComputedField::new(&computed_field_raw.name, &computed_field_raw.expression)
+                    .map_err(|err| {
+                        CreateTableMapperErrorKind::ComputedField(computed_field_raw.name, err)
+                    })
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|err| CreateTableMapperError::new(&raw.table_identifier, err))?;
+
         Ok(TableMapper {
             name: raw.name,
+            depends_on: raw.depends_on,
             field_group: raw.field_group,
             table_identifier: raw.table_identifier,
             delete_mode: raw.delete_mode,
             delete_action: raw.delete_action,
             duplicate_action: raw.duplicate_action,
+            preprocess_function: raw.preprocess_function,
             preprocess_transform,
+            deduplication: raw.deduplication,
+            lookup_cache: raw.lookup_cache,
+            deferred_lookup_resolution: raw.deferred_lookup_resolution,
+            concurrency: raw.concurrency,
+            import_options_override: raw.import_options,
+            computed_fields,
             columns,
             key_columns,
+            key_normalization,
+            merge_order,
+            default_on_insert,
+            surrogate_key,
+            hierarchy,
+            bridge,
+            operation,
+            history,
+            route: raw.route,
         })
     }
 
@@ -291,14 +1116,54 @@ impl TableMapper {
         &self.name
     }
 
+    pub fn depends_on(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.depends_on.iter().map(String::as_str)
+    }
+
     pub fn field_group(&self) -> &str {
         &self.field_group
     }
 
+    pub fn delete_mode(&self) -> DeleteMode {
+        self.delete_mode
+    }
+
+    pub fn duplicate_action(&self) -> &DuplicateAction {
+        &self.duplicate_action
+    }
+
+    pub fn preprocess_function(&self) -> Option<&str> {
+        self.preprocess_function.as_deref()
+    }
+
     pub fn preprocess_transform(&self) -> Option<&dyn PreprocessTransform> {
         self.preprocess_transform.as_deref()
     }
 
+    pub fn deduplication(&self) -> Option<&Deduplication> {
+        self.deduplication.as_ref()
+    }
+
+    pub fn lookup_cache(&self) -> bool {
+        self.lookup_cache
+    }
+
+    pub fn deferred_lookup_resolution(&self) -> bool {
+        self.deferred_lookup_resolution
+    }
+
+    pub fn concurrency(&self) -> Concurrency {
+        self.concurrency
+    }
+
+    pub fn import_options_override(&self) -> &ImportOptionsOverride {
+        &self.import_options_override
+    }
+
+    pub fn computed_fields(&self) -> &[ComputedField] {
+        &self.computed_fields
+    }
+
     pub fn columns(&self) -> impl ExactSizeIterator<Item = &TableMapperColumn> {
         self.columns.iter()
     }
@@ -306,6 +1171,194 @@ impl TableMapper {
     pub fn key_columns(&self) -> impl ExactSizeIterator<Item = &ColumnIdentifier> {
         self.key_columns.iter()
     }
+
+    /// Key columns with their normalizations, which are empty for key columns compared as is.
+    pub fn normalized_key_columns(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&ColumnIdentifier, &[KeyNormalization])> {
+        self.key_columns.iter().map(|key_column| {
+            (
+                key_column,
+                self.key_normalization
+                    .get(key_column)
+                    .map_or(&[][..], Vec::as_slice),
+            )
+        })
+    }
+
+    pub fn merge_order(&self) -> Option<&MergeOrder> {
+        self.merge_order.as_ref()
+    }
+
+    /// Columns omitted from the insert of new target table rows, taking their default instead.
+    pub fn default_on_insert(&self) -> &[ColumnIdentifier] {
+        &self.default_on_insert
+    }
+
+    pub fn surrogate_key(&self) -> Option<&SurrogateKey> {
+        self.surrogate_key.as_ref()
+    }
+
+    pub fn hierarchy(&self) -> Option<&Hierarchy> {
+        self.hierarchy.as_ref()
+    }
+
+    pub fn bridge(&self) -> Option<&Bridge> {
+        self.bridge.as_ref()
+    }
+
+    pub fn route(&self) -> Option<&Route> {
+        self.route.as_ref()
+    }
+
+    pub fn operation(&self) -> Option<&Operation> {
+        self.operation.as_ref()
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+}
+
+impl MergeOrder {
+    /// Columns ordering the rows, ending with the tie-breaker.
+    pub fn columns(&self) -> impl Iterator<Item = &MergeOrderColumn> {
+        self.order_by.iter().chain([&self.tie_breaker])
+    }
+}
+
+impl Hierarchy {
+    pub fn parent_column(&self) -> &ColumnIdentifier {
+        &self.parent_column
+    }
+
+    pub fn parent_key_column(&self) -> &ColumnIdentifier {
+        &self.parent_key_column
+    }
+}
+
+impl Route {
+    pub(crate) fn new(field: String, value: String) -> Self {
+        Route { field, value }
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Whether the record is routed to the target table of the table mapper.
+    pub fn matches(&self, record: &DataSourceRecord) -> bool {
+        record.get(&self.field) == Some(self.value.as_str())
+    }
+}
+
+impl Operation {
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Column of the temporary table the operation of each record is staged into.
+    pub fn identifier(&self) -> &ColumnIdentifier {
+        &self.column_identifier
+    }
+
+    pub fn insert(&self) -> &str {
+        &self.insert
+    }
+
+    pub fn update(&self) -> &str {
+        &self.update
+    }
+
+    pub fn delete(&self) -> &str {
+        &self.delete
+    }
+
+    /// Whether the value is the value of one of the operations.
+    pub fn is_operation(&self, value: &str) -> bool {
+        value == self.insert || value == self.update || value == self.delete
+    }
+}
+
+impl History {
+    pub fn valid_from_column(&self) -> &ColumnIdentifier {
+        &self.valid_from_column
+    }
+
+    pub fn valid_to_column(&self) -> &ColumnIdentifier {
+        &self.valid_to_column
+    }
+
+    pub fn tracked_columns(&self) -> &[ColumnIdentifier] {
+        &self.tracked_columns
+    }
+}
+
+impl Bridge {
+    pub fn left_column(&self) -> &ColumnIdentifier {
+        &self.left_column
+    }
+
+    pub fn right_column(&self) -> &ColumnIdentifier {
+        &self.right_column
+    }
+
+    pub fn symmetric(&self) -> bool {
+        self.symmetric
+    }
+}
+
+impl SurrogateKey {
+    pub fn identifier(&self) -> &ColumnIdentifier {
+        &self.column_identifier
+    }
+
+    /// Table the surrogate keys of inserted rows are captured into, with the key columns and the
+    /// run id.
+    pub fn capture_table(&self) -> &TableIdentifier {
+        &self.capture_table
+    }
+}
+
+impl MergeOrderColumn {
+    pub fn identifier(&self) -> &ColumnIdentifier {
+        &self.column_identifier
+    }
+
+    pub fn direction(&self) -> SortDirection {
+        self.direction
+    }
+}
+
+impl KeyNormalization {
+    /// Wraps the SQL expression in the normalization.
+    pub fn sql_expression(self, expression: &str) -> String {
+        match self {
+            KeyNormalization::Trim => format!("LTRIM(RTRIM({expression}))"),
+            KeyNormalization::Upper => format!("UPPER({expression})"),
+            KeyNormalization::Lower => format!("LOWER({expression})"),
+            KeyNormalization::ZeroPad(width) => {
+                let text = format!("CAST({expression} AS NVARCHAR(4000))");
+
+                format!(
+                    "CASE WHEN LEN({text}) < {width} THEN RIGHT(REPLICATE(N'0', {width}) + {text}, {width}) ELSE {text} END"
+                )
+            }
+        }
+    }
+}
+
+impl SortDirection {
+    pub fn sql_keyword(self) -> &'static str {
+        match self {
+            SortDirection::Ascending => "ASC",
+            SortDirection::Descending => "DESC",
+        }
+    }
 }
 
 impl Table for TableMapper {
@@ -340,6 +1393,16 @@ impl Column for ParserColumn {
     }
 }
 
+impl Column for SequenceColumn {
+    fn identifier(&self) -> &ColumnIdentifier {
+        &self.column_identifier
+    }
+
+    fn map(&self) -> bool {
+        self.map_column
+    }
+}
+
 impl Column for LookupColumn {
     fn identifier(&self) -> &ColumnIdentifier {
         &self.column_identifier
@@ -382,6 +1445,7 @@ impl Column for TableMapperColumn {
             TableMapperColumn::Static(static_column) => Column::identifier(static_column),
             TableMapperColumn::Parser(parser_column) => Column::identifier(parser_column),
             TableMapperColumn::Lookup(lookup_column) => Column::identifier(lookup_column),
+            TableMapperColumn::Sequence(sequence_column) => Column::identifier(sequence_column),
         }
     }
 
@@ -390,6 +1454,7 @@ impl Column for TableMapperColumn {
             TableMapperColumn::Static(static_column) => Column::map(static_column),
             TableMapperColumn::Parser(parser_column) => Column::map(parser_column),
             TableMapperColumn::Lookup(lookup_column) => Column::map(lookup_column),
+            TableMapperColumn::Sequence(sequence_column) => Column::map(sequence_column),
         }
     }
 }
@@ -400,8 +1465,124 @@ impl FieldColumn for ParserColumn {
     }
 }
 
+impl SequenceColumn {
+    pub fn new(
+        column_identifier: &ColumnIdentifier,
+        map_column: bool,
+        start: i64,
+        group_fields: Vec<String>,
+    ) -> SequenceColumn {
+        SequenceColumn {
+            column_identifier: column_identifier.clone(),
+            map_column,
+            start,
+            group_fields,
+            // distinct from the names of data source fields
+            field_name: format!("$sequence_{}", column_identifier.part_unescaped()),
+        }
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn group_fields(&self) -> &[String] {
+        &self.group_fields
+    }
+}
+
+/// Sequence columns are written from a field added to each record, see
+/// [`crate::sequence::Sequencer`].
+impl FieldColumn for SequenceColumn {
+    fn field_name(&self) -> &str {
+        &self.field_name
+    }
+}
+
 impl FieldColumn for ParserKeyColumn {
     fn field_name(&self) -> &str {
         &self.field_name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_run_id_for_each_run() {
+        let run_id = new_run_id();
+
+        assert_ne!(run_id, new_run_id());
+        assert_eq!(run_id.len(), 36);
+        assert_eq!(&run_id[14..15], "4");
+    }
+
+    #[test]
+    fn reject_quarantine_with_deferred_lookup_resolution() {
+        let raw = |deferred_lookup_resolution| -> TableMapperRaw {
+            serde_json::from_value(serde_json::json!({
+                "name": "Country",
+                "field_group": "Country",
+                "table_identifier": "[dbo].[Country]",
+                "delete_mode": "Partial",
+                "delete_action": "None",
+                "duplicate_action": "Reject",
+                "deferred_lookup_resolution": deferred_lookup_resolution,
+                "columns": [
+                    { "Parser": { "column_identifier": "[Code]", "map_column": true, "field_name": "code" } },
+                    { "Lookup": {
+                        "column_identifier": "[RegionID]",
+                        "map_column": true,
+                        "table_identifier": "[dbo].[Region]",
+                        "output_column_identifier": "[ID]",
+                        "key_columns": [{ "ParserKeyColumn": { "key_column_identifier": "[Code]", "field_name": "region" } }],
+                        "orphan_policy": "Quarantine"
+                    } }
+                ],
+                "key_columns": ["[Code]"]
+            }))
+            .unwrap()
+        };
+
+        assert!(TableMapper::new(raw(false), None).is_ok());
+        assert!(matches!(
+            TableMapper::new(raw(true), None),
+            Err(CreateTableMapperError {
+                source: CreateTableMapperErrorKind::DeferredQuarantine(column),
+                ..
+            }) if column == "RegionID"
+        ));
+    }
+
+    #[test]
+    fn normalize_lookup_key_values() {
+        let normalization =
+            |unicode, case_insensitive, accent_insensitive| LookupKeyNormalization {
+                unicode,
+                case_insensitive,
+                accent_insensitive,
+            };
+
+        assert!(matches!(
+            normalization(false, false, false).apply("Cafe\u{301}"),
+            Cow::Borrowed("Cafe\u{301}")
+        ));
+        assert_eq!(
+            normalization(true, false, false).apply("Cafe\u{301}"),
+            "Caf\u{e9}"
+        );
+        assert_eq!(
+            normalization(false, true, false).apply("CAF\u{c9}"),
+            "caf\u{e9}"
+        );
+        assert_eq!(normalization(false, false, true).apply("Caf\u{e9}"), "Cafe");
+        assert_eq!(normalization(true, true, true).apply("CAFE\u{301}"), "cafe");
+
+        assert_eq!(normalization(true, false, false).collation(), None);
+        assert_eq!(
+            normalization(false, true, true).collation(),
+            Some("Latin1_General_100_CI_AI")
+        );
+    }
+}
@@ -1,73 +1,362 @@
+use crate::deduplication::Deduplication;
 use crate::identifier::TableIdentifier;
-use crate::table_mapper::{DeleteAction, DeleteMode, DuplicateAction};
+use crate::import_options::ImportOptionsOverride;
+use crate::table_mapper::{
+    Concurrency, DeleteAction, DeleteMode, DuplicateAction, KeyNormalization,
+    LookupKeyNormalization, OrphanPolicy, Route, SortDirection, Spatial,
+};
+use rustc_hash::FxHashMap as HashMap;
 use schemars::JsonSchema;
 use serde::Deserialize;
 
-#[derive(Debug, JsonSchema, Deserialize)]
-#[serde(rename = "TableMapper")]
+/// Maps a field group of the data source onto a target table
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "TableMapper", deny_unknown_fields)]
 pub struct TableMapperRaw {
+    /// Unique name of the table mapper
     pub(crate) name: String,
+    /// Names of table mappers which must be executed before this table mapper
+    #[serde(default)]
+    pub(crate) depends_on: Vec<String>,
+    /// Name of the field group of the data source to read records from
     pub(crate) field_group: String,
+    /// Target table
     pub(crate) table_identifier: TableIdentifier,
     pub(crate) delete_mode: DeleteMode,
     pub(crate) delete_action: DeleteAction,
     pub(crate) duplicate_action: DuplicateAction,
+    /// Name of the function within the preprocess script to transform each record with
     pub(crate) preprocess_function: Option<String>,
+    /// Drop records whose fields match a record already read from the data source
+    #[serde(default)]
+    pub(crate) deduplication: Option<Deduplication>,
+    /// Resolve lookups once per distinct key tuple rather than once per staged row
+    #[serde(default)]
+    pub(crate) lookup_cache: bool,
+    /// Re-resolve NULL lookups once their tables are imported, merging the resolved rows again.
+    /// No other table mapper may load the same target table
+    #[serde(default)]
+    pub(crate) deferred_lookup_resolution: bool,
+    /// Hints limiting the load the table mapper puts on the server
+    #[serde(default)]
+    pub(crate) concurrency: Concurrency,
+    /// Import options taking precedence over the command line for this table mapper
+    #[serde(default)]
+    pub(crate) import_options: ImportOptionsOverride,
+    /// Fields computed from each record by an expression, in order, each visible to the later
+    /// computed fields and to the columns, such as `if([Amount] < 0, 'debit', 'credit')`
+    #[serde(default)]
+    pub(crate) computed_fields: Vec<ComputedFieldRaw>,
     pub(crate) columns: Vec<TableMapperColumnRaw>,
+    /// Columns of the target table identifying a row when merging
+    #[schemars(schema_with = "crate::identifier::column_names_schema")]
     pub(crate) key_columns: Vec<String>,
+    /// Normalizations of key column values, applied in order to both the temporary table and the
+    /// target table values when comparing them, keyed by key column name
+    #[serde(default)]
+    pub(crate) key_normalization: HashMap<String, Vec<KeyNormalization>>,
+    /// Order of temporary table rows with the same key columns, only the first of which is
+    /// merged, making the merge deterministic when duplicates are retained
+    #[serde(default)]
+    pub(crate) merge_order: Option<MergeOrderRaw>,
+    /// Columns omitted from the insert of new target table rows, such that the column default
+    /// constraint applies, yet which are still updated on matched target table rows
+    #[serde(default)]
+    #[schemars(schema_with = "crate::identifier::column_names_schema")]
+    pub(crate) default_on_insert: Vec<String>,
+    /// Identity column of the target table, whose values assigned to the rows inserted by the
+    /// merge are captured alongside the key columns into the `[import].[<table>_surrogate_keys]`
+    /// table, with the run id, such that later table mappers may look them up
+    #[serde(default)]
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(crate) surrogate_key: Option<String>,
+    /// Self-referencing hierarchy of the target table, whose rows are merged a level at a time,
+    /// parents before their children, resolving the parent column of each level once its parents
+    /// are merged
+    #[serde(default)]
+    pub(crate) hierarchy: Option<HierarchyRaw>,
+    /// Many-to-many bridge of the target table, whose key columns are the lookups of its two sides,
+    /// the relationships of each left key being replaced by those of the data source
+    #[serde(default)]
+    pub(crate) bridge: Option<BridgeRaw>,
+    /// Field of each record of a delta file holding its operation, choosing whether the record
+    /// is inserted, updates or deletes the target table row matching its key columns
+    #[serde(default)]
+    pub(crate) operation: Option<OperationRaw>,
+    /// Effective-dated history of the target table, a slowly changing dimension of type 2, whose
+    /// current row of each key is closed and succeeded by a new row when a tracked column changes
+    #[serde(default)]
+    pub(crate) history: Option<HistoryRaw>,
+    /// Target table of each record, chosen by the value of a field, the table mapper being
+    /// executed once for each target table, staging and merging only the records routed to it
+    #[serde(default)]
+    pub(crate) routing: Option<RoutingRaw>,
+    /// Field value selecting the records of a table mapper expanded from a routing
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub(crate) route: Option<Route>,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
+impl TableMapperRaw {
+    /// Expands the table mapper into a table mapper for each target table of its routing, named
+    /// after the table mapper and the field value, such as `Orders[2024-01]`, or returns the
+    /// table mapper itself if it is not routed.
+    pub(crate) fn expand_routing(mut self) -> Vec<TableMapperRaw> {
+        let Some(routing) = self.routing.take() else {
+            return vec![self];
+        };
+
+        let mut targets = routing.targets.into_iter().collect::<Vec<_>>();
+
+        targets.sort_by(|(value, _), (other_value, _)| value.cmp(other_value));
+
+        targets
+            .into_iter()
+            .map(|(value, table_identifier)| TableMapperRaw {
+                name: format!("{}[{}]", self.name, value),
+                table_identifier,
+                route: Some(Route::new(routing.field.clone(), value)),
+                ..self.clone()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "ComputedField", deny_unknown_fields)]
+pub struct ComputedFieldRaw {
+    /// Name of the field added to each record
+    pub(super) name: String,
+    /// Expression of the fields of the record, of arithmetic, comparison and logical operators,
+    /// `&` concatenating text, and functions such as `if`, `coalesce`, `upper` and `round`
+    pub(super) expression: String,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "Routing", deny_unknown_fields)]
+pub struct RoutingRaw {
+    /// Field whose value chooses the target table of each record
+    pub(super) field: String,
+    /// Target table of the records of each field value, keyed by field value, records of any
+    /// other value not being imported
+    #[schemars(length(min = 1))]
+    pub(super) targets: HashMap<String, TableIdentifier>,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "Bridge", deny_unknown_fields)]
+pub struct BridgeRaw {
+    /// Lookup key column of the side whose relationships each data source lists in full, such as
+    /// `A_CountryID`
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) left_column: String,
+    /// Lookup key column of the other side, such as `B_CountryID`
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) right_column: String,
+    /// Relationships have no direction, each pair being ordered such that the left key is less
+    /// than the right key, and the left keys of the data source matching either side
+    #[serde(default)]
+    pub(super) symmetric: bool,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "Operation", deny_unknown_fields)]
+pub struct OperationRaw {
+    /// Field holding the operation of each record
+    pub(super) field: String,
+    /// Value of records inserted, or updating the matching row if there is one, `I` by default
+    #[serde(default = "OperationRaw::default_insert")]
+    pub(super) insert: String,
+    /// Value of records updating the matching row, ignored if there is none, `U` by default
+    #[serde(default = "OperationRaw::default_update")]
+    pub(super) update: String,
+    /// Value of records deleting the matching row, ignored if there is none, `D` by default
+    #[serde(default = "OperationRaw::default_delete")]
+    pub(super) delete: String,
+}
+
+impl OperationRaw {
+    fn default_insert() -> String {
+        String::from("I")
+    }
+
+    fn default_update() -> String {
+        String::from("U")
+    }
+
+    fn default_delete() -> String {
+        String::from("D")
+    }
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "History", deny_unknown_fields)]
+pub struct HistoryRaw {
+    /// Column of the start of the period of each row, such as `ValidFrom`, set to the time of the
+    /// merge unless it is a column of the table mapper
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) valid_from_column: String,
+    /// Column of the end of the period of each row, such as `ValidTo`, NULL for the current row of
+    /// each key
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) valid_to_column: String,
+    /// Columns whose change closes the current row and inserts its successor, the other columns
+    /// of the current row being updated in place
+    #[schemars(schema_with = "crate::identifier::column_names_schema")]
+    pub(super) tracked_columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "Hierarchy", deny_unknown_fields)]
+pub struct HierarchyRaw {
+    /// Lookup column resolving the parent row from the target table itself, such as `ParentID`,
+    /// with the `Null` orphan policy
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) parent_column: String,
+    /// Parser column holding the key of the parent row, such as `ParentCode`, matched against the
+    /// key column of the table mapper
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) parent_key_column: String,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "MergeOrder", deny_unknown_fields)]
+pub struct MergeOrderRaw {
+    /// Columns ordering the rows, before the tie-breaker
+    #[serde(default)]
+    pub(super) order_by: Vec<MergeOrderColumnRaw>,
+    /// Column ordering rows not ordered by the other columns, which should be unique for each
+    /// record, such as a sequence column without group fields
+    pub(super) tie_breaker: MergeOrderColumnRaw,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "MergeOrderColumn", deny_unknown_fields)]
+pub struct MergeOrderColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) column_identifier: String,
+    #[serde(default)]
+    pub(super) direction: SortDirection,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
 #[serde(rename = "TableMapperColumn")]
 pub enum TableMapperColumnRaw {
+    /// Column with the same value for every record
     Static(StaticColumnRaw),
+    /// Column with the value of a field from each record
     Parser(ParserColumnRaw),
+    /// Column with the value of a column from a lookup table, matched by key columns
     Lookup(LookupColumnRaw),
+    /// Column numbering each record of the run, or of each group of records
+    Sequence(SequenceColumnRaw),
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
-#[serde(rename = "StaticColumn")]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "StaticColumn", deny_unknown_fields)]
 pub struct StaticColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) column_identifier: String,
+    /// Whether the column is written to the target table
     pub(super) map_column: bool,
+    /// Value of the column, or a token expanded when the table mapper is executed: `$now`,
+    /// `$utcnow`, `$user`, `$hostname` or `$run_id`
     pub(super) value: String,
+    /// Whether the value is a secret, redacted from logs and descriptions of the import profile
+    #[serde(default)]
+    pub(super) sensitive: bool,
+    /// Whether the value is a template rendered per record, interpolating record fields and
+    /// built-in tokens such as `{SourceSystem}-{Code}` or `{$record_number}`
+    #[serde(default)]
+    pub(super) template: bool,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
-#[serde(rename = "ParserColumn")]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "ParserColumn", deny_unknown_fields)]
 pub struct ParserColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) column_identifier: String,
+    /// Whether the column is written to the target table
     pub(super) map_column: bool,
     pub(super) field_name: String,
+    /// Whether the field values are JSON, such that values which are not valid JSON are NULL
+    /// rather than written to columns consumed by `OPENJSON`
+    #[serde(default)]
+    pub(super) json: bool,
+    /// Spatial type the field values are converted into, for `geography` and `geometry` columns
+    #[serde(default)]
+    pub(super) spatial: Option<Spatial>,
+    /// Number of decimal places implied by the field values, e.g. `2` for amounts in cents, by
+    /// which the values are divided when converted into an integer, floating point, decimal or
+    /// money column
+    #[serde(default)]
+    pub(super) implied_decimals: u8,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "SequenceColumn", deny_unknown_fields)]
+pub struct SequenceColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
+    pub(super) column_identifier: String,
+    /// Whether the column is written to the target table
+    pub(super) map_column: bool,
+    /// Number of the first record of the run, or of each group
+    #[serde(default = "SequenceColumnRaw::default_start")]
+    pub(super) start: i64,
+    /// Fields whose combined values group records, each group being numbered separately
+    #[serde(default)]
+    pub(super) group_fields: Vec<String>,
+}
+
+impl SequenceColumnRaw {
+    fn default_start() -> i64 {
+        1
+    }
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
-#[serde(rename = "LookupColumn")]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "LookupColumn", deny_unknown_fields)]
 pub struct LookupColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) column_identifier: String,
+    /// Whether the column is written to the target table
     pub(super) map_column: bool,
+    /// Lookup table
     pub(super) table_identifier: TableIdentifier,
+    /// Column of the lookup table providing the value
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) output_column_identifier: String,
     pub(super) key_columns: Vec<LookupKeyColumnRaw>,
+    #[serde(default)]
+    pub(super) orphan_policy: OrphanPolicy,
+    /// Normalization of the key values, such that values differing only by their Unicode
+    /// representation, case or accents match
+    #[serde(default)]
+    pub(super) key_normalization: LookupKeyNormalization,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
 #[serde(rename = "LookupKeyColumn")]
 pub enum LookupKeyColumnRaw {
+    /// Lookup table column matched against a field from each record
     ParserKeyColumn(ParserKeyColumnRaw),
+    /// Lookup table column matched against a column of the table mapper
     ProcessedKeyColumn(ProcessedKeyColumnRaw),
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
-#[serde(rename = "ParserKeyColumn")]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "ParserKeyColumn", deny_unknown_fields)]
 pub struct ParserKeyColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) key_column_identifier: String,
     pub(super) field_name: String,
 }
 
-#[derive(Debug, JsonSchema, Deserialize)]
-#[serde(rename = "ProcessedKeyColumn")]
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename = "ProcessedKeyColumn", deny_unknown_fields)]
 pub struct ProcessedKeyColumnRaw {
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) key_column_identifier: String,
+    #[schemars(schema_with = "crate::identifier::column_name_schema")]
     pub(super) column_identifier: String,
 }
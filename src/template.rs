@@ -0,0 +1,212 @@
+use crate::data_source::DataSourceRecord;
+use crate::identifier::ColumnIdentifier;
+use crate::table_mapper::{TableMapper, TableMapperColumn};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Value of a static column interpolating the fields of each record and built-in tokens, such as
+/// `{SourceSystem}-{Code}` or `{$run_id}`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+    field_name: String,
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+enum TemplatePart {
+    Literal(String),
+    Field(String),
+    Token(TemplateToken),
+}
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+enum TemplateToken {
+    RunId,
+    RecordNumber,
+    LineNumber,
+}
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("unclosed '{{' at position {0}")]
+    Unclosed(usize),
+    #[error("unmatched '}}' at position {0}")]
+    Unmatched(usize),
+    #[error("empty field name at position {0}")]
+    EmptyField(usize),
+    #[error("unknown token '{0}'")]
+    UnknownToken(String),
+}
+
+impl Template {
+    pub fn parse(column_identifier: &ColumnIdentifier, value: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = value.char_indices().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => literal.push('}'),
+                '}' => return Err(TemplateError::Unmatched(position)),
+                '{' => {
+                    let name = chars
+                        .by_ref()
+                        .map(|(_, c)| c)
+                        .take_while(|&c| c != '}')
+                        .collect::<String>();
+
+                    if !value[position + 1..].contains('}') {
+                        return Err(TemplateError::Unclosed(position));
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    parts.push(match name.trim() {
+                        "" => return Err(TemplateError::EmptyField(position)),
+                        "$run_id" => TemplatePart::Token(TemplateToken::RunId),
+                        "$record_number" => TemplatePart::Token(TemplateToken::RecordNumber),
+                        "$line_number" => TemplatePart::Token(TemplateToken::LineNumber),
+                        token if token.starts_with('$') => {
+                            return Err(TemplateError::UnknownToken(token.to_owned()));
+                        }
+                        field => TemplatePart::Field(field.to_owned()),
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        Ok(Template {
+            parts,
+            // distinct from the names of data source fields
+            field_name: format!("$template_{}", column_identifier.part_unescaped()),
+        })
+    }
+
+    /// Name of the field the template is rendered into.
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    /// Fields of the records interpolated by the template.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            TemplatePart::Field(field) => Some(field.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn render(&self, record: &DataSourceRecord, run_id: &str) -> String {
+        let mut rendered = String::new();
+
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => rendered.push_str(literal),
+                TemplatePart::Field(field) => rendered.push_str(record.get(field).unwrap_or("")),
+                TemplatePart::Token(TemplateToken::RunId) => rendered.push_str(run_id),
+                TemplatePart::Token(TemplateToken::RecordNumber) => {
+                    rendered.push_str(&record.index().record_number.to_string());
+                }
+                TemplatePart::Token(TemplateToken::LineNumber) => {
+                    rendered.push_str(&record.index().line_start.to_string());
+                }
+            }
+        }
+
+        rendered
+    }
+}
+
+/// Renders the templated static columns of a table mapper for the records read from a data
+/// source, adding the value of each templated static column to each record as a field.
+#[derive(Debug)]
+pub struct Templater<'a> {
+    templates: Vec<(&'a Template, Arc<str>)>,
+    run_id: String,
+}
+
+impl<'a> Templater<'a> {
+    /// Returns `None` if the table mapper has no templated static columns.
+    pub fn new(table_mapper: &'a TableMapper, run_id: &str) -> Option<Self> {
+        let templates = table_mapper
+            .columns()
+            .filter_map(|column| match column {
+                TableMapperColumn::Static(static_column) => static_column.template(),
+                _ => None,
+            })
+            .map(|template| (template, Arc::from(template.field_name())))
+            .collect::<Vec<_>>();
+
+        (!templates.is_empty()).then(|| Templater {
+            templates,
+            run_id: run_id.to_owned(),
+        })
+    }
+
+    pub fn render(&self, record: &mut DataSourceRecord) {
+        for (template, field_name) in &self.templates {
+            let rendered = template.render(record, &self.run_id);
+
+            record.fields_mut().insert(field_name.clone(), &rendered);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSourceRecordIndex;
+    use crate::data_source::string_map::StringMap;
+    use std::num::NonZero;
+
+    #[test]
+    fn render_fields_and_tokens() {
+        let column_identifier: ColumnIdentifier = "[dbo].[Product].[SourceKey]".parse().unwrap();
+        let template = |value| Template::parse(&column_identifier, value);
+
+        let record = DataSourceRecord::new(
+            StringMap::from([("SourceSystem", "ERP"), ("Code", "A-1")]),
+            DataSourceRecordIndex {
+                record_number: NonZero::new(3).unwrap(),
+                line_start: 4,
+                line_end: 4,
+                byte_start: 0,
+                byte_end: 0,
+            },
+        );
+
+        let source_key = template("{SourceSystem}-{ Code }").unwrap();
+
+        assert_eq!(source_key.field_name(), "$template_SourceKey");
+        assert_eq!(
+            source_key.fields().collect::<Vec<_>>(),
+            ["SourceSystem", "Code"]
+        );
+        assert_eq!(source_key.render(&record, "run"), "ERP-A-1");
+        assert_eq!(
+            template("{{{$record_number}:{$line_number}}} {Missing}.")
+                .unwrap()
+                .render(&record, "run"),
+            "{3:4} ."
+        );
+        assert_eq!(template("{$run_id}").unwrap().render(&record, "run"), "run");
+
+        assert!(matches!(template("{Code"), Err(TemplateError::Unclosed(0))));
+        assert!(matches!(
+            template("Code}"),
+            Err(TemplateError::Unmatched(4))
+        ));
+        assert!(matches!(template("a{}"), Err(TemplateError::EmptyField(1))));
+        assert!(matches!(
+            template("{$now}"),
+            Err(TemplateError::UnknownToken(token)) if token == "$now"
+        ));
+    }
+}
@@ -1,23 +1,90 @@
-use crate::column_graph::ColumnGraph;
-use crate::identifier::{Identifier, SchemaIdentifier, TableIdentifier};
+use crate::backend::SqlBackend;
+use crate::column_graph::{ColumnGraph, ColumnNode};
+use crate::identifier::{Identifier, ParseIdentifierError, SchemaIdentifier, TableIdentifier};
 use crate::import_options::ImportOptions;
-use crate::table_mapper::Table;
+use crate::insert_processor::staging_type;
+use crate::spatial;
+use crate::table_mapper::{OrphanPolicy, Table, TableMapper, TableMapperColumn};
 use crate::trace_sql;
 use indoc::formatdoc;
 use itertools::{Itertools, Position};
-use log::trace;
+use log::{trace, warn};
+use rustc_hash::FxHasher;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::{Arc, Mutex, PoisonError};
 use thiserror::Error;
-use tiberius::{Client, ColumnFlag};
-use tokio::net::TcpStream;
-use tokio_util::compat::Compat;
+use tiberius::ColumnFlag;
 
+/// Staging table of a table mapper, dropped when finalized unless `--no-drop` is set, or otherwise
+/// abandoned to the [`AbandonedTables`] of its connection when dropped.
 #[derive(Debug)]
 pub struct TemporaryTable {
     table_identifier: TableIdentifier,
+    orphans_identifier: TableIdentifier,
+    /// Whether the table is still to be dropped, unset once finalized or if `--no-drop` is set
+    pending_drop: bool,
+    release: Release,
+    /// Whether each row records the number of the record it was inserted from, in
+    /// [`RECORD_NUMBER_COLUMN`]
+    record_numbers: bool,
+    abandoned_tables: AbandonedTables,
 }
 
+/// Statement releasing the temporary table once the table mapper is executed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Release {
+    Drop,
+    /// Empties a table reused by later runs, such that its indexes are retained
+    Truncate,
+    /// Empties a reused table which may be memory-optimized, which cannot be truncated
+    Delete,
+}
+
+/// Temporary tables of a connection abandoned without being finalized, and how each is released,
+/// to be released on the connection by [`drop_abandoned`].
+#[derive(Debug, Clone, Default)]
+pub struct AbandonedTables(Arc<Mutex<Vec<(TableIdentifier, Release)>>>);
+
+impl AbandonedTables {
+    // the list is consistent even if poisoned, as neither pushing nor taking panics midway
+    fn push(&self, table_identifier: TableIdentifier, release: Release) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((table_identifier, release));
+    }
+
+    fn take(&self) -> Vec<(TableIdentifier, Release)> {
+        mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Forgets the abandoned tables in tempdb, dropped by the server as the session of the
+    /// connection ended, such that the remaining tables are dropped on another connection.
+    pub fn end_session(&self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(table_identifier, _)| !table_identifier.part_unescaped().starts_with('#'));
+    }
+}
+
+/// Column of the number of the record each row was inserted from, such that rows are traced back
+/// to their records after merging.
+pub const RECORD_NUMBER_COLUMN: &str = "[$record_number]";
+
+/// Column marking the rows of which a lookup resolving to NULL when merged is resolved by the
+/// deferred lookup resolution, such that only those rows are merged again.
+pub const RESOLVED_COLUMN: &str = "[$resolved]";
+
+/// Name of the extended property of a reused temporary table recording a hash of its columns.
+const COLUMNS_PROPERTY: &str = "import_columns";
+
 #[derive(Debug, Error)]
 pub enum CreateTemporaryTableError {
+    #[error("invalid temporary table name for table '{0}'")]
+    InvalidTableName(TableIdentifier, #[source] ParseIdentifierError),
     #[error("no non transient columns for temporary table creation")]
     NoNonTransientColumns,
     #[error("temporary table could not be created: {0}")]
@@ -26,15 +93,31 @@ pub enum CreateTemporaryTableError {
 
 impl TemporaryTable {
     pub async fn new(
-        client: &mut Client<Compat<TcpStream>>,
-        target_table: &TableIdentifier,
+        client: &mut impl SqlBackend,
+        table_mapper: &TableMapper,
         column_graph: &ColumnGraph,
+        import_options: &ImportOptions,
+        abandoned_tables: &AbandonedTables,
     ) -> Result<TemporaryTable, CreateTemporaryTableError> {
+        let target_table = table_mapper.identifier();
         let schema: SchemaIdentifier = "[import]".parse().unwrap();
-        let table_identifier = TableIdentifier::with_schema(&schema, target_table.part_unescaped())
-            .expect("Temporary table identifier should be valid");
+        let invalid_table_name =
+            |err| CreateTemporaryTableError::InvalidTableName(target_table.to_owned(), err);
+        let tempdb_staging = import_options.tempdb_staging;
+        // the schema of a temporary table in tempdb is ignored, and it is only visible to the
+        // session creating it, which drops it at the latest once disconnected
+        let table_identifier = if tempdb_staging {
+            TableIdentifier::with_schema(
+                &"[dbo]".parse().unwrap(),
+                &format!("#{}", target_table.part_unescaped()),
+            )
+        } else {
+            TableIdentifier::with_schema(&schema, target_table.part_unescaped())
+        }
+        .map_err(invalid_table_name)?;
+        let orphans_identifier = orphans_identifier(target_table)?;
 
-        let columns = column_graph
+        let mut columns = column_graph
             .groups()
             .with_position()
             .flat_map(|(position, group)| {
@@ -44,69 +127,600 @@ impl TemporaryTable {
                         let nullable = metadata.flags.contains(ColumnFlag::Nullable)
                             || matches!(position, Position::Middle | Position::Last);
 
-                        Some(format!(
-                            "{column_name} {column_type} {nullable}",
-                            column_name = node.unique_identifier().part(),
-                            column_type = metadata.ty,
-                            nullable = if nullable { "NULL" } else { "NOT NULL" },
-                        ))
+                        let definition = match node.column() {
+                            // spatial values are converted from the inserted Well-Known Text
+                            ColumnNode::ParserColumn {
+                                column: parser_column,
+                                ..
+                            } if let Some(spatial) = parser_column.spatial() => format!(
+                                "{column_name} {column_type} NULL, {text_column} nvarchar(max) NULL",
+                                column_name = node.unique_identifier().part(),
+                                column_type = spatial.spatial_type().keyword(),
+                                text_column = spatial::text_column(node.unique_identifier()),
+                            ),
+                            _ => format!(
+                                "{column_name} {column_type} {nullable}",
+                                column_name = node.unique_identifier().part(),
+                                column_type = staging_type(&metadata.ty),
+                                nullable = if nullable { "NULL" } else { "NOT NULL" },
+                            ),
+                        };
+
+                        let column_name = node.unique_identifier().part().to_owned();
+
+                        Some((*node.index(), (column_name, definition)))
                     } else {
                         None
                     }
                 })
             })
+            // nodes are added in the order columns are declared by the table mapper
+            .sorted_by_key(|(index, _)| *index)
+            .map(|(_, column)| column)
             .collect::<Vec<_>>();
 
         if columns.is_empty() {
             return Err(CreateTemporaryTableError::NoNonTransientColumns);
         }
 
-        let statement = formatdoc!(
-            "
-            IF EXISTS (SELECT * FROM sys.objects WHERE object_id = OBJECT_ID(N'{table_name}') AND type in (N'U'))
-            BEGIN
-                DROP TABLE {table_name}
-            END
+        // record numbers identify the rows whose deduplication history keys are saved once merged
+        let record_numbers = import_options.defer_constraints
+            || table_mapper
+                .deduplication()
+                .is_some_and(|deduplication| deduplication.history());
+
+        if record_numbers {
+            columns.push((
+                RECORD_NUMBER_COLUMN.to_owned(),
+                format!("{} BIGINT NOT NULL", RECORD_NUMBER_COLUMN),
+            ));
+        }
 
+        if table_mapper.deferred_lookup_resolution() {
+            columns.push((
+                RESOLVED_COLUMN.to_owned(),
+                format!("{} BIT NULL", RESOLVED_COLUMN),
+            ));
+        }
+
+        let drop_table = if tempdb_staging {
+            formatdoc!(
+                "
+                IF OBJECT_ID(N'tempdb..{table_part}', N'U') IS NOT NULL
+                BEGIN
+                    DROP TABLE {table_name}
+                END
+                ",
+                table_part = table_identifier.part(),
+                table_name = table_identifier.full(),
+            )
+        } else {
+            formatdoc!(
+                "
+                IF EXISTS (SELECT * FROM sys.objects WHERE object_id = OBJECT_ID(N'{table_name}') AND type in (N'U'))
+                BEGIN
+                    DROP TABLE {table_name}
+                END
+                ",
+                table_name = table_identifier.full(),
+            )
+        };
+
+        // tables in tempdb are neither reused, placed on a filegroup of the database, nor
+        // memory-optimized
+        let reuse_staging = import_options.reuse_staging && !tempdb_staging;
+        let memory_optimized_staging = import_options.memory_optimized_staging && !tempdb_staging;
+        let staging_filegroup = import_options
+            .staging_filegroup
+            .as_ref()
+            .filter(|_| !tempdb_staging);
+
+        let mut create_table = formatdoc!(
+            "
             CREATE TABLE {table_name} (
                 {columns}
             )
             ",
             table_name = table_identifier.full(),
-            columns = columns.join(",\n    "),
+            columns = columns
+                .iter()
+                .map(|(_, definition)| definition)
+                .join(",\n    "),
         );
 
+        if let Some(staging_filegroup) = staging_filegroup {
+            writeln!(
+                create_table,
+                "ON [{}]",
+                staging_filegroup.replace(']', "]]")
+            )
+            .expect("Write to string should be infallible");
+        }
+
+        if let Some(staging_compression) = import_options.staging_compression {
+            writeln!(
+                create_table,
+                "WITH (DATA_COMPRESSION = {})",
+                staging_compression.keyword()
+            )
+            .expect("Write to string should be infallible");
+        }
+
+        if memory_optimized_staging {
+            // memory-optimized tables require an index, and the rows of the temporary table are
+            // matched to the target table by its key columns
+            let index_columns = column_graph
+                .target_columns()
+                .filter(|column| {
+                    !column.column().is_transient()
+                        && table_mapper
+                            .key_columns()
+                            .any(|key_column| key_column == column.column().identifier())
+                })
+                .map(|column| column.unique_identifier().part().to_owned())
+                .collect::<Vec<_>>();
+
+            let index_columns = if index_columns.is_empty() {
+                // without key columns the first column is indexed
+                vec![columns[0].0.clone()]
+            } else {
+                index_columns
+            };
+
+            // falls back to the disk-based table where the server or database does not support
+            // memory-optimized tables
+            create_table = formatdoc!(
+                "
+                IF SERVERPROPERTY('IsXTPSupported') = 1 AND EXISTS (SELECT * FROM sys.filegroups WHERE type = N'FX')
+                BEGIN
+                    CREATE TABLE {table_name} (
+                        {columns},
+                        INDEX [IX_Key] NONCLUSTERED ({index_columns})
+                    )
+                    WITH (MEMORY_OPTIMIZED = ON, DURABILITY = SCHEMA_ONLY)
+                END
+                ELSE
+                BEGIN
+                    {create_table}
+                END
+                ",
+                table_name = table_identifier.full(),
+                columns = columns
+                    .iter()
+                    .map(|(_, definition)| definition)
+                    .join(",\n        "),
+                index_columns = index_columns.join(", "),
+                create_table = create_table.trim().replace('\n', "\n    "),
+            );
+        }
+
+        let release = match (reuse_staging, memory_optimized_staging) {
+            (false, _) => Release::Drop,
+            (true, false) => Release::Truncate,
+            (true, true) => Release::Delete,
+        };
+
+        let statement = if reuse_staging {
+            // the definition of the table is recorded in an extended property of the table, such
+            // that a table created with other columns or options is recreated rather than reused
+            let mut hasher = FxHasher::default();
+            create_table.hash(&mut hasher);
+
+            formatdoc!(
+                "
+                IF EXISTS (
+                    SELECT * FROM sys.extended_properties
+                    WHERE class = 1 AND major_id = OBJECT_ID(N'{table_name}') AND minor_id = 0
+                        AND name = N'{columns_property}' AND CAST(value AS nvarchar(max)) = N'{columns_hash}'
+                )
+                BEGIN
+                    {release_table}
+                END
+                ELSE
+                BEGIN
+                    {drop_table}
+
+                    {create_table}
+
+                    EXEC sys.sp_addextendedproperty
+                        @name = N'{columns_property}', @value = N'{columns_hash}',
+                        @level0type = N'SCHEMA', @level0name = N'{schema_name}',
+                        @level1type = N'TABLE', @level1name = N'{table_name_unescaped}'
+                END
+                ",
+                table_name = table_identifier.full(),
+                table_name_unescaped = table_identifier.part_unescaped(),
+                schema_name = schema.part_unescaped(),
+                columns_property = COLUMNS_PROPERTY,
+                columns_hash = format!("{:016x}", hasher.finish()),
+                release_table = release.statement(&table_identifier).trim(),
+                drop_table = drop_table.trim().replace('\n', "\n    "),
+                create_table = create_table.trim().replace('\n', "\n    "),
+            )
+        } else {
+            formatdoc!(
+                "
+                {drop_table}
+                {create_table}
+                ",
+            )
+        };
+
         trace_sql!(statement);
 
-        client.execute(statement, &[]).await?;
+        client.execute(&statement, &[]).await?;
+
+        Ok(TemporaryTable {
+            table_identifier,
+            orphans_identifier,
+            pending_drop: !import_options.no_drop,
+            release,
+            record_numbers,
+            abandoned_tables: abandoned_tables.clone(),
+        })
+    }
+
+    /// Table receiving rows quarantined by lookups with [`OrphanPolicy::Quarantine`], retained
+    /// after the temporary table is dropped.
+    ///
+    /// [`OrphanPolicy::Quarantine`]: crate::table_mapper::OrphanPolicy::Quarantine
+    pub fn orphans_identifier(&self) -> &TableIdentifier {
+        &self.orphans_identifier
+    }
 
-        Ok(TemporaryTable { table_identifier })
+    pub fn record_numbers(&self) -> bool {
+        self.record_numbers
     }
 
+    /// Drops the table unless `--no-drop` is set, or empties it if it is reused. If the table could
+    /// not be dropped it is abandoned, such that dropping it is retried by [`drop_abandoned`].
     pub async fn finalize(
-        self,
-        client: &mut Client<Compat<TcpStream>>,
-        import_options: &ImportOptions,
+        mut self,
+        client: &mut impl SqlBackend,
     ) -> Result<(), tiberius::error::Error> {
-        if !import_options.no_drop {
-            let statement = formatdoc!(
-                "
-                DROP TABLE {table_name}
-                ",
-                table_name = self.table_identifier.full(),
+        if self.pending_drop {
+            execute_release(client, &self.table_identifier, self.release).await?;
+
+            self.pending_drop = false;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryTable {
+    fn drop(&mut self) {
+        if self.pending_drop {
+            warn!(
+                "Temporary table {} was abandoned before it was finalized",
+                self.table_identifier
             );
 
-            trace_sql!(statement);
+            self.abandoned_tables
+                .push(self.table_identifier.clone(), self.release);
+        }
+    }
+}
+
+/// Table receiving the rows quarantined for the target table. Orphans are retained after the
+/// temporary table is dropped, so are never in tempdb.
+fn orphans_identifier(
+    target_table: &TableIdentifier,
+) -> Result<TableIdentifier, CreateTemporaryTableError> {
+    TableIdentifier::with_schema(
+        &"[import]".parse().unwrap(),
+        &format!("{}_orphans", target_table.part_unescaped()),
+    )
+    .map_err(|err| CreateTemporaryTableError::InvalidTableName(target_table.to_owned(), err))
+}
+
+/// Drops the orphans tables of the table mappers quarantining orphans, once at the start of a run,
+/// such that the orphans of every batch and table mapper of the run are kept together.
+pub async fn drop_orphans<'a>(
+    client: &mut impl SqlBackend,
+    table_mappers: impl IntoIterator<Item = &'a TableMapper>,
+) -> Result<(), CreateTemporaryTableError> {
+    let orphans_identifiers = table_mappers
+        .into_iter()
+        .filter(|table_mapper| {
+            table_mapper.columns().any(|column| {
+                matches!(column, TableMapperColumn::Lookup(lookup_column)
+                    if lookup_column.orphan_policy() == OrphanPolicy::Quarantine)
+            })
+        })
+        .map(|table_mapper| orphans_identifier(table_mapper.identifier()))
+        .collect::<Result<Vec<_>, _>>()?;
 
-            client.execute(statement, &[]).await?;
+    for orphans_identifier in orphans_identifiers.into_iter().unique() {
+        let statement = formatdoc!(
+            "
+            IF EXISTS (SELECT * FROM sys.objects WHERE object_id = OBJECT_ID(N'{orphans_table_name}') AND type in (N'U'))
+            BEGIN
+                DROP TABLE {orphans_table_name}
+            END
+            ",
+            orphans_table_name = orphans_identifier.full(),
+        );
+
+        trace_sql!(statement);
+
+        client.execute(&statement, &[]).await?;
+    }
+
+    Ok(())
+}
+
+/// Drops the temporary tables abandoned without being finalized, leaving those which could not be
+/// dropped for the `cleanup` command.
+pub async fn drop_abandoned(client: &mut impl SqlBackend, abandoned_tables: &AbandonedTables) {
+    for (table_identifier, release) in abandoned_tables.take() {
+        if let Err(err) = execute_release(client, &table_identifier, release).await {
+            warn!(
+                "Could not drop abandoned temporary table {}: {}",
+                table_identifier, err
+            );
         }
+    }
+}
 
-        Ok(())
+impl Release {
+    fn statement(self, table_identifier: &TableIdentifier) -> String {
+        formatdoc!(
+            "
+            {command} {table_name}
+            ",
+            command = match self {
+                Release::Drop => "DROP TABLE",
+                Release::Truncate => "TRUNCATE TABLE",
+                Release::Delete => "DELETE FROM",
+            },
+            table_name = table_identifier.full(),
+        )
     }
 }
 
+async fn execute_release(
+    client: &mut impl SqlBackend,
+    table_identifier: &TableIdentifier,
+    release: Release,
+) -> Result<(), tiberius::error::Error> {
+    let statement = release.statement(table_identifier);
+
+    trace_sql!(statement);
+
+    client.execute(&statement, &[]).await?;
+
+    Ok(())
+}
+
 impl Table for TemporaryTable {
     fn identifier(&self) -> &TableIdentifier {
         &self.table_identifier
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use std::collections::HashMap;
+
+    fn temporary_table(
+        table_name: &str,
+        release: Release,
+        abandoned_tables: &AbandonedTables,
+    ) -> TemporaryTable {
+        TemporaryTable {
+            table_identifier: format!("[import].[{}]", table_name).parse().unwrap(),
+            orphans_identifier: format!("[import].[{}_orphans]", table_name)
+                .parse()
+                .unwrap(),
+            pending_drop: true,
+            release,
+            record_numbers: false,
+            abandoned_tables: abandoned_tables.clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_abandoned_temporary_tables() -> Result<(), tiberius::error::Error> {
+        let abandoned_tables = AbandonedTables::default();
+        let other_abandoned_tables = AbandonedTables::default();
+        let mut backend = MockBackend::new();
+
+        temporary_table("Country", Release::Drop, &abandoned_tables)
+            .finalize(&mut backend)
+            .await?;
+
+        drop(temporary_table(
+            "Currency",
+            Release::Drop,
+            &abandoned_tables,
+        ));
+        drop(temporary_table(
+            "Price",
+            Release::Truncate,
+            &abandoned_tables,
+        ));
+        drop(temporary_table("Rate", Release::Delete, &abandoned_tables));
+        drop(temporary_table(
+            "Region",
+            Release::Drop,
+            &other_abandoned_tables,
+        ));
+
+        drop_abandoned(&mut backend, &abandoned_tables).await;
+        drop_abandoned(&mut backend, &abandoned_tables).await;
+
+        let statements = backend
+            .statements()
+            .iter()
+            .map(|statement| statement.statement.trim())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            statements,
+            [
+                "DROP TABLE [import].[Country]",
+                "DROP TABLE [import].[Currency]",
+                "TRUNCATE TABLE [import].[Price]",
+                "DELETE FROM [import].[Rate]"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drop_orphans_of_quarantining_table_mappers() -> Result<(), CreateTemporaryTableError> {
+        let table_mapper = |name: &str, table: &str, orphan_policy: &str| {
+            TableMapper::new(
+                serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "field_group": name,
+                    "table_identifier": table,
+                    "delete_mode": "Partial",
+                    "delete_action": "None",
+                    "duplicate_action": "Reject",
+                    "columns": [
+                        { "Parser": { "column_identifier": "[Code]", "map_column": true, "field_name": "code" } },
+                        { "Lookup": {
+                            "column_identifier": "[RegionID]",
+                            "map_column": true,
+                            "table_identifier": "[dbo].[Region]",
+                            "output_column_identifier": "[ID]",
+                            "key_columns": [{ "ParserKeyColumn": { "key_column_identifier": "[Code]", "field_name": "region" } }],
+                            "orphan_policy": orphan_policy
+                        } }
+                    ],
+                    "key_columns": ["[Code]"]
+                }))
+                .unwrap(),
+                None,
+            )
+            .unwrap()
+        };
+
+        let table_mappers = [
+            table_mapper("Country", "[dbo].[Country]", "Quarantine"),
+            table_mapper("CountryAlias", "[dbo].[Country]", "Quarantine"),
+            table_mapper("Currency", "[dbo].[Currency]", "Null"),
+        ];
+
+        let mut backend = MockBackend::new();
+
+        drop_orphans(&mut backend, &table_mappers).await?;
+
+        let statements = backend.statements();
+
+        assert_eq!(statements.len(), 1);
+        assert!(
+            statements[0]
+                .statement
+                .contains("DROP TABLE [import].[Country_orphans]")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stage_in_tempdb() -> Result<(), Box<dyn std::error::Error>> {
+        let table_mapper = TableMapper::new(
+            serde_json::from_value(serde_json::json!({
+                "name": "Country",
+                "field_group": "Country",
+                "table_identifier": "[dbo].[Country]",
+                "delete_mode": "Partial",
+                "delete_action": "None",
+                "duplicate_action": "Reject",
+                "columns": [
+                    { "Parser": { "column_identifier": "[Code]", "map_column": true, "field_name": "code" } }
+                ],
+                "key_columns": ["[Code]"]
+            }))?,
+            None,
+        )?;
+        let table_metadata = HashMap::from_iter([(table_mapper.identifier(), HashMap::default())]);
+        let import_options = ImportOptions {
+            tempdb_staging: true,
+            reuse_staging: true,
+            ..ImportOptions::default()
+        };
+        let column_graph = ColumnGraph::new(&table_mapper, &table_metadata, &import_options)?;
+        let mut backend = MockBackend::new();
+
+        let abandoned_tables = AbandonedTables::default();
+        let temporary_table = TemporaryTable::new(
+            &mut backend,
+            &table_mapper,
+            &column_graph,
+            &import_options,
+            &abandoned_tables,
+        )
+        .await?;
+
+        assert_eq!(temporary_table.identifier().full(), "[dbo].[#Country]");
+        assert_eq!(temporary_table.release, Release::Drop);
+
+        let statement = &backend.statements()[0].statement;
+
+        assert!(statement.contains("IF OBJECT_ID(N'tempdb..[#Country]', N'U') IS NOT NULL"));
+        assert!(statement.contains("CREATE TABLE [dbo].[#Country]"));
+        assert!(!statement.contains("[import]"));
+
+        drop(temporary_table);
+
+        // the session of a cancelled import ended, dropping its tables in tempdb
+        abandoned_tables.end_session();
+        drop_abandoned(&mut backend, &abandoned_tables).await;
+
+        assert_eq!(backend.statements().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn index_first_memory_optimized_column() -> Result<(), Box<dyn std::error::Error>> {
+        let table_mapper = TableMapper::new(
+            serde_json::from_value(serde_json::json!({
+                "name": "Country",
+                "field_group": "Country",
+                "table_identifier": "[dbo].[Country]",
+                "delete_mode": "Partial",
+                "delete_action": "None",
+                "duplicate_action": "Reject",
+                "columns": [
+                    { "Parser": { "column_identifier": "[Country Code]", "map_column": true, "field_name": "code" } },
+                    { "Parser": { "column_identifier": "[Name]", "map_column": true, "field_name": "name" } }
+                ],
+                "key_columns": []
+            }))?,
+            None,
+        )?;
+        let table_metadata = HashMap::from_iter([(table_mapper.identifier(), HashMap::default())]);
+        let import_options = ImportOptions {
+            memory_optimized_staging: true,
+            ..ImportOptions::default()
+        };
+        let column_graph = ColumnGraph::new(&table_mapper, &table_metadata, &import_options)?;
+        let mut backend = MockBackend::new();
+
+        TemporaryTable::new(
+            &mut backend,
+            &table_mapper,
+            &column_graph,
+            &import_options,
+            &AbandonedTables::default(),
+        )
+        .await?;
+
+        // the identifier of the column is indexed whole, though it contains a space
+        assert!(
+            backend.statements()[0]
+                .statement
+                .contains("INDEX [IX_Key] NONCLUSTERED ([Country Code])")
+        );
+
+        Ok(())
+    }
+}
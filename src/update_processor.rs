@@ -1,48 +1,326 @@
+use crate::backend::SqlBackend;
 use crate::column_graph::{ColumnGraph, ColumnNode, IndexedColumnNode};
-use crate::identifier::Identifier;
-use crate::table_mapper::{Column, Table};
-use crate::temporary_table::TemporaryTable;
+use crate::identifier::{ColumnIdentifier, Identifier};
+use crate::insert_processor::{
+    MoneyType, fit_decimal, parse_binary, parse_date, parse_datetime2, parse_decimal, parse_time,
+    parse_xml,
+};
+use crate::table_mapper::{Column, OrphanPolicy, StaticColumn, Table};
+use crate::temporary_table::{RESOLVED_COLUMN, TemporaryTable};
 use crate::trace_sql;
 use indoc::formatdoc;
 use itertools::Itertools;
-use log::trace;
-use rust_decimal::Decimal;
-use std::error::Error;
+use log::{trace, warn};
 use std::fmt::Debug;
-use std::iter::successors;
+use std::iter::{once, successors};
+use std::str::FromStr;
 use thiserror::Error;
-use tiberius::{Client, FixedLenType, ToSql, TypeInfo, VarLenType};
-use tokio::net::TcpStream;
-use tokio_util::compat::Compat;
+use tiberius::{FixedLenType, ToSql, TypeInfo, VarLenType};
 
 struct LookupParts {
-    set: String,
-    outer_apply: String,
+    target_column: String,
+    target_column_unescaped: String,
+    target_column_type: String,
+    output_column: String,
+    lookup_table: String,
+    orphan_policy: OrphanPolicy,
+    /// Pairs of (lookup table key column, temporary table column)
+    column_dependencies: Vec<(String, String)>,
+    /// Collation each key column of the column dependencies is compared with, if not its own
+    column_dependency_collations: Vec<Option<&'static str>>,
+    /// Lookup table key columns bound to static values, with the SQL expression of the value if it
+    /// is a token, otherwise the value is bound as the next parameter
+    static_dependencies: Vec<(String, Option<String>)>,
+    parameters: Vec<Box<dyn ToSql>>,
+}
+
+impl LookupParts {
+    fn set(&self) -> String {
+        format!(
+            "t.{target_column} = l_{target_column_unescaped}.{output_column}",
+            target_column = self.target_column,
+            target_column_unescaped = self.target_column_unescaped,
+            output_column = self.output_column,
+        )
+    }
+
+    /// Rows where the lookup resolved to NULL is resolved by the outer apply of the lookup.
+    fn resolved_condition(&self) -> String {
+        format!(
+            "(t.{target_column} IS NULL AND l_{target_column_unescaped}.{output_column} IS NOT NULL)",
+            target_column = self.target_column,
+            target_column_unescaped = self.target_column_unescaped,
+            output_column = self.output_column,
+        )
+    }
+
+    /// Values of the static dependencies, either token expressions or parameter placeholders
+    /// numbered after `parameter_offset`, matching the position of this lookup's parameters within
+    /// the statement it is rendered into.
+    fn static_values(&self, parameter_offset: usize) -> Vec<String> {
+        let mut parameter_index = parameter_offset;
+
+        self.static_dependencies
+            .iter()
+            .map(|(_, expression)| match expression {
+                Some(expression) => expression.to_owned(),
+                None => {
+                    parameter_index += 1;
+
+                    format!("@P{parameter_index}")
+                }
+            })
+            .collect()
+    }
+
+    fn outer_apply(&self, parameter_offset: usize) -> String {
+        let conditions = self
+            .column_dependencies
+            .iter()
+            .zip(&self.column_dependency_collations)
+            .map(|((key_column, dependency), collation)| match collation {
+                Some(collation) => {
+                    format!("l_inner.{key_column} COLLATE {collation} = t.{dependency}")
+                }
+                None => format!("l_inner.{key_column} = t.{dependency}"),
+            })
+            .chain(
+                self.static_dependencies
+                    .iter()
+                    .zip(self.static_values(parameter_offset))
+                    .map(|((key_column, _), value)| format!("l_inner.{key_column} = {value}")),
+            )
+            .join("\n        AND ");
+
+        formatdoc!(
+            "
+            OUTER APPLY (
+                SELECT TOP 1 l_inner.{output_column}
+                FROM {lookup_table} l_inner
+                WHERE
+                    {conditions}
+            ) l_{target_column_unescaped}
+            ",
+            output_column = self.output_column,
+            lookup_table = self.lookup_table,
+            conditions = if conditions.is_empty() {
+                "1 = 1"
+            } else {
+                conditions.as_str()
+            },
+            target_column_unescaped = self.target_column_unescaped,
+        )
+    }
+
+    /// Matches the rows of the temporary table to the row of the lookup cache of their key tuple,
+    /// NULL key values matching each other as they do in the `SELECT DISTINCT` filling the cache.
+    fn cache_join_condition(&self) -> String {
+        if self.column_dependencies.is_empty() {
+            return "1 = 1".to_owned();
+        }
+
+        self.column_dependencies
+            .iter()
+            .map(|(_, column)| {
+                format!("(c.{column} = t.{column} OR (c.{column} IS NULL AND t.{column} IS NULL))")
+            })
+            .join(" AND ")
+    }
+
+    /// Checks a parameter is bound for each placeholder of the static dependencies, such that the
+    /// placeholders of the lookups following this lookup in a statement are numbered correctly.
+    fn check_parameters(&self) -> Result<(), UpdateProcessorError> {
+        let placeholders = self
+            .static_dependencies
+            .iter()
+            .filter(|(_, expression)| expression.is_none())
+            .count();
+
+        if placeholders != self.parameters.len() {
+            return Err(UpdateProcessorError::ParameterCountMismatch {
+                column: self.target_column.clone(),
+                placeholders,
+                parameters: self.parameters.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Rows where the lookup did not resolve, despite every key dependency having a value.
+    fn orphan_condition(&self) -> String {
+        once(format!("t.{} IS NULL", self.target_column))
+            .chain(
+                self.column_dependencies
+                    .iter()
+                    .map(|(_, dependency)| format!("t.{dependency} IS NOT NULL")),
+            )
+            .join(" AND ")
+    }
 }
 
 #[derive(Default)]
 struct TargetColumnStatementParts {
     lookups: Vec<LookupParts>,
-    parameters: Vec<Box<dyn ToSql>>,
+}
+
+/// Which temporary table rows a lookup is resolved for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LookupResolution {
+    /// Resolve lookups for all rows
+    All,
+    /// Resolve lookups only for rows where a lookup previously resolved to NULL
+    Unresolved,
+    /// Resolve lookups only for rows where a lookup previously resolved to NULL, marking the rows
+    /// of which a lookup is resolved in [`RESOLVED_COLUMN`]
+    Deferred,
 }
 
 #[derive(Debug, Error)]
 pub enum UpdateProcessorError {
+    #[error("invalid or out of range value for static column {column} of type '{ty}': '{value}'")]
+    InvalidStaticValue {
+        column: ColumnIdentifier,
+        ty: String,
+        value: String,
+    },
+    #[error("unsupported type '{ty}' for static column {column}")]
+    UnsupportedColumnType {
+        column: ColumnIdentifier,
+        ty: String,
+    },
+    #[error(
+        "lookup column {column} binds {parameters} parameters to {placeholders} parameter placeholders"
+    )]
+    ParameterCountMismatch {
+        column: String,
+        placeholders: usize,
+        parameters: usize,
+    },
     #[error(transparent)]
     UpdateFailed(#[from] tiberius::error::Error),
 }
 
+/// Whether values of the type are compared by collation.
+fn is_character_type(ty: &TypeInfo) -> bool {
+    matches!(
+        ty,
+        TypeInfo::VarLenSized(var_len_sized)
+            if matches!(
+                var_len_sized.r#type(),
+                VarLenType::BigVarChar
+                    | VarLenType::BigChar
+                    | VarLenType::NVarchar
+                    | VarLenType::NChar
+                    | VarLenType::Text
+                    | VarLenType::NText
+            )
+    )
+}
+
+/// Converts the value of a static column to a parameter of the column type.
+fn static_parameter(
+    static_column: &StaticColumn,
+    ty: &TypeInfo,
+) -> Result<Box<dyn ToSql>, UpdateProcessorError> {
+    let value = static_column.value();
+
+    let invalid_static_value = || UpdateProcessorError::InvalidStaticValue {
+        column: static_column.identifier().to_owned(),
+        ty: ty.to_string(),
+        value: value.to_owned(),
+    };
+
+    let unsupported_column_type = || UpdateProcessorError::UnsupportedColumnType {
+        column: static_column.identifier().to_owned(),
+        ty: ty.to_string(),
+    };
+
+    fn parse<T: FromStr + ToSql + 'static>(
+        value: &str,
+        invalid_static_value: impl FnOnce() -> UpdateProcessorError,
+    ) -> Result<Box<dyn ToSql>, UpdateProcessorError> {
+        value
+            .parse::<T>()
+            .map(|value| Box::new(value) as Box<dyn ToSql>)
+            .map_err(|_| invalid_static_value())
+    }
+
+    let money = || {
+        MoneyType::of(ty)
+            .and_then(|money_type| money_type.parse(value))
+            .map(|value| Box::new(value) as Box<dyn ToSql>)
+            .ok_or_else(invalid_static_value)
+    };
+
+    match *ty {
+        TypeInfo::FixedLen(fixed_len) => match fixed_len {
+            FixedLenType::Int1 => parse::<u8>(value, invalid_static_value),
+            FixedLenType::Bit => parse::<bool>(value, invalid_static_value),
+            FixedLenType::Int2 => parse::<i16>(value, invalid_static_value),
+            FixedLenType::Int4 => parse::<i32>(value, invalid_static_value),
+            FixedLenType::Float4 => parse::<f32>(value, invalid_static_value),
+            FixedLenType::Float8 => parse::<f64>(value, invalid_static_value),
+            FixedLenType::Int8 => parse::<i64>(value, invalid_static_value),
+            FixedLenType::Money | FixedLenType::Money4 => money(),
+            _ => Err(unsupported_column_type()),
+        },
+        TypeInfo::VarLenSized(var_len_sized) => match var_len_sized.r#type() {
+            VarLenType::BigVarChar
+            | VarLenType::NVarchar
+            | VarLenType::Text
+            | VarLenType::NText
+            | VarLenType::SSVariant => Ok(Box::new(value.to_owned())),
+            VarLenType::Image => parse_binary(value)
+                .map(|value| Box::new(value) as Box<dyn ToSql>)
+                .ok_or_else(invalid_static_value),
+            VarLenType::Daten => parse_date(value)
+                .map(|value| Box::new(value) as Box<dyn ToSql>)
+                .ok_or_else(invalid_static_value),
+            VarLenType::Timen => parse_time(value, var_len_sized.len() as u8)
+                .map(|value| Box::new(value) as Box<dyn ToSql>)
+                .ok_or_else(invalid_static_value),
+            VarLenType::Datetime2 => parse_datetime2(value, var_len_sized.len() as u8)
+                .map(|value| Box::new(value) as Box<dyn ToSql>)
+                .ok_or_else(invalid_static_value),
+            VarLenType::Money => money(),
+            _ => Err(unsupported_column_type()),
+        },
+        TypeInfo::VarLenSizedPrecision {
+            ty,
+            precision,
+            scale,
+            ..
+        } => match ty {
+            VarLenType::Decimaln | VarLenType::Numericn => parse_decimal(value)
+                .and_then(|value| fit_decimal(value, precision, scale))
+                .map(|value| Box::new(value) as Box<dyn ToSql>)
+                .ok_or_else(invalid_static_value),
+            VarLenType::Money => money(),
+            _ => Err(unsupported_column_type()),
+        },
+        TypeInfo::Xml { .. } => parse_xml(value)
+            .map(|value| Box::new(value.to_owned()) as Box<dyn ToSql>)
+            .ok_or_else(invalid_static_value),
+    }
+}
+
+/// Resolves the lookups of a group of columns, applying the orphan policy of each lookup if
+/// `orphan_policies` is set, which is only once the lookups are resolved for the last time.
 pub async fn execute(
-    client: &mut Client<Compat<TcpStream>>,
+    client: &mut impl SqlBackend,
     temporary_table: &TemporaryTable,
     columns: impl IntoIterator<Item = IndexedColumnNode<'_>>,
     column_graph: &ColumnGraph,
+    lookup_cache: bool,
+    lookup_resolution: LookupResolution,
+    orphan_policies: bool,
+    max_lookups: usize,
+    run_id: &str,
 ) -> Result<(), UpdateProcessorError> {
-    let mut static_column_parameter_index: usize = 0;
-
     let target_column_statement_parts = columns
         .into_iter()
-        .try_fold(TargetColumnStatementParts::default(), |mut acc, column| -> Result<TargetColumnStatementParts, Box<dyn Error>> {
+        .try_fold(TargetColumnStatementParts::default(), |mut acc, column| -> Result<TargetColumnStatementParts, UpdateProcessorError> {
             match column.column() {
                 ColumnNode::LookupColumn { column: lookup_column, .. } if !column.column().is_transient() => {
                     let (static_column_dependencies, column_dependencies): &(Vec<_>, Vec<_>) =
@@ -67,164 +345,495 @@ pub async fn execute(
                                     ),
                                 };
 
-                                (key_column_identifier, dependency)
+                                (key_column_identifier, target_column_dependency.metadata(), dependency)
                             })
-                            .partition(|(_, dependency)| {
+                            .partition(|(_, _, dependency)| {
                                 matches!(dependency.column(), ColumnNode::StaticColumn {..})
                             });
 
-                    let column_dependencies_condition = column_dependencies
-                        .iter()
-                        .map(|(key_column_identifier, dependency)| {
-                            format!(
-                                "l_inner.{key_column_identifier} = t.{dependency}",
-                                key_column_identifier = key_column_identifier.part(),
-                                dependency = dependency.unique_identifier().part(),
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                        .join(" AND ");
-
-                    let static_column_dependencies_condition = static_column_dependencies
-                        .iter()
-                        .map(|(key_column_identifier, _)| {
-                            let condition = format!(
-                                "l_inner.{key_column_identifier} = @P{static_column_parameter_index}",
-                                key_column_identifier = key_column_identifier.part(),
-                                static_column_parameter_index = static_column_parameter_index,
-                            );
-
-                            static_column_parameter_index += 1;
-
-                            condition
-                        })
-                        .collect::<Vec<_>>()
-                        .join(" AND ");
+                    let static_column_dependency_token = |dependency: &IndexedColumnNode| match dependency.column() {
+                        ColumnNode::StaticColumn { column: static_column, .. } => static_column.token(),
+                        _ => None,
+                    };
 
-                    let mut static_column_dependencies_parameters = static_column_dependencies
+                    let static_column_dependencies_parameters = static_column_dependencies
                         .iter()
-                        .map(|(_, dependency)| -> Result<Box<dyn ToSql>, Box<dyn Error>> {
-                            Ok(match dependency.column() {
-                                ColumnNode::StaticColumn{column: static_column, ..} => {
-                                    let metadata = dependency.metadata();
-
-                                    match metadata.ty {
-                                        TypeInfo::FixedLen(fixed_len) => match fixed_len {
-                                            FixedLenType::Int1 => Box::new(static_column.value().parse::<u8>()?),
-                                            FixedLenType::Bit => Box::new(static_column.value().parse::<bool>()?),
-                                            FixedLenType::Int2 => Box::new(static_column.value().parse::<i16>()?),
-                                            FixedLenType::Int4 => Box::new(static_column.value().parse::<i32>()?),
-                                            FixedLenType::Float4 => Box::new(static_column.value().parse::<f32>()?),
-                                            FixedLenType::Float8 => Box::new(static_column.value().parse::<f64>()?),
-                                            FixedLenType::Int8 => Box::new(static_column.value().parse::<i64>()?),
-                                            _ => Err(format!("Unsupported FixedLen column ({}) type: {:?}", static_column.identifier().part(), metadata.ty))?,
-                                        },
-                                        TypeInfo::VarLenSized(var_len_sized) => match var_len_sized.r#type() {
-                                            VarLenType::BigVarChar => Box::new(static_column.value().to_owned()),
-                                            VarLenType::NVarchar => Box::new(static_column.value().to_owned()),
-                                            _ => Err(format!("Unsupported VarLenSized column ({}) type: {:?}", static_column.identifier().part(), metadata.ty))?,
-                                        }
-                                        TypeInfo::VarLenSizedPrecision { ty, size: _, precision: _, scale: _ } => match ty {
-                                            VarLenType::Decimaln => Box::new(static_column.value().parse::<Decimal>()?),
-                                            VarLenType::Numericn => Box::new(static_column.value().parse::<Decimal>()?),
-                                            VarLenType::Money => Box::new(static_column.value().parse::<Decimal>()?),
-                                            _ => Err(format!("Unsupported VarLenSizedPrecision column ({}) type: {:?}", static_column.identifier().part(), metadata.ty))?,
-                                        }
-                                        TypeInfo::Xml { .. } => {
-                                            Err(format!("Unsupported Xml column ({}) type: {:?}", static_column.identifier().part(), metadata.ty))?
-                                        }
-                                    }
-                                }
-                                _ => unreachable!(),
-                            })
+                        .filter(|(_, _, dependency)| static_column_dependency_token(dependency).is_none())
+                        .map(|(_, _, dependency)| match dependency.column() {
+                            ColumnNode::StaticColumn { column: static_column, .. } => {
+                                static_parameter(static_column, &dependency.metadata().ty)
+                            }
+                            _ => unreachable!(),
                         })
                         .collect::<Result<Vec<_>, _>>()?;
 
-                    let statement_part_set = format!(
-                        "t.{target_column} = l_{target_column_unescaped}.{output_column}",
-                        target_column = column.unique_identifier().part(),
-                        target_column_unescaped = column.unique_identifier().part_unescaped(),
-                        output_column = lookup_column.output_column_identifier().part(),
-                    );
-
-                    let statement_part_outer_apply = formatdoc!(
-                        "
-                        OUTER APPLY (
-                            SELECT TOP 1 l_inner.{output_column}
-                            FROM {lookup_table} l_inner
-                            WHERE
-                                {column_dependencies_condition}
-                                {and_static_column_dependencies_condition}
-                                {static_column_dependencies_condition}
-                        ) l_{target_column_unescaped}
-                        ",
-                        target_column_unescaped = column.unique_identifier().part_unescaped(),
-                        output_column = lookup_column.output_column_identifier().part(),
-                        lookup_table = Table::identifier(lookup_column),
-                        column_dependencies_condition = column_dependencies_condition,
-                        and_static_column_dependencies_condition =
-                        if !static_column_dependencies_condition.is_empty() {
-                            "AND"
-                        } else {
-                            ""
-                        },
-                        static_column_dependencies_condition = static_column_dependencies_condition,
-                    );
-
                     acc.lookups.push(LookupParts {
-                        set: statement_part_set,
-                        outer_apply: statement_part_outer_apply,
+                        target_column: column.unique_identifier().part().to_owned(),
+                        target_column_unescaped: column.unique_identifier().part_unescaped().to_owned(),
+                        target_column_type: column.metadata().ty.to_string(),
+                        output_column: lookup_column.output_column_identifier().part().to_owned(),
+                        lookup_table: Table::identifier(lookup_column).full().to_owned(),
+                        orphan_policy: lookup_column.orphan_policy(),
+                        column_dependencies: column_dependencies
+                            .iter()
+                            .map(|(key_column_identifier, _, dependency)| {
+                                (
+                                    key_column_identifier.part().to_owned(),
+                                    dependency.unique_identifier().part().to_owned(),
+                                )
+                            })
+                            .collect(),
+                        column_dependency_collations: column_dependencies
+                            .iter()
+                            .map(|(_, key_column_metadata, _)| {
+                                lookup_column
+                                    .key_normalization()
+                                    .collation()
+                                    .filter(|_| is_character_type(&key_column_metadata.ty))
+                            })
+                            .collect(),
+                        static_dependencies: static_column_dependencies
+                            .iter()
+                            .map(|(key_column_identifier, _, dependency)| {
+                                (
+                                    key_column_identifier.part().to_owned(),
+                                    static_column_dependency_token(dependency).map(|token| token.sql_expression(run_id)),
+                                )
+                            })
+                            .collect(),
+                        parameters: static_column_dependencies_parameters,
                     });
-
-                    acc.parameters.append(&mut static_column_dependencies_parameters);
                 },
                 _ => {},
             };
 
             Ok(acc)
-        }).unwrap();
+        })?;
+
+    if lookup_cache {
+        for lookup in &target_column_statement_parts.lookups {
+            execute_cached_lookup(client, temporary_table, lookup, lookup_resolution).await?;
+        }
+    } else {
+        for lookups in chunk_lookups(&target_column_statement_parts.lookups, max_lookups) {
+            execute_lookups(client, temporary_table, lookups, lookup_resolution).await?;
+        }
+    }
+
+    if !orphan_policies {
+        return Ok(());
+    }
 
-    assert_eq!(
-        static_column_parameter_index,
-        target_column_statement_parts.parameters.len(),
-        "There must be an equal number of bound SQL parameters & placeholders",
+    for lookup in &target_column_statement_parts.lookups {
+        match lookup.orphan_policy {
+            OrphanPolicy::Null => {}
+            OrphanPolicy::Quarantine => {
+                quarantine_orphans(client, temporary_table, lookup).await?;
+            }
+            OrphanPolicy::Placeholder => {
+                insert_placeholder_parents(client, temporary_table, lookup).await?;
+                // placeholders resolved by a deferred resolution are marked like any other lookup
+                let lookup_resolution = match lookup_resolution {
+                    LookupResolution::Deferred => LookupResolution::Deferred,
+                    _ => LookupResolution::Unresolved,
+                };
+
+                execute_cached_lookup(client, temporary_table, lookup, lookup_resolution).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Maximum number of parameters bound to a single update statement, below the 2100 parameters
+/// SQL Server accepts per request.
+const MAX_PARAMETERS: usize = 2000;
+
+/// Splits the lookups of a group into consecutive chunks of at most `max_lookups` lookups and
+/// [`MAX_PARAMETERS`] parameters, each resolved by a separate statement.
+fn chunk_lookups(lookups: &[LookupParts], max_lookups: usize) -> Vec<&[LookupParts]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut parameters = 0;
+
+    for (index, lookup) in lookups.iter().enumerate() {
+        if index > start
+            && (index - start == max_lookups
+                || parameters + lookup.parameters.len() > MAX_PARAMETERS)
+        {
+            chunks.push(&lookups[start..index]);
+            start = index;
+            parameters = 0;
+        }
+
+        parameters += lookup.parameters.len();
+    }
+
+    if start < lookups.len() {
+        chunks.push(&lookups[start..]);
+    }
+
+    chunks
+}
+
+/// Resolves the lookups with a single update statement over the temporary table.
+async fn execute_lookups(
+    client: &mut impl SqlBackend,
+    temporary_table: &TemporaryTable,
+    lookups: &[LookupParts],
+    lookup_resolution: LookupResolution,
+) -> Result<(), UpdateProcessorError> {
+    let mut parameter_offset: usize = 0;
+
+    for lookup in lookups {
+        lookup.check_parameters()?;
+    }
+
+    // the values of the row before the update are compared, as the SET clause reads them
+    let set_resolved = match lookup_resolution {
+        LookupResolution::All | LookupResolution::Unresolved => String::new(),
+        LookupResolution::Deferred => format!(
+            ",\n    t.{resolved_column} = CASE WHEN {resolved_conditions} THEN 1 ELSE t.{resolved_column} END",
+            resolved_column = RESOLVED_COLUMN,
+            resolved_conditions = lookups.iter().map(LookupParts::resolved_condition).join(" OR "),
+        ),
+    };
+
+    let statement = formatdoc!(
+        "
+        UPDATE t
+        SET
+            {statement_parts_set}{set_resolved}
+        FROM {temporary_table} t
+        {statement_parts_outer_apply}
+        {where_unresolved}
+        ",
+        temporary_table = temporary_table.identifier().full(),
+        statement_parts_set = lookups.iter().map(LookupParts::set).join(",\n    "),
+        statement_parts_outer_apply = lookups
+            .iter()
+            .map(|l| {
+                let outer_apply = l.outer_apply(parameter_offset);
+
+                parameter_offset += l.parameters.len();
+
+                outer_apply
+            })
+            .join(""),
+        where_unresolved = match lookup_resolution {
+            LookupResolution::All => String::new(),
+            LookupResolution::Unresolved | LookupResolution::Deferred => format!(
+                "WHERE {}",
+                lookups
+                    .iter()
+                    .map(|l| format!("t.{} IS NULL", l.target_column))
+                    .join(" OR ")
+            ),
+        },
     );
 
-    if !target_column_statement_parts.lookups.is_empty() {
-        let statement = formatdoc!(
-            "
-            UPDATE t
-            SET
-                {statement_parts_set}
-            FROM {temporary_table} t
-            {statement_parts_outer_apply}
-            ",
-            temporary_table = temporary_table.identifier().full(),
-            statement_parts_set = target_column_statement_parts
-                .lookups
-                .iter()
-                .map(|l| &l.set)
-                .join(",\n    "),
-            statement_parts_outer_apply = target_column_statement_parts
-                .lookups
-                .iter()
-                .map(|l| &l.outer_apply)
-                .join(""),
+    let static_column_dependencies_parameters_refs: Vec<&dyn ToSql> = lookups
+        .iter()
+        .flat_map(|l| &l.parameters)
+        .map(|p| &**p)
+        .collect();
+
+    trace_sql!(statement);
+
+    client
+        .execute(&statement, &static_column_dependencies_parameters_refs)
+        .await?;
+
+    Ok(())
+}
+
+/// Resolves a lookup once per distinct key tuple in the temporary table, via a session scoped
+/// cache table, then propagates the results back onto every row sharing that key tuple.
+async fn execute_cached_lookup(
+    client: &mut impl SqlBackend,
+    temporary_table: &TemporaryTable,
+    lookup: &LookupParts,
+    lookup_resolution: LookupResolution,
+) -> Result<(), UpdateProcessorError> {
+    lookup.check_parameters()?;
+
+    let cache_table = format!(
+        "[#lookup_cache_{temporary_table_unescaped}]",
+        temporary_table_unescaped = temporary_table.identifier().part_unescaped(),
+    );
+
+    let distinct_columns = lookup
+        .column_dependencies
+        .iter()
+        .map(|(_, column)| format!("t.{column}"))
+        .chain(once(format!(
+            "CAST(NULL AS {target_column_type}) AS {target_column}",
+            target_column_type = lookup.target_column_type,
+            target_column = lookup.target_column,
+        )))
+        .join(", ");
+
+    let where_unresolved = match lookup_resolution {
+        LookupResolution::All => String::new(),
+        LookupResolution::Unresolved | LookupResolution::Deferred => {
+            format!("WHERE t.{} IS NULL", lookup.target_column)
+        }
+    };
+
+    // only rows whose lookup resolved to NULL are updated, so any value resolves the lookup
+    let set_resolved = match lookup_resolution {
+        LookupResolution::All | LookupResolution::Unresolved => String::new(),
+        LookupResolution::Deferred => format!(
+            ",\n    t.{resolved_column} = CASE WHEN c.{target_column} IS NOT NULL THEN 1 ELSE t.{resolved_column} END",
+            resolved_column = RESOLVED_COLUMN,
+            target_column = lookup.target_column,
+        ),
+    };
+
+    let statement = formatdoc!(
+        "
+        SELECT DISTINCT {distinct_columns}
+        INTO {cache_table}
+        FROM {temporary_table} t
+        {where_unresolved}
+
+        UPDATE t
+        SET
+            {set}
+        FROM {cache_table} t
+        {outer_apply}
+        UPDATE t
+        SET
+            t.{target_column} = c.{target_column}{set_resolved}
+        FROM {temporary_table} t
+        INNER JOIN {cache_table} c ON {join_condition}
+        {where_unresolved}
+
+        DROP TABLE {cache_table}
+        ",
+        temporary_table = temporary_table.identifier().full(),
+        set = lookup.set(),
+        outer_apply = lookup.outer_apply(0),
+        target_column = lookup.target_column,
+        join_condition = lookup.cache_join_condition(),
+    );
+
+    let parameters_refs: Vec<&dyn ToSql> = lookup.parameters.iter().map(|p| &**p).collect();
+
+    trace_sql!(statement);
+
+    client.execute(&statement, &parameters_refs).await?;
+
+    Ok(())
+}
+
+/// Moves rows whose lookup could not be resolved out of the temporary table, into the orphans
+/// table of the temporary table.
+async fn quarantine_orphans(
+    client: &mut impl SqlBackend,
+    temporary_table: &TemporaryTable,
+    lookup: &LookupParts,
+) -> Result<(), UpdateProcessorError> {
+    let statement = formatdoc!(
+        "
+        IF OBJECT_ID(N'{orphans_table}', N'U') IS NULL
+        BEGIN
+            SELECT * INTO {orphans_table} FROM {temporary_table} WHERE 1 = 0
+        END
+
+        INSERT INTO {orphans_table}
+        SELECT t.* FROM {temporary_table} t
+        WHERE {orphan_condition}
+
+        DELETE t FROM {temporary_table} t
+        WHERE {orphan_condition}
+        ",
+        orphans_table = temporary_table.orphans_identifier().full(),
+        temporary_table = temporary_table.identifier().full(),
+        orphan_condition = lookup.orphan_condition(),
+    );
+
+    trace_sql!(statement);
+
+    let result = client.execute(&statement, &[]).await?;
+
+    if let Some(&quarantined) = result.rows_affected().last()
+        && quarantined > 0
+    {
+        warn!(
+            "Quarantined {} orphan rows for lookup column {} into {}",
+            quarantined,
+            lookup.target_column,
+            temporary_table.orphans_identifier(),
         );
+    }
+
+    Ok(())
+}
 
-        let static_column_dependencies_parameters_refs: Vec<&dyn ToSql> =
-            target_column_statement_parts
-                .parameters
+/// Inserts a row into the lookup table for each distinct key tuple which could not be resolved,
+/// populating only the key columns.
+async fn insert_placeholder_parents(
+    client: &mut impl SqlBackend,
+    temporary_table: &TemporaryTable,
+    lookup: &LookupParts,
+) -> Result<(), UpdateProcessorError> {
+    let key_columns = lookup
+        .column_dependencies
+        .iter()
+        .map(|(key_column, _)| key_column.as_str())
+        .chain(
+            lookup
+                .static_dependencies
                 .iter()
-                .map(|p| &**p)
-                .collect();
+                .map(|(key_column, _)| key_column.as_str()),
+        )
+        .join(", ");
 
-        trace_sql!(statement);
+    let values = lookup
+        .column_dependencies
+        .iter()
+        .map(|(_, dependency)| format!("t.{dependency}"))
+        .chain(lookup.static_values(0))
+        .join(", ");
+
+    let statement = formatdoc!(
+        "
+        INSERT INTO {lookup_table} ({key_columns})
+        SELECT DISTINCT {values}
+        FROM {temporary_table} t
+        WHERE {orphan_condition}
+        ",
+        lookup_table = lookup.lookup_table,
+        temporary_table = temporary_table.identifier().full(),
+        orphan_condition = lookup.orphan_condition(),
+    );
 
-        client
-            .execute(&statement, &static_column_dependencies_parameters_refs)
-            .await?;
+    let parameters_refs: Vec<&dyn ToSql> = lookup.parameters.iter().map(|p| &**p).collect();
+
+    trace_sql!(statement);
+
+    let result = client.execute(&statement, &parameters_refs).await?;
+
+    if result.total() > 0 {
+        warn!(
+            "Inserted {} placeholder rows into {} for lookup column {}",
+            result.total(),
+            lookup.lookup_table,
+            lookup.target_column,
+        );
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(parameters: usize) -> LookupParts {
+        LookupParts {
+            target_column: String::from("[Lookup_1]"),
+            target_column_unescaped: String::from("Lookup_1"),
+            target_column_type: String::from("int"),
+            output_column: String::from("[ID]"),
+            lookup_table: String::from("[dbo].[Lookup]"),
+            orphan_policy: OrphanPolicy::Null,
+            column_dependencies: Vec::new(),
+            column_dependency_collations: Vec::new(),
+            static_dependencies: vec![(String::from("[Key]"), None); parameters],
+            parameters: (0..parameters)
+                .map(|parameter| Box::new(parameter as i32) as Box<dyn ToSql>)
+                .collect(),
+        }
+    }
+
+    fn chunk_lengths(lookups: &[LookupParts], max_lookups: usize) -> Vec<usize> {
+        chunk_lookups(lookups, max_lookups)
+            .iter()
+            .map(|chunk| chunk.len())
+            .collect()
+    }
+
+    #[test]
+    fn outer_apply_collates_key_columns() {
+        let lookup = LookupParts {
+            column_dependencies: vec![
+                (String::from("[Code]"), String::from("[Code_1]")),
+                (String::from("[Region]"), String::from("[Region_1]")),
+            ],
+            column_dependency_collations: vec![Some("Latin1_General_100_CI_AI"), None],
+            ..lookup(0)
+        };
+
+        assert!(lookup.outer_apply(0).contains(
+            "l_inner.[Code] COLLATE Latin1_General_100_CI_AI = t.[Code_1]\n        AND l_inner.[Region] = t.[Region_1]"
+        ));
+    }
+
+    #[test]
+    fn cache_join_condition_matches_null_keys() {
+        let lookup = LookupParts {
+            column_dependencies: vec![
+                (String::from("[Code]"), String::from("[Code_1]")),
+                (String::from("[Region]"), String::from("[Region_1]")),
+            ],
+            column_dependency_collations: vec![None, None],
+            ..lookup(0)
+        };
+
+        assert_eq!(
+            lookup.cache_join_condition(),
+            "(c.[Code_1] = t.[Code_1] OR (c.[Code_1] IS NULL AND t.[Code_1] IS NULL)) \
+            AND (c.[Region_1] = t.[Region_1] OR (c.[Region_1] IS NULL AND t.[Region_1] IS NULL))"
+        );
+        assert_eq!(lookup(0).cache_join_condition(), "1 = 1");
+    }
+
+    #[test]
+    fn check_parameters_of_static_dependencies() {
+        assert!(lookup(2).check_parameters().is_ok());
+
+        let lookup = LookupParts {
+            static_dependencies: vec![
+                (String::from("[Key]"), None),
+                (String::from("[Source]"), Some(String::from("SYSDATETIME()"))),
+                (String::from("[Region]"), None),
+            ],
+            ..lookup(1)
+        };
+
+        assert!(matches!(
+            lookup.check_parameters(),
+            Err(UpdateProcessorError::ParameterCountMismatch {
+                placeholders: 2,
+                parameters: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn chunk_lookups_bounds_lookup_count() {
+        let lookups = (0..5).map(|_| lookup(1)).collect::<Vec<_>>();
+
+        assert_eq!(chunk_lengths(&lookups, 2), [2, 2, 1]);
+        assert_eq!(chunk_lengths(&lookups, 5), [5]);
+        assert_eq!(chunk_lengths(&[], 2), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn chunk_lookups_bounds_parameter_count() {
+        let lookups = [
+            lookup(1200),
+            lookup(700),
+            lookup(200),
+            lookup(2500),
+            lookup(0),
+        ];
+
+        // a lookup exceeding the bound on its own is still resolved, in a chunk of its own
+        assert_eq!(chunk_lengths(&lookups, 16), [2, 1, 1, 1]);
+    }
+}
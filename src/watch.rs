@@ -0,0 +1,230 @@
+use crate::import_options::DataSourceDeletion;
+use log::{info, warn};
+use regex::Regex;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use thiserror::Error;
+
+/// Directory watched for data source files, each imported once its size is unchanged between two
+/// polls.
+#[derive(Debug)]
+pub struct WatchDirectory {
+    directory: PathBuf,
+    pattern: Option<Regex>,
+    archive_directory: Option<PathBuf>,
+    /// Size of each file not yet arrived when last polled
+    arriving: HashMap<PathBuf, u64>,
+    /// Files imported or failed, and retained in the directory
+    processed: HashSet<PathBuf>,
+}
+
+#[derive(Debug, Error)]
+pub enum WatchError {
+    #[error("invalid file name pattern: {0}")]
+    InvalidPattern(String, #[source] regex::Error),
+    #[error("could not create archive directory")]
+    CreateArchiveDirectory(#[source] io::Error),
+}
+
+impl WatchDirectory {
+    /// Watches the directory for files whose name matches the pattern, where `*` matches any
+    /// characters and `?` any single character, such as `rates_*.csv`, or any file without a
+    /// pattern. Creates the archive directory if it does not exist.
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        pattern: Option<&str>,
+        archive_directory: Option<PathBuf>,
+    ) -> Result<Self, WatchError> {
+        let pattern = pattern
+            .map(|pattern| {
+                Regex::new(&pattern_regex(pattern))
+                    .map_err(|err| WatchError::InvalidPattern(pattern.to_owned(), err))
+            })
+            .transpose()?;
+
+        if let Some(archive_directory) = &archive_directory {
+            fs::create_dir_all(archive_directory).map_err(WatchError::CreateArchiveDirectory)?;
+        }
+
+        Ok(WatchDirectory {
+            directory: directory.into(),
+            pattern,
+            archive_directory,
+            arriving: HashMap::default(),
+            processed: HashSet::default(),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Polls the directory, returning the files which have arrived since the last poll, in order
+    /// of file name.
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut sizes = HashMap::default();
+
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+
+            let matches = self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&entry.file_name().to_string_lossy()));
+
+            if metadata.is_file() && matches && !self.processed.contains(&path) {
+                sizes.insert(path, metadata.len());
+            }
+        }
+
+        // files removed from the directory are forgotten, such that a file of the same name
+        // arriving later is imported
+        self.processed.retain(|path| path.exists());
+
+        let mut arrived = sizes
+            .iter()
+            .filter(|&(path, size)| self.arriving.get(path) == Some(size))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        arrived.sort();
+
+        for path in &arrived {
+            sizes.remove(path);
+        }
+
+        self.arriving = sizes;
+
+        Ok(arrived)
+    }
+
+    /// Deletes the imported file, or moves it into the archive directory, otherwise retaining it
+    /// such that it is not imported again, as is a file which could not be deleted or moved.
+    pub fn imported(&mut self, path: &Path, deletion: DataSourceDeletion) -> io::Result<()> {
+        let result = self.remove_imported(path, deletion);
+
+        if result.is_err() {
+            self.processed.insert(path.to_owned());
+        }
+
+        result
+    }
+
+    fn remove_imported(&mut self, path: &Path, deletion: DataSourceDeletion) -> io::Result<()> {
+        match (deletion, &self.archive_directory) {
+            (DataSourceDeletion::Delete, _) => {
+                fs::remove_file(path)?;
+
+                info!("Deleted imported file {}", path.display());
+            }
+            (DataSourceDeletion::Retain, Some(archive_directory)) => {
+                let archive_path = archive_directory
+                    .join(path.file_name().expect("Files should have a file name"));
+
+                fs::rename(path, &archive_path)?;
+
+                info!(
+                    "Archived imported file {} to {}",
+                    path.display(),
+                    archive_path.display(),
+                );
+            }
+            (DataSourceDeletion::Retain, None) => {
+                self.processed.insert(path.to_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retains the file whose import failed, such that it is not imported again unless it is
+    /// removed from the directory and arrives again.
+    pub fn failed(&mut self, path: &Path) {
+        warn!(
+            "Retaining file {} whose import failed, which is not imported again",
+            path.display(),
+        );
+
+        self.processed.insert(path.to_owned());
+    }
+}
+
+/// Regular expression matching the whole of a file name matched by the pattern.
+fn pattern_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_files_once_arrived() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir().join(format!("watch_test_{}", std::process::id()));
+        let archive_directory = directory.join("archive");
+
+        fs::create_dir_all(&directory)?;
+
+        let mut watch_directory = WatchDirectory::new(
+            &directory,
+            Some("rates_*.csv"),
+            Some(archive_directory.clone()),
+        )?;
+
+        fs::write(directory.join("rates_1.csv"), "Code,Rate\n")?;
+        fs::write(directory.join("notes.txt"), "ignored")?;
+
+        // not yet arrived, as it may still be written
+        assert!(watch_directory.poll()?.is_empty());
+
+        fs::write(directory.join("rates_2.csv"), "Code,Rate\n")?;
+
+        let arrived = watch_directory.poll()?;
+
+        assert_eq!(arrived, [directory.join("rates_1.csv")]);
+
+        watch_directory.imported(&arrived[0], DataSourceDeletion::Retain)?;
+
+        assert!(archive_directory.join("rates_1.csv").exists());
+        assert_eq!(watch_directory.poll()?, [directory.join("rates_2.csv")]);
+
+        watch_directory.failed(&directory.join("rates_2.csv"));
+
+        assert!(watch_directory.poll()?.is_empty());
+        assert!(watch_directory.poll()?.is_empty());
+
+        // a file which could not be archived is skipped rather than imported again
+        fs::write(directory.join("rates_3.csv"), "Code,Rate\n")?;
+        fs::remove_dir_all(&archive_directory)?;
+
+        assert!(watch_directory.poll()?.is_empty());
+
+        let arrived = watch_directory.poll()?;
+
+        assert_eq!(arrived, [directory.join("rates_3.csv")]);
+        assert!(
+            watch_directory
+                .imported(&arrived[0], DataSourceDeletion::Retain)
+                .is_err()
+        );
+        assert!(watch_directory.poll()?.is_empty());
+
+        fs::remove_dir_all(&directory)?;
+
+        Ok(())
+    }
+}
@@ -1,15 +1,27 @@
 mod xml_data_source_stream;
+mod xsd;
 
+pub use xsd::{LoadXsdError, XsdValidationError};
+
+use crate::data_source_file::{DataSourceFile, skip_utf8_bom};
 use crate::import_profile::Field;
+use crate::record_accounting::RecordAccounting;
+use crate::xml_data_source::xsd::{Validator, XsdSchema};
 use arrayvec::ArrayVec;
 use indexmap::{IndexMap, IndexSet};
-use quick_xml::Reader;
+use itertools::Itertools;
+use quick_xml::events::Event;
+use quick_xml::name::{QName, ResolveResult};
+use quick_xml::{NsReader, Reader};
 use rustc_hash::FxBuildHasher as BuildHasher;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
 use std::num::NonZeroU64;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::Arc;
 use thiserror::Error;
-use tokio::fs::File;
 use tokio::io::BufReader;
 
 #[derive(Debug, Error)]
@@ -20,27 +32,283 @@ pub enum CreateXmlDataSourceError {
     InvalidSelector(String),
     #[error("invalid fields")]
     InvalidFields,
+    #[error("could not count records of data source file: {0}")]
+    CountRecordsError(PathBuf, #[source] quick_xml::Error),
+    #[error("could not load XSD: {0}")]
+    LoadXsdError(PathBuf, #[source] LoadXsdError),
+    #[error(transparent)]
+    XsdValidationError(#[from] XsdValidationError),
+}
+
+/// When the document is validated against the XSD.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, JsonSchema, Deserialize)]
+pub enum XsdValidation {
+    /// Validate the whole document before reading any record, reporting every violation found
+    #[default]
+    Document,
+    /// Validate the document as records are read, failing at the first violation, or rejecting the
+    /// record of the violation when recovering from malformed records
+    Streaming,
+}
+
+/// Handling of the elements below the record element which are not a field of the field group.
+#[derive(Debug, Clone, Default, PartialEq, Eq, JsonSchema, Deserialize)]
+pub enum UnknownFieldPolicy {
+    /// Reject the record
+    #[default]
+    Error,
+    /// Skip the element
+    Ignore,
+    /// Gather the source text of the elements into the catch-all field of the name, which may be
+    /// mapped like any other field
+    Collect(String),
 }
 
 #[derive(Debug)]
 pub struct XmlDataSource<R> {
-    reader: Reader<BufReader<R>>,
+    reader: NsReader<BufReader<R>>,
     buffer: Vec<u8>,
-    selector_parts: ArrayVec<Box<str>, 8>,
+    selectors: Selectors,
+    /// Selectors matching each open element down to the depth of the records
+    selector_matches: ArrayVec<SelectorMask, 8>,
     fields: IndexSet<Arc<str>, BuildHasher>,
     depth: usize,
     record_number: Option<NonZeroU64>,
     line_number: u64,
+    /// Skip malformed records rather than failing, resuming at the next record element
+    recover: bool,
+    resyncing: bool,
+    /// Skipping an element at the depth of the records which is not in the namespace of any
+    /// selector, until its end tag
+    skipping: bool,
+    rejected_records: u64,
+    /// Accounting of the records of the table mapper, counting each rejected record
+    record_accounting: Option<Rc<RecordAccounting>>,
+    last_error_position: Option<u64>,
+    /// Name of the field to hold the raw payload of each record, and the raw payload read so far
+    raw_payload: Option<(Arc<str>, String)>,
+    unknown_fields: UnknownFieldPolicy,
+    /// Name of the catch-all field of the unknown field policy, if collecting
+    collect_field: Option<Arc<str>>,
+    /// Validator of the document against the XSD, if validating while streaming
+    validator: Option<Validator>,
     current_record_state: CurrentRecordState,
 }
 
+/// Part of a selector, matching elements by local name or the `*` wildcard, and optionally by
+/// namespace prefix, `prefix:Name`, or URI, `{urn:example}Name`.
+#[derive(Debug)]
+struct SelectorPart {
+    local_name: Box<str>,
+    namespace: SelectorNamespace,
+}
+
+#[derive(Debug)]
+enum SelectorNamespace {
+    Any,
+    Prefix(Box<str>),
+    Uri(Box<str>),
+}
+
+impl SelectorPart {
+    fn parse(selector_part: &str) -> Option<Self> {
+        let (namespace, local_name) = if let Some(rest) = selector_part.strip_prefix('{') {
+            let (uri, local_name) = rest.split_once('}')?;
+
+            (SelectorNamespace::Uri(Box::from(uri.trim())), local_name)
+        } else if let Some((prefix, local_name)) = selector_part.split_once(':') {
+            let prefix = prefix.trim();
+
+            if prefix.is_empty() {
+                return None;
+            }
+
+            (SelectorNamespace::Prefix(Box::from(prefix)), local_name)
+        } else {
+            (SelectorNamespace::Any, selector_part)
+        };
+
+        let local_name = local_name.trim();
+
+        if local_name.is_empty() || local_name.contains([':', '{', '}']) {
+            return None;
+        }
+
+        Some(SelectorPart {
+            local_name: Box::from(local_name),
+            namespace,
+        })
+    }
+
+    fn matches_local_name(&self, name: QName) -> bool {
+        &*self.local_name == "*" || self.local_name.as_bytes() == name.local_name().into_inner()
+    }
+
+    /// Whether the element is in the namespace of the selector part, resolving its prefix against
+    /// the namespaces bound by the reader.
+    fn matches_namespace<R>(&self, reader: &NsReader<R>, name: QName) -> bool {
+        match &self.namespace {
+            SelectorNamespace::Any => true,
+            SelectorNamespace::Prefix(prefix) => {
+                name.prefix().map(|prefix| prefix.into_inner()) == Some(prefix.as_bytes())
+            }
+            SelectorNamespace::Uri(uri) => match reader.resolve_element(name).0 {
+                ResolveResult::Bound(namespace) => namespace.into_inner() == uri.as_bytes(),
+                ResolveResult::Unbound => uri.is_empty(),
+                ResolveResult::Unknown(_) => false,
+            },
+        }
+    }
+}
+
+impl Display for SelectorPart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.namespace {
+            SelectorNamespace::Any => write!(f, "{}", self.local_name),
+            SelectorNamespace::Prefix(prefix) => write!(f, "{}:{}", prefix, self.local_name),
+            SelectorNamespace::Uri(uri) => write!(f, "{{{}}}{}", uri, self.local_name),
+        }
+    }
+}
+
+/// Parses the selector into its parts, separated by `/`.
+fn parse_selector(selector: &str) -> Result<ArrayVec<SelectorPart, 8>, CreateXmlDataSourceError> {
+    let invalid_selector = || CreateXmlDataSourceError::InvalidSelector(selector.to_owned());
+    let mut selector_parts = ArrayVec::new();
+
+    // split on separators outside of namespace URIs, which may themselves contain `/`
+    let mut in_uri = false;
+
+    for selector_part in selector.split(|c| {
+        match c {
+            '{' => in_uri = true,
+            '}' => in_uri = false,
+            _ => {}
+        }
+
+        c == '/' && !in_uri
+    }) {
+        let selector_part = selector_part.trim();
+
+        if !selector_part.is_empty() {
+            selector_parts
+                .try_push(SelectorPart::parse(selector_part).ok_or_else(invalid_selector)?)
+                .map_err(|_| invalid_selector())?;
+        }
+    }
+
+    if selector_parts.is_empty() {
+        return Err(invalid_selector());
+    }
+
+    Ok(selector_parts)
+}
+
+/// Selectors of the record elements, all of the same depth, a record being read from each element
+/// matched by any of them.
+#[derive(Debug)]
+struct Selectors {
+    selectors: Vec<ArrayVec<SelectorPart, 8>>,
+}
+
+/// Set of the selectors matching an element, by index.
+type SelectorMask = u32;
+
+impl Selectors {
+    fn parse<'a>(
+        selectors: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, CreateXmlDataSourceError> {
+        let selectors = selectors
+            .into_iter()
+            .map(parse_selector)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let invalid_selectors = || {
+            CreateXmlDataSourceError::InvalidSelector(
+                selectors
+                    .iter()
+                    .map(|selector| selector.iter().join("/"))
+                    .join(", "),
+            )
+        };
+
+        if selectors.is_empty()
+            || selectors.len() > SelectorMask::BITS as usize
+            || !selectors.iter().map(ArrayVec::len).all_equal()
+        {
+            return Err(invalid_selectors());
+        }
+
+        Ok(Selectors { selectors })
+    }
+
+    /// Depth of the record elements.
+    fn depth(&self) -> usize {
+        self.selectors[0].len()
+    }
+
+    fn all(&self) -> SelectorMask {
+        SelectorMask::MAX >> (SelectorMask::BITS as usize - self.selectors.len())
+    }
+
+    /// Selectors of the parent mask whose part at the depth matches the element by local name,
+    /// and those which also match it by namespace.
+    fn matches<R>(
+        &self,
+        depth: usize,
+        parent: SelectorMask,
+        reader: &NsReader<R>,
+        name: QName,
+    ) -> (SelectorMask, SelectorMask) {
+        self.selectors.iter().enumerate().fold(
+            (0, 0),
+            |(local_name_matches, matches), (index, selector)| {
+                let part = &selector[depth];
+
+                if parent & (1 << index) == 0 || !part.matches_local_name(name) {
+                    (local_name_matches, matches)
+                } else if part.matches_namespace(reader, name) {
+                    (local_name_matches | 1 << index, matches | 1 << index)
+                } else {
+                    (local_name_matches | 1 << index, matches)
+                }
+            },
+        )
+    }
+}
+
+impl Display for Selectors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.selectors
+                .iter()
+                .filter_map(|selector| selector.last())
+                .map(|part| format!("<{}>", part))
+                .unique()
+                .join(" or ")
+        )
+    }
+}
+
 #[derive(Debug)]
 struct CurrentRecordState {
     field_data: String,
     field_indices: IndexMap<Arc<str>, usize, BuildHasher>,
     field_index: Option<usize>,
     field_start: usize,
+    /// Path of the elements below the record element enclosing the current element, such as
+    /// `Address/City`, matched against the field names
+    field_path: String,
+    /// Depth of the element of the current field
+    field_depth: usize,
+    /// Depth of the unknown element being ignored or collected
+    unknown_depth: usize,
+    /// Source text of the unknown elements collected so far
+    unknown_data: String,
     line_start: u64,
+    byte_start: u64,
 }
 
 impl CurrentRecordState {
@@ -50,43 +318,54 @@ impl CurrentRecordState {
             field_indices: IndexMap::with_capacity_and_hasher(fields_length, BuildHasher),
             field_index: None,
             field_start: 0,
+            field_path: String::new(),
+            field_depth: 0,
+            unknown_depth: 0,
+            unknown_data: String::new(),
             line_start: 0,
+            byte_start: 0,
         }
     }
 }
 
-impl XmlDataSource<File> {
+impl XmlDataSource<DataSourceFile> {
     pub async fn new<'fields>(
         path: impl AsRef<Path>,
         fields: impl IntoIterator<Item = &'fields Field>,
-        selector: &str,
+        selectors: &[&str],
+        xsd: Option<&Path>,
+        xsd_validation: XsdValidation,
+        recover: bool,
+        raw_payload_field: Option<&str>,
+        unknown_fields: &UnknownFieldPolicy,
     ) -> Result<Self, CreateXmlDataSourceError> {
-        let file = File::open(&path).await.map_err(|err| {
-            CreateXmlDataSourceError::OpenFileError(path.as_ref().to_owned(), err)
-        })?;
+        let mut validator = None;
 
-        let buf_reader = BufReader::new(file);
-        let reader = Reader::from_reader(buf_reader);
+        if let Some(xsd) = xsd {
+            let schema = XsdSchema::load(xsd)
+                .await
+                .map_err(|err| CreateXmlDataSourceError::LoadXsdError(xsd.to_owned(), err))?;
+            let schema = Arc::new(schema);
 
-        let selector_parts: ArrayVec<Box<str>, 8> = selector
-            .split('/')
-            .filter_map(|selector_part| {
-                let selector_part = selector_part.trim();
+            match xsd_validation {
+                XsdValidation::Document => {
+                    let buf_reader = open(path.as_ref()).await?;
 
-                if !selector_part.is_empty() {
-                    Some(Box::from(selector_part))
-                } else {
-                    None
+                    schema.validate(Reader::from_reader(buf_reader)).await?;
                 }
-            })
-            .collect();
-
-        if selector_parts.is_empty() {
-            return Err(CreateXmlDataSourceError::InvalidSelector(
-                selector.to_owned(),
-            ));
+                XsdValidation::Streaming => validator = Some(Validator::new(schema)),
+            }
         }
 
+        let selectors = Selectors::parse(selectors.iter().copied())?;
+
+        let buf_reader = open(path.as_ref()).await?;
+        let mut reader = NsReader::from_reader(buf_reader);
+
+        // Mismatched end tags are reported by the stream instead, such that the record is rejected
+        // without leaving the reader unable to match any later end tag
+        reader.config_mut().check_end_names = !recover;
+
         let fields: IndexSet<Arc<str>, BuildHasher> = fields
             .into_iter()
             .filter_map(|field| {
@@ -109,12 +388,102 @@ impl XmlDataSource<File> {
         Ok(XmlDataSource {
             reader,
             buffer: Vec::new(),
-            selector_parts,
+            selectors,
+            selector_matches: ArrayVec::new(),
             fields,
             depth: 0,
             record_number: None,
             line_number: 0,
+            recover,
+            resyncing: false,
+            skipping: false,
+            rejected_records: 0,
+            record_accounting: None,
+            last_error_position: None,
+            raw_payload: raw_payload_field
+                .map(|raw_payload_field| (Arc::from(raw_payload_field), String::new())),
+            unknown_fields: unknown_fields.clone(),
+            collect_field: match unknown_fields {
+                UnknownFieldPolicy::Collect(collect_field) => {
+                    Some(Arc::from(collect_field.as_str()))
+                }
+                _ => None,
+            },
+            validator,
             current_record_state,
         })
     }
+
+    /// Counts the records of the data source file, without collecting the fields of each record.
+    pub async fn count_records(
+        path: impl AsRef<Path>,
+        selectors: &[&str],
+    ) -> Result<u64, CreateXmlDataSourceError> {
+        let selectors = Selectors::parse(selectors.iter().copied())?;
+        let selector_depth = selectors.depth();
+
+        let mut reader = NsReader::from_reader(open(path.as_ref()).await?);
+        let mut buffer = Vec::new();
+
+        let mut depth = 0usize;
+        let mut records = 0u64;
+        // whether the element at the depth of the records is matched by a selector
+        let mut record = false;
+
+        loop {
+            buffer.clear();
+
+            match reader
+                .read_event_into_async(&mut buffer)
+                .await
+                .map_err(|err| {
+                    CreateXmlDataSourceError::CountRecordsError(path.as_ref().to_owned(), err)
+                })? {
+                Event::Start(start) => {
+                    depth += 1;
+
+                    if depth == selector_depth {
+                        record = selectors
+                            .matches(depth - 1, selectors.all(), &reader, start.name())
+                            .1
+                            != 0;
+                    }
+                }
+                Event::End(_) => {
+                    depth = depth.saturating_sub(1);
+
+                    if depth + 1 == selector_depth && record {
+                        records += 1;
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+/// Opens the data source file, transcoded to UTF-8 if it is UTF-16, skipping the byte order mark
+/// of a UTF-8 file.
+async fn open(path: &Path) -> Result<BufReader<DataSourceFile>, CreateXmlDataSourceError> {
+    let open_file_error = |err| CreateXmlDataSourceError::OpenFileError(path.to_owned(), err);
+    let mut buf_reader = DataSourceFile::open_text(path, None)
+        .await
+        .map_err(open_file_error)?;
+
+    skip_utf8_bom(&mut buf_reader)
+        .await
+        .map_err(open_file_error)?;
+
+    Ok(buf_reader)
+}
+
+impl<R> XmlDataSource<R> {
+    /// Counts each malformed record rejected when recovering in the record accounting.
+    pub fn with_record_accounting(mut self, record_accounting: Rc<RecordAccounting>) -> Self {
+        self.record_accounting = Some(record_accounting);
+        self
+    }
 }
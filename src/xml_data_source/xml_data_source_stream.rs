@@ -2,13 +2,20 @@ use crate::data_source::string_map::StringMap;
 use crate::data_source::{
     DataSourceErrorIndex, DataSourceRecord, DataSourceRecordIndex, ReadRecordError,
 };
-use crate::xml_data_source::{CurrentRecordState, XmlDataSource};
+use crate::xml_data_source::{CurrentRecordState, UnknownFieldPolicy, XmlDataSource};
 use futures::Stream;
+use indexmap::IndexSet;
+use itertools::Itertools;
+use log::warn;
 use memchr::memchr_iter;
-use quick_xml::events::Event::{CData, Empty, End, Eof, GeneralRef, Start, Text};
+use quick_xml::events::Event;
+use quick_xml::events::Event::{CData, Comment, Empty, End, Eof, GeneralRef, PI, Start, Text};
+use rustc_hash::FxBuildHasher as BuildHasher;
+use std::borrow::Cow;
 use std::num::NonZero;
 use std::pin::Pin;
 use std::str::Utf8Error;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use thiserror::Error;
 use tokio::io::AsyncRead;
@@ -29,10 +36,14 @@ enum XmlReadRecordErrorKind {
     XmlDecodingError(#[from] quick_xml::encoding::EncodingError),
     #[error("unexpected start tag: {0}")]
     UnexpectedStartTag(String),
+    #[error("unexpected end tag: {0}")]
+    UnexpectedEndTag(String),
     #[error("unknown field: {0}")]
     UnknownField(String),
     #[error("error interpreting UTF-8")]
     Utf8Error(#[from] Utf8Error),
+    #[error("document does not conform to the XSD: {0}")]
+    XsdViolation(String),
 }
 
 impl XmlReadRecordError {
@@ -54,6 +65,52 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
     type Item = Result<DataSourceRecord, XmlReadRecordError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.poll_read_record(cx) {
+                Poll::Ready(Some(Err(err)))
+                    if self.recover && self.depth >= self.selectors.depth() =>
+                {
+                    // Stop recovering if the reader cannot make progress past the error
+                    let position = self.reader.buffer_position();
+
+                    if self.last_error_position == Some(position) {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+
+                    warn!(
+                        "Rejected malformed XML record, resuming at the next {} element: {}: {}",
+                        self.selectors, err, err.source,
+                    );
+
+                    self.last_error_position = Some(position);
+                    self.record_number =
+                        NonZero::new(self.record_number.map_or(1, |r| r.get() + 1));
+                    self.rejected_records += 1;
+                    self.resyncing = true;
+                    self.current_record_state = CurrentRecordState::new(self.fields.len());
+
+                    if let Some(record_accounting) = &self.record_accounting {
+                        record_accounting.rejected();
+                    }
+                }
+                Poll::Ready(None) if self.rejected_records > 0 => {
+                    warn!("Rejected {} malformed XML records", self.rejected_records);
+
+                    self.rejected_records = 0;
+
+                    return Poll::Ready(None);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> XmlDataSource<R> {
+    fn poll_read_record(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<DataSourceRecord, XmlReadRecordError>>> {
         #[inline(always)]
         fn str_from_utf8(
             bytes: &[u8],
@@ -70,24 +127,38 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
         let XmlDataSource {
             reader,
             buffer,
-            selector_parts,
+            selectors,
+            selector_matches,
             fields,
             depth,
             record_number,
             line_number,
+            resyncing,
+            skipping,
+            raw_payload,
+            unknown_fields,
+            collect_field,
+            validator,
             current_record_state:
                 CurrentRecordState {
                     field_data: current_data,
                     field_indices: current_field_indices,
                     field_index: current_field_index,
                     field_start: current_field_start,
+                    field_path: current_field_path,
+                    field_depth: current_field_depth,
+                    unknown_depth: current_unknown_depth,
+                    unknown_data: current_unknown_data,
                     line_start: current_line_start,
+                    byte_start: current_byte_start,
                 },
-        } = &mut *self;
+            ..
+        } = self;
 
         loop {
             buffer.clear();
 
+            let event_start = reader.buffer_position();
             let event_result = Box::pin(reader.read_event_into_async(buffer))
                 .as_mut()
                 .poll(cx);
@@ -115,18 +186,140 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
                 line_number: *line_number + 1,
             };
 
+            if let Some(validator) = validator.as_mut() {
+                validator.event(&event);
+
+                let violations = validator.take_violations();
+
+                if !violations.is_empty() {
+                    // the element of the violation is entered, such that a violation of the record
+                    // element itself rejects the record when recovering
+                    if matches!(event, Start(_) | Empty(_)) {
+                        *depth += 1;
+                    }
+
+                    return Poll::Ready(Some(Err(XmlReadRecordError::new(
+                        index,
+                        XmlReadRecordErrorKind::XsdViolation(violations.iter().join("; ")),
+                    ))));
+                }
+            }
+
+            if *resyncing {
+                match &event {
+                    Start(start)
+                        if selectors
+                            .matches(selectors.depth() - 1, selectors.all(), reader, start.name())
+                            .1
+                            != 0 =>
+                    {
+                        // the ancestors of the record are unknown, so any selector may match it
+                        *resyncing = false;
+                        *depth = selectors.depth() - 1;
+
+                        selector_matches.clear();
+                        selector_matches.extend(std::iter::repeat_n(selectors.all(), *depth));
+                    }
+                    Eof => {}
+                    _ => continue,
+                }
+            }
+
+            if *skipping {
+                match &event {
+                    Start(_) => *depth += 1,
+                    End(_) => {
+                        *depth -= 1;
+
+                        if *depth == selectors.depth() - 1 {
+                            *skipping = false;
+                        }
+                    }
+                    Eof => return Poll::Ready(None),
+                    _ => {}
+                }
+
+                continue;
+            }
+
+            // selectors matching an element down to the depth of the records, by local name, and
+            // by local name and namespace
+            let start_matches = match &event {
+                Start(start) if *depth < selectors.depth() => {
+                    let parent = match *depth {
+                        0 => selectors.all(),
+                        parent_depth => selector_matches[parent_depth - 1],
+                    };
+
+                    Some(selectors.matches(*depth, parent, reader, start.name()))
+                }
+                _ => None,
+            };
+
+            if let Some((_, raw_payload)) = raw_payload.as_mut() {
+                let record_depth = match (&event, start_matches) {
+                    // skipped below, as not in the namespace of the records
+                    (Start(_), Some((local_name_matches, 0)))
+                        if *depth + 1 == selectors.depth() && local_name_matches != 0 =>
+                    {
+                        *depth
+                    }
+                    (Start(_), _) => *depth + 1,
+                    _ => *depth,
+                };
+
+                if record_depth == selectors.depth() && matches!(event, Start(_)) {
+                    raw_payload.clear();
+                }
+
+                if record_depth >= selectors.depth() {
+                    push_raw_payload(raw_payload, &event);
+                }
+            }
+
+            // the content of an unknown element is skipped, or gathered into the catch-all field
+            if *current_unknown_depth != 0 && !matches!(event, Eof) {
+                if collect_field.is_some() {
+                    push_raw_payload(current_unknown_data, &event);
+                }
+
+                match event {
+                    Start(_) => *depth += 1,
+                    End(_) => {
+                        *depth -= 1;
+
+                        if *depth < *current_unknown_depth {
+                            *current_unknown_depth = 0;
+
+                            pop_field_path(current_field_path);
+                        }
+                    }
+                    _ => {}
+                }
+
+                continue;
+            }
+
             match event {
                 Start(start) => {
                     *depth += 1;
 
                     let local_name = start.local_name().into_inner();
 
-                    if *depth <= selector_parts.len() {
-                        if *depth == selector_parts.len() {
+                    if let Some((local_name_matches, matches)) = start_matches {
+                        if *depth == selectors.depth() {
+                            // Elements of the same name in another namespace are not records
+                            if local_name_matches != 0 && matches == 0 {
+                                *skipping = true;
+
+                                continue;
+                            }
+
                             *current_line_start = *line_number + 1;
+                            *current_byte_start = event_start;
                         }
 
-                        if selector_parts[*depth - 1].as_bytes() != local_name {
+                        if matches == 0 {
                             return Poll::Ready(Some(Err(XmlReadRecordError::new(
                                 index,
                                 XmlReadRecordErrorKind::UnexpectedStartTag(
@@ -134,35 +327,52 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
                                 ),
                             ))));
                         }
-                    } else {
-                        let current_depth_past_selector = *depth - selector_parts.len();
 
-                        if current_depth_past_selector == 1 {
-                            let name = str_from_utf8(local_name, index)?;
+                        selector_matches.truncate(*depth - 1);
+                        selector_matches.push(matches);
+                    } else if current_field_index.is_none() {
+                        if !current_field_path.is_empty() {
+                            current_field_path.push('/');
+                        }
+
+                        *current_field_path += str_from_utf8(local_name, index)?;
 
-                            if let Some(field_index) = fields.get_index_of(name) {
-                                *current_field_index = Some(field_index);
-                            } else {
+                        if let Some(field_index) = fields.get_index_of(current_field_path.as_str())
+                        {
+                            *current_field_index = Some(field_index);
+                            *current_field_depth = *depth;
+                        } else if !encloses_field(fields, current_field_path) {
+                            if *unknown_fields == UnknownFieldPolicy::Error {
                                 return Poll::Ready(Some(Err(XmlReadRecordError::new(
                                     index,
-                                    XmlReadRecordErrorKind::UnknownField(name.to_owned()),
+                                    XmlReadRecordErrorKind::UnknownField(
+                                        current_field_path.clone(),
+                                    ),
                                 ))));
                             }
-                        } else {
-                            *current_data += "<";
-                            *current_data += str_from_utf8(&start, index)?;
-                            *current_data += ">";
+
+                            *current_unknown_depth = *depth;
+
+                            if collect_field.is_some() {
+                                *current_unknown_data += "<";
+                                *current_unknown_data += str_from_utf8(&start, index)?;
+                                *current_unknown_data += ">";
+                            }
                         }
+                    } else {
+                        *current_data += "<";
+                        *current_data += str_from_utf8(&start, index)?;
+                        *current_data += ">";
                     }
                 }
                 End(end) => {
                     *depth -= 1;
 
-                    if *depth > selector_parts.len() {
+                    if current_field_index.is_some() && *depth >= *current_field_depth {
                         *current_data += "</";
                         *current_data += str_from_utf8(&end, index)?;
                         *current_data += ">";
-                    } else if *depth == selector_parts.len() {
+                    } else if *depth >= selectors.depth() {
                         if let Some(field_name) = current_field_index
                             .take()
                             .and_then(|field_index| fields.get_index(field_index))
@@ -170,14 +380,32 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
                             current_field_indices.insert(field_name.clone(), current_data.len());
 
                             *current_field_start = current_data.len();
+                        }
+
+                        if !current_field_path.is_empty() {
+                            pop_field_path(current_field_path);
                         } else {
-                            unreachable!(
-                                "ill-formed document (unexpected end tag) should be rejected by quick-xml"
-                            );
+                            return Poll::Ready(Some(Err(XmlReadRecordError::new(
+                                index,
+                                XmlReadRecordErrorKind::UnexpectedEndTag(
+                                    str_from_utf8(end.local_name().into_inner(), index)?.to_owned(),
+                                ),
+                            ))));
                         }
-                    } else if *depth == selector_parts.len() - 1 {
+                    } else if *depth == selectors.depth() - 1 {
                         *record_number = NonZero::new(record_number.map_or(1, |r| r.get() + 1));
 
+                        if let Some(collect_field) = collect_field {
+                            current_data.push_str(current_unknown_data);
+                            current_field_indices.insert(collect_field.clone(), current_data.len());
+                        }
+
+                        if let Some((raw_payload_field, raw_payload)) = raw_payload.as_mut() {
+                            current_data.push_str(raw_payload);
+                            current_field_indices
+                                .insert(raw_payload_field.clone(), current_data.len());
+                        }
+
                         let record_fields = unsafe {
                             StringMap::new(
                                 std::mem::take(current_data),
@@ -191,6 +419,8 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
                                 record_number: record_number.expect("always non-zero"),
                                 line_start: *current_line_start,
                                 line_end: *line_number + 1,
+                                byte_start: *current_byte_start,
+                                byte_end: reader.buffer_position(),
                             },
                         );
 
@@ -207,6 +437,21 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
                     *current_data += str_from_utf8(&empty, index)?;
                     *current_data += "/>";
                 }
+                Empty(empty) if collect_field.is_some() && *depth >= selectors.depth() => {
+                    let local_name = str_from_utf8(empty.local_name().into_inner(), index)?;
+                    let field_path = if current_field_path.is_empty() {
+                        Cow::Borrowed(local_name)
+                    } else {
+                        Cow::Owned(format!("{}/{}", current_field_path, local_name))
+                    };
+
+                    if !fields.contains(field_path.as_ref()) && !encloses_field(fields, &field_path)
+                    {
+                        *current_unknown_data += "<";
+                        *current_unknown_data += str_from_utf8(&empty, index)?;
+                        *current_unknown_data += "/>";
+                    }
+                }
                 GeneralRef(general_ref) if current_field_index.is_some() => {
                     *current_data += &general_ref
                         .decode()
@@ -223,3 +468,385 @@ impl<R: AsyncRead + Unpin> Stream for XmlDataSource<R> {
         }
     }
 }
+
+/// Whether the element of the path encloses the elements of nested fields, such as `Address` of
+/// `Address/City`.
+fn encloses_field(fields: &IndexSet<Arc<str>, BuildHasher>, field_path: &str) -> bool {
+    fields.iter().any(|field| {
+        field
+            .strip_prefix(field_path)
+            .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Removes the last element from the field path.
+fn pop_field_path(field_path: &mut String) {
+    field_path.truncate(field_path.rfind('/').unwrap_or(0));
+}
+
+/// Appends the source text of the event to the raw payload of the current record.
+fn push_raw_payload(raw_payload: &mut String, event: &Event) {
+    let (prefix, content, suffix) = match event {
+        Start(start) => ("<", &**start, ">"),
+        End(end) => ("</", &**end, ">"),
+        Empty(empty) => ("<", &**empty, "/>"),
+        Text(text) => ("", &**text, ""),
+        CData(cdata) => ("<![CDATA[", &**cdata, "]]>"),
+        Comment(comment) => ("<!--", &**comment, "-->"),
+        PI(pi) => ("<?", &**pi, "?>"),
+        GeneralRef(general_ref) => ("&", &**general_ref, ";"),
+        _ => return,
+    };
+
+    *raw_payload += prefix;
+    *raw_payload += &String::from_utf8_lossy(content);
+    *raw_payload += suffix;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import_profile::Field;
+    use crate::xml_data_source::XsdValidation;
+    use futures::{StreamExt, TryStreamExt};
+
+    #[tokio::test]
+    async fn match_selector_namespace() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_namespaces_{}.xml",
+            std::process::id()
+        ));
+
+        tokio::fs::write(
+            &path,
+            r#"<Orders xmlns:a="urn:a" xmlns:b="urn:b">
+                <a:Order><Id>1</Id></a:Order>
+                <b:Order><Id>2</Id></b:Order>
+                <Order xmlns="urn:a"><Id>3</Id></Order>
+                <Order><Id>4</Id></Order>
+            </Orders>"#,
+        )
+        .await?;
+
+        let fields: Vec<Field> = serde_json::from_value(serde_json::json!([{ "name": "Id" }]))?;
+
+        for (selector, ids) in [
+            ("Orders/Order", vec!["1", "2", "3", "4"]),
+            ("Orders/b:Order", vec!["2"]),
+            ("Orders/{urn:a}Order", vec!["1", "3"]),
+            ("Orders/{}Order", vec!["4"]),
+        ] {
+            let records = XmlDataSource::new(
+                &path,
+                &fields,
+                &[selector],
+                None,
+                XsdValidation::Document,
+                false,
+                None,
+                &UnknownFieldPolicy::Error,
+            )
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+            assert_eq!(
+                records
+                    .iter()
+                    .map(|record| record.get("Id").unwrap())
+                    .collect::<Vec<_>>(),
+                ids,
+                "{}",
+                selector
+            );
+            assert_eq!(
+                XmlDataSource::count_records(&path, &[selector]).await?,
+                ids.len() as u64
+            );
+        }
+
+        for selector in ["Orders/:Order", "Orders/{urn:a", "Orders/{urn:a}"] {
+            assert!(matches!(
+                XmlDataSource::new(
+                    &path,
+                    &fields,
+                    &[selector],
+                    None,
+                    XsdValidation::Document,
+                    false,
+                    None,
+                    &UnknownFieldPolicy::Error
+                )
+                .await,
+                Err(crate::xml_data_source::CreateXmlDataSourceError::InvalidSelector(_))
+            ));
+        }
+
+        tokio::fs::remove_file(&path).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn match_multiple_selectors() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_selectors_{}.xml",
+            std::process::id()
+        ));
+
+        tokio::fs::write(
+            &path,
+            r#"<Root>
+                <Orders><Item><Id>1</Id></Item></Orders>
+                <Returns><Item><Id>2</Id></Item><Note><Id>3</Id></Note></Returns>
+            </Root>"#,
+        )
+        .await?;
+
+        let fields: Vec<Field> = serde_json::from_value(serde_json::json!([{ "name": "Id" }]))?;
+
+        for (selectors, ids) in [
+            (&["Root/*/Item"][..], vec!["1", "2"]),
+            (
+                &["Root/Orders/Item", "Root/Returns/Note"][..],
+                vec!["1", "3"],
+            ),
+            (&["Root/*/*"][..], vec!["1", "2", "3"]),
+        ] {
+            let mut xml_data_source = XmlDataSource::new(
+                &path,
+                &fields,
+                selectors,
+                None,
+                XsdValidation::Document,
+                true,
+                None,
+                &UnknownFieldPolicy::Error,
+            )
+            .await?;
+            let mut records = Vec::new();
+
+            // elements matched by no selector at the depth of the records are malformed records
+            while let Some(record) = xml_data_source.next().await {
+                if let Ok(record) = record {
+                    records.push(record.get("Id").unwrap().to_owned());
+                }
+            }
+
+            assert_eq!(records, ids, "{:?}", selectors);
+            assert_eq!(
+                XmlDataSource::count_records(&path, selectors).await?,
+                ids.len() as u64
+            );
+        }
+
+        assert!(matches!(
+            XmlDataSource::new(
+                &path,
+                &fields,
+                &["Root/Item", "Root/*/Item"],
+                None,
+                XsdValidation::Document,
+                false,
+                None,
+                &UnknownFieldPolicy::Error
+            )
+            .await,
+            Err(crate::xml_data_source::CreateXmlDataSourceError::InvalidSelector(_))
+        ));
+
+        tokio::fs::remove_file(&path).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn apply_unknown_field_policy() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_unknown_fields_{}.xml",
+            std::process::id()
+        ));
+
+        tokio::fs::write(
+            &path,
+            r#"<Orders><Order><Id>1</Id><Vendor><Code>V</Code></Vendor><Flag/></Order></Orders>"#,
+        )
+        .await?;
+
+        let fields: Vec<Field> = serde_json::from_value(serde_json::json!([{ "name": "Id" }]))?;
+        let read = |unknown_fields: UnknownFieldPolicy| {
+            let path = &path;
+            let fields = &fields;
+
+            async move {
+                XmlDataSource::new(
+                    path,
+                    fields,
+                    &["Orders/Order"],
+                    None,
+                    XsdValidation::Document,
+                    false,
+                    None,
+                    &unknown_fields,
+                )
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+            }
+        };
+
+        assert!(matches!(
+            read(UnknownFieldPolicy::Error).await,
+            Err(XmlReadRecordError {
+                source: XmlReadRecordErrorKind::UnknownField(field),
+                ..
+            }) if field == "Vendor"
+        ));
+
+        let records = read(UnknownFieldPolicy::Ignore).await?;
+
+        assert_eq!(records[0].get("Id"), Some("1"));
+        assert_eq!(records[0].get("Extra"), None);
+
+        let records = read(UnknownFieldPolicy::Collect("Extra".to_owned())).await?;
+
+        assert_eq!(records[0].get("Id"), Some("1"));
+        assert_eq!(
+            records[0].get("Extra"),
+            Some("<Vendor><Code>V</Code></Vendor><Flag/>")
+        );
+
+        tokio::fs::remove_file(&path).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_nested_field_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_nested_{}.xml",
+            std::process::id()
+        ));
+
+        tokio::fs::write(
+            &path,
+            r#"<Customers>
+                <Customer>
+                    <Name>Ada</Name>
+                    <Address><City>London</City><Lines><Line>1</Line></Lines></Address>
+                </Customer>
+                <Customer>
+                    <Address><Street>Main</Street></Address>
+                </Customer>
+            </Customers>"#,
+        )
+        .await?;
+
+        let fields: Vec<Field> = serde_json::from_value(serde_json::json!([
+            { "name": "Name" },
+            { "name": "Address/City" },
+            { "name": "Address/Lines" },
+        ]))?;
+
+        let mut xml_data_source = XmlDataSource::new(
+            &path,
+            &fields,
+            &["Customers/Customer"],
+            None,
+            XsdValidation::Document,
+            false,
+            None,
+            &UnknownFieldPolicy::Error,
+        )
+        .await?;
+
+        let record = xml_data_source.try_next().await?.expect("record");
+
+        assert_eq!(record.get("Name"), Some("Ada"));
+        assert_eq!(record.get("Address/City"), Some("London"));
+        assert_eq!(record.get("Address/Lines"), Some("<Line>1</Line>"));
+
+        assert!(matches!(
+            xml_data_source.try_next().await,
+            Err(XmlReadRecordError {
+                source: XmlReadRecordErrorKind::UnknownField(field),
+                ..
+            }) if field == "Address/Street"
+        ));
+
+        tokio::fs::remove_file(&path).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_records_violating_xsd() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "sql_bulk_import_profile_streaming_xsd_{}.xml",
+            std::process::id()
+        ));
+        let xsd_path = path.with_extension("xsd");
+
+        tokio::fs::write(
+            &xsd_path,
+            r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+                <xs:element name="Currencies">
+                    <xs:complexType>
+                        <xs:sequence>
+                            <xs:element name="Currency" maxOccurs="unbounded">
+                                <xs:complexType>
+                                    <xs:sequence>
+                                        <xs:element name="Code" type="xs:string"/>
+                                        <xs:element name="Digits" type="xs:int"/>
+                                    </xs:sequence>
+                                    <xs:attribute name="id" type="xs:int" use="required"/>
+                                </xs:complexType>
+                            </xs:element>
+                        </xs:sequence>
+                    </xs:complexType>
+                </xs:element>
+            </xs:schema>"#,
+        )
+        .await?;
+
+        tokio::fs::write(
+            &path,
+            r#"<Currencies>
+                <Currency id="1"><Code>GBP</Code><Digits>2</Digits></Currency>
+                <Currency><Code>EUR</Code><Digits>2</Digits></Currency>
+                <Currency id="3"><Code>USD</Code><Digits>two</Digits></Currency>
+                <Currency id="4"><Code>JPY</Code><Digits>0</Digits></Currency>
+            </Currencies>"#,
+        )
+        .await?;
+
+        let fields: Vec<Field> =
+            serde_json::from_value(serde_json::json!([{ "name": "Code" }, { "name": "Digits" }]))?;
+
+        let read_codes = async |recover| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            Ok(XmlDataSource::new(
+                &path,
+                &fields,
+                &["Currencies/Currency"],
+                Some(&xsd_path),
+                XsdValidation::Streaming,
+                recover,
+                None,
+                &UnknownFieldPolicy::Error,
+            )
+            .await?
+            .map_ok(|record| record.get("Code").unwrap_or_default().to_owned())
+            .try_collect()
+            .await?)
+        };
+
+        assert_eq!(read_codes(true).await?, ["GBP", "JPY"]);
+        assert!(read_codes(false).await.is_err());
+
+        tokio::fs::remove_file(&path).await?;
+        tokio::fs::remove_file(&xsd_path).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2044 @@
+//! Validation of XML data sources against a subset of XML Schema (XSD), failing to load schemas
+//! with unsupported constructs.
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+use memchr::memchr_iter;
+use quick_xml::Reader;
+use quick_xml::escape::{resolve_predefined_entity, unescape};
+use quick_xml::events::attributes::AttrError;
+use quick_xml::events::{BytesStart, Event};
+use regex::Regex;
+use rustc_hash::{FxBuildHasher as BuildHasher, FxHashMap as HashMap};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::Utf8Error;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncRead, BufReader};
+
+const MAX_VIOLATIONS: usize = 20;
+
+#[derive(Debug, Error)]
+pub enum LoadXsdError {
+    #[error("could not read XSD file")]
+    ReadError(#[from] std::io::Error),
+    #[error("could not parse XSD")]
+    XmlError(#[from] quick_xml::Error),
+    #[error("could not parse XSD attribute")]
+    AttributeError(#[from] AttrError),
+    #[error("could not interpret XSD as UTF-8")]
+    Utf8Error(#[from] Utf8Error),
+    #[error("could not unescape XSD attribute")]
+    EscapeError(#[from] quick_xml::escape::EscapeError),
+    #[error("root element is not a schema")]
+    NotSchema,
+    #[error("unknown type '{0}'")]
+    UnknownType(String),
+    #[error("element is missing a name or ref")]
+    UnnamedElement,
+    #[error("invalid occurrence bound '{0}'")]
+    InvalidOccurs(String),
+    #[error("invalid value '{1}' of facet '{0}'")]
+    InvalidFacet(String, String),
+    #[error("invalid pattern '{0}'")]
+    InvalidPattern(String, #[source] regex::Error),
+    #[error("unsupported XSD construct '{0}'")]
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct XsdViolation {
+    line_number: u64,
+    message: String,
+}
+
+impl Display for XsdViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line_number, self.message)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("document does not conform to XSD:\n{}", .violations.iter().join("\n"))]
+pub struct XsdValidationError {
+    violations: Vec<XsdViolation>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct TypeId(usize);
+
+#[derive(Debug, Clone)]
+enum XsdType {
+    Any,
+    Simple(SimpleType),
+    Complex(ComplexType),
+}
+
+#[derive(Debug, Clone)]
+struct SimpleType {
+    base: BuiltinType,
+    enumeration: Vec<String>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    /// Patterns of each restriction the type derives from, each of which the value must match
+    patterns: Vec<Pattern>,
+    min: Option<Bound>,
+    max: Option<Bound>,
+    total_digits: Option<usize>,
+    fraction_digits: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+#[derive(Debug, Clone)]
+struct Bound {
+    value: String,
+    inclusive: bool,
+}
+
+impl Bound {
+    /// Whether the value is within the bound, rather than beyond it in the direction given.
+    fn admits(&self, base: BuiltinType, value: &str, beyond: Ordering) -> bool {
+        match base.compare(value, &self.value) {
+            Some(Ordering::Equal) => self.inclusive,
+            Some(ordering) => ordering != beyond,
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum BuiltinType {
+    String,
+    Integer { min: i128, max: i128 },
+    Decimal,
+    Float,
+    Boolean,
+    Date,
+    DateTime,
+    Time,
+}
+
+#[derive(Debug, Clone)]
+struct ComplexType {
+    attributes: Vec<AttributeDecl>,
+    content: Content,
+}
+
+#[derive(Debug, Clone)]
+enum Content {
+    Empty,
+    Simple(TypeId),
+    Elements { group: Group, mixed: bool },
+    Any,
+}
+
+#[derive(Debug, Clone)]
+struct AttributeDecl {
+    name: String,
+    required: bool,
+    type_id: TypeId,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum GroupKind {
+    Sequence,
+    Choice,
+    All,
+    Lenient,
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    kind: GroupKind,
+    particles: Vec<Particle>,
+    max: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    name: String,
+    namespace: Option<String>,
+    min: u32,
+    max: Option<u32>,
+    element_type: ElementType,
+}
+
+#[derive(Debug, Clone)]
+enum ElementType {
+    Typed(TypeId),
+    Ref(String),
+}
+
+#[derive(Debug)]
+struct XsdNode {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<XsdNode>,
+}
+
+impl XsdNode {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XsdNode> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&XsdNode> {
+        self.children(name).next()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct XsdSchema {
+    types: Vec<XsdType>,
+    elements: HashMap<String, TypeId>,
+    /// Namespace of the global elements, and of local elements qualified by the schema
+    target_namespace: Option<String>,
+}
+
+impl XsdSchema {
+    pub(crate) async fn load(path: &Path) -> Result<Self, LoadXsdError> {
+        let xsd = tokio::fs::read_to_string(path).await?;
+
+        Self::parse(&xsd)
+    }
+
+    fn parse(xsd: &str) -> Result<Self, LoadXsdError> {
+        let (root, schema_prefix) = parse_nodes(xsd)?;
+
+        if root.name != "schema" {
+            return Err(LoadXsdError::NotSchema);
+        }
+
+        if let Some(composition) = root.children.iter().find(|child| {
+            matches!(
+                child.name.as_str(),
+                "include" | "import" | "redefine" | "override"
+            )
+        }) {
+            return Err(LoadXsdError::Unsupported(composition.name.clone()));
+        }
+
+        let target_namespace = root.attribute("targetNamespace").map(str::to_owned);
+
+        let mut builder = SchemaBuilder {
+            schema_prefix,
+            target_namespace: target_namespace.clone(),
+            qualified: root.attribute("elementFormDefault") == Some("qualified"),
+            named_types: root
+                .children
+                .iter()
+                .filter(|child| matches!(child.name.as_str(), "complexType" | "simpleType"))
+                .filter_map(|child| Some((child.attribute("name")?.to_owned(), child)))
+                .collect(),
+            built_types: HashMap::default(),
+            types: Vec::new(),
+        };
+
+        let elements = root
+            .children("element")
+            .map(|element| {
+                let name = element
+                    .attribute("name")
+                    .ok_or(LoadXsdError::UnnamedElement)?;
+
+                Ok((name.to_owned(), builder.element_type(element)?))
+            })
+            .collect::<Result<_, LoadXsdError>>()?;
+
+        Ok(Self {
+            types: builder.types,
+            elements,
+            target_namespace,
+        })
+    }
+
+    fn get(&self, type_id: TypeId) -> &XsdType {
+        &self.types[type_id.0]
+    }
+
+    /// Validates the document read by the reader, collecting violations up to a limit.
+    pub(crate) async fn validate<R: AsyncRead + Unpin>(
+        self: Arc<Self>,
+        mut reader: Reader<BufReader<R>>,
+    ) -> Result<(), XsdValidationError> {
+        let mut validator = Validator::new(self);
+        let mut buffer = Vec::new();
+
+        while validator.violations.len() < MAX_VIOLATIONS {
+            buffer.clear();
+
+            match reader.read_event_into_async(&mut buffer).await {
+                Ok(Event::Eof) => break,
+                Ok(event) => validator.event(&event),
+                Err(err) => {
+                    validator.violation(format!("could not read XML: {}", err));
+                    break;
+                }
+            }
+        }
+
+        if validator.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(XsdValidationError {
+                violations: validator.violations,
+            })
+        }
+    }
+}
+
+/// Parses the XSD into a tree of nodes, returning the root node and the prefix of the XML Schema
+/// namespace.
+fn parse_nodes(xsd: &str) -> Result<(XsdNode, Option<String>), LoadXsdError> {
+    fn node(start: &BytesStart) -> Result<XsdNode, LoadXsdError> {
+        Ok(XsdNode {
+            name: str::from_utf8(start.local_name().as_ref())?.to_owned(),
+            attributes: start
+                .attributes()
+                .map(|attribute| {
+                    let attribute = attribute?;
+
+                    Ok((
+                        str::from_utf8(attribute.key.as_ref())?.to_owned(),
+                        unescape(str::from_utf8(&attribute.value)?)?.into_owned(),
+                    ))
+                })
+                .collect::<Result<_, LoadXsdError>>()?,
+            children: Vec::new(),
+        })
+    }
+
+    let mut reader = Reader::from_str(xsd);
+    let mut stack: Vec<XsdNode> = Vec::new();
+    let mut schema_prefix = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                if stack.is_empty() {
+                    schema_prefix = start
+                        .name()
+                        .prefix()
+                        .map(|prefix| str::from_utf8(prefix.as_ref()).map(str::to_owned))
+                        .transpose()?;
+                }
+
+                stack.push(node(&start)?);
+            }
+            Event::Empty(empty) => {
+                let node = node(&empty)?;
+
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => return Ok((node, schema_prefix)),
+                }
+            }
+            Event::End(_) => {
+                let node = stack.pop().ok_or(LoadXsdError::NotSchema)?;
+
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => return Ok((node, schema_prefix)),
+                }
+            }
+            Event::Eof => return Err(LoadXsdError::NotSchema),
+            _ => {}
+        }
+    }
+}
+
+struct SchemaBuilder<'a> {
+    schema_prefix: Option<String>,
+    target_namespace: Option<String>,
+    /// Whether local elements are in the target namespace unless their `form` is unqualified
+    qualified: bool,
+    named_types: HashMap<String, &'a XsdNode>,
+    built_types: HashMap<String, TypeId>,
+    types: Vec<XsdType>,
+}
+
+impl<'a> SchemaBuilder<'a> {
+    fn push(&mut self, xsd_type: XsdType) -> TypeId {
+        self.types.push(xsd_type);
+
+        TypeId(self.types.len() - 1)
+    }
+
+    fn element_type(&mut self, element: &'a XsdNode) -> Result<TypeId, LoadXsdError> {
+        if let Some(type_name) = element.attribute("type") {
+            self.named_type(type_name)
+        } else if let Some(complex_type) = element.child("complexType") {
+            let complex_type = self.complex_type(complex_type)?;
+
+            Ok(self.push(XsdType::Complex(complex_type)))
+        } else if let Some(simple_type) = element.child("simpleType") {
+            let simple_type = self.simple_type(simple_type)?;
+
+            Ok(self.push(XsdType::Simple(simple_type)))
+        } else {
+            Ok(self.push(XsdType::Any))
+        }
+    }
+
+    fn named_type(&mut self, qualified_name: &str) -> Result<TypeId, LoadXsdError> {
+        let (prefix, name) = match qualified_name.split_once(':') {
+            Some((prefix, name)) => (Some(prefix), name),
+            None => (None, qualified_name),
+        };
+
+        if prefix == self.schema_prefix.as_deref()
+            && let Some(builtin_type) = BuiltinType::from_name(name)
+        {
+            return Ok(self.push(XsdType::Simple(SimpleType::new(builtin_type))));
+        }
+
+        if let Some(&type_id) = self.built_types.get(name) {
+            return Ok(type_id);
+        }
+
+        let node = *self
+            .named_types
+            .get(name)
+            .ok_or_else(|| LoadXsdError::UnknownType(qualified_name.to_owned()))?;
+
+        // Reserve the type before building it, such that recursive types resolve.
+        let type_id = self.push(XsdType::Any);
+
+        self.built_types.insert(name.to_owned(), type_id);
+
+        self.types[type_id.0] = match node.name.as_str() {
+            "complexType" => XsdType::Complex(self.complex_type(node)?),
+            _ => XsdType::Simple(self.simple_type(node)?),
+        };
+
+        Ok(type_id)
+    }
+
+    fn simple_type(&mut self, node: &'a XsdNode) -> Result<SimpleType, LoadXsdError> {
+        let Some(restriction) = node.child("restriction") else {
+            let derivation = node
+                .children
+                .iter()
+                .find(|child| child.name != "annotation")
+                .map_or("simpleType", |child| child.name.as_str());
+
+            return Err(LoadXsdError::Unsupported(derivation.to_owned()));
+        };
+
+        let mut simple_type = match restriction.attribute("base") {
+            Some(base_name) => {
+                let base = self.named_type(base_name)?;
+
+                match self.types[base.0].clone() {
+                    XsdType::Simple(simple_type) => simple_type,
+                    _ => return Err(LoadXsdError::UnknownType(base_name.to_owned())),
+                }
+            }
+            None => match restriction.child("simpleType") {
+                Some(simple_type) => self.simple_type(simple_type)?,
+                None => return Err(LoadXsdError::Unsupported("restriction".to_owned())),
+            },
+        };
+
+        if let Some(facet) = restriction.children.iter().find(|child| {
+            !matches!(
+                child.name.as_str(),
+                "annotation"
+                    | "simpleType"
+                    | "enumeration"
+                    | "length"
+                    | "minLength"
+                    | "maxLength"
+                    | "pattern"
+                    | "minInclusive"
+                    | "maxInclusive"
+                    | "minExclusive"
+                    | "maxExclusive"
+                    | "totalDigits"
+                    | "fractionDigits"
+                    | "whiteSpace"
+            )
+        }) {
+            return Err(LoadXsdError::Unsupported(facet.name.clone()));
+        }
+
+        let enumeration = restriction
+            .children("enumeration")
+            .filter_map(|enumeration| enumeration.attribute("value"))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        if !enumeration.is_empty() {
+            simple_type.enumeration = enumeration;
+        }
+
+        let facet_value = |name: &str| {
+            restriction
+                .child(name)
+                .map(|facet| facet.attribute("value").unwrap_or_default())
+        };
+
+        let facet = |name: &str| {
+            facet_value(name)
+                .map(|value| {
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| LoadXsdError::InvalidFacet(name.to_owned(), value.to_owned()))
+                })
+                .transpose()
+        };
+
+        if let Some(length) = facet("length")? {
+            simple_type.min_length = Some(length);
+            simple_type.max_length = Some(length);
+        }
+
+        simple_type.min_length = facet("minLength")?.or(simple_type.min_length);
+        simple_type.max_length = facet("maxLength")?.or(simple_type.max_length);
+
+        // patterns of the same restriction are alternatives, whereas those of each restriction
+        // derived from must all match
+        let patterns = restriction
+            .children("pattern")
+            .filter_map(|pattern| pattern.attribute("value"))
+            .collect::<Vec<_>>();
+
+        if !patterns.is_empty() {
+            let source = patterns.join("|");
+            let regex = Regex::new(&format!(
+                "^(?:{})$",
+                patterns
+                    .iter()
+                    .map(|pattern| translate_pattern(pattern))
+                    .join("|")
+            ))
+            .map_err(|err| LoadXsdError::InvalidPattern(source.clone(), err))?;
+
+            simple_type.patterns.push(Pattern { source, regex });
+        }
+
+        let bound = |name: &str, inclusive: bool| -> Result<Option<Bound>, LoadXsdError> {
+            let Some(value) = facet_value(name) else {
+                return Ok(None);
+            };
+
+            if !simple_type.base.ordered() {
+                return Err(LoadXsdError::Unsupported(name.to_owned()));
+            }
+
+            simple_type
+                .base
+                .validate(value)
+                .map_err(|_| LoadXsdError::InvalidFacet(name.to_owned(), value.to_owned()))?;
+
+            Ok(Some(Bound {
+                value: value.trim().to_owned(),
+                inclusive,
+            }))
+        };
+
+        let min = bound("minInclusive", true)?.or(bound("minExclusive", false)?);
+        let max = bound("maxInclusive", true)?.or(bound("maxExclusive", false)?);
+
+        simple_type.min = min.or(simple_type.min);
+        simple_type.max = max.or(simple_type.max);
+
+        for (name, digits) in [
+            ("totalDigits", &mut simple_type.total_digits),
+            ("fractionDigits", &mut simple_type.fraction_digits),
+        ] {
+            if let Some(value) = facet(name)? {
+                if !matches!(
+                    simple_type.base,
+                    BuiltinType::Integer { .. } | BuiltinType::Decimal
+                ) {
+                    return Err(LoadXsdError::Unsupported(name.to_owned()));
+                }
+
+                *digits = Some(value);
+            }
+        }
+
+        Ok(simple_type)
+    }
+
+    fn complex_type(&mut self, node: &'a XsdNode) -> Result<ComplexType, LoadXsdError> {
+        let mixed = node.attribute("mixed") == Some("true");
+
+        let mut complex_type = if let Some(simple_content) = node.child("simpleContent") {
+            let derivation = simple_content
+                .child("extension")
+                .or_else(|| simple_content.child("restriction"));
+
+            let content_type = match derivation.and_then(|derivation| derivation.attribute("base"))
+            {
+                Some(base) => self.named_type(base)?,
+                None => self.push(XsdType::Any),
+            };
+
+            let content = match &self.types[content_type.0] {
+                XsdType::Complex(base) => base.content.clone(),
+                _ => Content::Simple(content_type),
+            };
+
+            ComplexType {
+                attributes: Vec::new(),
+                content,
+            }
+        } else if let Some(complex_content) = node.child("complexContent") {
+            let Some(extension) = complex_content.child("extension") else {
+                return Err(LoadXsdError::Unsupported(
+                    "complexContent restriction".to_owned(),
+                ));
+            };
+
+            let mut base = match extension.attribute("base") {
+                Some(base) => {
+                    let base = self.named_type(base)?;
+
+                    match &self.types[base.0] {
+                        XsdType::Complex(base) => base.clone(),
+                        _ => ComplexType {
+                            attributes: Vec::new(),
+                            content: Content::Any,
+                        },
+                    }
+                }
+                None => ComplexType {
+                    attributes: Vec::new(),
+                    content: Content::Empty,
+                },
+            };
+
+            let extension_content = self.content(extension, mixed)?;
+
+            base.attributes.extend(self.attributes(extension)?);
+            base.content = match (base.content, extension_content) {
+                (Content::Empty, content) | (content, Content::Empty) => content,
+                (
+                    Content::Elements {
+                        group: mut base_group,
+                        mixed: base_mixed,
+                    },
+                    Content::Elements { group, mixed },
+                ) => {
+                    if base_group.kind != GroupKind::Sequence || group.kind != GroupKind::Sequence {
+                        base_group.kind = GroupKind::Lenient;
+                    }
+
+                    base_group.particles.extend(group.particles);
+
+                    Content::Elements {
+                        group: base_group,
+                        mixed: base_mixed || mixed,
+                    }
+                }
+                _ => Content::Any,
+            };
+
+            base
+        } else {
+            ComplexType {
+                attributes: Vec::new(),
+                content: self.content(node, mixed)?,
+            }
+        };
+
+        complex_type.attributes.extend(self.attributes(node)?);
+
+        Ok(complex_type)
+    }
+
+    fn content(&mut self, node: &'a XsdNode, mixed: bool) -> Result<Content, LoadXsdError> {
+        let Some(group_node) = node
+            .children
+            .iter()
+            .find(|child| matches!(child.name.as_str(), "sequence" | "choice" | "all"))
+        else {
+            if let Some(particle) = node
+                .children
+                .iter()
+                .find(|child| matches!(child.name.as_str(), "group" | "any"))
+            {
+                return Err(LoadXsdError::Unsupported(particle.name.clone()));
+            }
+
+            return Ok(Content::Empty);
+        };
+
+        let mut kind = match group_node.name.as_str() {
+            "sequence" => GroupKind::Sequence,
+            "choice" => GroupKind::Choice,
+            _ => GroupKind::All,
+        };
+
+        let mut particles = Vec::new();
+
+        self.particles(group_node, &mut particles, &mut kind)?;
+
+        Ok(Content::Elements {
+            group: Group {
+                kind,
+                particles,
+                max: max_occurs(group_node.attribute("maxOccurs"))?,
+            },
+            mixed,
+        })
+    }
+
+    fn particles(
+        &mut self,
+        group_node: &'a XsdNode,
+        particles: &mut Vec<Particle>,
+        kind: &mut GroupKind,
+    ) -> Result<(), LoadXsdError> {
+        for child in &group_node.children {
+            match child.name.as_str() {
+                "element" => {
+                    let min = min_occurs(child.attribute("minOccurs"))?;
+                    let max = max_occurs(child.attribute("maxOccurs"))?;
+
+                    let (name, namespace, element_type) =
+                        match (child.attribute("name"), child.attribute("ref")) {
+                            (Some(name), _) => {
+                                let qualified = match child.attribute("form") {
+                                    Some(form) => form == "qualified",
+                                    None => self.qualified,
+                                };
+
+                                (
+                                    name.to_owned(),
+                                    self.target_namespace.clone().filter(|_| qualified),
+                                    ElementType::Typed(self.element_type(child)?),
+                                )
+                            }
+                            // referenced elements are global, so in the target namespace
+                            (None, Some(reference)) => {
+                                let reference = reference
+                                    .split_once(':')
+                                    .map_or(reference, |(_, name)| name);
+
+                                (
+                                    reference.to_owned(),
+                                    self.target_namespace.clone(),
+                                    ElementType::Ref(reference.to_owned()),
+                                )
+                            }
+                            (None, None) => return Err(LoadXsdError::UnnamedElement),
+                        };
+
+                    particles.push(Particle {
+                        name,
+                        namespace,
+                        min,
+                        max,
+                        element_type,
+                    });
+                }
+                "sequence" | "choice" | "all" => {
+                    *kind = GroupKind::Lenient;
+
+                    self.particles(child, particles, kind)?;
+                }
+                "any" | "group" => {
+                    return Err(LoadXsdError::Unsupported(child.name.clone()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn attributes(&mut self, node: &'a XsdNode) -> Result<Vec<AttributeDecl>, LoadXsdError> {
+        let mut attribute_decls = Vec::new();
+
+        if node.child("attributeGroup").is_some() {
+            return Err(LoadXsdError::Unsupported("attributeGroup".to_owned()));
+        }
+
+        for attribute in node.children("attribute") {
+            // undeclared attributes are allowed, so only required references are unsupported
+            let Some(name) = attribute.attribute("name") else {
+                if attribute.attribute("use") == Some("required") {
+                    return Err(LoadXsdError::Unsupported("attribute ref".to_owned()));
+                }
+
+                continue;
+            };
+
+            let type_id = match (attribute.attribute("type"), attribute.child("simpleType")) {
+                (Some(type_name), _) => self.named_type(type_name)?,
+                (None, Some(simple_type)) => {
+                    let simple_type = self.simple_type(simple_type)?;
+
+                    self.push(XsdType::Simple(simple_type))
+                }
+                (None, None) => self.push(XsdType::Any),
+            };
+
+            attribute_decls.push(AttributeDecl {
+                name: name.to_owned(),
+                required: attribute.attribute("use") == Some("required"),
+                type_id,
+            });
+        }
+
+        Ok(attribute_decls)
+    }
+}
+
+fn min_occurs(value: Option<&str>) -> Result<u32, LoadXsdError> {
+    value.map_or(Ok(1), |value| {
+        value
+            .parse::<u32>()
+            .map_err(|_| LoadXsdError::InvalidOccurs(value.to_owned()))
+    })
+}
+
+/// Parses a maximum occurrence bound, with `None` representing unbounded.
+fn max_occurs(value: Option<&str>) -> Result<Option<u32>, LoadXsdError> {
+    match value {
+        Some("unbounded") => Ok(None),
+        value => min_occurs(value).map(Some),
+    }
+}
+
+/// Translates an XSD regular expression, implicitly anchored to the whole value, into a regex.
+fn translate_pattern(pattern: &str) -> String {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    let mut class_depth = 0;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                translated.push(char);
+                translated.extend(chars.next());
+            }
+            '[' => {
+                class_depth += 1;
+                translated.push(char);
+            }
+            ']' if class_depth > 0 => {
+                class_depth -= 1;
+                translated.push(char);
+            }
+            '-' if class_depth > 0 && chars.peek() == Some(&'[') => translated.push_str("--"),
+            '^' | '$' if class_depth == 0 => {
+                translated.push('\\');
+                translated.push(char);
+            }
+            _ => translated.push(char),
+        }
+    }
+
+    translated
+}
+
+/// Compares decimal values exactly, such that bounds beyond the precision of a float hold.
+fn compare_decimal(a: &str, b: &str) -> Ordering {
+    let (a_negative, a_integer, a_fraction) = decimal_parts(a);
+    let (b_negative, b_integer, b_fraction) = decimal_parts(b);
+
+    let magnitude = a_integer
+        .len()
+        .cmp(&b_integer.len())
+        .then_with(|| a_integer.cmp(b_integer))
+        .then_with(|| a_fraction.cmp(b_fraction));
+
+    match (a_negative, b_negative) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => magnitude,
+        (true, true) => magnitude.reverse(),
+    }
+}
+
+/// Sign, significant integer digits and significant fraction digits of a decimal value.
+fn decimal_parts(value: &str) -> (bool, &str, &str) {
+    let value = value.trim();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let integer = integer.trim_start_matches('0');
+    let fraction = fraction.trim_end_matches('0');
+
+    (
+        negative && !(integer.is_empty() && fraction.is_empty()),
+        integer,
+        fraction,
+    )
+}
+
+impl BuiltinType {
+    fn from_name(name: &str) -> Option<Self> {
+        let integer = |min, max| Some(BuiltinType::Integer { min, max });
+
+        match name {
+            "string" | "normalizedString" | "token" | "anyURI" | "QName" | "ID" | "IDREF"
+            | "NCName" | "Name" | "NMTOKEN" | "language" | "anySimpleType" | "base64Binary"
+            | "hexBinary" | "duration" => Some(BuiltinType::String),
+            "integer" => integer(i128::MIN, i128::MAX),
+            "long" => integer(i64::MIN as i128, i64::MAX as i128),
+            "int" => integer(i32::MIN as i128, i32::MAX as i128),
+            "short" => integer(i16::MIN as i128, i16::MAX as i128),
+            "byte" => integer(i8::MIN as i128, i8::MAX as i128),
+            "nonNegativeInteger" => integer(0, i128::MAX),
+            "positiveInteger" => integer(1, i128::MAX),
+            "nonPositiveInteger" => integer(i128::MIN, 0),
+            "negativeInteger" => integer(i128::MIN, -1),
+            "unsignedLong" => integer(0, u64::MAX as i128),
+            "unsignedInt" => integer(0, u32::MAX as i128),
+            "unsignedShort" => integer(0, u16::MAX as i128),
+            "unsignedByte" => integer(0, u8::MAX as i128),
+            "decimal" => Some(BuiltinType::Decimal),
+            "float" | "double" => Some(BuiltinType::Float),
+            "boolean" => Some(BuiltinType::Boolean),
+            "date" => Some(BuiltinType::Date),
+            "dateTime" => Some(BuiltinType::DateTime),
+            "time" => Some(BuiltinType::Time),
+            _ => None,
+        }
+    }
+
+    /// Whether the range facets apply to values of the type.
+    fn ordered(self) -> bool {
+        matches!(
+            self,
+            BuiltinType::Integer { .. } | BuiltinType::Decimal | BuiltinType::Float
+        )
+    }
+
+    /// Compares valid values of an ordered type, `None` if either is not a number.
+    fn compare(self, a: &str, b: &str) -> Option<Ordering> {
+        match self {
+            BuiltinType::Integer { .. } | BuiltinType::Decimal => Some(compare_decimal(a, b)),
+            BuiltinType::Float => a
+                .trim()
+                .parse::<f64>()
+                .ok()?
+                .partial_cmp(&b.trim().parse::<f64>().ok()?),
+            _ => None,
+        }
+    }
+
+    fn validate(self, value: &str) -> Result<(), &'static str> {
+        let value = value.trim();
+
+        let valid = match self {
+            BuiltinType::String => true,
+            BuiltinType::Integer { min, max } => value
+                .strip_prefix('+')
+                .unwrap_or(value)
+                .parse::<i128>()
+                .is_ok_and(|value| (min..=max).contains(&value)),
+            BuiltinType::Decimal => {
+                let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+
+                digits.chars().filter(|&c| c == '.').count() <= 1
+                    && digits.chars().any(|c| c.is_ascii_digit())
+                    && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+            }
+            BuiltinType::Float => {
+                matches!(value, "INF" | "+INF" | "-INF" | "NaN")
+                    || (value.parse::<f64>().is_ok() && !value.contains(['i', 'I', 'n', 'N']))
+            }
+            BuiltinType::Boolean => matches!(value, "true" | "false" | "1" | "0"),
+            BuiltinType::Date => is_date(strip_timezone(value)),
+            BuiltinType::DateTime => strip_timezone(value)
+                .split_once('T')
+                .is_some_and(|(date, time)| is_date(date) && is_time(time)),
+            BuiltinType::Time => is_time(strip_timezone(value)),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(match self {
+                BuiltinType::String => "invalid string",
+                BuiltinType::Integer { .. } => "invalid or out of range integer",
+                BuiltinType::Decimal => "invalid decimal",
+                BuiltinType::Float => "invalid floating point number",
+                BuiltinType::Boolean => "invalid boolean",
+                BuiltinType::Date => "invalid date",
+                BuiltinType::DateTime => "invalid date time",
+                BuiltinType::Time => "invalid time",
+            })
+        }
+    }
+}
+
+fn describe_namespace(namespace: Option<&str>) -> String {
+    match namespace {
+        Some(namespace) => format!("namespace '{}'", namespace),
+        None => "no namespace".to_owned(),
+    }
+}
+
+fn strip_timezone(value: &str) -> &str {
+    if let Some(value) = value.strip_suffix('Z') {
+        return value;
+    }
+
+    match value
+        .len()
+        .checked_sub(6)
+        .and_then(|index| value.get(index..))
+    {
+        Some(timezone)
+            if timezone.starts_with(['+', '-'])
+                && timezone.as_bytes()[3] == b':'
+                && is_digits(&timezone[1..3], 2)
+                && is_digits(&timezone[4..], 2) =>
+        {
+            &value[..value.len() - 6]
+        }
+        _ => value,
+    }
+}
+
+fn is_digits(value: &str, length: usize) -> bool {
+    value.len() == length && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+fn is_date(value: &str) -> bool {
+    let mut parts = value.rsplitn(3, '-');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(day), Some(month), Some(year)) => {
+            let year = year.strip_prefix('-').unwrap_or(year);
+
+            year.len() >= 4
+                && year.bytes().all(|byte| byte.is_ascii_digit())
+                && is_digits(month, 2)
+                && is_digits(day, 2)
+                && (1..=12).contains(&month.parse::<u8>().unwrap_or(0))
+                && (1..=31).contains(&day.parse::<u8>().unwrap_or(0))
+        }
+        _ => false,
+    }
+}
+
+fn is_time(value: &str) -> bool {
+    let (value, fraction) = match value.split_once('.') {
+        Some((value, fraction)) => (value, Some(fraction)),
+        None => (value, None),
+    };
+
+    let mut parts = value.split(':');
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(hours), Some(minutes), Some(seconds), None) => {
+            is_digits(hours, 2)
+                && is_digits(minutes, 2)
+                && is_digits(seconds, 2)
+                && hours.parse::<u8>().is_ok_and(|hours| hours <= 24)
+                && minutes.parse::<u8>().is_ok_and(|minutes| minutes <= 59)
+                && seconds.parse::<u8>().is_ok_and(|seconds| seconds <= 60)
+                && fraction.is_none_or(|fraction| {
+                    !fraction.is_empty() && fraction.bytes().all(|byte| byte.is_ascii_digit())
+                })
+        }
+        _ => false,
+    }
+}
+
+impl SimpleType {
+    fn new(base: BuiltinType) -> Self {
+        SimpleType {
+            base,
+            enumeration: Vec::new(),
+            min_length: None,
+            max_length: None,
+            patterns: Vec::new(),
+            min: None,
+            max: None,
+            total_digits: None,
+            fraction_digits: None,
+        }
+    }
+
+    fn validate(&self, value: &str) -> Result<(), String> {
+        self.base.validate(value).map_err(str::to_owned)?;
+
+        let length = value.chars().count();
+
+        if !self.enumeration.is_empty() && !self.enumeration.iter().any(|e| e == value.trim()) {
+            return Err(format!(
+                "value not one of [{}]",
+                self.enumeration.iter().join(", ")
+            ));
+        }
+
+        if let Some(min_length) = self.min_length
+            && length < min_length
+        {
+            return Err(format!("value shorter than {} characters", min_length));
+        }
+
+        if let Some(max_length) = self.max_length
+            && length > max_length
+        {
+            return Err(format!("value longer than {} characters", max_length));
+        }
+
+        if let Some(pattern) = self
+            .patterns
+            .iter()
+            .find(|pattern| !pattern.regex.is_match(value.trim()))
+        {
+            return Err(format!("value does not match pattern '{}'", pattern.source));
+        }
+
+        // whether the value is within the bound, rather than beyond it in the outside direction
+        if let Some(min) = &self.min
+            && !min.admits(self.base, value, Ordering::Less)
+        {
+            return Err(format!(
+                "value {} {}",
+                if min.inclusive {
+                    "less than"
+                } else {
+                    "not greater than"
+                },
+                min.value
+            ));
+        }
+
+        if let Some(max) = &self.max
+            && !max.admits(self.base, value, Ordering::Greater)
+        {
+            return Err(format!(
+                "value {} {}",
+                if max.inclusive {
+                    "greater than"
+                } else {
+                    "not less than"
+                },
+                max.value
+            ));
+        }
+
+        let (_, integer, fraction) = decimal_parts(value);
+
+        if let Some(total_digits) = self.total_digits
+            && integer.len() + fraction.len() > total_digits
+        {
+            return Err(format!("value has more than {} digits", total_digits));
+        }
+
+        if let Some(fraction_digits) = self.fraction_digits
+            && fraction.len() > fraction_digits
+        {
+            return Err(format!(
+                "value has more than {} fraction digits",
+                fraction_digits
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    line_number: u64,
+    type_id: Option<TypeId>,
+    text: String,
+    text_reported: bool,
+    counts: Vec<u32>,
+    position: usize,
+    /// Namespaces declared by the element, by prefix, the default namespace having no prefix
+    namespaces: Vec<(String, String)>,
+}
+
+/// Validator of a document against the schema, fed the events of the document as they are read.
+#[derive(Debug)]
+pub(crate) struct Validator {
+    schema: Arc<XsdSchema>,
+    frames: Vec<Frame>,
+    violations: Vec<XsdViolation>,
+    line_number: u64,
+}
+
+impl Validator {
+    pub(crate) fn new(schema: Arc<XsdSchema>) -> Self {
+        Validator {
+            schema,
+            frames: Vec::new(),
+            violations: Vec::new(),
+            line_number: 1,
+        }
+    }
+
+    /// Validates the next event of the document.
+    pub(crate) fn event(&mut self, event: &Event) {
+        let newlines = memchr_iter(b'\n', event).count() as u64;
+
+        match event {
+            Event::Start(start) => self.start(start),
+            Event::Empty(empty) => {
+                self.start(empty);
+                self.end();
+            }
+            Event::End(_) => self.end(),
+            Event::Text(text) => match str::from_utf8(text) {
+                Ok(text) => self.text(text),
+                Err(err) => self.violation(format!("invalid UTF-8: {}", err)),
+            },
+            Event::CData(cdata) => match str::from_utf8(cdata) {
+                Ok(cdata) => self.text(cdata),
+                Err(err) => self.violation(format!("invalid UTF-8: {}", err)),
+            },
+            Event::GeneralRef(general_ref) => match general_ref.resolve_char_ref() {
+                Ok(Some(char)) => self.text(char.encode_utf8(&mut [0; 4])),
+                _ => match general_ref.decode() {
+                    Ok(name) => match resolve_predefined_entity(&name) {
+                        Some(entity) => self.text(entity),
+                        None => self.violation(format!("unknown entity '{}'", name)),
+                    },
+                    Err(err) => self.violation(format!("invalid entity: {}", err)),
+                },
+            },
+            _ => {}
+        }
+
+        self.line_number += newlines;
+    }
+
+    /// Takes the violations found since they were last taken.
+    pub(crate) fn take_violations(&mut self) -> Vec<XsdViolation> {
+        std::mem::take(&mut self.violations)
+    }
+
+    fn violation(&mut self, message: String) {
+        self.violations.push(XsdViolation {
+            line_number: self.line_number,
+            message,
+        });
+    }
+
+    fn start(&mut self, start: &BytesStart) {
+        let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+        let schema = Arc::clone(&self.schema);
+        let namespaces = start
+            .attributes()
+            .filter_map(Result::ok)
+            .filter_map(|attribute| {
+                let prefix = match attribute.key.as_ref() {
+                    b"xmlns" => String::new(),
+                    key => String::from_utf8_lossy(key.strip_prefix(b"xmlns:")?).into_owned(),
+                };
+
+                Some((
+                    prefix,
+                    String::from_utf8_lossy(&attribute.value).into_owned(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let namespace = self.namespace(start, &namespaces);
+
+        // expected namespace of the element, if it was matched against the schema
+        let (type_id, expected_namespace) = match self.frames.last_mut() {
+            None => match schema.elements.get(&name) {
+                Some(&type_id) => (Some(type_id), Some(schema.target_namespace.as_deref())),
+                None => {
+                    self.violation(format!("unexpected root element '{}'", name));
+                    (None, None)
+                }
+            },
+            Some(parent) => match parent.type_id.map(|type_id| schema.get(type_id)) {
+                None | Some(XsdType::Any) => (None, None),
+                Some(XsdType::Complex(ComplexType {
+                    content: Content::Any,
+                    ..
+                })) => (None, None),
+                Some(XsdType::Complex(ComplexType {
+                    content: Content::Elements { group, .. },
+                    ..
+                })) => {
+                    let mut messages = Vec::new();
+                    let particle = Self::particle(parent, group, &name, &mut messages);
+
+                    messages
+                        .into_iter()
+                        .for_each(|message| self.violation(message));
+
+                    match particle {
+                        Ok(particle) => (
+                            match &particle.element_type {
+                                ElementType::Typed(type_id) => Some(*type_id),
+                                ElementType::Ref(reference) => {
+                                    schema.elements.get(reference).copied()
+                                }
+                            },
+                            Some(particle.namespace.as_deref()),
+                        ),
+                        Err(message) => {
+                            self.violation(message);
+                            (None, None)
+                        }
+                    }
+                }
+                Some(_) => {
+                    let message = format!(
+                        "element '{}' not allowed within element '{}'",
+                        name, parent.name
+                    );
+
+                    self.violation(message);
+                    (None, None)
+                }
+            },
+        };
+
+        if let Some(expected_namespace) = expected_namespace
+            && let Ok(namespace) = &namespace
+            && expected_namespace != namespace.as_deref()
+        {
+            self.violation(format!(
+                "element '{}' in {}, expected {}",
+                name,
+                describe_namespace(namespace.as_deref()),
+                describe_namespace(expected_namespace),
+            ));
+        }
+
+        if let Err(prefix) = namespace {
+            self.violation(format!(
+                "undeclared namespace prefix '{}' of element '{}'",
+                prefix, name
+            ));
+        }
+
+        let xsd_type = type_id.map(|type_id| schema.get(type_id));
+
+        if let Some(XsdType::Complex(complex_type)) = xsd_type {
+            self.attributes(start, &name, complex_type);
+        }
+
+        let counts = match xsd_type {
+            Some(XsdType::Complex(ComplexType {
+                content: Content::Elements { group, .. },
+                ..
+            })) => vec![0; group.particles.len()],
+            _ => Vec::new(),
+        };
+
+        self.frames.push(Frame {
+            name,
+            line_number: self.line_number,
+            type_id,
+            text: String::new(),
+            text_reported: false,
+            counts,
+            position: 0,
+            namespaces,
+        });
+    }
+
+    /// Namespace of the element, resolved from its prefix by the namespaces declared by the
+    /// element and its ancestors, or the undeclared prefix.
+    fn namespace(
+        &self,
+        start: &BytesStart,
+        namespaces: &[(String, String)],
+    ) -> Result<Option<String>, String> {
+        let prefix = start
+            .name()
+            .prefix()
+            .map(|prefix| String::from_utf8_lossy(prefix.as_ref()).into_owned())
+            .unwrap_or_default();
+
+        let namespace = namespaces
+            .iter()
+            .rev()
+            .chain(
+                self.frames
+                    .iter()
+                    .rev()
+                    .flat_map(|frame| frame.namespaces.iter().rev()),
+            )
+            .find(|(declared, _)| *declared == prefix)
+            .map(|(_, namespace)| namespace);
+
+        match namespace {
+            // an empty default namespace undeclares the default namespace
+            Some(namespace) => {
+                Ok(Some(namespace.to_owned()).filter(|namespace| !namespace.is_empty()))
+            }
+            None if prefix.is_empty() => Ok(None),
+            None => Err(prefix),
+        }
+    }
+
+    /// Matches a child element against the content model of its parent, pushing violations which
+    /// still allow it to be matched to `messages`.
+    fn particle<'g>(
+        parent: &mut Frame,
+        group: &'g Group,
+        name: &str,
+        messages: &mut Vec<String>,
+    ) -> Result<&'g Particle, String> {
+        let index_of = |from: usize| {
+            group.particles[from..]
+                .iter()
+                .position(|particle| particle.name == name)
+                .map(|index| index + from)
+        };
+
+        let index = match group.kind {
+            GroupKind::Sequence => match index_of(parent.position) {
+                Some(index) => {
+                    if let Some(missing) = (parent.position..index)
+                        .find(|&skipped| parent.counts[skipped] < group.particles[skipped].min)
+                    {
+                        messages.push(format!(
+                            "missing element '{}' before element '{}' within element '{}'",
+                            group.particles[missing].name, name, parent.name
+                        ));
+                    }
+
+                    index
+                }
+                None => match index_of(0) {
+                    Some(index) if group.max != Some(1) => {
+                        parent.counts.iter_mut().for_each(|count| *count = 0);
+                        index
+                    }
+                    Some(_) => {
+                        return Err(format!(
+                            "element '{}' out of order within element '{}'",
+                            name, parent.name
+                        ));
+                    }
+                    None => {
+                        return Err(format!(
+                            "unexpected element '{}' within element '{}'",
+                            name, parent.name
+                        ));
+                    }
+                },
+            },
+            GroupKind::Choice => match index_of(0) {
+                Some(index)
+                    if group.max == Some(1)
+                        && parent
+                            .counts
+                            .iter()
+                            .enumerate()
+                            .any(|(other, &count)| other != index && count > 0) =>
+                {
+                    return Err(format!(
+                        "element '{}' not allowed within element '{}', another choice was made",
+                        name, parent.name
+                    ));
+                }
+                Some(index) => index,
+                None => {
+                    return Err(format!(
+                        "unexpected element '{}' within element '{}'",
+                        name, parent.name
+                    ));
+                }
+            },
+            GroupKind::All | GroupKind::Lenient => index_of(0).ok_or_else(|| {
+                format!(
+                    "unexpected element '{}' within element '{}'",
+                    name, parent.name
+                )
+            })?,
+        };
+
+        let particle = &group.particles[index];
+
+        parent.position = index;
+        parent.counts[index] += 1;
+
+        if group.kind != GroupKind::Lenient
+            && particle
+                .max
+                .is_some_and(|max| parent.counts[index] > max && group.max == Some(1))
+        {
+            messages.push(format!(
+                "element '{}' occurs more than {} times within element '{}'",
+                name,
+                particle.max.unwrap_or_default(),
+                parent.name
+            ));
+        }
+
+        Ok(particle)
+    }
+
+    fn attributes(&mut self, start: &BytesStart, name: &str, complex_type: &ComplexType) {
+        let attributes = start
+            .attributes()
+            .filter_map(Result::ok)
+            .map(|attribute| {
+                (
+                    String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned(),
+                    String::from_utf8_lossy(&attribute.value).into_owned(),
+                )
+            })
+            .collect::<IndexMap<_, _, BuildHasher>>();
+
+        for attribute_decl in &complex_type.attributes {
+            match attributes.get(&attribute_decl.name) {
+                Some(value) => {
+                    if let XsdType::Simple(simple_type) = self.schema.get(attribute_decl.type_id)
+                        && let Err(message) = simple_type.validate(value)
+                    {
+                        self.violation(format!(
+                            "invalid value '{}' for attribute '{}' of element '{}': {}",
+                            value, attribute_decl.name, name, message
+                        ));
+                    }
+                }
+                None if attribute_decl.required => {
+                    self.violation(format!(
+                        "missing required attribute '{}' of element '{}'",
+                        attribute_decl.name, name
+                    ));
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let schema = Arc::clone(&self.schema);
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+
+        match frame.type_id.map(|type_id| schema.get(type_id)) {
+            Some(XsdType::Simple(_))
+            | Some(XsdType::Complex(ComplexType {
+                content: Content::Simple(_),
+                ..
+            })) => frame.text.push_str(text),
+            Some(XsdType::Complex(ComplexType {
+                content: Content::Empty | Content::Elements { mixed: false, .. },
+                ..
+            })) if !frame.text_reported && !text.trim().is_empty() => {
+                frame.text_reported = true;
+
+                let message = format!("text not allowed within element '{}'", frame.name);
+
+                self.violation(message);
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+
+        let schema = Arc::clone(&self.schema);
+        let xsd_type = frame.type_id.map(|type_id| schema.get(type_id));
+
+        let simple_type = match xsd_type {
+            Some(XsdType::Simple(simple_type)) => Some(simple_type),
+            Some(XsdType::Complex(ComplexType {
+                content: Content::Simple(type_id),
+                ..
+            })) => match schema.get(*type_id) {
+                XsdType::Simple(simple_type) => Some(simple_type),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(simple_type) = simple_type
+            && let Err(message) = simple_type.validate(&frame.text)
+        {
+            self.violation(format!(
+                "invalid value '{}' for element '{}': {}",
+                frame.text, frame.name, message
+            ));
+        }
+
+        if let Some(XsdType::Complex(ComplexType {
+            content: Content::Elements { group, .. },
+            ..
+        })) = xsd_type
+        {
+            let missing = match group.kind {
+                GroupKind::Sequence => (frame.position..group.particles.len())
+                    .find(|&index| frame.counts[index] < group.particles[index].min)
+                    .map(|index| group.particles[index].name.clone()),
+                GroupKind::Choice => (frame.counts.iter().all(|&count| count == 0)
+                    && !group.particles.is_empty()
+                    && group.particles.iter().all(|particle| particle.min > 0))
+                .then(|| {
+                    group
+                        .particles
+                        .iter()
+                        .map(|particle| particle.name.as_str())
+                        .join(" | ")
+                }),
+                GroupKind::All => (0..group.particles.len())
+                    .find(|&index| frame.counts[index] < group.particles[index].min)
+                    .map(|index| group.particles[index].name.clone()),
+                GroupKind::Lenient => None,
+            };
+
+            if let Some(missing) = missing {
+                self.violations.push(XsdViolation {
+                    line_number: frame.line_number,
+                    message: format!(
+                        "missing element '{}' within element '{}'",
+                        missing, frame.name
+                    ),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const XSD: &str = r#"
+        <xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+            <xs:simpleType name="CurrencyCode">
+                <xs:restriction base="xs:string">
+                    <xs:length value="3"/>
+                </xs:restriction>
+            </xs:simpleType>
+            <xs:element name="Currencies">
+                <xs:complexType>
+                    <xs:sequence>
+                        <xs:element name="Currency" minOccurs="0" maxOccurs="unbounded">
+                            <xs:complexType>
+                                <xs:sequence>
+                                    <xs:element name="Code" type="CurrencyCode"/>
+                                    <xs:element name="Name" type="xs:string"/>
+                                    <xs:element name="Digits" type="xs:int" minOccurs="0"/>
+                                </xs:sequence>
+                                <xs:attribute name="id" type="xs:int" use="required"/>
+                            </xs:complexType>
+                        </xs:element>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:element>
+        </xs:schema>
+    "#;
+
+    async fn validate(xml: &'static str) -> Result<(), XsdValidationError> {
+        validate_against(XSD, xml).await
+    }
+
+    async fn validate_against(xsd: &str, xml: &'static str) -> Result<(), XsdValidationError> {
+        let schema = XsdSchema::parse(xsd).unwrap();
+        let reader = Reader::from_reader(BufReader::new(Cursor::new(xml)));
+
+        Arc::new(schema).validate(reader).await
+    }
+
+    fn violations(result: Result<(), XsdValidationError>) -> Vec<(u64, String)> {
+        result
+            .unwrap_err()
+            .violations
+            .into_iter()
+            .map(|violation| (violation.line_number, violation.message))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn validate_conforming_document() {
+        let xml = indoc::indoc! {r#"
+            <Currencies>
+                <Currency id="1"><Code>GBP</Code><Name>Pound</Name><Digits>2</Digits></Currency>
+                <Currency id="2"><Code>EUR</Code><Name>Euro</Name></Currency>
+            </Currencies>
+        "#};
+
+        assert!(validate(xml).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn validate_reports_violations_with_line_numbers() {
+        let xml = indoc::indoc! {r#"
+            <Currencies>
+                <Currency id="1"><Code>GBP</Code><Name>Pound</Name><Digits>two</Digits></Currency>
+                <Currency><Code>EURO</Code><Name>Euro</Name></Currency>
+                <Currency id="3"><Name>Dollar</Name></Currency>
+                <Currency id="4"><Code>JPY</Code><Name>Yen</Name><Symbol>¥</Symbol></Currency>
+            </Currencies>
+        "#};
+
+        assert_eq!(
+            violations(validate(xml).await),
+            [
+                (
+                    2,
+                    "invalid value 'two' for element 'Digits': invalid or out of range integer"
+                        .to_owned()
+                ),
+                (
+                    3,
+                    "missing required attribute 'id' of element 'Currency'".to_owned()
+                ),
+                (
+                    3,
+                    "invalid value 'EURO' for element 'Code': value longer than 3 characters"
+                        .to_owned()
+                ),
+                (
+                    4,
+                    "missing element 'Code' before element 'Name' within element 'Currency'"
+                        .to_owned()
+                ),
+                (
+                    5,
+                    "unexpected element 'Symbol' within element 'Currency'".to_owned()
+                ),
+            ]
+        );
+    }
+
+    const FACETS_XSD: &str = r#"
+        <xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+            <xs:simpleType name="Percent">
+                <xs:restriction base="xs:decimal">
+                    <xs:minInclusive value="0"/>
+                    <xs:maxInclusive value="100"/>
+                </xs:restriction>
+            </xs:simpleType>
+            <xs:element name="Values">
+                <xs:complexType>
+                    <xs:choice maxOccurs="unbounded">
+                        <xs:element name="Code">
+                            <xs:simpleType>
+                                <xs:restriction base="xs:string">
+                                    <xs:pattern value="[A-Z]{2}\d"/>
+                                    <xs:pattern value="X+"/>
+                                </xs:restriction>
+                            </xs:simpleType>
+                        </xs:element>
+                        <xs:element name="Percent" type="Percent"/>
+                        <xs:element name="Rate">
+                            <xs:simpleType>
+                                <xs:restriction base="xs:float">
+                                    <xs:minExclusive value="0"/>
+                                    <xs:maxExclusive value="1"/>
+                                </xs:restriction>
+                            </xs:simpleType>
+                        </xs:element>
+                        <xs:element name="Amount">
+                            <xs:simpleType>
+                                <xs:restriction base="xs:decimal">
+                                    <xs:totalDigits value="5"/>
+                                    <xs:fractionDigits value="2"/>
+                                </xs:restriction>
+                            </xs:simpleType>
+                        </xs:element>
+                    </xs:choice>
+                </xs:complexType>
+            </xs:element>
+        </xs:schema>
+    "#;
+
+    #[tokio::test]
+    async fn validate_pattern_facet() {
+        let xml = indoc::indoc! {r#"
+            <Values>
+                <Code>GB1</Code>
+                <Code>XXX</Code>
+                <Code>gb1</Code>
+                <Code>GB12</Code>
+            </Values>
+        "#};
+
+        assert_eq!(
+            violations(validate_against(FACETS_XSD, xml).await),
+            [
+                (
+                    4,
+                    r"invalid value 'gb1' for element 'Code': value does not match pattern '[A-Z]{2}\d|X+'"
+                        .to_owned()
+                ),
+                (
+                    5,
+                    r"invalid value 'GB12' for element 'Code': value does not match pattern '[A-Z]{2}\d|X+'"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_inclusive_range_facets() {
+        let xml = indoc::indoc! {r#"
+            <Values>
+                <Percent>-0</Percent>
+                <Percent>100.00</Percent>
+                <Percent>100.01</Percent>
+                <Percent>-0.5</Percent>
+            </Values>
+        "#};
+
+        assert_eq!(
+            violations(validate_against(FACETS_XSD, xml).await),
+            [
+                (
+                    4,
+                    "invalid value '100.01' for element 'Percent': value greater than 100"
+                        .to_owned()
+                ),
+                (
+                    5,
+                    "invalid value '-0.5' for element 'Percent': value less than 0".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_exclusive_range_facets() {
+        let xml = indoc::indoc! {r#"
+            <Values>
+                <Rate>0.5</Rate>
+                <Rate>0</Rate>
+                <Rate>1E0</Rate>
+            </Values>
+        "#};
+
+        assert_eq!(
+            violations(validate_against(FACETS_XSD, xml).await),
+            [
+                (
+                    3,
+                    "invalid value '0' for element 'Rate': value not greater than 0".to_owned()
+                ),
+                (
+                    4,
+                    "invalid value '1E0' for element 'Rate': value not less than 1".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_digits_facets() {
+        let xml = indoc::indoc! {r#"
+            <Values>
+                <Amount>123.45</Amount>
+                <Amount>00123.450</Amount>
+                <Amount>1234.56</Amount>
+                <Amount>1.234</Amount>
+            </Values>
+        "#};
+
+        assert_eq!(
+            violations(validate_against(FACETS_XSD, xml).await),
+            [
+                (
+                    4,
+                    "invalid value '1234.56' for element 'Amount': value has more than 5 digits"
+                        .to_owned()
+                ),
+                (
+                    5,
+                    "invalid value '1.234' for element 'Amount': value has more than 2 fraction digits"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn translate_xsd_patterns() {
+        assert_eq!(translate_pattern(r"^\d+$"), r"\^\d+\$");
+        assert_eq!(translate_pattern(r"[^a-z]\[x"), r"[^a-z]\[x");
+        assert_eq!(translate_pattern("[a-z-[aeiou]]"), "[a-z--[aeiou]]");
+    }
+
+    #[test]
+    fn compare_decimals_exactly() {
+        assert_eq!(compare_decimal("10", "9.99"), Ordering::Greater);
+        assert_eq!(compare_decimal("-0", "0.000"), Ordering::Equal);
+        assert_eq!(compare_decimal("-1.5", "-1.25"), Ordering::Less);
+        assert_eq!(
+            compare_decimal("12345678901234567890.1", "12345678901234567890.05"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn reject_unsupported_constructs() {
+        let unsupported = |content: &str| {
+            let xsd = format!(
+                r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">{}</xs:schema>"#,
+                content
+            );
+
+            match XsdSchema::parse(&xsd) {
+                Err(LoadXsdError::Unsupported(construct)) => construct,
+                result => panic!("expected unsupported construct, got {:?}", result),
+            }
+        };
+
+        assert_eq!(
+            unsupported(r#"<xs:include schemaLocation="other.xsd"/>"#),
+            "include"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:simpleType><xs:list itemType="xs:int"/></xs:simpleType></xs:element>"#
+            ),
+            "list"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:simpleType><xs:union memberTypes="xs:int xs:date"/></xs:simpleType></xs:element>"#
+            ),
+            "union"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:simpleType><xs:restriction base="xs:string"><xs:assertion test="true()"/></xs:restriction></xs:simpleType></xs:element>"#
+            ),
+            "assertion"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:simpleType><xs:restriction base="xs:date"><xs:minInclusive value="2000-01-01"/></xs:restriction></xs:simpleType></xs:element>"#
+            ),
+            "minInclusive"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:simpleType><xs:restriction base="xs:string"><xs:totalDigits value="2"/></xs:restriction></xs:simpleType></xs:element>"#
+            ),
+            "totalDigits"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:complexType><xs:sequence><xs:any/></xs:sequence></xs:complexType></xs:element>"#
+            ),
+            "any"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:complexType><xs:group ref="B"/></xs:complexType></xs:element>"#
+            ),
+            "group"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:complexType><xs:complexContent><xs:restriction base="xs:anyType"/></xs:complexContent></xs:complexType></xs:element>"#
+            ),
+            "complexContent restriction"
+        );
+        assert_eq!(
+            unsupported(
+                r#"<xs:element name="A"><xs:complexType><xs:attributeGroup ref="B"/></xs:complexType></xs:element>"#
+            ),
+            "attributeGroup"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_facets() {
+        let xsd = |facet: &str| {
+            format!(
+                r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema"><xs:element name="A"><xs:simpleType><xs:restriction base="xs:int">{}</xs:restriction></xs:simpleType></xs:element></xs:schema>"#,
+                facet
+            )
+        };
+
+        assert!(matches!(
+            XsdSchema::parse(&xsd(r#"<xs:maxLength value="ten"/>"#)),
+            Err(LoadXsdError::InvalidFacet(..))
+        ));
+        assert!(matches!(
+            XsdSchema::parse(&xsd(r#"<xs:maxInclusive value="1.5"/>"#)),
+            Err(LoadXsdError::InvalidFacet(..))
+        ));
+        assert!(matches!(
+            XsdSchema::parse(&xsd(r#"<xs:pattern value="\i\c*"/>"#)),
+            Err(LoadXsdError::InvalidPattern(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn validate_namespaces() {
+        let xsd = r#"
+            <xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:currencies" elementFormDefault="qualified">
+                <xs:element name="Currencies">
+                    <xs:complexType>
+                        <xs:sequence>
+                            <xs:element name="Code" type="xs:string" maxOccurs="unbounded"/>
+                            <xs:element name="Name" type="xs:string" minOccurs="0" form="unqualified"/>
+                        </xs:sequence>
+                    </xs:complexType>
+                </xs:element>
+            </xs:schema>
+        "#;
+
+        assert!(
+            validate_against(
+                xsd,
+                r#"<Currencies xmlns="urn:currencies"><Code>GBP</Code><Name xmlns="">Pound</Name></Currencies>"#
+            )
+            .await
+            .is_ok()
+        );
+        assert!(
+            validate_against(
+                xsd,
+                r#"<c:Currencies xmlns:c="urn:currencies"><c:Code>GBP</c:Code><Name>Pound</Name></c:Currencies>"#
+            )
+            .await
+            .is_ok()
+        );
+
+        let xml = indoc::indoc! {r#"
+            <Currencies>
+                <c:Code xmlns:c="urn:other">GBP</c:Code>
+                <c:Code>EUR</c:Code>
+            </Currencies>
+        "#};
+
+        assert_eq!(
+            violations(validate_against(xsd, xml).await),
+            [
+                (
+                    1,
+                    "element 'Currencies' in no namespace, expected namespace 'urn:currencies'"
+                        .to_owned()
+                ),
+                (
+                    2,
+                    "element 'Code' in namespace 'urn:other', expected namespace 'urn:currencies'"
+                        .to_owned()
+                ),
+                (
+                    3,
+                    "undeclared namespace prefix 'c' of element 'Code'".to_owned()
+                ),
+            ]
+        );
+    }
+}
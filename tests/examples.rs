@@ -6,6 +6,7 @@ mod xml;
 use crate::sql_server::run_with_database;
 use color_eyre::Report;
 use sql_bulk_import_profile::import_executor;
+use sql_bulk_import_profile::import_executor::ImportHandle;
 use sql_bulk_import_profile::import_options::ImportOptions;
 use sql_bulk_import_profile::import_profile::ImportProfile;
 use std::io::Cursor;
@@ -47,8 +48,13 @@ async fn currencies_import_profile() -> Result<(), Report> {
                 )
                 .await?;
 
-            import_executor::import_executor(&mut client, import_profile, ImportOptions::default())
-                .await?;
+            import_executor::import_executor(
+                &mut client,
+                import_profile,
+                ImportOptions::default(),
+                &ImportHandle::default(),
+            )
+            .await?;
 
             let result = client
                 .simple_query("SELECT [ID], [Code], [Name] FROM [dbo].[Currency]")
@@ -118,8 +124,13 @@ async fn companies_import_profile() -> Result<(), Report> {
                 )
                 .await?;
 
-            import_executor::import_executor(&mut client, import_profile, ImportOptions::default())
-                .await?;
+            import_executor::import_executor(
+                &mut client,
+                import_profile,
+                ImportOptions::default(),
+                &ImportHandle::default(),
+            )
+            .await?;
 
             let result = client
                 .simple_query("SELECT [ID], [Code], [Name], [CountryID] FROM [dbo].[Company]")
@@ -145,6 +156,274 @@ async fn companies_import_profile() -> Result<(), Report> {
     .await
 }
 
+#[tokio::test]
+async fn prices_import_profile() -> Result<(), Report> {
+    run_with_database(
+        &"prices_import_profile".parse()?,
+        async |mut client: Client<Compat<TcpStream>>| {
+            let import_profile =
+                ImportProfile::new(Cursor::new(include_example!("prices_import_profile.json")))
+                    .await?;
+
+            client
+                .execute(
+                    "
+                    CREATE TABLE [Price]
+                    (
+                         ID     INT PRIMARY KEY IDENTITY(1, 1) NOT NULL
+                        ,Code   NVARCHAR(10) NOT NULL
+                        ,Amount MONEY NOT NULL
+                        ,Small  SMALLMONEY NULL
+                        ,Rate   DECIMAL(9, 4) NOT NULL
+                        ,Ratio  NUMERIC(5, 2) NULL
+                    );
+                    ",
+                    &[],
+                )
+                .await?;
+
+            import_executor::import_executor(
+                &mut client,
+                import_profile,
+                ImportOptions::default(),
+                &ImportHandle::default(),
+            )
+            .await?;
+
+            let result = client
+                .simple_query(
+                    "
+                    SELECT
+                         [Code]
+                        ,CONVERT(NVARCHAR(40), [Amount], 2)
+                        ,CONVERT(NVARCHAR(40), [Small], 2)
+                        ,CONVERT(NVARCHAR(40), [Rate])
+                        ,CONVERT(NVARCHAR(40), [Ratio])
+                    FROM [dbo].[Price]
+                    ORDER BY [Code]
+                    ",
+                )
+                .await?
+                .into_first_result()
+                .await?;
+
+            let expected = vec![
+                (
+                    "MAX",
+                    "922337203685477.5807",
+                    "214748.3647",
+                    "99999.9999",
+                    "999.99",
+                ),
+                (
+                    "MIN",
+                    "-922337203685477.5808",
+                    "-214748.3648",
+                    "-99999.9999",
+                    "-999.99",
+                ),
+                ("ROUND", "1.2346", "-1.2346", "1.2346", "1.01"), // Rounded half away from zero
+                ("SCI", "1500.0000", "0.0002", "0.0000", "150.00"), // Scientific notation
+            ];
+
+            itertools::assert_equal(
+                expected.into_iter().map(IntoRow::into_row),
+                result.iter().map(Row::data).cloned(),
+            );
+
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tokio::test]
+async fn exchange_rates_import_profile() -> Result<(), Report> {
+    run_with_database(
+        &"exchange_rates_import_profile".parse()?,
+        async |mut client: Client<Compat<TcpStream>>| {
+            let import_profile = ImportProfile::new(Cursor::new(include_example!(
+                "exchange_rates_import_profile.json"
+            )))
+            .await?;
+
+            client
+                .execute(
+                    "
+                    CREATE TABLE [ExchangeRate]
+                    (
+                         ID       INT PRIMARY KEY IDENTITY(1, 1) NOT NULL
+                        ,Currency NVARCHAR(3) NOT NULL
+                        ,Date     DATE NOT NULL
+                        ,Rate     DECIMAL(9, 4) NOT NULL
+                    );
+                    ",
+                    &[],
+                )
+                .await?;
+
+            import_executor::import_executor(
+                &mut client,
+                import_profile,
+                ImportOptions::default(),
+                &ImportHandle::default(),
+            )
+            .await?;
+
+            let result = client
+                .simple_query(
+                    "
+                    SELECT
+                         [Currency]
+                        ,CONVERT(NVARCHAR(10), [Date], 23)
+                        ,CONVERT(NVARCHAR(40), [Rate])
+                    FROM [dbo].[ExchangeRate]
+                    ORDER BY [Currency]
+                    ",
+                )
+                .await?
+                .into_first_result()
+                .await?;
+
+            // latest date of each currency, then last occurrence of that date
+            let expected = vec![
+                ("EUR", "2024-01-02", "1.0940"),
+                ("GBP", "2024-01-01", "1.2730"),
+                ("USD", "2024-01-02", "1.0000"),
+            ];
+
+            itertools::assert_equal(
+                expected.into_iter().map(IntoRow::into_row),
+                result.iter().map(Row::data).cloned(),
+            );
+
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tokio::test]
+async fn employees_import_profile() -> Result<(), Report> {
+    run_with_database(
+        &"employees_import_profile".parse()?,
+        async |mut client: Client<Compat<TcpStream>>| {
+            let import_profile = ImportProfile::new(Cursor::new(include_example!(
+                "employees_import_profile.json"
+            )))
+            .await?;
+
+            client
+                .execute(
+                    "
+                    CREATE TABLE [Department]
+                    (
+                         ID   INT PRIMARY KEY IDENTITY(1, 1) NOT NULL
+                        ,Code NVARCHAR(3) NOT NULL
+                        ,Name NVARCHAR(50) NOT NULL
+                    );
+
+                    INSERT INTO [Department] (Code, Name) VALUES ('SAL', 'Sales');
+
+                    CREATE TABLE [Employee]
+                    (
+                         ID           INT PRIMARY KEY IDENTITY(1, 1) NOT NULL
+                        ,Code         NVARCHAR(2) NOT NULL
+                        ,Name         NVARCHAR(50) NOT NULL
+                        ,DepartmentID INT NULL
+                    );
+
+                    CREATE TABLE [EmployeeAudit]
+                    (
+                         ID     INT PRIMARY KEY IDENTITY(1, 1) NOT NULL
+                        ,Code   NVARCHAR(2) NOT NULL
+                        ,Action NVARCHAR(6) NOT NULL
+                    );
+                    ",
+                    &[],
+                )
+                .await?;
+
+            client
+                .execute(
+                    "
+                    CREATE TRIGGER [EmployeeAuditTrigger] ON [Employee] AFTER INSERT, UPDATE AS
+                    INSERT INTO [EmployeeAudit] (Code, Action)
+                    SELECT
+                         i.Code
+                        ,CASE WHEN d.ID IS NULL THEN 'INSERT' ELSE 'UPDATE' END
+                    FROM inserted AS i
+                    LEFT JOIN deleted AS d ON d.ID = i.ID;
+                    ",
+                    &[],
+                )
+                .await?;
+
+            import_executor::import_executor(
+                &mut client,
+                import_profile,
+                ImportOptions::default(),
+                &ImportHandle::default(),
+            )
+            .await?;
+
+            let result = client
+                .simple_query(
+                    "
+                    SELECT
+                         e.[Code]
+                        ,d.[Code]
+                    FROM [dbo].[Employee] AS e
+                    LEFT JOIN [dbo].[Department] AS d ON d.[ID] = e.[DepartmentID]
+                    ORDER BY e.[Code]
+                    ",
+                )
+                .await?
+                .into_first_result()
+                .await?;
+
+            // departments imported after the employees are resolved by the deferred resolution
+            let expected = vec![("E1", "SAL"), ("E2", "ENG"), ("E3", "HR")];
+
+            itertools::assert_equal(
+                expected.into_iter().map(IntoRow::into_row),
+                result.iter().map(Row::data).cloned(),
+            );
+
+            let result = client
+                .simple_query(
+                    "
+                    SELECT
+                         [Code]
+                        ,[Action]
+                    FROM [dbo].[EmployeeAudit]
+                    ORDER BY [Action], [Code]
+                    ",
+                )
+                .await?
+                .into_first_result()
+                .await?;
+
+            // only the employees of which the lookup is resolved are updated again
+            let expected = vec![
+                ("E1", "INSERT"),
+                ("E2", "INSERT"),
+                ("E3", "INSERT"),
+                ("E2", "UPDATE"),
+                ("E3", "UPDATE"),
+            ];
+
+            itertools::assert_equal(
+                expected.into_iter().map(IntoRow::into_row),
+                result.iter().map(Row::data).cloned(),
+            );
+
+            Ok(())
+        },
+    )
+    .await
+}
+
 #[tokio::test]
 async fn countries_import_profile() -> Result<(), Report> {
     return Ok(());
@@ -195,8 +474,13 @@ async fn countries_import_profile() -> Result<(), Report> {
                 )
                 .await?;
 
-            import_executor::import_executor(&mut client, import_profile, ImportOptions::default())
-                .await?;
+            import_executor::import_executor(
+                &mut client,
+                import_profile,
+                ImportOptions::default(),
+                &ImportHandle::default(),
+            )
+            .await?;
 
             // TODO implement test
 